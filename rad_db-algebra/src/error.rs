@@ -1,5 +1,8 @@
 use rad_db_structure::identifier::Identifier;
+use rad_db_structure::relations::foreign::ForeignError;
+use rad_db_types::arithmetic::ArithmeticError;
 use std::fmt::{Display, Formatter};
+use std::io;
 macro_rules! quick_error {
     ($error:ty) => {
         impl std::fmt::Display for $error {
@@ -31,4 +34,58 @@ pub struct MissingFieldError { field: Identifier }
 
  */
 
-quick_error!{ MissingFieldError; field: Identifier }
\ No newline at end of file
+// A field that a query refers to but that isn't produced where it's used, with the closest
+// field that is produced, if any is close enough to be a likely typo
+quick_error! { MissingFieldError; field: Identifier, suggestion: Option<Identifier> }
+
+impl MissingFieldError {
+    pub fn field(&self) -> &Identifier {
+        &self.field
+    }
+
+    /// The field that was most likely meant instead
+    pub fn suggestion(&self) -> Option<&Identifier> {
+        self.suggestion.as_ref()
+    }
+}
+
+/// Why a query stopped before producing all of its tuples
+#[derive(Debug)]
+pub enum QueryError {
+    /// A foreign relation the query reads couldn't be read
+    Foreign(ForeignError),
+    /// A value computed by the query couldn't be computed, such as a sum that overflowed
+    Arithmetic(ArithmeticError),
+    /// Tuples the query spilled to disk couldn't be read back
+    Io(io::Error),
+}
+
+impl Display for QueryError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            QueryError::Foreign(error) => write!(f, "{}", error),
+            QueryError::Arithmetic(error) => write!(f, "{}", error),
+            QueryError::Io(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for QueryError {}
+
+impl From<ForeignError> for QueryError {
+    fn from(error: ForeignError) -> Self {
+        QueryError::Foreign(error)
+    }
+}
+
+impl From<ArithmeticError> for QueryError {
+    fn from(error: ArithmeticError) -> Self {
+        QueryError::Arithmetic(error)
+    }
+}
+
+impl From<io::Error> for QueryError {
+    fn from(error: io::Error) -> Self {
+        QueryError::Io(error)
+    }
+}
@@ -0,0 +1,237 @@
+//! Batches of tuples stored a column at a time, so that operators can work on a whole column of
+//! a batch at once instead of dispatching on every tuple
+
+use crate::query::conditions::Condition;
+use rad_db_structure::identifier::Identifier;
+use rad_db_structure::tuple::Tuple;
+use rad_db_types::Value;
+
+/// Tuples with the same fields, stored as a vector of values per field
+#[derive(Debug, Clone, PartialEq)]
+pub struct Batch {
+    fields: Vec<Identifier>,
+    columns: Vec<Vec<Value>>,
+    len: usize,
+}
+
+impl Batch {
+    /// Splits the tuples into their columns
+    pub fn from_tuples(fields: Vec<Identifier>, tuples: Vec<Tuple>) -> Self {
+        let len = tuples.len();
+        let mut columns: Vec<Vec<Value>> = fields.iter().map(|_| Vec::with_capacity(len)).collect();
+        for tuple in tuples {
+            for (column, value) in columns.iter_mut().zip(tuple) {
+                column.push(value);
+            }
+        }
+        Batch {
+            fields,
+            columns,
+            len,
+        }
+    }
+
+    pub fn fields(&self) -> &[Identifier] {
+        &self.fields
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Gets the values of a field
+    ///
+    /// # Panic
+    /// Panics if there's no field with the name
+    pub fn column(&self, field: &Identifier) -> &[Value] {
+        let index = self
+            .fields
+            .iter()
+            .position(|id| id == field)
+            .unwrap_or_else(|| panic!("No field named {} in this batch", field));
+        &self.columns[index]
+    }
+
+    /// Keeps the tuples whose entry in the mask is true
+    pub fn filter(self, mask: &[bool]) -> Self {
+        let len = mask.iter().filter(|&&keep| keep).count();
+        let columns = self
+            .columns
+            .into_iter()
+            .map(|column| {
+                column
+                    .into_iter()
+                    .zip(mask)
+                    .filter_map(|(value, &keep)| if keep { Some(value) } else { None })
+                    .collect()
+            })
+            .collect();
+        Batch {
+            fields: self.fields,
+            columns,
+            len,
+        }
+    }
+
+    /// Keeps the fields at the indexes, in that order
+    pub fn project(self, indexes: &[usize]) -> Self {
+        let fields = indexes.iter().map(|&i| self.fields[i].clone()).collect();
+        let columns = indexes.iter().map(|&i| self.columns[i].clone()).collect();
+        Batch {
+            fields,
+            columns,
+            len: self.len,
+        }
+    }
+
+    /// Joins the columns back into tuples
+    pub fn into_tuples(self) -> Vec<Tuple> {
+        let mut columns: Vec<_> = self.columns.into_iter().map(Vec::into_iter).collect();
+        (0..self.len)
+            .map(|_| {
+                columns
+                    .iter_mut()
+                    .map(|column| column.next().unwrap())
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+/// Runs a selection over tuples a batch at a time
+pub fn select_batched<I: IntoIterator<Item = Tuple>>(
+    condition: &Condition,
+    fields: &[Identifier],
+    tuples: I,
+    batch_size: usize,
+) -> Vec<Tuple> {
+    batched(fields, tuples, batch_size, |batch| {
+        let mask = condition.evaluate_batch(&batch);
+        batch.filter(&mask)
+    })
+}
+
+/// Runs a projection over tuples a batch at a time
+pub fn project_batched<I: IntoIterator<Item = Tuple>>(
+    indexes: &[usize],
+    fields: &[Identifier],
+    tuples: I,
+    batch_size: usize,
+) -> Vec<Tuple> {
+    batched(fields, tuples, batch_size, |batch| batch.project(indexes))
+}
+
+fn batched<I, F>(fields: &[Identifier], tuples: I, batch_size: usize, mut operator: F) -> Vec<Tuple>
+where
+    I: IntoIterator<Item = Tuple>,
+    F: FnMut(Batch) -> Batch,
+{
+    let batch_size = batch_size.max(1);
+    let mut output = vec![];
+    let mut tuples = tuples.into_iter().peekable();
+    while tuples.peek().is_some() {
+        let chunk: Vec<Tuple> = tuples.by_ref().take(batch_size).collect();
+        let batch = Batch::from_tuples(fields.to_vec(), chunk);
+        output.extend(operator(batch).into_tuples());
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::query::conditions::{ConditionOperation, Operand};
+    use crate::wrapped_tuple::WrappedTuple;
+
+    fn tuples() -> Vec<Tuple> {
+        (0..25u64)
+            .map(|i| {
+                Tuple::new(vec![
+                    Value::from(i % 5),
+                    Value::from(i),
+                    Value::from(format!("{}", i)),
+                ])
+            })
+            .collect()
+    }
+
+    fn fields() -> Vec<Identifier> {
+        vec![
+            Identifier::new("group"),
+            Identifier::new("id"),
+            Identifier::new("name"),
+        ]
+    }
+
+    #[test]
+    fn batches_round_trip() {
+        let batch = Batch::from_tuples(fields(), tuples());
+        assert_eq!(batch.len(), 25);
+        assert_eq!(batch.column(&Identifier::new("id"))[7], Value::from(7u64));
+        assert_eq!(batch.into_tuples(), tuples());
+    }
+
+    #[test]
+    fn batched_selection_matches_tuple_at_a_time() {
+        let fields = fields();
+        let conditions = vec![
+            Condition::new(
+                "group",
+                ConditionOperation::Equals(Operand::UnsignedNumber(3)),
+            ),
+            Condition::new(
+                "group",
+                ConditionOperation::Or(
+                    Box::new(ConditionOperation::Equals(Operand::UnsignedNumber(1))),
+                    Box::new(Condition::new(
+                        "name",
+                        ConditionOperation::Equals(Operand::String("12".to_string())),
+                    )),
+                ),
+            ),
+            Condition::new(
+                "group",
+                ConditionOperation::And(
+                    Box::new(ConditionOperation::Nequals(Operand::Id(Identifier::new(
+                        "id",
+                    )))),
+                    Box::new(Condition::new(
+                        "name",
+                        ConditionOperation::Equals(Operand::UnsignedNumber(3)),
+                    )),
+                ),
+            ),
+        ];
+        for condition in conditions {
+            let expected: Vec<Tuple> = tuples()
+                .into_iter()
+                .filter(|tuple| {
+                    matches!(
+                        condition.evaluate_on(&WrappedTuple::new(&fields, tuple)),
+                        Ok(true)
+                    )
+                })
+                .collect();
+            for batch_size in &[1, 4, 100] {
+                assert_eq!(
+                    select_batched(&condition, &fields, tuples(), *batch_size),
+                    expected
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn batched_projection() {
+        let projected = project_batched(&[2, 0], &fields(), tuples(), 8);
+        assert_eq!(projected.len(), 25);
+        assert_eq!(
+            projected[6],
+            Tuple::new(vec![Value::from("6"), Value::from(1u64)])
+        );
+    }
+}
@@ -1,22 +1,42 @@
+use crate::query::batch::Batch;
 use crate::query::query_node::QueryNode;
 use crate::wrapped_tuple::WrappedTuple;
 use rad_db_structure::identifier::Identifier;
 use rad_db_structure::tuple::Tuple;
-use rad_db_types::Value;
-use std::cmp::min;
+use rad_db_types::bits::BitOperator;
+use rad_db_types::comparison::TextComparison;
+use rad_db_types::geometry::{Point, Polygon};
+use rad_db_types::{BitString, Inet, Integer, Numeric, Text, Type, Value};
+use std::cmp::Ordering;
 use std::collections::HashSet;
-use std::convert::TryFrom;
 use std::iter::FromIterator;
+use std::ops::Bound;
 
 #[derive(Debug, Clone)]
 pub struct JoinCondition {
     left_id: Identifier,
     right_id: Identifier,
+    using: bool,
 }
 
 impl JoinCondition {
     pub fn new(left_id: Identifier, right_id: Identifier) -> Self {
-        JoinCondition { left_id, right_id }
+        JoinCondition {
+            left_id,
+            right_id,
+            using: false,
+        }
+    }
+
+    /// Joins on a field that both sides share, like `USING (field)`. The joined tuples only
+    /// have the field once, taken from whichever side isn't `NULL`.
+    pub fn using<I: Into<Identifier>>(field: I) -> Self {
+        let field = field.into();
+        JoinCondition {
+            left_id: field.clone(),
+            right_id: field,
+            using: true,
+        }
     }
 
     pub fn left_id(&self) -> &Identifier {
@@ -25,6 +45,29 @@ impl JoinCondition {
     pub fn right_id(&self) -> &Identifier {
         &self.right_id
     }
+
+    /// Whether the joined tuples only have the join field once
+    pub fn is_using(&self) -> bool {
+        self.using
+    }
+
+    /// The same condition with the fields of each side qualified by the name of that side
+    pub fn qualified(&self, left: &str, right: &str) -> Self {
+        JoinCondition {
+            left_id: Identifier::concat(left, &self.left_id),
+            right_id: Identifier::concat(right, &self.right_id),
+            using: self.using,
+        }
+    }
+
+    /// The same condition with the sides swapped
+    pub fn reversed(&self) -> Self {
+        JoinCondition {
+            left_id: self.right_id.clone(),
+            right_id: self.left_id.clone(),
+            using: self.using,
+        }
+    }
 }
 
 #[derive(PartialEq, Debug, Clone)]
@@ -42,6 +85,23 @@ pub enum Operand {
 pub enum ConditionOperation {
     Equals(Operand),
     Nequals(Operand),
+    /// Matches text against a pattern, where `%` matches any number of characters and `_`
+    /// matches exactly one
+    Like(String),
+    /// Equal to any of the operands
+    In(Vec<Operand>),
+    /// Within the bounds, where an included bound can be equal to the value
+    Range(Bound<Operand>, Bound<Operand>),
+    /// A point at most the radius away from the center
+    WithinRadius(Point, f64),
+    /// A point inside the polygon
+    WithinPolygon(Polygon),
+    /// An IP address in the subnet
+    WithinSubnet(Inet),
+    /// A bit string whose bit at the index is one
+    BitSet(usize),
+    /// A bit string of the same length as the mask with every bit of the mask set
+    HasBits(BitString),
     And(Box<ConditionOperation>, Box<Condition>),
     Or(Box<ConditionOperation>, Box<Condition>),
 }
@@ -65,6 +125,22 @@ macro_rules! min_float {
     };
 }
 
+/// The fraction of tuples a `LIKE` pattern with wildcards is assumed to match
+const LIKE_SELECTIVITY: f64 = 0.1;
+
+/// The fraction of tuples a range is assumed to contain when there's no histogram of the field
+const RANGE_SELECTIVITY: f64 = 1.0 / 3.0;
+
+/// The fraction of tuples a radius or polygon is assumed to contain
+const AREA_SELECTIVITY: f64 = 0.1;
+
+/// The fraction of tuples a subnet is assumed to contain, as addresses are rarely spread evenly
+/// enough over their space for the length of the prefix to tell
+const SUBNET_SELECTIVITY: f64 = 0.1;
+
+/// The fraction of tuples a bit is assumed to be set in
+const BIT_SELECTIVITY: f64 = 0.5;
+
 /// This operation was invalid for some reason
 #[derive(Debug)]
 pub struct InvalidOperation;
@@ -74,6 +150,21 @@ impl ConditionOperation {
         let ret = match self {
             ConditionOperation::Equals(_) => 1.0 / max_tuples as f64,
             ConditionOperation::Nequals(_) => 1.0 - 1.0 / max_tuples as f64,
+            ConditionOperation::Like(pattern) => {
+                if pattern.contains(|c| c == '%' || c == '_') {
+                    LIKE_SELECTIVITY
+                } else {
+                    1.0 / max_tuples as f64
+                }
+            }
+            ConditionOperation::In(list) => list.len() as f64 / max_tuples as f64,
+            ConditionOperation::Range(..) => RANGE_SELECTIVITY,
+            ConditionOperation::WithinRadius(..) | ConditionOperation::WithinPolygon(_) => {
+                AREA_SELECTIVITY
+            }
+            ConditionOperation::WithinSubnet(_) => SUBNET_SELECTIVITY,
+            ConditionOperation::BitSet(_) => BIT_SELECTIVITY,
+            ConditionOperation::HasBits(mask) => BIT_SELECTIVITY.powi(mask.count_ones() as i32),
             ConditionOperation::And(c, r) => c.selectivity(max_tuples) * r.selectivity(max_tuples),
             ConditionOperation::Or(c, r) => {
                 min_float!(c.selectivity(max_tuples) + r.selectivity(max_tuples), 1.0)
@@ -94,6 +185,22 @@ impl ConditionOperation {
         match &self {
             ConditionOperation::Equals(Operand::Id(id)) => HashSet::from_iter(vec![id.clone()]),
             ConditionOperation::Nequals(Operand::Id(id)) => HashSet::from_iter(vec![id.clone()]),
+            ConditionOperation::In(list) => list
+                .iter()
+                .filter_map(|operand| match operand {
+                    Operand::Id(id) => Some(id.clone()),
+                    _ => None,
+                })
+                .collect(),
+            ConditionOperation::Range(lower, upper) => [lower, upper]
+                .iter()
+                .filter_map(|bound| match bound {
+                    Bound::Included(Operand::Id(id)) | Bound::Excluded(Operand::Id(id)) => {
+                        Some(id.clone())
+                    }
+                    _ => None,
+                })
+                .collect(),
             ConditionOperation::And(left, more) => {
                 let mut relevant = left.relevant_fields();
                 relevant.extend(more.relevant_fields());
@@ -108,39 +215,534 @@ impl ConditionOperation {
         }
     }
 
-    fn evaluate_on(&self, compare: Value, tuple: &WrappedTuple) -> Result<bool, InvalidOperation> {
+    fn evaluate_on(
+        &self,
+        compare: &Value,
+        tuple: &WrappedTuple,
+        text_comparison: TextComparison,
+    ) -> Result<bool, InvalidOperation> {
         match self {
-            ConditionOperation::Equals(eq) => match eq {
-                Operand::Id(id) => {
-                    let right = &tuple[id];
-                    Ok(&compare == right)
+            ConditionOperation::Equals(eq) => eq.equals(compare, tuple, text_comparison),
+            ConditionOperation::Nequals(neq) => {
+                neq.equals(compare, tuple, text_comparison).map(|b| !b)
+            }
+            ConditionOperation::Like(pattern) => like(compare, pattern, text_comparison),
+            ConditionOperation::In(list) => {
+                if LiteralSet::new(list, text_comparison).contains(compare) {
+                    return Ok(true);
                 }
-                Operand::SignedNumber(signed) => {
-                    let number = i64::try_from(compare).map_err(|_| InvalidOperation)?;
-                    Ok(*signed == number)
+                for operand in list.iter().filter(|operand| operand.constant().is_none()) {
+                    if operand.equals(compare, tuple, text_comparison)? {
+                        return Ok(true);
+                    }
                 }
-                Operand::UnsignedNumber(unsigned) => {
-                    let number = u64::try_from(compare).map_err(|_| InvalidOperation)?;
-                    Ok(*unsigned == number)
+                Ok(false)
+            }
+            ConditionOperation::Range(lower, upper) => {
+                let bound = |bound: &Bound<Operand>| {
+                    bound.as_ref().map(|operand| match operand {
+                        Operand::Id(id) => tuple[id].clone(),
+                        constant => constant.constant().unwrap(),
+                    })
+                };
+                within(
+                    compare,
+                    bound(lower).as_ref(),
+                    bound(upper).as_ref(),
+                    text_comparison,
+                )
+            }
+            ConditionOperation::WithinRadius(center, radius) => {
+                inside(compare, &|point| point.within_radius(center, *radius))
+            }
+            ConditionOperation::WithinPolygon(polygon) => {
+                inside(compare, &|point| polygon.contains(point))
+            }
+            ConditionOperation::WithinSubnet(subnet) => in_subnet(compare, subnet),
+            ConditionOperation::BitSet(index) => bits_set(compare, &|bits| bits.get(*index)),
+            ConditionOperation::HasBits(mask) => bits_set(compare, &|bits| has_bits(bits, mask)),
+            ConditionOperation::And(inner, next) => {
+                Ok(inner.evaluate_on(compare, tuple, text_comparison)?
+                    && next.evaluate_on(tuple)?)
+            }
+            ConditionOperation::Or(inner, next) => {
+                Ok(inner.evaluate_on(compare, tuple, text_comparison)?
+                    || next.evaluate_on(tuple)?)
+            }
+        }
+    }
+}
+
+impl ConditionOperation {
+    /// Evaluates the operation on every tuple of a batch, where `compare` is the column of the
+    /// base field. A tuple the operation is invalid for is `None`.
+    fn evaluate_batch(
+        &self,
+        compare: &[Value],
+        batch: &Batch,
+        text_comparison: TextComparison,
+    ) -> Vec<Option<bool>> {
+        match self {
+            ConditionOperation::Equals(eq) => eq.equals_column(compare, batch, text_comparison),
+            ConditionOperation::Nequals(neq) => neq
+                .equals_column(compare, batch, text_comparison)
+                .into_iter()
+                .map(|equal| equal.map(|b| !b))
+                .collect(),
+            ConditionOperation::Like(pattern) => compare
+                .iter()
+                .map(|value| like(value, pattern, text_comparison).ok())
+                .collect(),
+            ConditionOperation::In(list) => {
+                // the set is only built once for the whole batch
+                let literals = LiteralSet::new(list, text_comparison);
+                let mut matches: Vec<Option<bool>> = compare
+                    .iter()
+                    .map(|value| Some(literals.contains(value)))
+                    .collect();
+                for operand in list.iter().filter(|operand| operand.constant().is_none()) {
+                    let equal = operand.equals_column(compare, batch, text_comparison);
+                    for (matched, equal) in matches.iter_mut().zip(equal) {
+                        if *matched == Some(false) {
+                            *matched = equal;
+                        }
+                    }
                 }
-                Operand::Float(f) => {
-                    let number = f64::try_from(compare).map_err(|_| InvalidOperation)?;
-                    Ok(*f == number)
+                matches
+            }
+            ConditionOperation::Range(lower, upper) => {
+                let bound = |bound: &Bound<Operand>, index: usize| {
+                    bound.as_ref().map(|operand| match operand {
+                        Operand::Id(id) => batch.column(id)[index].clone(),
+                        constant => constant.constant().unwrap(),
+                    })
+                };
+                compare
+                    .iter()
+                    .enumerate()
+                    .map(|(index, value)| {
+                        within(
+                            value,
+                            bound(lower, index).as_ref(),
+                            bound(upper, index).as_ref(),
+                            text_comparison,
+                        )
+                        .ok()
+                    })
+                    .collect()
+            }
+            ConditionOperation::WithinRadius(center, radius) => compare
+                .iter()
+                .map(|value| inside(value, &|point| point.within_radius(center, *radius)).ok())
+                .collect(),
+            ConditionOperation::WithinPolygon(polygon) => compare
+                .iter()
+                .map(|value| inside(value, &|point| polygon.contains(point)).ok())
+                .collect(),
+            ConditionOperation::WithinSubnet(subnet) => compare
+                .iter()
+                .map(|value| in_subnet(value, subnet).ok())
+                .collect(),
+            ConditionOperation::BitSet(index) => compare
+                .iter()
+                .map(|value| bits_set(value, &|bits| bits.get(*index)).ok())
+                .collect(),
+            ConditionOperation::HasBits(mask) => compare
+                .iter()
+                .map(|value| bits_set(value, &|bits| has_bits(bits, mask)).ok())
+                .collect(),
+            ConditionOperation::And(inner, next) => {
+                let inner = inner.evaluate_batch(compare, batch, text_comparison);
+                let next = next.evaluate_batch_helper(batch);
+                inner
+                    .into_iter()
+                    .zip(next)
+                    .map(|(inner, next)| match inner {
+                        Some(true) => next,
+                        other => other,
+                    })
+                    .collect()
+            }
+            ConditionOperation::Or(inner, next) => {
+                let inner = inner.evaluate_batch(compare, batch, text_comparison);
+                let next = next.evaluate_batch_helper(batch);
+                inner
+                    .into_iter()
+                    .zip(next)
+                    .map(|(inner, next)| match inner {
+                        Some(false) => next,
+                        other => other,
+                    })
+                    .collect()
+            }
+        }
+    }
+}
+
+/// A constant normalized so that constants that are equal to each other are also equal as
+/// literals, and hash the same
+#[derive(Debug, PartialEq, Eq, Hash)]
+enum Literal {
+    Integer(i128),
+    /// Integers too wide for an `i128`
+    Wide(Integer),
+    Float(u64),
+    Text(String),
+    Boolean(bool),
+}
+
+/// The constants of an `IN` list, so that checking if a value is one of them doesn't compare it
+/// to each constant
+struct LiteralSet {
+    literals: HashSet<Literal>,
+    text_comparison: TextComparison,
+}
+
+impl LiteralSet {
+    fn new(operands: &[Operand], text_comparison: TextComparison) -> Self {
+        let literals = operands
+            .iter()
+            .filter_map(Operand::constant)
+            .filter_map(|constant| literal(&constant, text_comparison))
+            .collect();
+        LiteralSet {
+            literals,
+            text_comparison,
+        }
+    }
+
+    fn contains(&self, value: &Value) -> bool {
+        literal(value, self.text_comparison)
+            .map_or(false, |literal| self.literals.contains(&literal))
+    }
+}
+
+/// The literal of a value, where numbers of any width are equal if they're numerically equal,
+/// and text is equal if it's equal under the comparison. `NULL`, `NaN` and values that can't be
+/// compared to constants have no literal.
+fn literal(value: &Value, text_comparison: TextComparison) -> Option<Literal> {
+    match value {
+        Value::Optional(inner) => literal(inner.as_ref()?, text_comparison),
+        Value::Numeric(Numeric::Signed(signed)) => {
+            Some(Literal::Integer(Into::<i64>::into(*signed) as i128))
+        }
+        Value::Numeric(Numeric::Unsigned(unsigned)) => {
+            Some(Literal::Integer(Into::<u64>::into(*unsigned) as i128))
+        }
+        Value::Numeric(Numeric::Float(float)) => float_literal(*float as f64),
+        Value::Numeric(Numeric::Double(double)) => float_literal(*double),
+        Value::Numeric(Numeric::Decimal(decimal)) => match decimal.to_integer() {
+            Some(integer) => Some(Literal::Integer(integer)),
+            None => float_literal(decimal.to_f64()),
+        },
+        Value::Numeric(Numeric::BigInt(integer)) => Some(match integer.to_i128() {
+            Some(integer) => Literal::Integer(integer),
+            None => Literal::Wide(integer.clone()),
+        }),
+        Value::Text(Text::Char(c)) => Some(Literal::Text(
+            text_comparison.normalize(c.encode_utf8(&mut [0; 4])),
+        )),
+        Value::Text(text) => Some(Literal::Text(text_comparison.normalize(text.as_str()?))),
+        Value::Boolean(b) => Some(Literal::Boolean(*b)),
+        // labels are equal to the text they're made of
+        Value::Enum(e) => Some(Literal::Text(text_comparison.normalize(e.label()))),
+        _ => None,
+    }
+}
+
+fn float_literal(float: f64) -> Option<Literal> {
+    if float.is_nan() {
+        None
+    } else if float.fract() == 0.0 && float.abs() < 2f64.powi(64) {
+        // whole floats are equal to the integers with the same value
+        Some(Literal::Integer(float as i128))
+    } else {
+        Some(Literal::Float(float.to_bits()))
+    }
+}
+
+/// Checks if a value is within the bounds. `NULL` is never within any bounds.
+fn within(
+    value: &Value,
+    lower: Bound<&Value>,
+    upper: Bound<&Value>,
+    text_comparison: TextComparison,
+) -> Result<bool, InvalidOperation> {
+    if let Value::Optional(None) = value {
+        return Ok(false);
+    }
+    let above = match lower {
+        Bound::Included(bound) => order(value, bound, text_comparison)? != Ordering::Less,
+        Bound::Excluded(bound) => order(value, bound, text_comparison)? == Ordering::Greater,
+        Bound::Unbounded => true,
+    };
+    let below = match upper {
+        Bound::Included(bound) => order(value, bound, text_comparison)? != Ordering::Greater,
+        Bound::Excluded(bound) => order(value, bound, text_comparison)? == Ordering::Less,
+        Bound::Unbounded => true,
+    };
+    Ok(above && below)
+}
+
+/// The value of a bound, if it's a constant or there isn't one
+fn constant_bound(bound: &Bound<Operand>) -> Option<Bound<Value>> {
+    match bound {
+        Bound::Included(operand) => operand.constant().map(Bound::Included),
+        Bound::Excluded(operand) => operand.constant().map(Bound::Excluded),
+        Bound::Unbounded => Some(Bound::Unbounded),
+    }
+}
+
+/// Whether a bound of a range is at least as tight as the same bound of another range, where
+/// `inwards` is the order of a value inside the range to the lower or upper bound
+fn tighter(
+    bound: &Bound<Operand>,
+    other: &Bound<Operand>,
+    inwards: Ordering,
+    text_comparison: TextComparison,
+) -> bool {
+    let (bound, other) = match (constant_bound(bound), constant_bound(other)) {
+        (Some(bound), Some(other)) => (bound, other),
+        _ => return false,
+    };
+    let (value, other_value) = match (&bound, &other) {
+        (_, Bound::Unbounded) => return true,
+        (Bound::Unbounded, _) => return false,
+        (Bound::Included(value), Bound::Included(other_value))
+        | (Bound::Included(value), Bound::Excluded(other_value))
+        | (Bound::Excluded(value), Bound::Included(other_value))
+        | (Bound::Excluded(value), Bound::Excluded(other_value)) => (value, other_value),
+    };
+    match order(value, other_value, text_comparison) {
+        Ok(ordering) if ordering == inwards => true,
+        Ok(Ordering::Equal) => {
+            matches!(bound, Bound::Excluded(_)) || matches!(other, Bound::Included(_))
+        }
+        _ => false,
+    }
+}
+
+/// Orders a value against a bound, with text ordered by its form under the comparison
+fn order(
+    value: &Value,
+    bound: &Value,
+    text_comparison: TextComparison,
+) -> Result<Ordering, InvalidOperation> {
+    let normalized = |value: &Value| match value {
+        Value::Text(Text::Char(c)) => Some(text_comparison.normalize(c.encode_utf8(&mut [0; 4]))),
+        Value::Text(text) => text.as_str().map(|text| text_comparison.normalize(text)),
+        _ => None,
+    };
+    if text_comparison != TextComparison::Exact {
+        if let (Some(value), Some(bound)) = (normalized(value), normalized(bound)) {
+            return Ok(value.cmp(&bound));
+        }
+    }
+    value.compare(bound).ok_or(InvalidOperation)
+}
+
+/// Checks if a text value matches a `LIKE` pattern. `NULL` never matches.
+fn like(
+    value: &Value,
+    pattern: &str,
+    text_comparison: TextComparison,
+) -> Result<bool, InvalidOperation> {
+    match value {
+        Value::Optional(None) => Ok(false),
+        Value::Optional(Some(inner)) => like(inner, pattern, text_comparison),
+        Value::Text(Text::Char(c)) => Ok(text_comparison.like(c.encode_utf8(&mut [0; 4]), pattern)),
+        Value::Text(text) => text
+            .as_str()
+            .map(|text| text_comparison.like(text, pattern))
+            .ok_or(InvalidOperation),
+        _ => Err(InvalidOperation),
+    }
+}
+
+/// Checks whether a value is a point inside an area, where `NULL` is never inside it
+fn inside(value: &Value, area: &dyn Fn(&Point) -> bool) -> Result<bool, InvalidOperation> {
+    match value {
+        Value::Optional(None) => Ok(false),
+        Value::Optional(Some(inner)) => inside(inner, area),
+        Value::Point(point) => Ok(area(point)),
+        _ => Err(InvalidOperation),
+    }
+}
+
+/// Checks whether a value is an IP address in the subnet, where `NULL` is never in it
+fn in_subnet(value: &Value, subnet: &Inet) -> Result<bool, InvalidOperation> {
+    match value {
+        Value::Optional(None) => Ok(false),
+        Value::Optional(Some(inner)) => in_subnet(inner, subnet),
+        Value::Inet(inet) => Ok(subnet.contains(inet)),
+        _ => Err(InvalidOperation),
+    }
+}
+
+/// Checks whether the bits of a value are set, where `NULL` never has them set. The check returns
+/// `None` if it can't be made on the bits, such as for an index past their end.
+fn bits_set(
+    value: &Value,
+    check: &dyn Fn(&BitString) -> Option<bool>,
+) -> Result<bool, InvalidOperation> {
+    match value {
+        Value::Optional(None) => Ok(false),
+        Value::Optional(Some(inner)) => bits_set(inner, check),
+        Value::Text(Text::BitString(bits, _)) => check(bits).ok_or(InvalidOperation),
+        _ => Err(InvalidOperation),
+    }
+}
+
+fn has_bits(bits: &BitString, mask: &BitString) -> Option<bool> {
+    let masked = bits.apply(BitOperator::And, mask).ok()?;
+    Some(masked == *mask)
+}
+
+/// Checks if a value is numerically equal to a numeric operand
+/// Checks whether a value is equal to a constant. A constant of another kind than the value, such
+/// as a number compared to text, is cast into the kind of the value first. Returns `None` if the
+/// value is `NULL` or the constant can't be cast into its kind.
+fn equals_constant(
+    value: &Value,
+    constant: &Value,
+    text_comparison: TextComparison,
+) -> Option<bool> {
+    match (value, constant) {
+        (Value::Optional(None), _) => None,
+        (Value::Optional(Some(inner)), constant) => {
+            equals_constant(inner, constant, text_comparison)
+        }
+        (Value::Text(text), Value::Text(constant)) => Some(text.eq_with(constant, text_comparison)),
+        (Value::Numeric(_), Value::Numeric(_)) => Some(value.numeric_eq(constant)),
+        (Value::Boolean(value), Value::Boolean(constant)) => Some(value == constant),
+        (value, constant) => match (value, constant.cast_to(value).ok()?) {
+            (Value::Text(text), Value::Text(cast)) => Some(text.eq_with(&cast, text_comparison)),
+            (value, cast) => Some(value.numeric_eq(&cast)),
+        },
+    }
+}
+
+impl Operand {
+    /// Checks whether a value is equal to this operand
+    fn equals(
+        &self,
+        compare: &Value,
+        tuple: &WrappedTuple,
+        text_comparison: TextComparison,
+    ) -> Result<bool, InvalidOperation> {
+        match self {
+            Operand::Id(id) => {
+                let right = &tuple[id];
+                match (compare, right) {
+                    (Value::Text(left), Value::Text(right)) => {
+                        Ok(left.eq_with(right, text_comparison))
+                    }
+                    (left, right) => Ok(left.numeric_eq(right)),
                 }
-                Operand::String(_) => {}
-                Operand::Boolean(_) => {}
-            },
-            ConditionOperation::Nequals(neq) => {}
-            ConditionOperation::And(_, _) => {}
-            ConditionOperation::Or(_, _) => {}
+            }
+            constant => {
+                let constant = constant.constant().unwrap();
+                equals_constant(compare, &constant, text_comparison).ok_or(InvalidOperation)
+            }
         }
     }
+
+    /// Checks whether each value of a column is equal to this operand
+    fn equals_column(
+        &self,
+        compare: &[Value],
+        batch: &Batch,
+        text_comparison: TextComparison,
+    ) -> Vec<Option<bool>> {
+        match self {
+            Operand::Id(id) => compare
+                .iter()
+                .zip(batch.column(id))
+                .map(|(left, right)| match (left, right) {
+                    (Value::Text(left), Value::Text(right)) => {
+                        Some(left.eq_with(right, text_comparison))
+                    }
+                    (left, right) => Some(left.numeric_eq(right)),
+                })
+                .collect(),
+            _ => {
+                // the constant is only created once for the whole column
+                let constant = self.constant().unwrap();
+                compare
+                    .iter()
+                    .map(|value| equals_constant(value, &constant, text_comparison))
+                    .collect()
+            }
+        }
+    }
+
+    /// The value of the operand, if it's a constant
+    fn constant(&self) -> Option<Value> {
+        match self {
+            Operand::Id(_) => None,
+            Operand::SignedNumber(signed) => Some(Value::from(*signed)),
+            Operand::UnsignedNumber(unsigned) => Some(Value::from(*unsigned)),
+            Operand::Float(f) => Some(Numeric::Double(*f).into()),
+            Operand::String(string) => Some(Value::Text(Text::String(string.clone(), None))),
+            Operand::Char(c) => Some(Value::Text(Text::Char(*c))),
+            Operand::Boolean(b) => Some(Value::Boolean(*b)),
+        }
+    }
+}
+
+/// Whether two constants are equal, or `None` if they can't be compared
+fn constants_equal(left: &Value, right: &Value, text_comparison: TextComparison) -> Option<bool> {
+    match (left, right) {
+        (Value::Numeric(_), Value::Numeric(_)) => Some(left.numeric_eq(right)),
+        (Value::Text(left), Value::Text(right)) => Some(left.eq_with(right, text_comparison)),
+        (Value::Boolean(left), Value::Boolean(right)) => Some(left == right),
+        _ => None,
+    }
+}
+
+/// Whether text that is equal under the `finer` comparison is always equal under the `coarser` one
+fn refines(finer: TextComparison, coarser: TextComparison) -> bool {
+    use TextComparison::*;
+    match (finer, coarser) {
+        (Exact, _) | (_, CaseInsensitiveTrimmed) => true,
+        (CaseInsensitive, CaseInsensitive) | (TrimTrailing, TrimTrailing) => true,
+        _ => false,
+    }
+}
+
+/// What is known about a condition without evaluating it on any tuples
+#[derive(PartialEq, Debug, Clone)]
+pub enum Simplified {
+    /// The condition is true for every tuple
+    Always,
+    /// The condition is false for every tuple
+    Never,
+    /// The condition depends on the tuple, and is equivalent to this simpler condition
+    Condition(Condition),
+}
+
+/// Whether every value of the field is equal to itself, which isn't true of floats because of
+/// `NaN`. Fields that can't be found are never assumed to be.
+fn is_reflexive(field: &Identifier, fields: &[(Identifier, Type)]) -> bool {
+    fn reflexive_type(ty: &Type) -> bool {
+        match ty {
+            Type::Optional(Some(inner)) => reflexive_type(inner),
+            Type::Numeric(Numeric::Float(_)) | Type::Numeric(Numeric::Double(_)) => false,
+            _ => true,
+        }
+    }
+    fields
+        .iter()
+        .find(|(id, _)| id == field)
+        .map_or(false, |(_, ty)| reflexive_type(ty))
 }
 
 #[derive(PartialEq, Debug, Clone)]
 pub struct Condition {
     base: Identifier,
     operation: ConditionOperation,
+    /// `None` until either the condition or the field it's on sets how text is compared
+    text_comparison: Option<TextComparison>,
 }
 
 impl Condition {
@@ -148,15 +750,288 @@ impl Condition {
         Condition {
             base: base.into(),
             operation,
+            text_comparison: None,
+        }
+    }
+
+    /// Sets how text values are compared when this condition is evaluated
+    pub fn with_text_comparison(mut self, text_comparison: TextComparison) -> Self {
+        self.text_comparison = Some(text_comparison);
+        self
+    }
+
+    /// Gets how text values are compared when this condition is evaluated
+    pub fn text_comparison(&self) -> TextComparison {
+        self.text_comparison.unwrap_or_default()
+    }
+
+    /// Compares text the way the field the condition is on is declared to, unless the condition
+    /// sets how it compares text itself. Conditions joined to this one use the comparison of
+    /// their own fields.
+    pub fn with_column_comparisons<F: Fn(&Identifier) -> Option<TextComparison>>(
+        mut self,
+        column: &F,
+    ) -> Self {
+        if self.text_comparison.is_none() {
+            self.text_comparison = column(&self.base);
         }
+        self.operation = match self.operation {
+            ConditionOperation::And(inner, next) => {
+                ConditionOperation::And(inner, Box::new(next.with_column_comparisons(column)))
+            }
+            ConditionOperation::Or(inner, next) => {
+                ConditionOperation::Or(inner, Box::new(next.with_column_comparisons(column)))
+            }
+            operation => operation,
+        };
+        self
+    }
+
+    /// A condition that the field is within the bounds
+    pub fn range<I: Into<Identifier>>(
+        base: I,
+        lower: Bound<Operand>,
+        upper: Bound<Operand>,
+    ) -> Self {
+        Condition::new(base, ConditionOperation::Range(lower, upper))
+    }
+
+    /// A condition that the field is between two operands, including both, like `BETWEEN`
+    pub fn between<I: Into<Identifier>>(base: I, low: Operand, high: Operand) -> Self {
+        Self::range(base, Bound::Included(low), Bound::Included(high))
+    }
+
+    /// A condition that the field is a point at most the radius away from the center
+    pub fn within_radius<I: Into<Identifier>>(base: I, center: Point, radius: f64) -> Self {
+        Condition::new(base, ConditionOperation::WithinRadius(center, radius))
+    }
+
+    /// A condition that the field is a point inside the polygon
+    pub fn within_polygon<I: Into<Identifier>>(base: I, polygon: Polygon) -> Self {
+        Condition::new(base, ConditionOperation::WithinPolygon(polygon))
+    }
+
+    /// A condition that the field is an IP address in the subnet, including the subnet itself
+    pub fn within_subnet<I: Into<Identifier>>(base: I, subnet: Inet) -> Self {
+        Condition::new(base, ConditionOperation::WithinSubnet(subnet))
+    }
+
+    /// A condition that the field is a bit string whose bit at the index is one
+    pub fn bit_set<I: Into<Identifier>>(base: I, index: usize) -> Self {
+        Condition::new(base, ConditionOperation::BitSet(index))
+    }
+
+    /// A condition that the field is a bit string with every bit of the mask set
+    pub fn has_bits<I: Into<Identifier>>(base: I, mask: BitString) -> Self {
+        Condition::new(base, ConditionOperation::HasBits(mask))
     }
 
     pub fn and(left: Self, right: Self) -> Self {
-        let Condition { base, operation } = left;
-        Condition::new(
+        let Condition {
             base,
-            ConditionOperation::And(Box::new(operation), Box::new(right)),
-        )
+            operation,
+            text_comparison,
+        } = left;
+        Condition {
+            base,
+            operation: ConditionOperation::And(Box::new(operation), Box::new(right)),
+            text_comparison,
+        }
+    }
+
+    pub fn or(left: Self, right: Self) -> Self {
+        let Condition {
+            base,
+            operation,
+            text_comparison,
+        } = left;
+        Condition {
+            base,
+            operation: ConditionOperation::Or(Box::new(operation), Box::new(right)),
+            text_comparison,
+        }
+    }
+
+    /// Folds the comparisons whose result doesn't depend on the tuple, such as a field compared
+    /// to itself, and then simplifies the conjunctions and disjunctions that contain them. The
+    /// fields are those of the tuples the condition is evaluated on.
+    pub fn simplify(&self, fields: &[(Identifier, Type)]) -> Simplified {
+        match &self.operation {
+            ConditionOperation::Equals(Operand::Id(id))
+                if id == &self.base && is_reflexive(id, fields) =>
+            {
+                Simplified::Always
+            }
+            ConditionOperation::Nequals(Operand::Id(id))
+                if id == &self.base && is_reflexive(id, fields) =>
+            {
+                Simplified::Never
+            }
+            ConditionOperation::In(list) if list.is_empty() => Simplified::Never,
+            ConditionOperation::Equals(_)
+            | ConditionOperation::Nequals(_)
+            | ConditionOperation::Like(_)
+            | ConditionOperation::In(_)
+            | ConditionOperation::Range(..)
+            | ConditionOperation::WithinRadius(..)
+            | ConditionOperation::WithinPolygon(_)
+            | ConditionOperation::WithinSubnet(_)
+            | ConditionOperation::BitSet(_)
+            | ConditionOperation::HasBits(_) => Simplified::Condition(self.clone()),
+            ConditionOperation::And(inner, next) | ConditionOperation::Or(inner, next) => {
+                let inner = Condition {
+                    base: self.base.clone(),
+                    operation: (**inner).clone(),
+                    text_comparison: self.text_comparison,
+                }
+                .simplify(fields);
+                let next = next.simplify(fields);
+                let is_and = matches!(self.operation, ConditionOperation::And(..));
+                // the value that decides the result no matter what the other side is
+                let (absorbing, identity) = if is_and {
+                    (Simplified::Never, Simplified::Always)
+                } else {
+                    (Simplified::Always, Simplified::Never)
+                };
+                match (inner, next) {
+                    (inner, next) if inner == absorbing || next == absorbing => absorbing,
+                    (other, next) if next == identity => other,
+                    (inner, other) if inner == identity => other,
+                    (Simplified::Condition(inner), Simplified::Condition(next)) => {
+                        if inner == next {
+                            Simplified::Condition(inner)
+                        } else if is_and {
+                            Simplified::Condition(Condition::and(inner, next))
+                        } else {
+                            Simplified::Condition(Condition::or(inner, next))
+                        }
+                    }
+                    _ => unreachable!(),
+                }
+            }
+        }
+    }
+
+    /// Whether the conditions can never all be true for the same tuple, because they need a field
+    /// to equal two different constants, or to both equal and not equal the same constant
+    pub fn are_contradictory(conditions: &[Condition]) -> bool {
+        let mut comparisons = vec![];
+        for condition in conditions {
+            for part in condition.clone().split_and() {
+                let (equal, operand) = match &part.operation {
+                    ConditionOperation::Equals(operand) => (true, operand),
+                    ConditionOperation::Nequals(operand) => (false, operand),
+                    _ => continue,
+                };
+                if let Some(constant) = operand.constant() {
+                    comparisons.push((part.base.clone(), equal, constant, part.text_comparison()));
+                }
+            }
+        }
+
+        for (i, (base, equal, constant, text_comparison)) in comparisons.iter().enumerate() {
+            for (other_base, other_equal, other_constant, other_text_comparison) in
+                &comparisons[i + 1..]
+            {
+                if base != other_base {
+                    continue;
+                }
+                let contradiction = match (equal, other_equal) {
+                    // text equal under different comparisons can still match the same value
+                    (true, true) if text_comparison == other_text_comparison => {
+                        constants_equal(constant, other_constant, *text_comparison) == Some(false)
+                    }
+                    // the field equals the constant under the comparison of the `=`, so it's only
+                    // known to also equal it under the `<>` if that one ignores at least as much
+                    (true, false) if refines(*text_comparison, *other_text_comparison) => {
+                        constants_equal(constant, other_constant, *text_comparison) == Some(true)
+                    }
+                    (false, true) if refines(*other_text_comparison, *text_comparison) => {
+                        constants_equal(constant, other_constant, *other_text_comparison)
+                            == Some(true)
+                    }
+                    _ => false,
+                };
+                if contradiction {
+                    return true;
+                }
+            }
+        }
+        false
+    }
+
+    /// Whether this condition is true for every tuple that all of the conditions are true for.
+    /// Each part of a conjunction has to be implied by a single condition, such as `age >= 21` by
+    /// `age = 30`, so a condition that is implied some other way isn't known to be.
+    pub fn implied_by(&self, conditions: &[Condition]) -> bool {
+        let known: Vec<Condition> = conditions
+            .iter()
+            .flat_map(|condition| condition.clone().split_and())
+            .collect();
+        self.clone()
+            .split_and()
+            .iter()
+            .all(|part| known.iter().any(|condition| condition.implies(part)))
+    }
+
+    /// Whether this condition implies another, where neither is a conjunction
+    fn implies(&self, other: &Condition) -> bool {
+        if self == other {
+            return true;
+        }
+        let text_comparison = self.text_comparison();
+        if self.base != other.base || text_comparison != other.text_comparison() {
+            return false;
+        }
+        let values: Vec<Value> = match &self.operation {
+            ConditionOperation::Equals(operand) => match operand.constant() {
+                Some(constant) => vec![constant],
+                None => return false,
+            },
+            ConditionOperation::In(list) => {
+                match list.iter().map(Operand::constant).collect::<Option<_>>() {
+                    Some(constants) => constants,
+                    None => return false,
+                }
+            }
+            ConditionOperation::Range(lower, upper) => {
+                return match &other.operation {
+                    ConditionOperation::Range(other_lower, other_upper) => {
+                        tighter(lower, other_lower, Ordering::Greater, text_comparison)
+                            && tighter(upper, other_upper, Ordering::Less, text_comparison)
+                    }
+                    _ => false,
+                }
+            }
+            _ => return false,
+        };
+        // every value this condition allows has to be allowed by the other
+        values.iter().all(|value| match &other.operation {
+            ConditionOperation::Equals(operand) => operand.constant().is_some_and(|constant| {
+                constants_equal(value, &constant, text_comparison) == Some(true)
+            }),
+            ConditionOperation::Nequals(operand) => operand.constant().is_some_and(|constant| {
+                constants_equal(value, &constant, text_comparison) == Some(false)
+            }),
+            ConditionOperation::In(list) => list.iter().any(|operand| {
+                operand.constant().is_some_and(|constant| {
+                    constants_equal(value, &constant, text_comparison) == Some(true)
+                })
+            }),
+            ConditionOperation::Like(pattern) => {
+                like(value, pattern, text_comparison).unwrap_or(false)
+            }
+            ConditionOperation::Range(lower, upper) => {
+                match (constant_bound(lower), constant_bound(upper)) {
+                    (Some(lower), Some(upper)) => {
+                        within(value, lower.as_ref(), upper.as_ref(), text_comparison)
+                            .unwrap_or(false)
+                    }
+                    _ => false,
+                }
+            }
+            _ => false,
+        })
     }
 
     /// Splits a conditional from a list of and statements c<sub>1</sub> AND c_<sub>2</sub> AND ... AND c<sub>n</sub>
@@ -167,9 +1042,14 @@ impl Condition {
         while let Self {
             base,
             operation: ConditionOperation::And(inner, next),
+            text_comparison,
         } = ptr
         {
-            let extracted = Condition::new(base, *inner);
+            let extracted = Condition {
+                base,
+                operation: *inner,
+                text_comparison,
+            };
             let flattened = extracted.split_and();
             output.extend(flattened);
             ptr = *next;
@@ -191,6 +1071,95 @@ impl Condition {
         ret
     }
 
+    /// The fields that must be equal to a constant for the condition to be true
+    pub fn fixed_fields(&self) -> Vec<Identifier> {
+        self.fixed_values()
+            .into_iter()
+            .map(|(field, _)| field)
+            .collect()
+    }
+
+    /// The fields that must be equal to a constant for the condition to be true, along with the
+    /// constants
+    pub fn fixed_values(&self) -> Vec<(Identifier, Value)> {
+        match &self.operation {
+            ConditionOperation::Equals(operand) => operand
+                .constant()
+                .map(|constant| (self.base.clone(), constant))
+                .into_iter()
+                .collect(),
+            ConditionOperation::And(inner, next) => {
+                let mut fixed = Condition::new(self.base.clone(), (**inner).clone()).fixed_values();
+                fixed.extend(next.fixed_values());
+                fixed
+            }
+            _ => vec![],
+        }
+    }
+
+    /// The fields that must be one of a list of constants for the condition to be true, along
+    /// with how many constants are in the list
+    pub fn listed_fields(&self) -> Vec<(Identifier, usize)> {
+        match &self.operation {
+            ConditionOperation::In(list)
+                if list.iter().all(|operand| operand.constant().is_some()) =>
+            {
+                vec![(self.base.clone(), list.len())]
+            }
+            ConditionOperation::And(inner, next) => {
+                let mut listed =
+                    Condition::new(self.base.clone(), (**inner).clone()).listed_fields();
+                listed.extend(next.listed_fields());
+                listed
+            }
+            _ => vec![],
+        }
+    }
+
+    /// The fields that must be within bounds of constants for the condition to be true, along
+    /// with the bounds
+    pub fn ranged_fields(&self) -> Vec<(Identifier, Bound<Value>, Bound<Value>)> {
+        match &self.operation {
+            ConditionOperation::Range(lower, upper) => {
+                let constant = |bound: &Bound<Operand>| match bound {
+                    Bound::Included(operand) => operand.constant().map(Bound::Included),
+                    Bound::Excluded(operand) => operand.constant().map(Bound::Excluded),
+                    Bound::Unbounded => Some(Bound::Unbounded),
+                };
+                match (constant(lower), constant(upper)) {
+                    (Some(lower), Some(upper)) => vec![(self.base.clone(), lower, upper)],
+                    _ => vec![],
+                }
+            }
+            ConditionOperation::And(inner, next) => {
+                let mut ranged =
+                    Condition::new(self.base.clone(), (**inner).clone()).ranged_fields();
+                ranged.extend(next.ranged_fields());
+                ranged
+            }
+            _ => vec![],
+        }
+    }
+
+    /// The text before the first wildcard of a `LIKE` pattern, which every matching value starts
+    /// with, if this is a `LIKE` condition whose pattern starts with any
+    pub fn like_prefix(&self) -> Option<&str> {
+        match &self.operation {
+            ConditionOperation::Like(pattern) => {
+                let end = pattern
+                    .find(|c| c == '%' || c == '_')
+                    .unwrap_or(pattern.len());
+                Some(&pattern[..end]).filter(|prefix| !prefix.is_empty())
+            }
+            _ => None,
+        }
+    }
+
+    /// The field the condition is on
+    pub fn base(&self) -> &Identifier {
+        &self.base
+    }
+
     /// Tests whether this is a conjunction or not
     pub fn not_conjunction(&self) -> bool {
         match &self.operation {
@@ -199,16 +1168,26 @@ impl Condition {
         }
     }
 
-    pub fn evaluate_on(&self, tuple: WrappedTuple) -> bool {
+    /// Evaluates this condition on a tuple
+    pub fn evaluate_on(&self, tuple: &WrappedTuple) -> Result<bool, InvalidOperation> {
         let left_value = &tuple[&self.base];
-        let right_value: Operand = {
-            match &self.operation {
-                ConditionOperation::Equals(eq) => eq.clone(),
-                ConditionOperation::Nequals(neq) => neq.clone(),
-                ConditionOperation::And(l, r) => {}
-                ConditionOperation::Or(_, _) => {}
-            }
-        };
+        self.operation
+            .evaluate_on(left_value, tuple, self.text_comparison())
+    }
+
+    /// Evaluates this condition on every tuple of a batch at once, giving whether each tuple
+    /// passes. Tuples the condition is invalid for don't pass.
+    pub fn evaluate_batch(&self, batch: &Batch) -> Vec<bool> {
+        self.evaluate_batch_helper(batch)
+            .into_iter()
+            .map(|passes| passes == Some(true))
+            .collect()
+    }
+
+    fn evaluate_batch_helper(&self, batch: &Batch) -> Vec<Option<bool>> {
+        let compare = batch.column(&self.base);
+        self.operation
+            .evaluate_batch(compare, batch, self.text_comparison())
     }
 }
 
@@ -221,6 +1200,7 @@ impl<I: Into<Identifier>> From<I> for Operand {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use rad_db_types::BitWidth;
 
     #[test]
     fn split_and() {
@@ -329,4 +1309,494 @@ mod tests {
             ]
         );
     }
+
+    #[test]
+    fn text_comparison_modes() {
+        let fields = vec![Identifier::new("name")];
+        let tuple = Tuple::from_iter(&[Value::from("Hello   ")]);
+        let wrapped = WrappedTuple::new(&fields, &tuple);
+
+        let exact = Condition::new(
+            "name",
+            ConditionOperation::Equals(Operand::String("hello".to_string())),
+        );
+        assert!(!exact.evaluate_on(&wrapped).unwrap());
+
+        let case_insensitive = exact
+            .clone()
+            .with_text_comparison(TextComparison::CaseInsensitive);
+        assert!(!case_insensitive.evaluate_on(&wrapped).unwrap());
+
+        let trimmed = exact
+            .clone()
+            .with_text_comparison(TextComparison::CaseInsensitiveTrimmed);
+        assert!(trimmed.evaluate_on(&wrapped).unwrap());
+
+        let not_equal = Condition::new(
+            "name",
+            ConditionOperation::Nequals(Operand::String("HELLO".to_string())),
+        )
+        .with_text_comparison(TextComparison::CaseInsensitiveTrimmed);
+        assert!(!not_equal.evaluate_on(&wrapped).unwrap());
+    }
+
+    #[test]
+    fn operands_cast_into_the_field() {
+        let fields = vec![Identifier::new("value")];
+        let tuples: Vec<Tuple> = vec![
+            Tuple::from_iter(&[Value::Optional(Some(Box::new(Value::from(5i32))))]),
+            Tuple::from_iter(&[Value::from("5")]),
+            Tuple::from_iter(&[Value::from(5i32)]),
+            Tuple::from_iter(&[Value::Optional(None)]),
+            Tuple::from_iter(&[Value::from(true)]),
+        ];
+        let equal = |operand: Operand| -> Vec<Option<bool>> {
+            let condition = Condition::new("value", ConditionOperation::Equals(operand));
+            tuples
+                .iter()
+                .map(|tuple| {
+                    condition
+                        .evaluate_on(&WrappedTuple::new(&fields, tuple))
+                        .ok()
+                })
+                .collect()
+        };
+        assert_eq!(
+            equal(Operand::UnsignedNumber(5)),
+            vec![Some(true), Some(true), Some(true), None, None]
+        );
+        assert_eq!(
+            equal(Operand::String("5".to_string())),
+            vec![Some(true), Some(true), Some(true), None, None]
+        );
+        assert_eq!(
+            equal(Operand::String("five".to_string())),
+            vec![None, Some(false), None, None, None]
+        );
+    }
+
+    #[test]
+    fn like() {
+        let fields = vec![Identifier::new("name")];
+        let tuples: Vec<Tuple> = vec![
+            Tuple::from_iter(&[Value::from("apple")]),
+            Tuple::from_iter(&[Value::from("Apricot")]),
+            Tuple::from_iter(&[Value::Optional(None)]),
+            Tuple::from_iter(&[Value::from(3u64)]),
+        ];
+        let matching = |condition: &Condition| -> Vec<Option<bool>> {
+            tuples
+                .iter()
+                .map(|tuple| {
+                    condition
+                        .evaluate_on(&WrappedTuple::new(&fields, tuple))
+                        .ok()
+                })
+                .collect()
+        };
+
+        let prefix = Condition::new("name", ConditionOperation::Like("ap%".to_string()));
+        assert_eq!(
+            matching(&prefix),
+            vec![Some(true), Some(false), Some(false), None]
+        );
+        let ignoring_case = prefix
+            .clone()
+            .with_text_comparison(TextComparison::CaseInsensitive);
+        assert_eq!(
+            matching(&ignoring_case),
+            vec![Some(true), Some(true), Some(false), None]
+        );
+        let batch = Batch::from_tuples(fields.clone(), tuples.clone());
+        assert_eq!(
+            ignoring_case.evaluate_batch(&batch),
+            vec![true, true, false, false]
+        );
+
+        assert_eq!(prefix.like_prefix(), Some("ap"));
+        let wildcard_first = Condition::new("name", ConditionOperation::Like("_pple".to_string()));
+        assert_eq!(wildcard_first.like_prefix(), None);
+        assert_eq!(
+            matching(&wildcard_first),
+            vec![Some(true), Some(false), Some(false), None]
+        );
+    }
+
+    #[test]
+    fn areas() {
+        let fields = vec![Identifier::new("location")];
+        let tuples: Vec<Tuple> = vec![
+            Tuple::from_iter(&[Point::new(1.0, 1.0).into()]),
+            Tuple::from_iter(&[Point::new(3.0, 4.0).into()]),
+            Tuple::from_iter(&[Point::new(-6.0, 0.0).into()]),
+            Tuple::from_iter(&[Value::Optional(None)]),
+            Tuple::from_iter(&[Value::from(3u64)]),
+        ];
+        let matching = |condition: &Condition| -> Vec<Option<bool>> {
+            tuples
+                .iter()
+                .map(|tuple| {
+                    condition
+                        .evaluate_on(&WrappedTuple::new(&fields, tuple))
+                        .ok()
+                })
+                .collect()
+        };
+
+        let near = Condition::within_radius("location", Point::new(0.0, 0.0), 5.0);
+        assert_eq!(
+            matching(&near),
+            vec![Some(true), Some(true), Some(false), Some(false), None]
+        );
+        let square = Polygon::new(vec![
+            Point::new(0.0, 0.0),
+            Point::new(2.0, 0.0),
+            Point::new(2.0, 2.0),
+            Point::new(0.0, 2.0),
+        ]);
+        let inside = Condition::within_polygon("location", square);
+        assert_eq!(
+            matching(&inside),
+            vec![Some(true), Some(false), Some(false), Some(false), None]
+        );
+        let batch = Batch::from_tuples(fields.clone(), tuples.clone());
+        assert_eq!(
+            near.evaluate_batch(&batch),
+            vec![true, true, false, false, false]
+        );
+    }
+
+    #[test]
+    fn subnets() {
+        let fields = vec![Identifier::new("source")];
+        let tuples: Vec<Tuple> = vec![
+            Tuple::from_iter(&[Inet::parse("192.168.0.7").unwrap().into()]),
+            Tuple::from_iter(&[Inet::parse("192.168.0.128/25").unwrap().into()]),
+            Tuple::from_iter(&[Inet::parse("192.168.1.7").unwrap().into()]),
+            Tuple::from_iter(&[Inet::parse("::1").unwrap().into()]),
+            Tuple::from_iter(&[Value::Optional(None)]),
+            Tuple::from_iter(&[Value::from("192.168.0.7")]),
+        ];
+        let local = Condition::within_subnet("source", Inet::parse("192.168.0.0/24").unwrap());
+        let matching: Vec<Option<bool>> = tuples
+            .iter()
+            .map(|tuple| local.evaluate_on(&WrappedTuple::new(&fields, tuple)).ok())
+            .collect();
+        assert_eq!(
+            matching,
+            vec![
+                Some(true),
+                Some(true),
+                Some(false),
+                Some(false),
+                Some(false),
+                None
+            ]
+        );
+        let batch = Batch::from_tuples(fields.clone(), tuples.clone());
+        assert_eq!(
+            local.evaluate_batch(&batch),
+            vec![true, true, false, false, false, false]
+        );
+    }
+
+    #[test]
+    fn bits() {
+        let fields = vec![Identifier::new("flags")];
+        let flags = |bits: &str| -> Value {
+            Text::BitString(BitString::parse(bits).unwrap(), BitWidth::Fixed(4)).into()
+        };
+        let tuples: Vec<Tuple> = vec![
+            Tuple::from_iter(&[flags("1010")]),
+            Tuple::from_iter(&[flags("1110")]),
+            Tuple::from_iter(&[flags("0110")]),
+            Tuple::from_iter(&[Value::Optional(None)]),
+            Tuple::from_iter(&[Value::from(10u8)]),
+        ];
+        let matching = |condition: &Condition| -> Vec<Option<bool>> {
+            tuples
+                .iter()
+                .map(|tuple| {
+                    condition
+                        .evaluate_on(&WrappedTuple::new(&fields, tuple))
+                        .ok()
+                })
+                .collect()
+        };
+
+        let first = Condition::bit_set("flags", 0);
+        assert_eq!(
+            matching(&first),
+            vec![Some(true), Some(true), Some(false), Some(false), None]
+        );
+        let past_end = Condition::bit_set("flags", 4);
+        assert_eq!(
+            matching(&past_end),
+            vec![None, None, None, Some(false), None]
+        );
+        let mask = Condition::has_bits("flags", BitString::parse("0110").unwrap());
+        assert_eq!(
+            matching(&mask),
+            vec![Some(false), Some(true), Some(true), Some(false), None]
+        );
+        let batch = Batch::from_tuples(fields.clone(), tuples.clone());
+        assert_eq!(
+            mask.evaluate_batch(&batch),
+            vec![false, true, true, false, false]
+        );
+    }
+
+    #[test]
+    fn in_list() {
+        let fields = vec![Identifier::new("value"), Identifier::new("other")];
+        let tuples: Vec<Tuple> = vec![
+            Tuple::from_iter(&[Value::from(3u8), Value::from(0u64)]),
+            Tuple::from_iter(&[Numeric::Double(2.0).into(), Value::from(0u64)]),
+            Tuple::from_iter(&[Numeric::Double(2.5).into(), Value::from(0u64)]),
+            Tuple::from_iter(&[Value::from("Abc "), Value::from(0u64)]),
+            Tuple::from_iter(&[Value::from(7i64), Value::from(7u64)]),
+            Tuple::from_iter(&[Value::Optional(None), Value::from(0u64)]),
+            Tuple::from_iter(&[Value::from(true), Value::from(0u64)]),
+        ];
+        let condition = Condition::new(
+            "value",
+            ConditionOperation::In(vec![
+                Operand::UnsignedNumber(3),
+                Operand::SignedNumber(2),
+                Operand::String("abc".to_string()),
+                Operand::Id(Identifier::new("other")),
+            ]),
+        )
+        .with_text_comparison(TextComparison::CaseInsensitiveTrimmed);
+
+        let matching: Vec<bool> = tuples
+            .iter()
+            .map(|tuple| {
+                matches!(
+                    condition.evaluate_on(&WrappedTuple::new(&fields, tuple)),
+                    Ok(true)
+                )
+            })
+            .collect();
+        assert_eq!(matching, vec![true, true, false, true, true, false, false]);
+        let batch = Batch::from_tuples(fields.clone(), tuples.clone());
+        assert_eq!(condition.evaluate_batch(&batch), matching);
+
+        assert!(condition.listed_fields().is_empty());
+        let constants = Condition::new(
+            "value",
+            ConditionOperation::In(vec![Operand::UnsignedNumber(1), Operand::Float(1.5)]),
+        );
+        assert_eq!(
+            constants.listed_fields(),
+            vec![(Identifier::new("value"), 2)]
+        );
+        let empty = Condition::new("value", ConditionOperation::In(vec![]));
+        assert_eq!(empty.simplify(&[]), Simplified::Never);
+    }
+
+    #[test]
+    fn ranges() {
+        let fields = vec![Identifier::new("value"), Identifier::new("limit")];
+        let tuples: Vec<Tuple> = vec![
+            Tuple::from_iter(&[Value::from(1u8), Value::from(5u64)]),
+            Tuple::from_iter(&[Value::from(5i64), Value::from(5u64)]),
+            Tuple::from_iter(&[Numeric::Double(4.5).into(), Value::from(4u64)]),
+            Tuple::from_iter(&[Value::Optional(None), Value::from(5u64)]),
+            Tuple::from_iter(&[Value::from(2u64), Value::from(9u64)]),
+        ];
+        let matching = |condition: &Condition| -> Vec<bool> {
+            let matching: Vec<bool> = tuples
+                .iter()
+                .map(|tuple| {
+                    matches!(
+                        condition.evaluate_on(&WrappedTuple::new(&fields, tuple)),
+                        Ok(true)
+                    )
+                })
+                .collect();
+            let batch = Batch::from_tuples(fields.clone(), tuples.clone());
+            assert_eq!(condition.evaluate_batch(&batch), matching);
+            matching
+        };
+
+        let between = Condition::between("value", Operand::UnsignedNumber(2), Operand::Float(4.5));
+        assert_eq!(matching(&between), vec![false, false, true, false, true]);
+        let below_limit = Condition::range(
+            "value",
+            Bound::Excluded(Operand::SignedNumber(1)),
+            Bound::Excluded(Operand::from("limit")),
+        );
+        assert_eq!(
+            matching(&below_limit),
+            vec![false, false, false, false, true]
+        );
+        assert!(below_limit.ranged_fields().is_empty());
+        assert_eq!(
+            between.ranged_fields(),
+            vec![(
+                Identifier::new("value"),
+                Bound::Included(Value::from(2u64)),
+                Bound::Included(Numeric::Double(4.5).into())
+            )]
+        );
+
+        let text_fields = vec![Identifier::new("name")];
+        let name = Tuple::from_iter(&[Value::from("Bob")]);
+        let from_a_to_c = Condition::range(
+            "name",
+            Bound::Included(Operand::String("a".to_string())),
+            Bound::Excluded(Operand::String("c".to_string())),
+        );
+        let wrapped = WrappedTuple::new(&text_fields, &name);
+        assert!(!from_a_to_c.evaluate_on(&wrapped).unwrap());
+        let ignoring_case = from_a_to_c.with_text_comparison(TextComparison::CaseInsensitive);
+        assert!(ignoring_case.evaluate_on(&wrapped).unwrap());
+    }
+
+    #[test]
+    fn simplify() {
+        let fields = vec![
+            (Identifier::new("id"), Type::from(0u64)),
+            (Identifier::new("score"), Type::from(Numeric::Double(0.0))),
+        ];
+        let cond = |base: &str, operation| Condition::new(base, operation);
+        let itself = || cond("id", ConditionOperation::Equals(Operand::from("id")));
+        let not_itself = || cond("id", ConditionOperation::Nequals(Operand::from("id")));
+        let one = || cond("id", ConditionOperation::Equals(Operand::UnsignedNumber(1)));
+
+        assert_eq!(itself().simplify(&fields), Simplified::Always);
+        assert_eq!(not_itself().simplify(&fields), Simplified::Never);
+        // NaN isn't equal to itself
+        let score = cond("score", ConditionOperation::Equals(Operand::from("score")));
+        assert_eq!(
+            score.simplify(&fields),
+            Simplified::Condition(score.clone())
+        );
+
+        assert_eq!(
+            Condition::and(itself(), one()).simplify(&fields),
+            Simplified::Condition(one())
+        );
+        assert_eq!(
+            Condition::and(one(), not_itself()).simplify(&fields),
+            Simplified::Never
+        );
+        assert_eq!(
+            Condition::or(one(), itself()).simplify(&fields),
+            Simplified::Always
+        );
+        assert_eq!(
+            Condition::or(not_itself(), Condition::and(one(), one())).simplify(&fields),
+            Simplified::Condition(one())
+        );
+        let either = Condition::or(one(), score.clone());
+        assert_eq!(either.simplify(&fields), Simplified::Condition(either));
+    }
+
+    #[test]
+    fn implications() {
+        let eq = |field, operand| Condition::new(field, ConditionOperation::Equals(operand));
+        let not_deleted = eq("deleted", Operand::Boolean(false));
+        let adult = Condition::range(
+            "age",
+            Bound::Included(Operand::UnsignedNumber(18)),
+            Bound::Unbounded,
+        );
+
+        assert!(not_deleted.implied_by(std::slice::from_ref(&not_deleted)));
+        assert!(not_deleted.implied_by(&[Condition::and(
+            eq("age", Operand::UnsignedNumber(30)),
+            not_deleted.clone()
+        )]));
+        assert!(!not_deleted.implied_by(&[eq("deleted", Operand::Boolean(true))]));
+        assert!(!not_deleted.implied_by(&[]));
+
+        assert!(adult.implied_by(&[eq("age", Operand::UnsignedNumber(30))]));
+        assert!(!adult.implied_by(&[eq("age", Operand::UnsignedNumber(12))]));
+        assert!(adult.implied_by(&[Condition::between(
+            "age",
+            Operand::UnsignedNumber(18),
+            Operand::UnsignedNumber(65)
+        )]));
+        assert!(!adult.implied_by(&[Condition::range(
+            "age",
+            Bound::Excluded(Operand::UnsignedNumber(17)),
+            Bound::Unbounded
+        )]));
+        assert!(adult.implied_by(&[Condition::new(
+            "age",
+            ConditionOperation::In(vec![
+                Operand::UnsignedNumber(20),
+                Operand::UnsignedNumber(40)
+            ])
+        )]));
+
+        // both parts of a conjunction have to be implied
+        let both = Condition::and(adult.clone(), not_deleted.clone());
+        assert!(both.implied_by(&[eq("age", Operand::UnsignedNumber(30)), not_deleted.clone()]));
+        assert!(!both.implied_by(&[not_deleted]));
+    }
+
+    #[test]
+    fn contradictions() {
+        let eq = |operand| Condition::new("x", ConditionOperation::Equals(operand));
+        let neq = |operand| Condition::new("x", ConditionOperation::Nequals(operand));
+
+        assert!(Condition::are_contradictory(&[
+            eq(Operand::UnsignedNumber(1)),
+            eq(Operand::SignedNumber(2))
+        ]));
+        assert!(!Condition::are_contradictory(&[
+            eq(Operand::UnsignedNumber(1)),
+            eq(Operand::Float(1.0))
+        ]));
+        assert!(Condition::are_contradictory(&[Condition::and(
+            eq(Operand::Boolean(true)),
+            neq(Operand::Boolean(true))
+        )]));
+        assert!(!Condition::are_contradictory(&[
+            eq(Operand::UnsignedNumber(1)),
+            Condition::new("y", ConditionOperation::Equals(Operand::UnsignedNumber(2)))
+        ]));
+        assert!(!Condition::are_contradictory(&[Condition::or(
+            eq(Operand::UnsignedNumber(1)),
+            eq(Operand::UnsignedNumber(2))
+        )]));
+
+        let case_insensitive =
+            |operand| eq(operand).with_text_comparison(TextComparison::CaseInsensitive);
+        assert!(!Condition::are_contradictory(&[
+            case_insensitive(Operand::String("a".to_string())),
+            case_insensitive(Operand::Char('A'))
+        ]));
+        assert!(Condition::are_contradictory(&[
+            case_insensitive(Operand::String("a".to_string())),
+            case_insensitive(Operand::String("b".to_string()))
+        ]));
+        assert!(!Condition::are_contradictory(&[
+            eq(Operand::String("a".to_string())),
+            case_insensitive(Operand::String("A".to_string()))
+        ]));
+
+        let case_insensitive_neq =
+            |operand| neq(operand).with_text_comparison(TextComparison::CaseInsensitive);
+        assert!(Condition::are_contradictory(&[
+            case_insensitive(Operand::String("a".to_string())),
+            case_insensitive_neq(Operand::String("A".to_string()))
+        ]));
+        assert!(Condition::are_contradictory(&[
+            eq(Operand::String("a".to_string())),
+            case_insensitive_neq(Operand::String("a".to_string()))
+        ]));
+        // 'A' is case insensitively equal to 'a' without being exactly equal to it
+        assert!(!Condition::are_contradictory(&[
+            case_insensitive(Operand::String("a".to_string())),
+            neq(Operand::String("a".to_string()))
+        ]));
+        assert!(!Condition::are_contradictory(&[
+            case_insensitive(Operand::String("a".to_string())),
+            neq(Operand::String("A".to_string()))
+        ]));
+    }
 }
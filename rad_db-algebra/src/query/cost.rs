@@ -0,0 +1,172 @@
+//! The cost model the optimizer compares plans with.
+//!
+//! Costs are in units of reading one block of a relation from its file, so a cost of 10 is about
+//! as much work as reading 10 blocks. The cost of a node only counts the work the node does
+//! itself: reading blocks, comparing tuples, and inserting tuples into hash tables. The defaults
+//! suit blocks read from a disk, and can be tuned to calibrate the model for other hardware.
+
+use crate::query::hash_join::JoinSide;
+use crate::query::query_node::{as_slices, QueryNode, QueryOperation};
+use rad_db_structure::relations::Relation;
+
+/// The estimated costs of the work the operations of a query do
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CostModel {
+    block_read: f64,
+    tuple_comparison: f64,
+    hash_insert: f64,
+}
+
+impl CostModel {
+    /// The default cost of reading a block of a relation
+    pub const BLOCK_READ_COST: f64 = 1.0;
+    /// The default cost of comparing a tuple against a condition or a key
+    pub const TUPLE_COMPARISON_COST: f64 = 0.01;
+    /// The default cost of inserting a tuple into the hash table of a hash join
+    pub const HASH_INSERT_COST: f64 = 0.5;
+
+    pub fn block_read(&self) -> f64 {
+        self.block_read
+    }
+
+    pub fn tuple_comparison(&self) -> f64 {
+        self.tuple_comparison
+    }
+
+    pub fn hash_insert(&self) -> f64 {
+        self.hash_insert
+    }
+
+    pub fn with_block_read(mut self, cost: f64) -> Self {
+        self.block_read = cost;
+        self
+    }
+
+    pub fn with_tuple_comparison(mut self, cost: f64) -> Self {
+        self.tuple_comparison = cost;
+        self
+    }
+
+    pub fn with_hash_insert(mut self, cost: f64) -> Self {
+        self.hash_insert = cost;
+        self
+    }
+
+    /// The cost of reading every block of the relation and checking each of its tuples
+    pub fn scan(&self, relation: &Relation) -> f64 {
+        relation.block_count() as f64 * self.block_read
+            + Self::tuples_of(relation) as f64 * self.tuple_comparison
+    }
+
+    /// The cost of reading the one block a key hashes into and finding the key among its tuples
+    pub fn key_lookup(&self, relation: &Relation) -> f64 {
+        self.block_read
+            + relation.bucket_size().min(Self::tuples_of(relation)) as f64 * self.tuple_comparison
+    }
+
+    /// The cost of reading tuples through an ordered index, which reads a block for each of them
+    pub fn index_range(&self, tuples: usize) -> f64 {
+        tuples as f64 * (self.block_read + self.tuple_comparison)
+    }
+
+    /// The cost of comparing every pair of tuples of the inputs of a join
+    pub fn nested_loop_join(&self, left: usize, right: usize) -> f64 {
+        left.saturating_mul(right) as f64 * self.tuple_comparison
+    }
+
+    /// The cost of loading one input of a join into a hash table and looking up the tuples of the
+    /// other input in it
+    pub fn hash_join(&self, build: usize, probe: usize) -> f64 {
+        build as f64 * self.hash_insert + probe as f64 * self.tuple_comparison
+    }
+
+    /// The cost of the work the node does itself, not counting the work of its children
+    pub fn cost_of(&self, node: &QueryNode<'_>) -> f64 {
+        let input = |index: usize| {
+            node.children()
+                .get(index)
+                .map_or(0, |child| child.approximate_created_tuples())
+        };
+        match node.query_operation() {
+            QueryOperation::Source(source) => self.scan(source.relation()),
+            QueryOperation::HistoricalSource(relation, _) => self.scan(relation),
+            QueryOperation::PartitionedSource(_) | QueryOperation::ForeignSource(_) => {
+                node.approximate_created_tuples() as f64 * self.tuple_comparison
+            }
+            QueryOperation::KeyLookup(relation, _) => self.key_lookup(relation),
+            QueryOperation::KeyRange(relation, lower, upper) => {
+                self.index_range(relation.key_range_len(as_slices(lower), as_slices(upper)))
+            }
+            QueryOperation::IndexScan(relation, index, lower, upper) => self
+                .index_range(relation.index_range_len(index, as_slices(lower), as_slices(upper))),
+            // the index holds the values, so no blocks are read
+            QueryOperation::IndexOnlyScan(relation, index, lower, upper) => {
+                relation.index_range_len(index, as_slices(lower), as_slices(upper)) as f64
+                    * self.tuple_comparison
+            }
+            QueryOperation::Selection(_) => input(0) as f64 * self.tuple_comparison,
+            QueryOperation::HashJoin(_, build) => {
+                let (left, right) = (input(0), input(1));
+                match build {
+                    JoinSide::Left => self.hash_join(left, right),
+                    JoinSide::Right => self.hash_join(right, left),
+                }
+            }
+            QueryOperation::PartitionWiseJoin(_) => {
+                self.hash_join(input(0).min(input(1)), input(0).max(input(1)))
+            }
+            QueryOperation::CrossProduct
+            | QueryOperation::InnerJoin(_)
+            | QueryOperation::LeftJoin(_)
+            | QueryOperation::RightJoin(_)
+            | QueryOperation::FullJoin(_)
+            | QueryOperation::NaturalJoin
+            | QueryOperation::NegatedSubquery(..)
+            | QueryOperation::AntiJoin(..) => self.nested_loop_join(input(0), input(1)),
+            QueryOperation::Sort(_) => {
+                let tuples = input(0) as f64;
+                tuples * tuples.max(1.0).log2() * self.tuple_comparison
+            }
+            QueryOperation::TopN(_, limit) => {
+                input(0) as f64 * (*limit as f64 + 1.0).log2() * self.tuple_comparison
+            }
+            QueryOperation::Projection(_)
+            | QueryOperation::Recursive
+            | QueryOperation::WorkingTable
+            | QueryOperation::Empty
+            | QueryOperation::Pivot(_)
+            | QueryOperation::Unpivot(_)
+            | QueryOperation::Sample(_)
+            | QueryOperation::Limit(_)
+            | QueryOperation::Count
+            | QueryOperation::RelationCount(_) => 0.0,
+        }
+    }
+
+    /// The cost of the node and every node below it
+    pub fn total_cost(&self, node: &QueryNode<'_>) -> f64 {
+        self.cost_of(node)
+            + node
+                .children()
+                .iter()
+                .map(|child| self.total_cost(child))
+                .sum::<f64>()
+    }
+
+    /// The number of tuples of the relation, from its statistics if it has them
+    fn tuples_of(relation: &Relation) -> usize {
+        relation
+            .statistics()
+            .map_or(relation.len(), |statistics| statistics.tuples())
+    }
+}
+
+impl Default for CostModel {
+    fn default() -> Self {
+        CostModel {
+            block_read: Self::BLOCK_READ_COST,
+            tuple_comparison: Self::TUPLE_COMPARISON_COST,
+            hash_insert: Self::HASH_INSERT_COST,
+        }
+    }
+}
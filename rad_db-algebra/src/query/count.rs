@@ -0,0 +1,153 @@
+//! Counting the tuples of a relation a condition is true for, and checking whether there are any,
+//! without materializing them.
+//!
+//! Conditions that are true for every tuple are answered from the lengths of the blocks of the
+//! relation. Otherwise the tuples are read through the primary key or an index when the optimizer
+//! would choose to for a selection with the condition, and are checked one at a time, so finding
+//! whether any tuple matches stops at the first one that does.
+
+use rad_db_structure::identifier::Identifier;
+use rad_db_structure::relations::Relation;
+use rad_db_structure::tuple::Tuple;
+
+use crate::error::MissingFieldError;
+use crate::query::conditions::{Condition, Simplified};
+use crate::query::cost::CostModel;
+use crate::query::optimization::Optimizer;
+use crate::query::query_node::{as_slices, QueryNode, QueryOperation};
+use crate::wrapped_tuple::WrappedTuple;
+
+/// The number of tuples of the relation the condition is true for
+pub fn count_where(relation: &Relation, condition: &Condition) -> Result<usize, MissingFieldError> {
+    Ok(match matching(relation, condition)? {
+        Matching::All => relation.len(),
+        Matching::None => 0,
+        Matching::Some(tuples) => tuples.count(),
+    })
+}
+
+/// Whether the condition is true for any tuple of the relation
+pub fn exists_where(relation: &Relation, condition: &Condition) -> Result<bool, MissingFieldError> {
+    Ok(match matching(relation, condition)? {
+        Matching::All => !relation.is_empty(),
+        Matching::None => false,
+        Matching::Some(mut tuples) => tuples.next().is_some(),
+    })
+}
+
+enum Matching<'r> {
+    All,
+    None,
+    Some(Box<dyn Iterator<Item = Tuple> + 'r>),
+}
+
+/// The tuples of the relation the condition is true for
+fn matching<'r>(
+    relation: &'r Relation,
+    condition: &Condition,
+) -> Result<Matching<'r>, MissingFieldError> {
+    let selection = QueryNode::select_on_condition(QueryNode::source(relation), condition.clone());
+    let fields: Vec<Identifier> = selection
+        .resulting_relation()
+        .iter()
+        .map(|(field, _)| field.clone())
+        .collect();
+    let missing = condition
+        .relevant_fields()
+        .into_iter()
+        .find(|field| !fields.contains(field));
+    if let Some(field) = missing {
+        return Err(MissingFieldError::new(field, None));
+    }
+    let condition = match condition.simplify(selection.resulting_relation()) {
+        Simplified::Always => return Ok(Matching::All),
+        Simplified::Never => return Ok(Matching::None),
+        Simplified::Condition(condition) => condition,
+    };
+
+    let access = Optimizer::access_path(&selection, &CostModel::default());
+    let tuples: Box<dyn Iterator<Item = Tuple> + 'r> = match access {
+        Some(QueryOperation::KeyLookup(relation, key)) => {
+            Box::new(relation.find_by_key(&key).into_iter())
+        }
+        Some(QueryOperation::KeyRange(relation, lower, upper)) => {
+            Box::new(relation.key_range(as_slices(&lower), as_slices(&upper)))
+        }
+        Some(QueryOperation::IndexScan(relation, index, lower, upper)) => {
+            Box::new(relation.index_range(&index, as_slices(&lower), as_slices(&upper)))
+        }
+        _ => Box::new(relation.tuples()),
+    };
+    Ok(Matching::Some(Box::new(tuples.filter(move |tuple| {
+        let wrapped = WrappedTuple::new(&fields, tuple);
+        matches!(condition.evaluate_on(&wrapped), Ok(true))
+    }))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::query::conditions::{ConditionOperation, Operand};
+    use crate::query::query_node::COUNT_FIELD;
+    use rad_db_structure::key::primary::PrimaryKeyDefinition;
+    use rad_db_types::{Type, Value};
+    use std::iter::FromIterator;
+
+    #[test]
+    fn count_and_exists() {
+        let mut relation = Relation::new_volatile(
+            Identifier::new("counted"),
+            vec![("id", Type::from(0u64)), ("group", Type::from(0u64))],
+            8,
+            PrimaryKeyDefinition::new(vec![0]),
+        );
+        for i in 0..100u64 {
+            relation
+                .insert(Tuple::from_iter(&[Value::from(i), Value::from(i % 4)]))
+                .unwrap();
+        }
+        let equals = |field: &str, value: u64| {
+            Condition::new(
+                field,
+                ConditionOperation::Equals(Operand::UnsignedNumber(value)),
+            )
+        };
+
+        assert_eq!(count_where(&relation, &equals("group", 1)).unwrap(), 25);
+        assert_eq!(count_where(&relation, &equals("id", 42)).unwrap(), 1);
+        assert_eq!(
+            count_where(
+                &relation,
+                &Condition::new("id", ConditionOperation::Equals(Operand::from("id")))
+            )
+            .unwrap(),
+            100
+        );
+        assert!(exists_where(&relation, &equals("group", 3)).unwrap());
+        assert!(!exists_where(&relation, &equals("group", 4)).unwrap());
+        assert!(!exists_where(&relation, &equals("id", 100)).unwrap());
+        assert!(count_where(&relation, &equals("missing", 0)).is_err());
+
+        let count = QueryNode::count_tuples(QueryNode::source(&relation)).optimized();
+        assert!(
+            count.explain().contains("RelationCount counted"),
+            "{}",
+            count.explain()
+        );
+        let counted: Vec<Tuple> = count.execute_query().into_iter().collect();
+        assert_eq!(counted, vec![Tuple::from_iter(&[Value::from(100u64)])]);
+
+        let filtered = QueryNode::count_tuples(QueryNode::select_on_condition(
+            QueryNode::source(&relation),
+            equals("group", 2),
+        ))
+        .optimized();
+        assert!(!filtered.explain().contains("RelationCount"));
+        assert_eq!(
+            filtered.resulting_relation()[0].0,
+            Identifier::new(COUNT_FIELD)
+        );
+        let counted: Vec<Tuple> = filtered.execute_query().into_iter().collect();
+        assert_eq!(counted, vec![Tuple::from_iter(&[Value::from(25u64)])]);
+    }
+}
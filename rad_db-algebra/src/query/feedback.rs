@@ -0,0 +1,230 @@
+//! Feedback on how many tuples the selections of a relation actually produced.
+//!
+//! The estimates of selections are often far off, such as when values are skewed or fields are
+//! correlated. Queries executed with a [CardinalityFeedback] record how many tuples each of their
+//! selections of a relation produced, keyed by the relation and the conditions of the selections,
+//! and queries optimized with it later hint those selections with the number of tuples they
+//! produced, so that repeated queries are planned with the actual numbers.
+
+use crate::query::conditions::Condition;
+use crate::query::hints::Hint;
+use crate::query::query_node::{QueryNode, QueryOperation};
+use crate::query::rules::{Rule, RuleSet, Traversal};
+use rad_db_structure::identifier::Identifier;
+use std::collections::HashMap;
+
+/// What feedback is kept under: the name of a relation, and the conditions its tuples are
+/// selected by
+pub(super) type FeedbackKey = (Identifier, String);
+
+/// The number of tuples a selection of a relation produced
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Observation {
+    estimated: usize,
+    actual: usize,
+    executions: usize,
+}
+
+impl Observation {
+    /// The number of tuples the selection was estimated to produce when it was last executed
+    pub fn estimated(&self) -> usize {
+        self.estimated
+    }
+
+    /// The number of tuples the selection produced when it was last executed
+    pub fn actual(&self) -> usize {
+        self.actual
+    }
+
+    /// The number of times the selection was executed
+    pub fn executions(&self) -> usize {
+        self.executions
+    }
+}
+
+/// The number of tuples selections of relations produced when they were executed
+#[derive(Debug, Clone, Default)]
+pub struct CardinalityFeedback {
+    observations: HashMap<FeedbackKey, Observation>,
+}
+
+impl CardinalityFeedback {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The observation of a selection of a relation, if one like it was executed
+    pub fn observation(&self, selection: &QueryNode) -> Option<&Observation> {
+        self.observations.get(&feedback_key(selection)?)
+    }
+
+    pub(super) fn record(&mut self, key: FeedbackKey, estimated: usize, actual: usize) {
+        let observation = self.observations.entry(key).or_insert(Observation {
+            estimated,
+            actual,
+            executions: 0,
+        });
+        observation.estimated = estimated;
+        observation.actual = actual;
+        observation.executions += 1;
+    }
+
+    /// The number of selections observed
+    pub fn len(&self) -> usize {
+        self.observations.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.observations.is_empty()
+    }
+
+    /// Forgets every observation, such as after the relations were changed a lot
+    pub fn clear(&mut self) {
+        self.observations.clear();
+    }
+
+    /// The default rules of the optimizer, which also hint the selections observed with the number
+    /// of tuples they produced
+    pub fn rules(&self) -> RuleSet {
+        let mut rules = RuleSet::default();
+        // selections are only in the form they're observed in once their conditions are split
+        rules.insert_after("remove_contradictions", ApplyFeedback(self.clone()));
+        rules
+    }
+}
+
+/// Hints the selections of relations that were observed with the number of tuples they produced
+pub struct ApplyFeedback(pub CardinalityFeedback);
+
+impl Rule for ApplyFeedback {
+    fn name(&self) -> &str {
+        "apply_feedback"
+    }
+
+    fn traversal(&self) -> Traversal {
+        Traversal::TopDown
+    }
+
+    fn matches(&self, node: &QueryNode<'_>) -> bool {
+        matches!(node.query_operation(), QueryOperation::Selection(_))
+    }
+
+    fn apply(&self, node: &mut QueryNode<'_>) -> bool {
+        match self.0.observation(node) {
+            Some(observation) if node.cardinality_hint() != Some(observation.actual) => {
+                node.add_hint(Hint::Cardinality(observation.actual));
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+/// The key of a selection directly above a scan of a relation, which may read the relation
+/// through a key or an index and have its fields projected. The conditions are split on `AND`
+/// and ordered, so that a selection has the same key before and after it's optimized.
+pub(super) fn feedback_key(selection: &QueryNode) -> Option<FeedbackKey> {
+    if !matches!(selection.query_operation(), QueryOperation::Selection(_)) {
+        return None;
+    }
+    let mut conditions: Vec<String> = vec![];
+    let mut node = selection;
+    loop {
+        match node.query_operation() {
+            QueryOperation::Selection(condition) => conditions.extend(
+                condition
+                    .clone()
+                    .split_and()
+                    .iter()
+                    .map(|condition: &Condition| format!("{:?}", condition)),
+            ),
+            QueryOperation::Projection(_) => {}
+            _ => break,
+        }
+        node = node.children()[0];
+    }
+    let relation = match node.query_operation() {
+        QueryOperation::Source(source) => source.relation(),
+        QueryOperation::KeyLookup(relation, _)
+        | QueryOperation::KeyRange(relation, ..)
+        | QueryOperation::IndexScan(relation, ..)
+        | QueryOperation::IndexOnlyScan(relation, ..) => relation,
+        _ => return None,
+    };
+    conditions.sort();
+    conditions.dedup();
+    Some((relation.name().clone(), conditions.join(" AND ")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::query::conditions::{ConditionOperation, Operand};
+    use rad_db_structure::key::primary::PrimaryKeyDefinition;
+    use rad_db_structure::relations::Relation;
+    use rad_db_structure::tuple::Tuple;
+    use rad_db_types::{Type, Value};
+    use std::iter::FromIterator;
+
+    #[test]
+    fn skewed_selections() {
+        let mut relation = Relation::new_volatile(
+            Identifier::new("skewed"),
+            vec![("id", Type::from(0u64)), ("kind", Type::from(0u64))],
+            64,
+            PrimaryKeyDefinition::new(vec![0]),
+        );
+        for i in 0..200u64 {
+            let kind = if i % 2 == 0 { 0 } else { i };
+            relation
+                .insert(Tuple::from_iter(&[Value::from(i), Value::from(kind)]))
+                .unwrap();
+        }
+        let query = || {
+            let equals = |field: &str, value: u64| {
+                Condition::new(
+                    field,
+                    ConditionOperation::Equals(Operand::UnsignedNumber(value)),
+                )
+            };
+            let nequals = Condition::new(
+                "id",
+                ConditionOperation::Nequals(Operand::UnsignedNumber(3)),
+            );
+            QueryNode::select_on_condition(
+                QueryNode::source(&relation),
+                Condition::and(equals("kind", 0), nequals),
+            )
+        };
+
+        let mut feedback = CardinalityFeedback::new();
+        assert!(feedback.observation(&query()).is_none());
+        let produced = query()
+            .optimized()
+            .execute_with_feedback(&mut feedback)
+            .into_iter()
+            .count();
+        assert_eq!(produced, 100);
+
+        // the conditions were split into a selection of each when the query was optimized
+        assert_eq!(feedback.len(), 2);
+        let observation = *feedback.observation(&query()).unwrap();
+        assert_eq!(observation.actual(), 100);
+        assert_ne!(observation.estimated(), 100);
+        assert_eq!(observation.executions(), 1);
+
+        let mut optimized = query();
+        optimized.optimize_with_feedback(&feedback);
+        assert_eq!(optimized.approximate_created_tuples(), 100);
+        assert_eq!(
+            optimized
+                .execute_with_feedback(&mut feedback)
+                .into_iter()
+                .count(),
+            100
+        );
+        let observation = *feedback.observation(&query()).unwrap();
+        assert_eq!(observation.estimated(), 100);
+        assert_eq!(observation.executions(), 2);
+    }
+}
@@ -0,0 +1,149 @@
+//! Snapshots of optimized plans. Each test optimizes a query and compares its plan with the plan
+//! stored for it under `plans/`, so that a change to the optimizer that changes a plan fails
+//! until the snapshot is updated.
+//!
+//! A missing snapshot is written by the test that compares against it, and setting the
+//! `UPDATE_PLANS` environment variable rewrites every snapshot that is compared against.
+
+use crate::query::query_node::QueryNode;
+use std::fs;
+use std::path::PathBuf;
+
+/// Checks that the plan of the query is the same as the snapshot with the name
+///
+/// # Panic
+/// Panics if the plan is different from the snapshot
+pub(crate) fn assert_plan(name: &str, query: &QueryNode) {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("plans")
+        .join(format!("{}.plan", name));
+    let plan = query.plan_text();
+    if std::env::var_os("UPDATE_PLANS").is_some() || !path.exists() {
+        fs::create_dir_all(path.parent().unwrap()).expect("Couldn't create the plans directory");
+        fs::write(&path, &plan).expect("Couldn't write the snapshot");
+        return;
+    }
+    let expected = fs::read_to_string(&path).expect("Couldn't read the snapshot");
+    assert!(
+        expected == plan,
+        "The plan of {} changed, set UPDATE_PLANS to update the snapshot\nexpected:\n{}\nfound:\n{}",
+        name,
+        expected,
+        plan
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::query::conditions::{Condition, JoinCondition, Operand};
+    use crate::query::sort::SortKey;
+    use rad_db_structure::identifier::Identifier;
+    use rad_db_structure::key::primary::PrimaryKeyDefinition;
+    use rad_db_structure::relations::Relation;
+    use rad_db_structure::tuple::Tuple;
+    use rad_db_types::{Type, Value};
+    use std::iter::FromIterator;
+
+    fn customers() -> Relation {
+        let mut customers = Relation::new_volatile(
+            Identifier::new("customers"),
+            vec![("customer_id", Type::from(0u64)), ("name", Type::from(""))],
+            64,
+            PrimaryKeyDefinition::new(vec![0]),
+        );
+        for i in 0..100u64 {
+            customers
+                .insert(Tuple::from_iter(&[
+                    Value::from(i),
+                    Value::from(format!("customer {}", i)),
+                ]))
+                .unwrap();
+        }
+        customers
+    }
+
+    fn orders() -> Relation {
+        let mut orders = Relation::new_volatile(
+            Identifier::new("orders"),
+            vec![
+                ("order_id", Type::from(0u64)),
+                ("buyer", Type::from(0u64)),
+                ("amount", Type::from(0u64)),
+            ],
+            64,
+            PrimaryKeyDefinition::new(vec![0]),
+        );
+        for i in 0..1000u64 {
+            orders
+                .insert(Tuple::from_iter(&[
+                    Value::from(i),
+                    Value::from(i % 100),
+                    Value::from(i * 37 % 500),
+                ]))
+                .unwrap();
+        }
+        orders
+    }
+
+    fn orders_of_customer<'r>(customers: &'r Relation, orders: &'r Relation) -> QueryNode<'r> {
+        QueryNode::select_eq(
+            QueryNode::inner_join(
+                QueryNode::source(customers),
+                QueryNode::source(orders),
+                JoinCondition::new(Identifier::new("customer_id"), Identifier::new("buyer")),
+            ),
+            Identifier::new("customer_id"),
+            Operand::UnsignedNumber(3),
+        )
+    }
+
+    #[test]
+    fn selection_through_join() {
+        let (customers, orders) = (customers(), orders());
+        let query = orders_of_customer(&customers, &orders).optimized();
+        assert_plan("selection_through_join", &query);
+    }
+
+    #[test]
+    fn key_ranges() {
+        let orders = orders();
+        let query = QueryNode::select_on_condition(
+            QueryNode::source(&orders),
+            Condition::between(
+                "order_id",
+                Operand::UnsignedNumber(10),
+                Operand::UnsignedNumber(20),
+            ),
+        )
+        .optimized();
+        assert_plan("key_ranges", &query);
+    }
+
+    #[test]
+    fn top_n() {
+        let orders = orders();
+        let query = QueryNode::limit(
+            QueryNode::sort(
+                QueryNode::source(&orders),
+                vec![SortKey::descending("amount")],
+            ),
+            5,
+        )
+        .optimized();
+        assert_plan("top_n", &query);
+    }
+
+    #[test]
+    fn plan_equality() {
+        let (customers, orders) = (customers(), orders());
+        let first = orders_of_customer(&customers, &orders).optimized();
+        let second = orders_of_customer(&customers, &orders).optimized();
+        assert!(first.same_plan(&second));
+        assert_eq!(first.plan_hash(), second.plan_hash());
+
+        let unoptimized = orders_of_customer(&customers, &orders);
+        assert!(!first.same_plan(&unoptimized));
+        assert_ne!(first.plan_hash(), unoptimized.plan_hash());
+    }
+}
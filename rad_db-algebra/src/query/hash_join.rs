@@ -0,0 +1,534 @@
+//! Joining tuples by building a hash table over one of the inputs
+//!
+//! When the build input of a join doesn't fit in the memory budget, both inputs are split by the
+//! hash of their joined fields into files in the spill directory, and each pair of partitions is
+//! joined on its own (a grace hash join). Equal values always end up in partitions with the same index, so
+//! only one pair of partitions has to be loaded at a time.
+
+use crate::query::partition::PartitionScheme;
+use rad_db_structure::memory::{tuple_size, MemoryBudget};
+use rad_db_structure::spill::SpillFile;
+use rad_db_structure::tuple::Tuple;
+use rad_db_types::{Numeric, Type, Value};
+use std::collections::HashMap;
+use std::io;
+
+/// The number of partitions each input of a join is split into when it's spilled to disk
+pub const SPILL_PARTITIONS: usize = 16;
+
+/// The input of a hash join that is loaded into the hash table
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JoinSide {
+    Left,
+    Right,
+}
+
+impl JoinSide {
+    pub fn opposite(self) -> Self {
+        match self {
+            JoinSide::Left => JoinSide::Right,
+            JoinSide::Right => JoinSide::Left,
+        }
+    }
+}
+
+/// A value that hashes the same as every other value it's equal to using
+/// [numeric_eq](rad_db_types::Type::numeric_eq), so that numbers of different widths end up in
+/// the same bucket
+#[derive(Debug, PartialEq, Eq, Hash)]
+pub(crate) enum HashKey {
+    Integer(i128),
+    Float(u64),
+    Other(Value),
+}
+
+impl HashKey {
+    /// Creates the key of a value, or `None` for `NULL`, which never matches anything
+    pub(crate) fn new(value: &Value) -> Option<Self> {
+        let key = match value {
+            Type::Optional(None) => return None,
+            Type::Optional(Some(inner)) => return Self::new(inner),
+            Type::Numeric(Numeric::Signed(signed)) => {
+                HashKey::Integer(Into::<i64>::into(*signed) as i128)
+            }
+            Type::Numeric(Numeric::Unsigned(unsigned)) => {
+                HashKey::Integer(Into::<u64>::into(*unsigned) as i128)
+            }
+            Type::Numeric(Numeric::Float(float)) => Self::float(*float as f64),
+            Type::Numeric(Numeric::Double(double)) => Self::float(*double),
+            Type::Numeric(Numeric::Decimal(decimal)) => match decimal.to_integer() {
+                Some(integer) => HashKey::Integer(integer),
+                None => Self::float(decimal.to_f64()),
+            },
+            Type::Numeric(Numeric::BigInt(integer)) => match integer.to_i128() {
+                Some(integer) => HashKey::Integer(integer),
+                // wide integers are equal regardless of their bits, like they hash
+                None => HashKey::Other(value.clone()),
+            },
+            other => HashKey::Other(other.clone()),
+        };
+        Some(key)
+    }
+
+    /// Whole numbers are stored as integers so that they match integers of the same value
+    fn float(float: f64) -> Self {
+        if float.fract() == 0.0 && float.abs() < 2f64.powi(64) {
+            HashKey::Integer(float as i128)
+        } else {
+            HashKey::Float(float.to_bits())
+        }
+    }
+}
+
+/// Joins the tuples of two inputs where the fields at the indexes are equal. The output always
+/// has the fields of the left tuple first, regardless of which side is built.
+///
+/// Both inputs are spilled to disk when the build input doesn't fit in the memory budget, like in
+/// [grace_hash_join], where the types are the types of the fields of each input.
+pub(crate) fn hash_join<L, R>(
+    left: L,
+    right: R,
+    left_index: usize,
+    right_index: usize,
+    build: JoinSide,
+    types: (&[Type], &[Type]),
+    budget: &MemoryBudget,
+) -> io::Result<Vec<Tuple>>
+where
+    L: IntoIterator<Item = Tuple>,
+    R: IntoIterator<Item = Tuple>,
+{
+    let mut output = vec![];
+    grace_hash_join(
+        left,
+        right,
+        left_index,
+        right_index,
+        build,
+        types,
+        budget,
+        |tuple| output.push(tuple),
+    )?;
+    Ok(output)
+}
+
+/// Joins the tuples of two inputs where the fields at the indexes are equal, spilling both inputs
+/// to disk when the build input doesn't fit in the memory budget and joining them one pair of
+/// partitions at a time. The types are the types of the fields of each input, which the
+/// partitions are read back with.
+///
+/// Every joined tuple is given to `output` as soon as it's joined, instead of being collected.
+/// Fails if the spilled partitions can't be read back, after giving `output` the tuples joined
+/// so far.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn grace_hash_join<L, R, O>(
+    left: L,
+    right: R,
+    left_index: usize,
+    right_index: usize,
+    build: JoinSide,
+    types: (&[Type], &[Type]),
+    budget: &MemoryBudget,
+    mut output: O,
+) -> io::Result<()>
+where
+    L: IntoIterator<Item = Tuple>,
+    R: IntoIterator<Item = Tuple>,
+    O: FnMut(Tuple),
+{
+    match build {
+        JoinSide::Left => grace_join(
+            left,
+            left_index,
+            types.0,
+            right,
+            right_index,
+            types.1,
+            budget,
+            |left, right| output(left + right),
+        ),
+        JoinSide::Right => grace_join(
+            right,
+            right_index,
+            types.1,
+            left,
+            left_index,
+            types.0,
+            budget,
+            |right, left| output(left + right),
+        ),
+    }
+}
+
+/// Loads the build input into a hash table if it fits in the budget, and otherwise partitions
+/// both inputs and joins every pair of partitions in chunks
+#[allow(clippy::too_many_arguments)]
+fn grace_join<B, P, F>(
+    build: B,
+    build_index: usize,
+    build_types: &[Type],
+    probe_input: P,
+    probe_index: usize,
+    probe_types: &[Type],
+    budget: &MemoryBudget,
+    mut joined: F,
+) -> io::Result<()>
+where
+    B: IntoIterator<Item = Tuple>,
+    P: IntoIterator<Item = Tuple>,
+    F: FnMut(&Tuple, &Tuple),
+{
+    let mut build = build.into_iter();
+    let mut table: HashMap<HashKey, Vec<Tuple>> = HashMap::new();
+    let mut reservation = budget.reserve_anyway(0);
+    let mut overflow = None;
+    for tuple in &mut build {
+        if let Some(key) = HashKey::new(&tuple[build_index]) {
+            if reservation.grow(tuple_size(&tuple)).is_err() {
+                overflow = Some(tuple);
+                break;
+            }
+            table.entry(key).or_default().push(tuple);
+        }
+    }
+    let overflow = match overflow {
+        Some(overflow) => overflow,
+        None => {
+            for probe_tuple in probe_input {
+                for built in probe(&table, &probe_tuple[probe_index]) {
+                    joined(built, &probe_tuple);
+                }
+            }
+            return Ok(());
+        }
+    };
+
+    let mut spilled_build = SpilledInput::new(build_types, build_index);
+    for tuple in table.into_values().flatten() {
+        spilled_build.push(tuple);
+    }
+    std::mem::drop(reservation);
+    spilled_build.push(overflow);
+    for tuple in build {
+        spilled_build.push(tuple);
+    }
+    let mut spilled_probe = SpilledInput::new(probe_types, probe_index);
+    for tuple in probe_input {
+        spilled_probe.push(tuple);
+    }
+
+    for partition in 0..SPILL_PARTITIONS {
+        let mut probe_tuples = spilled_probe.partition(partition)?.peekable();
+        while probe_tuples.peek().is_some() {
+            // the probed tuples take up at most half of the budget, leaving the rest for the
+            // tables built over the build partition
+            let mut chunk = vec![];
+            let mut reservation = budget.reserve_anyway(0);
+            while let Some(tuple) = probe_tuples.peek() {
+                let size = tuple_size(tuple);
+                let fits = reservation.bytes() + size <= budget.limit() / 2
+                    && reservation.grow(size).is_ok();
+                if !fits {
+                    if !chunk.is_empty() {
+                        break;
+                    }
+                    reservation.grow_anyway(size);
+                }
+                chunk.extend(probe_tuples.next());
+            }
+            join_in_chunks(
+                spilled_build.partition(partition)?,
+                build_index,
+                &chunk,
+                probe_index,
+                budget,
+                &mut joined,
+            );
+        }
+    }
+    Ok(())
+}
+
+/// The tuples of one input of a join, split between spill files by the hash of the joined field
+struct SpilledInput {
+    index: usize,
+    scheme: PartitionScheme,
+    /// The file of every partition, unless it couldn't be created
+    partitions: Vec<Option<SpillFile>>,
+    /// Tuples that can't be written to the file of their partition, which are kept in memory
+    /// instead
+    unspillable: Vec<Vec<Tuple>>,
+}
+
+impl SpilledInput {
+    fn new(types: &[Type], index: usize) -> Self {
+        SpilledInput {
+            index,
+            scheme: PartitionScheme::Hash(SPILL_PARTITIONS),
+            partitions: (0..SPILL_PARTITIONS)
+                .map(|_| SpillFile::new(types.to_vec()).ok())
+                .collect(),
+            unspillable: vec![vec![]; SPILL_PARTITIONS],
+        }
+    }
+
+    /// Adds the tuple to the partition of its joined field, dropping it if the field is `NULL`
+    fn push(&mut self, tuple: Tuple) {
+        if HashKey::new(&tuple[self.index]).is_none() {
+            return;
+        }
+        let partition = self.scheme.partition_of(&tuple[self.index]);
+        let spilled = match &mut self.partitions[partition] {
+            Some(file) => matches!(file.push(&tuple), Ok(true)),
+            None => false,
+        };
+        if !spilled {
+            self.unspillable[partition].push(tuple);
+        }
+    }
+
+    /// The tuples of a partition, or the error reading its file back
+    fn partition(&mut self, partition: usize) -> io::Result<impl Iterator<Item = Tuple> + '_> {
+        let spilled = match &mut self.partitions[partition] {
+            Some(file) => Some(file.read()?),
+            None => None,
+        };
+        Ok(spilled
+            .into_iter()
+            .flatten()
+            .chain(self.unspillable[partition].iter().cloned()))
+    }
+}
+
+/// Builds hash tables over as many tuples of the build input as fit in the budget at a time,
+/// calling `joined` with every matching pair of a built tuple and a probed tuple
+fn join_in_chunks<B, F>(
+    build: B,
+    build_index: usize,
+    probe_tuples: &[Tuple],
+    probe_index: usize,
+    budget: &MemoryBudget,
+    mut joined: F,
+) where
+    B: IntoIterator<Item = Tuple>,
+    F: FnMut(&Tuple, &Tuple),
+{
+    let mut build = build.into_iter();
+    let mut next = build.next();
+    while next.is_some() {
+        let mut table: HashMap<HashKey, Vec<Tuple>> = HashMap::new();
+        let mut reservation = budget.reserve_anyway(0);
+        while let Some(tuple) = next.take() {
+            if let Some(key) = HashKey::new(&tuple[build_index]) {
+                let size = tuple_size(&tuple);
+                // every chunk holds at least one tuple, so that the join always makes progress
+                if table.is_empty() {
+                    reservation.grow_anyway(size);
+                } else if reservation.grow(size).is_err() {
+                    next = Some(tuple);
+                    break;
+                }
+                table.entry(key).or_default().push(tuple);
+            }
+            next = build.next();
+        }
+        for probe_tuple in probe_tuples {
+            for built in probe(&table, &probe_tuple[probe_index]) {
+                joined(built, probe_tuple);
+            }
+        }
+    }
+}
+
+fn probe<'t>(table: &'t HashMap<HashKey, Vec<Tuple>>, value: &Value) -> &'t [Tuple] {
+    HashKey::new(value)
+        .and_then(|key| table.get(&key))
+        .map_or(&[], |tuples| tuples.as_slice())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_follow_numeric_equality() {
+        let values = [
+            Value::from(3u8),
+            Value::from(3i64),
+            Value::from(3u64),
+            Numeric::Double(3.0).into(),
+            Value::Optional(Some(Box::new(Value::from(3i16)))),
+        ];
+        for left in &values {
+            for right in &values {
+                assert!(left.numeric_eq(right));
+                assert_eq!(HashKey::new(left), HashKey::new(right));
+            }
+        }
+        assert_ne!(
+            HashKey::new(&Value::from(-1i64)),
+            HashKey::new(&Value::from(u64::MAX))
+        );
+        assert_eq!(HashKey::new(&Value::Optional(None)), None);
+    }
+
+    #[test]
+    fn build_sides_agree() {
+        let left: Vec<Tuple> = (0..20u32)
+            .map(|i| Tuple::new(vec![Value::from(i % 5)]))
+            .collect();
+        let right: Vec<Tuple> = (0..10i64)
+            .map(|i| Tuple::new(vec![Value::from(i), Value::from(i * 2)]))
+            .collect();
+        let types = (
+            &[Type::from(0u32)][..],
+            &[Type::from(0i64), Type::from(0i64)][..],
+        );
+        let budget = MemoryBudget::unlimited();
+        let mut built_left = hash_join(
+            left.clone(),
+            right.clone(),
+            0,
+            0,
+            JoinSide::Left,
+            types,
+            &budget,
+        )
+        .unwrap();
+        let mut built_right =
+            hash_join(left, right, 0, 0, JoinSide::Right, types, &budget).unwrap();
+        assert_eq!(built_left.len(), 20);
+        let key = |tuple: &Tuple| tuple.to_string();
+        built_left.sort_by_key(key);
+        built_right.sort_by_key(key);
+        assert_eq!(built_left, built_right);
+        assert!(built_left
+            .iter()
+            .all(|tuple| tuple[0].numeric_eq(&tuple[1])));
+    }
+
+    #[test]
+    fn build_side_is_split_to_fit_the_budget() {
+        let left: Vec<Tuple> = (0..100u32)
+            .map(|i| Tuple::new(vec![Value::from(i % 10)]))
+            .collect();
+        let right: Vec<Tuple> = (0..10u32)
+            .map(|i| Tuple::new(vec![Value::from(i)]))
+            .collect();
+        let types = [Type::from(0u32)];
+        let budget = MemoryBudget::new(tuple_size(&left[0]) * 8);
+        let mut joined = hash_join(
+            left.clone(),
+            right.clone(),
+            0,
+            0,
+            JoinSide::Left,
+            (&types, &types),
+            &budget,
+        )
+        .unwrap();
+        assert_eq!(budget.used(), 0);
+        let mut expected = hash_join(
+            left,
+            right,
+            0,
+            0,
+            JoinSide::Left,
+            (&types, &types),
+            &MemoryBudget::unlimited(),
+        )
+        .unwrap();
+        let key = |tuple: &Tuple| tuple.to_string();
+        joined.sort_by_key(key);
+        expected.sort_by_key(key);
+        assert_eq!(joined.len(), 100);
+        assert_eq!(joined, expected);
+    }
+
+    #[test]
+    fn inputs_larger_than_the_budget_are_spilled() {
+        let mut left: Vec<Tuple> = (0..200u32)
+            .map(|i| Tuple::new(vec![Value::from(i % 20), Value::from(i)]))
+            .collect();
+        left.push(Tuple::new(vec![Value::from(3u32), Value::from(0u32)]));
+        left.push(Tuple::new(vec![Value::Optional(None), Value::from(0u32)]));
+        let mut right: Vec<Tuple> = (0..20u32)
+            .map(|i| Tuple::new(vec![Value::from(i)]))
+            .collect();
+        // not of the declared type, so it stays in memory, but still joins
+        right.push(Tuple::new(vec![Value::from(7i64)]));
+        let left_types = [Type::from(0u32), Type::from(0u32)];
+        let right_types = [Type::from(0u32)];
+
+        let budget = MemoryBudget::new(tuple_size(&left[0]) * 8);
+        let mut joined = vec![];
+        grace_hash_join(
+            left.clone(),
+            right.clone(),
+            0,
+            0,
+            JoinSide::Left,
+            (&left_types, &right_types),
+            &budget,
+            |tuple| joined.push(tuple),
+        )
+        .unwrap();
+        assert_eq!(budget.used(), 0);
+        let mut expected = hash_join(
+            left,
+            right,
+            0,
+            0,
+            JoinSide::Left,
+            (&left_types, &right_types),
+            &MemoryBudget::unlimited(),
+        )
+        .unwrap();
+        let key = |tuple: &Tuple| tuple.to_string();
+        joined.sort_by_key(key);
+        expected.sort_by_key(key);
+        assert_eq!(joined.len(), 211);
+        assert_eq!(joined, expected);
+    }
+
+    #[test]
+    fn probe_partitions_are_split_to_fit_the_budget() {
+        let left: Vec<Tuple> = (0..200u32)
+            .map(|i| Tuple::new(vec![Value::from(i % 20), Value::from(i)]))
+            .collect();
+        let right: Vec<Tuple> = (0..200u32)
+            .map(|i| Tuple::new(vec![Value::from(i % 20), Value::from(i)]))
+            .collect();
+        let types = [Type::from(0u32), Type::from(0u32)];
+
+        let budget = MemoryBudget::new(tuple_size(&left[0]) * 8);
+        let mut joined = vec![];
+        grace_hash_join(
+            left.clone(),
+            right.clone(),
+            0,
+            0,
+            JoinSide::Left,
+            (&types, &types),
+            &budget,
+            |tuple| joined.push(tuple),
+        )
+        .unwrap();
+        assert_eq!(budget.used(), 0);
+        let mut expected = hash_join(
+            left,
+            right,
+            0,
+            0,
+            JoinSide::Left,
+            (&types, &types),
+            &MemoryBudget::unlimited(),
+        )
+        .unwrap();
+        let key = |tuple: &Tuple| tuple.to_string();
+        joined.sort_by_key(key);
+        expected.sort_by_key(key);
+        assert_eq!(joined.len(), 2000);
+        assert_eq!(joined, expected);
+    }
+}
@@ -0,0 +1,19 @@
+//! Hints that override the choices the optimizer makes for a node
+
+/// A hint attached to a node of a query, which the optimizer follows instead of its own estimates
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Hint {
+    /// Always execute this join as a hash join
+    HashJoin,
+    /// Always execute this join by comparing every pair of tuples
+    NestedLoopJoin,
+    /// Keep the inputs of this join in the order they were written, so the right input is
+    /// always the one loaded into memory
+    NoReorder,
+    /// Assume this node produces this many tuples, such as the number it produced the last time
+    /// the query was executed
+    Cardinality(usize),
+    /// Read the source below this selection through the secondary index with this name whenever
+    /// the index can answer the selection, even if scanning the source is estimated to be cheaper
+    Index(String),
+}
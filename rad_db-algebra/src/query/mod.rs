@@ -1,10 +1,27 @@
 use std::marker::PhantomData;
 use std::ops::{Deref, DerefMut};
 
+pub mod batch;
 pub mod conditions;
+pub mod cost;
+pub mod count;
+pub mod feedback;
+#[cfg(test)]
+mod golden;
+pub mod hash_join;
+pub mod hints;
+pub mod partial_index;
+pub mod partition;
+pub mod pivot;
+pub mod plan_cache;
 pub mod query_iterator;
 pub mod query_node;
 pub mod query_result;
+pub mod rules;
+pub mod sample;
+pub mod sort;
+pub mod table;
+pub mod workload;
 pub mod optimization;
 
 /// An object that can be turned into an iterator multiple times
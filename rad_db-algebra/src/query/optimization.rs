@@ -1,13 +1,20 @@
 use crate::error::MissingFieldError;
-use crate::query::conditions::{Condition, JoinCondition};
+use crate::query::conditions::{Condition, JoinCondition, Simplified};
+use crate::query::cost::CostModel;
+use crate::query::hash_join::JoinSide;
+use crate::query::hints::Hint;
+use crate::query::partial_index::index_condition;
 use crate::query::query_node::QueryOperation;
-use crate::query::query_node::{QueryChildren, QueryNode, Source};
+use crate::query::query_node::{as_slices, QueryChildren, QueryNode};
+use crate::query::rules::{Rule, RuleSet, RuleStatistics, Traversal};
+use crate::query::sample;
 use rad_db_structure::identifier::Identifier;
 use rad_db_structure::relations::Relation;
-use rad_db_types::Value;
-use rand::seq::IteratorRandom;
+use rad_db_types::comparison::TextComparison;
+use rad_db_types::{Type, Value};
 use std::collections::{HashMap, HashSet};
 use std::iter::FromIterator;
+use std::ops::Bound;
 
 pub struct Optimizer<'a, 'q>
 where
@@ -17,21 +24,82 @@ where
     start_tuples: usize,
     /// A sample of some amount of random values of the relevant fields in selections
     samples: HashMap<Identifier, Vec<Value>>,
+    rules: RuleSet,
+    statistics: Vec<RuleStatistics>,
 }
 
+/// The smallest string that is greater than every string starting with the prefix, or `None` if
+/// there isn't one
+fn successor(prefix: &str) -> Option<String> {
+    let mut chars: Vec<char> = prefix.chars().collect();
+    while let Some(last) = chars.pop() {
+        let next = (last as u32 + 1..=char::MAX as u32).find_map(std::char::from_u32);
+        if let Some(next) = next {
+            chars.push(next);
+            return Some(chars.into_iter().collect());
+        }
+    }
+    None
+}
+
+/// The lower and upper bounds of a range of keys, as prefixes of the keys
+type KeyBounds = (Bound<Vec<Value>>, Bound<Vec<Value>>);
+
+/// The bounds on a field that the conditions keep its values within, as prefixes of a key that
+/// starts with the field. Bounds are only given when they're exactly of the field's type, as
+/// they're encoded like it.
+fn bounds_on(field: &Identifier, field_type: &Type, conditions: &[Condition]) -> Vec<KeyBounds> {
+    let coerced = |bound: Bound<Value>| -> Option<Bound<Vec<Value>>> {
+        Some(match bound {
+            Bound::Included(value) => Bound::Included(vec![value.coerce_like(field_type)?]),
+            Bound::Excluded(value) => Bound::Excluded(vec![value.coerce_like(field_type)?]),
+            Bound::Unbounded => Bound::Unbounded,
+        })
+    };
+    let mut ranges = vec![];
+    for condition in conditions
+        .iter()
+        .filter(|condition| condition.base() == field)
+    {
+        if let Some(prefix) = condition.like_prefix() {
+            let upper = match successor(prefix) {
+                Some(successor) => Bound::Excluded(Value::from(successor)),
+                None => Bound::Unbounded,
+            };
+            ranges.push((Bound::Included(Value::from(prefix)), upper));
+        }
+        for (_, lower, upper) in condition.ranged_fields() {
+            ranges.push((lower, upper));
+        }
+        for (_, value) in condition.fixed_values() {
+            ranges.push((Bound::Included(value.clone()), Bound::Included(value)));
+        }
+    }
+    ranges
+        .into_iter()
+        .filter_map(|(lower, upper)| Some((coerced(lower)?, coerced(upper)?)))
+        .collect()
+}
+
+/// Samples the values of a field of the relation the node reads
 fn sample_field(
     field: &Identifier,
-    source: &Relation,
+    node: &QueryNode,
     samples: usize,
 ) -> Result<Vec<Value>, MissingFieldError> {
-    let field_index = source.get_field_index(field);
-    if field_index.is_none() {
-        return Err(MissingFieldError::new(field.clone()));
-    }
-    let field_index = field_index.unwrap();
-    let tuples = source.tuples();
+    let (source, field_index) = match (node.my_relation(), node.relation_field_index(field)) {
+        (Some(source), Some(field_index)) => (source, field_index),
+        _ => return Err(MissingFieldError::new(field.clone(), None)),
+    };
     let mut random = rand::thread_rng();
-    let sampled_tuples = tuples.choose_multiple(&mut random, samples);
+    // Only a random subset of the blocks is read, with enough tuples expected to fill the sample
+    let fraction = (2 * samples) as f64 / source.len().max(1) as f64;
+    let sampled_tuples = if fraction < 1.0 {
+        let blocks = sample::bernoulli(source.blocks(), fraction, &mut random);
+        sample::reservoir(blocks.into_iter().flatten(), samples, &mut random)
+    } else {
+        sample::reservoir(source.tuples(), samples, &mut random)
+    };
     let samples_values: Vec<_> = sampled_tuples
         .into_iter()
         .map(|tuple| tuple.take(field_index))
@@ -57,7 +125,13 @@ impl<'a, 'query> Optimizer<'a, 'query>
 where
     'query: 'a,
 {
-    pub fn new(query: &'a mut QueryNode<'query>, samples: usize) -> Self {
+    /// Creates an optimizer that uses the default rules, choosing between plans with the costs
+    pub fn new(query: &'a mut QueryNode<'query>, samples: usize, costs: CostModel) -> Self {
+        Self::with_rules(query, samples, RuleSet::with_costs(costs))
+    }
+
+    /// Creates an optimizer that rewrites the query with the rules instead of the default ones
+    pub fn with_rules(query: &'a mut QueryNode<'query>, samples: usize, rules: RuleSet) -> Self {
         let tuples = query.approximate_created_tuples();
         let mut sampled_fields = HashMap::new();
 
@@ -68,8 +142,8 @@ where
             .collect();
         for field in all_relevant_fields {
             if let Some(node) = query.find_node_with_field(&field) {
-                if let Some(relation) = node.my_relation() {
-                    let sample = sample_field(&field, relation, samples)
+                if node.my_relation().is_some() {
+                    let sample = sample_field(&field, node, samples)
                         .expect(&*format!("Field {} went missing", field));
                     sampled_fields.insert(field, sample);
                 }
@@ -80,9 +154,16 @@ where
             query_node: query,
             start_tuples: tuples,
             samples: sampled_fields,
+            rules,
+            statistics: vec![],
         }
     }
 
+    /// How often each rule changed the query the last time it was optimized
+    pub fn statistics(&self) -> &[RuleStatistics] {
+        &self.statistics
+    }
+
     fn get_relations(query: &QueryNode<'query>) -> Vec<&'query Relation> {
         if let QueryOperation::Source(s) = query.query_operation() {
             vec![s.relation()]
@@ -101,33 +182,273 @@ where
     /// The optimizer can be ran multiple times, theoretically, but all subsequent runs will not
     /// have an effect, and will likely return an efficiency ratio of 1.0
     pub fn optimize(&mut self) -> f64 {
-        Self::split_all_ands(self.query_node);
+        self.statistics = self.rules.apply(self.query_node);
+        self.query_node.recalculate_resulting_relation();
         self.query_node.approximate_created_tuples() as f64 / self.start_tuples as f64
     }
 
-    /// Splits all AND conditionals into multiple selection nodes
-    fn split_all_ands(node: &mut QueryNode<'query>) {
-        let split_conditions = if let QueryOperation::Selection(condition) = node.query_mut() {
-            condition.clone().split_and()
+    /// The cheapest way to read the source below a chain of selections through its primary key or
+    /// an index, if there's one that's cheaper than scanning the source. An index one of the
+    /// selections is hinted to use is chosen regardless of the cost, if it can answer them.
+    pub(super) fn access_path(
+        selection: &QueryNode<'query>,
+        costs: &CostModel,
+    ) -> Option<QueryOperation<'query>> {
+        let mut node = selection;
+        let mut hinted = None;
+        while let QueryOperation::Selection(_) = node.query_operation() {
+            hinted = hinted.or_else(|| node.index_hint());
+            node = node.children()[0];
+        }
+        hinted
+            .and_then(|index| Self::index_scan(selection, costs, Some(index)))
+            .or_else(|| Self::key_lookup(selection, costs))
+            .or_else(|| Self::key_range_scan(selection, costs))
+            .or_else(|| Self::index_scan(selection, costs, None))
+    }
+
+    /// The key lookup that can replace the source below a chain of selections, if it's cheaper
+    /// than scanning the source
+    fn key_lookup(
+        selection: &QueryNode<'query>,
+        costs: &CostModel,
+    ) -> Option<QueryOperation<'query>> {
+        let mut fixed = vec![];
+        let mut node = selection;
+        while let QueryOperation::Selection(condition) = node.query_operation() {
+            // keys are hashed exactly, so text compared some other way can't be looked up
+            if condition.text_comparison() == TextComparison::Exact {
+                fixed.extend(condition.fixed_values());
+            }
+            node = node.children()[0];
+        }
+        let relation = match node.query_operation() {
+            QueryOperation::Source(source) => source.relation(),
+            _ => return None,
+        };
+
+        let mut key_fields = relation.primary_key().to_vec();
+        key_fields.sort_unstable();
+        let mut key = vec![];
+        for index in key_fields {
+            let field = &node.resulting_relation()[index].0;
+            let (_, value) = fixed.iter().find(|(fixed, _)| fixed == field)?;
+            key.push(value.coerce_like(&relation.attributes()[index].1)?);
+        }
+
+        if costs.key_lookup(relation) < costs.scan(relation) {
+            Some(QueryOperation::KeyLookup(relation, key))
+        } else {
+            None
+        }
+    }
+
+    /// The range of keys that can replace the source below a chain of selections, if they bound
+    /// the first field of the key by constants or match it against a `LIKE` pattern with a prefix,
+    /// and reading the smallest such range is cheaper than scanning the source
+    fn key_range_scan(
+        selection: &QueryNode<'query>,
+        costs: &CostModel,
+    ) -> Option<QueryOperation<'query>> {
+        let mut conditions = vec![];
+        let mut node = selection;
+        while let QueryOperation::Selection(condition) = node.query_operation() {
+            // the key index orders text bytewise
+            if condition.text_comparison() == TextComparison::Exact {
+                conditions.extend(condition.clone().split_and());
+            }
+            node = node.children()[0];
+        }
+        let relation = match node.query_operation() {
+            QueryOperation::Source(source) => source.relation(),
+            _ => return None,
+        };
+
+        let first = relation.primary_key().iter().copied().min()?;
+        let field = &node.resulting_relation()[first].0;
+        let field_type = &relation.attributes()[first].1;
+        let (lower, upper, matching) = bounds_on(field, field_type, &conditions)
+            .into_iter()
+            .map(|(lower, upper)| {
+                let matching = relation.key_range_len(as_slices(&lower), as_slices(&upper));
+                (lower, upper, matching)
+            })
+            .min_by_key(|(_, _, matching)| *matching)?;
+
+        if costs.index_range(matching) < costs.scan(relation) {
+            Some(QueryOperation::KeyRange(relation, lower, upper))
         } else {
-            vec![]
+            None
+        }
+    }
+
+    /// The index that can replace the source below a chain of selections, if the selections
+    /// imply the condition of a partial index or bound the first indexed field by constants,
+    /// and reading the fewest tuples held by such an index is cheaper than scanning the source.
+    /// Only the hinted index is considered if there is one, and it's used regardless of the cost.
+    fn index_scan(
+        selection: &QueryNode<'query>,
+        costs: &CostModel,
+        hinted: Option<&str>,
+    ) -> Option<QueryOperation<'query>> {
+        let mut conditions = vec![];
+        let mut node = selection;
+        while let QueryOperation::Selection(condition) = node.query_operation() {
+            // indexes order text bytewise
+            if condition.text_comparison() == TextComparison::Exact {
+                conditions.extend(condition.clone().split_and());
+            }
+            node = node.children()[0];
+        }
+        let relation = match node.query_operation() {
+            QueryOperation::Source(source) => source.relation(),
+            _ => return None,
         };
 
-        if split_conditions.len() > 1 {
-            let ptr = std::mem::replace(node.children_mut(), QueryChildren::None);
-            if let QueryChildren::One(mut ptr) = ptr {
-                for condition in split_conditions {
-                    ptr = QueryNode::select_on_condition(ptr, condition);
-                }
-                *node = ptr;
-            } else {
-                panic!("invalid query")
+        let mut scans = vec![];
+        for index in relation.indexes() {
+            if hinted.is_some_and(|hinted| hinted != index.name()) {
+                continue;
             }
+            let partial = match index.predicate() {
+                None => false,
+                Some(_) => match index_condition(index) {
+                    Some(condition) if condition.implied_by(&conditions) => true,
+                    _ => continue,
+                },
+            };
+            let mut ranges = index
+                .fields()
+                .first()
+                .map(|&first| {
+                    let field = &node.resulting_relation()[first].0;
+                    bounds_on(field, &relation.attributes()[first].1, &conditions)
+                })
+                .unwrap_or_default();
+            // a partial index is worth reading whole, as it only holds the tuples that are wanted
+            if partial {
+                ranges.push((Bound::Unbounded, Bound::Unbounded));
+            }
+            for (lower, upper) in ranges {
+                let matching =
+                    relation.index_range_len(index.name(), as_slices(&lower), as_slices(&upper));
+                scans.push((index.name(), lower, upper, matching));
+            }
+        }
+        let (index, lower, upper, matching) =
+            scans.into_iter().min_by_key(|(.., matching)| *matching)?;
+
+        if hinted.is_some() || costs.index_range(matching) < costs.scan(relation) {
+            Some(QueryOperation::IndexScan(
+                relation,
+                index.to_string(),
+                lower,
+                upper,
+            ))
+        } else {
+            None
         }
+    }
 
+    /// Adds projections directly above sources that drop every field no node above the source
+    /// uses, so that tuples are as small as possible from the start. `required` is the set of
+    /// fields used above this node, or `None` if all of them are. Gives the number of
+    /// projections added.
+    ///
+    /// The resulting relations of the nodes above the new projections must be recalculated
+    /// afterwards.
+    fn prune_unused_fields(
+        node: &mut QueryNode<'query>,
+        required: Option<HashSet<Identifier>>,
+    ) -> usize {
+        let with = |fields: Vec<Identifier>| {
+            required.clone().map(|mut required| {
+                required.extend(fields);
+                required
+            })
+        };
+        // the fields used by this node or any node above it
+        let used = match node.query_operation() {
+            QueryOperation::Source(_)
+            | QueryOperation::ForeignSource(_)
+            | QueryOperation::KeyLookup(..)
+            | QueryOperation::KeyRange(..)
+            | QueryOperation::IndexScan(..)
+            | QueryOperation::IndexOnlyScan(..) => {
+                if let Some(required) = required {
+                    let kept: Vec<Identifier> = node
+                        .resulting_relation()
+                        .iter()
+                        .map(|(id, _)| id.clone())
+                        .filter(|id| required.contains(id))
+                        .collect();
+                    if kept.len() < node.resulting_relation().len() {
+                        let placeholder = QueryNode::working_table(node);
+                        let source = std::mem::replace(node, placeholder);
+                        *node = QueryNode::projection(source, kept);
+                        return 1;
+                    }
+                }
+                return 0;
+            }
+            QueryOperation::Projection(fields) => {
+                if let QueryOperation::Source(_)
+                | QueryOperation::ForeignSource(_)
+                | QueryOperation::KeyLookup(..)
+                | QueryOperation::KeyRange(..)
+                | QueryOperation::IndexScan(..)
+                | QueryOperation::IndexOnlyScan(..) = node.children()[0].query_operation()
+                {
+                    return 0;
+                }
+                Some(fields.iter().cloned().collect())
+            }
+            QueryOperation::Selection(condition) => {
+                with(condition.relevant_fields().into_iter().collect())
+            }
+            QueryOperation::Sort(keys) | QueryOperation::TopN(keys, _) => {
+                with(keys.iter().map(|key| key.field().clone()).collect())
+            }
+            QueryOperation::InnerJoin(join)
+            | QueryOperation::HashJoin(join, _)
+            | QueryOperation::LeftJoin(join)
+            | QueryOperation::RightJoin(join)
+            | QueryOperation::FullJoin(join)
+            | QueryOperation::NegatedSubquery(join, _)
+            | QueryOperation::AntiJoin(join, _) => {
+                with(vec![join.left_id().clone(), join.right_id().clone()])
+            }
+            QueryOperation::Pivot(pivot) => Some(pivot.input_fields().into_iter().collect()),
+            QueryOperation::Unpivot(unpivot) => with(unpivot.rotated_columns().to_vec()),
+            QueryOperation::CrossProduct | QueryOperation::Sample(_) | QueryOperation::Limit(_) => {
+                required
+            }
+            // the step of a recursive query must keep producing the same fields as its base, and
+            // partition-wise joins read the partitions of their inputs directly
+            QueryOperation::PartitionedSource(_)
+            | QueryOperation::HistoricalSource(..)
+            | QueryOperation::PartitionWiseJoin(_)
+            | QueryOperation::NaturalJoin
+            | QueryOperation::Recursive
+            | QueryOperation::WorkingTable
+            | QueryOperation::Empty
+            | QueryOperation::Count
+            | QueryOperation::RelationCount(_) => None,
+        };
+
+        let mut added = 0;
         for child in node.children_mut_list() {
-            Self::split_all_ands(child);
+            let child_required = used.as_ref().map(|used| {
+                child
+                    .resulting_relation()
+                    .iter()
+                    .map(|(id, _)| id.clone())
+                    .filter(|id| used.contains(id))
+                    .collect()
+            });
+            added += Self::prune_unused_fields(child, child_required);
         }
+        added
     }
 
     fn push_selects_down(&self) {}
@@ -215,22 +536,57 @@ where
         false
     }
 
-    /// Swaps the children of a join operation for inner joins or cross products
-    fn commute_join(join: &'query mut QueryNode<'query>) -> bool {
-        let is_join = match join.query_operation() {
-            QueryOperation::CrossProduct => true,
-            QueryOperation::InnerJoin(_) => true,
-            QueryOperation::NaturalJoin => true,
-            _ => false,
+    /// Swaps the children of a join. Inner joins and cross products can always be swapped, while
+    /// a left join becomes a right join and the other way around, so that the same side of the
+    /// join keeps all of its tuples.
+    fn commute_join(join: &mut QueryNode<'query>) -> bool {
+        if join.has_hint(Hint::NoReorder) {
+            return false;
+        }
+        let commuted = match join.query_operation() {
+            QueryOperation::CrossProduct => QueryOperation::CrossProduct,
+            QueryOperation::NaturalJoin => QueryOperation::NaturalJoin,
+            QueryOperation::InnerJoin(condition) => QueryOperation::InnerJoin(condition.reversed()),
+            QueryOperation::HashJoin(condition, build) => {
+                QueryOperation::HashJoin(condition.reversed(), build.opposite())
+            }
+            QueryOperation::PartitionWiseJoin(condition) => {
+                QueryOperation::PartitionWiseJoin(condition.reversed())
+            }
+            QueryOperation::LeftJoin(condition) => QueryOperation::RightJoin(condition.reversed()),
+            QueryOperation::RightJoin(condition) => QueryOperation::LeftJoin(condition.reversed()),
+            QueryOperation::FullJoin(condition) => QueryOperation::FullJoin(condition.reversed()),
+            _ => return false,
         };
 
-        if is_join {
-            if let QueryChildren::Two(left, right) = join.children_mut() {
-                std::mem::swap(left, right);
+        if let QueryChildren::Two(left, right) = join.children_mut() {
+            std::mem::swap(left, right);
+        }
+        *join.query_mut() = commuted;
+        join.recalculate_resulting_relation();
+        true
+    }
+
+    /// Checks whether a selection above a join can instead filter one of the children of the
+    /// join. A selection can't be moved into a child that an outer join pads with `NULL`, as the
+    /// selection would no longer remove the padded tuples.
+    fn can_push_selection_into(join: &QueryOperation<'query>, side: JoinSide) -> bool {
+        match join {
+            QueryOperation::CrossProduct
+            | QueryOperation::InnerJoin(_)
+            | QueryOperation::HashJoin(..)
+            | QueryOperation::NaturalJoin
+            | QueryOperation::LeftJoin(_)
+            | QueryOperation::RightJoin(_)
+            | QueryOperation::FullJoin(_) => {
+                let (keep_left, keep_right) = join.preserved_inputs();
+                match side {
+                    JoinSide::Left => !keep_right,
+                    JoinSide::Right => !keep_left,
+                }
             }
-            return true;
+            _ => false,
         }
-        false
     }
 
     /// Turns a selection followed by a cross product into a inner join, if select.f1=f2(R1xR2) is
@@ -364,57 +720,551 @@ where
         false
     }
 
-    /*
-    fn push_selection_through_join(selection: &'query mut QueryNode<'query>) -> bool {
-        unimplemented!()
-        /*
-        enum Child { Left, Right}
+    /// Moves a selection directly above a join into the child of the join that has all of the
+    /// fields used by the selection, if doing so doesn't change the result of the join
+    fn push_selection_through_join(selection: &mut QueryNode<'query>) -> bool {
+        let (condition, side) =
+            if let QueryOperation::Selection(condition) = selection.query_operation() {
+                let join = selection.children()[0];
+                let children = join.children();
+                if children.len() != 2 {
+                    return false;
+                }
+                let fields = condition.relevant_fields();
+                let has_fields = |child: &QueryNode| {
+                    fields
+                        .iter()
+                        .all(|field| child.resulting_relation().iter().any(|(id, _)| id == field))
+                };
+                let side = if has_fields(children[0]) {
+                    JoinSide::Left
+                } else if has_fields(children[1]) {
+                    JoinSide::Right
+                } else {
+                    return false;
+                };
+                if !Self::can_push_selection_into(join.query_operation(), side) {
+                    return false;
+                }
+                (condition.clone(), side)
+            } else {
+                return false;
+            };
 
+        if let QueryChildren::One(mut join) = selection.take_children() {
+            if let QueryChildren::Two(left, right) = join.take_children() {
+                *join.children_mut() = match side {
+                    JoinSide::Left => {
+                        QueryChildren::Two(QueryNode::select_on_condition(left, condition), right)
+                    }
+                    JoinSide::Right => {
+                        QueryChildren::Two(left, QueryNode::select_on_condition(right, condition))
+                    }
+                };
+            }
+            *selection = join;
+        }
+        true
+    }
+}
 
-        let (push, side): (bool, Option<Child>) = if let QueryOperation::Selection(condition) = selection.query_operation() {
-            let child = selection.children()[0];
-            if child.is_join() {
-                let child_children = child.children();
-                let left = child_children[0];
-                let right = child_children[1];
+/// Splits all AND conditionals into multiple selection nodes
+pub struct SplitAnds;
 
-                let fields = condition.relevant_fields();
-                let mut relevant_nodes = vec![];
-                for field in fields {
-                    if let Some(node) = child.find_node_with_field(field) {
-                        relevant_nodes.push(node);
-                    } else {
-                        return false; // node not in this tree
-                    }
+impl Rule for SplitAnds {
+    fn name(&self) -> &str {
+        "split_ands"
+    }
+
+    fn traversal(&self) -> Traversal {
+        Traversal::TopDown
+    }
+
+    fn matches(&self, node: &QueryNode<'_>) -> bool {
+        match node.query_operation() {
+            QueryOperation::Selection(condition) => condition.clone().split_and().len() > 1,
+            _ => false,
+        }
+    }
+
+    fn apply(&self, node: &mut QueryNode<'_>) -> bool {
+        let split_conditions = if let QueryOperation::Selection(condition) = node.query_mut() {
+            condition.clone().split_and()
+        } else {
+            return false;
+        };
+
+        let ptr = std::mem::replace(node.children_mut(), QueryChildren::None);
+        if let QueryChildren::One(mut ptr) = ptr {
+            for condition in split_conditions {
+                ptr = QueryNode::select_on_condition(ptr, condition);
+            }
+            *node = ptr;
+        } else {
+            panic!("invalid query")
+        }
+        true
+    }
+}
+
+/// Simplifies the condition of every selection, removing the selections that are always true
+/// and replacing the selections that are never true with an empty node
+pub struct FoldConditions;
+
+impl Rule for FoldConditions {
+    fn name(&self) -> &str {
+        "fold_conditions"
+    }
+
+    fn traversal(&self) -> Traversal {
+        Traversal::BottomUp
+    }
+
+    fn matches(&self, node: &QueryNode<'_>) -> bool {
+        matches!(node.query_operation(), QueryOperation::Selection(_))
+    }
+
+    fn apply(&self, node: &mut QueryNode<'_>) -> bool {
+        let simplified = if let QueryOperation::Selection(condition) = node.query_operation() {
+            match condition.simplify(node.children()[0].resulting_relation()) {
+                Simplified::Condition(simplified) if &simplified == condition => return false,
+                simplified => simplified,
+            }
+        } else {
+            return false;
+        };
+        match simplified {
+            Simplified::Always => {
+                if let QueryChildren::One(child) = node.take_children() {
+                    *node = child;
                 }
-                let mut side = None;
-                let mut push = false;
+            }
+            Simplified::Never => *node = QueryNode::empty(node),
+            Simplified::Condition(condition) => {
+                *node.query_mut() = QueryOperation::Selection(condition)
+            }
+        }
+        true
+    }
+}
+
+/// Replaces the parts of the query that can never produce any tuples with empty nodes. This
+/// happens when a chain of selections contradicts itself, or when the input that a node needs
+/// tuples from is empty.
+pub struct RemoveContradictions;
+
+impl Rule for RemoveContradictions {
+    fn name(&self) -> &str {
+        "remove_contradictions"
+    }
 
-                for relevant_node in relevant_nodes {
+    fn traversal(&self) -> Traversal {
+        Traversal::BottomUp
+    }
+
+    fn matches(&self, node: &QueryNode<'_>) -> bool {
+        !matches!(node.query_operation(), QueryOperation::Empty)
+    }
 
+    fn apply(&self, node: &mut QueryNode<'_>) -> bool {
+        let is_empty = |node: &QueryNode| matches!(node.query_operation(), QueryOperation::Empty);
+        let children = node.children();
+        let empty = match node.query_operation() {
+            QueryOperation::Selection(_) => {
+                let mut conditions = vec![];
+                let mut ptr: &QueryNode = node;
+                while let QueryOperation::Selection(condition) = ptr.query_operation() {
+                    conditions.push(condition.clone());
+                    ptr = ptr.children()[0];
                 }
+                is_empty(children[0]) || Condition::are_contradictory(&conditions)
+            }
+            QueryOperation::Limit(limit) | QueryOperation::TopN(_, limit) => {
+                *limit == 0 || is_empty(children[0])
+            }
+            QueryOperation::Projection(_)
+            | QueryOperation::Sort(_)
+            | QueryOperation::Sample(_)
+            | QueryOperation::Pivot(_)
+            | QueryOperation::Unpivot(_)
+            | QueryOperation::NegatedSubquery(..)
+            | QueryOperation::AntiJoin(..)
+            | QueryOperation::Recursive => is_empty(children[0]),
+            QueryOperation::CrossProduct
+            | QueryOperation::InnerJoin(_)
+            | QueryOperation::HashJoin(..)
+            | QueryOperation::PartitionWiseJoin(_)
+            | QueryOperation::LeftJoin(_)
+            | QueryOperation::RightJoin(_)
+            | QueryOperation::FullJoin(_)
+            | QueryOperation::NaturalJoin => {
+                // a preserved input keeps its tuples even if the other input is empty
+                let (keep_left, keep_right) = node.query_operation().preserved_inputs();
+                let left = is_empty(children[0]);
+                let right = is_empty(children[1]);
+                (left || right) && (left || !keep_left) && (right || !keep_right)
+            }
+            QueryOperation::Source(_)
+            | QueryOperation::PartitionedSource(_)
+            | QueryOperation::HistoricalSource(..)
+            | QueryOperation::ForeignSource(_)
+            | QueryOperation::KeyLookup(..)
+            | QueryOperation::KeyRange(..)
+            | QueryOperation::IndexScan(..)
+            | QueryOperation::IndexOnlyScan(..)
+            | QueryOperation::WorkingTable
+            | QueryOperation::Empty
+            // a count of no tuples is still a tuple
+            | QueryOperation::Count
+            | QueryOperation::RelationCount(_) => false,
+        };
 
-                ()
-            } else {
-                (false, None)
+        if empty {
+            *node = QueryNode::empty(node);
+        }
+        empty
+    }
+}
+
+/// Turns all `NOT IN` and `NOT EXISTS` predicates into anti joins, so that the subquery is only
+/// evaluated once instead of once per tuple
+pub struct RewriteNegatedSubqueries;
+
+impl Rule for RewriteNegatedSubqueries {
+    fn name(&self) -> &str {
+        "rewrite_negated_subqueries"
+    }
+
+    fn traversal(&self) -> Traversal {
+        Traversal::TopDown
+    }
+
+    fn matches(&self, node: &QueryNode<'_>) -> bool {
+        matches!(node.query_operation(), QueryOperation::NegatedSubquery(..))
+    }
+
+    fn apply(&self, node: &mut QueryNode<'_>) -> bool {
+        if let QueryOperation::NegatedSubquery(condition, kind) = node.query_operation() {
+            let anti_join = QueryOperation::AntiJoin(condition.clone(), *kind);
+            *node.query_mut() = anti_join;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// Turns a limit directly above a sort into a single top-n node, which only keeps as many tuples
+/// as the limit instead of sorting the entire input
+pub struct MergeLimitsIntoSorts;
+
+impl Rule for MergeLimitsIntoSorts {
+    fn name(&self) -> &str {
+        "merge_limits_into_sorts"
+    }
+
+    fn traversal(&self) -> Traversal {
+        Traversal::TopDown
+    }
+
+    fn matches(&self, node: &QueryNode<'_>) -> bool {
+        matches!(node.query_operation(), QueryOperation::Limit(_))
+            && matches!(
+                node.children()[0].query_operation(),
+                QueryOperation::Sort(_)
+            )
+    }
+
+    fn apply(&self, node: &mut QueryNode<'_>) -> bool {
+        let top_n = match (node.query_operation(), node.children()[0].query_operation()) {
+            (QueryOperation::Limit(limit), QueryOperation::Sort(keys)) => {
+                QueryOperation::TopN(keys.clone(), *limit)
             }
+            _ => return false,
+        };
+
+        if let QueryChildren::One(mut sort) = node.take_children() {
+            *node.children_mut() = sort.take_children();
+        }
+        *node.query_mut() = top_n;
+        true
+    }
+}
+
+/// Executes inner joins of relations partitioned the same way on the joined fields one pair of
+/// partitions at a time, and other inner joins as hash joins when that's estimated to cost less
+/// than comparing every pair of tuples, with the smaller input loaded into the hash table
+pub struct ChooseJoinStrategies(pub CostModel);
+
+impl Rule for ChooseJoinStrategies {
+    fn name(&self) -> &str {
+        "choose_join_strategies"
+    }
+
+    fn traversal(&self) -> Traversal {
+        Traversal::BottomUp
+    }
+
+    fn matches(&self, node: &QueryNode<'_>) -> bool {
+        matches!(node.query_operation(), QueryOperation::InnerJoin(_))
+    }
+
+    fn apply(&self, node: &mut QueryNode<'_>) -> bool {
+        let condition = match node.query_operation() {
+            QueryOperation::InnerJoin(condition) => condition,
+            _ => return false,
+        };
+        let children = node.children();
+        let left = children[0].approximate_created_tuples();
+        let right = children[1].approximate_created_tuples();
+        let partitioned = match (children[0].query_operation(), children[1].query_operation()) {
+            (QueryOperation::PartitionedSource(left), QueryOperation::PartitionedSource(right)) => {
+                left.field() == condition.left_id()
+                    && right.field() == condition.right_id()
+                    && left.is_partitioned_like(right)
+            }
+            _ => false,
+        };
+        let hash_join = || {
+            let build = if left < right && !node.has_hint(Hint::NoReorder) {
+                JoinSide::Left
+            } else {
+                JoinSide::Right
+            };
+            Some(QueryOperation::HashJoin(condition.clone(), build))
+        };
+
+        let strategy = if node.has_hint(Hint::HashJoin) {
+            hash_join()
+        } else if node.has_hint(Hint::NestedLoopJoin) {
+            None
+        } else if partitioned {
+            Some(QueryOperation::PartitionWiseJoin(condition.clone()))
+        } else if self.0.hash_join(left.min(right), left.max(right))
+            < self.0.nested_loop_join(left, right)
+        {
+            hash_join()
         } else {
-            (false, None)
+            None
+        };
+
+        match strategy {
+            Some(strategy) => {
+                *node.query_mut() = strategy;
+                true
+            }
+            None => false,
         }
+    }
+}
 
-         */
+/// Looks up the tuple of a source by its primary key when the selections directly above the
+/// source fix every field of the key to a constant, and reading the one block the key hashes into
+/// is estimated to cost less than scanning every block of the source. Otherwise, bounds on the
+/// first field of the key, or a `LIKE` pattern on it with a fixed prefix, read a range of keys
+/// instead, if reading the block of every key in it is estimated to be cheaper. The selections
+/// are kept to check the rest of their conditions.
+pub struct ChooseAccessPaths(pub CostModel);
+
+impl Rule for ChooseAccessPaths {
+    fn name(&self) -> &str {
+        "choose_access_paths"
+    }
+
+    fn traversal(&self) -> Traversal {
+        Traversal::TopDown
     }
 
-     */
+    fn matches(&self, node: &QueryNode<'_>) -> bool {
+        matches!(node.query_operation(), QueryOperation::Selection(_))
+    }
+
+    fn apply(&self, node: &mut QueryNode<'_>) -> bool {
+        match Optimizer::access_path(node, &self.0) {
+            Some(access) => {
+                let mut source = &mut *node;
+                while let QueryOperation::Selection(_) = source.query_operation() {
+                    source = source.children_mut_list().remove(0);
+                }
+                *source.query_mut() = access;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// Counts the tuples of a source from the lengths of its blocks instead of reading them, when
+/// nothing between the count and the source filters its tuples. Projections and sorts keep every
+/// tuple, so they're skipped over.
+pub struct CountFromMetadata;
+
+impl Rule for CountFromMetadata {
+    fn name(&self) -> &str {
+        "count_from_metadata"
+    }
+
+    fn traversal(&self) -> Traversal {
+        Traversal::TopDown
+    }
+
+    fn matches(&self, node: &QueryNode<'_>) -> bool {
+        matches!(node.query_operation(), QueryOperation::Count)
+    }
+
+    fn apply(&self, node: &mut QueryNode<'_>) -> bool {
+        let mut counted = node.children()[0];
+        while let QueryOperation::Projection(_) | QueryOperation::Sort(_) =
+            counted.query_operation()
+        {
+            counted = counted.children()[0];
+        }
+        match counted.query_operation() {
+            QueryOperation::Source(source) => {
+                let relation = source.relation();
+                *node.query_mut() = QueryOperation::RelationCount(relation);
+                node.take_children();
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Removes sorts whose input is already in their order, such as a range of keys, turning top-n
+/// nodes into plain limits. The order has to match exactly, including where `NULL` values are
+/// placed.
+pub struct RemoveSatisfiedSorts;
+
+impl Rule for RemoveSatisfiedSorts {
+    fn name(&self) -> &str {
+        "remove_satisfied_sorts"
+    }
+
+    fn traversal(&self) -> Traversal {
+        Traversal::BottomUp
+    }
+
+    fn matches(&self, node: &QueryNode<'_>) -> bool {
+        match node.query_operation() {
+            QueryOperation::Sort(keys) | QueryOperation::TopN(keys, _) => {
+                node.children()[0].sorted_by().starts_with(keys)
+            }
+            _ => false,
+        }
+    }
+
+    fn apply(&self, node: &mut QueryNode<'_>) -> bool {
+        if let QueryOperation::TopN(_, limit) = node.query_operation() {
+            *node.query_mut() = QueryOperation::Limit(*limit);
+        } else if let QueryChildren::One(child) = node.take_children() {
+            *node = child;
+        }
+        true
+    }
+}
+
+/// Adds projections directly above sources that drop every field no node above the source uses,
+/// so that tuples are as small as possible from the start
+pub struct PruneUnusedFields;
+
+impl Rule for PruneUnusedFields {
+    fn name(&self) -> &str {
+        "prune_unused_fields"
+    }
+
+    /// Which fields are used depends on every node above a source, so the whole query is pruned
+    /// at once
+    fn traversal(&self) -> Traversal {
+        Traversal::Root
+    }
+
+    fn matches(&self, _node: &QueryNode<'_>) -> bool {
+        true
+    }
+
+    fn apply(&self, node: &mut QueryNode<'_>) -> bool {
+        Optimizer::prune_unused_fields(node, None) > 0
+    }
+}
+
+/// Reads the fields projected from a scan straight from an index that covers them, so that no
+/// blocks are read. An index scan reads its own index, and a scan of a whole relation reads the
+/// smallest index that holds every tuple.
+///
+/// Must run after [PruneUnusedFields], which adds the projections above the scans.
+pub struct ChooseCoveringIndexes;
+
+impl Rule for ChooseCoveringIndexes {
+    fn name(&self) -> &str {
+        "choose_covering_indexes"
+    }
+
+    fn traversal(&self) -> Traversal {
+        Traversal::TopDown
+    }
+
+    fn matches(&self, node: &QueryNode<'_>) -> bool {
+        matches!(node.query_operation(), QueryOperation::Projection(_))
+    }
+
+    fn apply(&self, node: &mut QueryNode<'_>) -> bool {
+        let fields = match node.query_operation() {
+            QueryOperation::Projection(fields) => fields.clone(),
+            _ => return false,
+        };
+        let scan = &mut node.children_mut_list()[0];
+        let projected = fields
+            .iter()
+            .map(|field| {
+                scan.resulting_relation()
+                    .iter()
+                    .position(|(id, _)| id == field)
+            })
+            .collect::<Option<Vec<usize>>>();
+        let access = match (scan.query_operation(), projected) {
+            (QueryOperation::IndexScan(relation, index, lower, upper), Some(projected)) => relation
+                .index(index)
+                .filter(|index| index.covers(&projected))
+                .map(|_| (*relation, index.clone(), lower.clone(), upper.clone())),
+            (QueryOperation::Source(source), Some(projected)) => {
+                let relation = source.relation();
+                relation
+                    .indexes()
+                    .iter()
+                    .filter(|index| !index.is_partial() && index.covers(&projected))
+                    .min_by_key(|index| index.covered().len())
+                    .map(|index| {
+                        let name = index.name().to_string();
+                        (relation, name, Bound::Unbounded, Bound::Unbounded)
+                    })
+            }
+            _ => None,
+        };
+        match access {
+            Some((relation, index, lower, upper)) => {
+                scan.read_index_only(relation, index, lower, upper);
+                true
+            }
+            None => false,
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::query::conditions::JoinCondition;
     use crate::query::conditions::{Condition, ConditionOperation, Operand};
+    use crate::query::partial_index::create_partial_index;
+    use crate::query::partition::{PartitionScheme, PartitionedRelation};
+    use crate::query::query_node::AntiJoinKind;
+    use crate::query::sort::SortKey;
     use rad_db_structure::prelude::*;
     use rad_db_types::{Type, Value};
+    use std::convert::TryFrom;
     use std::iter::FromIterator;
+    use std::ops::Bound;
 
     #[test]
     fn and_equivalence() {
@@ -426,7 +1276,9 @@ mod tests {
         );
         for i in 0..1000u64 {
             //println!("Inserting tuple {}", i);
-            relation1.insert(Tuple::from_iter(&[Value::from(i)]));
+            relation1
+                .insert(Tuple::from_iter(&[Value::from(i)]))
+                .unwrap();
         }
         let query = QueryNode::select_on_condition(
             QueryNode::source(&relation1),
@@ -451,4 +1303,1019 @@ mod tests {
         assert_ne!(optimized.nodes(), query_copied.nodes()); // shouldn't be same
         assert_eq!(optimized.nodes() - 1, query_copied.nodes()); // should be exactly one more node
     }
+
+    fn nullable_relation(name: &str, values: Vec<Option<u64>>) -> Relation {
+        let mut relation = Relation::new_volatile(
+            Identifier::new(name),
+            vec![
+                (format!("{}_id", name), Type::from(0u64)),
+                (
+                    format!("{}_value", name),
+                    Type::Optional(Some(Box::new(Type::from(0u64)))),
+                ),
+            ],
+            64,
+            PrimaryKeyDefinition::new(vec![0]),
+        );
+        for (id, value) in values.into_iter().enumerate() {
+            let value = Type::Optional(value.map(|v| Box::new(Type::from(v))));
+            relation
+                .insert(Tuple::from_iter(&[Value::from(id as u64), value]))
+                .unwrap();
+        }
+        relation
+    }
+
+    fn anti_join_values(query: QueryNode) -> Vec<Option<u64>> {
+        let mut values: Vec<Option<u64>> = query
+            .execute_query()
+            .into_iter()
+            .map(|tuple| match tuple.take(1) {
+                Type::Optional(Some(inner)) => Some(u64::try_from(*inner).unwrap()),
+                _ => None,
+            })
+            .collect();
+        values.sort();
+        values
+    }
+
+    #[test]
+    fn negated_subqueries_become_anti_joins() {
+        let left = nullable_relation("l", vec![Some(1), Some(2), Some(3), None]);
+        let right = nullable_relation("r", vec![Some(2)]);
+        let right_with_null = nullable_relation("rn", vec![Some(2), None]);
+        let empty = nullable_relation("e", vec![]);
+
+        let cases = vec![
+            (
+                &right,
+                "r_value",
+                AntiJoinKind::NotIn,
+                vec![Some(1), Some(3)],
+            ),
+            (
+                &right,
+                "r_value",
+                AntiJoinKind::NotExists,
+                vec![None, Some(1), Some(3)],
+            ),
+            (&right_with_null, "rn_value", AntiJoinKind::NotIn, vec![]),
+            (
+                &right_with_null,
+                "rn_value",
+                AntiJoinKind::NotExists,
+                vec![None, Some(1), Some(3)],
+            ),
+            (
+                &empty,
+                "e_value",
+                AntiJoinKind::NotIn,
+                vec![None, Some(1), Some(2), Some(3)],
+            ),
+        ];
+
+        for (subquery, field, kind, expected) in cases {
+            let condition = JoinCondition::new(Identifier::new("l_value"), Identifier::new(field));
+            let query = match kind {
+                AntiJoinKind::NotIn => QueryNode::not_in(
+                    QueryNode::source(&left),
+                    QueryNode::source(subquery),
+                    condition,
+                ),
+                AntiJoinKind::NotExists => QueryNode::not_exists(
+                    QueryNode::source(&left),
+                    QueryNode::source(subquery),
+                    condition,
+                ),
+            };
+            let optimized = query.clone().optimized();
+            if let QueryOperation::AntiJoin(_, optimized_kind) = optimized.query_operation() {
+                assert_eq!(*optimized_kind, kind);
+            } else {
+                panic!("Negated subquery wasn't rewritten into an anti join");
+            }
+            assert_eq!(anti_join_values(query), expected);
+            assert_eq!(anti_join_values(optimized), expected);
+        }
+    }
+
+    #[test]
+    fn limit_over_sort_becomes_top_n() {
+        let mut relation = Relation::new_volatile(
+            Identifier::new("test1"),
+            vec![("field1", Type::from(0u64))],
+            64,
+            PrimaryKeyDefinition::new(vec![0]),
+        );
+        for i in 0..200u64 {
+            relation
+                .insert(Tuple::from_iter(&[Value::from(i)]))
+                .unwrap();
+        }
+        let query = QueryNode::limit(
+            QueryNode::sort(
+                QueryNode::source(&relation),
+                vec![SortKey::descending("field1")],
+            ),
+            5,
+        );
+        let optimized = query.clone().optimized();
+        if let QueryOperation::TopN(_, limit) = optimized.query_operation() {
+            assert_eq!(*limit, 5);
+        } else {
+            panic!("Limit should have been merged into the sort")
+        }
+        assert_eq!(optimized.nodes(), 2);
+
+        let expected: Vec<Tuple> = (195..200u64)
+            .rev()
+            .map(|i| Tuple::from_iter(&[Value::from(i)]))
+            .collect();
+        let unoptimized: Vec<Tuple> = query.execute_query().into_iter().collect();
+        let optimized: Vec<Tuple> = optimized.execute_query().into_iter().collect();
+        assert_eq!(unoptimized, expected);
+        assert_eq!(optimized, expected);
+    }
+
+    #[test]
+    fn rule_sets() {
+        let relation = numbered_relation("rules", 20);
+        let query = QueryNode::limit(
+            QueryNode::sort(
+                QueryNode::source(&relation),
+                vec![SortKey::descending("rules_id")],
+            ),
+            5,
+        );
+
+        let mut merged = query.clone();
+        let rules = RuleSet::new().with(MergeLimitsIntoSorts);
+        let mut optimizer = Optimizer::with_rules(&mut merged, 10, rules);
+        optimizer.optimize();
+        let statistics = optimizer.statistics();
+        assert_eq!(statistics.len(), 1);
+        assert_eq!(statistics[0].name(), "merge_limits_into_sorts");
+        assert_eq!(statistics[0].applications(), 1);
+        assert!(matches!(merged.query_operation(), QueryOperation::TopN(..)));
+
+        // the query already can't be changed by the rule
+        let rules = RuleSet::new().with(MergeLimitsIntoSorts);
+        let mut optimizer = Optimizer::with_rules(&mut merged, 10, rules);
+        optimizer.optimize();
+        assert_eq!(optimizer.statistics()[0].applications(), 0);
+
+        let mut rules = RuleSet::default();
+        assert!(rules.disable("merge_limits_into_sorts"));
+        assert!(!rules.disable("push_selections_down"));
+        assert!(!rules.names().contains(&"merge_limits_into_sorts"));
+        let mut unmerged = query.clone();
+        let mut optimizer = Optimizer::with_rules(&mut unmerged, 10, rules);
+        optimizer.optimize();
+        assert!(optimizer
+            .statistics()
+            .iter()
+            .all(|statistics| statistics.name() != "merge_limits_into_sorts"));
+        assert!(matches!(
+            unmerged.query_operation(),
+            QueryOperation::Limit(5)
+        ));
+    }
+
+    #[test]
+    fn rules_reach_a_fixpoint() {
+        let relation = numbered_relation("fixpoint", 20);
+        let not = |value: u64| {
+            Condition::new(
+                "fixpoint_id",
+                ConditionOperation::Nequals(Operand::UnsignedNumber(value)),
+            )
+        };
+        let condition = Condition::and(not(2), Condition::and(not(5), not(7)));
+        let mut query = QueryNode::select_on_condition(QueryNode::source(&relation), condition);
+
+        let mut rules = RuleSet::new().with(SplitAnds).with(SplitAnds);
+        rules.set_max_iterations(1);
+        let mut optimizer = Optimizer::with_rules(&mut query, 10, rules);
+        optimizer.optimize();
+        let applications: Vec<usize> = optimizer
+            .statistics()
+            .iter()
+            .map(|statistics| statistics.applications())
+            .collect();
+        assert_eq!(applications, vec![1, 0]);
+        assert_eq!(query.nodes(), 4);
+        assert_eq!(SplitAnds.rewrite(&mut query), 0);
+    }
+
+    fn numbered_relation(name: &str, count: u64) -> Relation {
+        let mut relation = Relation::new_volatile(
+            Identifier::new(name),
+            vec![(format!("{}_id", name), Type::from(0u64))],
+            64,
+            PrimaryKeyDefinition::new(vec![0]),
+        );
+        for i in 0..count {
+            relation
+                .insert(Tuple::from_iter(&[Value::from(i)]))
+                .unwrap();
+        }
+        relation
+    }
+
+    #[test]
+    fn join_hints() {
+        let small = numbered_relation("small", 50);
+        let large = numbered_relation("large", 400);
+        let join = |hint: Option<Hint>| {
+            let join = QueryNode::inner_join(
+                QueryNode::source(&small),
+                QueryNode::source(&large),
+                JoinCondition::new(Identifier::new("small_id"), Identifier::new("large_id")),
+            );
+            match hint {
+                None => join,
+                Some(hint) => join.with_hint(hint),
+            }
+        };
+        let strategy = |query: &QueryNode| match query.query_operation() {
+            QueryOperation::HashJoin(_, build) => Some(*build),
+            QueryOperation::InnerJoin(_) => None,
+            _ => panic!("Join should stay a join"),
+        };
+
+        let expected: Vec<Tuple> = join(None).execute_query().into_iter().collect();
+        assert_eq!(expected.len(), 50);
+
+        let optimized = join(None).optimized();
+        assert_eq!(strategy(&optimized), Some(JoinSide::Left));
+        let mut tuples: Vec<Tuple> = optimized.execute_query().into_iter().collect();
+        tuples.sort_by_key(|tuple| tuple.to_string());
+        let mut sorted_expected = expected.clone();
+        sorted_expected.sort_by_key(|tuple| tuple.to_string());
+        assert_eq!(tuples, sorted_expected);
+
+        let optimized = join(Some(Hint::NestedLoopJoin)).optimized();
+        assert_eq!(strategy(&optimized), None);
+        let optimized = join(Some(Hint::NoReorder)).optimized();
+        assert_eq!(strategy(&optimized), Some(JoinSide::Right));
+
+        let tiny = numbered_relation("tiny", 5);
+        let optimized = QueryNode::inner_join(
+            QueryNode::source(&tiny),
+            QueryNode::source(&small),
+            JoinCondition::new(Identifier::new("tiny_id"), Identifier::new("small_id")),
+        )
+        .with_hint(Hint::HashJoin)
+        .optimized();
+        assert_eq!(strategy(&optimized), Some(JoinSide::Left));
+        assert_eq!(optimized.execute_query().into_iter().count(), 5);
+    }
+
+    #[test]
+    fn index_hints() {
+        let mut relation = Relation::new_volatile(
+            Identifier::new("flags"),
+            vec![("id", Type::from(0u64)), ("flag", Type::from(0u8))],
+            64,
+            PrimaryKeyDefinition::new(vec![0]),
+        );
+        for i in 0..400u64 {
+            relation
+                .insert(Tuple::from_iter(&[
+                    Value::from(i),
+                    Value::from((i % 2) as u8),
+                ]))
+                .unwrap();
+        }
+        assert!(relation.create_index("by_flag", vec![1]));
+        let flagged = || {
+            QueryNode::select_eq(
+                QueryNode::source(&relation),
+                Identifier::new("flag"),
+                Operand::UnsignedNumber(1),
+            )
+        };
+
+        // half of the tuples are cheaper to read by scanning the source
+        let optimized = flagged().optimized();
+        assert!(!optimized.explain().contains("IndexScan"));
+
+        let optimized = flagged()
+            .with_hint(Hint::Index("by_flag".to_string()))
+            .optimized();
+        assert!(optimized
+            .explain()
+            .contains("IndexScan by_flag on flags >= (1) <= (1)"));
+        let expected = sorted(flagged());
+        assert_eq!(expected.len(), 200);
+        assert_eq!(sorted(optimized), expected);
+
+        // an index that doesn't exist can't be used
+        let optimized = flagged()
+            .with_hint(Hint::Index("missing".to_string()))
+            .optimized();
+        assert!(!optimized.explain().contains("IndexScan"));
+        assert_eq!(sorted(optimized), expected);
+    }
+
+    #[test]
+    fn tuned_costs() {
+        let small = numbered_relation("small", 50);
+        let large = numbered_relation("large", 400);
+        let join = || {
+            QueryNode::inner_join(
+                QueryNode::source(&small),
+                QueryNode::source(&large),
+                JoinCondition::new(Identifier::new("small_id"), Identifier::new("large_id")),
+            )
+        };
+        let optimize = |costs: CostModel| {
+            let mut query = join();
+            Optimizer::new(&mut query, 10, costs).optimize();
+            query
+        };
+
+        let hashed = optimize(CostModel::default());
+        assert!(matches!(
+            hashed.query_operation(),
+            QueryOperation::HashJoin(..)
+        ));
+        let expensive_inserts = CostModel::default().with_hash_insert(10.0);
+        let nested = optimize(expensive_inserts);
+        assert!(matches!(
+            nested.query_operation(),
+            QueryOperation::InnerJoin(_)
+        ));
+
+        let costs = CostModel::default()
+            .with_block_read(2.0)
+            .with_tuple_comparison(0.5);
+        let scan = 2.0 * small.block_count() as f64 + 0.5 * 50.0;
+        assert_eq!(costs.cost_of(&QueryNode::source(&small)), scan);
+        assert_eq!(costs.cost_of(&nested), 0.5 * 50.0 * 400.0);
+        assert!(
+            nested
+                .explain_with_costs(&costs)
+                .contains(&format!("Scan small (~50 tuples) (cost {:.2})", scan)),
+            "{}",
+            nested.explain_with_costs(&costs)
+        );
+        assert_eq!(
+            costs.total_cost(&nested),
+            costs.cost_of(&nested) + scan + costs.scan(&large)
+        );
+    }
+
+    #[test]
+    fn access_paths() {
+        let single = numbered_relation("single", 1);
+        let large = numbered_relation("large", 400);
+        fn select<'r>(relation: &'r Relation, field: &str, id: u64) -> QueryNode<'r> {
+            QueryNode::select_eq(
+                QueryNode::source(relation),
+                Identifier::new(field),
+                Operand::UnsignedNumber(id),
+            )
+            .optimized()
+        }
+
+        let lookup = select(&large, "large_id", 7);
+        assert!(
+            lookup.explain().contains("KeyLookup large (7)"),
+            "{}",
+            lookup.explain()
+        );
+        let tuples: Vec<Tuple> = lookup.execute_query().into_iter().collect();
+        assert_eq!(tuples, vec![Tuple::from_iter(&[Value::from(7u64)])]);
+
+        // every tuple of a relation with one block is read either way
+        let scan = select(&single, "single_id", 0);
+        assert!(scan.explain().contains("Scan single"), "{}", scan.explain());
+        assert_eq!(scan.execute_query().into_iter().count(), 1);
+    }
+
+    fn customers_and_orders() -> (Relation, Relation) {
+        let customers = numbered_relation("customer", 5);
+        let mut orders = Relation::new_volatile(
+            Identifier::new("orders"),
+            vec![("order_id", Type::from(0u64)), ("buyer", Type::from(0u64))],
+            64,
+            PrimaryKeyDefinition::new(vec![0]),
+        );
+        for (order, buyer) in &[(0u64, 0u64), (1, 0), (2, 1), (3, 9)] {
+            orders
+                .insert(Tuple::from_iter(&[
+                    Value::from(*order),
+                    Value::from(*buyer),
+                ]))
+                .unwrap();
+        }
+        (customers, orders)
+    }
+
+    fn sorted(query: QueryNode) -> Vec<String> {
+        let mut tuples: Vec<String> = query
+            .execute_query()
+            .into_iter()
+            .map(|tuple| tuple.to_string())
+            .collect();
+        tuples.sort();
+        tuples
+    }
+
+    #[test]
+    fn outer_join_rules() {
+        let (customers, orders) = customers_and_orders();
+        let condition =
+            || JoinCondition::new(Identifier::new("customer_id"), Identifier::new("buyer"));
+        let left_join = || {
+            QueryNode::left_join(
+                QueryNode::source(&customers),
+                QueryNode::source(&orders),
+                condition(),
+            )
+        };
+
+        let tuples: Vec<Tuple> = left_join().execute_query().into_iter().collect();
+        assert_eq!(tuples.len(), 6);
+        let padded: Vec<&Tuple> = tuples
+            .iter()
+            .filter(|tuple| tuple[1] == Value::Optional(None))
+            .collect();
+        assert_eq!(padded.len(), 3);
+        let full_join = QueryNode::full_join(
+            QueryNode::source(&customers),
+            QueryNode::source(&orders),
+            condition(),
+        );
+        assert_eq!(full_join.execute_query().into_iter().count(), 7);
+
+        let mut commuted = left_join();
+        assert!(Optimizer::commute_join(&mut commuted));
+        assert!(matches!(
+            commuted.query_operation(),
+            QueryOperation::RightJoin(_)
+        ));
+        let swapped: Vec<Tuple> = commuted.execute_query().into_iter().collect();
+        assert_eq!(swapped.len(), 6);
+        for tuple in swapped {
+            let original = Tuple::new(vec![tuple[2].clone(), tuple[0].clone(), tuple[1].clone()]);
+            assert!(tuples.contains(&original));
+        }
+
+        // filtering the preserved side is the same before and after the join
+        let select_customer = |node| {
+            QueryNode::select_eq(
+                node,
+                Identifier::new("customer_id"),
+                Operand::UnsignedNumber(4),
+            )
+        };
+        let mut pushed = select_customer(left_join());
+        assert!(Optimizer::push_selection_through_join(&mut pushed));
+        assert_eq!(sorted(pushed), sorted(select_customer(left_join())));
+
+        // filtering the padded side would keep tuples that the selection removes
+        let select_buyer =
+            |node| QueryNode::select_eq(node, Identifier::new("buyer"), Operand::UnsignedNumber(0));
+        let mut not_pushed = select_buyer(left_join());
+        assert!(!Optimizer::push_selection_through_join(&mut not_pushed));
+        let mut inner = select_buyer(QueryNode::inner_join(
+            QueryNode::source(&customers),
+            QueryNode::source(&orders),
+            condition(),
+        ));
+        assert!(Optimizer::push_selection_through_join(&mut inner));
+        assert_eq!(sorted(inner).len(), 2);
+    }
+
+    #[test]
+    fn unused_fields_are_pruned() {
+        let (customers, orders) = customers_and_orders();
+        let join = || {
+            QueryNode::inner_join(
+                QueryNode::source(&customers),
+                QueryNode::source(&orders),
+                JoinCondition::new(Identifier::new("customer_id"), Identifier::new("buyer")),
+            )
+        };
+        let query = || QueryNode::projection(join(), vec![Identifier::new("customer_id")]);
+
+        let optimized = query().optimized();
+        let optimized_join = optimized.children()[0];
+        assert_eq!(optimized_join.resulting_relation().len(), 2);
+        let children = optimized_join.children();
+        assert!(matches!(
+            children[0].query_operation(),
+            QueryOperation::Source(_)
+        ));
+        match children[1].query_operation() {
+            QueryOperation::Projection(fields) => {
+                assert_eq!(fields, &vec![Identifier::new("buyer")])
+            }
+            _ => panic!("Unused fields of orders should have been pruned"),
+        }
+        assert_eq!(sorted(optimized), sorted(query()));
+
+        // fields used by a selection above the join are kept
+        let selected = || {
+            QueryNode::projection(
+                QueryNode::select_eq(
+                    join(),
+                    Identifier::new("order_id"),
+                    Operand::UnsignedNumber(2),
+                ),
+                vec![Identifier::new("customer_id")],
+            )
+        };
+        let optimized = selected().optimized();
+        let optimized_join = optimized.children()[0].children()[0];
+        assert!(matches!(
+            optimized_join.children()[1].query_operation(),
+            QueryOperation::Source(_)
+        ));
+        assert_eq!(sorted(optimized), sorted(selected()));
+    }
+
+    #[test]
+    fn constant_conditions_are_folded() {
+        let numbers = numbered_relation("number", 10);
+        let compared = |operation| {
+            QueryNode::select_on_condition(
+                QueryNode::source(&numbers),
+                Condition::new("number_id", operation),
+            )
+        };
+
+        let always = compared(ConditionOperation::Equals(Operand::from("number_id"))).optimized();
+        assert!(matches!(
+            always.query_operation(),
+            QueryOperation::Source(_)
+        ));
+        assert_eq!(always.execute_query().into_iter().count(), 10);
+
+        let never = compared(ConditionOperation::Nequals(Operand::from("number_id")));
+        assert_eq!(never.clone().execute_query().into_iter().count(), 0);
+        let never = never.optimized();
+        assert!(matches!(never.query_operation(), QueryOperation::Empty));
+        assert_eq!(never.resulting_relation().len(), 1);
+        assert_eq!(never.execute_query().into_iter().count(), 0);
+    }
+
+    #[test]
+    fn contradictions_become_empty() {
+        let (customers, orders) = customers_and_orders();
+        let contradiction = |node| {
+            QueryNode::select_on_condition(
+                node,
+                Condition::and(
+                    Condition::new(
+                        "buyer",
+                        ConditionOperation::Equals(Operand::UnsignedNumber(1)),
+                    ),
+                    Condition::new(
+                        "buyer",
+                        ConditionOperation::Equals(Operand::UnsignedNumber(2)),
+                    ),
+                ),
+            )
+        };
+        let condition =
+            || JoinCondition::new(Identifier::new("customer_id"), Identifier::new("buyer"));
+
+        let inner = QueryNode::inner_join(
+            QueryNode::source(&customers),
+            contradiction(QueryNode::source(&orders)),
+            condition(),
+        )
+        .optimized();
+        assert!(matches!(inner.query_operation(), QueryOperation::Empty));
+        assert_eq!(inner.resulting_relation().len(), 3);
+        assert_eq!(inner.execute_query().into_iter().count(), 0);
+
+        // the customers are still kept when none of the orders can match
+        let left = || {
+            QueryNode::left_join(
+                QueryNode::source(&customers),
+                contradiction(QueryNode::source(&orders)),
+                condition(),
+            )
+        };
+        let optimized = left().optimized();
+        assert!(matches!(
+            optimized.query_operation(),
+            QueryOperation::LeftJoin(_)
+        ));
+        assert!(matches!(
+            optimized.children()[1].query_operation(),
+            QueryOperation::Empty
+        ));
+        assert_eq!(sorted(optimized), sorted(left()));
+        assert_eq!(sorted(left()).len(), 5);
+    }
+
+    /// Splits the tuples between relations by the value of a field using the scheme
+    fn partitioned(
+        name: &str,
+        fields: Vec<(&str, Type)>,
+        field: usize,
+        scheme: &PartitionScheme,
+        tuples: Vec<Tuple>,
+    ) -> Vec<Relation> {
+        let mut partitions: Vec<Relation> = (0..scheme.partitions())
+            .map(|i| {
+                Relation::new_volatile(
+                    Identifier::new(format!("{}_{}", name, i)),
+                    fields.clone(),
+                    16,
+                    PrimaryKeyDefinition::new(vec![0]),
+                )
+            })
+            .collect();
+        for tuple in tuples {
+            partitions[scheme.partition_of(&tuple[field])]
+                .insert(tuple)
+                .unwrap();
+        }
+        partitions
+    }
+
+    #[test]
+    fn partition_wise_joins() {
+        let scheme = PartitionScheme::Hash(3);
+        let customers = |scheme: &PartitionScheme| {
+            partitioned(
+                "customer",
+                vec![("customer_id", Type::from(0u64))],
+                0,
+                scheme,
+                (0..20u64)
+                    .map(|i| Tuple::from_iter(&[Value::from(i)]))
+                    .collect(),
+            )
+        };
+        let orders = partitioned(
+            "orders",
+            vec![("order_id", Type::from(0u64)), ("buyer", Type::from(0u32))],
+            1,
+            &scheme,
+            (0..60u64)
+                .map(|i| Tuple::from_iter(&[Value::from(i), Value::from((i * 7 % 25) as u32)]))
+                .collect(),
+        );
+        let range = PartitionScheme::Range(vec![Value::from(10u64), Value::from(15u64)]);
+        let hashed_customers = customers(&scheme);
+        let ranged_customers = customers(&range);
+        let join = |customer_scheme: &PartitionScheme| {
+            let customers = if customer_scheme == &scheme {
+                &hashed_customers
+            } else {
+                &ranged_customers
+            };
+            QueryNode::inner_join(
+                QueryNode::partitioned_source(PartitionedRelation::new(
+                    "customer_id",
+                    customer_scheme.clone(),
+                    customers.iter().collect(),
+                )),
+                QueryNode::partitioned_source(PartitionedRelation::new(
+                    "buyer",
+                    scheme.clone(),
+                    orders.iter().collect(),
+                )),
+                JoinCondition::new(Identifier::new("customer_id"), Identifier::new("buyer")),
+            )
+        };
+
+        let optimized = join(&scheme).optimized();
+        assert!(matches!(
+            optimized.query_operation(),
+            QueryOperation::PartitionWiseJoin(_)
+        ));
+        let expected = sorted(join(&scheme));
+        assert_eq!(expected.len(), 48);
+        assert_eq!(sorted(optimized), expected);
+
+        // partitions with the same index can't be joined alone when the schemes differ
+        let mismatched = join(&range).optimized();
+        assert!(!matches!(
+            mismatched.query_operation(),
+            QueryOperation::PartitionWiseJoin(_)
+        ));
+        assert_eq!(sorted(mismatched), expected);
+    }
+
+    #[test]
+    fn satisfied_sorts() {
+        let mut relation = Relation::new_volatile(
+            Identifier::new("ordered"),
+            vec![("id", Type::from(0u64)), ("value", Type::from(0u64))],
+            8,
+            PrimaryKeyDefinition::new(vec![0]),
+        );
+        for i in 0..20u64 {
+            relation
+                .insert(Tuple::from_iter(&[Value::from(i), Value::from(i % 3)]))
+                .unwrap();
+        }
+        let range = || {
+            QueryNode::key_range(
+                &relation,
+                Bound::Included(vec![Value::from(5u64)]),
+                Bound::Unbounded,
+            )
+        };
+
+        let sorted = QueryNode::sort(range(), vec![SortKey::ascending("id").nulls_first()]);
+        let optimized = sorted.clone().optimized();
+        assert!(matches!(
+            optimized.query_operation(),
+            QueryOperation::KeyRange(..)
+        ));
+        let ids = |query: QueryNode| -> Vec<Tuple> { query.execute_query().into_iter().collect() };
+        assert_eq!(ids(optimized), ids(sorted));
+
+        let top = QueryNode::limit(
+            QueryNode::sort(range(), vec![SortKey::ascending("id").nulls_first()]),
+            3,
+        )
+        .optimized();
+        assert!(matches!(top.query_operation(), QueryOperation::Limit(3)));
+
+        // NULL values would be placed differently than in the key order
+        let nulls_last = QueryNode::sort(range(), vec![SortKey::ascending("id")]).optimized();
+        assert!(matches!(
+            nulls_last.query_operation(),
+            QueryOperation::Sort(_)
+        ));
+        let other_field = QueryNode::sort(range(), vec![SortKey::ascending("value")]).optimized();
+        assert!(matches!(
+            other_field.query_operation(),
+            QueryOperation::Sort(_)
+        ));
+    }
+
+    #[test]
+    fn like_prefix_ranges() {
+        let mut words = Relation::new_volatile(
+            Identifier::new("words"),
+            vec![("word", Type::from("")), ("length", Type::from(0u64))],
+            4,
+            PrimaryKeyDefinition::new(vec![0]),
+        );
+        for i in 0..500u64 {
+            let word = format!("{}{:03}", ["ab", "ac", "b"][i as usize % 3], i);
+            words
+                .insert(Tuple::from_iter(&[
+                    Value::from(word.as_str()),
+                    Value::from(word.len() as u64),
+                ]))
+                .unwrap();
+        }
+        fn like<'r>(relation: &'r Relation, pattern: &str) -> QueryNode<'r> {
+            QueryNode::select_on_condition(
+                QueryNode::source(relation),
+                Condition::new("word", ConditionOperation::Like(pattern.to_string())),
+            )
+        }
+        fn words_of(query: QueryNode) -> Vec<String> {
+            let mut words: Vec<String> = query
+                .execute_query()
+                .into_iter()
+                .map(|tuple| tuple[0].to_string())
+                .collect();
+            words.sort();
+            words
+        }
+
+        let query = like(&words, "ab1%");
+        let optimized = query.clone().optimized();
+        assert!(optimized
+            .explain()
+            .contains("KeyRange words >= (\"ab1\") < (\"ab2\")"));
+        let expected = words_of(query);
+        assert_eq!(expected.len(), 33);
+        assert_eq!(words_of(optimized), expected);
+
+        // most words match, so scanning is cheaper
+        let broad = like(&words, "a%").optimized();
+        assert!(!broad.explain().contains("KeyRange"));
+        // a pattern without a prefix can't be a range
+        let suffix = like(&words, "%99").optimized();
+        assert!(!suffix.explain().contains("KeyRange"));
+        let ignoring_case = QueryNode::select_on_condition(
+            QueryNode::source(&words),
+            Condition::new("word", ConditionOperation::Like("ab1%".to_string()))
+                .with_text_comparison(TextComparison::CaseInsensitive),
+        )
+        .optimized();
+        assert!(!ignoring_case.explain().contains("KeyRange"));
+
+        assert_eq!(successor("ab"), Some("ac".to_string()));
+        assert_eq!(successor("a\u{10FFFF}"), Some("b".to_string()));
+        assert_eq!(successor("\u{10FFFF}"), None);
+    }
+
+    #[test]
+    fn bounded_key_ranges() {
+        let mut events = Relation::new_volatile(
+            Identifier::new("events"),
+            vec![("time", Type::from(0u32)), ("kind", Type::from(0u64))],
+            4,
+            PrimaryKeyDefinition::new(vec![0]),
+        );
+        for i in 0..600u32 {
+            events
+                .insert(Tuple::from_iter(&[
+                    Value::from(i),
+                    Value::from(i as u64 % 5),
+                ]))
+                .unwrap();
+        }
+        fn ranged<'r>(
+            relation: &'r Relation,
+            lower: Bound<Operand>,
+            upper: Bound<Operand>,
+        ) -> QueryNode<'r> {
+            QueryNode::select_on_condition(
+                QueryNode::source(relation),
+                Condition::range("time", lower, upper),
+            )
+        }
+        fn times_of(query: QueryNode) -> Vec<Tuple> {
+            let mut tuples: Vec<Tuple> = query.execute_query().into_iter().collect();
+            tuples.sort_by(|left, right| left[0].compare(&right[0]).unwrap());
+            tuples
+        }
+
+        let query = ranged(
+            &events,
+            Bound::Excluded(Operand::UnsignedNumber(100)),
+            Bound::Included(Operand::SignedNumber(120)),
+        );
+        let optimized = query.clone().optimized();
+        assert!(optimized
+            .explain()
+            .contains("KeyRange events > (100) <= (120)"));
+        let expected = times_of(query);
+        assert_eq!(expected.len(), 20);
+        assert_eq!(times_of(optimized), expected);
+
+        let broad = ranged(
+            &events,
+            Bound::Included(Operand::UnsignedNumber(10)),
+            Bound::Unbounded,
+        );
+        assert!(!broad.optimized().explain().contains("KeyRange"));
+        // a bound that isn't exactly a value of the key's type can't be encoded like the keys
+        let fractional = ranged(
+            &events,
+            Bound::Included(Operand::Float(100.5)),
+            Bound::Excluded(Operand::UnsignedNumber(110)),
+        );
+        let optimized = fractional.clone().optimized();
+        assert!(!optimized.explain().contains("KeyRange"));
+        assert_eq!(times_of(optimized).len(), 9);
+    }
+
+    #[test]
+    fn partial_index_scans() {
+        let mut tasks = Relation::new_volatile(
+            Identifier::new("tasks"),
+            vec![
+                ("id", Type::from(0u32)),
+                ("owner", Type::from(0u64)),
+                ("deleted", Type::from(false)),
+            ],
+            4,
+            PrimaryKeyDefinition::new(vec![0]),
+        );
+        for i in 0..600u32 {
+            tasks
+                .insert(Tuple::from_iter(&[
+                    Value::from(i),
+                    Value::from(i as u64 % 7),
+                    Value::from(i % 10 != 0),
+                ]))
+                .unwrap();
+        }
+        let not_deleted = Condition::new(
+            "deleted",
+            ConditionOperation::Equals(Operand::Boolean(false)),
+        );
+        let owned_by = |owner: u64| {
+            Condition::new(
+                "owner",
+                ConditionOperation::Equals(Operand::UnsignedNumber(owner)),
+            )
+        };
+        assert!(create_partial_index(
+            &mut tasks,
+            "open_tasks",
+            vec![Identifier::new("owner")],
+            not_deleted.clone(),
+        )
+        .unwrap());
+        assert_eq!(tasks.index("open_tasks").unwrap().len(), 60);
+        assert!(create_partial_index(
+            &mut tasks,
+            "missing",
+            vec![Identifier::new("owner")],
+            Condition::new(
+                "archived",
+                ConditionOperation::Equals(Operand::Boolean(false))
+            ),
+        )
+        .is_err());
+
+        fn select<'r>(relation: &'r Relation, conditions: Vec<Condition>) -> QueryNode<'r> {
+            conditions
+                .into_iter()
+                .fold(QueryNode::source(relation), QueryNode::select_on_condition)
+        }
+        fn ids_of(query: QueryNode) -> Vec<Tuple> {
+            let mut tuples: Vec<Tuple> = query.execute_query().into_iter().collect();
+            tuples.sort_by(|left, right| left[0].compare(&right[0]).unwrap());
+            tuples
+        }
+
+        let query = select(&tasks, vec![owned_by(3), not_deleted.clone()]);
+        let optimized = query.clone().optimized();
+        assert!(optimized
+            .explain()
+            .contains("IndexScan open_tasks on tasks >= (3) <= (3)"));
+        let expected = ids_of(query);
+        assert_eq!(expected.len(), 9);
+        assert_eq!(ids_of(optimized), expected);
+
+        // the whole index holds exactly the tuples that are wanted
+        let query = select(&tasks, vec![not_deleted]);
+        let optimized = query.clone().optimized();
+        assert!(optimized
+            .explain()
+            .contains("IndexScan open_tasks on tasks (~60 tuples)"));
+        assert_eq!(ids_of(optimized), ids_of(query));
+
+        // deleted tasks aren't in the index, so it can't answer queries that may want them
+        let query = select(&tasks, vec![owned_by(3)]).optimized();
+        assert!(!query.explain().contains("IndexScan"));
+        assert_eq!(ids_of(query).len(), 86);
+    }
+
+    #[test]
+    fn covering_index_scans() {
+        let mut accounts = Relation::new_volatile(
+            Identifier::new("accounts"),
+            vec![
+                ("id", Type::from(0u32)),
+                ("owner", Type::from(0u64)),
+                ("balance", Type::from(0u32)),
+                ("frozen", Type::from(false)),
+            ],
+            4,
+            PrimaryKeyDefinition::new(vec![0]),
+        );
+        for i in 0..600u32 {
+            accounts
+                .insert(Tuple::from_iter(&[
+                    Value::from(i),
+                    Value::from(i as u64 % 7),
+                    Value::from(i * 3 % 100),
+                    Value::from(i % 10 == 0),
+                ]))
+                .unwrap();
+        }
+        assert!(accounts.create_covering_index("by_owner", vec![1], vec![2]));
+        let fields = |fields: &[&str]| fields.iter().map(Identifier::new).collect::<Vec<_>>();
+
+        // every balance can be read from the index
+        let query =
+            QueryNode::projection(QueryNode::source(&accounts), fields(&["owner", "balance"]));
+        let optimized = query.clone().optimized();
+        assert!(optimized
+            .explain()
+            .contains("IndexOnlyScan by_owner on accounts"));
+        assert_eq!(sorted(optimized), sorted(query));
+
+        // the index scan chosen for the selection doesn't need to read the tuples either
+        let owned_by = QueryNode::select_eq(
+            QueryNode::source(&accounts),
+            Identifier::new("owner"),
+            Operand::UnsignedNumber(3),
+        );
+        let query = QueryNode::projection(owned_by.clone(), fields(&["id", "balance"]));
+        let optimized = query.clone().optimized();
+        assert!(optimized
+            .explain()
+            .contains("IndexOnlyScan by_owner on accounts >= (3) <= (3)"));
+        let expected = sorted(query);
+        assert_eq!(expected.len(), 86);
+        assert_eq!(sorted(optimized), expected);
+
+        // frozen isn't kept in the index
+        let query = QueryNode::projection(owned_by, fields(&["id", "frozen"]));
+        let optimized = query.clone().optimized();
+        assert!(!optimized.explain().contains("IndexOnlyScan"));
+        assert_eq!(sorted(optimized), sorted(query));
+    }
 }
@@ -0,0 +1,84 @@
+//! Partial indexes whose predicate is a [Condition], so that the optimizer can tell which
+//! queries only look for tuples the index holds.
+
+use std::any::Any;
+
+use rad_db_structure::identifier::Identifier;
+use rad_db_structure::key::secondary::{IndexPredicate, SecondaryIndex};
+use rad_db_structure::relations::Relation;
+use rad_db_structure::tuple::Tuple;
+
+use crate::error::MissingFieldError;
+use crate::query::conditions::Condition;
+use crate::wrapped_tuple::WrappedTuple;
+
+/// Holds the tuples of a relation a condition is true for
+pub struct ConditionPredicate {
+    condition: Condition,
+    /// The fields of the relation, as a source of it names them
+    fields: Vec<Identifier>,
+}
+
+impl ConditionPredicate {
+    pub fn condition(&self) -> &Condition {
+        &self.condition
+    }
+}
+
+impl IndexPredicate for ConditionPredicate {
+    fn matches(&self, tuple: &Tuple) -> bool {
+        let wrapped = WrappedTuple::new(&self.fields, tuple);
+        matches!(self.condition.evaluate_on(&wrapped), Ok(true))
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// Creates an index of the fields of the relation that only holds the tuples the condition is
+/// true for, returning whether it was created. It isn't if there's already an index with the
+/// name. Queries whose selections imply the condition can read the index instead of the
+/// relation.
+pub fn create_partial_index<S: ToString>(
+    relation: &mut Relation,
+    name: S,
+    fields: Vec<Identifier>,
+    condition: Condition,
+) -> Result<bool, MissingFieldError> {
+    let relation_fields: Vec<Identifier> = relation
+        .attributes()
+        .iter()
+        .map(|(field, _)| Identifier::new(field))
+        .collect();
+    let missing = condition
+        .relevant_fields()
+        .into_iter()
+        .find(|field| !relation_fields.contains(field));
+    if let Some(field) = missing {
+        return Err(MissingFieldError::new(field, None));
+    }
+    let indexed = fields
+        .into_iter()
+        .map(|field| {
+            relation_fields
+                .iter()
+                .position(|relation_field| relation_field == &field)
+                .ok_or_else(|| MissingFieldError::new(field, None))
+        })
+        .collect::<Result<Vec<usize>, _>>()?;
+    let predicate = ConditionPredicate {
+        condition,
+        fields: relation_fields,
+    };
+    Ok(relation.create_partial_index(name, indexed, predicate))
+}
+
+/// The condition of a partial index created by [create_partial_index]
+pub fn index_condition(index: &SecondaryIndex) -> Option<&Condition> {
+    index
+        .predicate()?
+        .as_any()
+        .downcast_ref::<ConditionPredicate>()
+        .map(ConditionPredicate::condition)
+}
@@ -0,0 +1,200 @@
+//! Relations that are split into several relations by the value of a field
+
+use crate::query::hash_join::{hash_join, HashKey, JoinSide};
+use rad_db_structure::identifier::Identifier;
+use rad_db_structure::memory::MemoryBudget;
+use rad_db_structure::relations::Relation;
+use rad_db_structure::tuple::Tuple;
+use rad_db_types::{Type, Value};
+use std::cmp::Ordering;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::io;
+
+/// How the tuples of a partitioned relation are split between its partitions. `NULL` values are
+/// always in the first partition.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PartitionScheme {
+    /// By the hash of the field, modulo this number of partitions. Numbers that are equal have
+    /// the same hash, regardless of their width.
+    Hash(usize),
+    /// By ranges of the field, where each partition has the values below its bound that aren't
+    /// in an earlier partition, and one last partition has the values above every bound
+    Range(Vec<Value>),
+}
+
+impl PartitionScheme {
+    /// The number of partitions values are split between
+    pub fn partitions(&self) -> usize {
+        match self {
+            PartitionScheme::Hash(partitions) => *partitions,
+            PartitionScheme::Range(bounds) => bounds.len() + 1,
+        }
+    }
+
+    /// The index of the partition a value belongs in
+    pub fn partition_of(&self, value: &Value) -> usize {
+        let key = match HashKey::new(value) {
+            Some(key) => key,
+            None => return 0,
+        };
+        match self {
+            PartitionScheme::Hash(partitions) => {
+                let mut hasher = DefaultHasher::new();
+                key.hash(&mut hasher);
+                (hasher.finish() % *partitions as u64) as usize
+            }
+            PartitionScheme::Range(bounds) => bounds
+                .iter()
+                .position(|bound| value.compare(bound) == Some(Ordering::Less))
+                .unwrap_or(bounds.len()),
+        }
+    }
+}
+
+/// A relation that is split into several relations with the same fields, where a value of the
+/// partitioning field is only ever found within the partition the scheme puts it in
+#[derive(Debug, Clone)]
+pub struct PartitionedRelation<'a> {
+    field: Identifier,
+    scheme: PartitionScheme,
+    partitions: Vec<&'a Relation>,
+}
+
+impl<'a> PartitionedRelation<'a> {
+    /// Creates a partitioned relation, where the partitions are in the order of the scheme
+    ///
+    /// # Panic
+    /// Panics if there are no partitions, if the number of partitions doesn't match the scheme,
+    /// or if any partition is missing the field.
+    pub fn new<I: Into<Identifier>>(
+        field: I,
+        scheme: PartitionScheme,
+        partitions: Vec<&'a Relation>,
+    ) -> Self {
+        let field = field.into();
+        if scheme.partitions() == 0 {
+            panic!("A partitioned relation needs at least one partition")
+        }
+        if partitions.len() != scheme.partitions() {
+            panic!(
+                "Expected {} partitions, but got {}",
+                scheme.partitions(),
+                partitions.len()
+            )
+        }
+        for partition in &partitions {
+            if partition.get_field_index(field.clone()).is_none() {
+                panic!("Partition {} has no field {}", partition.name(), field)
+            }
+        }
+        PartitionedRelation {
+            field,
+            scheme,
+            partitions,
+        }
+    }
+
+    /// The field the relation is partitioned by
+    pub fn field(&self) -> &Identifier {
+        &self.field
+    }
+
+    pub fn scheme(&self) -> &PartitionScheme {
+        &self.scheme
+    }
+
+    pub fn partitions(&self) -> &[&'a Relation] {
+        &self.partitions
+    }
+
+    /// The total number of tuples in all of the partitions
+    pub fn len(&self) -> usize {
+        self.partitions
+            .iter()
+            .map(|partition| partition.len())
+            .sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Whether equal values of the partitioning fields of both relations are always found in
+    /// partitions with the same index
+    pub fn is_partitioned_like(&self, other: &PartitionedRelation) -> bool {
+        self.scheme == other.scheme
+    }
+
+    /// All of the tuples of every partition
+    pub(crate) fn tuples(&self) -> impl Iterator<Item = Tuple> + 'a {
+        self.partitions
+            .clone()
+            .into_iter()
+            .flat_map(|partition| partition.tuples())
+    }
+}
+
+/// Joins two relations that are partitioned the same way on the joined fields one pair of
+/// partitions at a time, so that only one pair of partitions is ever loaded into memory. Fails if
+/// a pair of partitions had to be spilled to disk and couldn't be read back.
+pub(crate) fn partition_wise_join(
+    left: &PartitionedRelation,
+    right: &PartitionedRelation,
+    left_index: usize,
+    right_index: usize,
+) -> io::Result<Vec<Tuple>> {
+    let types_of = |relation: &Relation| -> Vec<Type> {
+        relation
+            .attributes()
+            .iter()
+            .map(|(_, field_type)| field_type.clone())
+            .collect()
+    };
+    let mut output = vec![];
+    for (left, right) in left.partitions.iter().zip(&right.partitions) {
+        let build = if left.len() < right.len() {
+            JoinSide::Left
+        } else {
+            JoinSide::Right
+        };
+        output.extend(hash_join(
+            left.tuples(),
+            right.tuples(),
+            left_index,
+            right_index,
+            build,
+            (&types_of(left), &types_of(right)),
+            MemoryBudget::global(),
+        )?);
+    }
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rad_db_types::Numeric;
+
+    #[test]
+    fn partition_of() {
+        let hash = PartitionScheme::Hash(4);
+        for i in 0..50u64 {
+            let partition = hash.partition_of(&Value::from(i));
+            assert!(partition < 4);
+            assert_eq!(hash.partition_of(&Value::from(i as i32)), partition);
+            assert_eq!(
+                hash.partition_of(&Numeric::Double(i as f64).into()),
+                partition
+            );
+        }
+        assert_eq!(hash.partition_of(&Value::Optional(None)), 0);
+
+        let range = PartitionScheme::Range(vec![Value::from(10u64), Value::from(20u64)]);
+        assert_eq!(range.partitions(), 3);
+        assert_eq!(range.partition_of(&Value::from(-5i32)), 0);
+        assert_eq!(range.partition_of(&Value::from(10u8)), 1);
+        assert_eq!(range.partition_of(&Value::from(19u64)), 1);
+        assert_eq!(range.partition_of(&Value::from(400u16)), 2);
+    }
+}
@@ -0,0 +1,527 @@
+//! Operators that rotate the values of rows into columns, and columns back into rows
+
+use rad_db_structure::identifier::Identifier;
+use rad_db_structure::tuple::Tuple;
+use rad_db_types::arithmetic::{ArithmeticError, Operator, Overflow};
+use rad_db_types::decimal::MAX_PRECISION;
+use rad_db_types::integer::MAX_BITS;
+use rad_db_types::{Decimal, Integer, Numeric, Type, Value};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+/// How the values that end up in the same cell of a pivot are combined
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Aggregate {
+    /// The number of non-null values
+    Count,
+    /// The sum of the values, which must be numeric
+    Sum,
+    /// The smallest value
+    Min,
+    /// The largest value
+    Max,
+}
+
+/// Adds numeric values, failing if the sum doesn't fit in the kind both are promoted to
+fn add_values(left: &Value, right: &Value) -> Result<Value, ArithmeticError> {
+    match left.promote_pair(right) {
+        Some((Type::Numeric(left), Type::Numeric(right))) => left
+            .apply(Operator::Add, right, Overflow::Fail)
+            .map(Value::from),
+        _ => Err(ArithmeticError::NotNumeric),
+    }
+}
+
+impl Aggregate {
+    /// The type of the cells created by this aggregate over values of this type
+    ///
+    /// # Panic
+    /// Panics if the aggregate is a sum and the type isn't numeric.
+    pub fn result_type(&self, value_type: &Type) -> Type {
+        match self {
+            Aggregate::Count => Type::from(0u64),
+            Aggregate::Sum => match value_type.unwrap_optional() {
+                Type::Numeric(Numeric::Signed(_)) => Type::from(0i64).nullable(),
+                Type::Numeric(Numeric::Unsigned(_)) => Type::from(0u64).nullable(),
+                Type::Numeric(Numeric::Decimal(decimal)) => {
+                    Type::from(Decimal::zero(MAX_PRECISION, decimal.scale())).nullable()
+                }
+                Type::Numeric(Numeric::BigInt(_)) => Type::from(Integer::zero(MAX_BITS)).nullable(),
+                Type::Numeric(_) => Type::from(Numeric::Double(0.0)).nullable(),
+                _ => panic!("Can only sum numeric values"),
+            },
+            Aggregate::Min | Aggregate::Max => value_type.unwrap_optional().clone().nullable(),
+        }
+    }
+
+    /// Adds a value to the current state of a cell, failing if a sum overflows
+    fn accumulate(
+        &self,
+        state: Option<Value>,
+        value: &Value,
+    ) -> Result<Option<Value>, ArithmeticError> {
+        if value.is_null() {
+            return Ok(state);
+        }
+        let value = value.unwrap_optional();
+        let state = match state {
+            None => {
+                return match self {
+                    Aggregate::Count => Ok(Some(Value::from(1u64))),
+                    Aggregate::Sum => add_values(&Value::from(0u8), value).map(Some),
+                    _ => Ok(Some(value.clone())),
+                };
+            }
+            Some(state) => state,
+        };
+        let next = match self {
+            Aggregate::Count => add_values(&state, &Value::from(1u64))?,
+            Aggregate::Sum => add_values(&state, value)?,
+            Aggregate::Min => match value.compare(&state) {
+                Some(Ordering::Less) => value.clone(),
+                _ => state,
+            },
+            Aggregate::Max => match value.compare(&state) {
+                Some(Ordering::Greater) => value.clone(),
+                _ => state,
+            },
+        };
+        Ok(Some(next))
+    }
+
+    /// Turns the state of a cell into the value of the cell
+    fn finish(&self, state: Option<Value>) -> Value {
+        match (self, state) {
+            (Aggregate::Count, state) => state.unwrap_or_else(|| Value::from(0u64)),
+            (_, state) => Value::Optional(state.map(Box::new)),
+        }
+    }
+}
+
+/// Rotates the values of a column into columns of their own. One tuple is created for each
+/// distinct value of the key, with one cell for every value of the pivot column that was asked
+/// for, containing the aggregate of the value column over the tuples with that key and pivot.
+#[derive(Debug, Clone)]
+pub struct Pivot {
+    key: Identifier,
+    pivot: Identifier,
+    value: Identifier,
+    columns: Vec<Value>,
+    aggregate: Aggregate,
+}
+
+impl Pivot {
+    pub fn new<I1, I2, I3>(
+        key: I1,
+        pivot: I2,
+        value: I3,
+        columns: Vec<Value>,
+        aggregate: Aggregate,
+    ) -> Self
+    where
+        I1: Into<Identifier>,
+        I2: Into<Identifier>,
+        I3: Into<Identifier>,
+    {
+        Pivot {
+            key: key.into(),
+            pivot: pivot.into(),
+            value: value.into(),
+            columns,
+            aggregate,
+        }
+    }
+
+    /// The number of columns created by the pivot
+    pub fn columns(&self) -> usize {
+        self.columns.len()
+    }
+
+    /// The field whose values become the rows of the pivot
+    pub fn key(&self) -> &Identifier {
+        &self.key
+    }
+
+    /// The fields of the input that are read by the pivot
+    pub fn input_fields(&self) -> Vec<Identifier> {
+        vec![self.key.clone(), self.pivot.clone(), self.value.clone()]
+    }
+
+    /// Gets the relation created by pivoting a relation
+    ///
+    /// # Panic
+    /// Panics if the relation is missing the key or value field.
+    pub fn resulting_relation(&self, relation: &[(Identifier, Type)]) -> Vec<(Identifier, Type)> {
+        let find = |id: &Identifier| {
+            relation
+                .iter()
+                .find(|(field, _)| field == id)
+                .map(|(_, ty)| ty.clone())
+                .unwrap_or_else(|| panic!("No field named {} to pivot on", id))
+        };
+        let cell_type = self.aggregate.result_type(&find(&self.value));
+        let mut output = vec![(self.key.clone(), find(&self.key))];
+        for column in &self.columns {
+            output.push((Identifier::new(column.to_string()), cell_type.clone()));
+        }
+        output
+    }
+
+    /// The index of the column a value of the pivot field goes in, if it was asked for
+    fn column_of(&self, pivot: &Value) -> Option<usize> {
+        self.columns
+            .iter()
+            .position(|column| column.numeric_eq(pivot))
+    }
+
+    fn finish_group(&self, (key, cells): (Value, Vec<Option<Value>>)) -> Tuple {
+        let mut values = vec![key];
+        values.extend(cells.into_iter().map(|cell| self.aggregate.finish(cell)));
+        Tuple::new(values)
+    }
+
+    /// Pivots tuples, where the mappings give the index of each field within the tuples. Fails
+    /// if the sum of a cell overflows.
+    pub(crate) fn execute<I: IntoIterator<Item = Tuple>>(
+        &self,
+        mappings: &HashMap<Identifier, usize>,
+        tuples: I,
+    ) -> Result<Vec<Tuple>, ArithmeticError> {
+        let key_index = mappings[&self.key];
+        let pivot_index = mappings[&self.pivot];
+        let value_index = mappings[&self.value];
+
+        let mut groups: Vec<(Value, Vec<Option<Value>>)> = vec![];
+        for tuple in tuples {
+            let column = match self.column_of(&tuple[pivot_index]) {
+                Some(column) => column,
+                None => continue,
+            };
+            let key = &tuple[key_index];
+            let group = match groups.iter().position(|(other, _)| other.numeric_eq(key)) {
+                Some(group) => group,
+                None => {
+                    groups.push((key.clone(), vec![None; self.columns.len()]));
+                    groups.len() - 1
+                }
+            };
+            let cells = &mut groups[group].1;
+            let state = cells[column].take();
+            cells[column] = self.aggregate.accumulate(state, &tuple[value_index])?;
+        }
+
+        Ok(groups
+            .into_iter()
+            .map(|group| self.finish_group(group))
+            .collect())
+    }
+
+    /// Pivots tuples that are ordered on the key, finishing each group as soon as a tuple with
+    /// the next key is found instead of keeping every group until all of the tuples are read
+    pub(crate) fn execute_sorted<I: IntoIterator<Item = Tuple>>(
+        &self,
+        mappings: &HashMap<Identifier, usize>,
+        tuples: I,
+    ) -> Result<Vec<Tuple>, ArithmeticError> {
+        let key_index = mappings[&self.key];
+        let pivot_index = mappings[&self.pivot];
+        let value_index = mappings[&self.value];
+
+        let mut output = vec![];
+        let mut current: Option<(Value, Vec<Option<Value>>)> = None;
+        for tuple in tuples {
+            let column = match self.column_of(&tuple[pivot_index]) {
+                Some(column) => column,
+                None => continue,
+            };
+            let key = &tuple[key_index];
+            let same_group = matches!(&current, Some((current, _)) if current.numeric_eq(key));
+            if !same_group {
+                let next = (key.clone(), vec![None; self.columns.len()]);
+                if let Some(finished) = current.replace(next) {
+                    output.push(self.finish_group(finished));
+                }
+            }
+            if let Some((_, cells)) = &mut current {
+                let state = cells[column].take();
+                cells[column] = self.aggregate.accumulate(state, &tuple[value_index])?;
+            }
+        }
+        output.extend(current.map(|group| self.finish_group(group)));
+        Ok(output)
+    }
+}
+
+/// Rotates columns into rows. Every tuple creates one tuple for each of the columns that isn't
+/// null, made up of the fields that aren't rotated, the name of the column, and its value.
+#[derive(Debug, Clone)]
+pub struct Unpivot {
+    columns: Vec<Identifier>,
+    name: Identifier,
+    value: Identifier,
+}
+
+impl Unpivot {
+    pub fn new<I1, I2, C, I>(columns: C, name: I1, value: I2) -> Self
+    where
+        I1: Into<Identifier>,
+        I2: Into<Identifier>,
+        I: Into<Identifier>,
+        C: IntoIterator<Item = I>,
+    {
+        Unpivot {
+            columns: columns.into_iter().map(|column| column.into()).collect(),
+            name: name.into(),
+            value: value.into(),
+        }
+    }
+
+    /// The number of columns that are rotated
+    pub fn columns(&self) -> usize {
+        self.columns.len()
+    }
+
+    /// The columns of the input that are rotated into rows
+    pub fn rotated_columns(&self) -> &[Identifier] {
+        &self.columns
+    }
+
+    /// Gets the relation created by unpivoting a relation. The value field uses the type of the
+    /// first rotated column.
+    ///
+    /// # Panic
+    /// Panics if the relation is missing any of the rotated columns.
+    pub fn resulting_relation(&self, relation: &[(Identifier, Type)]) -> Vec<(Identifier, Type)> {
+        let value_type = self
+            .columns
+            .iter()
+            .map(|column| {
+                relation
+                    .iter()
+                    .find(|(field, _)| field == column)
+                    .map(|(_, ty)| ty.unwrap_optional().clone())
+                    .unwrap_or_else(|| panic!("No field named {} to unpivot", column))
+            })
+            .next()
+            .expect("Must unpivot at least one column");
+        let mut output: Vec<_> = relation
+            .iter()
+            .filter(|(field, _)| !self.columns.contains(field))
+            .cloned()
+            .collect();
+        output.push((self.name.clone(), Type::from("")));
+        output.push((self.value.clone(), value_type));
+        output
+    }
+
+    /// Unpivots tuples, where the mappings give the index of each field within the tuples
+    pub(crate) fn execute<I: IntoIterator<Item = Tuple>>(
+        &self,
+        mappings: &HashMap<Identifier, usize>,
+        tuples: I,
+    ) -> Vec<Tuple> {
+        let rotated: Vec<usize> = self.columns.iter().map(|column| mappings[column]).collect();
+        let mut output = vec![];
+        for tuple in tuples {
+            let kept: Vec<Value> = tuple
+                .iter()
+                .enumerate()
+                .filter(|(index, _)| !rotated.contains(index))
+                .map(|(_, value)| value.clone())
+                .collect();
+            for (column, &index) in self.columns.iter().zip(&rotated) {
+                if tuple[index].is_null() {
+                    continue;
+                }
+                let mut values = kept.clone();
+                values.push(Value::from(column.to_string()));
+                values.push(tuple[index].unwrap_optional().clone());
+                output.push(Tuple::new(values));
+            }
+        }
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::QueryError;
+    use crate::query::query_node::QueryNode;
+    use crate::query::sort::SortKey;
+    use rad_db_structure::key::primary::PrimaryKeyDefinition;
+    use rad_db_structure::relations::Relation;
+    use rad_db_types::SameType;
+    use std::iter::FromIterator;
+
+    fn sales() -> Relation {
+        let mut relation = Relation::new_volatile(
+            Identifier::new("sales"),
+            vec![
+                ("id", Type::from(0u64)),
+                ("region", Type::from("")),
+                ("quarter", Type::from(0u8)),
+                ("amount", Type::from(0i32)),
+            ],
+            64,
+            PrimaryKeyDefinition::new(vec![0]),
+        );
+        let rows = [
+            ("north", 1u8, 10i32),
+            ("north", 1, 5),
+            ("north", 2, 7),
+            ("south", 2, -3),
+            ("south", 3, 4),
+        ];
+        for (id, (region, quarter, amount)) in rows.iter().enumerate() {
+            relation
+                .insert(Tuple::from_iter(&[
+                    Value::from(id as u64),
+                    Value::from(*region),
+                    Value::from(*quarter),
+                    Value::from(*amount),
+                ]))
+                .unwrap();
+        }
+        relation
+    }
+
+    fn cell(value: Option<i64>) -> Value {
+        Value::Optional(value.map(|value| Box::new(Value::from(value))))
+    }
+
+    #[test]
+    fn pivot_and_unpivot() {
+        let relation = sales();
+        let pivot = Pivot::new(
+            "region",
+            "quarter",
+            "amount",
+            vec![Value::from(1u8), Value::from(2u8)],
+            Aggregate::Sum,
+        );
+        let pivoted = QueryNode::pivot(QueryNode::source(&relation), pivot);
+        let tuples: Vec<Tuple> = pivoted.clone().execute_query().into_iter().collect();
+        assert_eq!(tuples.len(), 2);
+        let north = Tuple::new(vec![Value::from("north"), cell(Some(15)), cell(Some(7))]);
+        let south = Tuple::new(vec![Value::from("south"), cell(None), cell(Some(-3))]);
+        assert!(tuples.contains(&north));
+        assert!(tuples.contains(&south));
+
+        let unpivoted =
+            QueryNode::unpivot(pivoted, Unpivot::new(vec!["1", "2"], "quarter", "total"));
+        let result = unpivoted.execute_query();
+        let fields: Vec<Identifier> = result.relation().iter().map(|(id, _)| id.clone()).collect();
+        assert_eq!(
+            fields,
+            vec![
+                Identifier::new("region"),
+                Identifier::new("quarter"),
+                Identifier::new("total")
+            ]
+        );
+        let tuples: Vec<Tuple> = result.into_iter().collect();
+        assert_eq!(tuples.len(), 3);
+        assert!(tuples.contains(&Tuple::new(vec![
+            Value::from("south"),
+            Value::from("2"),
+            Value::from(-3i64)
+        ])));
+    }
+
+    #[test]
+    fn pivot_sorted_input() {
+        let relation = sales();
+        let pivot = Pivot::new(
+            "region",
+            "quarter",
+            "amount",
+            vec![Value::from(1u8), Value::from(2u8), Value::from(3u8)],
+            Aggregate::Max,
+        );
+        let sorted = QueryNode::sort(
+            QueryNode::source(&relation),
+            vec![SortKey::descending("region")],
+        );
+        assert_eq!(sorted.ordered_on(), vec![Identifier::new("region")]);
+        let streamed: Vec<Tuple> = QueryNode::pivot(sorted, pivot.clone())
+            .execute_query()
+            .into_iter()
+            .collect();
+        let hashed: Vec<Tuple> = QueryNode::pivot(QueryNode::source(&relation), pivot)
+            .execute_query()
+            .into_iter()
+            .collect();
+        assert_eq!(streamed.len(), 2);
+        assert_eq!(streamed[0][0], Value::from("south"));
+        assert!(streamed.iter().all(|tuple| hashed.contains(tuple)));
+    }
+
+    #[test]
+    fn aggregates() {
+        let values = [Value::from(3u32), Value::Optional(None), Value::from(1u32)];
+        let fold = |aggregate: Aggregate| {
+            let state = values
+                .iter()
+                .try_fold(None, |state, value| aggregate.accumulate(state, value))
+                .unwrap();
+            aggregate.finish(state)
+        };
+        assert_eq!(fold(Aggregate::Count), Value::from(2u64));
+        assert_eq!(
+            fold(Aggregate::Min),
+            Value::Optional(Some(Box::new(Value::from(1u32))))
+        );
+        assert_eq!(
+            fold(Aggregate::Max),
+            Value::Optional(Some(Box::new(Value::from(3u32))))
+        );
+        assert_eq!(
+            fold(Aggregate::Sum),
+            Value::Optional(Some(Box::new(Value::from(4u64))))
+        );
+        assert_eq!(Aggregate::Sum.finish(None), Value::Optional(None));
+
+        let price = |price| Value::from(Decimal::parse(price, 5, 2).unwrap());
+        let state = Aggregate::Sum.accumulate(None, &price("1.25")).unwrap();
+        let state = Aggregate::Sum.accumulate(state, &price("2.5")).unwrap();
+        let sum = Aggregate::Sum.finish(state);
+        assert_eq!(sum, Value::Optional(Some(Box::new(price("3.75")))));
+        assert!(sum.same_type(&Aggregate::Sum.result_type(&price("0"))));
+    }
+
+    #[test]
+    fn overflowing_sums_fail_the_query() {
+        let mut relation = Relation::new_volatile(
+            Identifier::new("totals"),
+            vec![
+                ("id", Type::from(0u64)),
+                ("group", Type::from(0u8)),
+                ("amount", Type::from(0i64)),
+            ],
+            64,
+            PrimaryKeyDefinition::new(vec![0]),
+        );
+        for id in 0..2u64 {
+            relation
+                .insert(Tuple::from_iter(&[
+                    Value::from(id),
+                    Value::from(1u8),
+                    Value::from(i64::MAX),
+                ]))
+                .unwrap();
+        }
+        let pivot = Pivot::new(
+            "group",
+            "group",
+            "amount",
+            vec![Value::from(1u8)],
+            Aggregate::Sum,
+        );
+        let result = QueryNode::pivot(QueryNode::source(&relation), pivot).execute_query();
+        assert!(matches!(
+            result.error(),
+            Some(QueryError::Arithmetic(ArithmeticError::Overflow))
+        ));
+    }
+}
@@ -0,0 +1,559 @@
+//! Caches optimized plans, so that a query that is run repeatedly is only optimized once.
+//!
+//! Plans are keyed by the structure of the query, and remember the schema version of every
+//! relation they read. A plan is only reused while none of those relations have changed their
+//! schema since it was made. Plans don't borrow the relations they were made for; the sources of
+//! a cached plan are taken from the query it's reused for.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::ops::Bound;
+
+use rad_db_structure::identifier::Identifier;
+use rad_db_structure::relations::Relation;
+use rad_db_types::Value;
+
+use crate::query::query_node::{QueryChildren, QueryNode, QueryOperation};
+
+/// An optimized query with its sources taken out
+enum Plan {
+    /// The source at this index of the query the plan is used for, in the order they're read
+    Source(usize),
+    /// The source at this index, read by looking up the tuple with the key instead
+    Lookup(usize, Vec<Value>),
+    /// The source at this index, read by the range of keys within the bounds instead
+    Range(usize, Bound<Vec<Value>>, Bound<Vec<Value>>),
+    /// The source at this index, read by the named index within the bounds instead
+    IndexRange(usize, String, Bound<Vec<Value>>, Bound<Vec<Value>>),
+    /// The fields of the source at this index the named index covers, read from the index alone
+    /// within the bounds instead
+    IndexOnly(usize, String, Bound<Vec<Value>>, Bound<Vec<Value>>),
+    Node(QueryNode<'static>, Vec<Plan>),
+}
+
+struct CachedPlan {
+    plan: Plan,
+    /// The name and schema version of every relation the query reads
+    versions: Vec<(Identifier, u64)>,
+    last_used: u64,
+}
+
+/// Optimized plans of previously optimized queries. When the cache is full, the plan that was
+/// used least recently is dropped.
+///
+/// Plans are only invalidated by schema changes, so a plan chosen for the data the relations
+/// had when it was made is kept as the data changes.
+pub struct PlanCache {
+    capacity: usize,
+    plans: HashMap<String, CachedPlan>,
+    clock: u64,
+    hits: usize,
+    misses: usize,
+}
+
+impl PlanCache {
+    /// Creates a cache that holds at most `capacity` plans
+    pub fn new(capacity: usize) -> Self {
+        PlanCache {
+            capacity,
+            plans: HashMap::new(),
+            clock: 0,
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn len(&self) -> usize {
+        self.plans.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.plans.is_empty()
+    }
+
+    /// How many queries reused a cached plan
+    pub fn hits(&self) -> usize {
+        self.hits
+    }
+
+    /// How many queries had to be optimized
+    pub fn misses(&self) -> usize {
+        self.misses
+    }
+
+    /// Optimizes the query, reusing the plan of an earlier query with the same structure if
+    /// none of the relations it reads have changed their schema since
+    pub fn optimize<'a>(&mut self, query: QueryNode<'a>) -> QueryNode<'a> {
+        self.clock += 1;
+        let key = structure_of(&query);
+        let sources = sources_of(&query);
+        let versions = versions_of(&sources);
+
+        if let Some(cached) = self.plans.get_mut(&key) {
+            if cached.versions == versions {
+                cached.last_used = self.clock;
+                self.hits += 1;
+                return instantiate(&cached.plan, &sources);
+            }
+            self.plans.remove(&key);
+        }
+
+        self.misses += 1;
+        let optimized = query.optimized();
+        if self.capacity == 0 {
+            return optimized;
+        }
+        let mut used = vec![false; sources.len()];
+        let plan = match plan_of(optimized.clone(), &sources, &mut used) {
+            Some(plan) => plan,
+            None => return optimized,
+        };
+        if self.plans.len() >= self.capacity {
+            let oldest = self
+                .plans
+                .iter()
+                .min_by_key(|(_, cached)| cached.last_used)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                self.plans.remove(&oldest);
+            }
+        }
+        self.plans.insert(
+            key,
+            CachedPlan {
+                plan,
+                versions,
+                last_used: self.clock,
+            },
+        );
+        optimized
+    }
+
+    /// Drops the plans that read the relation, returning how many were dropped
+    pub fn invalidate<I: Into<Identifier>>(&mut self, relation: I) -> usize {
+        let relation = relation.into();
+        let before = self.plans.len();
+        self.plans
+            .retain(|_, cached| cached.versions.iter().all(|(name, _)| *name != relation));
+        before - self.plans.len()
+    }
+
+    pub fn clear(&mut self) {
+        self.plans.clear();
+    }
+}
+
+/// The relations a source reads
+fn relations_of<'a>(source: &QueryNode<'a>) -> Vec<&'a Relation> {
+    match source.query_operation() {
+        QueryOperation::Source(source) => vec![source.relation()],
+        QueryOperation::HistoricalSource(relation, _) => vec![*relation],
+        QueryOperation::KeyLookup(relation, _)
+        | QueryOperation::KeyRange(relation, ..)
+        | QueryOperation::IndexScan(relation, ..)
+        | QueryOperation::IndexOnlyScan(relation, ..) => vec![*relation],
+        QueryOperation::PartitionedSource(partitioned) => partitioned.partitions().to_vec(),
+        _ => vec![],
+    }
+}
+
+/// The nodes of the query that read relations, in the order they're read
+fn sources_of<'a>(query: &QueryNode<'a>) -> Vec<QueryNode<'a>> {
+    if query.detached().is_none() {
+        return vec![query.clone()];
+    }
+    query
+        .children()
+        .into_iter()
+        .flat_map(|child| sources_of(child))
+        .collect()
+}
+
+fn versions_of(sources: &[QueryNode]) -> Vec<(Identifier, u64)> {
+    sources
+        .iter()
+        .flat_map(|source| relations_of(source))
+        .map(|relation| (relation.name().clone(), relation.schema_version()))
+        .collect()
+}
+
+/// Whether two sources read the same relations in the same way
+fn same_source(left: &QueryNode, right: &QueryNode) -> bool {
+    let same_relations = {
+        let (left, right) = (relations_of(left), relations_of(right));
+        left.len() == right.len()
+            && left
+                .iter()
+                .zip(&right)
+                .all(|(left, right)| std::ptr::eq(*left, *right))
+    };
+    let same_operation = match (left.query_operation(), right.query_operation()) {
+        (QueryOperation::Source(_), QueryOperation::Source(_)) => true,
+        (QueryOperation::ForeignSource(left), QueryOperation::ForeignSource(right)) => {
+            left.name() == right.name()
+        }
+        // a key lookup, key range or index scan reads the source it replaced
+        (QueryOperation::KeyLookup(..), QueryOperation::Source(_))
+        | (QueryOperation::KeyRange(..), QueryOperation::Source(_))
+        | (QueryOperation::IndexScan(..), QueryOperation::Source(_))
+        | (QueryOperation::IndexOnlyScan(..), QueryOperation::Source(_)) => true,
+        (
+            QueryOperation::KeyRange(_, left_lower, left_upper),
+            QueryOperation::KeyRange(_, right_lower, right_upper),
+        ) => left_lower == right_lower && left_upper == right_upper,
+        (
+            QueryOperation::IndexScan(_, left_index, left_lower, left_upper),
+            QueryOperation::IndexScan(_, right_index, right_lower, right_upper),
+        ) => left_index == right_index && left_lower == right_lower && left_upper == right_upper,
+        (
+            QueryOperation::IndexOnlyScan(_, left_index, left_lower, left_upper),
+            QueryOperation::IndexOnlyScan(_, right_index, right_lower, right_upper),
+        ) => left_index == right_index && left_lower == right_lower && left_upper == right_upper,
+        (
+            QueryOperation::HistoricalSource(_, left_time),
+            QueryOperation::HistoricalSource(_, right_time),
+        ) => left_time == right_time,
+        (QueryOperation::PartitionedSource(left), QueryOperation::PartitionedSource(right)) => {
+            left.field() == right.field() && left.scheme() == right.scheme()
+        }
+        _ => false,
+    };
+    let same_fields = match (left.query_operation(), right.query_operation()) {
+        // an index-only scan only produces the fields its index covers
+        (
+            QueryOperation::IndexOnlyScan(relation, index, lower, upper),
+            QueryOperation::Source(_),
+        ) if same_relations => {
+            let mut narrowed = right.clone();
+            narrowed.read_index_only(relation, index.clone(), lower.clone(), upper.clone());
+            left.resulting_relation() == narrowed.resulting_relation()
+        }
+        _ => left.resulting_relation() == right.resulting_relation(),
+    };
+    same_relations && same_operation && same_fields
+}
+
+/// Takes the sources out of an optimized query, replacing each with its index in the sources
+/// of the query before it was optimized
+fn plan_of<'a>(
+    mut optimized: QueryNode<'a>,
+    sources: &[QueryNode<'a>],
+    used: &mut [bool],
+) -> Option<Plan> {
+    match optimized.detached() {
+        Some(node) => {
+            let children = match optimized.take_children() {
+                QueryChildren::None => vec![],
+                QueryChildren::One(child) => vec![plan_of(child, sources, used)?],
+                QueryChildren::Two(left, right) => vec![
+                    plan_of(left, sources, used)?,
+                    plan_of(right, sources, used)?,
+                ],
+            };
+            Some(Plan::Node(node, children))
+        }
+        None => {
+            let matching = |index: &usize| same_source(&optimized, &sources[*index]);
+            let index = (0..sources.len())
+                .filter(|index| !used[*index])
+                .find(matching)
+                .or_else(|| (0..sources.len()).find(matching))?;
+            used[index] = true;
+            match optimized.query_operation() {
+                QueryOperation::KeyLookup(_, key) => Some(Plan::Lookup(index, key.clone())),
+                QueryOperation::KeyRange(_, lower, upper) => {
+                    Some(Plan::Range(index, lower.clone(), upper.clone()))
+                }
+                QueryOperation::IndexScan(_, name, lower, upper) => Some(Plan::IndexRange(
+                    index,
+                    name.clone(),
+                    lower.clone(),
+                    upper.clone(),
+                )),
+                QueryOperation::IndexOnlyScan(_, name, lower, upper) => Some(Plan::IndexOnly(
+                    index,
+                    name.clone(),
+                    lower.clone(),
+                    upper.clone(),
+                )),
+                _ => Some(Plan::Source(index)),
+            }
+        }
+    }
+}
+
+fn instantiate<'a>(plan: &Plan, sources: &[QueryNode<'a>]) -> QueryNode<'a> {
+    match plan {
+        Plan::Source(index) => sources[*index].clone(),
+        Plan::Lookup(index, key) => {
+            let mut source = sources[*index].clone();
+            let relation = relations_of(&source)[0];
+            *source.query_mut() = QueryOperation::KeyLookup(relation, key.clone());
+            source
+        }
+        Plan::Range(index, lower, upper) => {
+            let mut source = sources[*index].clone();
+            let relation = relations_of(&source)[0];
+            *source.query_mut() = QueryOperation::KeyRange(relation, lower.clone(), upper.clone());
+            source
+        }
+        Plan::IndexRange(index, name, lower, upper) => {
+            let mut source = sources[*index].clone();
+            let relation = relations_of(&source)[0];
+            *source.query_mut() =
+                QueryOperation::IndexScan(relation, name.clone(), lower.clone(), upper.clone());
+            source
+        }
+        Plan::IndexOnly(index, name, lower, upper) => {
+            let mut source = sources[*index].clone();
+            let relation = relations_of(&source)[0];
+            source.read_index_only(relation, name.clone(), lower.clone(), upper.clone());
+            source
+        }
+        Plan::Node(node, children) => {
+            let mut node: QueryNode<'a> = node.clone();
+            *node.children_mut() = match &children[..] {
+                [] => QueryChildren::None,
+                [child] => QueryChildren::One(instantiate(child, sources)),
+                [left, right] => {
+                    QueryChildren::Two(instantiate(left, sources), instantiate(right, sources))
+                }
+                _ => unreachable!("A node has at most two children"),
+            };
+            node
+        }
+    }
+}
+
+/// A description of the query that two queries only share if the optimizer would optimize them
+/// the same way, given that the relations they read have the same schema
+pub(super) fn structure_of(query: &QueryNode) -> String {
+    let mut structure = String::new();
+    write_structure(query, &mut structure).expect("Writing to a string can't fail");
+    structure
+}
+
+fn write_structure(query: &QueryNode, output: &mut String) -> std::fmt::Result {
+    match query.query_operation() {
+        QueryOperation::Source(source) => write!(output, "Source({})", source.relation().name())?,
+        QueryOperation::PartitionedSource(partitioned) => {
+            write!(
+                output,
+                "PartitionedSource({:?}, {:?}",
+                partitioned.field(),
+                partitioned.scheme()
+            )?;
+            for partition in partitioned.partitions() {
+                write!(output, ", {}", partition.name())?;
+            }
+            write!(output, ")")?;
+        }
+        QueryOperation::HistoricalSource(relation, time) => {
+            write!(output, "HistoricalSource({}, {:?})", relation.name(), time)?
+        }
+        QueryOperation::ForeignSource(relation) => {
+            write!(output, "ForeignSource({})", relation.name())?
+        }
+        QueryOperation::KeyLookup(relation, key) => {
+            write!(output, "KeyLookup({}, {:?})", relation.name(), key)?
+        }
+        QueryOperation::KeyRange(relation, lower, upper) => write!(
+            output,
+            "KeyRange({}, {:?}, {:?})",
+            relation.name(),
+            lower,
+            upper
+        )?,
+        QueryOperation::IndexScan(relation, index, lower, upper) => write!(
+            output,
+            "IndexScan({}, {}, {:?}, {:?})",
+            relation.name(),
+            index,
+            lower,
+            upper
+        )?,
+        QueryOperation::IndexOnlyScan(relation, index, lower, upper) => write!(
+            output,
+            "IndexOnlyScan({}, {}, {:?}, {:?})",
+            relation.name(),
+            index,
+            lower,
+            upper
+        )?,
+        QueryOperation::Projection(fields) => write!(output, "Projection({:?})", fields)?,
+        QueryOperation::Selection(condition) => write!(output, "Selection({:?})", condition)?,
+        QueryOperation::CrossProduct => write!(output, "CrossProduct")?,
+        QueryOperation::InnerJoin(join) => write!(output, "InnerJoin({:?})", join)?,
+        QueryOperation::HashJoin(join, side) => write!(output, "HashJoin({:?}, {:?})", join, side)?,
+        QueryOperation::PartitionWiseJoin(join) => write!(output, "PartitionWiseJoin({:?})", join)?,
+        QueryOperation::LeftJoin(join) => write!(output, "LeftJoin({:?})", join)?,
+        QueryOperation::RightJoin(join) => write!(output, "RightJoin({:?})", join)?,
+        QueryOperation::FullJoin(join) => write!(output, "FullJoin({:?})", join)?,
+        QueryOperation::NaturalJoin => write!(output, "NaturalJoin")?,
+        QueryOperation::NegatedSubquery(join, kind) => {
+            write!(output, "NegatedSubquery({:?}, {:?})", join, kind)?
+        }
+        QueryOperation::AntiJoin(join, kind) => write!(output, "AntiJoin({:?}, {:?})", join, kind)?,
+        QueryOperation::Recursive => write!(output, "Recursive")?,
+        QueryOperation::WorkingTable => write!(output, "WorkingTable")?,
+        QueryOperation::Empty => write!(output, "Empty")?,
+        QueryOperation::Pivot(pivot) => write!(output, "Pivot({:?})", pivot)?,
+        QueryOperation::Unpivot(unpivot) => write!(output, "Unpivot({:?})", unpivot)?,
+        QueryOperation::Sample(sample) => write!(output, "Sample({:?})", sample)?,
+        QueryOperation::Sort(keys) => write!(output, "Sort({:?})", keys)?,
+        QueryOperation::Limit(limit) => write!(output, "Limit({})", limit)?,
+        QueryOperation::TopN(keys, limit) => write!(output, "TopN({:?}, {})", keys, limit)?,
+        QueryOperation::Count => write!(output, "Count")?,
+        QueryOperation::RelationCount(relation) => {
+            write!(output, "RelationCount({})", relation.name())?
+        }
+    }
+    write!(
+        output,
+        "{:?}{:?}[",
+        query.hints(),
+        query.resulting_relation()
+    )?;
+    for child in query.children() {
+        write_structure(child, output)?;
+        write!(output, ";")?;
+    }
+    write!(output, "]")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::iter::FromIterator;
+
+    use rad_db_structure::identifier::CasePolicy;
+    use rad_db_structure::key::primary::PrimaryKeyDefinition;
+    use rad_db_structure::tuple::Tuple;
+    use rad_db_types::{Type, Value};
+
+    use super::*;
+    use crate::query::conditions::{JoinCondition, Operand};
+
+    fn relation(name: &str, field: &str) -> Relation {
+        let mut relation = Relation::new_volatile(
+            Identifier::new(name),
+            vec![(field, Type::from(0u64))],
+            64,
+            PrimaryKeyDefinition::new(vec![0]),
+        );
+        for i in 0..20u64 {
+            relation
+                .insert(Tuple::from_iter(&[Value::from(i)]))
+                .unwrap();
+        }
+        relation
+    }
+
+    fn query<'a>(left: &'a Relation, right: &'a Relation, value: u64) -> QueryNode<'a> {
+        QueryNode::select_eq(
+            QueryNode::inner_join(
+                QueryNode::source(left),
+                QueryNode::source(right),
+                JoinCondition::new(Identifier::new("a"), Identifier::new("b")),
+            ),
+            Identifier::new("a"),
+            Operand::UnsignedNumber(value),
+        )
+    }
+
+    fn results(query: QueryNode) -> Vec<Tuple> {
+        query.execute_query().tuples().into_iter().collect()
+    }
+
+    #[test]
+    fn plans_are_reused() {
+        let left = relation("left", "a");
+        let right = relation("right", "b");
+        let mut cache = PlanCache::new(8);
+
+        let first = cache.optimize(query(&left, &right, 3));
+        let second = cache.optimize(query(&left, &right, 3));
+        assert_eq!((cache.hits(), cache.misses(), cache.len()), (1, 1, 1));
+        assert_eq!(second.nodes(), first.nodes());
+        assert_eq!(
+            results(second),
+            results(query(&left, &right, 3).optimized())
+        );
+
+        cache.optimize(query(&left, &right, 4));
+        assert_eq!((cache.hits(), cache.misses(), cache.len()), (1, 2, 2));
+        assert_eq!(
+            results(cache.optimize(query(&left, &right, 4))),
+            vec![Tuple::from_iter(&[Value::from(4u64), Value::from(4u64)])]
+        );
+    }
+
+    #[test]
+    fn schema_changes_invalidate_plans() {
+        let mut left = relation("left", "a");
+        let right = relation("right", "b");
+        let mut cache = PlanCache::new(8);
+        cache.optimize(query(&left, &right, 3));
+
+        left.set_case_policy(CasePolicy::Preserve);
+        cache.optimize(query(&left, &right, 3));
+        assert_eq!((cache.hits(), cache.misses(), cache.len()), (0, 2, 1));
+        cache.optimize(query(&left, &right, 3));
+        assert_eq!(cache.hits(), 1);
+
+        assert_eq!(cache.invalidate("right"), 1);
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn recreated_relations_invalidate_plans() {
+        fn keyed_by(key: usize, a: fn(u64) -> u64) -> Relation {
+            let mut relation = Relation::new_volatile(
+                Identifier::new("left"),
+                vec![("a", Type::from(0u64)), ("k", Type::from(0u64))],
+                64,
+                PrimaryKeyDefinition::new(vec![key]),
+            );
+            for i in 0..20u64 {
+                relation
+                    .insert(Tuple::from_iter(&[Value::from(a(i)), Value::from(i)]))
+                    .unwrap();
+            }
+            relation
+        }
+
+        let right = relation("right", "b");
+        let mut cache = PlanCache::new(8);
+        let left = keyed_by(0, |i| i);
+        cache.optimize(query(&left, &right, 3));
+        std::mem::drop(left);
+
+        // the same name and fields, keyed by the other field
+        let left = keyed_by(1, |i| (i + 1) % 5);
+        let planned = cache.optimize(query(&left, &right, 3));
+        assert_eq!((cache.hits(), cache.misses()), (0, 2));
+        assert_eq!(
+            results(planned),
+            results(query(&left, &right, 3).optimized())
+        );
+        assert_eq!(results(query(&left, &right, 3).optimized()).len(), 4);
+    }
+
+    #[test]
+    fn least_recently_used_plans_are_dropped() {
+        let left = relation("left", "a");
+        let right = relation("right", "b");
+        let mut cache = PlanCache::new(2);
+        for value in &[1, 2, 1, 3] {
+            cache.optimize(query(&left, &right, *value));
+        }
+        assert_eq!(cache.len(), 2);
+        cache.optimize(query(&left, &right, 1));
+        assert_eq!(cache.hits(), 2);
+        cache.optimize(query(&left, &right, 2));
+        assert_eq!(cache.misses(), 4);
+    }
+}
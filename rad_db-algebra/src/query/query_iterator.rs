@@ -13,7 +13,11 @@ pub struct QueryIterator<'a> {
 }
 
 impl<'a> QueryIterator<'a> {
-    pub(crate) fn new(backing: QueryResultFullData<'a>) -> Self {
+    pub(crate) fn new(mut backing: QueryResultFullData<'a>) -> Self {
+        // tuples are popped off the end, so they're reversed to be returned in order
+        if let QueryResultFullData::Tuples(tuples) = &mut backing {
+            tuples.reverse();
+        }
         QueryIterator {
             backing,
             buffer: VecDeque::new(),
@@ -31,22 +35,12 @@ impl Iterator for QueryIterator<'_> {
 
         match &mut self.backing {
             QueryResultFullData::Tuples(tuples) => tuples.pop(),
-            QueryResultFullData::BlockData(blocks) => {
-                match blocks {
-                    QueryResultBlocks::Blocks(blocks) => {
-                        if let Some(tuples) = blocks.pop() {
-                            self.buffer.extend(tuples);
-                        }
-                    }
-                    QueryResultBlocks::Source(source) => {
-                        if let Some(tuples) = source.next() {
-                            self.buffer.extend(tuples);
-                        }
-                    }
+            QueryResultFullData::BlockData(blocks) => loop {
+                if let Some(tuple) = self.buffer.pop_front() {
+                    return Some(tuple);
                 }
-
-                self.buffer.pop_front()
-            }
+                self.buffer.extend(blocks.next()?);
+            },
         }
     }
 }
@@ -54,7 +48,8 @@ impl Iterator for QueryIterator<'_> {
 pub struct ReferencedQueryIterator<'a> {
     backing: &'a QueryResultFullData<'a>,
     buffer: VecDeque<Tuple>,
-    blocks_count: usize,
+    /// The index of the next tuple or block of tuples to read
+    position: usize,
     block_iterator: Option<BlockIterator<'a>>,
 }
 
@@ -63,7 +58,7 @@ impl<'a> ReferencedQueryIterator<'a> {
         ReferencedQueryIterator {
             backing,
             buffer: VecDeque::new(),
-            blocks_count: 0,
+            position: 0,
             block_iterator: None,
         }
     }
@@ -73,44 +68,30 @@ impl Iterator for ReferencedQueryIterator<'_> {
     type Item = Tuple;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if !self.buffer.is_empty() {
-            return self.buffer.pop_front();
-        }
-
-        if let Some(block_iterator) = &mut self.block_iterator {
-            let tuples: Option<Vec<Tuple>> = block_iterator.next();
-            if let Some(tuples) = tuples {
-                self.buffer.extend(tuples);
+        loop {
+            if let Some(tuple) = self.buffer.pop_front() {
+                return Some(tuple);
             }
-            return self.buffer.pop_front();
-        }
 
-        match &mut self.backing {
-            QueryResultFullData::Tuples(tuples) => {
-                self.buffer.extend(tuples.into_iter().cloned());
-                self.buffer.pop_front()
-            }
-            QueryResultFullData::BlockData(blocks) => {
-                match blocks {
-                    QueryResultBlocks::Blocks(blocks) => {
-                        if let Some(tuples) = blocks.get(self.blocks_count) {
-                            self.buffer.extend(tuples.into_iter().cloned());
-                        }
-                        self.blocks_count += 1;
-                    }
-                    QueryResultBlocks::Source(source) => {
-                        let mut block_iterator: BlockIterator = source.get_iterator();
-                        let tuples: Option<Vec<Tuple>> = block_iterator.next();
-                        if let Some(tuples) = tuples {
-                            self.buffer.extend(tuples);
-                        }
-                        return self.buffer.pop_front();
-                        self.block_iterator = Some(block_iterator);
-                    }
+            let block = match self.backing {
+                QueryResultFullData::Tuples(tuples) => {
+                    let tuple = tuples.get(self.position).cloned();
+                    self.position += 1;
+                    return tuple;
+                }
+                QueryResultFullData::BlockData(QueryResultBlocks::Blocks(blocks)) => {
+                    let block = blocks.get(self.position).cloned();
+                    self.position += 1;
+                    block
                 }
+                QueryResultFullData::BlockData(QueryResultBlocks::Source(source)) => self
+                    .block_iterator
+                    .get_or_insert_with(|| source.get_iterator())
+                    .next(),
+            };
 
-                self.buffer.pop_front()
-            }
+            // blocks can be empty, so keep going until a block with tuples is found
+            self.buffer.extend(block?);
         }
     }
 }
@@ -1,17 +1,44 @@
+use crate::error::{MissingFieldError, QueryError};
+use crate::query::batch::{project_batched, select_batched};
 use crate::query::conditions::{Condition, ConditionOperation, JoinCondition, Operand};
+use crate::query::cost::CostModel;
+use crate::query::feedback::{feedback_key, CardinalityFeedback};
+use crate::query::hash_join::{grace_hash_join, HashKey, JoinSide};
+use crate::query::hints::Hint;
 use crate::query::optimization::Optimizer;
+use crate::query::partition::{partition_wise_join, PartitionedRelation};
+use crate::query::pivot::{Pivot, Unpivot};
+use crate::query::plan_cache::structure_of;
 use crate::query::query_iterator::QueryIterator;
 use crate::query::query_result::QueryResult;
+use crate::query::sample::Sample;
+use crate::query::sort::{SortKey, TupleComparator};
 use crate::query::Repeatable;
 use crate::relation_mapping::MappedRelation;
-use rad_db_structure::identifier::Identifier;
+use crate::wrapped_tuple::WrappedTuple;
+use rad_db_structure::identifier::{CasePolicy, Identifier};
+use rad_db_structure::memory::MemoryBudget;
+use rad_db_structure::relations::foreign::ForeignRelation;
 use rad_db_structure::relations::tuple_storage::{BlockIterator, StoredTupleIterator};
 use rad_db_structure::relations::Relation;
+use rad_db_structure::settings::{Settings, EXECUTION_BATCH_SIZE, OPTIMIZER_SAMPLES};
 use rad_db_structure::tuple::Tuple;
+use rad_db_types::comparison::TextComparison;
 use rad_db_types::{Type, Value};
-use std::cmp::max;
+use std::cell::RefCell;
+use std::cmp::{max, min};
+use std::collections::hash_map::DefaultHasher;
 use std::collections::{HashMap, HashSet};
-use std::ops::Deref;
+use std::fmt::{Display, Formatter};
+use std::hash::{Hash, Hasher};
+use std::ops::{Bound, Deref};
+use std::time::SystemTime;
+
+/// How many tuples a foreign relation is assumed to have when it can't tell
+const ASSUMED_FOREIGN_TUPLES: usize = 1000;
+
+/// The name of the field holding the number of tuples counted by a count node
+pub const COUNT_FIELD: &str = "count";
 
 #[derive(Clone)]
 pub struct Crawler<'a> {
@@ -81,13 +108,541 @@ impl Iterator for Source<'_> {
 #[derive(Clone)]
 pub enum QueryOperation<'a> {
     Source(Source<'a>),
+    /// All of the tuples of every partition of a partitioned relation
+    PartitionedSource(PartitionedRelation<'a>),
+    /// The tuples of a relation as they were at a point in the past
+    HistoricalSource(&'a Relation, SystemTime),
+    /// All of the tuples of a relation kept outside of the database
+    ForeignSource(&'a dyn ForeignRelation),
+    /// The tuple of a relation with a primary key, found by reading only the block the key hashes
+    /// into instead of scanning every block. The values of the key are in the order the fields of
+    /// the key appear in the relation.
+    KeyLookup(&'a Relation, Vec<Value>),
+    /// The tuples of a relation with primary keys between two bounds, in key order, read through
+    /// the ordered index of its keys instead of scanning every block. Each bound is a prefix of
+    /// the key, as in [Relation::key_range].
+    KeyRange(&'a Relation, Bound<Vec<Value>>, Bound<Vec<Value>>),
+    /// The tuples held by an index of a relation, with indexed values between two bounds, in
+    /// index order. Each bound is a prefix of the indexed values, as in
+    /// [Relation::index_range].
+    IndexScan(&'a Relation, String, Bound<Vec<Value>>, Bound<Vec<Value>>),
+    /// The values of the fields an index of a relation covers, read from the index alone for the
+    /// tuples it holds with indexed values between two bounds, in index order.
+    IndexOnlyScan(&'a Relation, String, Bound<Vec<Value>>, Bound<Vec<Value>>),
     Projection(Vec<Identifier>),
     Selection(Condition),
     CrossProduct,
     InnerJoin(JoinCondition),
+    /// An inner join that loads one side into a hash table, then looks up the tuples of the
+    /// other side in it
+    HashJoin(JoinCondition, JoinSide),
+    /// An inner join of two partitioned sources that are partitioned the same way on the joined
+    /// fields, which only joins partitions with the same index with each other
+    PartitionWiseJoin(JoinCondition),
+    /// Keeps every tuple of the left child, padding the fields of the right child with `NULL`
+    /// when there's no match
     LeftJoin(JoinCondition),
+    /// Keeps every tuple of the right child, padding the fields of the left child with `NULL`
+    /// when there's no match
     RightJoin(JoinCondition),
+    /// Keeps every tuple of both children, padding the fields of the other child with `NULL`
+    /// when there's no match
+    FullJoin(JoinCondition),
     NaturalJoin,
+    /// A `NOT IN` or `NOT EXISTS` predicate, where the right child is the subquery. The subquery
+    /// is evaluated for every tuple of the left child.
+    NegatedSubquery(JoinCondition, AntiJoinKind),
+    /// Keeps the tuples of the left child that have no match within the right child
+    AntiJoin(JoinCondition, AntiJoinKind),
+    /// Produces the tuples of the left child, then repeatedly executes the right child over the
+    /// tuples found by the previous iteration until no new tuples are found
+    Recursive,
+    /// The tuples found by the previous iteration of the closest recursive node above this node
+    WorkingTable,
+    /// Produces no tuples, in place of a query that can never produce any
+    Empty,
+    /// Rotates the values of a column into columns
+    Pivot(Pivot),
+    /// Rotates columns into rows
+    Unpivot(Unpivot),
+    /// A random subset of the tuples of the child
+    Sample(Sample),
+    /// Orders the tuples of the child
+    Sort(Vec<SortKey>),
+    /// Keeps only the first tuples of the child
+    Limit(usize),
+    /// The first tuples of the child in sorted order, found without sorting the whole child
+    TopN(Vec<SortKey>, usize),
+    /// The number of tuples of the child, as a single tuple with a [COUNT_FIELD] field
+    Count,
+    /// The number of tuples of a relation, read from the lengths of its blocks without reading
+    /// any of its tuples
+    RelationCount(&'a Relation),
+}
+
+impl QueryOperation<'_> {
+    /// Which children of an outer join keep all of their tuples even when they have no match, as
+    /// `(left, right)`. Every other operation preserves neither.
+    pub fn preserved_inputs(&self) -> (bool, bool) {
+        match self {
+            QueryOperation::LeftJoin(_) => (true, false),
+            QueryOperation::RightJoin(_) => (false, true),
+            QueryOperation::FullJoin(_) => (true, true),
+            _ => (false, false),
+        }
+    }
+
+    /// The condition of an operation that joins the fields of its children
+    pub fn join_condition(&self) -> Option<&JoinCondition> {
+        match self {
+            QueryOperation::InnerJoin(join)
+            | QueryOperation::HashJoin(join, _)
+            | QueryOperation::PartitionWiseJoin(join)
+            | QueryOperation::LeftJoin(join)
+            | QueryOperation::RightJoin(join)
+            | QueryOperation::FullJoin(join)
+            | QueryOperation::NegatedSubquery(join, _)
+            | QueryOperation::AntiJoin(join, _) => Some(join),
+            _ => None,
+        }
+    }
+
+    /// The condition of a join that only keeps one copy of the field it joins on
+    pub fn using_join(&self) -> Option<&JoinCondition> {
+        match self {
+            QueryOperation::InnerJoin(join)
+            | QueryOperation::HashJoin(join, _)
+            | QueryOperation::PartitionWiseJoin(join)
+            | QueryOperation::LeftJoin(join)
+            | QueryOperation::RightJoin(join)
+            | QueryOperation::FullJoin(join)
+                if join.is_using() =>
+            {
+                Some(join)
+            }
+            _ => None,
+        }
+    }
+
+    /// A copy of the operation that doesn't borrow any relations, or `None` if the operation
+    /// reads a relation
+    pub(super) fn detached(&self) -> Option<QueryOperation<'static>> {
+        Some(match self {
+            QueryOperation::Source(_)
+            | QueryOperation::PartitionedSource(_)
+            | QueryOperation::HistoricalSource(..)
+            | QueryOperation::ForeignSource(_)
+            | QueryOperation::KeyLookup(..)
+            | QueryOperation::KeyRange(..)
+            | QueryOperation::IndexScan(..)
+            | QueryOperation::IndexOnlyScan(..)
+            | QueryOperation::RelationCount(_) => return None,
+            QueryOperation::Projection(fields) => QueryOperation::Projection(fields.clone()),
+            QueryOperation::Selection(condition) => QueryOperation::Selection(condition.clone()),
+            QueryOperation::CrossProduct => QueryOperation::CrossProduct,
+            QueryOperation::InnerJoin(join) => QueryOperation::InnerJoin(join.clone()),
+            QueryOperation::HashJoin(join, side) => QueryOperation::HashJoin(join.clone(), *side),
+            QueryOperation::PartitionWiseJoin(join) => {
+                QueryOperation::PartitionWiseJoin(join.clone())
+            }
+            QueryOperation::LeftJoin(join) => QueryOperation::LeftJoin(join.clone()),
+            QueryOperation::RightJoin(join) => QueryOperation::RightJoin(join.clone()),
+            QueryOperation::FullJoin(join) => QueryOperation::FullJoin(join.clone()),
+            QueryOperation::NaturalJoin => QueryOperation::NaturalJoin,
+            QueryOperation::NegatedSubquery(join, kind) => {
+                QueryOperation::NegatedSubquery(join.clone(), *kind)
+            }
+            QueryOperation::AntiJoin(join, kind) => QueryOperation::AntiJoin(join.clone(), *kind),
+            QueryOperation::Recursive => QueryOperation::Recursive,
+            QueryOperation::WorkingTable => QueryOperation::WorkingTable,
+            QueryOperation::Empty => QueryOperation::Empty,
+            QueryOperation::Pivot(pivot) => QueryOperation::Pivot(pivot.clone()),
+            QueryOperation::Unpivot(unpivot) => QueryOperation::Unpivot(unpivot.clone()),
+            QueryOperation::Sample(sample) => QueryOperation::Sample(*sample),
+            QueryOperation::Sort(keys) => QueryOperation::Sort(keys.clone()),
+            QueryOperation::Limit(limit) => QueryOperation::Limit(*limit),
+            QueryOperation::TopN(keys, limit) => QueryOperation::TopN(keys.clone(), *limit),
+            QueryOperation::Count => QueryOperation::Count,
+        })
+    }
+}
+
+impl Display for QueryOperation<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            QueryOperation::Source(source) => write!(f, "Scan {}", source.relation().name()),
+            QueryOperation::PartitionedSource(partitioned) => write!(
+                f,
+                "PartitionedScan {} partitions on {}",
+                partitioned.partitions().len(),
+                partitioned.field()
+            ),
+            QueryOperation::HistoricalSource(relation, time) => {
+                write!(f, "Scan {} as of {:?}", relation.name(), time)
+            }
+            QueryOperation::ForeignSource(relation) => {
+                write!(f, "ForeignScan {}", relation.name())
+            }
+            QueryOperation::KeyLookup(relation, key) => {
+                write!(f, "KeyLookup {} {}", relation.name(), describe_key(key))
+            }
+            QueryOperation::KeyRange(relation, lower, upper) => {
+                write!(f, "KeyRange {}", relation.name())?;
+                describe_bounds(f, lower, upper)
+            }
+            QueryOperation::IndexScan(relation, index, lower, upper) => {
+                write!(f, "IndexScan {} on {}", index, relation.name())?;
+                describe_bounds(f, lower, upper)
+            }
+            QueryOperation::IndexOnlyScan(relation, index, lower, upper) => {
+                write!(f, "IndexOnlyScan {} on {}", index, relation.name())?;
+                describe_bounds(f, lower, upper)
+            }
+            QueryOperation::Projection(fields) => {
+                let fields: Vec<String> = fields.iter().map(|field| field.to_string()).collect();
+                write!(f, "Projection {}", fields.join(", "))
+            }
+            QueryOperation::Selection(condition) => write!(f, "Selection {:?}", condition),
+            QueryOperation::CrossProduct => write!(f, "CrossProduct"),
+            QueryOperation::InnerJoin(join) => write!(f, "InnerJoin {:?}", join),
+            QueryOperation::HashJoin(join, side) => {
+                write!(f, "HashJoin {:?} building {:?}", join, side)
+            }
+            QueryOperation::PartitionWiseJoin(join) => write!(f, "PartitionWiseJoin {:?}", join),
+            QueryOperation::LeftJoin(join) => write!(f, "LeftJoin {:?}", join),
+            QueryOperation::RightJoin(join) => write!(f, "RightJoin {:?}", join),
+            QueryOperation::FullJoin(join) => write!(f, "FullJoin {:?}", join),
+            QueryOperation::NaturalJoin => write!(f, "NaturalJoin"),
+            QueryOperation::NegatedSubquery(join, kind) => {
+                write!(f, "NegatedSubquery {:?} {:?}", kind, join)
+            }
+            QueryOperation::AntiJoin(join, kind) => write!(f, "AntiJoin {:?} {:?}", kind, join),
+            QueryOperation::Recursive => write!(f, "Recursive"),
+            QueryOperation::WorkingTable => write!(f, "WorkingTable"),
+            QueryOperation::Empty => write!(f, "Empty"),
+            QueryOperation::Pivot(pivot) => write!(f, "Pivot {:?}", pivot),
+            QueryOperation::Unpivot(unpivot) => write!(f, "Unpivot {:?}", unpivot),
+            QueryOperation::Sample(sample) => write!(f, "Sample {:?}", sample),
+            QueryOperation::Sort(keys) => write!(f, "Sort {:?}", keys),
+            QueryOperation::Limit(limit) => write!(f, "Limit {}", limit),
+            QueryOperation::TopN(keys, limit) => write!(f, "TopN {} {:?}", limit, keys),
+            QueryOperation::Count => write!(f, "Count"),
+            QueryOperation::RelationCount(relation) => {
+                write!(f, "RelationCount {}", relation.name())
+            }
+        }
+    }
+}
+
+pub(super) fn as_slices(bound: &Bound<Vec<Value>>) -> Bound<&[Value]> {
+    match bound {
+        Bound::Included(key) => Bound::Included(key),
+        Bound::Excluded(key) => Bound::Excluded(key),
+        Bound::Unbounded => Bound::Unbounded,
+    }
+}
+
+fn describe_key(key: &[Value]) -> String {
+    let values: Vec<String> = key.iter().map(|value| value.to_string()).collect();
+    format!("({})", values.join(", "))
+}
+
+fn describe_bounds(
+    f: &mut Formatter<'_>,
+    lower: &Bound<Vec<Value>>,
+    upper: &Bound<Vec<Value>>,
+) -> std::fmt::Result {
+    match lower {
+        Bound::Included(key) => write!(f, " >= {}", describe_key(key))?,
+        Bound::Excluded(key) => write!(f, " > {}", describe_key(key))?,
+        Bound::Unbounded => {}
+    }
+    match upper {
+        Bound::Included(key) => write!(f, " <= {}", describe_key(key)),
+        Bound::Excluded(key) => write!(f, " < {}", describe_key(key)),
+        Bound::Unbounded => Ok(()),
+    }
+}
+
+/// The fields of an outer join, where the fields of a child that can be padded become nullable
+fn outer_join_relation(
+    operation: &QueryOperation,
+    left: &[(Identifier, Type)],
+    right: &[(Identifier, Type)],
+) -> Vec<(Identifier, Type)> {
+    let (keep_left, keep_right) = operation.preserved_inputs();
+    let pad = |fields: &[(Identifier, Type)], padded: bool| {
+        fields
+            .iter()
+            .map(|(id, ty)| {
+                if padded {
+                    (id.clone(), ty.clone().nullable())
+                } else {
+                    (id.clone(), ty.clone())
+                }
+            })
+            .collect::<Vec<_>>()
+    };
+    let mut output = pad(left, keep_right);
+    output.extend(pad(right, keep_left));
+    output
+}
+
+/// The fields of a join, where a join on a shared field only keeps the copy of the left child.
+/// A right join keeps the type of the right copy, as that copy is never padded.
+fn join_relation(
+    operation: &QueryOperation,
+    left: &[(Identifier, Type)],
+    right: &[(Identifier, Type)],
+    policy: CasePolicy,
+) -> Vec<(Identifier, Type)> {
+    let mut output = outer_join_relation(operation, left, right);
+    if let Some(join) = operation.using_join() {
+        let (left_index, right_index) = using_indexes(join, left, right, policy);
+        let (_, right_type) = output.remove(right_index);
+        if let (false, true) = operation.preserved_inputs() {
+            output[left_index].1 = right_type;
+        }
+    }
+    output
+}
+
+/// The field with the closest name to the field, if it's close enough to be a likely typo. A
+/// field with the same base name, but under a different parent, is always close enough.
+fn closest_field(field: &Identifier, fields: &[(Identifier, Type)]) -> Option<Identifier> {
+    let name = field.to_string();
+    let allowed = max(2, name.len() / 3);
+    fields
+        .iter()
+        .map(|(id, _)| {
+            let distance = min(
+                edit_distance(&name, &id.to_string()),
+                edit_distance(field.base(), id.base()),
+            );
+            (distance, id)
+        })
+        .filter(|&(distance, _)| distance <= allowed)
+        .min_by_key(|&(distance, _)| distance)
+        .map(|(_, id)| id.clone())
+}
+
+/// The number of single character insertions, deletions and substitutions that turn one string
+/// into another
+fn edit_distance(left: &str, right: &str) -> usize {
+    let right: Vec<char> = right.chars().collect();
+    let mut previous: Vec<usize> = (0..=right.len()).collect();
+    for (i, left_char) in left.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, right_char) in right.iter().enumerate() {
+            let substitution = previous[j] + if left_char == *right_char { 0 } else { 1 };
+            current.push(min(substitution, min(previous[j + 1], current[j]) + 1));
+        }
+        previous = current;
+    }
+    previous[right.len()]
+}
+
+/// The index of the field with the name. A field with exactly the name is preferred over one
+/// whose name only matches under the case policy.
+fn field_position(
+    fields: &[(Identifier, Type)],
+    id: &Identifier,
+    policy: CasePolicy,
+) -> Option<usize> {
+    fields
+        .iter()
+        .position(|(field, _)| field == id)
+        .or_else(|| {
+            fields
+                .iter()
+                .position(|(field, _)| policy.identifiers_match(field, id))
+        })
+}
+
+/// The indexes of the fields of both children that a join joins on
+fn join_positions(
+    join: &JoinCondition,
+    left: &[(Identifier, Type)],
+    right: &[(Identifier, Type)],
+    policy: CasePolicy,
+) -> (usize, usize) {
+    let index_of = |fields: &[(Identifier, Type)], id: &Identifier| {
+        field_position(fields, id, policy)
+            .unwrap_or_else(|| panic!("No field named {} to join on", id))
+    };
+    (
+        index_of(left, join.left_id()),
+        index_of(right, join.right_id()),
+    )
+}
+
+/// The indexes of both copies of the shared field of a join within the joined tuples
+fn using_indexes(
+    join: &JoinCondition,
+    left: &[(Identifier, Type)],
+    right: &[(Identifier, Type)],
+    policy: CasePolicy,
+) -> (usize, usize) {
+    let (left_index, right_index) = join_positions(join, left, right, policy);
+    (left_index, left.len() + right_index)
+}
+
+/// The names of the fields, where a field whose name only matches one of the names under the
+/// case policy is renamed to that name, so that looking up the names finds the fields
+fn resolved_names<'i, I: IntoIterator<Item = &'i Identifier>>(
+    fields: &[(Identifier, Type)],
+    names: I,
+    policy: CasePolicy,
+) -> Vec<Identifier> {
+    let mut resolved: Vec<Identifier> = fields.iter().map(|(id, _)| id.clone()).collect();
+    for name in names {
+        if let Some(index) = field_position(fields, name, policy) {
+            if &fields[index].0 != name {
+                resolved[index] = name.clone();
+            }
+        }
+    }
+    resolved
+}
+
+/// The case policy of a node reading from relations with the policies, which folds case when any
+/// of the relations does
+fn combined_policy<I: IntoIterator<Item = CasePolicy>>(policies: I) -> CasePolicy {
+    policies
+        .into_iter()
+        .find(|policy| *policy == CasePolicy::LowercaseFold)
+        .unwrap_or_default()
+}
+
+/// Removes the right copy of the shared field of a joined tuple, using it in place of the left
+/// copy when the left copy is padding
+fn merge_using_field(tuple: Tuple, (left_index, right_index): (usize, usize)) -> Tuple {
+    let mut values: Vec<Value> = tuple.into_iter().collect();
+    let right = values.remove(right_index);
+    if values[left_index].is_null() {
+        values[left_index] = right;
+    }
+    Tuple::new(values)
+}
+
+/// Joins every pair of tuples with equal fields, and then adds the tuples of the preserved sides
+/// that had no match, padded with `NULL`. `NULL` never matches anything.
+fn outer_join<L, R>(
+    left: L,
+    right: R,
+    (left_index, left_width): (usize, usize),
+    (right_index, right_width): (usize, usize),
+    (keep_left, keep_right): (bool, bool),
+) -> Vec<Tuple>
+where
+    L: IntoIterator<Item = Tuple>,
+    R: IntoIterator<Item = Tuple>,
+{
+    // the same padding is used for every tuple without a match
+    let nulls = |width: usize| Tuple::new(vec![Value::Optional(None); width]);
+    let (left_padding, right_padding) = (nulls(left_width), nulls(right_width));
+    let right: Vec<Tuple> = right.into_iter().collect();
+    let mut right_matched = vec![false; right.len()];
+    let mut output = vec![];
+    for left_tuple in left {
+        let value = &left_tuple[left_index];
+        let mut matched = false;
+        if !value.is_null() {
+            for (right_tuple, right_matched) in right.iter().zip(right_matched.iter_mut()) {
+                if value.numeric_eq(&right_tuple[right_index]) {
+                    output.push(&left_tuple + right_tuple);
+                    matched = true;
+                    *right_matched = true;
+                }
+            }
+        }
+        if keep_left && !matched {
+            output.push(left_tuple + &right_padding);
+        }
+    }
+    if keep_right {
+        for (right_tuple, matched) in right.into_iter().zip(right_matched) {
+            if !matched {
+                output.push(&left_padding + &right_tuple);
+            }
+        }
+    }
+    output
+}
+
+/// The kind of negated subquery an anti join came from, which determines how `NULL` values
+/// are treated
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AntiJoinKind {
+    /// `NOT EXISTS`, where a `NULL` never matches anything, so tuples with a `NULL` are kept
+    NotExists,
+    /// `NOT IN`, where comparing against a `NULL` is unknown. A tuple with a `NULL` is only kept
+    /// if the subquery is empty, and no tuples are kept if the subquery contains a `NULL`.
+    NotIn,
+}
+
+/// The values a negated subquery produced, hashed so that a value is matched against all of
+/// them at once. `NULL` values never match, so only whether there were any is kept.
+struct SubqueryValues {
+    keys: HashSet<HashKey>,
+    is_empty: bool,
+    has_null: bool,
+}
+
+impl SubqueryValues {
+    fn new<I: IntoIterator<Item = Value>>(values: I) -> Self {
+        let mut subquery = SubqueryValues {
+            keys: HashSet::new(),
+            is_empty: true,
+            has_null: false,
+        };
+        for value in values {
+            subquery.is_empty = false;
+            match HashKey::new(&value) {
+                Some(key) => {
+                    subquery.keys.insert(key);
+                }
+                None => subquery.has_null = true,
+            }
+        }
+        subquery
+    }
+}
+
+impl AntiJoinKind {
+    /// Whether a tuple with this value should be kept given the values of the subquery
+    fn keeps(&self, value: &Value, subquery: &SubqueryValues) -> bool {
+        let matched = HashKey::new(value).map_or(false, |key| subquery.keys.contains(&key));
+        match self {
+            AntiJoinKind::NotExists => !matched,
+            AntiJoinKind::NotIn => {
+                subquery.is_empty || (!matched && !value.is_null() && !subquery.has_null)
+            }
+        }
+    }
+}
+
+/// What a query is executed with
+#[derive(Clone, Copy)]
+struct Execution<'w> {
+    /// The tuples produced by any working table nodes
+    working: &'w [Tuple],
+    /// How many tuples selections and projections process at a time, if they process batches
+    batch_size: Option<usize>,
+    /// Where the number of tuples selections of relations produce is recorded
+    feedback: Option<&'w RefCell<CardinalityFeedback>>,
+    /// Where the first error that stops the query is kept
+    failure: &'w RefCell<Option<QueryError>>,
+}
+
+impl Execution<'_> {
+    /// Records why the query failed, unless an earlier failure was already recorded
+    fn fail<E: Into<QueryError>>(&self, error: E) {
+        let mut failure = self.failure.borrow_mut();
+        if failure.is_none() {
+            *failure = Some(error.into());
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -104,6 +659,7 @@ pub struct QueryNode<'a> {
     resulting_relation: Vec<(Identifier, Type)>,
     mapping: HashMap<Identifier, Identifier>,
     id: usize,
+    hints: Vec<Hint>,
 }
 
 impl<'a> PartialEq<&QueryNode<'a>> for &QueryNode<'a> {
@@ -133,6 +689,7 @@ impl<'a> QueryNode<'a> {
                 .collect(),
             mapping,
             id: 0,
+            hints: vec![],
         }
     }
 
@@ -157,13 +714,152 @@ impl<'a> QueryNode<'a> {
                 .collect(),
             mapping,
             id: 0,
+            hints: vec![],
+        }
+    }
+
+    /// Joins a relation with itself. Each side reads the relation under a generated alias,
+    /// `<relation>_1` for the left side and `<relation>_2` for the right side, and the fields of
+    /// the condition are fields of the relation, resolved against the alias of their side.
+    pub fn self_join(relation: &'a Relation, condition: JoinCondition) -> Self {
+        let (left, right) = Self::self_join_aliases(relation);
+        let condition = condition.qualified(&left, &right);
+        Self::inner_join(
+            Self::source_with_name(relation, left),
+            Self::source_with_name(relation, right),
+            condition,
+        )
+    }
+
+    /// The aliases of the left and right sides of a [self join](QueryNode::self_join) of the
+    /// relation
+    pub fn self_join_aliases(relation: &Relation) -> (String, String) {
+        let name = relation.name().base();
+        (format!("{}_1", name), format!("{}_2", name))
+    }
+
+    /// Creates a node that reads the tuples of the relation as they were at the time, or `None`
+    /// if the relation doesn't [retain its history](Relation::retain_history)
+    pub fn source_as_of(relation: &'a Relation, time: SystemTime) -> Option<Self> {
+        relation.history()?;
+        let mut node = Self::source(relation);
+        node.query = QueryOperation::HistoricalSource(relation, time);
+        Some(node)
+    }
+
+    /// Creates a node that reads the tuples of the relation whose primary keys are within the
+    /// bounds, in key order. Each bound is a prefix of the key, as in [Relation::key_range].
+    pub fn key_range(
+        relation: &'a Relation,
+        lower: Bound<Vec<Value>>,
+        upper: Bound<Vec<Value>>,
+    ) -> Self {
+        let mut node = Self::source(relation);
+        node.query = QueryOperation::KeyRange(relation, lower, upper);
+        node
+    }
+
+    /// Creates a node that reads the tuples held by an index of the relation whose indexed values
+    /// are within the bounds, in index order. Each bound is a prefix of the indexed values, as
+    /// in [Relation::index_range].
+    pub fn index_scan<S: ToString>(
+        relation: &'a Relation,
+        index: S,
+        lower: Bound<Vec<Value>>,
+        upper: Bound<Vec<Value>>,
+    ) -> Self {
+        let mut node = Self::source(relation);
+        node.query = QueryOperation::IndexScan(relation, index.to_string(), lower, upper);
+        node
+    }
+
+    /// Creates a node that reads the values of the fields an index of the relation covers from
+    /// the index alone, for the tuples it holds whose indexed values are within the bounds
+    pub fn index_only_scan<S: ToString>(
+        relation: &'a Relation,
+        index: S,
+        lower: Bound<Vec<Value>>,
+        upper: Bound<Vec<Value>>,
+    ) -> Self {
+        let mut node = Self::source(relation);
+        node.read_index_only(relation, index.to_string(), lower, upper);
+        node
+    }
+
+    /// Makes a node that produces every field of the relation read the fields an index covers
+    /// from the index instead
+    ///
+    /// # Panic
+    /// Panics if there's no index with the name
+    pub(super) fn read_index_only(
+        &mut self,
+        relation: &'a Relation,
+        index: String,
+        lower: Bound<Vec<Value>>,
+        upper: Bound<Vec<Value>>,
+    ) {
+        let covered = relation
+            .index(&index)
+            .unwrap_or_else(|| panic!("No index named {} on {}", index, relation.name()))
+            .covered();
+        self.resulting_relation = covered
+            .iter()
+            .map(|&field| self.resulting_relation[field].clone())
+            .collect();
+        self.query = QueryOperation::IndexOnlyScan(relation, index, lower, upper);
+    }
+
+    /// Creates a node that reads every partition of a partitioned relation. The fields are those
+    /// of the first partition.
+    pub fn partitioned_source(relation: PartitionedRelation<'a>) -> Self {
+        let resulting_relation: Vec<(Identifier, Type)> = relation.partitions()[0]
+            .attributes()
+            .iter()
+            .map(|(id, ty)| (Identifier::new(id), ty.clone()))
+            .collect();
+        let mapping = resulting_relation
+            .iter()
+            .map(|(id, _)| (id.clone(), id.clone()))
+            .collect();
+        Self {
+            query: QueryOperation::PartitionedSource(relation),
+            children: Box::new(QueryChildren::None),
+            resulting_relation,
+            mapping,
+            id: 0,
+            hints: vec![],
+        }
+    }
+
+    /// Creates a node that reads every tuple of a relation kept outside of the database
+    pub fn foreign_source(relation: &'a dyn ForeignRelation) -> Self {
+        let resulting_relation: Vec<(Identifier, Type)> = relation
+            .attributes()
+            .iter()
+            .map(|(id, ty)| (Identifier::new(id), ty.clone()))
+            .collect();
+        let mapping = resulting_relation
+            .iter()
+            .map(|(id, _)| (id.clone(), id.clone()))
+            .collect();
+        Self {
+            query: QueryOperation::ForeignSource(relation),
+            children: Box::new(QueryChildren::None),
+            resulting_relation,
+            mapping,
+            id: 0,
+            hints: vec![],
         }
     }
 
     pub fn inner_join(mut left: Self, mut right: Self, condition: JoinCondition) -> Self {
-        let mut result = Vec::new();
-        result.extend(left.resulting_relation.iter().cloned());
-        result.extend(right.resulting_relation.iter().cloned());
+        let query = QueryOperation::InnerJoin(condition);
+        let result = join_relation(
+            &query,
+            &left.resulting_relation,
+            &right.resulting_relation,
+            combined_policy(vec![left.case_policy(), right.case_policy()]),
+        );
         let mapping = result
             .iter()
             .map(|(id, _)| (id.clone(), id.clone()))
@@ -172,14 +868,54 @@ impl<'a> QueryNode<'a> {
         right.increase_id_by(1 + left.count());
 
         QueryNode {
-            query: QueryOperation::InnerJoin(condition),
+            query,
             children: Box::new(QueryChildren::Two(left, right)),
             resulting_relation: result,
             mapping: mapping,
             id: 0,
+            hints: vec![],
+        }
+    }
+
+    fn outer_join(mut left: Self, mut right: Self, query: QueryOperation<'a>) -> Self {
+        let result = join_relation(
+            &query,
+            &left.resulting_relation,
+            &right.resulting_relation,
+            combined_policy(vec![left.case_policy(), right.case_policy()]),
+        );
+        let mapping = result
+            .iter()
+            .map(|(id, _)| (id.clone(), id.clone()))
+            .collect();
+        left.increment_id();
+        right.increase_id_by(1 + left.count());
+
+        QueryNode {
+            query,
+            children: Box::new(QueryChildren::Two(left, right)),
+            resulting_relation: result,
+            mapping,
+            id: 0,
+            hints: vec![],
         }
     }
 
+    /// Joins the nodes, keeping every tuple of the left node
+    pub fn left_join(left: Self, right: Self, condition: JoinCondition) -> Self {
+        Self::outer_join(left, right, QueryOperation::LeftJoin(condition))
+    }
+
+    /// Joins the nodes, keeping every tuple of the right node
+    pub fn right_join(left: Self, right: Self, condition: JoinCondition) -> Self {
+        Self::outer_join(left, right, QueryOperation::RightJoin(condition))
+    }
+
+    /// Joins the nodes, keeping every tuple of both nodes
+    pub fn full_join(left: Self, right: Self, condition: JoinCondition) -> Self {
+        Self::outer_join(left, right, QueryOperation::FullJoin(condition))
+    }
+
     pub fn cross_product(mut left: Self, mut right: Self) -> Self {
         let mut result = Vec::new();
         result.extend(left.resulting_relation.iter().cloned());
@@ -197,10 +933,195 @@ impl<'a> QueryNode<'a> {
             resulting_relation: result,
             mapping: mapping,
             id: 0,
+            hints: vec![],
+        }
+    }
+
+    fn negated_subquery(
+        mut node: Self,
+        mut subquery: Self,
+        condition: JoinCondition,
+        kind: AntiJoinKind,
+    ) -> Self {
+        let result = node.resulting_relation.clone();
+        let mapping = result
+            .iter()
+            .chain(subquery.resulting_relation.iter())
+            .map(|(id, _)| (id.clone(), id.clone()))
+            .collect();
+        node.increment_id();
+        subquery.increase_id_by(1 + node.count());
+
+        QueryNode {
+            query: QueryOperation::NegatedSubquery(condition, kind),
+            children: Box::new(QueryChildren::Two(node, subquery)),
+            resulting_relation: result,
+            mapping,
+            id: 0,
+            hints: vec![],
+        }
+    }
+
+    /// Keeps the tuples of the node where the left field of the condition is not in the values
+    /// of the right field produced by the subquery
+    pub fn not_in(node: Self, subquery: Self, condition: JoinCondition) -> Self {
+        Self::negated_subquery(node, subquery, condition, AntiJoinKind::NotIn)
+    }
+
+    /// Keeps the tuples of the node where no tuple in the subquery has its right field equal
+    /// to the left field of the condition
+    pub fn not_exists(node: Self, subquery: Self, condition: JoinCondition) -> Self {
+        Self::negated_subquery(node, subquery, condition, AntiJoinKind::NotExists)
+    }
+
+    /// Creates a node that produces the tuples found by the previous iteration of the closest
+    /// recursive node above it. The node has the same fields as `like`.
+    pub fn working_table(like: &Self) -> Self {
+        let resulting_relation = like.resulting_relation.clone();
+        let mapping = resulting_relation
+            .iter()
+            .map(|(id, _)| (id.clone(), id.clone()))
+            .collect();
+        Self {
+            query: QueryOperation::WorkingTable,
+            children: Box::new(QueryChildren::None),
+            resulting_relation,
+            mapping,
+            id: 0,
+            hints: vec![],
+        }
+    }
+
+    /// Creates a node that produces no tuples, with the same fields as `like`
+    pub fn empty(like: &Self) -> Self {
+        let resulting_relation = like.resulting_relation.clone();
+        let mapping = like.mapping.clone();
+        Self {
+            query: QueryOperation::Empty,
+            children: Box::new(QueryChildren::None),
+            resulting_relation,
+            mapping,
+            id: like.id,
+            hints: vec![],
+        }
+    }
+
+    /// Creates a query that finds the fixpoint of the step query. The result starts as the tuples
+    /// of the base query, and the step is then executed over the newly found tuples, which it can
+    /// read using a [working_table](Self::working_table), until no new tuples are produced.
+    ///
+    /// # Panic
+    /// Panics if the step doesn't produce the same number of fields as the base.
+    pub fn recursive(mut base: Self, mut step: Self) -> Self {
+        if base.resulting_relation.len() != step.resulting_relation.len() {
+            panic!("The step of a recursive query must produce the same fields as the base")
+        }
+        let resulting_relation = base.resulting_relation.clone();
+        let mapping = base.mapping.clone();
+        base.increment_id();
+        step.increase_id_by(1 + base.count());
+
+        Self {
+            query: QueryOperation::Recursive,
+            children: Box::new(QueryChildren::Two(base, step)),
+            resulting_relation,
+            mapping,
+            id: 0,
+            hints: vec![],
+        }
+    }
+
+    fn rotation(mut node: Self, query: QueryOperation<'a>) -> Self {
+        let resulting_relation = match &query {
+            QueryOperation::Pivot(pivot) => pivot.resulting_relation(&node.resulting_relation),
+            QueryOperation::Unpivot(unpivot) => {
+                unpivot.resulting_relation(&node.resulting_relation)
+            }
+            _ => unreachable!(),
+        };
+        let mapping = resulting_relation
+            .iter()
+            .map(|(id, _)| (id.clone(), id.clone()))
+            .collect();
+        node.increment_id();
+
+        Self {
+            query,
+            children: Box::new(QueryChildren::One(node)),
+            resulting_relation,
+            mapping,
+            id: 0,
+            hints: vec![],
         }
     }
 
+    /// Rotates the values of a column of the node into columns
+    pub fn pivot(node: Self, pivot: Pivot) -> Self {
+        Self::rotation(node, QueryOperation::Pivot(pivot))
+    }
+
+    /// Rotates columns of the node into rows
+    pub fn unpivot(node: Self, unpivot: Unpivot) -> Self {
+        Self::rotation(node, QueryOperation::Unpivot(unpivot))
+    }
+
+    /// Creates a node that keeps the fields of its only child
+    fn pass_through(mut node: Self, query: QueryOperation<'a>) -> Self {
+        let resulting_relation = node.resulting_relation.clone();
+        let mapping = node.mapping.clone();
+        node.increment_id();
+
+        Self {
+            query,
+            children: Box::new(QueryChildren::One(node)),
+            resulting_relation,
+            mapping,
+            id: 0,
+            hints: vec![],
+        }
+    }
+
+    /// Keeps a random subset of the tuples of the node
+    pub fn sample(node: Self, sample: Sample) -> Self {
+        Self::pass_through(node, QueryOperation::Sample(sample))
+    }
+
+    /// Orders the tuples of the node by the keys, where later keys break ties of earlier keys
+    pub fn sort(node: Self, keys: Vec<SortKey>) -> Self {
+        Self::pass_through(node, QueryOperation::Sort(keys))
+    }
+
+    /// Keeps only the first `limit` tuples of the node
+    pub fn limit(node: Self, limit: usize) -> Self {
+        Self::pass_through(node, QueryOperation::Limit(limit))
+    }
+
+    /// Counts the tuples of the node, producing a single tuple with the count in its
+    /// [COUNT_FIELD] field
+    pub fn count_tuples(mut node: Self) -> Self {
+        let resulting_relation = vec![(Identifier::new(COUNT_FIELD), Type::from(0u64))];
+        let mapping = resulting_relation
+            .iter()
+            .map(|(id, _)| (id.clone(), id.clone()))
+            .collect();
+        node.increment_id();
+
+        Self {
+            query: QueryOperation::Count,
+            children: Box::new(QueryChildren::One(node)),
+            resulting_relation,
+            mapping,
+            id: 0,
+            hints: vec![],
+        }
+    }
+
+    /// Creates a node that keeps the tuples the condition holds for. Text is compared the way the
+    /// fields of the relations the condition is on are declared to compare it, unless the
+    /// condition sets how it's compared.
     pub fn select_on_condition(node: Self, condition: Condition) -> Self {
+        let condition =
+            condition.with_column_comparisons(&|field| node.column_text_comparison(field));
         let vec = node.resulting_relation.clone();
         let map = node.mapping.clone();
         Self {
@@ -209,6 +1130,7 @@ impl<'a> QueryNode<'a> {
             resulting_relation: vec,
             mapping: map,
             id: 0,
+            hints: vec![],
         }
     }
 
@@ -221,14 +1143,11 @@ impl<'a> QueryNode<'a> {
         fields: I,
     ) -> Self {
         let projections: Vec<Identifier> = fields.into_iter().map(|i| i.into()).collect();
+        let policy = node.case_policy();
         let resulting_relation = projections
             .iter()
             .filter_map(|id| {
-                if let Some(pos) = node
-                    .resulting_relation
-                    .iter()
-                    .position(|(inner_id, _)| id == inner_id)
-                {
+                if let Some(pos) = field_position(&node.resulting_relation, id, policy) {
                     let (_, ty) = &node.resulting_relation[pos];
                     Some((id.clone(), ty.clone()))
                 } else {
@@ -243,9 +1162,53 @@ impl<'a> QueryNode<'a> {
             resulting_relation,
             mapping: Default::default(),
             id: 0,
+            hints: vec![],
         }
     }
 
+    /// Attaches a hint to this node, which the optimizer follows over its own estimates
+    pub fn with_hint(mut self, hint: Hint) -> Self {
+        self.add_hint(hint);
+        self
+    }
+
+    /// Attaches a hint to this node, replacing the cardinality it's assumed to have if the hint
+    /// is a cardinality
+    pub fn add_hint(&mut self, hint: Hint) {
+        if let Hint::Cardinality(_) = hint {
+            self.hints
+                .retain(|hint| !matches!(hint, Hint::Cardinality(_)));
+        }
+        if !self.hints.contains(&hint) {
+            self.hints.push(hint);
+        }
+    }
+
+    /// The hints attached to this node
+    pub fn hints(&self) -> &[Hint] {
+        &self.hints
+    }
+
+    pub fn has_hint(&self, hint: Hint) -> bool {
+        self.hints.contains(&hint)
+    }
+
+    /// The number of tuples this node is hinted to produce
+    pub fn cardinality_hint(&self) -> Option<usize> {
+        self.hints.iter().find_map(|hint| match hint {
+            Hint::Cardinality(tuples) => Some(*tuples),
+            _ => None,
+        })
+    }
+
+    /// The index this node is hinted to read its source through
+    pub fn index_hint(&self) -> Option<&str> {
+        self.hints.iter().find_map(|hint| match hint {
+            Hint::Index(index) => Some(index.as_str()),
+            _ => None,
+        })
+    }
+
     /// Increases the ids of all of the nodes in this tree by one
     fn increment_id(&mut self) {
         self.increase_id_by(1)
@@ -268,8 +1231,25 @@ impl<'a> QueryNode<'a> {
         ret
     }
 
+    /// Optimizes the query with the global settings
     pub fn optimize_query(&mut self) {
-        let mut optimizer = Optimizer::new(self, 500);
+        let samples = Settings::global().usize(OPTIMIZER_SAMPLES);
+        let mut optimizer = Optimizer::new(self, samples, CostModel::default());
+        optimizer.optimize();
+    }
+
+    /// Optimizes the query with the settings of a session
+    pub fn optimize_with_settings(&mut self, settings: &Settings) {
+        let samples = settings.usize(OPTIMIZER_SAMPLES);
+        let mut optimizer = Optimizer::new(self, samples, CostModel::default());
+        optimizer.optimize();
+    }
+
+    /// Optimizes the query, assuming its selections of relations produce as many tuples as they
+    /// did when they were last executed with the feedback
+    pub fn optimize_with_feedback(&mut self, feedback: &CardinalityFeedback) {
+        let samples = Settings::global().usize(OPTIMIZER_SAMPLES);
+        let mut optimizer = Optimizer::with_rules(self, samples, feedback.rules());
         optimizer.optimize();
     }
 
@@ -278,34 +1258,110 @@ impl<'a> QueryNode<'a> {
         self
     }
 
+    /// Executes the query with the global settings
     pub fn execute_query<'q>(self) -> QueryResult<'q>
     where
         'a: 'q,
     {
+        let batch_size = Settings::global().usize(EXECUTION_BATCH_SIZE);
+        self.execute_reporting(Some(batch_size).filter(|&size| size > 0), None)
+    }
+
+    /// Executes the query with the settings of a session
+    pub fn execute_with_settings<'q>(self, settings: &Settings) -> QueryResult<'q>
+    where
+        'a: 'q,
+    {
+        let batch_size = settings.usize(EXECUTION_BATCH_SIZE);
+        self.execute_reporting(Some(batch_size).filter(|&size| size > 0), None)
+    }
+
+    /// Executes the query, recording how many tuples its selections of relations produced compared
+    /// to how many they were estimated to produce
+    pub fn execute_with_feedback<'q>(self, feedback: &mut CardinalityFeedback) -> QueryResult<'q>
+    where
+        'a: 'q,
+    {
+        let recorded = RefCell::new(std::mem::take(feedback));
+        let result = self.execute_reporting(None, Some(&recorded));
+        *feedback = recorded.into_inner();
+        result
+    }
+
+    /// Executes the query, running selections and projections over batches of tuples stored a
+    /// column at a time instead of one tuple at a time
+    pub fn execute_batched<'q>(self, batch_size: usize) -> QueryResult<'q>
+    where
+        'a: 'q,
+    {
+        self.execute_reporting(Some(batch_size), None)
+    }
+
+    /// Executes the query, attaching the first error that stopped it to the result
+    fn execute_reporting<'q>(
+        self,
+        batch_size: Option<usize>,
+        feedback: Option<&RefCell<CardinalityFeedback>>,
+    ) -> QueryResult<'q>
+    where
+        'a: 'q,
+    {
+        let failure = RefCell::new(None);
+        let result = self.execute_in(Execution {
+            working: &[],
+            batch_size,
+            feedback,
+            failure: &failure,
+        });
+        result.with_error(failure.into_inner())
+    }
+
+    fn execute_in<'q>(self, execution: Execution<'_>) -> QueryResult<'q>
+    where
+        'a: 'q,
+    {
+        let working = execution.working;
+        let observed = execution
+            .feedback
+            .and_then(|_| feedback_key(&self))
+            .map(|key| (key, self.approximate_created_tuples()));
         let mut output_tuples: Vec<Tuple> = vec![];
         let relation = self.resulting_relation.clone();
         let mut extra = 0;
+        let policy = self.case_policy();
+        let using = self.query.using_join().map(|join| {
+            let children = self.children();
+            using_indexes(
+                join,
+                &children[0].resulting_relation,
+                &children[1].resulting_relation,
+                policy,
+            )
+        });
+        // the indexes of the joined fields within the tuples of both children
+        let joined = self.query.join_condition().map(|join| {
+            let children = self.children();
+            join_positions(
+                join,
+                &children[0].resulting_relation,
+                &children[1].resulting_relation,
+                policy,
+            )
+        });
 
         match (self.query, *self.children) {
             (QueryOperation::Source(source), QueryChildren::None) => {
                 let inner = QueryResult::from_source(relation, source);
                 return inner;
             }
-            (QueryOperation::InnerJoin(join), QueryChildren::Two(left, right)) => {
-                let left_id = &self.mapping[join.left_id()]; // the name of the left id in the left result
-                let right_id = &self.mapping[join.right_id()]; // the name of the right id in the right result
+            (QueryOperation::InnerJoin(_), QueryChildren::Two(left, right)) => {
+                let (left_index, right_index) = joined.unwrap();
 
-                let left = left.execute_query();
-                let right = right.execute_query();
+                let left = left.execute_in(execution);
+                let right = right.execute_in(execution);
 
                 extra += left.total_created_tuples() + right.total_created_tuples();
 
-                let left_mappings = left.identifier_mappings();
-                let right_mappings = right.identifier_mappings();
-
-                let left_index = left_mappings[left_id];
-                let right_index = right_mappings[right_id];
-
                 if right.repeatable_blocks().is_some() {
                     let left_blocks = left.blocks();
                     for left_block in left_blocks {
@@ -313,7 +1369,8 @@ impl<'a> QueryNode<'a> {
                         for right_block in right_blocks {
                             for left_tuple in &left_block {
                                 for right_tuple in &right_block {
-                                    if left_tuple[left_index] == right_tuple[right_index] {
+                                    if left_tuple[left_index].numeric_eq(&right_tuple[right_index])
+                                    {
                                         output_tuples.push(left_tuple + right_tuple);
                                     }
                                 }
@@ -321,19 +1378,122 @@ impl<'a> QueryNode<'a> {
                         }
                     }
                 } else {
+                    // the right side is read once for every left tuple, so it's borrowed instead
+                    // of copied each time
                     let mut right = right;
+                    let right_tuples = right.materialized();
                     for left_tuple in left {
-                        for right_tuple in &right {
-                            if left_tuple[left_index] == right_tuple[right_index] {
+                        for right_tuple in right_tuples {
+                            if left_tuple[left_index].numeric_eq(&right_tuple[right_index]) {
                                 output_tuples.push(&left_tuple + right_tuple);
                             }
                         }
                     }
                 }
             }
+            (QueryOperation::PartitionedSource(relation), QueryChildren::None) => {
+                output_tuples.extend(relation.tuples());
+            }
+            (QueryOperation::HistoricalSource(relation, time), QueryChildren::None) => {
+                // the node was only built if the history is retained, which it can't stop being
+                // while the node borrows the relation
+                if let Some(history) = relation.history() {
+                    output_tuples.extend(history.as_of(time).cloned());
+                }
+            }
+            (QueryOperation::ForeignSource(relation), QueryChildren::None) => {
+                for block in relation.blocks() {
+                    match block {
+                        Ok(block) => output_tuples.extend(block),
+                        Err(error) => {
+                            execution.fail(error);
+                            break;
+                        }
+                    }
+                }
+            }
+            (QueryOperation::KeyLookup(relation, key), QueryChildren::None) => {
+                output_tuples.extend(relation.find_by_key(&key));
+            }
+            (QueryOperation::KeyRange(relation, lower, upper), QueryChildren::None) => {
+                output_tuples.extend(relation.key_range(as_slices(&lower), as_slices(&upper)));
+            }
+            (QueryOperation::IndexScan(relation, index, lower, upper), QueryChildren::None) => {
+                output_tuples.extend(relation.index_range(
+                    &index,
+                    as_slices(&lower),
+                    as_slices(&upper),
+                ));
+            }
+            (QueryOperation::IndexOnlyScan(relation, index, lower, upper), QueryChildren::None) => {
+                output_tuples.extend(relation.index_only_range(
+                    &index,
+                    as_slices(&lower),
+                    as_slices(&upper),
+                ));
+            }
+            (QueryOperation::PartitionWiseJoin(_), QueryChildren::Two(left, right)) => {
+                let (left_index, right_index) = joined.unwrap();
+                match (left.query, right.query) {
+                    (
+                        QueryOperation::PartitionedSource(left),
+                        QueryOperation::PartitionedSource(right),
+                    ) => {
+                        extra += left.len() + right.len();
+                        match partition_wise_join(&left, &right, left_index, right_index) {
+                            Ok(tuples) => output_tuples = tuples,
+                            Err(error) => execution.fail(error),
+                        }
+                    }
+                    _ => panic!("A partition-wise join can only join partitioned sources"),
+                }
+            }
+            (QueryOperation::HashJoin(_, build), QueryChildren::Two(left, right)) => {
+                let (left_index, right_index) = joined.unwrap();
+                let types_of = |node: &QueryNode| -> Vec<Type> {
+                    node.resulting_relation()
+                        .iter()
+                        .map(|(_, field_type)| field_type.clone())
+                        .collect()
+                };
+                let left_types = types_of(&left);
+                let right_types = types_of(&right);
+
+                let left = left.execute_in(execution);
+                let right = right.execute_in(execution);
+                extra += left.total_created_tuples() + right.total_created_tuples();
+
+                let spilled = grace_hash_join(
+                    left,
+                    right,
+                    left_index,
+                    right_index,
+                    build,
+                    (&left_types, &right_types),
+                    MemoryBudget::global(),
+                    |tuple| output_tuples.push(tuple),
+                );
+                if let Err(error) = spilled {
+                    execution.fail(error);
+                }
+            }
+            (outer @ QueryOperation::LeftJoin(_), QueryChildren::Two(left, right))
+            | (outer @ QueryOperation::RightJoin(_), QueryChildren::Two(left, right))
+            | (outer @ QueryOperation::FullJoin(_), QueryChildren::Two(left, right)) => {
+                let (left_index, right_index) = joined.unwrap();
+
+                let left = left.execute_in(execution);
+                let right = right.execute_in(execution);
+                extra += left.total_created_tuples() + right.total_created_tuples();
+
+                let left_key = (left_index, left.relation().len());
+                let right_key = (right_index, right.relation().len());
+                output_tuples =
+                    outer_join(left, right, left_key, right_key, outer.preserved_inputs());
+            }
             (QueryOperation::CrossProduct, QueryChildren::Two(left, right)) => {
-                let left = left.execute_query();
-                let right = right.execute_query();
+                let left = left.execute_in(execution);
+                let right = right.execute_in(execution);
 
                 extra += left.total_created_tuples() + right.total_created_tuples();
 
@@ -351,25 +1511,197 @@ impl<'a> QueryNode<'a> {
                     }
                 } else {
                     let mut right = right;
+                    let right_tuples = right.materialized();
                     for left_tuple in left {
-                        for right_tuple in &right {
+                        for right_tuple in right_tuples {
                             output_tuples.push(&left_tuple + right_tuple);
                         }
                     }
                 }
             }
-            (QueryOperation::Selection(condition), QueryChildren::One(child)) => {}
-            (QueryOperation::Projection(projection), QueryChildren::One(child)) => {}
+            (QueryOperation::NegatedSubquery(_, kind), QueryChildren::Two(left, right)) => {
+                let (left_index, right_index) = joined.unwrap();
+
+                let left = left.execute_in(execution);
+                extra += left.total_created_tuples();
+
+                for left_tuple in left {
+                    let subquery = right.clone().execute_in(execution);
+                    extra += subquery.total_created_tuples();
+                    let values = SubqueryValues::new(
+                        subquery.into_iter().map(|tuple| tuple.take(right_index)),
+                    );
+                    if kind.keeps(&left_tuple[left_index], &values) {
+                        output_tuples.push(left_tuple);
+                    }
+                }
+            }
+            (QueryOperation::AntiJoin(_, kind), QueryChildren::Two(left, right)) => {
+                let (left_index, right_index) = joined.unwrap();
+
+                let left = left.execute_in(execution);
+                let right = right.execute_in(execution);
+                extra += left.total_created_tuples() + right.total_created_tuples();
+
+                let values =
+                    SubqueryValues::new(right.into_iter().map(|tuple| tuple.take(right_index)));
+                for left_tuple in left {
+                    if kind.keeps(&left_tuple[left_index], &values) {
+                        output_tuples.push(left_tuple);
+                    }
+                }
+            }
+            (QueryOperation::Selection(condition), QueryChildren::One(child)) => {
+                let child = child.execute_in(execution);
+                extra += child.total_created_tuples();
+                let fields = resolved_names(child.relation(), &condition.relevant_fields(), policy);
+                if let Some(batch_size) = execution.batch_size {
+                    output_tuples = select_batched(&condition, &fields, child, batch_size);
+                } else {
+                    for tuple in child {
+                        let wrapped = WrappedTuple::new(&fields, &tuple);
+                        if let Ok(true) = condition.evaluate_on(&wrapped) {
+                            output_tuples.push(tuple);
+                        }
+                    }
+                }
+            }
+            (QueryOperation::Projection(projection), QueryChildren::One(child)) => {
+                let child = child.execute_in(execution);
+                extra += child.total_created_tuples();
+                let indexes: Vec<usize> = projection
+                    .iter()
+                    .filter_map(|id| field_position(child.relation(), id, policy))
+                    .collect();
+                if let Some(batch_size) = execution.batch_size {
+                    let fields: Vec<Identifier> =
+                        child.relation().iter().map(|(id, _)| id.clone()).collect();
+                    output_tuples = project_batched(&indexes, &fields, child, batch_size);
+                } else {
+                    for tuple in child {
+                        output_tuples.push(tuple.project(&indexes));
+                    }
+                }
+            }
+            (QueryOperation::Pivot(pivot), QueryChildren::One(child)) => {
+                let sorted = child.ordered_on().first() == Some(pivot.key());
+                let child = child.execute_in(execution);
+                extra += child.total_created_tuples();
+                let mappings = child.identifier_mappings();
+                let pivoted = if sorted {
+                    pivot.execute_sorted(&mappings, child)
+                } else {
+                    pivot.execute(&mappings, child)
+                };
+                match pivoted {
+                    Ok(tuples) => output_tuples = tuples,
+                    Err(error) => execution.fail(error),
+                }
+            }
+            (QueryOperation::Unpivot(unpivot), QueryChildren::One(child)) => {
+                let child = child.execute_in(execution);
+                extra += child.total_created_tuples();
+                let mappings = child.identifier_mappings();
+                output_tuples = unpivot.execute(&mappings, child);
+            }
+            (QueryOperation::Sample(sample), QueryChildren::One(child)) => {
+                let child = child.execute_in(execution);
+                extra += child.total_created_tuples();
+                output_tuples = sample.execute(child);
+            }
+            (QueryOperation::Sort(keys), QueryChildren::One(child)) => {
+                let child = child.execute_in(execution);
+                extra += child.total_created_tuples();
+                let comparator = TupleComparator::new(&keys, &child.identifier_mappings());
+                let types: Vec<Type> = child.relation().iter().map(|(_, t)| t.clone()).collect();
+                output_tuples = comparator.sort(child, &types, MemoryBudget::global());
+            }
+            (QueryOperation::Limit(limit), QueryChildren::One(child)) => {
+                let child = child.execute_in(execution);
+                extra += child.total_created_tuples();
+                output_tuples.extend(child.into_iter().take(limit));
+            }
+            (QueryOperation::TopN(keys, limit), QueryChildren::One(child)) => {
+                let child = child.execute_in(execution);
+                extra += child.total_created_tuples();
+                let comparator = TupleComparator::new(&keys, &child.identifier_mappings());
+                output_tuples = comparator.top_n(child, limit);
+            }
+            (QueryOperation::WorkingTable, QueryChildren::None) => {
+                output_tuples.extend(working.iter().cloned());
+            }
+            (QueryOperation::Empty, QueryChildren::None) => {}
+            (QueryOperation::Count, QueryChildren::One(child)) => {
+                let child = child.execute_in(execution);
+                extra += child.total_created_tuples();
+                let count = child.into_iter().count();
+                output_tuples.push(Tuple::new(vec![Value::from(count as u64)]));
+            }
+            (QueryOperation::RelationCount(relation), QueryChildren::None) => {
+                output_tuples.push(Tuple::new(vec![Value::from(relation.len() as u64)]));
+            }
+            (QueryOperation::Recursive, QueryChildren::Two(base, step)) => {
+                let base = base.execute_in(execution);
+                extra += base.total_created_tuples();
+                // every tuple found so far, which is the output once no new ones are found
+                let mut seen: HashSet<Tuple> = HashSet::new();
+                let mut delta: Vec<Tuple> = base
+                    .into_iter()
+                    .filter(|tuple| seen.insert(tuple.clone()))
+                    .collect();
+
+                while !delta.is_empty() {
+                    output_tuples.extend(delta.iter().cloned());
+                    let found = step.clone().execute_in(Execution {
+                        working: &delta,
+                        ..execution
+                    });
+                    extra += found.total_created_tuples();
+                    delta = found
+                        .into_iter()
+                        .filter(|tuple| seen.insert(tuple.clone()))
+                        .collect();
+                }
+            }
 
             _ => panic!("Invalid query"),
         }
 
+        if let (Some(feedback), Some((key, estimated))) = (execution.feedback, observed) {
+            feedback
+                .borrow_mut()
+                .record(key, estimated, output_tuples.len());
+        }
+
+        if let Some(indexes) = using {
+            output_tuples = output_tuples
+                .into_iter()
+                .map(|tuple| merge_using_field(tuple, indexes))
+                .collect();
+        }
+
         QueryResult::with_tuples(relation, &mut output_tuples.into_iter(), extra)
     }
 
     pub fn approximate_created_tuples(&self) -> usize {
+        if let Some(tuples) = self.cardinality_hint() {
+            return tuples;
+        }
         match &self.query {
             QueryOperation::Source(s) => s.source_len(),
+            QueryOperation::PartitionedSource(relation) => relation.len(),
+            QueryOperation::HistoricalSource(relation, _) => relation.len(),
+            QueryOperation::ForeignSource(relation) => {
+                relation.len_hint().unwrap_or(ASSUMED_FOREIGN_TUPLES)
+            }
+            QueryOperation::KeyLookup(relation, _) => min(relation.len(), 1),
+            QueryOperation::KeyRange(relation, lower, upper) => {
+                relation.key_range_len(as_slices(lower), as_slices(upper))
+            }
+            QueryOperation::IndexScan(relation, index, lower, upper)
+            | QueryOperation::IndexOnlyScan(relation, index, lower, upper) => {
+                relation.index_range_len(index, as_slices(lower), as_slices(upper))
+            }
             QueryOperation::Projection(_) => {
                 if let QueryChildren::One(child) = &*self.children {
                     child.approximate_created_tuples()
@@ -379,7 +1711,14 @@ impl<'a> QueryNode<'a> {
             }
             QueryOperation::Selection(c) => {
                 if let QueryChildren::One(child) = &*self.children {
-                    c.selectivity(child.approximate_created_tuples()) as usize
+                    let estimate = Self::estimate_with_statistics(c, child).unwrap_or_else(|| {
+                        c.selectivity(child.approximate_created_tuples()) as usize
+                    });
+                    if self.unique_keys().iter().any(|key| key.is_empty()) {
+                        min(estimate, 1)
+                    } else {
+                        estimate
+                    }
                 } else {
                     panic!("Invalid query")
                 }
@@ -391,7 +1730,40 @@ impl<'a> QueryNode<'a> {
                     panic!("Invalid query")
                 }
             }
-            QueryOperation::InnerJoin(_) => {
+            QueryOperation::InnerJoin(join)
+            | QueryOperation::HashJoin(join, _)
+            | QueryOperation::PartitionWiseJoin(join) => {
+                if let QueryChildren::Two(l, r) = &*self.children {
+                    let left = l.approximate_created_tuples();
+                    let right = r.approximate_created_tuples();
+                    match (l.is_key(join.left_id()), r.is_key(join.right_id())) {
+                        (true, true) => min(left, right),
+                        (false, true) => left,
+                        (true, false) => right,
+                        (false, false) => max(left, right),
+                    }
+                } else {
+                    panic!("Invalid query")
+                }
+            }
+            QueryOperation::LeftJoin(_) | QueryOperation::RightJoin(_) => {
+                if let QueryChildren::Two(l, r) = &*self.children {
+                    max(
+                        l.approximate_created_tuples(),
+                        r.approximate_created_tuples(),
+                    )
+                } else {
+                    panic!("Invalid query")
+                }
+            }
+            QueryOperation::FullJoin(_) => {
+                if let QueryChildren::Two(l, r) = &*self.children {
+                    l.approximate_created_tuples() + r.approximate_created_tuples()
+                } else {
+                    panic!("Invalid query")
+                }
+            }
+            QueryOperation::NaturalJoin => {
                 if let QueryChildren::Two(l, r) = &*self.children {
                     max(
                         l.approximate_created_tuples(),
@@ -401,33 +1773,272 @@ impl<'a> QueryNode<'a> {
                     panic!("Invalid query")
                 }
             }
-            QueryOperation::LeftJoin(_) => {
-                if let QueryChildren::Two(l, r) = &*self.children {
-                    l.approximate_created_tuples() * r.approximate_created_tuples()
+            QueryOperation::NegatedSubquery(..) => {
+                if let QueryChildren::Two(l, r) = &*self.children {
+                    let left = l.approximate_created_tuples();
+                    left + left * r.approximate_created_tuples()
+                } else {
+                    panic!("Invalid query")
+                }
+            }
+            QueryOperation::AntiJoin(..) => {
+                if let QueryChildren::Two(l, r) = &*self.children {
+                    l.approximate_created_tuples() + r.approximate_created_tuples()
+                } else {
+                    panic!("Invalid query")
+                }
+            }
+            QueryOperation::Recursive => {
+                if let QueryChildren::Two(l, r) = &*self.children {
+                    let base = l.approximate_created_tuples();
+                    base + base * r.approximate_created_tuples()
+                } else {
+                    panic!("Invalid query")
+                }
+            }
+            QueryOperation::WorkingTable => 1,
+            QueryOperation::Empty => 0,
+            QueryOperation::Count | QueryOperation::RelationCount(_) => 1,
+            QueryOperation::Sort(_) => {
+                if let QueryChildren::One(child) = &*self.children {
+                    child.approximate_created_tuples()
+                } else {
+                    panic!("Invalid query")
+                }
+            }
+            QueryOperation::Limit(limit) | QueryOperation::TopN(_, limit) => {
+                if let QueryChildren::One(child) = &*self.children {
+                    min(*limit, child.approximate_created_tuples())
+                } else {
+                    panic!("Invalid query")
+                }
+            }
+            QueryOperation::Sample(sample) => {
+                if let QueryChildren::One(child) = &*self.children {
+                    sample.approximate_len(child.approximate_created_tuples())
+                } else {
+                    panic!("Invalid query")
+                }
+            }
+            QueryOperation::Pivot(_) => {
+                if let QueryChildren::One(child) = &*self.children {
+                    child.approximate_created_tuples()
+                } else {
+                    panic!("Invalid query")
+                }
+            }
+            QueryOperation::Unpivot(unpivot) => {
+                if let QueryChildren::One(child) = &*self.children {
+                    child.approximate_created_tuples() * unpivot.columns()
+                } else {
+                    panic!("Invalid query")
+                }
+            }
+        }
+    }
+
+    /// Estimates the tuples a selection keeps from the statistics of the relation it reads, when
+    /// the relation has been analyzed and the condition fixes fields to constants or lists of
+    /// constants, or bounds them by constants. A list of `k` constants keeps `k / distinct` of the
+    /// tuples, and bounds keep the fraction of the field's histogram within them.
+    fn estimate_with_statistics(condition: &Condition, child: &QueryNode) -> Option<usize> {
+        let relation = child.my_relation()?;
+        let statistics = relation.statistics()?;
+        let fixed = condition.fixed_fields();
+        let listed = condition.listed_fields();
+        let ranged = condition.ranged_fields();
+        if fixed.is_empty() && listed.is_empty() && ranged.is_empty() {
+            return None;
+        }
+        let mut estimate = child.approximate_created_tuples() as f64;
+        for field in fixed {
+            let index = child.relation_field_index(&field)?;
+            estimate /= statistics.field(index)?.distinct().max(1) as f64;
+        }
+        for (field, count) in listed {
+            let index = child.relation_field_index(&field)?;
+            let distinct = statistics.field(index)?.distinct().max(1) as f64;
+            estimate *= (count as f64).min(distinct) / distinct;
+        }
+        for (field, lower, upper) in ranged {
+            let index = child.relation_field_index(&field)?;
+            let field = statistics.field(index)?;
+            let within = field
+                .histogram()?
+                .fraction_within(lower.as_ref(), upper.as_ref())?;
+            // NULL values aren't in the histogram, and are never within a range
+            let nulls = field.nulls() as f64 / statistics.tuples().max(1) as f64;
+            estimate *= within * (1.0 - nulls);
+        }
+        Some(estimate.ceil() as usize)
+    }
+
+    /// The fields the tuples produced by this node are ordered on, where later fields order the
+    /// tuples with the same values for the fields before them
+    pub fn ordered_on(&self) -> Vec<Identifier> {
+        self.sorted_by()
+            .into_iter()
+            .map(|key| key.field().clone())
+            .collect()
+    }
+
+    /// The order of the tuples produced by this node, including the direction of each field and
+    /// where its `NULL` values are placed
+    pub fn sorted_by(&self) -> Vec<SortKey> {
+        match (&self.query, &*self.children) {
+            (QueryOperation::Sort(keys), _) | (QueryOperation::TopN(keys, _), _) => keys.clone(),
+            (QueryOperation::KeyRange(relation, ..), _) => {
+                let mut fields = relation.primary_key().to_vec();
+                fields.sort_unstable();
+                // encoded keys place NULL before every other value
+                fields
+                    .into_iter()
+                    .map(|index| {
+                        SortKey::ascending(self.resulting_relation[index].0.clone()).nulls_first()
+                    })
+                    .collect()
+            }
+            (QueryOperation::IndexScan(relation, index, ..), _) => {
+                relation.index(index).map_or(vec![], |index| {
+                    index
+                        .fields()
+                        .iter()
+                        .map(|&field| {
+                            SortKey::ascending(self.resulting_relation[field].0.clone())
+                                .nulls_first()
+                        })
+                        .collect()
+                })
+            }
+            (QueryOperation::IndexOnlyScan(relation, index, ..), _) => {
+                relation.index(index).map_or(vec![], |index| {
+                    index
+                        .fields()
+                        .iter()
+                        .filter_map(|field| index.covered().iter().position(|c| c == field))
+                        .map(|position| {
+                            SortKey::ascending(self.resulting_relation[position].0.clone())
+                                .nulls_first()
+                        })
+                        .collect()
+                })
+            }
+            (QueryOperation::Selection(_), QueryChildren::One(child))
+            | (QueryOperation::Limit(_), QueryChildren::One(child)) => child.sorted_by(),
+            (QueryOperation::Projection(fields), QueryChildren::One(child)) => child
+                .sorted_by()
+                .into_iter()
+                .take_while(|key| fields.contains(key.field()))
+                .collect(),
+            _ => vec![],
+        }
+    }
+
+    /// Sets of fields where no two tuples produced by this node have the same values for all of
+    /// the fields. An empty set means that the node produces at most one tuple.
+    pub fn unique_keys(&self) -> Vec<Vec<Identifier>> {
+        match (&self.query, &*self.children) {
+            (QueryOperation::KeyLookup(..), _) => vec![vec![]],
+            (QueryOperation::HistoricalSource(relation, _), _) => {
+                let key = relation
+                    .primary_key()
+                    .iter()
+                    .map(|&index| self.resulting_relation[index].0.clone())
+                    .collect();
+                vec![key]
+            }
+            (QueryOperation::KeyRange(relation, ..), _)
+            | (QueryOperation::IndexScan(relation, ..), _) => {
+                let key = relation
+                    .primary_key()
+                    .iter()
+                    .map(|&index| self.resulting_relation[index].0.clone())
+                    .collect();
+                vec![key]
+            }
+            (QueryOperation::IndexOnlyScan(relation, index, ..), _) => {
+                // an index covers every field of the primary key
+                let covered = relation
+                    .index(index)
+                    .map_or(vec![], |index| index.covered().to_vec());
+                let key = relation
+                    .primary_key()
+                    .iter()
+                    .filter_map(|field| covered.iter().position(|covered| covered == field))
+                    .map(|position| self.resulting_relation[position].0.clone())
+                    .collect();
+                vec![key]
+            }
+            (QueryOperation::Source(source), _) => {
+                let key = source
+                    .relation()
+                    .primary_key()
+                    .iter()
+                    .map(|&index| self.resulting_relation[index].0.clone())
+                    .collect();
+                vec![key]
+            }
+            (QueryOperation::Selection(condition), QueryChildren::One(child)) => {
+                let fixed = condition.fixed_fields();
+                child
+                    .unique_keys()
+                    .into_iter()
+                    .map(|key| key.into_iter().filter(|id| !fixed.contains(id)).collect())
+                    .collect()
+            }
+            (QueryOperation::Projection(fields), QueryChildren::One(child)) => child
+                .unique_keys()
+                .into_iter()
+                .filter(|key| key.iter().all(|id| fields.contains(id)))
+                .collect(),
+            (QueryOperation::Sort(_), QueryChildren::One(child))
+            | (QueryOperation::Limit(_), QueryChildren::One(child))
+            | (QueryOperation::TopN(..), QueryChildren::One(child))
+            | (QueryOperation::Sample(_), QueryChildren::One(child)) => child.unique_keys(),
+            (QueryOperation::Empty, _)
+            | (QueryOperation::Count, _)
+            | (QueryOperation::RelationCount(_), _) => vec![vec![]],
+            (QueryOperation::PartitionedSource(relation), _) => {
+                // equal values of the partitioning field are in the same partition, so a key of
+                // every partition with that field is unique across all of them
+                let key: Vec<Identifier> = relation.partitions()[0]
+                    .primary_key()
+                    .iter()
+                    .map(|&index| self.resulting_relation[index].0.clone())
+                    .collect();
+                if key.contains(relation.field()) {
+                    vec![key]
                 } else {
-                    panic!("Invalid query")
+                    vec![]
                 }
             }
-            QueryOperation::RightJoin(_) => {
-                if let QueryChildren::Two(l, r) = &*self.children {
-                    l.approximate_created_tuples() * r.approximate_created_tuples()
-                } else {
-                    panic!("Invalid query")
+            (QueryOperation::NegatedSubquery(..), QueryChildren::Two(left, _))
+            | (QueryOperation::AntiJoin(..), QueryChildren::Two(left, _)) => left.unique_keys(),
+            (QueryOperation::InnerJoin(join), QueryChildren::Two(left, right))
+            | (QueryOperation::HashJoin(join, _), QueryChildren::Two(left, right))
+            | (QueryOperation::PartitionWiseJoin(join), QueryChildren::Two(left, right)) => {
+                // every tuple matches at most one tuple of a side joined on its key, so the keys
+                // of the other side stay unique
+                let mut keys = vec![];
+                if right.is_key(join.right_id()) {
+                    keys.extend(left.unique_keys());
                 }
-            }
-            QueryOperation::NaturalJoin => {
-                if let QueryChildren::Two(l, r) = &*self.children {
-                    max(
-                        l.approximate_created_tuples(),
-                        r.approximate_created_tuples(),
-                    )
-                } else {
-                    panic!("Invalid query")
+                if left.is_key(join.left_id()) {
+                    keys.extend(right.unique_keys());
                 }
+                keys
             }
+            _ => vec![],
         }
     }
 
+    /// Whether the field alone is enough to identify a tuple produced by this node
+    fn is_key(&self, field: &Identifier) -> bool {
+        self.unique_keys()
+            .iter()
+            .any(|key| key.iter().all(|id| id == field))
+    }
+
     pub fn children(&self) -> Vec<&QueryNode<'a>> {
         match &*self.children {
             QueryChildren::None => {
@@ -460,6 +2071,19 @@ impl<'a> QueryNode<'a> {
         }
     }
 
+    /// A copy of this node without its children that doesn't borrow any relations, or `None`
+    /// if the node reads a relation
+    pub(super) fn detached(&self) -> Option<QueryNode<'static>> {
+        Some(QueryNode {
+            query: self.query.detached()?,
+            children: Box::new(QueryChildren::None),
+            resulting_relation: self.resulting_relation.clone(),
+            mapping: self.mapping.clone(),
+            id: self.id,
+            hints: self.hints.clone(),
+        })
+    }
+
     pub fn query_operation(&self) -> &QueryOperation<'a> {
         &self.query
     }
@@ -478,6 +2102,58 @@ impl<'a> QueryNode<'a> {
                 .sum::<usize>()
     }
 
+    /// Describes the plan of the query, one node per line with the children of each node
+    /// indented below it, along with the number of tuples each node is estimated to produce and
+    /// the cost of the node in the default [CostModel]
+    pub fn explain(&self) -> String {
+        self.explain_with_costs(&CostModel::default())
+    }
+
+    /// Describes the plan of the query like [explain](QueryNode::explain), with the cost of each
+    /// node in the cost model
+    pub fn explain_with_costs(&self, costs: &CostModel) -> String {
+        let mut output = String::new();
+        self.explain_into(&mut output, 0, Some(costs));
+        output
+    }
+
+    /// Describes the plan of the query like [explain](QueryNode::explain), but without the
+    /// estimates, so that the description only changes when the plan does
+    pub fn plan_text(&self) -> String {
+        let mut output = String::new();
+        self.explain_into(&mut output, 0, None);
+        output
+    }
+
+    fn explain_into(&self, output: &mut String, depth: usize, costs: Option<&CostModel>) {
+        output.push_str(&format!("{}{}", "  ".repeat(depth), self.query));
+        if let Some(costs) = costs {
+            output.push_str(&format!(
+                " (~{} tuples) (cost {:.2})",
+                self.approximate_created_tuples(),
+                costs.cost_of(self)
+            ));
+        }
+        output.push('\n');
+        for child in self.children() {
+            child.explain_into(output, depth + 1, costs);
+        }
+    }
+
+    /// Whether the queries have the same plan, which reads relations with the same names in
+    /// the same way. The ids of the nodes aren't compared.
+    pub fn same_plan(&self, other: &QueryNode) -> bool {
+        structure_of(self) == structure_of(other)
+    }
+
+    /// A hash of the plan of the query, which is the same for queries with the
+    /// [same plan](QueryNode::same_plan)
+    pub fn plan_hash(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        structure_of(self).hash(&mut hasher);
+        hasher.finish()
+    }
+
     /// Finds the lowest node with this relation in it. If multiple children contain the
     /// relation, this node is the lowest node.
     pub fn find_relation<I: Into<Identifier> + ToOwned<Owned = I>>(
@@ -549,6 +2225,38 @@ impl<'a> QueryNode<'a> {
         (found_relations, None)
     }
 
+    /// The index of a field within the relation of this node, seeing through the alias of the
+    /// relation
+    pub(super) fn relation_field_index(&self, field: &Identifier) -> Option<usize> {
+        if let QueryOperation::Source(source) = &self.query {
+            return source.source.get_field_index(field);
+        }
+        self.my_relation()?.get_field_index(field)
+    }
+
+    /// How the names of the fields of this node are matched, which folds case when any relation
+    /// this node reads from does
+    pub(super) fn case_policy(&self) -> CasePolicy {
+        let own = match &self.query {
+            QueryOperation::Source(source) => Some(source.relation()),
+            QueryOperation::HistoricalSource(relation, _)
+            | QueryOperation::KeyLookup(relation, _)
+            | QueryOperation::KeyRange(relation, ..)
+            | QueryOperation::IndexScan(relation, ..)
+            | QueryOperation::IndexOnlyScan(relation, ..)
+            | QueryOperation::RelationCount(relation) => Some(*relation),
+            QueryOperation::PartitionedSource(partitioned) => {
+                partitioned.partitions().first().copied()
+            }
+            _ => None,
+        };
+        combined_policy(
+            own.map(Relation::case_policy)
+                .into_iter()
+                .chain(self.children().into_iter().map(QueryNode::case_policy)),
+        )
+    }
+
     /// If this node only has one relation, this function finds such relation. If there are
     /// multiple relations that this is parent of, None is returned.
     pub(super) fn my_relation(&self) -> Option<&'a Relation> {
@@ -556,6 +2264,13 @@ impl<'a> QueryNode<'a> {
             // Garuanteed no children
             return Some(source.source.relation());
         }
+        if let QueryOperation::KeyLookup(relation, _)
+        | QueryOperation::KeyRange(relation, ..)
+        | QueryOperation::IndexScan(relation, ..)
+        | QueryOperation::IndexOnlyScan(relation, ..) = &self.query
+        {
+            return Some(relation);
+        }
 
         let mut ret = None;
         for child in self.children() {
@@ -575,15 +2290,105 @@ impl<'a> QueryNode<'a> {
     /// part of the same relation
     pub fn find_node_with_field<I: Into<Identifier>>(&self, field: I) -> Option<&QueryNode<'a>> {
         let id = field.into();
-        if let QueryOperation::Source(source) = &self.query {
-            if source.source.contains_field(&id) {
+        // a source that produces the field under exactly this name is preferred, so that the
+        // field of an unaliased relation isn't ambiguous with an aliased copy of the relation
+        let named = |node: &QueryNode<'a>| node.resulting_relation.iter().any(|(f, _)| f == &id);
+        let resolves = |node: &QueryNode<'a>| match &node.query {
+            QueryOperation::Source(source) => source.source.contains_field(&id),
+            _ => false,
+        };
+        self.find_source(&named)
+            .or_else(|| self.find_source(&resolves))
+    }
+
+    /// Checks that every field the plan refers to is produced by the children of the node that
+    /// refers to it, so that a plan that would fail during execution is caught before execution
+    /// begins. The first missing field is reported along with the closest field that does exist.
+    pub fn validate(&self) -> Result<(), MissingFieldError> {
+        for child in self.children() {
+            child.validate()?;
+        }
+
+        // the fields that must be produced by the child at each index
+        let required: Vec<(usize, Identifier)> = match &self.query {
+            QueryOperation::Selection(condition) => {
+                let mut fields: Vec<_> = condition.relevant_fields().into_iter().collect();
+                fields.sort_by_key(|field| field.to_string());
+                fields.into_iter().map(|field| (0, field)).collect()
+            }
+            QueryOperation::Projection(fields) => {
+                fields.iter().map(|field| (0, field.clone())).collect()
+            }
+            QueryOperation::Sort(keys) | QueryOperation::TopN(keys, _) => {
+                keys.iter().map(|key| (0, key.field().clone())).collect()
+            }
+            QueryOperation::Pivot(pivot) => pivot
+                .input_fields()
+                .into_iter()
+                .map(|field| (0, field))
+                .collect(),
+            QueryOperation::Unpivot(unpivot) => unpivot
+                .rotated_columns()
+                .iter()
+                .map(|field| (0, field.clone()))
+                .collect(),
+            QueryOperation::InnerJoin(join)
+            | QueryOperation::HashJoin(join, _)
+            | QueryOperation::PartitionWiseJoin(join)
+            | QueryOperation::LeftJoin(join)
+            | QueryOperation::RightJoin(join)
+            | QueryOperation::FullJoin(join)
+            | QueryOperation::NegatedSubquery(join, _)
+            | QueryOperation::AntiJoin(join, _) => {
+                vec![(0, join.left_id().clone()), (1, join.right_id().clone())]
+            }
+            _ => vec![],
+        };
+
+        let children = self.children();
+        for (child, field) in required {
+            let fields = &children[child].resulting_relation;
+            if !fields.iter().any(|(id, _)| id == &field) {
+                let suggestion = closest_field(&field, fields);
+                return Err(MissingFieldError::new(field, suggestion));
+            }
+        }
+        Ok(())
+    }
+
+    /// How the relation the field is read from compares the text of the field, if it's read from
+    /// a relation
+    fn column_text_comparison(&self, field: &Identifier) -> Option<TextComparison> {
+        let relation = match &self.query {
+            QueryOperation::Source(source) => source.relation(),
+            QueryOperation::HistoricalSource(relation, _)
+            | QueryOperation::KeyLookup(relation, _)
+            | QueryOperation::KeyRange(relation, ..)
+            | QueryOperation::IndexScan(relation, ..)
+            | QueryOperation::IndexOnlyScan(relation, ..) => relation,
+            _ => {
+                return self
+                    .children()
+                    .into_iter()
+                    .find_map(|child| child.column_text_comparison(field))
+            }
+        };
+        relation
+            .get_field_index(field.clone())
+            .map(|index| relation.text_comparison(index))
+    }
+
+    /// Finds the only source below this node that matches, or `None` if multiple sources match
+    fn find_source(&self, matches: &dyn Fn(&QueryNode<'a>) -> bool) -> Option<&QueryNode<'a>> {
+        if let QueryOperation::Source(_) = &self.query {
+            if matches(self) {
                 return Some(self);
             }
         }
 
         let mut ret = None;
         for child in self.children() {
-            if let Some(node) = child.find_node_with_field(&id) {
+            if let Some(node) = child.find_source(matches) {
                 if ret == None {
                     ret = Some(node);
                 } else {
@@ -627,7 +2432,18 @@ impl<'a> QueryNode<'a> {
         }
 
         let relation = match &self.query {
-            QueryOperation::Source(_) => self.resulting_relation.clone(),
+            QueryOperation::Source(_)
+            | QueryOperation::PartitionedSource(_)
+            | QueryOperation::HistoricalSource(..)
+            | QueryOperation::ForeignSource(_)
+            | QueryOperation::KeyLookup(..)
+            | QueryOperation::KeyRange(..)
+            | QueryOperation::IndexScan(..)
+            | QueryOperation::IndexOnlyScan(..)
+            | QueryOperation::WorkingTable
+            | QueryOperation::Empty
+            | QueryOperation::Count
+            | QueryOperation::RelationCount(_) => self.resulting_relation.clone(),
             QueryOperation::Projection(p) => {
                 let child = self.children()[0];
                 child
@@ -642,15 +2458,35 @@ impl<'a> QueryNode<'a> {
                     })
                     .collect::<Vec<_>>()
             }
-            QueryOperation::Selection(_) => {
+            QueryOperation::Selection(_)
+            | QueryOperation::NegatedSubquery(..)
+            | QueryOperation::AntiJoin(..)
+            | QueryOperation::Recursive
+            | QueryOperation::Sample(_)
+            | QueryOperation::Sort(_)
+            | QueryOperation::Limit(_)
+            | QueryOperation::TopN(..) => {
                 let child = self.children()[0];
                 child.resulting_relation.clone()
             }
-            QueryOperation::CrossProduct
-            | QueryOperation::InnerJoin(_)
+            QueryOperation::InnerJoin(_)
+            | QueryOperation::HashJoin(..)
+            | QueryOperation::PartitionWiseJoin(_)
             | QueryOperation::LeftJoin(_)
             | QueryOperation::RightJoin(_)
-            | QueryOperation::NaturalJoin => {
+            | QueryOperation::FullJoin(_) => join_relation(
+                &self.query,
+                &self.children()[0].resulting_relation,
+                &self.children()[1].resulting_relation,
+                self.case_policy(),
+            ),
+            QueryOperation::Pivot(pivot) => {
+                pivot.resulting_relation(&self.children()[0].resulting_relation)
+            }
+            QueryOperation::Unpivot(unpivot) => {
+                unpivot.resulting_relation(&self.children()[0].resulting_relation)
+            }
+            QueryOperation::CrossProduct | QueryOperation::NaturalJoin => {
                 let mut left = self.children()[0].resulting_relation.clone();
                 left.extend(self.children()[1].resulting_relation.clone());
                 left
@@ -660,6 +2496,11 @@ impl<'a> QueryNode<'a> {
         self.resulting_relation = relation;
     }
 
+    /// The fields of the tuples produced by this node
+    pub fn resulting_relation(&self) -> &Vec<(Identifier, Type)> {
+        &self.resulting_relation
+    }
+
     /// Gets the tree-specific id of the node
     pub fn id(&self) -> usize {
         self.id
@@ -670,9 +2511,12 @@ impl<'a> QueryNode<'a> {
         match self.query_operation() {
             QueryOperation::CrossProduct => true,
             QueryOperation::InnerJoin(_) => true,
+            QueryOperation::HashJoin(..) => true,
             QueryOperation::LeftJoin(_) => true,
             QueryOperation::RightJoin(_) => true,
+            QueryOperation::FullJoin(_) => true,
             QueryOperation::NaturalJoin => true,
+            QueryOperation::AntiJoin(..) => true,
             _ => false,
         }
     }
@@ -708,7 +2552,9 @@ mod join_tests {
         );
         for i in 0..100u64 {
             //println!("Inserting tuple {}", i);
-            relation1.insert(Tuple::from_iter(&[Value::from(i)]));
+            relation1
+                .insert(Tuple::from_iter(&[Value::from(i)]))
+                .unwrap();
         }
         let mut relation2 = Relation::new_volatile(
             Identifier::new("test2"),
@@ -718,7 +2564,9 @@ mod join_tests {
         );
         for i in 0..100u64 {
             //println!("Inserting tuple {}", i);
-            relation2.insert(Tuple::from_iter(&[Value::from(i)]));
+            relation2
+                .insert(Tuple::from_iter(&[Value::from(i)]))
+                .unwrap();
         }
 
         let mut query_node =
@@ -732,4 +2580,713 @@ mod join_tests {
             }
         }
     }
+
+    #[test]
+    fn inner_join_mixed_widths() {
+        let mut relation1 = Relation::new_volatile(
+            Identifier::new("test1"),
+            vec![("small", Type::from(0u32))],
+            64,
+            PrimaryKeyDefinition::new(vec![0]),
+        );
+        for i in 0..10u32 {
+            relation1
+                .insert(Tuple::from_iter(&[Value::from(i)]))
+                .unwrap();
+        }
+        let mut relation2 = Relation::new_volatile(
+            Identifier::new("test2"),
+            vec![("large", Type::from(0u64))],
+            64,
+            PrimaryKeyDefinition::new(vec![0]),
+        );
+        for i in 5..15u64 {
+            relation2
+                .insert(Tuple::from_iter(&[Value::from(i)]))
+                .unwrap();
+        }
+
+        let query_node = QueryNode::inner_join(
+            QueryNode::source(&relation1),
+            QueryNode::source(&relation2),
+            JoinCondition::new(Identifier::new("small"), Identifier::new("large")),
+        );
+        let result = query_node.execute_query();
+        let resulting_tuples: Vec<Tuple> = result.tuples().into_iter().collect();
+        assert_eq!(resulting_tuples.len(), 5);
+        for i in 5..10u32 {
+            assert!(resulting_tuples
+                .contains(&Tuple::from_iter(&[Value::from(i), Value::from(i as u64)])));
+        }
+    }
+
+    #[test]
+    fn using_joins() {
+        let mut customers = Relation::new_volatile(
+            Identifier::new("customers"),
+            vec![("customer_id", Type::from(0u64)), ("name", Type::from(""))],
+            64,
+            PrimaryKeyDefinition::new(vec![0]),
+        );
+        for i in 0..5u64 {
+            customers
+                .insert(Tuple::from_iter(&[
+                    Value::from(i),
+                    Value::from(format!("c{}", i)),
+                ]))
+                .unwrap();
+        }
+        let mut orders = Relation::new_volatile(
+            Identifier::new("orders"),
+            vec![
+                ("order_id", Type::from(0u64)),
+                ("customer_id", Type::from(0u64)),
+            ],
+            64,
+            PrimaryKeyDefinition::new(vec![0]),
+        );
+        for (order, customer) in &[(0u64, 2u64), (1, 3), (2, 3), (3, 7)] {
+            orders
+                .insert(Tuple::from_iter(&[
+                    Value::from(*order),
+                    Value::from(*customer),
+                ]))
+                .unwrap();
+        }
+        let fields = |node: &QueryNode| -> Vec<Identifier> {
+            node.resulting_relation()
+                .iter()
+                .map(|(id, _)| id.clone())
+                .collect()
+        };
+        let expected_fields = vec![
+            Identifier::new("customer_id"),
+            Identifier::new("name"),
+            Identifier::new("order_id"),
+        ];
+
+        let inner = QueryNode::inner_join(
+            QueryNode::source(&customers),
+            QueryNode::source(&orders),
+            JoinCondition::using("customer_id"),
+        );
+        assert_eq!(fields(&inner), expected_fields);
+        let tuples: Vec<Tuple> = inner.execute_query().tuples().into_iter().collect();
+        assert_eq!(tuples.len(), 3);
+        assert!(tuples.contains(&Tuple::from_iter(&[
+            Value::from(3u64),
+            Value::from("c3"),
+            Value::from(2u64),
+        ])));
+
+        let full = QueryNode::full_join(
+            QueryNode::source(&customers),
+            QueryNode::source(&orders),
+            JoinCondition::using("customer_id"),
+        );
+        assert_eq!(fields(&full), expected_fields);
+        let tuples: Vec<Tuple> = full.execute_query().tuples().into_iter().collect();
+        assert_eq!(tuples.len(), 7);
+        // the order without a customer still has its customer id
+        assert!(tuples.contains(&Tuple::from_iter(&[
+            Value::from(7u64),
+            Value::Optional(None),
+            Value::from(3u64),
+        ])));
+        assert!(tuples.contains(&Tuple::from_iter(&[
+            Value::from(4u64),
+            Value::from("c4"),
+            Value::Optional(None),
+        ])));
+
+        // the right copy is never padded in a right join
+        let right = QueryNode::right_join(
+            QueryNode::source(&customers),
+            QueryNode::source(&orders),
+            JoinCondition::using("customer_id"),
+        );
+        assert_eq!(right.resulting_relation()[0].1, Type::from(0u64));
+        assert_eq!(right.execute_query().tuples().into_iter().count(), 4);
+    }
+
+    #[test]
+    fn fields_resolve_through_case_policy() {
+        let mut customers = Relation::new_volatile(
+            Identifier::new("customers"),
+            vec![("Customer_Id", Type::from(0u64)), ("Name", Type::from(""))],
+            64,
+            PrimaryKeyDefinition::new(vec![0]),
+        );
+        customers.set_case_policy(CasePolicy::LowercaseFold);
+        let mut orders = Relation::new_volatile(
+            Identifier::new("orders"),
+            vec![
+                ("order_id", Type::from(0u64)),
+                ("customer", Type::from(0u64)),
+            ],
+            64,
+            PrimaryKeyDefinition::new(vec![0]),
+        );
+        for i in 0..5u64 {
+            customers
+                .insert(Tuple::from_iter(&[
+                    Value::from(i),
+                    Value::from(format!("c{}", i)),
+                ]))
+                .unwrap();
+            orders
+                .insert(Tuple::from_iter(&[Value::from(i), Value::from(i % 2)]))
+                .unwrap();
+        }
+
+        let join = QueryNode::inner_join(
+            QueryNode::source(&customers),
+            QueryNode::source(&orders),
+            JoinCondition::new("customer_id".into(), "customer".into()),
+        );
+        let selected = QueryNode::select_eq(join, "CUSTOMER_ID".into(), Operand::UnsignedNumber(1));
+        let projected = QueryNode::projection(selected, vec!["name", "order_id"]);
+        assert_eq!(projected.resulting_relation().len(), 2);
+        let mut tuples: Vec<Tuple> = projected.execute_query().tuples().into_iter().collect();
+        tuples.sort_by_key(|tuple| tuple.to_string());
+        assert_eq!(
+            tuples,
+            vec![
+                Tuple::from_iter(&[Value::from("c1"), Value::from(1u64)]),
+                Tuple::from_iter(&[Value::from("c1"), Value::from(3u64)]),
+            ]
+        );
+
+        // names only match exactly when no relation folds case
+        customers.set_case_policy(CasePolicy::Preserve);
+        let projected = QueryNode::projection(QueryNode::source(&customers), vec!["name"]);
+        assert!(projected.resulting_relation().is_empty());
+    }
+
+    #[test]
+    fn self_join() {
+        let mut employees = Relation::new_volatile(
+            Identifier::new("employees"),
+            vec![("id", Type::from(0u64)), ("manager", Type::from(0u64))],
+            64,
+            PrimaryKeyDefinition::new(vec![0]),
+        );
+        for (id, manager) in &[(1u64, 1u64), (2, 1), (3, 1), (4, 2), (5, 4)] {
+            employees
+                .insert(Tuple::from_iter(&[Value::from(*id), Value::from(*manager)]))
+                .unwrap();
+        }
+        assert_eq!(
+            QueryNode::self_join_aliases(&employees),
+            ("employees_1".to_string(), "employees_2".to_string())
+        );
+
+        let join = QueryNode::self_join(
+            &employees,
+            JoinCondition::new("manager".into(), "id".into()),
+        );
+        let fields: Vec<Identifier> = join
+            .resulting_relation()
+            .iter()
+            .map(|(id, _)| id.clone())
+            .collect();
+        assert_eq!(
+            fields,
+            vec![
+                Identifier::concat("employees_1", "id"),
+                Identifier::concat("employees_1", "manager"),
+                Identifier::concat("employees_2", "id"),
+                Identifier::concat("employees_2", "manager"),
+            ]
+        );
+        let children = join.children();
+        assert!(
+            join.find_node_with_field(Identifier::concat("employees_1", "manager"))
+                == Some(children[0])
+        );
+        assert!(
+            join.find_node_with_field(Identifier::concat("employees_2", "id")) == Some(children[1])
+        );
+        assert!(join.find_node_with_field("id").is_none());
+
+        // the employees whose manager reports to employee 1
+        let query = || {
+            QueryNode::projection(
+                QueryNode::select_eq(
+                    QueryNode::self_join(
+                        &employees,
+                        JoinCondition::new("manager".into(), "id".into()),
+                    ),
+                    Identifier::concat("employees_2", "manager"),
+                    Operand::UnsignedNumber(1),
+                ),
+                vec![Identifier::concat("employees_1", "id")],
+            )
+        };
+        let ids = |node: QueryNode| {
+            let mut ids: Vec<Tuple> = node.execute_query().tuples().into_iter().collect();
+            ids.sort_by_key(|tuple| format!("{:?}", tuple));
+            ids
+        };
+        let expected: Vec<Tuple> = (1..=4u64)
+            .map(|id| Tuple::from_iter(&[Value::from(id)]))
+            .collect();
+        assert_eq!(ids(query()), expected);
+        assert_eq!(ids(query().optimized()), expected);
+    }
+
+    #[test]
+    fn transitive_closure() {
+        let mut edges = Relation::new_volatile(
+            Identifier::new("edges"),
+            vec![("src", Type::from(0u64)), ("dst", Type::from(0u64))],
+            64,
+            PrimaryKeyDefinition::new(vec![0, 1]),
+        );
+        for (src, dst) in &[(1u64, 2u64), (2, 3), (3, 4), (5, 6)] {
+            edges
+                .insert(Tuple::from_iter(&[Value::from(*src), Value::from(*dst)]))
+                .unwrap();
+        }
+
+        let base = QueryNode::source(&edges);
+        let step = QueryNode::projection(
+            QueryNode::inner_join(
+                QueryNode::working_table(&base),
+                QueryNode::source_with_name(&edges, "e".to_string()),
+                JoinCondition::new(Identifier::new("dst"), Identifier::concat("e", "src")),
+            ),
+            vec![Identifier::new("src"), Identifier::concat("e", "dst")],
+        );
+        let query_node = QueryNode::recursive(base, step);
+        let result = query_node.execute_query();
+        let resulting_tuples: Vec<Tuple> = result.tuples().into_iter().collect();
+
+        let expected = [(1u64, 2u64), (2, 3), (3, 4), (5, 6), (1, 3), (2, 4), (1, 4)];
+        assert_eq!(resulting_tuples.len(), expected.len());
+        for (src, dst) in &expected {
+            assert!(resulting_tuples
+                .contains(&Tuple::from_iter(&[Value::from(*src), Value::from(*dst)])));
+        }
+    }
+
+    #[test]
+    fn primary_key_estimates() {
+        let mut orders = Relation::new_volatile(
+            Identifier::new("orders"),
+            vec![
+                ("order_id", Type::from(0u64)),
+                ("line", Type::from(0u64)),
+                ("customer", Type::from(0u64)),
+            ],
+            64,
+            PrimaryKeyDefinition::new(vec![1, 0]),
+        );
+        for i in 0..30u64 {
+            orders
+                .insert(Tuple::from_iter(&[
+                    Value::from(i / 3),
+                    Value::from(i % 3),
+                    Value::from(i % 5),
+                ]))
+                .unwrap();
+        }
+        let mut customers = Relation::new_volatile(
+            Identifier::new("customers"),
+            vec![("customer_id", Type::from(0u64))],
+            64,
+            PrimaryKeyDefinition::new(vec![0]),
+        );
+        for i in 0..1000u64 {
+            customers
+                .insert(Tuple::from_iter(&[Value::from(i)]))
+                .unwrap();
+        }
+
+        let by_order = QueryNode::select_eq(
+            QueryNode::source(&orders),
+            Identifier::new("order_id"),
+            Operand::UnsignedNumber(4),
+        );
+        assert_eq!(by_order.unique_keys(), vec![vec![Identifier::new("line")]]);
+        let by_line = QueryNode::select_eq(
+            by_order,
+            Identifier::new("line"),
+            Operand::UnsignedNumber(1),
+        );
+        assert_eq!(by_line.unique_keys(), vec![Vec::<Identifier>::new()]);
+        assert!(by_line.approximate_created_tuples() <= 1);
+
+        let join = QueryNode::inner_join(
+            QueryNode::source(&orders),
+            QueryNode::source(&customers),
+            JoinCondition::new(Identifier::new("customer"), Identifier::new("customer_id")),
+        );
+        assert_eq!(join.approximate_created_tuples(), 30);
+        assert_eq!(
+            join.unique_keys(),
+            vec![vec![Identifier::new("line"), Identifier::new("order_id")]]
+        );
+    }
+
+    #[test]
+    fn statistics_estimates() {
+        let mut orders = Relation::new_volatile(
+            Identifier::new("orders"),
+            vec![
+                ("order_id", Type::from(0u64)),
+                ("customer", Type::from(0u64)),
+            ],
+            64,
+            PrimaryKeyDefinition::new(vec![0]),
+        );
+        for i in 0..100u64 {
+            orders
+                .insert(Tuple::from_iter(&[Value::from(i), Value::from(i % 4)]))
+                .unwrap();
+        }
+        fn by_customer(orders: &Relation) -> QueryNode<'_> {
+            QueryNode::select_eq(
+                QueryNode::source(orders),
+                Identifier::new("customer"),
+                Operand::UnsignedNumber(1),
+            )
+        }
+        assert!(by_customer(&orders).approximate_created_tuples() <= 1);
+        orders.analyze();
+        assert_eq!(by_customer(&orders).approximate_created_tuples(), 25);
+
+        let listed = |customers: Vec<u64>| {
+            QueryNode::select_on_condition(
+                QueryNode::source(&orders),
+                Condition::new(
+                    "customer",
+                    ConditionOperation::In(
+                        customers.into_iter().map(Operand::UnsignedNumber).collect(),
+                    ),
+                ),
+            )
+            .approximate_created_tuples()
+        };
+        assert_eq!(listed(vec![1, 2]), 50);
+        // listing more values than there are distinct values can't keep more than every tuple
+        assert_eq!(listed((0..10).collect()), 100);
+
+        let ranged = QueryNode::select_on_condition(
+            QueryNode::source(&orders),
+            Condition::range(
+                "order_id",
+                Bound::Included(Operand::UnsignedNumber(10)),
+                Bound::Excluded(Operand::UnsignedNumber(30)),
+            ),
+        );
+        let estimate = ranged.approximate_created_tuples();
+        assert!((17..=23).contains(&estimate), "estimated {}", estimate);
+    }
+
+    #[test]
+    fn fields_set_how_their_text_is_compared() {
+        let mut codes = Relation::new_volatile(
+            Identifier::new("codes"),
+            vec![("id", Type::from(0u64)), ("code", Type::from(""))],
+            16,
+            PrimaryKeyDefinition::new(vec![0]),
+        );
+        for (id, code) in [(0u64, "abc  "), (1, "ABC"), (2, "abd")] {
+            codes
+                .insert(Tuple::from_iter(&[Value::from(id), Value::from(code)]))
+                .unwrap();
+        }
+        codes.set_text_comparison(1, TextComparison::CaseInsensitiveTrimmed);
+        let equal_to_abc = |condition: Condition| {
+            QueryNode::select_on_condition(QueryNode::source(&codes), condition)
+                .execute_query()
+                .into_iter()
+                .count()
+        };
+        let abc = || {
+            Condition::new(
+                "code",
+                ConditionOperation::Equals(Operand::String("abc".to_string())),
+            )
+        };
+        assert_eq!(equal_to_abc(abc()), 2);
+        // a condition that sets how it compares text overrides its field
+        assert_eq!(
+            equal_to_abc(abc().with_text_comparison(TextComparison::Exact)),
+            0
+        );
+    }
+
+    #[test]
+    fn historical_source() {
+        use rad_db_structure::history::RetentionPolicy;
+        use std::time::{Duration, SystemTime};
+
+        let mut prices = Relation::new_volatile(
+            Identifier::new("prices"),
+            vec![("item", Type::from(0u64)), ("price", Type::from(0u64))],
+            64,
+            PrimaryKeyDefinition::new(vec![0]),
+        );
+        prices.retain_history(RetentionPolicy::Forever);
+        for i in 0..5u64 {
+            prices
+                .insert(Tuple::from_iter(&[Value::from(i), Value::from(10u64)]))
+                .unwrap();
+        }
+        std::thread::sleep(Duration::from_millis(5));
+        let before = SystemTime::now();
+        std::thread::sleep(Duration::from_millis(5));
+        prices
+            .insert(Tuple::from_iter(&[Value::from(0u64), Value::from(20u64)]))
+            .unwrap();
+        prices
+            .insert(Tuple::from_iter(&[Value::from(5u64), Value::from(20u64)]))
+            .unwrap();
+
+        let then: Vec<Tuple> = QueryNode::source_as_of(&prices, before)
+            .unwrap()
+            .execute_query()
+            .tuples()
+            .into_iter()
+            .collect();
+        assert_eq!(then.len(), 5);
+        assert!(then.contains(&Tuple::from_iter(&[Value::from(0u64), Value::from(10u64)])));
+
+        let now: Vec<Tuple> = QueryNode::source_as_of(&prices, SystemTime::now())
+            .unwrap()
+            .execute_query()
+            .tuples()
+            .into_iter()
+            .collect();
+        assert_eq!(now.len(), 6);
+        assert!(now.contains(&Tuple::from_iter(&[Value::from(0u64), Value::from(20u64)])));
+        assert!(!now.contains(&Tuple::from_iter(&[Value::from(0u64), Value::from(10u64)])));
+
+        let forgetful = Relation::new_volatile(
+            Identifier::new("forgetful"),
+            vec![("item", Type::from(0u64))],
+            64,
+            PrimaryKeyDefinition::new(vec![0]),
+        );
+        assert!(QueryNode::source_as_of(&forgetful, before).is_none());
+    }
+
+    #[test]
+    fn batched_execution() {
+        let mut relation = Relation::new_volatile(
+            Identifier::new("numbers"),
+            vec![("n", Type::from(0u64)), ("parity", Type::from(0u64))],
+            64,
+            PrimaryKeyDefinition::new(vec![0]),
+        );
+        for i in 0..100u64 {
+            relation
+                .insert(Tuple::from_iter(&[Value::from(i), Value::from(i % 2)]))
+                .unwrap();
+        }
+        let query = || {
+            QueryNode::projection(
+                QueryNode::select_eq(
+                    QueryNode::source(&relation),
+                    Identifier::new("parity"),
+                    Operand::UnsignedNumber(1),
+                ),
+                vec![Identifier::new("n")],
+            )
+        };
+        let mut expected: Vec<Tuple> = query().execute_query().tuples().into_iter().collect();
+        let mut batched: Vec<Tuple> = query().execute_batched(16).tuples().into_iter().collect();
+        assert_eq!(batched.len(), 50);
+        expected.sort_by_key(|tuple| format!("{:?}", tuple));
+        batched.sort_by_key(|tuple| format!("{:?}", tuple));
+        assert_eq!(batched, expected);
+
+        let mut settings = Settings::session();
+        settings.execute("SET execution_batch_size = 16").unwrap();
+        let mut session: Vec<Tuple> = query()
+            .execute_with_settings(&settings)
+            .tuples()
+            .into_iter()
+            .collect();
+        session.sort_by_key(|tuple| format!("{:?}", tuple));
+        assert_eq!(session, expected);
+    }
+
+    #[test]
+    fn key_range() {
+        let mut readings = Relation::new_volatile(
+            Identifier::new("readings"),
+            vec![
+                ("sensor", Type::from(0u8)),
+                ("time", Type::from(0u64)),
+                ("value", Type::from(0u64)),
+            ],
+            8,
+            PrimaryKeyDefinition::new(vec![0, 1]),
+        );
+        for sensor in 0..3u8 {
+            for time in 0..10u64 {
+                readings
+                    .insert(Tuple::from_iter(&[
+                        Value::from(sensor),
+                        Value::from(time),
+                        Value::from(time * 10),
+                    ]))
+                    .unwrap();
+            }
+        }
+
+        let query = QueryNode::key_range(
+            &readings,
+            Bound::Included(vec![Value::from(1u64), Value::from(5u64)]),
+            Bound::Excluded(vec![Value::from(2u64)]),
+        );
+        assert_eq!(query.approximate_created_tuples(), 5);
+        assert_eq!(
+            query.ordered_on(),
+            vec![Identifier::new("sensor"), Identifier::new("time")]
+        );
+        assert!(query
+            .explain()
+            .starts_with("KeyRange readings >= (1, 5) < (2)"));
+        let times: Vec<Value> = query
+            .execute_query()
+            .tuples()
+            .into_iter()
+            .map(|tuple| tuple[1].clone())
+            .collect();
+        assert_eq!(times, (5..10u64).map(Value::from).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn validation() {
+        let customers = Relation::new_volatile(
+            Identifier::new("customers"),
+            vec![("customer_id", Type::from(0u64)), ("name", Type::from(""))],
+            64,
+            PrimaryKeyDefinition::new(vec![0]),
+        );
+        let orders = Relation::new_volatile(
+            Identifier::new("orders"),
+            vec![("order_id", Type::from(0u64)), ("buyer", Type::from(0u64))],
+            64,
+            PrimaryKeyDefinition::new(vec![0]),
+        );
+        let join = |condition: JoinCondition| {
+            QueryNode::inner_join(
+                QueryNode::source(&customers),
+                QueryNode::source(&orders),
+                condition,
+            )
+        };
+        let valid = QueryNode::projection(
+            join(JoinCondition::new("customer_id".into(), "buyer".into())),
+            vec![Identifier::new("name"), Identifier::new("order_id")],
+        );
+        assert!(valid.validate().is_ok());
+
+        let error = QueryNode::select_eq(
+            join(JoinCondition::new("customer_id".into(), "buyer".into())),
+            Identifier::new("nmae"),
+            Operand::String("a".to_string()),
+        )
+        .validate()
+        .unwrap_err();
+        assert_eq!(error.field(), &Identifier::new("nmae"));
+        assert_eq!(error.suggestion(), Some(&Identifier::new("name")));
+
+        // the join field is looked for in the child it belongs to
+        let condition = JoinCondition::new("customer_id".into(), "customer_id".into());
+        let error = join(condition).validate().unwrap_err();
+        assert_eq!(error.field(), &Identifier::new("customer_id"));
+        assert_eq!(error.suggestion(), None);
+
+        // missing fields are found below other nodes, and fields under another alias are
+        // suggested
+        let error = QueryNode::projection(
+            QueryNode::select_eq(
+                QueryNode::source_with_name(&customers, "c".to_string()),
+                Identifier::new("name"),
+                Operand::String("a".to_string()),
+            ),
+            vec![Identifier::new("wrong")],
+        )
+        .validate()
+        .unwrap_err();
+        assert_eq!(error.field(), &Identifier::new("name"));
+        assert_eq!(error.suggestion(), Some(&Identifier::concat("c", "name")));
+    }
+
+    #[test]
+    fn foreign_relations_join_local_ones() {
+        use rad_db_structure::relations::foreign::{Connector, TextRelation};
+        use std::io::{BufRead, Cursor};
+
+        let mut customers = Relation::new_volatile(
+            Identifier::new("customers"),
+            vec![("customer_id", Type::from(0u64)), ("name", Type::from(""))],
+            64,
+            PrimaryKeyDefinition::new(vec![0]),
+        );
+        for (id, name) in [(1u64, "ana"), (2, "bo"), (3, "cy")] {
+            customers
+                .insert(Tuple::from_iter(&[Value::from(id), Value::from(name)]))
+                .unwrap();
+        }
+        let connect: Connector = Box::new(|| {
+            let sent = "10|1|5\n11|3|7\n12|1|2\n".as_bytes().to_vec();
+            Ok(Box::new(Cursor::new(sent)) as Box<dyn BufRead>)
+        });
+        let orders = TextRelation::new(
+            "orders",
+            vec![
+                ("order_id", Type::from(0u64)),
+                ("buyer", Type::from(0u64)),
+                ("quantity", Type::from(0u32)),
+            ],
+            connect,
+        );
+
+        let query = QueryNode::projection(
+            QueryNode::inner_join(
+                QueryNode::source(&customers),
+                QueryNode::foreign_source(&orders),
+                JoinCondition::new("customer_id".into(), "buyer".into()),
+            ),
+            vec![Identifier::new("name"), Identifier::new("order_id")],
+        );
+        let names = |query: QueryNode| {
+            let mut rows: Vec<String> = query
+                .execute_query()
+                .into_iter()
+                .map(|tuple| tuple.to_string())
+                .collect();
+            rows.sort();
+            rows
+        };
+        let optimized = query.clone().optimized();
+        assert!(optimized.explain().contains("ForeignScan orders"));
+        let expected = vec![r#""ana"|10"#, r#""ana"|12"#, r#""cy"|11"#];
+        assert_eq!(names(query), expected);
+        assert_eq!(names(optimized), expected);
+    }
+
+    #[test]
+    fn unreadable_foreign_relations_fail_the_query() {
+        use rad_db_structure::relations::foreign::{ForeignError, TextRelation};
+
+        let missing = TextRelation::csv(
+            "missing",
+            vec![("id", Type::from(0u64))],
+            "DB_STORAGE/no_such_file.csv",
+        );
+        let result = QueryNode::foreign_source(&missing).execute_query();
+        assert!(matches!(
+            result.error(),
+            Some(QueryError::Foreign(ForeignError::Io(_)))
+        ));
+        assert_eq!(result.into_iter().count(), 0);
+    }
 }
@@ -1,3 +1,4 @@
+use crate::error::QueryError;
 use crate::query::query_iterator::{QueryIterator, ReferencedQueryIterator};
 use crate::query::query_node::Source;
 use crate::query::Repeatable;
@@ -5,8 +6,12 @@ use rad_db_structure::identifier::Identifier;
 use rad_db_structure::relations::tuple_storage::BlockIterator;
 use rad_db_structure::relations::RelationDefinition;
 use rad_db_structure::tuple::Tuple;
+use rad_db_types::json::{json_string, json_value};
+use rad_db_types::serialization::{serialize_values_to_with, TextFormat};
 use rad_db_types::{Type, Value};
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
+use std::io;
+use std::io::Write;
 use std::iter::FromIterator;
 use std::marker::PhantomData;
 
@@ -16,7 +21,7 @@ pub enum QueryResultFullData<'a> {
 }
 
 pub enum QueryResultBlocks<'a> {
-    Blocks(Vec<Vec<Tuple>>),
+    Blocks(VecDeque<Vec<Tuple>>),
     Source(Source<'a>),
 }
 
@@ -24,6 +29,7 @@ pub struct QueryResult<'a> {
     relation: Vec<(Identifier, Type)>,
     internal: QueryResultFullData<'a>,
     total_created_tuples: usize,
+    error: Option<QueryError>,
 }
 const ITEMS_PER_BLOCK: usize = 16;
 impl<'a> QueryResult<'a> {
@@ -38,6 +44,7 @@ impl<'a> QueryResult<'a> {
             relation,
             internal: QueryResultFullData::Tuples(vec),
             total_created_tuples: len + extra,
+            error: None,
         }
     }
 
@@ -47,9 +54,21 @@ impl<'a> QueryResult<'a> {
             relation,
             internal: QueryResultFullData::BlockData(QueryResultBlocks::Source(source)),
             total_created_tuples: len,
+            error: None,
         }
     }
 
+    pub(crate) fn with_error(mut self, error: Option<QueryError>) -> Self {
+        self.error = error;
+        self
+    }
+
+    /// Why the query failed, such as a foreign relation it reads that couldn't be read, in which
+    /// case the result only holds what was produced before the failure
+    pub fn error(&self) -> Option<&QueryError> {
+        self.error.as_ref()
+    }
+
     pub fn relation(&self) -> &Vec<(Identifier, Type)> {
         &self.relation
     }
@@ -59,8 +78,9 @@ impl<'a> QueryResult<'a> {
         self.internal
     }
 
-    /// Attempts to get an iterator of tuples without consuming itself
-    pub fn repeatable_tuples(&mut self) -> impl Iterator<Item = Tuple> {
+    /// Loads every tuple of the result into memory, so that they can be borrowed instead of
+    /// copied every time they're read
+    pub fn materialized(&mut self) -> &[Tuple] {
         if let QueryResultFullData::BlockData(_) = &self.internal {
             let old = std::mem::replace(&mut self.internal, QueryResultFullData::Tuples(vec![]));
 
@@ -74,29 +94,34 @@ impl<'a> QueryResult<'a> {
         }
 
         if let QueryResultFullData::Tuples(vector) = &self.internal {
-            vector.clone().into_iter()
+            vector
         } else {
             unreachable!()
         }
     }
 
+    /// Attempts to get an iterator of tuples without consuming itself
+    pub fn repeatable_tuples(&mut self) -> impl Iterator<Item = Tuple> {
+        self.materialized().to_vec().into_iter()
+    }
+
     /// Converts the result into an iterator of blocks of tuples
     pub fn blocks(self) -> QueryResultBlocks<'a> {
         match self.internal {
             QueryResultFullData::Tuples(s) => {
-                let mut ret = Vec::new();
+                let mut ret = VecDeque::new();
                 let mut current = Vec::new();
 
                 let mut iterator = s.into_iter();
                 while let Some(tuple) = iterator.next() {
                     current.push(tuple.clone());
                     if current.len() >= ITEMS_PER_BLOCK {
-                        ret.push(current);
+                        ret.push_back(current);
                         current = vec![];
                     }
                 }
                 if !current.is_empty() {
-                    ret.push(current);
+                    ret.push_back(current);
                 }
                 QueryResultBlocks::Blocks(ret)
             }
@@ -140,6 +165,50 @@ impl<'a> QueryResult<'a> {
     pub fn total_created_tuples(&self) -> usize {
         self.total_created_tuples
     }
+
+    /// Writes the result as CSV, with a header naming the fields. Tuples are written as they're
+    /// read, so the result is never loaded into memory all at once.
+    pub fn write_csv<W: Write>(self, mut writer: W) -> io::Result<()> {
+        let format = TextFormat::new(',', '"', "NULL");
+        let names: Vec<Type> = self
+            .relation
+            .iter()
+            .map(|(id, _)| Type::from(id.to_string()))
+            .collect();
+        serialize_values_to_with(names, &format, &mut writer)?;
+        writeln!(writer)?;
+        for tuple in self {
+            serialize_values_to_with(tuple, &format, &mut writer)?;
+            writeln!(writer)?;
+        }
+        writer.flush()
+    }
+
+    /// Writes the result as a JSON array with an object for each tuple, keyed by the names of
+    /// the fields. Like [write_csv](QueryResult::write_csv), tuples are written as they're read.
+    pub fn write_json<W: Write>(self, mut writer: W) -> io::Result<()> {
+        let keys: Vec<String> = self
+            .relation
+            .iter()
+            .map(|(id, _)| json_string(&id.to_string()))
+            .collect();
+        write!(writer, "[")?;
+        for (index, tuple) in self.into_iter().enumerate() {
+            if index > 0 {
+                write!(writer, ",")?;
+            }
+            write!(writer, "\n{{")?;
+            for (field, (key, value)) in keys.iter().zip(tuple.iter()).enumerate() {
+                if field > 0 {
+                    write!(writer, ",")?;
+                }
+                write!(writer, "{}:{}", key, json_value(value))?;
+            }
+            write!(writer, "}}")?;
+        }
+        writeln!(writer, "\n]")?;
+        writer.flush()
+    }
 }
 
 impl<'a> Iterator for QueryResultBlocks<'a> {
@@ -147,7 +216,7 @@ impl<'a> Iterator for QueryResultBlocks<'a> {
 
     fn next(&mut self) -> Option<Self::Item> {
         match self {
-            QueryResultBlocks::Blocks(blocks) => blocks.pop(),
+            QueryResultBlocks::Blocks(blocks) => blocks.pop_front(),
             QueryResultBlocks::Source(source) => source.next(),
         }
     }
@@ -179,3 +248,50 @@ impl<'a> IntoIterator for &'a QueryResult<'a> {
         ReferencedQueryIterator::new(&self.internal)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn people() -> QueryResult<'static> {
+        QueryResult::with_tuples(
+            vec![
+                (Identifier::new("id"), Type::from(0u32)),
+                (Identifier::new("name"), Type::from("")),
+                (
+                    Identifier::new("manager"),
+                    Type::Optional(Some(Box::new(Type::from(0u32)))),
+                ),
+            ],
+            vec![
+                Tuple::from_iter(&[1u32.into(), "Ada, \"A\"".into(), Type::Optional(None)]),
+                Tuple::from_iter(&[2u32.into(), "Bob".into(), 1u32.into()]),
+            ],
+            0,
+        )
+    }
+
+    #[test]
+    fn export() {
+        let mut csv = vec![];
+        people().write_csv(&mut csv).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "\"id\",\"name\",\"manager\"\n1,\"Ada, \\\"A\\\"\",NULL\n2,\"Bob\",1\n"
+        );
+
+        let mut json = vec![];
+        people().write_json(&mut json).unwrap();
+        assert_eq!(
+            String::from_utf8(json).unwrap(),
+            "[\n{\"id\":1,\"name\":\"Ada, \\\"A\\\"\",\"manager\":null},\
+             \n{\"id\":2,\"name\":\"Bob\",\"manager\":1}\n]\n"
+        );
+
+        let mut empty = vec![];
+        QueryResult::with_tuples(vec![], vec![], 0)
+            .write_json(&mut empty)
+            .unwrap();
+        assert_eq!(empty, b"[\n]\n");
+    }
+}
@@ -0,0 +1,232 @@
+//! The rewrite rules the optimizer applies to queries.
+//!
+//! Every rule finds the nodes it [matches](Rule::matches) and [applies](Rule::apply) itself to
+//! them. A [RuleSet] applies its rules in order, over and over, until none of them changes the
+//! query anymore, and keeps statistics on how often each rule changed it. Rules can be added to a
+//! set or disabled in it, so that a rule can be tested on its own.
+
+use crate::query::cost::CostModel;
+use crate::query::optimization::{
+    ChooseAccessPaths, ChooseCoveringIndexes, ChooseJoinStrategies, CountFromMetadata,
+    FoldConditions, MergeLimitsIntoSorts, PruneUnusedFields, RemoveContradictions,
+    RemoveSatisfiedSorts, RewriteNegatedSubqueries, SplitAnds,
+};
+use crate::query::query_node::QueryNode;
+use std::time::{Duration, Instant};
+
+/// The order a rule visits the nodes of a query in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Traversal {
+    /// Every node is visited before its children, which are the children it has after the rule
+    /// was applied to it
+    TopDown,
+    /// Every node is visited after its children
+    BottomUp,
+    /// Only the root of the query is visited, for rules that rewrite the whole query at once
+    Root,
+}
+
+/// A rewrite of a query that keeps the tuples it produces the same
+pub trait Rule {
+    /// The name the rule is disabled by and its statistics are kept under
+    fn name(&self) -> &str;
+
+    /// The order the rule visits the nodes of a query in
+    fn traversal(&self) -> Traversal;
+
+    /// Whether the rule might change the node
+    fn matches(&self, node: &QueryNode<'_>) -> bool;
+
+    /// Rewrites a node the rule matches, and gives whether anything changed
+    fn apply(&self, node: &mut QueryNode<'_>) -> bool;
+
+    /// Applies the rule to every node of the query it matches, and gives how many of them were
+    /// changed
+    fn rewrite(&self, query: &mut QueryNode<'_>) -> usize {
+        match self.traversal() {
+            Traversal::TopDown => {
+                let mut changed = (self.matches(query) && self.apply(query)) as usize;
+                for child in query.children_mut_list() {
+                    changed += self.rewrite(child);
+                }
+                changed
+            }
+            Traversal::BottomUp => {
+                let mut changed = 0;
+                for child in query.children_mut_list() {
+                    changed += self.rewrite(child);
+                }
+                changed + (self.matches(query) && self.apply(query)) as usize
+            }
+            Traversal::Root => (self.matches(query) && self.apply(query)) as usize,
+        }
+    }
+}
+
+/// How often a rule changed the queries it was applied to, and how long it took
+#[derive(Debug, Clone, PartialEq)]
+pub struct RuleStatistics {
+    name: String,
+    applications: usize,
+    time: Duration,
+}
+
+impl RuleStatistics {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The number of nodes the rule changed
+    pub fn applications(&self) -> usize {
+        self.applications
+    }
+
+    /// The time spent applying the rule, including to nodes it didn't change
+    pub fn time(&self) -> Duration {
+        self.time
+    }
+}
+
+/// An ordered set of rules, applied to a query until it stops changing
+pub struct RuleSet {
+    rules: Vec<(Box<dyn Rule>, bool)>,
+    max_iterations: usize,
+}
+
+impl RuleSet {
+    /// The default number of times the rules are applied before the query is assumed to have
+    /// stopped changing
+    pub const DEFAULT_MAX_ITERATIONS: usize = 8;
+
+    /// Creates a set without any rules
+    pub fn new() -> Self {
+        RuleSet {
+            rules: vec![],
+            max_iterations: Self::DEFAULT_MAX_ITERATIONS,
+        }
+    }
+
+    /// Adds a rule, which is applied after every rule already in the set
+    pub fn push<R: Rule + 'static>(&mut self, rule: R) {
+        self.rules.push((Box::new(rule), true));
+    }
+
+    pub fn with<R: Rule + 'static>(mut self, rule: R) -> Self {
+        self.push(rule);
+        self
+    }
+
+    /// Adds a rule right after the rule with the name, returning whether there is one
+    pub fn insert_after<R: Rule + 'static>(&mut self, name: &str, rule: R) -> bool {
+        match self.rules.iter().position(|(rule, _)| rule.name() == name) {
+            Some(position) => {
+                self.rules.insert(position + 1, (Box::new(rule), true));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Stops applying the rule with the name, returning whether there is one
+    pub fn disable(&mut self, name: &str) -> bool {
+        self.set_enabled(name, false)
+    }
+
+    /// Applies the rule with the name again after it was disabled, returning whether there is one
+    pub fn enable(&mut self, name: &str) -> bool {
+        self.set_enabled(name, true)
+    }
+
+    fn set_enabled(&mut self, name: &str, enabled: bool) -> bool {
+        let mut found = false;
+        for (rule, rule_enabled) in &mut self.rules {
+            if rule.name() == name {
+                *rule_enabled = enabled;
+                found = true;
+            }
+        }
+        found
+    }
+
+    pub fn without(mut self, name: &str) -> Self {
+        self.disable(name);
+        self
+    }
+
+    /// The names of the enabled rules, in the order they're applied in
+    pub fn names(&self) -> Vec<&str> {
+        self.rules
+            .iter()
+            .filter(|(_, enabled)| *enabled)
+            .map(|(rule, _)| rule.name())
+            .collect()
+    }
+
+    pub fn max_iterations(&self) -> usize {
+        self.max_iterations
+    }
+
+    /// Sets how many times the rules are applied at most, in case they keep changing the query
+    pub fn set_max_iterations(&mut self, max_iterations: usize) {
+        self.max_iterations = max_iterations;
+    }
+
+    /// Applies the enabled rules in order until none of them changes the query, or they were
+    /// applied [max_iterations](Self::max_iterations) times, and gives the statistics of every
+    /// enabled rule. The resulting relations of the nodes are recalculated every time the query
+    /// changes.
+    pub fn apply(&self, query: &mut QueryNode<'_>) -> Vec<RuleStatistics> {
+        let mut statistics: Vec<RuleStatistics> = self
+            .rules
+            .iter()
+            .filter(|(_, enabled)| *enabled)
+            .map(|(rule, _)| RuleStatistics {
+                name: rule.name().to_string(),
+                applications: 0,
+                time: Duration::default(),
+            })
+            .collect();
+
+        for _ in 0..self.max_iterations {
+            let mut changed = 0;
+            let rules = self.rules.iter().filter(|(_, enabled)| *enabled);
+            for ((rule, _), statistics) in rules.zip(&mut statistics) {
+                let start = Instant::now();
+                let applications = rule.rewrite(query);
+                statistics.time += start.elapsed();
+                statistics.applications += applications;
+                changed += applications;
+            }
+            if changed == 0 {
+                break;
+            }
+            query.recalculate_resulting_relation();
+        }
+        statistics
+    }
+}
+
+impl RuleSet {
+    /// The default rules, which choose access paths and join strategies by the costs
+    pub fn with_costs(costs: CostModel) -> Self {
+        RuleSet::new()
+            .with(SplitAnds)
+            .with(FoldConditions)
+            .with(RemoveContradictions)
+            .with(RewriteNegatedSubqueries)
+            .with(MergeLimitsIntoSorts)
+            .with(ChooseJoinStrategies(costs))
+            .with(CountFromMetadata)
+            .with(ChooseAccessPaths(costs))
+            .with(RemoveSatisfiedSorts)
+            .with(PruneUnusedFields)
+            .with(ChooseCoveringIndexes)
+    }
+}
+
+impl Default for RuleSet {
+    /// The rules the optimizer uses unless it's given others
+    fn default() -> Self {
+        RuleSet::with_costs(CostModel::default())
+    }
+}
@@ -0,0 +1,136 @@
+//! Reading random subsets of tuples
+
+use rad_db_structure::tuple::Tuple;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+/// How much of the input a sample keeps
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SampleSize {
+    /// Every tuple is kept with this probability, so roughly this fraction of the input is kept
+    Fraction(f64),
+    /// Exactly this many tuples are kept, or all of them if there are fewer
+    Rows(usize),
+}
+
+/// A random subset of tuples. The same seed always picks the same tuples from the same input.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sample {
+    size: SampleSize,
+    seed: u64,
+}
+
+impl Sample {
+    /// Keeps each tuple with a probability of `fraction`
+    ///
+    /// # Panic
+    /// Panics if the fraction isn't between 0 and 1.
+    pub fn fraction(fraction: f64, seed: u64) -> Self {
+        if !(0.0..=1.0).contains(&fraction) {
+            panic!("Sample fraction must be between 0 and 1, was {}", fraction)
+        }
+        Sample {
+            size: SampleSize::Fraction(fraction),
+            seed,
+        }
+    }
+
+    /// Keeps `rows` tuples, chosen uniformly at random
+    pub fn rows(rows: usize, seed: u64) -> Self {
+        Sample {
+            size: SampleSize::Rows(rows),
+            seed,
+        }
+    }
+
+    pub fn size(&self) -> SampleSize {
+        self.size
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// The approximate number of tuples kept from an input of this size
+    pub fn approximate_len(&self, input: usize) -> usize {
+        match self.size {
+            SampleSize::Fraction(fraction) => (input as f64 * fraction).ceil() as usize,
+            SampleSize::Rows(rows) => rows.min(input),
+        }
+    }
+
+    pub(crate) fn execute<I: IntoIterator<Item = Tuple>>(&self, tuples: I) -> Vec<Tuple> {
+        let mut rng = StdRng::seed_from_u64(self.seed);
+        match self.size {
+            SampleSize::Fraction(fraction) => bernoulli(tuples, fraction, &mut rng),
+            SampleSize::Rows(rows) => reservoir(tuples, rows, &mut rng),
+        }
+    }
+}
+
+/// Keeps each item with a probability of `fraction`, without knowing how many items there are
+pub fn bernoulli<T, I, R>(items: I, fraction: f64, rng: &mut R) -> Vec<T>
+where
+    I: IntoIterator<Item = T>,
+    R: Rng + ?Sized,
+{
+    items
+        .into_iter()
+        .filter(|_| rng.gen_bool(fraction))
+        .collect()
+}
+
+/// Keeps `count` items chosen uniformly at random in a single pass, using reservoir sampling
+pub fn reservoir<T, I, R>(items: I, count: usize, rng: &mut R) -> Vec<T>
+where
+    I: IntoIterator<Item = T>,
+    R: Rng + ?Sized,
+{
+    let mut reservoir = Vec::with_capacity(count);
+    if count == 0 {
+        return reservoir;
+    }
+    for (seen, item) in items.into_iter().enumerate() {
+        if seen < count {
+            reservoir.push(item);
+        } else {
+            let index = rng.gen_range(0..=seen);
+            if index < count {
+                reservoir[index] = item;
+            }
+        }
+    }
+    reservoir
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rad_db_types::Value;
+
+    fn tuples(count: u64) -> Vec<Tuple> {
+        (0..count)
+            .map(|i| Tuple::new(vec![Value::from(i)]))
+            .collect()
+    }
+
+    #[test]
+    fn rows() {
+        let sample = Sample::rows(10, 7);
+        let sampled = sample.execute(tuples(1000));
+        assert_eq!(sampled.len(), 10);
+        assert_eq!(sampled, sample.execute(tuples(1000)));
+        assert_ne!(sampled, Sample::rows(10, 8).execute(tuples(1000)));
+        assert_eq!(sample.execute(tuples(4)).len(), 4);
+    }
+
+    #[test]
+    fn fraction() {
+        let sample = Sample::fraction(0.25, 3);
+        let sampled = sample.execute(tuples(4000));
+        assert!(sampled.len() > 800 && sampled.len() < 1200);
+        assert_eq!(sampled, sample.execute(tuples(4000)));
+        assert!(Sample::fraction(0.0, 3).execute(tuples(100)).is_empty());
+        assert_eq!(Sample::fraction(1.0, 3).execute(tuples(100)).len(), 100);
+    }
+}
@@ -0,0 +1,393 @@
+//! Ordering tuples by their fields
+
+use rad_db_structure::identifier::Identifier;
+use rad_db_structure::memory::{tuple_size, MemoryBudget};
+use rad_db_structure::spill::SpillFile;
+use rad_db_structure::tuple::Tuple;
+use rad_db_types::{Type, Value};
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+
+/// A field to order by, the direction to order it in, and where its `NULL` values are placed
+#[derive(Debug, Clone, PartialEq)]
+pub struct SortKey {
+    field: Identifier,
+    ascending: bool,
+    nulls_first: bool,
+}
+
+impl SortKey {
+    /// Orders by the field in ascending order, with `NULL` values last
+    pub fn ascending<I: Into<Identifier>>(field: I) -> Self {
+        SortKey {
+            field: field.into(),
+            ascending: true,
+            nulls_first: false,
+        }
+    }
+
+    /// Orders by the field in descending order, with `NULL` values last
+    pub fn descending<I: Into<Identifier>>(field: I) -> Self {
+        SortKey {
+            field: field.into(),
+            ascending: false,
+            nulls_first: false,
+        }
+    }
+
+    /// Places `NULL` values before every other value, like `NULLS FIRST`
+    pub fn nulls_first(mut self) -> Self {
+        self.nulls_first = true;
+        self
+    }
+
+    /// Places `NULL` values after every other value, like `NULLS LAST`
+    pub fn nulls_last(mut self) -> Self {
+        self.nulls_first = false;
+        self
+    }
+
+    pub fn field(&self) -> &Identifier {
+        &self.field
+    }
+
+    pub fn is_ascending(&self) -> bool {
+        self.ascending
+    }
+
+    pub fn places_nulls_first(&self) -> bool {
+        self.nulls_first
+    }
+
+    /// Compares two values in the direction of this key. `NULL` values are placed first or last
+    /// regardless of the direction, and values that can't be ordered are treated as equal.
+    fn compare(&self, left: &Value, right: &Value) -> Ordering {
+        let null = if self.nulls_first {
+            Ordering::Less
+        } else {
+            Ordering::Greater
+        };
+        match (left.is_null(), right.is_null()) {
+            (true, true) => Ordering::Equal,
+            (true, false) => null,
+            (false, true) => null.reverse(),
+            (false, false) => {
+                let ordering = left.compare(right).unwrap_or(Ordering::Equal);
+                if self.ascending {
+                    ordering
+                } else {
+                    ordering.reverse()
+                }
+            }
+        }
+    }
+}
+
+/// Tuples a sort has already sorted
+enum SortedRun {
+    InMemory(Vec<Tuple>),
+    Spilled(SpillFile),
+}
+
+/// Compares tuples using a list of keys, where later keys are only used to break ties
+pub(crate) struct TupleComparator {
+    keys: Vec<(usize, SortKey)>,
+}
+
+impl TupleComparator {
+    /// Creates a comparator, where the mappings give the index of each field within the tuples
+    pub(crate) fn new(keys: &[SortKey], mappings: &HashMap<Identifier, usize>) -> Self {
+        let keys = keys
+            .iter()
+            .map(|key| match mappings.get(key.field()) {
+                Some(index) => (*index, key.clone()),
+                None => panic!("No field named {} to sort by", key.field()),
+            })
+            .collect();
+        TupleComparator { keys }
+    }
+
+    pub(crate) fn compare(&self, left: &Tuple, right: &Tuple) -> Ordering {
+        for (index, key) in &self.keys {
+            match key.compare(&left[*index], &right[*index]) {
+                Ordering::Equal => continue,
+                ordering => return ordering,
+            }
+        }
+        Ordering::Equal
+    }
+
+    /// Sorts all of the tuples, whose fields are of the types. The sort is stable.
+    ///
+    /// The tuples being sorted are counted against the memory budget. Whenever the next tuple
+    /// doesn't fit, the tuples read so far are sorted and spilled to disk as a run, and the runs
+    /// are merged once every tuple has been read.
+    pub(crate) fn sort<I: IntoIterator<Item = Tuple>>(
+        &self,
+        tuples: I,
+        types: &[Type],
+        budget: &MemoryBudget,
+    ) -> Vec<Tuple> {
+        let mut runs = vec![];
+        let mut run = vec![];
+        let mut reservation = budget.reserve_anyway(0);
+        for tuple in tuples {
+            let size = tuple_size(&tuple);
+            if reservation.grow(size).is_err() {
+                if !run.is_empty() {
+                    runs.push(self.spill(std::mem::take(&mut run), types));
+                    if let SortedRun::Spilled(_) = runs.last().unwrap() {
+                        reservation.shrink(reservation.bytes());
+                    }
+                }
+                // a run always holds at least one tuple, so that the sort always makes progress
+                if reservation.grow(size).is_err() {
+                    reservation.grow_anyway(size);
+                }
+            }
+            run.push(tuple);
+        }
+        run.sort_by(|left, right| self.compare(left, right));
+        if runs.is_empty() {
+            return run;
+        }
+        runs.push(SortedRun::InMemory(run));
+        self.merge(runs)
+    }
+
+    /// Sorts the tuples and writes them to a spill file, or keeps them in memory if they can't be
+    /// spilled
+    fn spill(&self, mut run: Vec<Tuple>, types: &[Type]) -> SortedRun {
+        run.sort_by(|left, right| self.compare(left, right));
+        let mut file = match SpillFile::new(types.to_vec()) {
+            Ok(file) => file,
+            Err(_) => return SortedRun::InMemory(run),
+        };
+        for tuple in &run {
+            if !matches!(file.push(tuple), Ok(true)) {
+                return SortedRun::InMemory(run);
+            }
+        }
+        SortedRun::Spilled(file)
+    }
+
+    /// Merges sorted runs, where tuples that are equal are taken from earlier runs first
+    fn merge(&self, mut runs: Vec<SortedRun>) -> Vec<Tuple> {
+        let mut sources: Vec<Box<dyn Iterator<Item = Tuple> + '_>> = vec![];
+        for run in &mut runs {
+            match run {
+                SortedRun::InMemory(tuples) => {
+                    sources.push(Box::new(std::mem::take(tuples).into_iter()))
+                }
+                SortedRun::Spilled(file) => sources.push(Box::new(
+                    file.read()
+                        .expect("Couldn't read back the spilled tuples of a sort"),
+                )),
+            }
+        }
+        let mut heads: Vec<Option<Tuple>> = sources.iter_mut().map(Iterator::next).collect();
+        let mut merged = vec![];
+        loop {
+            let mut smallest: Option<usize> = None;
+            for (index, head) in heads.iter().enumerate() {
+                if let Some(tuple) = head {
+                    let smaller = match smallest {
+                        None => true,
+                        Some(other) => {
+                            let other = heads[other].as_ref().unwrap();
+                            self.compare(tuple, other) == Ordering::Less
+                        }
+                    };
+                    if smaller {
+                        smallest = Some(index);
+                    }
+                }
+            }
+            match smallest {
+                Some(index) => {
+                    let next = sources[index].next();
+                    merged.push(std::mem::replace(&mut heads[index], next).unwrap());
+                }
+                None => return merged,
+            }
+        }
+    }
+
+    /// Finds the first `limit` tuples in sorted order, only ever keeping `limit` tuples in memory
+    pub(crate) fn top_n<I: IntoIterator<Item = Tuple>>(
+        &self,
+        tuples: I,
+        limit: usize,
+    ) -> Vec<Tuple> {
+        if limit == 0 {
+            return vec![];
+        }
+        // the limit can be far larger than the input, so room is only made as tuples are kept
+        let mut kept = BinaryHeap::with_capacity(limit.min(TOP_N_CAPACITY));
+        for (position, tuple) in tuples.into_iter().enumerate() {
+            let ranked = Ranked {
+                tuple,
+                position,
+                comparator: self,
+            };
+            if kept.len() < limit {
+                kept.push(ranked);
+            } else if let Some(mut last) = kept.peek_mut() {
+                if ranked < *last {
+                    *last = ranked;
+                }
+            }
+        }
+        kept.into_sorted_vec()
+            .into_iter()
+            .map(|ranked| ranked.tuple)
+            .collect()
+    }
+}
+
+/// The most tuples [top_n](TupleComparator::top_n) makes room for before it starts keeping any
+const TOP_N_CAPACITY: usize = 1024;
+
+/// A tuple kept by [top_n](TupleComparator::top_n), ordered by the comparator and then by when
+/// it was found, so that the heap of the last tuples kept sorts like a stable sort
+struct Ranked<'c> {
+    tuple: Tuple,
+    position: usize,
+    comparator: &'c TupleComparator,
+}
+
+impl PartialEq for Ranked<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Ranked<'_> {}
+
+impl PartialOrd for Ranked<'_> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Ranked<'_> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.comparator
+            .compare(&self.tuple, &other.tuple)
+            .then(self.position.cmp(&other.position))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryFrom;
+
+    #[test]
+    fn top_n_matches_sort() {
+        let mut mappings = HashMap::new();
+        mappings.insert(Identifier::new("group"), 0);
+        mappings.insert(Identifier::new("value"), 1);
+        let comparator = TupleComparator::new(
+            &[SortKey::ascending("group"), SortKey::descending("value")],
+            &mappings,
+        );
+
+        let mut tuples: Vec<Tuple> = (0..50u64)
+            .map(|i| {
+                let value = if i % 7 == 0 {
+                    Value::Optional(None)
+                } else {
+                    Value::from((i * 31) % 17)
+                };
+                Tuple::new(vec![Value::from(i % 3), value])
+            })
+            .collect();
+        let top = comparator.top_n(tuples.clone(), 10);
+        let types = [Type::from(0u64), Type::from(0u64)];
+        tuples = comparator.sort(tuples, &types, &MemoryBudget::unlimited());
+        assert_eq!(top, tuples[..10].to_vec());
+        assert!(comparator.top_n(tuples.clone(), 0).is_empty());
+        assert_eq!(comparator.top_n(tuples.clone(), 100), tuples);
+    }
+
+    #[test]
+    fn top_n_past_the_input() {
+        let mut mappings = HashMap::new();
+        mappings.insert(Identifier::new("value"), 0);
+        let comparator = TupleComparator::new(&[SortKey::descending("value")], &mappings);
+        let tuples: Vec<Tuple> = (0..20u64)
+            .map(|i| Tuple::new(vec![Value::from((i * 7) % 20)]))
+            .collect();
+        let mut sorted = tuples.clone();
+        sorted.sort_by(|left, right| comparator.compare(left, right));
+        assert_eq!(comparator.top_n(tuples.clone(), 1_000_000_000), sorted);
+        assert_eq!(comparator.top_n(tuples, usize::MAX), sorted);
+    }
+
+    #[test]
+    fn sorts_spill_past_the_budget() {
+        let mut mappings = HashMap::new();
+        mappings.insert(Identifier::new("group"), 0);
+        let comparator = TupleComparator::new(&[SortKey::descending("group")], &mappings);
+        let types = [Type::from(0u64), Type::from(0u64)];
+        // the second field is the position of the tuple, so that the sort is seen to be stable
+        let tuples: Vec<Tuple> = (0..200u64)
+            .map(|i| Tuple::new(vec![Value::from((i * 37) % 11), Value::from(i)]))
+            .collect();
+
+        let expected = comparator.sort(tuples.clone(), &types, &MemoryBudget::unlimited());
+        let budget = MemoryBudget::new(tuple_size(&tuples[0]) * 16);
+        assert_eq!(comparator.sort(tuples, &types, &budget), expected);
+        assert_eq!(budget.used(), 0);
+        assert!(expected.windows(2).all(|pair| {
+            let position = |tuple: &Tuple| u64::try_from(tuple[1].clone()).unwrap();
+            pair[0][0] != pair[1][0] || position(&pair[0]) < position(&pair[1])
+        }));
+    }
+
+    #[test]
+    fn null_placement() {
+        let mut mappings = HashMap::new();
+        mappings.insert(Identifier::new("value"), 0);
+        let values = |tuples: &[Tuple]| -> Vec<Option<u64>> {
+            tuples
+                .iter()
+                .map(|tuple| match &tuple[0] {
+                    Value::Optional(None) => None,
+                    value => Some(u64::try_from(value.clone()).unwrap()),
+                })
+                .collect()
+        };
+        let tuples: Vec<Tuple> = vec![Value::from(2u64), Value::Optional(None), Value::from(1u64)]
+            .into_iter()
+            .map(|value| Tuple::new(vec![value]))
+            .collect();
+
+        let sorted = |key: SortKey| {
+            let types = [Type::from(0u64)];
+            let tuples = TupleComparator::new(&[key], &mappings).sort(
+                tuples.clone(),
+                &types,
+                &MemoryBudget::unlimited(),
+            );
+            values(&tuples)
+        };
+        assert_eq!(
+            sorted(SortKey::ascending("value")),
+            vec![Some(1), Some(2), None]
+        );
+        assert_eq!(
+            sorted(SortKey::ascending("value").nulls_first()),
+            vec![None, Some(1), Some(2)]
+        );
+        assert_eq!(
+            sorted(SortKey::descending("value").nulls_first()),
+            vec![None, Some(2), Some(1)]
+        );
+        assert_eq!(
+            sorted(SortKey::descending("value").nulls_first().nulls_last()),
+            vec![Some(2), Some(1), None]
+        );
+    }
+}
@@ -0,0 +1,124 @@
+//! Writes the results of queries as text tables, for showing them to people
+
+use std::io;
+use std::io::Write;
+
+use rad_db_types::display::{render_value, BinaryDisplay};
+
+use crate::query::query_result::QueryResult;
+
+/// Writes a result as a table, with a row for each tuple under a row of field names
+#[derive(Debug, Clone, Default)]
+pub struct TablePrinter {
+    binary: BinaryDisplay,
+}
+
+impl TablePrinter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets how binary text is shown, which is in hex by default
+    pub fn with_binary_display(mut self, binary: BinaryDisplay) -> Self {
+        self.binary = binary;
+        self
+    }
+
+    pub fn print<W: Write>(&self, result: QueryResult, writer: &mut W) -> io::Result<()> {
+        let header: Vec<String> = result
+            .relation()
+            .iter()
+            .map(|(field, _)| field.to_string())
+            .collect();
+        let rows: Vec<Vec<String>> = result
+            .into_iter()
+            .map(|tuple| {
+                tuple
+                    .iter()
+                    .map(|value| render_value(value, self.binary))
+                    .collect()
+            })
+            .collect();
+        let widths: Vec<usize> = (0..header.len())
+            .map(|column| {
+                std::iter::once(&header)
+                    .chain(&rows)
+                    .map(|row| row[column].chars().count())
+                    .max()
+                    .unwrap_or(0)
+            })
+            .collect();
+
+        write_row(writer, &header, &widths)?;
+        let separator: Vec<String> = widths.iter().map(|&width| "-".repeat(width)).collect();
+        writeln!(writer, "{}", separator.join("-+-"))?;
+        for row in &rows {
+            write_row(writer, row, &widths)?;
+        }
+        Ok(())
+    }
+
+    pub fn render(&self, result: QueryResult) -> String {
+        let mut written = vec![];
+        self.print(result, &mut written)
+            .expect("Writing to memory can't fail");
+        String::from_utf8(written).expect("Only strings were written")
+    }
+}
+
+fn write_row<W: Write>(writer: &mut W, row: &[String], widths: &[usize]) -> io::Result<()> {
+    let cells: Vec<String> = row
+        .iter()
+        .zip(widths)
+        .map(|(cell, &width)| format!("{:width$}", cell, width = width))
+        .collect();
+    writeln!(writer, "{}", cells.join(" | ").trim_end())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rad_db_structure::identifier::Identifier;
+    use rad_db_structure::tuple::Tuple;
+    use rad_db_types::{Text, Type, Value};
+
+    fn files() -> QueryResult<'static> {
+        QueryResult::with_tuples(
+            vec![
+                (Identifier::new("name"), Type::from("")),
+                (Identifier::new("contents"), Type::Text(Text::Blob(vec![]))),
+            ],
+            vec![
+                Tuple::new(vec![
+                    Value::from("a.txt"),
+                    Value::Text(Text::Blob(b"hi".to_vec())),
+                ]),
+                Tuple::new(vec![
+                    Value::from("b.bin"),
+                    Value::Text(Text::Blob(vec![0xff, b'!'])),
+                ]),
+            ],
+            0,
+        )
+    }
+
+    #[test]
+    fn tables() {
+        assert_eq!(
+            TablePrinter::new().render(files()),
+            "name  | contents\n\
+             ------+---------\n\
+             a.txt | 0x6869\n\
+             b.bin | 0xff21\n"
+        );
+        assert_eq!(
+            TablePrinter::new()
+                .with_binary_display(BinaryDisplay::Lossy)
+                .render(files()),
+            "name  | contents\n\
+             ------+---------\n\
+             a.txt | hi\n\
+             b.bin | \u{FFFD}!\n"
+        );
+    }
+}
@@ -0,0 +1,289 @@
+//! A log of the queries executed, for finding the ones that are worth tuning.
+//!
+//! Queries executed through a [WorkloadLog] are logged with their plan, the constants their
+//! selections fix fields to, how long they took and how many tuples they produced. Queries that
+//! took at least the slow query threshold are marked as slow. The log only keeps the most recent
+//! queries, and can be read as a relation so that it can be queried like any other.
+
+use std::collections::VecDeque;
+use std::iter::FromIterator;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use rad_db_structure::identifier::Identifier;
+use rad_db_structure::key::primary::PrimaryKeyDefinition;
+use rad_db_structure::relations::Relation;
+use rad_db_structure::tuple::Tuple;
+use rad_db_types::{Type, Value};
+
+use crate::query::query_node::{QueryNode, QueryOperation};
+use crate::query::query_result::QueryResult;
+
+/// A query that was executed
+#[derive(Debug, Clone)]
+pub struct LoggedQuery {
+    sequence: u64,
+    started: SystemTime,
+    plan: String,
+    parameters: Vec<(Identifier, Value)>,
+    duration: Duration,
+    rows: usize,
+    created_tuples: usize,
+    slow: bool,
+}
+
+impl LoggedQuery {
+    /// The number of queries logged before this one
+    pub fn sequence(&self) -> u64 {
+        self.sequence
+    }
+
+    pub fn started(&self) -> SystemTime {
+        self.started
+    }
+
+    /// The plan the query was executed with, as [explained](QueryNode::explain)
+    pub fn plan(&self) -> &str {
+        &self.plan
+    }
+
+    /// The fields the selections of the query fixed to constants, and the constants
+    pub fn parameters(&self) -> &[(Identifier, Value)] {
+        &self.parameters
+    }
+
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+
+    /// The number of tuples the query produced
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    /// The number of tuples every node of the query created together
+    pub fn created_tuples(&self) -> usize {
+        self.created_tuples
+    }
+
+    /// Whether the query took at least the slow query threshold of the log
+    pub fn is_slow(&self) -> bool {
+        self.slow
+    }
+}
+
+/// The most recent queries executed through it
+pub struct WorkloadLog {
+    capacity: usize,
+    slow_threshold: Option<Duration>,
+    queries: VecDeque<LoggedQuery>,
+    logged: u64,
+}
+
+impl WorkloadLog {
+    /// Creates a log that keeps the `capacity` most recent queries, without a slow query threshold
+    pub fn new(capacity: usize) -> Self {
+        WorkloadLog {
+            capacity,
+            slow_threshold: None,
+            queries: VecDeque::new(),
+            logged: 0,
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn slow_threshold(&self) -> Option<Duration> {
+        self.slow_threshold
+    }
+
+    /// Marks the queries that take at least the threshold as slow from now on, or no queries if
+    /// it's `None`
+    pub fn set_slow_threshold(&mut self, threshold: Option<Duration>) {
+        self.slow_threshold = threshold;
+    }
+
+    /// Executes the query, logging it once all of its tuples were produced
+    pub fn execute<'a: 'q, 'q>(&mut self, query: QueryNode<'a>) -> QueryResult<'q> {
+        let plan = query.explain();
+        let parameters = parameters_of(&query);
+        let started = SystemTime::now();
+        let start = Instant::now();
+        let mut result = query.execute_query();
+        let rows = result.materialized().len();
+        let duration = start.elapsed();
+        self.log(LoggedQuery {
+            sequence: 0,
+            started,
+            plan,
+            parameters,
+            duration,
+            rows,
+            created_tuples: result.total_created_tuples(),
+            slow: false,
+        });
+        result
+    }
+
+    /// Logs a query that was executed some other way
+    pub fn record(&mut self, query: &QueryNode, duration: Duration, rows: usize) {
+        self.log(LoggedQuery {
+            sequence: 0,
+            started: SystemTime::now() - duration,
+            plan: query.explain(),
+            parameters: parameters_of(query),
+            duration,
+            rows,
+            created_tuples: rows,
+            slow: false,
+        });
+    }
+
+    fn log(&mut self, mut query: LoggedQuery) {
+        query.sequence = self.logged;
+        query.slow = self
+            .slow_threshold
+            .map_or(false, |threshold| query.duration >= threshold);
+        self.logged += 1;
+        if self.capacity == 0 {
+            return;
+        }
+        if self.queries.len() >= self.capacity {
+            self.queries.pop_front();
+        }
+        self.queries.push_back(query);
+    }
+
+    /// The queries kept, from the oldest to the most recent
+    pub fn queries(&self) -> impl Iterator<Item = &LoggedQuery> {
+        self.queries.iter()
+    }
+
+    /// The slow queries kept, from the oldest to the most recent
+    pub fn slow_queries(&self) -> impl Iterator<Item = &LoggedQuery> {
+        self.queries.iter().filter(|query| query.slow)
+    }
+
+    pub fn len(&self) -> usize {
+        self.queries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queries.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.queries.clear();
+    }
+
+    /// A relation of the queries kept, with a tuple of each query keyed by its sequence number.
+    /// Times are in microseconds, with the start of a query since the Unix epoch.
+    pub fn to_relation(&self, name: Identifier) -> Relation {
+        let mut relation = Relation::new_volatile(
+            name,
+            vec![
+                ("sequence", Type::from(0u64)),
+                ("started_micros", Type::from(0u64)),
+                ("duration_micros", Type::from(0u64)),
+                ("rows", Type::from(0u64)),
+                ("created_tuples", Type::from(0u64)),
+                ("slow", Type::from(false)),
+                ("parameters", Type::from("")),
+                ("plan", Type::from("")),
+            ],
+            64,
+            PrimaryKeyDefinition::new(vec![0]),
+        );
+        for query in &self.queries {
+            let started = query.started.duration_since(UNIX_EPOCH).unwrap_or_default();
+            let parameters: Vec<String> = query
+                .parameters
+                .iter()
+                .map(|(field, value)| format!("{} = {}", field, value))
+                .collect();
+            let tuple = Tuple::from_iter(&[
+                Value::from(query.sequence),
+                Value::from(started.as_micros() as u64),
+                Value::from(query.duration.as_micros() as u64),
+                Value::from(query.rows as u64),
+                Value::from(query.created_tuples as u64),
+                Value::from(query.slow),
+                Value::from(parameters.join(", ")),
+                Value::from(query.plan.clone()),
+            ]);
+            relation
+                .insert(tuple)
+                .expect("Every logged query has its own sequence number");
+        }
+        relation
+    }
+}
+
+/// The fields the selections of the query fix to constants, and the constants
+fn parameters_of(query: &QueryNode) -> Vec<(Identifier, Value)> {
+    let mut parameters = vec![];
+    if let QueryOperation::Selection(condition) = query.query_operation() {
+        parameters.extend(condition.fixed_values());
+    }
+    for child in query.children() {
+        parameters.extend(parameters_of(child));
+    }
+    parameters
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::query::conditions::{Condition, ConditionOperation, Operand};
+
+    #[test]
+    fn logged_queries() {
+        let mut relation = Relation::new_volatile(
+            Identifier::new("logged"),
+            vec![("id", Type::from(0u64))],
+            64,
+            PrimaryKeyDefinition::new(vec![0]),
+        );
+        for i in 0..20u64 {
+            relation
+                .insert(Tuple::from_iter(&[Value::from(i)]))
+                .unwrap();
+        }
+        let select = |id: u64| {
+            QueryNode::select_on_condition(
+                QueryNode::source(&relation),
+                Condition::new(
+                    "id",
+                    ConditionOperation::Equals(Operand::UnsignedNumber(id)),
+                ),
+            )
+        };
+
+        let mut log = WorkloadLog::new(2);
+        assert_eq!(log.execute(select(3)).into_iter().count(), 1);
+        log.set_slow_threshold(Some(Duration::from_secs(0)));
+        log.execute(QueryNode::source(&relation));
+        log.execute(select(30));
+
+        assert_eq!(log.len(), 2);
+        let queries: Vec<&LoggedQuery> = log.queries().collect();
+        assert_eq!(queries[0].sequence(), 1);
+        assert_eq!(queries[0].rows(), 20);
+        assert_eq!(queries[1].rows(), 0);
+        assert_eq!(
+            queries[1].parameters(),
+            &[(Identifier::new("id"), Value::from(30u64))]
+        );
+        assert!(queries[1].plan().contains("logged"));
+        assert_eq!(log.slow_queries().count(), 2);
+
+        let logged = log.to_relation(Identifier::new("workload"));
+        assert_eq!(logged.len(), 2);
+        let rows: Vec<Tuple> = QueryNode::source(&logged)
+            .execute_query()
+            .into_iter()
+            .collect();
+        assert!(rows.iter().any(|tuple| tuple[3] == Value::from(20u64)));
+    }
+}
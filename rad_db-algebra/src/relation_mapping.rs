@@ -1,4 +1,4 @@
-use rad_db_structure::identifier::Identifier;
+use rad_db_structure::identifier::{CasePolicy, Identifier};
 use std::collections::HashMap;
 use rad_db_structure::relations::Relation;
 use std::ops::Deref;
@@ -28,6 +28,7 @@ impl<'r> MappedRelation<'r> {
     }
 
     pub fn add_field_alias<I : Into<Identifier>>(&mut self, field: I, alias: String) {
+        let alias = self.case_policy().normalize(&alias).into_owned();
         self.aliased_fields.insert(alias, field.into());
     }
 
@@ -44,7 +45,10 @@ impl<'r> MappedRelation<'r> {
         let id = field.into();
         match &id.parent() {
             None => {
-                if let Some(s) = self.aliased_fields.get(id.base()) {
+                if let Some(s) = self
+                    .aliased_fields
+                    .get(&*self.case_policy().normalize(id.base()))
+                {
                     Some(s.clone())
                 } else {
                     if let Some(index) = self.relation.get_field_index(id) {
@@ -60,11 +64,10 @@ impl<'r> MappedRelation<'r> {
             }
             Some(parent) => {
                 if self.valid_name(*parent) {
-                    if self.relation.get_field_index(id.base()).is_some() {
-                        Some(id)
-                    } else {
-                        None
-                    }
+                    self.relation
+                        .get_field_index(id.base())
+                        .and_then(|index| self.relation.attributes().get(index))
+                        .map(|(s, _)| Identifier::concat(&self.relation_identifier, s))
                 } else {
                     None
                 }
@@ -80,29 +83,29 @@ impl<'r> MappedRelation<'r> {
         }
     }
 
-    /// Determines if the name is a valid name when referring to the relation
+    /// Determines if the name is a valid name when referring to the relation. Once the relation
+    /// is aliased, it can only be referred to by its alias.
     pub fn valid_name(&self, name: &Identifier) -> bool {
-        if name == self.relation.name() {
-            return true;
+        let policy = self.case_policy();
+        if let Some(alias) = &self.aliased_self {
+            return name.parent().is_none() && policy.names_match(name.base(), alias);
         }
 
-        if name.parent().is_none() && name.base() == self.relation.name().base() {
+        if policy.identifiers_match(name, self.relation.name()) {
             return true;
         }
 
-        if name.parent().is_none() &&
-            self.aliased_self
-                .as_ref()
-                .map_or(false, |alias| name.base() == alias) {
-            return true;
-        }
-
-        false
+        name.parent().is_none() && policy.names_match(name.base(), self.relation.name().base())
     }
 
     pub fn relation(&self) -> &'r Relation {
         self.relation
     }
+
+    /// Names are resolved with the same case policy as the relation
+    fn case_policy(&self) -> CasePolicy {
+        self.relation.case_policy()
+    }
 }
 
 
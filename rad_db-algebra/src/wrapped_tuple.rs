@@ -21,7 +21,7 @@ impl<'a, I: Into<Identifier>> Index<I> for WrappedTuple<'a> {
 
     fn index(&self, index: I) -> &Self::Output {
         let id = index.into();
-        let pos = self.fields.iter().position(|f| f == id);
+        let pos = self.fields.iter().position(|f| f == &id);
         match pos {
             None => {
                 panic!("No field named {} in this tuple", id)
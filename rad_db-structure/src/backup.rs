@@ -0,0 +1,293 @@
+//! Hot backups of relations to a single tar archive.
+//!
+//! A backup first writes every changed block of the relations to its file and merges the logs of
+//! the blocks into their files, and then archives the files of each relation while it's borrowed,
+//! so no tuple can be changed between the checkpoint and the copy. Along with the block files, the archive holds a catalog of `CREATE TABLE`
+//! statements for every relation. The seeds the primary key of a relation hashes keys with and the
+//! files of blobs stored out of line are archived with the blocks of their relation. Relations that are only kept in memory have no files, so the catalog
+//! also holds `INSERT` statements for their tuples.
+//!
+//! Paths in the archive are relative to the storage root, so restoring an archive into a fresh
+//! directory lays the files out the way they were stored.
+
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Component, Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::dump::{dump_definition, dump_relation};
+use crate::relations::blob::BLOBS_DIRECTORY;
+use crate::relations::Relation;
+
+/// The name of the file in an archive that holds the definitions of the relations
+pub const CATALOG_FILE: &str = "CATALOG.sql";
+
+/// The size of the headers and blocks of a tar archive
+const BLOCK: usize = 512;
+
+/// Writes a tar archive of the relations to the path, returning how many files it holds
+pub fn backup_archive<'a, P, I>(relations: I, path: P) -> io::Result<usize>
+where
+    P: AsRef<Path>,
+    I: IntoIterator<Item = &'a Relation>,
+{
+    let relations: Vec<&Relation> = relations.into_iter().collect();
+    for relation in &relations {
+        relation.flush();
+        relation.compact();
+    }
+
+    let mut catalog = vec![];
+    let mut files = vec![];
+    for relation in &relations {
+        match relation.storage_directory() {
+            Some(directory) => {
+                dump_definition(relation, &mut catalog)?;
+                let prefix = PathBuf::from(relation.name());
+                let blobs = (
+                    directory.join(BLOBS_DIRECTORY),
+                    prefix.join(BLOBS_DIRECTORY),
+                );
+                for (directory, prefix) in vec![(directory, prefix), blobs] {
+                    for file in stored_files(&directory)? {
+                        let name = prefix.join(file.file_name().expect("Files are named"));
+                        files.push((name, file));
+                    }
+                }
+            }
+            None => dump_relation(relation, &mut catalog)?,
+        }
+    }
+
+    let mut writer = BufWriter::new(File::create(path)?);
+    write_entry(&mut writer, Path::new(CATALOG_FILE), &catalog)?;
+    for (name, file) in &files {
+        write_entry(&mut writer, name, &fs::read(file)?)?;
+    }
+    writer.write_all(&[0; 2 * BLOCK])?;
+    writer.flush()?;
+    Ok(files.len() + 1)
+}
+
+/// Writes the files of an archive into the root, which must not exist or be empty, returning the
+/// paths of the files written
+pub fn restore_archive<P: AsRef<Path>, R: AsRef<Path>>(
+    archive: P,
+    root: R,
+) -> io::Result<Vec<PathBuf>> {
+    let root = root.as_ref();
+    if root.exists() && fs::read_dir(root)?.next().is_some() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} isn't empty", root.display()),
+        ));
+    }
+    fs::create_dir_all(root)?;
+
+    let mut reader = BufReader::new(File::open(archive)?);
+    let mut restored = vec![];
+    let mut header = [0u8; BLOCK];
+    loop {
+        reader.read_exact(&mut header)?;
+        if header.iter().all(|&byte| byte == 0) {
+            break;
+        }
+        let (name, size) = read_header(&header)?;
+        let mut contents = vec![0; padded(size)];
+        reader.read_exact(&mut contents)?;
+        contents.truncate(size);
+
+        let path = root.join(name);
+        fs::create_dir_all(path.parent().expect("Restored files are within the root"))?;
+        fs::write(&path, contents)?;
+        restored.push(path);
+    }
+    Ok(restored)
+}
+
+/// The files directly in the directory, ordered by name. Directories within it are the storage of
+/// other relations.
+fn stored_files(directory: &Path) -> io::Result<Vec<PathBuf>> {
+    let entries = match fs::read_dir(directory) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(e),
+    };
+    let mut files = vec![];
+    for entry in entries {
+        let entry = entry?;
+        if entry.file_type()?.is_file() {
+            files.push(entry.path());
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+fn padded(size: usize) -> usize {
+    (size + BLOCK - 1) / BLOCK * BLOCK
+}
+
+/// Writes a ustar header for a regular file, followed by its contents padded to a whole block
+fn write_entry<W: Write>(writer: &mut W, name: &Path, contents: &[u8]) -> io::Result<()> {
+    let name: Vec<&str> = name
+        .components()
+        .map(|component| component.as_os_str().to_str())
+        .collect::<Option<_>>()
+        .ok_or_else(|| invalid("Archived paths must be unicode"))?;
+    let name = name.join("/");
+    let (prefix, name) = match name.len() {
+        0..=100 => ("", name.as_str()),
+        _ => name
+            .char_indices()
+            .filter(|&(i, c)| c == '/' && i <= 155 && name.len() - i - 1 <= 100)
+            .map(|(i, _)| (&name[..i], &name[i + 1..]))
+            .next()
+            .ok_or_else(|| invalid(format!("{} is too long to archive", name)))?,
+    };
+    let modified = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+
+    let mut header = [0u8; BLOCK];
+    header[..name.len()].copy_from_slice(name.as_bytes());
+    write_octal(&mut header[100..108], 0o644);
+    write_octal(&mut header[108..116], 0);
+    write_octal(&mut header[116..124], 0);
+    write_octal(&mut header[124..136], contents.len() as u64);
+    write_octal(&mut header[136..148], modified);
+    header[156] = b'0';
+    header[257..263].copy_from_slice(b"ustar\0");
+    header[263..265].copy_from_slice(b"00");
+    header[345..345 + prefix.len()].copy_from_slice(prefix.as_bytes());
+    header[148..156].copy_from_slice(b"        ");
+    let checksum: u64 = header.iter().map(|&byte| byte as u64).sum();
+    write_octal(&mut header[148..155], checksum);
+    header[155] = b' ';
+
+    writer.write_all(&header)?;
+    writer.write_all(contents)?;
+    writer.write_all(&vec![0; padded(contents.len()) - contents.len()])
+}
+
+/// Reads the path and size of a regular file from a header, rejecting paths that leave the root
+fn read_header(header: &[u8; BLOCK]) -> io::Result<(PathBuf, usize)> {
+    let stored: u64 = header[..148]
+        .iter()
+        .chain(b"        ")
+        .chain(&header[156..])
+        .map(|&byte| byte as u64)
+        .sum();
+    if read_octal(&header[148..156])? != stored {
+        return Err(invalid("The checksum of a header doesn't match"));
+    }
+    if header[156] != b'0' && header[156] != 0 {
+        return Err(invalid("Only regular files can be restored"));
+    }
+    let text = |field: &[u8]| -> io::Result<String> {
+        let end = field
+            .iter()
+            .position(|&byte| byte == 0)
+            .unwrap_or(field.len());
+        String::from_utf8(field[..end].to_vec()).map_err(invalid)
+    };
+    let prefix = text(&header[345..500])?;
+    let name = text(&header[..100])?;
+    let path = PathBuf::from(prefix).join(name);
+    if !path
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)))
+    {
+        return Err(invalid(format!(
+            "{} is outside of the root",
+            path.display()
+        )));
+    }
+    Ok((path, read_octal(&header[124..136])? as usize))
+}
+
+/// Writes the number as zero padded octal digits, followed by a `NUL`
+fn write_octal(field: &mut [u8], value: u64) {
+    let digits = format!("{:0width$o}", value, width = field.len() - 1);
+    field[..digits.len()].copy_from_slice(digits.as_bytes());
+    field[digits.len()] = 0;
+}
+
+fn read_octal(field: &[u8]) -> io::Result<u64> {
+    let digits: String = field
+        .iter()
+        .take_while(|&&byte| byte != 0)
+        .map(|&byte| byte as char)
+        .collect();
+    u64::from_str_radix(digits.trim(), 8).map_err(invalid)
+}
+
+fn invalid<E: Into<Box<dyn std::error::Error + Send + Sync>>>(error: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
+
+#[cfg(test)]
+mod tests {
+    use rad_db_types::Type;
+
+    use super::*;
+    use crate::identifier::Identifier;
+    use crate::key::primary::PrimaryKeyDefinition;
+    use crate::temp::{directory_of, unique_identifier};
+    use crate::tuple::Tuple;
+
+    #[test]
+    fn backup_and_restore() {
+        let name = unique_identifier("backed_up");
+        let mut relation = Relation::new(
+            name.clone(),
+            vec![("id", Type::from(0u8)), ("name", Type::from(""))],
+            4,
+            PrimaryKeyDefinition::new(vec![0]),
+        );
+        for i in 0..10u8 {
+            relation
+                .insert(Tuple::new(vec![i.into(), format!("n{}", i).into()]))
+                .unwrap();
+        }
+        let mut volatile = Relation::new_volatile(
+            Identifier::new("volatile"),
+            vec![("id", Type::from(0u8))],
+            4,
+            PrimaryKeyDefinition::new(vec![0]),
+        );
+        volatile.insert(Tuple::new(vec![7u8.into()])).unwrap();
+
+        let directory = directory_of(&unique_identifier("backup"));
+        fs::create_dir_all(&directory).unwrap();
+        let archive = directory.join("backup.tar");
+        let archived = backup_archive(vec![&relation, &volatile], &archive).unwrap();
+        // the blocks, the seeds of the primary key and the catalog
+        assert_eq!(archived, relation.block_count() + 2);
+
+        let root = directory.join("restored");
+        let restored = restore_archive(&archive, &root).unwrap();
+        assert_eq!(restored.len(), archived);
+        let catalog = fs::read_to_string(root.join(CATALOG_FILE)).unwrap();
+        assert_eq!(catalog.matches("CREATE TABLE").count(), 2);
+        assert!(catalog.contains("INSERT INTO \"volatile\""));
+        let stored = relation.storage_directory().unwrap();
+        for file in stored_files(&stored).unwrap() {
+            let copy = root
+                .join(PathBuf::from(relation.name()))
+                .join(file.file_name().unwrap());
+            assert_eq!(fs::read(copy).unwrap(), fs::read(file).unwrap());
+        }
+        assert_eq!(
+            restore_archive(&archive, &root).unwrap_err().kind(),
+            io::ErrorKind::AlreadyExists
+        );
+
+        std::mem::drop(relation);
+        fs::remove_dir_all(stored).unwrap();
+        fs::remove_dir_all(directory).unwrap();
+    }
+}
@@ -1 +1,560 @@
+//! Constraints are rules every tuple of a relation has to follow. They are attached to a
+//! relation and checked in order whenever a tuple is stored.
+//!
+//! When tuples are stored together as a batch, a constraint can be deferred to the end of the
+//! batch. A deferred constraint sees every other tuple of the batch as already stored, so tuples
+//! that reference each other can be stored in any order.
 
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::sync::Arc;
+
+use rad_db_types::Value;
+
+use crate::identifier::Identifier;
+use crate::key::foreign::ForeignKeyDefinition;
+use crate::relations::Relation;
+use crate::tuple::Tuple;
+
+/// Gets other relations by name, for constraints that look outside of their own relation
+pub trait RelationLookup {
+    fn lookup(&self, name: &Identifier) -> Option<&Relation>;
+}
+
+impl RelationLookup for () {
+    fn lookup(&self, _name: &Identifier) -> Option<&Relation> {
+        None
+    }
+}
+
+impl<const N: usize> RelationLookup for [&Relation; N] {
+    fn lookup(&self, name: &Identifier) -> Option<&Relation> {
+        self.iter()
+            .copied()
+            .find(|relation| relation.name() == name)
+    }
+}
+
+impl RelationLookup for Vec<&Relation> {
+    fn lookup(&self, name: &Identifier) -> Option<&Relation> {
+        self.iter()
+            .copied()
+            .find(|relation| relation.name() == name)
+    }
+}
+
+impl RelationLookup for HashMap<Identifier, Relation> {
+    fn lookup(&self, name: &Identifier) -> Option<&Relation> {
+        self.get(name)
+    }
+}
+
+/// A rule that every tuple of a relation has to follow. Constraints are shared by relations
+/// that are sent between threads.
+pub trait Constraint: Send + Sync {
+    /// The name violations of the constraint are reported under
+    fn name(&self) -> &str;
+
+    /// Checks a tuple that is about to be stored in the relation, returning why it can't be
+    /// stored if it breaks the constraint
+    fn check(
+        &self,
+        tuple: &Tuple,
+        relation: &Relation,
+        others: &dyn RelationLookup,
+    ) -> Result<(), String>;
+
+    /// Checks a tuple that is stored in a batch, where `pending` are the tuples of the batch
+    /// that count as stored along with it, not including the tuple itself. Constraints that
+    /// only look at the tuple can ignore `pending`.
+    fn check_pending(
+        &self,
+        tuple: &Tuple,
+        pending: &[&Tuple],
+        relation: &Relation,
+        others: &dyn RelationLookup,
+    ) -> Result<(), String> {
+        let _ = pending;
+        self.check(tuple, relation, others)
+    }
+}
+
+/// A tuple broke a constraint
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConstraintViolation {
+    pub constraint: String,
+    pub reason: String,
+}
+
+impl Display for ConstraintViolation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.constraint, self.reason)
+    }
+}
+
+impl Error for ConstraintViolation {}
+
+/// The constraints of a relation, in the order they are checked. Clones of a pipeline share its
+/// constraints.
+#[derive(Default, Clone)]
+pub struct ConstraintPipeline {
+    constraints: Vec<Arc<dyn Constraint>>,
+    /// The names of the constraints that are checked at the end of a batch
+    deferred: HashSet<String>,
+}
+
+impl ConstraintPipeline {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push<C: Constraint + 'static>(&mut self, constraint: C) {
+        self.constraints.push(Arc::new(constraint));
+    }
+
+    /// Removes the constraint with the name, returning whether there was one
+    pub fn remove(&mut self, name: &str) -> bool {
+        let len = self.constraints.len();
+        self.constraints
+            .retain(|constraint| constraint.name() != name);
+        self.deferred.remove(name);
+        self.constraints.len() != len
+    }
+
+    /// Sets whether the constraint with the name is checked at the end of a batch instead of as
+    /// each tuple of the batch is stored, returning whether there is such a constraint
+    pub fn defer(&mut self, name: &str, deferred: bool) -> bool {
+        if !self.names().any(|existing| existing == name) {
+            return false;
+        }
+        if deferred {
+            self.deferred.insert(name.to_string());
+        } else {
+            self.deferred.remove(name);
+        }
+        true
+    }
+
+    pub fn is_deferred(&self, name: &str) -> bool {
+        self.deferred.contains(name)
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.constraints.iter().map(|constraint| constraint.name())
+    }
+
+    pub fn len(&self) -> usize {
+        self.constraints.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.constraints.is_empty()
+    }
+
+    /// Checks every constraint against the tuple, reporting all of the constraints it breaks
+    pub fn check(
+        &self,
+        tuple: &Tuple,
+        relation: &Relation,
+        others: &dyn RelationLookup,
+    ) -> Result<(), Vec<ConstraintViolation>> {
+        let violations = self.violations(|constraint, _| constraint.check(tuple, relation, others));
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(violations)
+        }
+    }
+
+    /// Checks a batch of tuples that are stored together, reporting the index of every tuple
+    /// that breaks a constraint along with the constraints it breaks. A tuple sees the tuples
+    /// before it in the batch as stored, and deferred constraints see every other tuple of the
+    /// batch as stored.
+    pub fn check_batch(
+        &self,
+        tuples: &[Tuple],
+        relation: &Relation,
+        others: &dyn RelationLookup,
+    ) -> Vec<(usize, Vec<ConstraintViolation>)> {
+        let mut rejected = vec![];
+        if self.is_empty() {
+            return rejected;
+        }
+        let mut earlier: Vec<&Tuple> = Vec::with_capacity(tuples.len());
+        for (index, tuple) in tuples.iter().enumerate() {
+            let rest: Vec<&Tuple> = if self.deferred.is_empty() {
+                vec![]
+            } else {
+                earlier
+                    .iter()
+                    .copied()
+                    .chain(&tuples[index + 1..])
+                    .collect()
+            };
+            let violations = self.violations(|constraint, deferred| {
+                let pending = if deferred { &rest } else { &earlier };
+                constraint.check_pending(tuple, pending, relation, others)
+            });
+            if !violations.is_empty() {
+                rejected.push((index, violations));
+            }
+            earlier.push(tuple);
+        }
+        rejected
+    }
+
+    /// Runs every constraint through the check, which is given whether the constraint is
+    /// deferred
+    fn violations<F>(&self, mut check: F) -> Vec<ConstraintViolation>
+    where
+        F: FnMut(&dyn Constraint, bool) -> Result<(), String>,
+    {
+        self.constraints
+            .iter()
+            .filter_map(|constraint| {
+                let deferred = self.deferred.contains(constraint.name());
+                check(&**constraint, deferred)
+                    .err()
+                    .map(|reason| ConstraintViolation {
+                        constraint: constraint.name().to_string(),
+                        reason,
+                    })
+            })
+            .collect()
+    }
+}
+
+/// Values of nullable fields may be wrapped, so compare what they wrap
+fn same_value(left: &Value, right: &Value) -> bool {
+    match (left, right) {
+        (Value::Optional(Some(left)), right) => same_value(left, right),
+        (left, Value::Optional(Some(right))) => same_value(left, right),
+        (left, right) => left == right,
+    }
+}
+
+/// The field can't be `NULL`
+pub struct NotNull {
+    name: String,
+    field: usize,
+}
+
+impl NotNull {
+    pub fn new<S: ToString>(name: S, field: usize) -> Self {
+        NotNull {
+            name: name.to_string(),
+            field,
+        }
+    }
+}
+
+impl Constraint for NotNull {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn check(&self, tuple: &Tuple, _: &Relation, _: &dyn RelationLookup) -> Result<(), String> {
+        if tuple[self.field].is_null() {
+            Err(format!("field {} is NULL", self.field))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// No two tuples can have the same values in the fields. Tuples with a `NULL` in any of the
+/// fields never conflict.
+pub struct Unique {
+    name: String,
+    fields: Vec<usize>,
+}
+
+impl Unique {
+    pub fn new<S: ToString>(name: S, fields: Vec<usize>) -> Self {
+        Unique {
+            name: name.to_string(),
+            fields,
+        }
+    }
+}
+
+impl Constraint for Unique {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn check(
+        &self,
+        tuple: &Tuple,
+        relation: &Relation,
+        others: &dyn RelationLookup,
+    ) -> Result<(), String> {
+        self.check_pending(tuple, &[], relation, others)
+    }
+
+    fn check_pending(
+        &self,
+        tuple: &Tuple,
+        pending: &[&Tuple],
+        relation: &Relation,
+        _: &dyn RelationLookup,
+    ) -> Result<(), String> {
+        if self.fields.iter().any(|&field| tuple[field].is_null()) {
+            return Ok(());
+        }
+        let same = |existing: &Tuple| {
+            self.fields
+                .iter()
+                .all(|&field| same_value(&existing[field], &tuple[field]))
+        };
+        let duplicate = relation.tuples().any(|existing| same(&existing))
+            || pending.iter().any(|existing| same(existing));
+        if duplicate {
+            Err(format!(
+                "values of fields {:?} already present",
+                self.fields
+            ))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Every tuple has to satisfy a predicate
+pub struct Check {
+    name: String,
+    predicate: Box<dyn Fn(&Tuple) -> bool + Send + Sync>,
+}
+
+impl Check {
+    pub fn new<S, F>(name: S, predicate: F) -> Self
+    where
+        S: ToString,
+        F: Fn(&Tuple) -> bool + Send + Sync + 'static,
+    {
+        Check {
+            name: name.to_string(),
+            predicate: Box::new(predicate),
+        }
+    }
+}
+
+impl Constraint for Check {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn check(&self, tuple: &Tuple, _: &Relation, _: &dyn RelationLookup) -> Result<(), String> {
+        if (self.predicate)(tuple) {
+            Ok(())
+        } else {
+            Err("check failed".to_string())
+        }
+    }
+}
+
+/// The values of the fields have to be present in another relation. Tuples with a `NULL` in
+/// any of the fields don't reference anything.
+pub struct ForeignKey {
+    name: String,
+    definition: ForeignKeyDefinition,
+}
+
+impl ForeignKey {
+    pub fn new<S: ToString>(name: S, definition: ForeignKeyDefinition) -> Self {
+        ForeignKey {
+            name: name.to_string(),
+            definition,
+        }
+    }
+}
+
+impl Constraint for ForeignKey {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn check(
+        &self,
+        tuple: &Tuple,
+        relation: &Relation,
+        others: &dyn RelationLookup,
+    ) -> Result<(), String> {
+        self.check_pending(tuple, &[], relation, others)
+    }
+
+    /// A key that references its own relation can also be present in the tuple itself or in
+    /// the pending tuples
+    fn check_pending(
+        &self,
+        tuple: &Tuple,
+        pending: &[&Tuple],
+        relation: &Relation,
+        others: &dyn RelationLookup,
+    ) -> Result<(), String> {
+        let definition = &self.definition;
+        if definition
+            .fields()
+            .iter()
+            .any(|&field| tuple[field].is_null())
+        {
+            return Ok(());
+        }
+        let matches = |existing: &Tuple| {
+            definition
+                .fields()
+                .iter()
+                .zip(definition.referenced_fields())
+                .all(|(&field, &referenced_field)| {
+                    same_value(&existing[referenced_field], &tuple[field])
+                })
+        };
+        let present = if definition.referenced() == relation.name() {
+            matches(tuple)
+                || pending.iter().any(|existing| matches(existing))
+                || relation.tuples().any(|existing| matches(&existing))
+        } else {
+            let referenced = others.lookup(definition.referenced()).ok_or_else(|| {
+                format!(
+                    "referenced relation {} isn't available",
+                    definition.referenced()
+                )
+            })?;
+            referenced.tuples().any(|existing| matches(&existing))
+        };
+        if present {
+            Ok(())
+        } else {
+            Err(format!(
+                "values of fields {:?} not present in {}",
+                definition.fields(),
+                definition.referenced()
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rad_db_types::Type;
+
+    use super::*;
+    use crate::key::primary::PrimaryKeyDefinition;
+    use crate::relations::tuple_storage::TupleInsertionError;
+
+    #[test]
+    fn constraints_are_checked_on_insert() {
+        let mut parents = Relation::new_volatile(
+            Identifier::new("parents"),
+            vec![("id", Type::from(0u32))],
+            4,
+            PrimaryKeyDefinition::new(vec![0]),
+        );
+        parents.insert(Tuple::new(vec![Value::from(1u32)])).unwrap();
+
+        let mut children = Relation::new_volatile(
+            Identifier::new("children"),
+            vec![
+                ("id", Type::from(0u32)),
+                ("parent", Type::from(0u32).nullable()),
+                ("code", Type::from(0u32).nullable()),
+            ],
+            4,
+            PrimaryKeyDefinition::new(vec![0]),
+        );
+        children.add_constraint(Unique::new("unique_code", vec![2]));
+        children.add_constraint(Check::new("positive_id", |tuple: &Tuple| {
+            tuple[0] != Value::from(0u32)
+        }));
+        children.add_constraint(ForeignKey::new(
+            "parent_exists",
+            ForeignKeyDefinition::new(vec![1], "parents", vec![0]),
+        ));
+        assert_eq!(
+            children.constraints().names().collect::<Vec<_>>(),
+            vec!["unique_code", "positive_id", "parent_exists"]
+        );
+
+        let others = [&parents];
+        let tuple = |id: u32, parent: Option<u32>, code: Option<u32>| {
+            Tuple::new(vec![
+                Value::from(id),
+                Value::Optional(parent.map(|p| Box::new(Value::from(p)))),
+                Value::Optional(code.map(|c| Box::new(Value::from(c)))),
+            ])
+        };
+        children
+            .insert_with(tuple(1, Some(1), Some(7)), &others)
+            .unwrap();
+        children.insert_with(tuple(2, None, None), &others).unwrap();
+        children.insert_with(tuple(3, None, None), &others).unwrap();
+
+        match children.insert_with(tuple(0, Some(2), Some(7)), &others) {
+            Err(TupleInsertionError::ConstraintViolations(violations)) => {
+                let names: Vec<_> = violations.iter().map(|v| &*v.constraint).collect();
+                assert_eq!(names, vec!["unique_code", "positive_id", "parent_exists"]);
+            }
+            other => panic!("expected constraint violations, got {:?}", other),
+        }
+        assert!(children.insert(tuple(4, Some(1), None)).is_err());
+        assert_eq!(children.len(), 3);
+
+        children.add_constraint(NotNull::new("has_code", 2));
+        assert!(children.insert(tuple(5, None, None)).is_err());
+        assert!(children.remove_constraint("has_code"));
+        assert!(children.insert(tuple(5, None, None)).is_ok());
+    }
+
+    #[test]
+    fn deferred_constraints_in_batches() {
+        let mut employees = Relation::new_volatile(
+            Identifier::new("employees"),
+            vec![
+                ("id", Type::from(0u32)),
+                ("manager", Type::from(0u32).nullable()),
+            ],
+            4,
+            PrimaryKeyDefinition::new(vec![0]),
+        );
+        employees.add_constraint(ForeignKey::new(
+            "manager_exists",
+            ForeignKeyDefinition::new(vec![1], "employees", vec![0]),
+        ));
+        employees.add_constraint(Unique::new("unique_id", vec![0]));
+        let tuple = |id: u32, manager: u32| {
+            Tuple::new(vec![
+                Value::from(id),
+                Value::Optional(Some(Box::new(Value::from(manager)))),
+            ])
+        };
+        // the first two report to each other, and the last one reports to itself
+        let batch = vec![tuple(1, 2), tuple(2, 1), tuple(3, 3)];
+
+        let rejected = employees.constraints().check_batch(&batch, &employees, &());
+        let rejected: Vec<usize> = rejected.into_iter().map(|(index, _)| index).collect();
+        assert_eq!(rejected, vec![0]);
+
+        assert!(employees.defer_constraint("manager_exists", true));
+        assert!(!employees.defer_constraint("missing", true));
+        assert!(employees.constraints().is_deferred("manager_exists"));
+        assert!(employees
+            .constraints()
+            .check_batch(&batch, &employees, &())
+            .is_empty());
+
+        // a duplicate is only reported for the later tuple, unless the check is deferred
+        let duplicates = vec![tuple(1, 1), tuple(1, 1)];
+        let rejected = employees
+            .constraints()
+            .check_batch(&duplicates, &employees, &());
+        assert_eq!(rejected.len(), 1);
+        assert_eq!(rejected[0].0, 1);
+        assert_eq!(rejected[0].1[0].constraint, "unique_id");
+        employees.defer_constraint("unique_id", true);
+        let rejected = employees
+            .constraints()
+            .check_batch(&duplicates, &employees, &());
+        assert_eq!(rejected.len(), 2);
+    }
+}
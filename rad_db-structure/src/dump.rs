@@ -0,0 +1,121 @@
+//! Dumps relations as the SQL statements that would recreate them
+
+use std::io;
+use std::io::{Read, Write};
+
+use rad_db_types::sql::{quote_identifier, sql_column_type, sql_literal};
+use rad_db_types::{Text, Type, Value};
+
+use crate::relations::Relation;
+
+/// Writes a `CREATE TABLE` statement for each relation, followed by an `INSERT` statement for
+/// each of its tuples
+pub fn dump<'a, W, I>(relations: I, writer: &mut W) -> io::Result<()>
+where
+    W: Write,
+    I: IntoIterator<Item = &'a Relation>,
+{
+    for relation in relations {
+        dump_relation(relation, writer)?;
+    }
+    Ok(())
+}
+
+/// Writes the statements that recreate a single relation
+pub fn dump_relation<W: Write>(relation: &Relation, writer: &mut W) -> io::Result<()> {
+    dump_definition(relation, writer)?;
+    let table = quote_identifier(&relation.name().to_string());
+    let columns: Vec<_> = relation
+        .attributes()
+        .iter()
+        .map(|(name, _)| quote_identifier(name))
+        .collect();
+    let columns = columns.join(", ");
+    for tuple in relation.tuples() {
+        let values = tuple
+            .iter()
+            .map(|value| literal(relation, value))
+            .collect::<io::Result<Vec<_>>>()?;
+        writeln!(
+            writer,
+            "INSERT INTO {} ({}) VALUES ({});",
+            table,
+            columns,
+            values.join(", ")
+        )?;
+    }
+    Ok(())
+}
+
+/// The literal of a value, where blobs stored out of line are read so that the dump holds them
+fn literal(relation: &Relation, value: &Value) -> io::Result<String> {
+    match value {
+        Type::Optional(Some(inner)) => literal(relation, inner),
+        Type::Text(Text::BlobRef(_)) => {
+            let mut bytes = vec![];
+            relation.read_blob(value)?.read_to_end(&mut bytes)?;
+            Ok(sql_literal(&Type::Text(Text::Blob(bytes))))
+        }
+        value => Ok(sql_literal(value)),
+    }
+}
+
+/// Writes the `CREATE TABLE` statement of a relation, without its tuples
+pub fn dump_definition<W: Write>(relation: &Relation, writer: &mut W) -> io::Result<()> {
+    let table = quote_identifier(&relation.name().to_string());
+    let attributes = relation.attributes();
+
+    writeln!(writer, "CREATE TABLE {} (", table)?;
+    for (name, ty) in attributes {
+        writeln!(
+            writer,
+            "    {} {},",
+            quote_identifier(name),
+            sql_column_type(ty)
+        )?;
+    }
+    let key: Vec<_> = relation
+        .primary_key()
+        .iter()
+        .map(|&field| quote_identifier(&attributes[field].0))
+        .collect();
+    writeln!(writer, "    PRIMARY KEY ({})", key.join(", "))?;
+    writeln!(writer, ");")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use rad_db_types::{Type, Value};
+
+    use super::*;
+    use crate::identifier::Identifier;
+    use crate::key::primary::PrimaryKeyDefinition;
+    use crate::tuple::Tuple;
+
+    #[test]
+    fn dump_writes_create_and_inserts() {
+        let mut relation = Relation::new_volatile(
+            Identifier::new("people"),
+            vec![
+                ("id", Type::from(0u32)),
+                ("name", Type::Optional(Some(Box::new(Type::from(""))))),
+            ],
+            4,
+            PrimaryKeyDefinition::new(vec![0]),
+        );
+        relation
+            .insert(Tuple::new(vec![Value::from(1u32), Value::from("O'Neil")]))
+            .unwrap();
+
+        let mut output = Vec::new();
+        dump([&relation], &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(
+            output,
+            "CREATE TABLE \"people\" (\n    \"id\" BIGINT NOT NULL,\n    \"name\" TEXT,\n    \
+             PRIMARY KEY (\"id\")\n);\n\
+             INSERT INTO \"people\" (\"id\", \"name\") VALUES (1, 'O''Neil');\n"
+        );
+    }
+}
@@ -0,0 +1,139 @@
+//! Relations can retain the earlier versions of their tuples, so that they can be read as they
+//! were at a point in the past.
+
+use std::time::{Duration, SystemTime};
+
+use crate::tuple::Tuple;
+
+/// How much history vacuuming keeps
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum RetentionPolicy {
+    /// Every version is kept
+    #[default]
+    Forever,
+    /// Versions are kept until they have been replaced for longer than the duration
+    For(Duration),
+}
+
+/// A version of a tuple, and the time that it was current for
+#[derive(Debug, Clone)]
+pub struct TupleVersion {
+    pub tuple: Tuple,
+    pub created: SystemTime,
+    /// When the version was replaced, if it has been
+    pub replaced: Option<SystemTime>,
+}
+
+impl TupleVersion {
+    /// Whether this was the current version of the tuple at the time
+    pub fn is_current_at(&self, time: SystemTime) -> bool {
+        self.created <= time && self.replaced.map_or(true, |replaced| replaced > time)
+    }
+}
+
+/// The versions of the tuples of a relation
+#[derive(Debug, Clone, Default)]
+pub struct VersionHistory {
+    versions: Vec<TupleVersion>,
+    retention: RetentionPolicy,
+}
+
+impl VersionHistory {
+    pub fn new(retention: RetentionPolicy) -> Self {
+        VersionHistory {
+            versions: vec![],
+            retention,
+        }
+    }
+
+    pub fn retention(&self) -> RetentionPolicy {
+        self.retention
+    }
+
+    pub fn set_retention(&mut self, retention: RetentionPolicy) {
+        self.retention = retention;
+    }
+
+    /// Records that the tuple was stored at the time, replacing the current version of the
+    /// `replaced` tuple if there was one
+    pub fn record(&mut self, tuple: Tuple, replaced: Option<&Tuple>, time: SystemTime) {
+        if let Some(replaced) = replaced {
+            if let Some(version) = self
+                .versions
+                .iter_mut()
+                .rev()
+                .find(|version| version.replaced.is_none() && &version.tuple == replaced)
+            {
+                version.replaced = Some(time);
+            }
+        }
+        self.versions.push(TupleVersion {
+            tuple,
+            created: time,
+            replaced: None,
+        });
+    }
+
+    pub fn versions(&self) -> &[TupleVersion] {
+        &self.versions
+    }
+
+    /// The tuples of the relation as they were at the time
+    pub fn as_of(&self, time: SystemTime) -> impl Iterator<Item = &Tuple> {
+        self.versions
+            .iter()
+            .filter(move |version| version.is_current_at(time))
+            .map(|version| &version.tuple)
+    }
+
+    /// Removes the versions that the retention policy no longer keeps, returning how many were
+    /// removed. Versions that are still current are always kept.
+    pub fn vacuum(&mut self, now: SystemTime) -> usize {
+        let horizon = match self.retention {
+            RetentionPolicy::Forever => return 0,
+            RetentionPolicy::For(duration) => match now.checked_sub(duration) {
+                Some(horizon) => horizon,
+                None => return 0,
+            },
+        };
+        let len = self.versions.len();
+        self.versions
+            .retain(|version| version.replaced.map_or(true, |replaced| replaced > horizon));
+        len - self.versions.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rad_db_types::Value;
+
+    use super::*;
+
+    fn tuple(key: u32, value: &str) -> Tuple {
+        Tuple::new(vec![Value::from(key), Value::from(value)])
+    }
+
+    #[test]
+    fn versions_are_read_as_of_a_time() {
+        let start = SystemTime::UNIX_EPOCH;
+        let at = |secs: u64| start + Duration::from_secs(secs);
+        let mut history = VersionHistory::new(RetentionPolicy::For(Duration::from_secs(10)));
+        history.record(tuple(1, "a"), None, at(1));
+        history.record(tuple(2, "b"), None, at(2));
+        history.record(tuple(1, "c"), Some(&tuple(1, "a")), at(3));
+
+        assert_eq!(history.as_of(at(0)).count(), 0);
+        assert_eq!(
+            history.as_of(at(2)).cloned().collect::<Vec<_>>(),
+            vec![tuple(1, "a"), tuple(2, "b")]
+        );
+        assert_eq!(
+            history.as_of(at(3)).cloned().collect::<Vec<_>>(),
+            vec![tuple(2, "b"), tuple(1, "c")]
+        );
+
+        assert_eq!(history.vacuum(at(12)), 0);
+        assert_eq!(history.vacuum(at(14)), 1);
+        assert_eq!(history.versions().len(), 2);
+    }
+}
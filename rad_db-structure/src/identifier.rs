@@ -1,12 +1,74 @@
-use std::collections::VecDeque;
+use std::borrow::Cow;
+use std::collections::{HashSet, VecDeque};
 use std::fmt::{Display, Formatter};
+use std::hash::{Hash, Hasher};
 use std::iter::FromIterator;
 use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+/// A possibly namespaced name. The parts of the name are interned, so cloning, comparing and
+/// hashing identifiers doesn't touch the strings themselves.
+#[derive(Debug, Clone)]
 pub struct Identifier {
-    parent: Option<Box<Identifier>>,
-    base: String,
+    parent: Option<Arc<Identifier>>,
+    base: Arc<str>,
+}
+
+/// How the case of names is treated when they are resolved
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CasePolicy {
+    /// Names only match if they have the same case
+    Preserve,
+    /// Names are folded to lowercase, so `Users` and `users` are the same name
+    LowercaseFold,
+}
+
+impl Default for CasePolicy {
+    fn default() -> Self {
+        CasePolicy::Preserve
+    }
+}
+
+impl CasePolicy {
+    /// Gets the form of a name that is used for comparisons
+    pub fn normalize<'a>(&self, name: &'a str) -> Cow<'a, str> {
+        match self {
+            CasePolicy::LowercaseFold if name.chars().any(char::is_uppercase) => {
+                Cow::Owned(name.to_lowercase())
+            }
+            _ => Cow::Borrowed(name),
+        }
+    }
+
+    /// Checks if two names refer to the same thing
+    pub fn names_match(&self, left: &str, right: &str) -> bool {
+        left == right || self.normalize(left) == self.normalize(right)
+    }
+
+    /// Checks if every part of two identifiers refer to the same thing
+    pub fn identifiers_match(&self, left: &Identifier, right: &Identifier) -> bool {
+        left == right
+            || left.len() == right.len()
+                && left
+                    .into_iter()
+                    .zip(right)
+                    .all(|(left, right)| self.names_match(left, right))
+    }
+}
+
+/// Gets the single shared copy of a string
+fn intern(string: &str) -> Arc<str> {
+    static INTERNED: OnceLock<Mutex<HashSet<Arc<str>>>> = OnceLock::new();
+    let mut interned = INTERNED
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    if let Some(existing) = interned.get(string) {
+        return existing.clone();
+    }
+    let string: Arc<str> = Arc::from(string);
+    interned.insert(string.clone());
+    string
 }
 
 impl Identifier {
@@ -14,15 +76,15 @@ impl Identifier {
     pub fn new<S: AsRef<str>>(string: S) -> Self {
         Identifier {
             parent: None,
-            base: string.as_ref().to_string(),
+            base: intern(string.as_ref()),
         }
     }
 
     /// Creates an identifier within a parent namespace
     pub fn with_parent<S: AsRef<str>>(parent: &Identifier, string: S) -> Self {
         Identifier {
-            parent: Some(Box::new(parent.clone())),
-            base: string.as_ref().to_string(),
+            parent: Some(Arc::new(parent.clone())),
+            base: intern(string.as_ref()),
         }
     }
 
@@ -30,11 +92,11 @@ impl Identifier {
         let mut output = child.into();
         let output_first = output.first_mut();
         let parent = parent.into();
-        output_first.parent = Some(Box::new(parent));
+        output_first.parent = Some(Arc::new(parent));
         output
     }
 
-    pub fn base(&self) -> &String {
+    pub fn base(&self) -> &str {
         &self.base
     }
 
@@ -57,7 +119,7 @@ impl Identifier {
     fn first_mut(&mut self) -> &mut Identifier {
         if self.parent.is_some() {
             let parent = self.parent.as_mut().unwrap();
-            parent.first_mut()
+            Arc::make_mut(parent).first_mut()
         } else {
             self
         }
@@ -73,7 +135,7 @@ impl Identifier {
     pub fn strip_highest_parent(&self) -> Option<Identifier> {
         let parent = match &self.parent {
             None => return None,
-            Some(parent) => parent.strip_highest_parent().map(|id| Box::new(id)),
+            Some(parent) => parent.strip_highest_parent().map(Arc::new),
         };
 
         Some(Self {
@@ -82,6 +144,11 @@ impl Identifier {
         })
     }
 
+    /// Gets this identifier with every part normalized by the policy
+    pub fn normalized(&self, policy: CasePolicy) -> Identifier {
+        Identifier::from_iter(self.into_iter().map(|part| policy.normalize(part)))
+    }
+
     /// Returns the length of the identifier
     pub fn len(&self) -> usize {
         1 + match &self.parent {
@@ -120,9 +187,9 @@ impl IntoIterator for Identifier {
         let mut ptr = self;
         loop {
             let Identifier { parent, base } = ptr;
-            ret.push_front(base);
+            ret.push_front(base.to_string());
             if let Some(parent) = parent {
-                ptr = *parent;
+                ptr = Arc::try_unwrap(parent).unwrap_or_else(|parent| (*parent).clone());
             } else {
                 break;
             }
@@ -145,6 +212,28 @@ impl<A: AsRef<str>> FromIterator<A> for Identifier {
     }
 }
 
+impl PartialEq for Identifier {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.base, &other.base)
+            && match (&self.parent, &other.parent) {
+                (None, None) => true,
+                (Some(parent), Some(other_parent)) => {
+                    Arc::ptr_eq(parent, other_parent) || parent == other_parent
+                }
+                _ => false,
+            }
+    }
+}
+
+impl Eq for Identifier {}
+
+impl Hash for Identifier {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (self.base.as_ptr() as usize).hash(state);
+        self.parent.hash(state);
+    }
+}
+
 impl Display for Identifier {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match &self.parent {
@@ -188,8 +277,10 @@ impl<I: Into<Identifier> + ToOwned<Owned = I>> From<&I> for Identifier {
 
 #[cfg(test)]
 mod tests {
-    use crate::identifier::Identifier;
+    use crate::identifier::{CasePolicy, Identifier};
+    use std::collections::HashSet;
     use std::iter::FromIterator;
+    use std::sync::Arc;
 
     #[test]
     fn id_length() {
@@ -214,6 +305,41 @@ mod tests {
         Identifier::from_iter(Vec::<String>::new());
     }
 
+    #[test]
+    fn ids_are_interned() {
+        let first = Identifier::from_iter(vec!["db", "table", "field"]);
+        let second =
+            Identifier::from_iter(vec![String::from("db"), "table".into(), "field".into()]);
+        assert!(Arc::ptr_eq(&first.base, &second.base));
+        assert_eq!(first, second);
+        assert_ne!(first, Identifier::from_iter(vec!["db", "other", "field"]));
+        assert_ne!(first, Identifier::new("field"));
+
+        let set: HashSet<_> = vec![first.clone(), second, first.clone()]
+            .into_iter()
+            .collect();
+        assert_eq!(set.len(), 1);
+        assert!(set.contains(&Identifier::concat(
+            "db",
+            Identifier::from_iter(&["table", "field"])
+        )));
+        assert_eq!(
+            first.into_iter().collect::<Vec<_>>(),
+            vec!["db", "table", "field"]
+        );
+    }
+
+    #[test]
+    fn case_policies() {
+        let upper = Identifier::from_iter(&["Users", "ID"]);
+        let lower = Identifier::from_iter(&["users", "id"]);
+        assert!(!CasePolicy::Preserve.identifiers_match(&upper, &lower));
+        assert!(CasePolicy::LowercaseFold.identifiers_match(&upper, &lower));
+        assert!(!CasePolicy::LowercaseFold.identifiers_match(&upper, &Identifier::new("id")));
+        assert_eq!(upper.normalized(CasePolicy::LowercaseFold), lower);
+        assert_eq!(upper.normalized(CasePolicy::Preserve), upper);
+    }
+
     #[test]
     fn id_display() {
         let single = format!("{}", Identifier::new("table"));
@@ -0,0 +1,319 @@
+//! Encodes keys into bytes that sort the same way as the values they came from, so that keys
+//! can be compared bytewise and kept in ordered indexes.
+//!
+//! Every value starts with a marker byte, so `NULL` sorts before every other value. Integers
+//! are written big endian, with the sign bit of signed integers flipped. Decimals are written
+//! like 128 bit integers of their mantissa, as every value of a field has the same scale. Wide
+//! integers are written in two's complement across every byte their bits need. Floats
+//! have their sign bit flipped when positive and every bit flipped when negative. Strings and
+//! byte strings escape `0x00` as `0x00 0xFF` and end with `0x00 0x00`, so a string sorts before
+//! any longer string it is a prefix of, and the values after it in a composite key can't be
+//! mistaken for part of it. Arrays write `0x01` before each element and end with `0x00`, which
+//! sorts them the same way. Enumerations are written as their ordinal, so they sort in the order
+//! their labels were declared. Bit strings are written as their packed bytes like byte strings,
+//! followed by their length. Points are written as their two coordinates, like doubles. IP
+//! addresses are written as their version, their bytes and their prefix, and MAC addresses as
+//! their bytes.
+
+use std::fmt::{Debug, Formatter};
+
+use chrono::{Datelike, Timelike};
+use rad_db_types::{
+    canonical_bits, canonical_bits32, Numeric, Signed, Text, Time, Type, Unsigned, Value,
+};
+
+const NULL: u8 = 0x00;
+const NOT_NULL: u8 = 0x01;
+/// Comes before each element of an array, so an array sorts before any longer array it is a
+/// prefix of
+const ELEMENT: u8 = 0x01;
+const END_OF_ARRAY: u8 = 0x00;
+
+/// A key encoded so that comparing the bytes compares the values
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct EncodedKey(Vec<u8>);
+
+impl EncodedKey {
+    /// Encodes the values of a key, in order
+    pub fn new<'a, I: IntoIterator<Item = &'a Value>>(values: I) -> Self {
+        let mut bytes = Vec::new();
+        for value in values {
+            encode_value(value, &mut bytes);
+        }
+        EncodedKey(bytes)
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.0
+    }
+}
+
+impl Debug for EncodedKey {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "EncodedKey(")?;
+        for byte in &self.0 {
+            write!(f, "{:02x}", byte)?;
+        }
+        write!(f, ")")
+    }
+}
+
+/// Appends the order-preserving encoding of a value
+pub fn encode_value(value: &Value, output: &mut Vec<u8>) {
+    match value {
+        Type::Optional(None) => {
+            output.push(NULL);
+            return;
+        }
+        Type::Optional(Some(inner)) => return encode_value(inner, output),
+        _ => output.push(NOT_NULL),
+    }
+    match value {
+        Type::Numeric(numeric) => encode_numeric(numeric, output),
+        Type::Text(text) => match text {
+            Text::Char(c) => encode_bytes(c.to_string().as_bytes(), output),
+            Text::String(s, _) => encode_bytes(s.as_bytes(), output),
+            Text::Binary(b) => output.push(*b),
+            Text::BinaryString(b, _) | Text::Blob(b) => encode_bytes(b, output),
+            Text::BlobRef(handle) => encode_bytes(handle.to_string().as_bytes(), output),
+            // the bits after the end are zero, so the length breaks ties between a bit string and
+            // the longer bit strings it starts
+            Text::BitString(bits, _) => {
+                encode_bytes(bits.as_bytes(), output);
+                output.extend_from_slice(&(bits.len() as u64).to_be_bytes());
+            }
+        },
+        Type::Time(time) => match time {
+            Time::Date(date) => encode_signed(date.num_days_from_ce() as i64, 4, output),
+            Time::TimeOfDay(time) => {
+                output.extend_from_slice(&time.num_seconds_from_midnight().to_be_bytes());
+                output.extend_from_slice(&time.nanosecond().to_be_bytes());
+            }
+            Time::DateTime(date_time) => {
+                let date_time = date_time.and_utc();
+                encode_signed(date_time.timestamp(), 8, output);
+                output.extend_from_slice(&date_time.timestamp_subsec_nanos().to_be_bytes());
+            }
+            Time::Timestamp(timestamp) => {
+                encode_signed(timestamp.timestamp(), 8, output);
+                output.extend_from_slice(&timestamp.timestamp_subsec_nanos().to_be_bytes());
+            }
+            // ordered by the instant, like they compare
+            Time::TimestampTz(timestamp) => {
+                encode_signed(timestamp.timestamp(), 8, output);
+                output.extend_from_slice(&timestamp.timestamp_subsec_nanos().to_be_bytes());
+            }
+            Time::Year(year) => encode_signed(*year as i64, 4, output),
+        },
+        Type::Boolean(b) => output.push(*b as u8),
+        Type::Point(point) => {
+            encode_double(point.x(), output);
+            encode_double(point.y(), output);
+        }
+        Type::Inet(inet) => output.extend_from_slice(&inet.to_bytes()),
+        Type::MacAddress(address) => output.extend_from_slice(&address.bytes()),
+        Type::Extension(id, bytes) => {
+            output.extend_from_slice(&id.to_be_bytes());
+            encode_bytes(bytes, output);
+        }
+        Type::Array(values, _) => {
+            for value in values {
+                output.push(ELEMENT);
+                encode_value(value, output);
+            }
+            output.push(END_OF_ARRAY);
+        }
+        Type::Enum(e) => output.extend_from_slice(&e.ordinal().to_be_bytes()),
+        Type::Optional(_) => unreachable!(),
+    }
+}
+
+fn encode_numeric(numeric: &Numeric, output: &mut Vec<u8>) {
+    match numeric {
+        Numeric::Unsigned(unsigned) => match unsigned {
+            Unsigned::Byte(u) => output.push(*u),
+            Unsigned::Short(u) => output.extend_from_slice(&u.to_be_bytes()),
+            Unsigned::Int(u) => output.extend_from_slice(&u.to_be_bytes()),
+            Unsigned::Long(u) => output.extend_from_slice(&u.to_be_bytes()),
+        },
+        Numeric::Signed(signed) => match signed {
+            Signed::Byte(i) => encode_signed(*i as i64, 1, output),
+            Signed::Short(i) => encode_signed(*i as i64, 2, output),
+            Signed::Int(i) => encode_signed(*i as i64, 4, output),
+            Signed::Long(i) => encode_signed(*i, 8, output),
+        },
+        Numeric::Float(f) => {
+            let bits = canonical_bits32(*f);
+            let ordered = if bits >> 31 == 1 {
+                !bits
+            } else {
+                bits | 1 << 31
+            };
+            output.extend_from_slice(&ordered.to_be_bytes());
+        }
+        Numeric::Double(d) => encode_double(*d, output),
+        Numeric::Decimal(decimal) => {
+            let ordered = decimal.mantissa() as u128 ^ 1 << 127;
+            output.extend_from_slice(&ordered.to_be_bytes());
+        }
+        Numeric::BigInt(integer) => {
+            let width = (integer.bits() as usize + 7) / 8;
+            let bytes = integer.value().to_signed_bytes_be();
+            let extension = if bytes[0] & 0x80 != 0 { 0xFF } else { 0x00 };
+            let start = output.len();
+            output.extend(std::iter::repeat(extension).take(width - bytes.len()));
+            output.extend(bytes);
+            output[start] ^= 0x80;
+        }
+    }
+}
+
+/// Writes the double so that doubles which are equal, like `0.0` and `-0.0` or any two `NaN`s,
+/// are encoded the same way
+fn encode_double(double: f64, output: &mut Vec<u8>) {
+    let bits = canonical_bits(double);
+    let ordered = if bits >> 63 == 1 {
+        !bits
+    } else {
+        bits | 1 << 63
+    };
+    output.extend_from_slice(&ordered.to_be_bytes());
+}
+
+/// Writes the lowest `width` bytes of the integer with the sign bit of that width flipped
+fn encode_signed(value: i64, width: usize, output: &mut Vec<u8>) {
+    let bytes = value.to_be_bytes();
+    let mut bytes = bytes[8 - width..].to_vec();
+    bytes[0] ^= 0x80;
+    output.extend(bytes);
+}
+
+fn encode_bytes(bytes: &[u8], output: &mut Vec<u8>) {
+    for &byte in bytes {
+        output.push(byte);
+        if byte == 0x00 {
+            output.push(0xFF);
+        }
+    }
+    output.extend_from_slice(&[0x00, 0x00]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rad_db_types::{BitString, BitWidth, Decimal, Enumeration, Inet, Integer};
+
+    fn assert_ordered(values: Vec<Vec<Value>>) {
+        let keys: Vec<_> = values.iter().map(|key| EncodedKey::new(key)).collect();
+        for pair in keys.windows(2) {
+            assert!(
+                pair[0] < pair[1],
+                "{:?} should be before {:?}",
+                pair[0],
+                pair[1]
+            );
+        }
+    }
+
+    #[test]
+    fn encoding_preserves_order() {
+        let null = Value::Optional(None);
+        assert_ordered(
+            vec![
+                null.clone(),
+                Value::from(-300i32),
+                Value::from(-1i32),
+                Value::from(0i32),
+                Value::from(7i32),
+            ]
+            .into_iter()
+            .map(|v| vec![v])
+            .collect(),
+        );
+        assert_ordered(
+            vec![-2.5f64, -0.5, 0.0, 0.25, 1e10]
+                .into_iter()
+                .map(|v| vec![Value::Numeric(Numeric::Double(v))])
+                .collect(),
+        );
+        assert_ordered(
+            vec!["-12.5", "-0.01", "0", "0.2", "3.14"]
+                .into_iter()
+                .map(|v| vec![Value::from(Decimal::parse(v, 6, 2).unwrap())])
+                .collect(),
+        );
+        assert_ordered(
+            vec![-(1i128 << 99), -256, -1, 0, 255, (1 << 99) - 1]
+                .into_iter()
+                .map(|v| vec![Value::from(Integer::new(v, 100).unwrap())])
+                .collect(),
+        );
+        assert_ordered(
+            vec!["", "a", "a\0", "a\0b", "ab", "b"]
+                .into_iter()
+                .map(|v| vec![Value::from(v)])
+                .collect(),
+        );
+        assert_ordered(vec![
+            vec![Value::from("a"), Value::from(9u32)],
+            vec![Value::from("ab"), Value::from(1u32)],
+            vec![Value::from("b"), null.clone()],
+            vec![Value::from("b"), Value::from(0u32)],
+        ]);
+        let array = |values: Vec<u32>| {
+            vec![Type::array(
+                Type::from(0u32),
+                values.into_iter().map(Value::from),
+            )]
+        };
+        assert_ordered(vec![
+            array(vec![]),
+            array(vec![0]),
+            array(vec![0, 5]),
+            array(vec![1]),
+        ]);
+        let priority = Enumeration::new(vec!["low", "high", "critical"], 0).unwrap();
+        assert_ordered(
+            vec!["low", "high", "critical"]
+                .into_iter()
+                .map(|label| vec![Value::from(priority.with_label(label).unwrap())])
+                .collect(),
+        );
+        assert_ordered(
+            vec!["", "01", "0111", "1", "10", "100000000", "100000001", "101"]
+                .into_iter()
+                .map(|bits| {
+                    let bits = BitString::parse(bits).unwrap();
+                    vec![Value::from(Text::BitString(bits, BitWidth::Varying(None)))]
+                })
+                .collect(),
+        );
+        assert_ordered(
+            vec!["9.0.0.0/8", "10.0.0.0/8", "10.0.0.0/16", "10.0.0.1", "::1"]
+                .into_iter()
+                .map(|inet| vec![Value::from(Inet::parse(inet).unwrap())])
+                .collect(),
+        );
+        assert_eq!(
+            EncodedKey::new(&[Value::Optional(Some(Box::new(Value::from(3u8))))]),
+            EncodedKey::new(&[Value::from(3u8)])
+        );
+    }
+
+    #[test]
+    fn equal_floats_encode_equally() {
+        let double = |d: f64| EncodedKey::new(&[Value::Numeric(Numeric::Double(d))]);
+        let float = |f: f32| EncodedKey::new(&[Value::Numeric(Numeric::Float(f))]);
+        assert_eq!(double(-0.0), double(0.0));
+        assert_eq!(float(-0.0), float(0.0));
+        let other_nan = f64::from_bits(f64::NAN.to_bits() | 1);
+        assert_eq!(double(other_nan), double(f64::NAN));
+        assert_eq!(double(-f64::NAN), double(f64::NAN));
+        let other_nan = f32::from_bits(f32::NAN.to_bits() | 1);
+        assert_eq!(float(other_nan), float(f32::NAN));
+    }
+}
@@ -1 +1,43 @@
+use crate::identifier::Identifier;
 
+/// Fields of a relation whose values refer to fields of another relation
+#[derive(Debug, Clone, PartialEq)]
+pub struct ForeignKeyDefinition {
+    fields: Vec<usize>,
+    referenced: Identifier,
+    referenced_fields: Vec<usize>,
+}
+
+impl ForeignKeyDefinition {
+    /// # Panic
+    /// Panics if there aren't as many fields as referenced fields
+    pub fn new<I: Into<Identifier>>(
+        fields: Vec<usize>,
+        referenced: I,
+        referenced_fields: Vec<usize>,
+    ) -> Self {
+        assert_eq!(
+            fields.len(),
+            referenced_fields.len(),
+            "A foreign key must reference as many fields as it has"
+        );
+        ForeignKeyDefinition {
+            fields,
+            referenced: referenced.into(),
+            referenced_fields,
+        }
+    }
+
+    pub fn fields(&self) -> &[usize] {
+        &self.fields
+    }
+
+    /// The name of the referenced relation
+    pub fn referenced(&self) -> &Identifier {
+        &self.referenced
+    }
+
+    pub fn referenced_fields(&self) -> &[usize] {
+        &self.referenced_fields
+    }
+}
@@ -0,0 +1,138 @@
+//! An ordered index of the primary keys of a relation, so that the tuples with keys in a range
+//! can be read in key order without scanning every block.
+//!
+//! Bounds are [encoded](crate::key::encoding) prefixes of keys. A key is compared to a bound by
+//! the prefix of its encoding that's as long as the bound, so a bound on the first fields of a
+//! composite key includes or excludes every key starting with those fields.
+
+use std::collections::BTreeMap;
+use std::ops::Bound;
+
+use num_bigint::BigUint;
+
+use crate::key::encoding::EncodedKey;
+
+/// The encoded primary keys of a relation, each with the hash its tuple is stored under, or
+/// with whatever else the index is kept for
+#[derive(Debug, Clone)]
+pub struct KeyIndex<T = BigUint> {
+    keys: BTreeMap<EncodedKey, T>,
+}
+
+impl<T> Default for KeyIndex<T> {
+    fn default() -> Self {
+        KeyIndex {
+            keys: BTreeMap::new(),
+        }
+    }
+}
+
+impl<T> KeyIndex<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// Adds a key, replacing the hash it had if it was already in the index
+    pub fn insert(&mut self, key: EncodedKey, hash: T) {
+        self.keys.insert(key, hash);
+    }
+
+    pub fn remove(&mut self, key: &EncodedKey) -> Option<T> {
+        self.keys.remove(key)
+    }
+
+    /// The hashes of the keys within the bounds, in key order
+    pub fn range<'i>(
+        &'i self,
+        lower: Bound<&EncodedKey>,
+        upper: Bound<&EncodedKey>,
+    ) -> impl Iterator<Item = &'i T> + 'i {
+        // every key starting with a prefix sorts after the prefix itself
+        let start = match lower {
+            Bound::Included(prefix) | Bound::Excluded(prefix) => Bound::Included(prefix.clone()),
+            Bound::Unbounded => Bound::Unbounded,
+        };
+        let excluded: Option<Vec<u8>> = match lower {
+            Bound::Excluded(prefix) => Some(prefix.as_bytes().to_vec()),
+            _ => None,
+        };
+        let upper: Bound<Vec<u8>> = match upper {
+            Bound::Included(prefix) => Bound::Included(prefix.as_bytes().to_vec()),
+            Bound::Excluded(prefix) => Bound::Excluded(prefix.as_bytes().to_vec()),
+            Bound::Unbounded => Bound::Unbounded,
+        };
+        self.keys
+            .range((start, Bound::Unbounded))
+            .skip_while(move |(key, _)| {
+                excluded
+                    .as_ref()
+                    .map_or(false, |prefix| key.as_bytes().starts_with(prefix))
+            })
+            .take_while(move |(key, _)| match &upper {
+                Bound::Included(prefix) => prefix_of(key, prefix.len()) <= &prefix[..],
+                Bound::Excluded(prefix) => prefix_of(key, prefix.len()) < &prefix[..],
+                Bound::Unbounded => true,
+            })
+            .map(|(_, hash)| hash)
+    }
+}
+
+fn prefix_of(key: &EncodedKey, length: usize) -> &[u8] {
+    let bytes = key.as_bytes();
+    &bytes[..length.min(bytes.len())]
+}
+
+#[cfg(test)]
+mod tests {
+    use rad_db_types::Value;
+
+    use super::*;
+
+    fn key(values: &[u64]) -> EncodedKey {
+        let values: Vec<Value> = values.iter().map(|&value| Value::from(value)).collect();
+        EncodedKey::new(&values)
+    }
+
+    #[test]
+    fn prefix_bounds() {
+        let mut index = KeyIndex::new();
+        let mut hash = 0u32;
+        for tenant in 0..4 {
+            for time in 0..3 {
+                index.insert(key(&[tenant, time]), BigUint::from(hash));
+                hash += 1;
+            }
+        }
+        let range = |lower: Bound<&EncodedKey>, upper: Bound<&EncodedKey>| -> Vec<u32> {
+            index
+                .range(lower, upper)
+                .map(|hash| hash.to_u32_digits().first().copied().unwrap_or(0))
+                .collect()
+        };
+
+        assert_eq!(
+            range(Bound::Included(&key(&[1])), Bound::Included(&key(&[2]))),
+            vec![3, 4, 5, 6, 7, 8]
+        );
+        assert_eq!(
+            range(Bound::Excluded(&key(&[1])), Bound::Excluded(&key(&[3]))),
+            vec![6, 7, 8]
+        );
+        assert_eq!(
+            range(Bound::Included(&key(&[2, 1])), Bound::Unbounded),
+            vec![7, 8, 9, 10, 11]
+        );
+        assert_eq!(
+            range(Bound::Unbounded, Bound::Excluded(&key(&[0, 2]))),
+            vec![0, 1]
+        );
+    }
+}
@@ -1,3 +1,6 @@
 pub mod candidate;
+pub mod encoding;
 pub mod foreign;
+pub mod index;
 pub mod primary;
+pub mod secondary;
@@ -1,4 +1,6 @@
 use std::collections::hash_map::DefaultHasher;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
 use std::hash::{Hash, Hasher};
 use std::ops::Deref;
 
@@ -7,18 +9,63 @@ use num_traits::FromPrimitive;
 use rad_db_types::{Numeric, SameType, Type};
 use seahash::SeaHasher;
 
-#[derive(Debug, Clone)]
-pub struct PrimaryKeyDefinition(Vec<usize>);
+use crate::key::encoding::EncodedKey;
+use crate::tuple::Tuple;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PrimaryKeyDefinition(Vec<usize>, [u64; 4]);
 
 impl PrimaryKeyDefinition {
+    /// Creates a primary key over the fields, with seeds derived from the positions of the
+    /// fields. Relations whose fields may be reordered should record [seeds](Self::seeds) and
+    /// use [with_seeds](Self::with_seeds) instead, as moving the fields changes these seeds.
     pub fn new(fields: Vec<usize>) -> Self {
-        PrimaryKeyDefinition(fields)
+        let seeds = Self::derive_seeds(&fields);
+        PrimaryKeyDefinition(fields, seeds)
+    }
+
+    /// Creates a primary key over the fields that hashes with the given seeds
+    pub fn with_seeds(fields: Vec<usize>, seeds: [u64; 4]) -> Self {
+        PrimaryKeyDefinition(fields, seeds)
+    }
+
+    /// Gets the seeds keys are hashed with
+    pub fn seeds(&self) -> [u64; 4] {
+        self.1
+    }
+
+    /// Checks that keys are hashed with the seeds stored data was hashed with
+    pub fn verify_seeds(&self, stored: [u64; 4]) -> Result<(), SeedMismatch> {
+        if self.1 == stored {
+            Ok(())
+        } else {
+            Err(SeedMismatch {
+                expected: stored,
+                found: self.1,
+            })
+        }
+    }
+
+    /// Gets the key of a tuple, made of the values of the key's fields in the order they
+    /// appear in the tuple
+    pub fn key_of<'a>(&self, tuple: &'a Tuple) -> PrimaryKey<'a> {
+        let values = tuple
+            .iter()
+            .enumerate()
+            .filter(|(pos, _)| self.0.contains(pos))
+            .map(|(_, val)| val)
+            .collect();
+        PrimaryKey::new(values, self.1)
     }
 
     pub(crate) fn create_seeds(&self) -> [u64; 4] {
+        self.1
+    }
+
+    fn derive_seeds(fields: &[usize]) -> [u64; 4] {
         let mut start: u64 = 0;
         let add = true;
-        for f in &self.0 {
+        for f in fields {
             if add {
                 start = start.wrapping_add(*f as u64);
             } else {
@@ -34,6 +81,26 @@ impl PrimaryKeyDefinition {
     }
 }
 
+/// A primary key would hash with different seeds than the stored data was hashed with, so no
+/// stored tuple could be found by its key
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SeedMismatch {
+    pub expected: [u64; 4],
+    pub found: [u64; 4],
+}
+
+impl Display for SeedMismatch {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Primary key seeds {:?} don't match the stored seeds {:?}",
+            self.found, self.expected
+        )
+    }
+}
+
+impl Error for SeedMismatch {}
+
 impl Deref for PrimaryKeyDefinition {
     type Target = Vec<usize>;
 
@@ -49,6 +116,11 @@ impl<'a> PrimaryKey<'a> {
         PrimaryKey(attributes, seeds)
     }
 
+    /// Encodes the key so that it can be compared bytewise
+    pub fn encode(&self) -> EncodedKey {
+        EncodedKey::new(self.0.iter().copied())
+    }
+
     pub fn hash(&self) -> BigUint {
         if self.len() == 1 {
             if let Type::Numeric(Numeric::Unsigned(unsigned)) = *self.0[0] {
@@ -86,7 +158,7 @@ impl PartialEq for PrimaryKey<'_> {
         }
 
         let mut self_iter = self.iter();
-        let mut other_iter = self.iter();
+        let mut other_iter = other.iter();
 
         while let (Some(mine), Some(theirs)) = (self_iter.next(), other_iter.next()) {
             if mine != theirs {
@@ -118,3 +190,23 @@ impl<'a> IntoIterator for PrimaryKey<'a> {
 }
 
 impl Eq for PrimaryKey<'_> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn explicit_seeds_survive_reordering() {
+        let value = Type::from("key");
+        let original = PrimaryKeyDefinition::new(vec![0]);
+        let moved = PrimaryKeyDefinition::new(vec![1]);
+        assert!(moved.verify_seeds(original.seeds()).is_err());
+
+        let moved = PrimaryKeyDefinition::with_seeds(vec![1], original.seeds());
+        assert_eq!(moved.verify_seeds(original.seeds()), Ok(()));
+        assert_eq!(
+            PrimaryKey::new(vec![&value], original.create_seeds()).hash(),
+            PrimaryKey::new(vec![&value], moved.create_seeds()).hash()
+        );
+    }
+}
@@ -0,0 +1,268 @@
+//! Ordered indexes on fields other than the primary key, so that the tuples with values of those
+//! fields in a range can be read without scanning every block.
+//!
+//! A partial index only holds the tuples a predicate matches, which keeps it small when queries
+//! only ever look for those tuples, such as the rows that haven't been deleted. Each entry is
+//! the [encoded](crate::key::encoding) values of the indexed fields followed by the primary key
+//! of its tuple, so tuples with the same indexed values are still kept apart.
+//!
+//! An index also keeps the values of the fields it covers, which are the indexed fields, the
+//! fields of the primary key, and any other fields included in it. A query that only needs
+//! those fields can be answered from the index alone.
+
+use std::any::Any;
+use std::ops::Bound;
+
+use num_bigint::BigUint;
+
+use crate::key::encoding::EncodedKey;
+use crate::key::index::KeyIndex;
+use crate::tuple::Tuple;
+use rad_db_types::Value;
+
+/// Decides which tuples a partial index holds
+pub trait IndexPredicate: Send + Sync {
+    fn matches(&self, tuple: &Tuple) -> bool;
+
+    /// The predicate as `Any`, so that whatever created the index can recognize its predicate
+    fn as_any(&self) -> &dyn Any;
+}
+
+/// A tuple held by an index
+#[derive(Debug, Clone)]
+pub struct IndexEntry {
+    hash: BigUint,
+    values: Vec<Value>,
+}
+
+impl IndexEntry {
+    /// The hash the tuple is stored under
+    pub fn hash(&self) -> &BigUint {
+        &self.hash
+    }
+
+    /// The values of the fields the index covers, in the order of [SecondaryIndex::covered]
+    pub fn values(&self) -> &[Value] {
+        &self.values
+    }
+}
+
+/// An ordered index of some fields of a relation
+pub struct SecondaryIndex {
+    name: String,
+    fields: Vec<usize>,
+    included: Vec<usize>,
+    /// The indexed fields followed by the fields of the primary key
+    entry_fields: Vec<usize>,
+    covered: Vec<usize>,
+    predicate: Option<Box<dyn IndexPredicate>>,
+    entries: KeyIndex<IndexEntry>,
+}
+
+impl SecondaryIndex {
+    /// Creates an empty index of the fields of a relation with the primary key
+    pub fn new<S: ToString>(name: S, fields: Vec<usize>, primary_key: &[usize]) -> Self {
+        let entry_fields: Vec<usize> = fields.iter().chain(primary_key).copied().collect();
+        SecondaryIndex {
+            name: name.to_string(),
+            fields,
+            included: vec![],
+            covered: covered_by(&entry_fields),
+            entry_fields,
+            predicate: None,
+            entries: KeyIndex::new(),
+        }
+    }
+
+    /// Creates an empty index that only holds the tuples the predicate matches
+    pub fn partial<S: ToString, P: IndexPredicate + 'static>(
+        name: S,
+        fields: Vec<usize>,
+        primary_key: &[usize],
+        predicate: P,
+    ) -> Self {
+        let mut index = Self::new(name, fields, primary_key);
+        index.predicate = Some(Box::new(predicate));
+        index
+    }
+
+    /// Also keeps the values of the fields in the index, so that they can be read without the
+    /// tuples they came from
+    ///
+    /// # Panic
+    /// Panics if the index already holds tuples
+    pub fn including(mut self, fields: Vec<usize>) -> Self {
+        assert!(
+            self.is_empty(),
+            "Fields can only be included in an empty index"
+        );
+        let primary_key = &self.entry_fields[self.fields.len()..];
+        let covered: Vec<usize> = self
+            .fields
+            .iter()
+            .chain(&fields)
+            .chain(primary_key)
+            .copied()
+            .collect();
+        self.covered = covered_by(&covered);
+        self.included = fields;
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The indexed fields, in the order entries are sorted by
+    pub fn fields(&self) -> &[usize] {
+        &self.fields
+    }
+
+    /// The fields that aren't indexed but whose values are kept in the index
+    pub fn included(&self) -> &[usize] {
+        &self.included
+    }
+
+    /// The fields whose values are kept in the index, which are the indexed fields, the included
+    /// fields and the fields of the primary key, in the order of the relation
+    pub fn covered(&self) -> &[usize] {
+        &self.covered
+    }
+
+    /// Whether the values of all of the fields are kept in the index
+    pub fn covers(&self, fields: &[usize]) -> bool {
+        fields.iter().all(|field| self.covered.contains(field))
+    }
+
+    /// The predicate of a partial index
+    pub fn predicate(&self) -> Option<&dyn IndexPredicate> {
+        self.predicate.as_deref()
+    }
+
+    pub fn is_partial(&self) -> bool {
+        self.predicate.is_some()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Whether the tuple belongs in the index
+    pub fn holds(&self, tuple: &Tuple) -> bool {
+        self.predicate
+            .as_ref()
+            .is_none_or(|predicate| predicate.matches(tuple))
+    }
+
+    /// Adds a tuple to the index if it belongs in it
+    pub fn insert(&mut self, tuple: &Tuple, hash: BigUint) {
+        if self.holds(tuple) {
+            let key = self.entry_of(tuple);
+            let values = tuple.project_ref(&self.covered).iter().cloned().collect();
+            self.entries.insert(key, IndexEntry { hash, values });
+        }
+    }
+
+    /// Removes a tuple from the index, if it's in it
+    pub fn remove(&mut self, tuple: &Tuple) {
+        let entry = self.entry_of(tuple);
+        self.entries.remove(&entry);
+    }
+
+    /// The entries of the tuples whose indexed values are within the bounds, in index order.
+    /// The bounds are encoded prefixes of the indexed values.
+    pub fn range<'i>(
+        &'i self,
+        lower: Bound<&EncodedKey>,
+        upper: Bound<&EncodedKey>,
+    ) -> impl Iterator<Item = &'i IndexEntry> + 'i {
+        self.entries.range(lower, upper)
+    }
+
+    fn entry_of(&self, tuple: &Tuple) -> EncodedKey {
+        EncodedKey::new(tuple.project_ref(&self.entry_fields).iter())
+    }
+}
+
+fn covered_by(fields: &[usize]) -> Vec<usize> {
+    let mut covered = fields.to_vec();
+    covered.sort_unstable();
+    covered.dedup();
+    covered
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Flagged;
+
+    impl IndexPredicate for Flagged {
+        fn matches(&self, tuple: &Tuple) -> bool {
+            tuple[1] == true
+        }
+
+        fn as_any(&self) -> &dyn Any {
+            self
+        }
+    }
+
+    #[test]
+    fn partial_indexes_skip_other_tuples() {
+        let mut index = SecondaryIndex::partial("active_by_group", vec![2], &[0], Flagged);
+        for id in 0..10u64 {
+            let tuple = Tuple::new(vec![
+                Value::from(id),
+                Value::from(id % 2 == 0),
+                Value::from(id % 3),
+            ]);
+            index.insert(&tuple, BigUint::from(id));
+        }
+        assert!(index.is_partial());
+        assert_eq!(index.len(), 5);
+
+        let group = EncodedKey::new(&[Value::from(1u64)]);
+        let hashes: Vec<BigUint> = index
+            .range(Bound::Included(&group), Bound::Included(&group))
+            .map(|entry| entry.hash().clone())
+            .collect();
+        // 4 is the only even id in group 1
+        assert_eq!(hashes, vec![BigUint::from(4u32)]);
+
+        index.remove(&Tuple::new(vec![
+            Value::from(4u64),
+            Value::from(true),
+            Value::from(1u64),
+        ]));
+        assert_eq!(index.len(), 4);
+    }
+
+    #[test]
+    fn covered_fields() {
+        let index = SecondaryIndex::new("by_owner", vec![2, 0], &[0, 1]).including(vec![3, 1]);
+        assert_eq!(index.covered(), &[0, 1, 2, 3]);
+        assert!(index.covers(&[0, 1, 3]));
+        assert!(!index.covers(&[4]));
+
+        let mut index = SecondaryIndex::new("by_owner", vec![1], &[0]).including(vec![2]);
+        let tuple = Tuple::new(vec![
+            Value::from(7u64),
+            Value::from("al"),
+            Value::from(35u32),
+            Value::from(true),
+        ]);
+        index.insert(&tuple, BigUint::from(7u32));
+        let entry = index
+            .range(Bound::Unbounded, Bound::Unbounded)
+            .next()
+            .unwrap();
+        assert_eq!(
+            entry.values(),
+            &[Value::from(7u64), Value::from("al"), Value::from(35u32)]
+        );
+    }
+}
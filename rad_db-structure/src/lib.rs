@@ -1,9 +1,23 @@
 use crate::identifier::Identifier;
 
+pub mod backup;
 pub mod constraint;
+pub mod dump;
+pub mod history;
 pub mod identifier;
 pub mod key;
+pub mod maintenance;
+pub mod memory;
 pub mod relations;
+pub mod sequence;
+pub mod settings;
+pub mod snapshot;
+pub mod spill;
+#[cfg(any(test, feature = "testing"))]
+pub mod stress;
+pub mod temp;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
 pub mod tuple;
 
 pub trait Rename<I: Into<Identifier>> {
@@ -0,0 +1,253 @@
+//! Runs maintenance, such as compaction, statistics refreshes and index rebuilds, on a
+//! background thread. Maintenance shares an IO budget, so that it can't take the IO that
+//! foreground queries need.
+
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// Limits how many bytes can be read or written each second. Up to a second's worth of bytes
+/// can be used at once after the limiter has been idle.
+#[derive(Debug)]
+pub struct RateLimiter {
+    bytes_per_second: u64,
+    state: Mutex<Bucket>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    available: f64,
+    refilled: Instant,
+}
+
+impl RateLimiter {
+    /// # Panic
+    /// Panics if `bytes_per_second` is 0
+    pub fn new(bytes_per_second: u64) -> Self {
+        assert!(bytes_per_second > 0, "A rate limit must allow some IO");
+        RateLimiter {
+            bytes_per_second,
+            state: Mutex::new(Bucket {
+                available: bytes_per_second as f64,
+                refilled: Instant::now(),
+            }),
+        }
+    }
+
+    pub fn bytes_per_second(&self) -> u64 {
+        self.bytes_per_second
+    }
+
+    /// Waits until the bytes can be used. Requests larger than a second's worth of bytes wait
+    /// for as long as they would take at the limit.
+    pub fn acquire(&self, bytes: u64) {
+        let wait = {
+            let mut bucket = self.state.lock().unwrap_or_else(|e| e.into_inner());
+            let now = Instant::now();
+            let refill =
+                now.duration_since(bucket.refilled).as_secs_f64() * self.bytes_per_second as f64;
+            bucket.available = (bucket.available + refill).min(self.bytes_per_second as f64);
+            bucket.refilled = now;
+            bucket.available -= bytes as f64;
+            if bucket.available >= 0.0 {
+                return;
+            }
+            Duration::from_secs_f64(-bucket.available / self.bytes_per_second as f64)
+        };
+        std::thread::sleep(wait);
+    }
+}
+
+/// Work that keeps storage healthy, run periodically by a [MaintenanceScheduler]
+pub trait MaintenanceTask: Send {
+    fn name(&self) -> &str;
+
+    /// Runs the task once, acquiring the bytes it reads and writes from the limiter
+    fn run(&mut self, io: &RateLimiter);
+}
+
+impl<F: FnMut(&RateLimiter) + Send> MaintenanceTask for (&'static str, F) {
+    fn name(&self) -> &str {
+        self.0
+    }
+
+    fn run(&mut self, io: &RateLimiter) {
+        (self.1)(io)
+    }
+}
+
+struct Scheduled {
+    task: Box<dyn MaintenanceTask>,
+    every: Duration,
+    next: Instant,
+    runs: usize,
+}
+
+#[derive(Default)]
+struct Shared {
+    stopping: Mutex<bool>,
+    wake: Condvar,
+}
+
+/// Runs maintenance tasks on a background thread, one at a time, each once per interval
+pub struct MaintenanceScheduler {
+    limiter: Arc<RateLimiter>,
+    tasks: Vec<Scheduled>,
+}
+
+impl MaintenanceScheduler {
+    pub fn new(limiter: RateLimiter) -> Self {
+        MaintenanceScheduler {
+            limiter: Arc::new(limiter),
+            tasks: vec![],
+        }
+    }
+
+    /// Schedules a task to run every interval, first running as soon as the scheduler starts
+    pub fn schedule<T: MaintenanceTask + 'static>(&mut self, task: T, every: Duration) {
+        self.tasks.push(Scheduled {
+            task: Box::new(task),
+            every,
+            next: Instant::now(),
+            runs: 0,
+        });
+    }
+
+    pub fn limiter(&self) -> &Arc<RateLimiter> {
+        &self.limiter
+    }
+
+    /// Starts running the tasks on a background thread
+    pub fn start(self) -> RunningMaintenance {
+        let shared = Arc::new(Shared::default());
+        let thread_shared = shared.clone();
+        let handle = std::thread::Builder::new()
+            .name("maintenance".to_string())
+            .spawn(move || self.run(&thread_shared))
+            .expect("Couldn't start the maintenance thread");
+        RunningMaintenance {
+            shared,
+            handle: Some(handle),
+        }
+    }
+
+    fn run(mut self, shared: &Shared) -> Vec<(String, usize)> {
+        loop {
+            let now = Instant::now();
+            for scheduled in &mut self.tasks {
+                if scheduled.next <= now {
+                    log::debug!("Running maintenance task {}", scheduled.task.name());
+                    scheduled.task.run(&self.limiter);
+                    scheduled.runs += 1;
+                    scheduled.next = Instant::now() + scheduled.every;
+                }
+                if *shared.stopping.lock().unwrap_or_else(|e| e.into_inner()) {
+                    return self.runs();
+                }
+            }
+
+            let next = self.tasks.iter().map(|scheduled| scheduled.next).min();
+            let stopping = shared.stopping.lock().unwrap_or_else(|e| e.into_inner());
+            let stopping = match next {
+                Some(next) => {
+                    let wait = next.saturating_duration_since(Instant::now());
+                    shared
+                        .wake
+                        .wait_timeout_while(stopping, wait, |stopping| !*stopping)
+                        .unwrap_or_else(|e| e.into_inner())
+                        .0
+                }
+                None => shared
+                    .wake
+                    .wait_while(stopping, |stopping| !*stopping)
+                    .unwrap_or_else(|e| e.into_inner()),
+            };
+            if *stopping {
+                return self.runs();
+            }
+        }
+    }
+
+    fn runs(&self) -> Vec<(String, usize)> {
+        self.tasks
+            .iter()
+            .map(|scheduled| (scheduled.task.name().to_string(), scheduled.runs))
+            .collect()
+    }
+}
+
+/// Maintenance running in the background. Dropping it stops the maintenance after the task
+/// currently running finishes.
+pub struct RunningMaintenance {
+    shared: Arc<Shared>,
+    handle: Option<JoinHandle<Vec<(String, usize)>>>,
+}
+
+impl RunningMaintenance {
+    /// Stops the maintenance, returning how many times each task ran
+    pub fn stop(mut self) -> Vec<(String, usize)> {
+        self.stop_thread()
+    }
+
+    fn stop_thread(&mut self) -> Vec<(String, usize)> {
+        *self
+            .shared
+            .stopping
+            .lock()
+            .unwrap_or_else(|e| e.into_inner()) = true;
+        self.shared.wake.notify_all();
+        match self.handle.take() {
+            Some(handle) => handle.join().unwrap_or_default(),
+            None => vec![],
+        }
+    }
+}
+
+impl Drop for RunningMaintenance {
+    fn drop(&mut self) {
+        self.stop_thread();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    #[test]
+    fn rate_limiter_waits() {
+        let limiter = RateLimiter::new(1000);
+        let start = Instant::now();
+        limiter.acquire(1000);
+        assert!(start.elapsed() < Duration::from_millis(50));
+        limiter.acquire(100);
+        assert!(start.elapsed() >= Duration::from_millis(90));
+    }
+
+    #[test]
+    fn tasks_run_until_stopped() {
+        let bytes = Arc::new(AtomicUsize::new(0));
+        let counted = bytes.clone();
+        let mut scheduler = MaintenanceScheduler::new(RateLimiter::new(1 << 20));
+        scheduler.schedule(
+            ("compaction", move |io: &RateLimiter| {
+                io.acquire(16);
+                counted.fetch_add(16, Ordering::SeqCst);
+            }),
+            Duration::from_millis(5),
+        );
+        scheduler.schedule(
+            ("statistics", |_: &RateLimiter| {}),
+            Duration::from_secs(60),
+        );
+
+        let running = scheduler.start();
+        std::thread::sleep(Duration::from_millis(50));
+        let runs = running.stop();
+        assert_eq!(runs[0].0, "compaction");
+        assert!(runs[0].1 > 1);
+        assert_eq!(bytes.load(Ordering::SeqCst), runs[0].1 * 16);
+        assert_eq!(runs[1], ("statistics".to_string(), 1));
+    }
+}
@@ -0,0 +1,268 @@
+//! Accounts for the memory used by loaded blocks, caches and query operators against a single
+//! limit, so that one large query can't use up all of the memory of the process.
+//!
+//! Memory is used by [reserving](MemoryBudget::reserve) it, and returned to the budget when the
+//! [Reservation] drops. When a reservation would go past the limit, the budget first asks its
+//! evictors to free memory held by caches.
+
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, OnceLock};
+
+use rad_db_types::{Text, Type};
+
+use crate::tuple::Tuple;
+
+/// A reservation would take the memory in use past the limit of the budget
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryExceeded {
+    pub requested: usize,
+    pub used: usize,
+    pub limit: usize,
+}
+
+impl Display for MemoryExceeded {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Couldn't reserve {} bytes, {} of {} bytes are in use",
+            self.requested, self.used, self.limit
+        )
+    }
+}
+
+impl Error for MemoryExceeded {}
+
+/// Frees memory held by a cache, given how many bytes are needed, returning how many bytes it
+/// freed
+pub type Evictor = Box<dyn Fn(usize) -> usize + Send + Sync>;
+
+/// A limit on the memory used by the database
+pub struct MemoryBudget {
+    limit: AtomicUsize,
+    used: AtomicUsize,
+    evictors: Mutex<Vec<Evictor>>,
+}
+
+impl MemoryBudget {
+    pub fn new(limit: usize) -> Self {
+        MemoryBudget {
+            limit: AtomicUsize::new(limit),
+            used: AtomicUsize::new(0),
+            evictors: Mutex::new(vec![]),
+        }
+    }
+
+    pub fn unlimited() -> Self {
+        Self::new(usize::MAX)
+    }
+
+    /// The budget shared by every relation and query, which is unlimited until a limit is set
+    pub fn global() -> &'static MemoryBudget {
+        static GLOBAL: OnceLock<MemoryBudget> = OnceLock::new();
+        GLOBAL.get_or_init(MemoryBudget::unlimited)
+    }
+
+    pub fn limit(&self) -> usize {
+        self.limit.load(Ordering::Relaxed)
+    }
+
+    /// Changes the limit. Memory already in use is kept even if it's past the new limit.
+    pub fn set_limit(&self, limit: usize) {
+        self.limit.store(limit, Ordering::Relaxed);
+    }
+
+    /// The bytes currently reserved
+    pub fn used(&self) -> usize {
+        self.used.load(Ordering::Acquire)
+    }
+
+    /// Adds a cache that can be asked to free memory when a reservation would go past the limit.
+    /// Evictors must not reserve memory from this budget.
+    pub fn add_evictor<F: Fn(usize) -> usize + Send + Sync + 'static>(&self, evictor: F) {
+        self.evictors
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(Box::new(evictor));
+    }
+
+    /// Reserves memory, evicting memory held by caches if needed
+    pub fn reserve(&self, bytes: usize) -> Result<Reservation<'_>, MemoryExceeded> {
+        self.acquire(bytes)?;
+        Ok(Reservation {
+            budget: self,
+            bytes,
+        })
+    }
+
+    /// Reserves memory that is used even if it goes past the limit, such as a block that has to
+    /// be loaded, after evicting memory held by caches to make room for it if needed
+    pub fn reserve_anyway(&self, bytes: usize) -> Reservation<'_> {
+        if self.acquire(bytes).is_err() {
+            self.used.fetch_add(bytes, Ordering::AcqRel);
+        }
+        Reservation {
+            budget: self,
+            bytes,
+        }
+    }
+
+    fn try_acquire(&self, bytes: usize) -> Result<(), MemoryExceeded> {
+        let limit = self.limit();
+        self.used
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |used| {
+                used.checked_add(bytes).filter(|total| *total <= limit)
+            })
+            .map(|_| ())
+            .map_err(|used| MemoryExceeded {
+                requested: bytes,
+                used,
+                limit,
+            })
+    }
+
+    fn acquire(&self, bytes: usize) -> Result<(), MemoryExceeded> {
+        let exceeded = match self.try_acquire(bytes) {
+            Ok(()) => return Ok(()),
+            Err(exceeded) => exceeded,
+        };
+        let mut needed = (exceeded.used + bytes).saturating_sub(exceeded.limit);
+        let evictors = self.evictors.lock().unwrap_or_else(|e| e.into_inner());
+        for evictor in evictors.iter() {
+            needed = needed.saturating_sub(evictor(needed));
+            if needed == 0 {
+                break;
+            }
+        }
+        drop(evictors);
+        self.try_acquire(bytes)
+    }
+
+    fn release(&self, bytes: usize) {
+        self.used.fetch_sub(bytes, Ordering::AcqRel);
+    }
+}
+
+impl Default for MemoryBudget {
+    fn default() -> Self {
+        Self::unlimited()
+    }
+}
+
+/// Memory reserved from a budget, which is returned to the budget when the reservation drops
+pub struct Reservation<'b> {
+    budget: &'b MemoryBudget,
+    bytes: usize,
+}
+
+impl Reservation<'_> {
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    /// Reserves more memory as part of this reservation
+    pub fn grow(&mut self, bytes: usize) -> Result<(), MemoryExceeded> {
+        self.budget.acquire(bytes)?;
+        self.bytes += bytes;
+        Ok(())
+    }
+
+    /// Reserves more memory as part of this reservation even if it goes past the limit
+    pub fn grow_anyway(&mut self, bytes: usize) {
+        if self.budget.acquire(bytes).is_err() {
+            self.budget.used.fetch_add(bytes, Ordering::AcqRel);
+        }
+        self.bytes += bytes;
+    }
+
+    /// Returns some of the memory of this reservation to the budget
+    pub fn shrink(&mut self, bytes: usize) {
+        let bytes = bytes.min(self.bytes);
+        self.budget.release(bytes);
+        self.bytes -= bytes;
+    }
+}
+
+impl Drop for Reservation<'_> {
+    fn drop(&mut self) {
+        self.budget.release(self.bytes);
+    }
+}
+
+/// Approximately how many bytes a value takes up in memory
+pub fn value_size(value: &Type) -> usize {
+    let heap = match value {
+        Type::Text(Text::String(string, _)) => string.capacity(),
+        Type::Text(Text::BinaryString(bytes, _)) | Type::Text(Text::Blob(bytes)) => {
+            bytes.capacity()
+        }
+        Type::Optional(Some(inner)) => value_size(inner),
+        Type::Extension(_, bytes) => bytes.capacity(),
+        Type::Array(values, _) => values.iter().map(value_size).sum(),
+        _ => 0,
+    };
+    std::mem::size_of::<Type>() + heap
+}
+
+/// Approximately how many bytes a tuple takes up in memory
+pub fn tuple_size(tuple: &Tuple) -> usize {
+    std::mem::size_of::<Tuple>() + tuple.iter().map(value_size).sum::<usize>()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+
+    #[test]
+    fn reservations_are_limited() {
+        let budget = MemoryBudget::new(100);
+        let first = budget.reserve(60).unwrap();
+        let mut second = budget.reserve(40).unwrap();
+        assert_eq!(budget.used(), 100);
+        assert_eq!(
+            budget.reserve(1).err(),
+            Some(MemoryExceeded {
+                requested: 1,
+                used: 100,
+                limit: 100
+            })
+        );
+        second.shrink(30);
+        assert!(second.grow(20).is_ok());
+        drop(first);
+        assert_eq!(budget.used(), 30);
+        let forced = budget.reserve_anyway(200);
+        assert_eq!(budget.used(), 230);
+        drop(forced);
+        drop(second);
+        assert_eq!(budget.used(), 0);
+    }
+
+    #[test]
+    fn evictors_make_room() {
+        let budget: &'static MemoryBudget = Box::leak(Box::new(MemoryBudget::new(100)));
+        let cached = Arc::new(Mutex::new(vec![
+            budget.reserve(50).unwrap(),
+            budget.reserve(30).unwrap(),
+        ]));
+        let evicted = cached.clone();
+        budget.add_evictor(move |needed| {
+            let mut cached = evicted.lock().unwrap();
+            let mut freed = 0;
+            while freed < needed && !cached.is_empty() {
+                freed += cached.remove(0).bytes();
+            }
+            freed
+        });
+
+        let reserved = budget.reserve(40).unwrap();
+        assert_eq!(cached.lock().unwrap().len(), 1);
+        assert_eq!(budget.used(), 70);
+        assert!(budget.reserve(90).is_err());
+        assert!(cached.lock().unwrap().is_empty());
+        drop(reserved);
+    }
+}
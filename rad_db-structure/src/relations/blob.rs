@@ -0,0 +1,289 @@
+//! Blobs stored out of line, each in a file of its own in the `blobs` directory of the storage of
+//! a relation.
+//!
+//! When a tuple is inserted into a relation that's stored in files, its blobs that are longer than
+//! [INLINE_BLOB_BYTES] are written to files, and the tuple keeps a [BlobHandle] in their place, so
+//! that a block only holds a short line for the tuple however large its blobs are. Blobs can also
+//! be written a part at a time with a [BlobWriter], whose handle is inserted like any other value,
+//! and every blob, whether it's stored out of line or not, can be read a part at a time with a
+//! [BlobReader].
+//!
+//! The file of a blob isn't removed when its tuple is, as other tuples may hold its handle.
+//! Instead, files that no tuple holds the handle of are removed with
+//! [remove_unreferenced_blobs](crate::relations::Relation::remove_unreferenced_blobs).
+
+use std::fs;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::{BufReader, BufWriter, Cursor, Read, Take, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use rad_db_types::{BlobHandle, Text, Type, Value};
+
+/// The name of the directory within the storage of a relation that holds its blobs
+pub const BLOBS_DIRECTORY: &str = "blobs";
+
+/// The longest blobs that are kept within their tuples when they're inserted
+pub const INLINE_BLOB_BYTES: usize = 4096;
+
+/// Gives out the ids of the blobs of a relation
+#[derive(Debug, Default)]
+pub(crate) struct BlobIds {
+    next: AtomicU64,
+}
+
+impl BlobIds {
+    /// Creates the file of a new blob in the directory. Ids are tried in order, skipping the ones
+    /// files already exist for, so ids are never reused while their files are around.
+    pub(crate) fn create(&self, directory: &Path) -> io::Result<BlobWriter> {
+        fs::create_dir_all(directory)?;
+        loop {
+            let id = self.next.fetch_add(1, Ordering::Relaxed);
+            let path = file_of(directory, id);
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(file) => {
+                    return Ok(BlobWriter {
+                        file: BufWriter::new(file),
+                        path: Some(path),
+                        id,
+                        length: 0,
+                    })
+                }
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+/// The file the blob with the id is stored in
+pub(crate) fn file_of(directory: &Path, id: u64) -> PathBuf {
+    directory.join(format!("{}.blob", id))
+}
+
+/// The id of the blob stored in the file, if it's the file of a blob
+pub(crate) fn id_of(file: &Path) -> Option<u64> {
+    file.file_name()?
+        .to_str()?
+        .strip_suffix(".blob")?
+        .parse()
+        .ok()
+}
+
+/// The handles of the blobs stored out of line within the value
+pub(crate) fn handles_in(value: &Value) -> Vec<BlobHandle> {
+    match value {
+        Type::Text(Text::BlobRef(handle)) => vec![*handle],
+        Type::Optional(Some(inner)) => handles_in(inner),
+        Type::Array(values, _) => values.iter().flat_map(handles_in).collect(),
+        _ => vec![],
+    }
+}
+
+/// Writes a blob out of line a part at a time. The file of the blob is removed if the writer is
+/// dropped before it's [finished](BlobWriter::finish).
+#[derive(Debug)]
+pub struct BlobWriter {
+    file: BufWriter<File>,
+    path: Option<PathBuf>,
+    id: u64,
+    length: u64,
+}
+
+impl BlobWriter {
+    /// The number of bytes written so far
+    pub fn length(&self) -> u64 {
+        self.length
+    }
+
+    /// Finishes writing the blob, returning the value tuples hold in its place
+    pub fn finish(mut self) -> io::Result<Value> {
+        self.file.flush()?;
+        self.file.get_ref().sync_data()?;
+        self.path = None;
+        Ok(Type::Text(Text::BlobRef(BlobHandle::new(
+            self.id,
+            self.length,
+        ))))
+    }
+}
+
+impl Write for BlobWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.file.write(buf)?;
+        self.length += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+impl Drop for BlobWriter {
+    fn drop(&mut self) {
+        if let Some(path) = self.path.take() {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+/// Reads a blob a part at a time, whether it's stored out of line or within its tuple
+#[derive(Debug)]
+pub struct BlobReader {
+    source: Source,
+    length: u64,
+}
+
+#[derive(Debug)]
+enum Source {
+    Inline(Cursor<Vec<u8>>),
+    File(Take<BufReader<File>>),
+}
+
+impl BlobReader {
+    pub(crate) fn inline(bytes: Vec<u8>) -> Self {
+        BlobReader {
+            length: bytes.len() as u64,
+            source: Source::Inline(Cursor::new(bytes)),
+        }
+    }
+
+    /// Opens the file of a blob stored out of line in the directory
+    pub(crate) fn open(directory: &Path, handle: BlobHandle) -> io::Result<Self> {
+        let file = File::open(file_of(directory, handle.id()))?;
+        Ok(BlobReader {
+            source: Source::File(BufReader::new(file).take(handle.length())),
+            length: handle.length(),
+        })
+    }
+
+    /// The number of bytes in the blob
+    pub fn length(&self) -> u64 {
+        self.length
+    }
+}
+
+impl Read for BlobReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match &mut self.source {
+            Source::Inline(cursor) => cursor.read(buf),
+            Source::File(file) => file.read(buf),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dump::dump_relation;
+    use crate::identifier::Identifier;
+    use crate::key::primary::PrimaryKeyDefinition;
+    use crate::relations::Relation;
+    use crate::temp::unique_identifier;
+    use crate::tuple::Tuple;
+
+    fn read_all(relation: &Relation, value: &Value) -> Vec<u8> {
+        let mut bytes = vec![];
+        relation
+            .read_blob(value)
+            .unwrap()
+            .read_to_end(&mut bytes)
+            .unwrap();
+        bytes
+    }
+
+    fn stored(relation: &Relation, id: u8) -> Value {
+        relation.find_by_key(&[Value::from(id)]).unwrap()[1].clone()
+    }
+
+    #[test]
+    fn blobs_out_of_line() {
+        let fields = vec![
+            ("id", Type::from(0u8)),
+            ("contents", Type::Text(Text::Blob(vec![]))),
+        ];
+        let mut relation = Relation::new(
+            unique_identifier("blobs"),
+            fields.clone(),
+            4,
+            PrimaryKeyDefinition::new(vec![0]),
+        );
+        let small = Type::Text(Text::Blob(b"small".to_vec()));
+        let large: Vec<u8> = (0..3 * INLINE_BLOB_BYTES).map(|i| i as u8).collect();
+        relation
+            .insert(Tuple::new(vec![0u8.into(), small.clone()]))
+            .unwrap();
+        relation
+            .insert(Tuple::new(vec![
+                1u8.into(),
+                Text::Blob(large.clone()).into(),
+            ]))
+            .unwrap();
+
+        let mut writer = relation.write_blob().unwrap();
+        for chunk in large.chunks(1000) {
+            writer.write_all(chunk).unwrap();
+        }
+        assert_eq!(writer.length(), large.len() as u64);
+        let written = writer.finish().unwrap();
+        relation
+            .insert(Tuple::new(vec![2u8.into(), written.clone()]))
+            .unwrap();
+        std::mem::drop(relation.write_blob().unwrap());
+
+        assert_eq!(stored(&relation, 0), small);
+        assert!(matches!(stored(&relation, 1), Type::Text(Text::BlobRef(_))));
+        assert_eq!(stored(&relation, 2), written);
+        for id in 0..3 {
+            let bytes = read_all(&relation, &stored(&relation, id));
+            assert_eq!(
+                bytes,
+                if id == 0 {
+                    b"small".to_vec()
+                } else {
+                    large.clone()
+                }
+            );
+        }
+        let blobs = relation.blob_directory().unwrap();
+        assert_eq!(fs::read_dir(&blobs).unwrap().count(), 2);
+
+        relation.flush();
+        let mut dumped = vec![];
+        dump_relation(&relation, &mut dumped).unwrap();
+        assert!(!String::from_utf8(dumped).unwrap().contains("blob:"));
+
+        relation
+            .insert(Tuple::new(vec![1u8.into(), small.clone()]))
+            .unwrap();
+        assert_eq!(relation.remove_unreferenced_blobs().unwrap(), 1);
+        assert_eq!(read_all(&relation, &stored(&relation, 2)), large);
+
+        let mut volatile = Relation::new_volatile(
+            Identifier::new("volatile"),
+            fields,
+            4,
+            PrimaryKeyDefinition::new(vec![0]),
+        );
+        volatile
+            .insert(Tuple::new(vec![
+                0u8.into(),
+                Text::Blob(large.clone()).into(),
+            ]))
+            .unwrap();
+        assert_eq!(
+            volatile.find_by_key(&[Value::from(0u8)]).unwrap()[1],
+            Type::Text(Text::Blob(large))
+        );
+        assert_eq!(
+            volatile.write_blob().unwrap_err().kind(),
+            io::ErrorKind::Unsupported
+        );
+
+        let directory = relation.storage_directory().unwrap();
+        std::mem::drop(relation);
+        let _ = fs::remove_dir_all(directory);
+    }
+}
@@ -0,0 +1,95 @@
+//! How many tuples each block of a relation holds.
+//!
+//! A fixed number of tuples makes blocks of wide relations huge and blocks of narrow relations
+//! tiny, so the size can instead be given in bytes. The number of tuples a block holds then
+//! follows the average width of the tuples stored, and the blocks are rebuilt whenever that
+//! number drifts too far from the one they were built with.
+
+/// The size of the blocks of a relation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BucketSize {
+    /// Every block holds up to this many tuples
+    Tuples(usize),
+    /// Every block holds about this many bytes of tuples, as they're written in block files
+    Bytes(u64),
+}
+
+impl From<usize> for BucketSize {
+    fn from(tuples: usize) -> Self {
+        BucketSize::Tuples(tuples)
+    }
+}
+
+impl BucketSize {
+    /// The most tuples a block built from a size in bytes can hold, however narrow the tuples
+    pub const MAX_TUPLES_PER_BLOCK: usize = 1 << 16;
+
+    /// How many tuples a block should hold when tuples are `average_width` bytes wide
+    pub fn tuples_per_block(&self, average_width: u64) -> usize {
+        match self {
+            BucketSize::Tuples(tuples) => *tuples,
+            BucketSize::Bytes(bytes) => {
+                let tuples = bytes / average_width.max(1);
+                (tuples as usize).clamp(1, Self::MAX_TUPLES_PER_BLOCK)
+            }
+        }
+    }
+}
+
+/// The widths of the tuples stored in a relation whose blocks are sized in bytes
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct TupleWidths {
+    bytes: u64,
+    tuples: u64,
+}
+
+impl TupleWidths {
+    /// How many tuples have to be seen before their average width is trusted
+    const MIN_TUPLES: u64 = 16;
+
+    pub fn record(&mut self, width: u64) {
+        self.bytes += width;
+        self.tuples += 1;
+    }
+
+    /// The average width of the tuples, once enough have been seen
+    pub fn average(&self) -> Option<u64> {
+        if self.tuples < Self::MIN_TUPLES {
+            return None;
+        }
+        Some(self.bytes / self.tuples)
+    }
+}
+
+/// Whether blocks holding `current` tuples are far enough from holding `ideal` tuples to be
+/// worth rebuilding. Blocks are only rebuilt once the difference is at least double, so that
+/// small changes in the width of tuples don't rebuild them over and over.
+pub(crate) fn should_resize(current: usize, ideal: usize) -> bool {
+    ideal > current.saturating_mul(2) || ideal.saturating_mul(2) < current
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tuples_follow_the_width() {
+        let size = BucketSize::Bytes(4096);
+        assert_eq!(size.tuples_per_block(64), 64);
+        assert_eq!(size.tuples_per_block(10_000), 1);
+        assert_eq!(size.tuples_per_block(0), 4096);
+        assert_eq!(BucketSize::from(8).tuples_per_block(64), 8);
+
+        let mut widths = TupleWidths::default();
+        widths.record(10);
+        assert_eq!(widths.average(), None);
+        for _ in 1..TupleWidths::MIN_TUPLES {
+            widths.record(30);
+        }
+        assert_eq!(widths.average(), Some((10 + 15 * 30) / 16));
+
+        assert!(!should_resize(64, 100));
+        assert!(should_resize(64, 129));
+        assert!(should_resize(64, 31));
+    }
+}
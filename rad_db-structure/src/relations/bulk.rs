@@ -0,0 +1,438 @@
+//! Loads many tuples into a relation at once.
+//!
+//! Rows are parsed, checked against the relation's definition and hashed on a pool of worker
+//! threads, then stored ordered by their hash, so that the tuples of each bucket are stored
+//! together instead of jumping between buckets for every row.
+//!
+//! A load is atomic. Every row is checked before any is stored, so if a single row is rejected
+//! none of them are, and the relation is left as it was. The constraints of the relation are
+//! checked for the whole load at once, so [deferred](Relation::defer_constraint) constraints
+//! let rows reference rows that come after them.
+
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::io;
+use std::io::BufRead;
+
+use num_bigint::BigUint;
+use rad_db_types::deserialization::parse_using_types_with;
+use rayon::prelude::*;
+use rayon::ThreadPool;
+
+use crate::constraint::{ConstraintViolation, RelationLookup};
+use crate::relations::tuple_storage::TupleInsertionError;
+use crate::relations::{AsTypeList, Relation};
+use crate::tuple::{FieldTypeError, Tuple};
+
+/// How many lines of text are read before they're parsed together
+const LINES_PER_CHUNK: usize = 4096;
+
+/// Why a row of a bulk load was rejected
+#[derive(Debug)]
+pub enum BulkLoadError {
+    /// The line, counting from 0, couldn't be parsed
+    Unparsable(usize),
+    /// The row, counting from 0, doesn't match the relation's definition
+    InvalidTuple(usize, Vec<FieldTypeError>),
+    /// The row, counting from 0, breaks constraints of the relation
+    ConstraintViolations(usize, Vec<ConstraintViolation>),
+    /// The row, counting from 0, couldn't be stored, such as when it would take the relation
+    /// past its quota
+    NotStored(usize, TupleInsertionError),
+}
+
+impl BulkLoadError {
+    /// The row that was rejected
+    pub fn row(&self) -> usize {
+        match self {
+            BulkLoadError::Unparsable(row)
+            | BulkLoadError::InvalidTuple(row, _)
+            | BulkLoadError::ConstraintViolations(row, _)
+            | BulkLoadError::NotStored(row, _) => *row,
+        }
+    }
+}
+
+impl Display for BulkLoadError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BulkLoadError::Unparsable(row) => write!(f, "Couldn't parse row {}", row),
+            BulkLoadError::InvalidTuple(row, errors) => {
+                write!(f, "Row {} is invalid: ", row)?;
+                for (i, error) in errors.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", error)?;
+                }
+                Ok(())
+            }
+            BulkLoadError::ConstraintViolations(row, violations) => {
+                write!(f, "Row {} breaks constraints: ", row)?;
+                for (i, violation) in violations.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", violation)?;
+                }
+                Ok(())
+            }
+            BulkLoadError::NotStored(row, error) => {
+                write!(f, "Row {} couldn't be stored: {}", row, error)
+            }
+        }
+    }
+}
+
+impl Error for BulkLoadError {}
+
+/// What a bulk load did. Either every row was inserted, or none were and the rejected rows say
+/// why.
+#[derive(Debug, Default)]
+pub struct LoadSummary {
+    /// How many tuples were stored
+    pub inserted: usize,
+    /// The first error of every rejected row, in row order
+    pub rejected: Vec<BulkLoadError>,
+}
+
+impl LoadSummary {
+    /// Whether the rows were stored
+    pub fn is_committed(&self) -> bool {
+        self.rejected.is_empty()
+    }
+}
+
+/// Loads tuples into relations using a pool of worker threads.
+///
+/// A bulk load checks that tuples match the relation's definition, constraints and quota, and
+/// the relation's mutation hooks and history are told about every tuple stored, like they are
+/// for tuples inserted one at a time. Later rows replace earlier rows with the same primary key.
+pub struct BulkLoader {
+    pool: ThreadPool,
+}
+
+impl BulkLoader {
+    /// Creates a loader with a worker thread per CPU
+    pub fn new() -> Self {
+        Self::with_threads(0)
+    }
+
+    /// Creates a loader with the number of worker threads, where 0 means a thread per CPU
+    pub fn with_threads(threads: usize) -> Self {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .thread_name(|index| format!("bulk-loader-{}", index))
+            .build()
+            .expect("Couldn't start the bulk loader threads");
+        BulkLoader { pool }
+    }
+
+    pub fn threads(&self) -> usize {
+        self.pool.current_num_threads()
+    }
+
+    /// Loads the tuples into the relation, unless any of them is rejected
+    pub fn load<I: IntoIterator<Item = Tuple>>(
+        &self,
+        relation: &mut Relation,
+        tuples: I,
+    ) -> LoadSummary {
+        self.load_with(relation, tuples, &())
+    }
+
+    /// Loads the tuples into the relation, unless any of them is rejected, looking up the
+    /// relations referenced by its constraints in `others`
+    pub fn load_with<I: IntoIterator<Item = Tuple>>(
+        &self,
+        relation: &mut Relation,
+        tuples: I,
+        others: &dyn RelationLookup,
+    ) -> LoadSummary {
+        let rows = tuples.into_iter().enumerate().map(Ok).collect();
+        self.check_and_store(relation, rows, others)
+    }
+
+    /// Loads rows written in the relation's text format, one per line, unless any of them is
+    /// rejected. Empty lines are skipped, but still counted as rows.
+    pub fn load_text<R: BufRead>(
+        &self,
+        relation: &mut Relation,
+        reader: R,
+    ) -> io::Result<LoadSummary> {
+        self.load_text_with(relation, reader, &())
+    }
+
+    /// Loads rows written in the relation's text format like [load_text](BulkLoader::load_text),
+    /// looking up the relations referenced by its constraints in `others`
+    pub fn load_text_with<R: BufRead>(
+        &self,
+        relation: &mut Relation,
+        reader: R,
+        others: &dyn RelationLookup,
+    ) -> io::Result<LoadSummary> {
+        let types = relation.to_type_list();
+        let format = relation.text_format().clone();
+        let mut lines = reader.lines().enumerate();
+        let mut rows = vec![];
+        // the lines are parsed a chunk at a time as they're read, instead of reading all of the
+        // text before parsing any of it
+        loop {
+            let chunk = lines
+                .by_ref()
+                .take(LINES_PER_CHUNK)
+                .map(|(row, line)| line.map(|line| (row, line)))
+                .collect::<io::Result<Vec<_>>>()?;
+            if chunk.is_empty() {
+                break;
+            }
+            self.pool.install(|| {
+                rows.par_extend(
+                    chunk
+                        .into_par_iter()
+                        .filter(|(_, line)| !line.is_empty())
+                        .map(|(row, line)| {
+                            parse_using_types_with(&line, types.iter().cloned(), &format)
+                                .map(|values| (row, Tuple::new(values)))
+                                .map_err(|_| BulkLoadError::Unparsable(row))
+                        }),
+                )
+            });
+        }
+        Ok(self.check_and_store(relation, rows, others))
+    }
+
+    /// Checks and hashes the rows that could be read, only storing them if no row was rejected.
+    /// The constraints are only checked once every row matches the relation's definition.
+    fn check_and_store(
+        &self,
+        relation: &mut Relation,
+        rows: Vec<Result<(usize, Tuple), BulkLoadError>>,
+        others: &dyn RelationLookup,
+    ) -> LoadSummary {
+        let definition = relation.get_relation_definition();
+        let primary_key = relation.primary_key().clone();
+        let checked: Vec<Result<(usize, BigUint, Tuple), BulkLoadError>> =
+            self.pool.install(|| {
+                rows.into_par_iter()
+                    .map(|row| {
+                        let (row, tuple) = row?;
+                        tuple
+                            .validate(&definition)
+                            .map_err(|errors| BulkLoadError::InvalidTuple(row, errors))?;
+                        Ok((row, primary_key.key_of(&tuple).hash(), tuple))
+                    })
+                    .collect()
+            });
+
+        let mut keys = Vec::with_capacity(checked.len());
+        let mut tuples = Vec::with_capacity(checked.len());
+        let mut rejected = vec![];
+        for row in checked {
+            match row {
+                Ok((row, hash, tuple)) => {
+                    keys.push((row, hash));
+                    tuples.push(tuple);
+                }
+                Err(error) => rejected.push(error),
+            }
+        }
+        if rejected.is_empty() {
+            rejected = relation
+                .constraints()
+                .check_batch(&tuples, relation, others)
+                .into_iter()
+                .map(|(index, violations)| {
+                    BulkLoadError::ConstraintViolations(keys[index].0, violations)
+                })
+                .collect();
+        }
+        if !rejected.is_empty() {
+            return LoadSummary {
+                inserted: 0,
+                rejected,
+            };
+        }
+        let hashed = keys
+            .into_iter()
+            .zip(tuples)
+            .map(|((row, hash), tuple)| (row, hash, tuple))
+            .collect();
+        match self.store(relation, hashed) {
+            Ok(inserted) => LoadSummary { inserted, rejected },
+            Err(error) => LoadSummary {
+                inserted: 0,
+                rejected: vec![error],
+            },
+        }
+    }
+
+    fn store(
+        &self,
+        relation: &mut Relation,
+        mut hashed: Vec<(usize, BigUint, Tuple)>,
+    ) -> Result<usize, BulkLoadError> {
+        // a stable sort keeps later rows after earlier rows with the same key, so they replace
+        // them
+        self.pool
+            .install(|| hashed.par_sort_by(|(_, left, _), (_, right, _)| left.cmp(right)));
+        let rows: Vec<usize> = hashed.iter().map(|(row, _, _)| *row).collect();
+        relation
+            .store_hashed(hashed.into_iter().map(|(_, hash, tuple)| (hash, tuple)))
+            .map_err(|(position, error)| BulkLoadError::NotStored(rows[position], error))
+    }
+}
+
+impl Default for BulkLoader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rad_db_types::{Type, Value};
+
+    use super::*;
+    use crate::identifier::Identifier;
+    use crate::key::primary::PrimaryKeyDefinition;
+
+    fn relation() -> Relation {
+        Relation::new_volatile(
+            Identifier::new("loaded"),
+            vec![("id", Type::from(0u64)), ("name", Type::from(""))],
+            16,
+            PrimaryKeyDefinition::new(vec![0, 1]),
+        )
+    }
+
+    #[test]
+    fn load_tuples() {
+        let mut relation = relation();
+        let loader = BulkLoader::with_threads(4);
+        let tuples = (0..1000u64)
+            .map(|i| Tuple::new(vec![Value::from(i), Value::from(format!("name{}", i))]));
+        let summary = loader.load(&mut relation, tuples);
+        assert!(summary.is_committed());
+        assert_eq!(summary.inserted, 1000);
+        assert_eq!(relation.len(), 1000);
+        assert!(relation
+            .tuples()
+            .any(|tuple| tuple == Tuple::new(vec![Value::from(999u64), Value::from("name999")])));
+
+        let summary = loader.load(
+            &mut relation,
+            vec![
+                Tuple::new(vec![Value::from(1000u64), Value::from("name1000")]),
+                Tuple::new(vec![Value::from(1u64)]),
+            ],
+        );
+        match &summary.rejected[..] {
+            [BulkLoadError::InvalidTuple(1, _)] => {}
+            other => panic!("expected an invalid tuple, got {:?}", other),
+        }
+        assert_eq!(summary.inserted, 0);
+        assert_eq!(relation.len(), 1000);
+    }
+
+    #[test]
+    fn load_text() {
+        let mut relation = relation();
+        let loader = BulkLoader::with_threads(2);
+        let text = "1|\"a\"\n2|\"b\"\n\n3|\"c\"\n";
+        assert_eq!(
+            loader
+                .load_text(&mut relation, text.as_bytes())
+                .unwrap()
+                .inserted,
+            3
+        );
+        assert_eq!(relation.len(), 3);
+
+        let text = "4|\"d\"\nfive|\"e\"\n\n6\n7|\"g\"\n";
+        let summary = loader.load_text(&mut relation, text.as_bytes()).unwrap();
+        let rejected: Vec<usize> = summary.rejected.iter().map(BulkLoadError::row).collect();
+        assert_eq!(rejected, vec![1, 3]);
+        assert!(!summary.is_committed());
+        assert_eq!(relation.len(), 3);
+    }
+
+    #[test]
+    fn loads_are_stored_like_inserts() {
+        use crate::history::RetentionPolicy;
+        use crate::relations::quota::Quota;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+
+        let mut relation = relation();
+        relation.retain_history(RetentionPolicy::Forever);
+        let inserted = Arc::new(AtomicUsize::new(0));
+        let counter = inserted.clone();
+        relation.on_insert(move |_, _| {
+            counter.fetch_add(1, Ordering::SeqCst);
+        });
+        relation.set_quota(Quota::unlimited().with_max_tuples(10));
+        let loader = BulkLoader::with_threads(2);
+        let rows = |range: std::ops::Range<u64>| {
+            range
+                .map(|i| Tuple::new(vec![Value::from(i), Value::from(format!("name{}", i))]))
+                .collect::<Vec<_>>()
+        };
+
+        let summary = loader.load(&mut relation, rows(0..8));
+        assert!(summary.is_committed());
+        assert_eq!(inserted.load(Ordering::SeqCst), 8);
+        assert_eq!(relation.history().unwrap().versions().len(), 8);
+
+        // replacing tuples doesn't use any more of the quota, but new ones do
+        let summary = loader.load(&mut relation, rows(6..10));
+        assert!(summary.is_committed());
+        let summary = loader.load(&mut relation, rows(9..12));
+        match &summary.rejected[..] {
+            [BulkLoadError::NotStored(_, TupleInsertionError::QuotaExceeded { .. })] => {}
+            other => panic!("expected the quota to be exceeded, got {:?}", other),
+        }
+        assert_eq!(relation.len(), 10);
+        assert_eq!(inserted.load(Ordering::SeqCst), 12);
+    }
+
+    #[test]
+    fn load_with_deferred_constraints() {
+        use crate::constraint::ForeignKey;
+        use crate::key::foreign::ForeignKeyDefinition;
+
+        let mut relation = Relation::new_volatile(
+            Identifier::new("ring"),
+            vec![("id", Type::from(0u64)), ("next", Type::from(0u64))],
+            16,
+            PrimaryKeyDefinition::new(vec![0]),
+        );
+        relation.add_constraint(ForeignKey::new(
+            "next_exists",
+            ForeignKeyDefinition::new(vec![1], "ring", vec![0]),
+        ));
+        let loader = BulkLoader::with_threads(2);
+        // every row refers to the row after it, and the last to the first
+        let rows = |count: u64| {
+            (0..count)
+                .map(move |i| Tuple::new(vec![Value::from(i), Value::from((i + 1) % count)]))
+                .collect::<Vec<_>>()
+        };
+
+        let summary = loader.load(&mut relation, rows(10));
+        let rejected: Vec<usize> = summary.rejected.iter().map(BulkLoadError::row).collect();
+        assert_eq!(rejected, (0..9).collect::<Vec<_>>());
+        match &summary.rejected[0] {
+            BulkLoadError::ConstraintViolations(0, violations) => {
+                assert_eq!(violations[0].constraint, "next_exists")
+            }
+            other => panic!("expected constraint violations, got {:?}", other),
+        }
+        assert!(relation.is_empty());
+
+        relation.defer_constraint("next_exists", true);
+        let summary = loader.load(&mut relation, rows(10));
+        assert!(summary.is_committed());
+        assert_eq!(relation.len(), 10);
+    }
+}
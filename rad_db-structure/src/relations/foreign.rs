@@ -0,0 +1,248 @@
+//! Relations whose tuples are kept outside of the database, such as in a CSV file or by another
+//! server, so that queries can read them alongside the relations stored here.
+//!
+//! A foreign relation is read again from the start every time a query reads it, so it always
+//! gives the tuples the source has at that moment. Nothing is ever written to it.
+
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::io;
+use std::io::{BufRead, BufReader};
+use std::path::PathBuf;
+
+use rad_db_types::deserialization::parse_using_types_with;
+use rad_db_types::serialization::TextFormat;
+use rad_db_types::Type;
+
+use crate::identifier::Identifier;
+use crate::tuple::Tuple;
+
+/// How many tuples are read into each block by default
+const DEFAULT_BLOCK_SIZE: usize = 256;
+
+/// Why a foreign relation couldn't be read
+#[derive(Debug)]
+pub enum ForeignError {
+    /// The source couldn't be read
+    Io(io::Error),
+    /// The line, counting from 0, couldn't be parsed into a tuple of the relation
+    Unparsable(usize),
+}
+
+impl Display for ForeignError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ForeignError::Io(error) => write!(f, "{}", error),
+            ForeignError::Unparsable(line) => write!(f, "Couldn't parse line {}", line),
+        }
+    }
+}
+
+impl Error for ForeignError {}
+
+impl From<io::Error> for ForeignError {
+    fn from(error: io::Error) -> Self {
+        ForeignError::Io(error)
+    }
+}
+
+/// A source of tuples outside of the database
+pub trait ForeignRelation: Send + Sync {
+    fn name(&self) -> &Identifier;
+
+    /// The names and types of the fields of its tuples
+    fn attributes(&self) -> &[(String, Type)];
+
+    /// Reads every tuple from the start, a block at a time
+    fn blocks(&self) -> Box<dyn Iterator<Item = Result<Vec<Tuple>, ForeignError>> + '_>;
+
+    /// The number of tuples, if it's known without reading them
+    fn len_hint(&self) -> Option<usize> {
+        None
+    }
+}
+
+/// Opens the reader a [TextRelation] reads its tuples from
+pub type Connector = Box<dyn Fn() -> io::Result<Box<dyn BufRead>> + Send + Sync>;
+
+/// A foreign relation whose tuples are lines written in a text format
+pub struct TextRelation {
+    name: Identifier,
+    attributes: Vec<(String, Type)>,
+    format: TextFormat,
+    /// Whether the first line names the fields instead of being a tuple
+    header: bool,
+    block_size: usize,
+    connect: Connector,
+}
+
+impl TextRelation {
+    /// Reads a CSV file, whose first line names its fields
+    pub fn csv<I: Into<Identifier>, S: ToString, P: Into<PathBuf>>(
+        name: I,
+        attributes: Vec<(S, Type)>,
+        path: P,
+    ) -> Self {
+        let path = path.into();
+        let connect: Connector = Box::new(move || {
+            let file = File::open(&path)?;
+            Ok(Box::new(BufReader::new(file)) as Box<dyn BufRead>)
+        });
+        Self::new(name, attributes, connect)
+            .with_text_format(TextFormat::new(',', '"', "NULL"))
+            .with_header(true)
+    }
+
+    /// Reads whatever `connect` opens every time the relation is read, such as a connection to
+    /// another server that sends tuples in the text format
+    pub fn new<I: Into<Identifier>, S: ToString>(
+        name: I,
+        attributes: Vec<(S, Type)>,
+        connect: Connector,
+    ) -> Self {
+        TextRelation {
+            name: name.into(),
+            attributes: attributes
+                .into_iter()
+                .map(|(field, ty)| (field.to_string(), ty))
+                .collect(),
+            format: TextFormat::default(),
+            header: false,
+            block_size: DEFAULT_BLOCK_SIZE,
+            connect,
+        }
+    }
+
+    pub fn with_text_format(mut self, format: TextFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Sets whether the first line names the fields, in which case it's skipped
+    pub fn with_header(mut self, header: bool) -> Self {
+        self.header = header;
+        self
+    }
+
+    /// # Panic
+    /// Panics if the block size is 0
+    pub fn with_block_size(mut self, block_size: usize) -> Self {
+        assert_ne!(block_size, 0, "A block must hold tuples");
+        self.block_size = block_size;
+        self
+    }
+
+    fn read_block(
+        &self,
+        lines: &mut impl Iterator<Item = (usize, io::Result<String>)>,
+    ) -> Result<Vec<Tuple>, ForeignError> {
+        let types: Vec<Type> = self.attributes.iter().map(|(_, ty)| ty.clone()).collect();
+        let mut block = Vec::with_capacity(self.block_size);
+        while block.len() < self.block_size {
+            let (number, line) = match lines.next() {
+                Some((number, line)) => (number, line?),
+                None => break,
+            };
+            if line.is_empty() {
+                continue;
+            }
+            let values = parse_using_types_with(&line, types.iter().cloned(), &self.format)
+                .map_err(|_| ForeignError::Unparsable(number))?;
+            block.push(Tuple::new(values));
+        }
+        Ok(block)
+    }
+}
+
+impl ForeignRelation for TextRelation {
+    fn name(&self) -> &Identifier {
+        &self.name
+    }
+
+    fn attributes(&self) -> &[(String, Type)] {
+        &self.attributes
+    }
+
+    fn blocks(&self) -> Box<dyn Iterator<Item = Result<Vec<Tuple>, ForeignError>> + '_> {
+        let reader = match (self.connect)() {
+            Ok(reader) => reader,
+            Err(error) => return Box::new(std::iter::once(Err(error.into()))),
+        };
+        let skipped = if self.header { 1 } else { 0 };
+        let mut lines = reader.lines().enumerate().skip(skipped);
+        let mut failed = false;
+        Box::new(std::iter::from_fn(move || {
+            if failed {
+                return None;
+            }
+            match self.read_block(&mut lines) {
+                Ok(block) if block.is_empty() => None,
+                Ok(block) => Some(Ok(block)),
+                Err(error) => {
+                    failed = true;
+                    Some(Err(error))
+                }
+            }
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::temp::{directory_of, unique_identifier};
+    use std::fs;
+    use std::io::Cursor;
+
+    fn people() -> Vec<(&'static str, Type)> {
+        vec![("id", Type::from(0u32)), ("name", Type::from(""))]
+    }
+
+    #[test]
+    fn csv_files() {
+        let directory = directory_of(&unique_identifier("people"));
+        fs::create_dir_all(&directory).unwrap();
+        let path = directory.join("people.csv");
+        fs::write(&path, "id,name\n1,ana\n2,\"bo, jr\"\n\n3,cy\n").unwrap();
+
+        let relation = TextRelation::csv("people", people(), &path).with_block_size(2);
+        let blocks: Vec<Vec<Tuple>> = relation.blocks().map(Result::unwrap).collect();
+        assert_eq!(blocks.len(), 2);
+        let names: Vec<Type> = blocks
+            .into_iter()
+            .flatten()
+            .map(|tuple| tuple[1].clone())
+            .collect();
+        assert_eq!(names, vec!["ana", "bo, jr", "cy"]);
+
+        // the file is read again every time
+        fs::write(&path, "id,name\n4,di\nnot a number,ed\n").unwrap();
+        let read: Vec<_> = relation.blocks().collect();
+        assert_eq!(read.len(), 1);
+        assert!(matches!(read[0], Err(ForeignError::Unparsable(2))));
+
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(
+            relation.blocks().next(),
+            Some(Err(ForeignError::Io(_)))
+        ));
+    }
+
+    #[test]
+    fn connected_sources() {
+        let connect: Connector = Box::new(|| {
+            let sent = "1|ana\n2|bo\n".as_bytes().to_vec();
+            Ok(Box::new(Cursor::new(sent)) as Box<dyn BufRead>)
+        });
+        let relation = TextRelation::new("remote_people", people(), connect);
+        let tuples: Vec<Tuple> = relation.blocks().flat_map(Result::unwrap).collect();
+        assert_eq!(
+            tuples,
+            vec![
+                Tuple::new(vec![1u32.into(), "ana".into()]),
+                Tuple::new(vec![2u32.into(), "bo".into()]),
+            ]
+        );
+    }
+}
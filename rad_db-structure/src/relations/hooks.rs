@@ -0,0 +1,48 @@
+//! Callbacks that are told about the tuples stored in and removed from a relation
+
+use crate::key::primary::PrimaryKey;
+use crate::tuple::Tuple;
+
+/// A callback given a tuple and its primary key
+pub type MutationHook = Box<dyn FnMut(&Tuple, &PrimaryKey<'_>) + Send>;
+
+/// The callbacks registered on a relation, called in the order they were registered
+#[derive(Default)]
+pub struct MutationHooks {
+    on_insert: Vec<MutationHook>,
+    on_delete: Vec<MutationHook>,
+}
+
+// the hooks are only ever called through a mutable reference, so sharing the hooks between
+// threads can't call one from two threads at once
+unsafe impl Sync for MutationHooks {}
+
+impl MutationHooks {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn on_insert<F: FnMut(&Tuple, &PrimaryKey<'_>) + Send + 'static>(&mut self, hook: F) {
+        self.on_insert.push(Box::new(hook));
+    }
+
+    pub fn on_delete<F: FnMut(&Tuple, &PrimaryKey<'_>) + Send + 'static>(&mut self, hook: F) {
+        self.on_delete.push(Box::new(hook));
+    }
+
+    pub fn inserted(&mut self, tuple: &Tuple, key: &PrimaryKey<'_>) {
+        for hook in &mut self.on_insert {
+            hook(tuple, key);
+        }
+    }
+
+    pub fn deleted(&mut self, tuple: &Tuple, key: &PrimaryKey<'_>) {
+        for hook in &mut self.on_delete {
+            hook(tuple, key);
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.on_insert.is_empty() && self.on_delete.is_empty()
+    }
+}
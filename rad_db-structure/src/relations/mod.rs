@@ -4,6 +4,13 @@ use rad_db_types::Type;
 mod relation_struct;
 pub use relation_struct::*;
 
+pub mod blob;
+pub mod bucket_size;
+pub mod bulk;
+pub mod foreign;
+pub mod hooks;
+pub mod quota;
+pub mod statistics;
 pub mod tuple_storage;
 
 pub trait AsTypeList {
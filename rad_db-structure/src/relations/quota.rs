@@ -0,0 +1,55 @@
+//! Limits on how large a relation can grow
+
+use std::fmt::{Display, Formatter};
+
+/// The most tuples and bytes a relation may hold. Bytes are counted as the size of the tuples
+/// in the relation's block files.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Quota {
+    max_tuples: Option<usize>,
+    max_bytes: Option<u64>,
+}
+
+impl Quota {
+    /// A quota without any limits
+    pub fn unlimited() -> Self {
+        Self::default()
+    }
+
+    pub fn with_max_tuples(mut self, max_tuples: usize) -> Self {
+        self.max_tuples = Some(max_tuples);
+        self
+    }
+
+    pub fn with_max_bytes(mut self, max_bytes: u64) -> Self {
+        self.max_bytes = Some(max_bytes);
+        self
+    }
+
+    pub fn max_tuples(&self) -> Option<usize> {
+        self.max_tuples
+    }
+
+    pub fn max_bytes(&self) -> Option<u64> {
+        self.max_bytes
+    }
+
+    /// Whether the usage is within the limits of the quota
+    pub fn allows(&self, usage: QuotaUsage) -> bool {
+        self.max_tuples.map_or(true, |max| usage.tuples <= max)
+            && self.max_bytes.map_or(true, |max| usage.bytes <= max)
+    }
+}
+
+/// How much of its quota a relation uses
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct QuotaUsage {
+    pub tuples: usize,
+    pub bytes: u64,
+}
+
+impl Display for QuotaUsage {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} tuples, {} bytes", self.tuples, self.bytes)
+    }
+}
@@ -1,23 +1,76 @@
+use std::collections::{HashMap, HashSet};
 use std::fmt::{Debug, Formatter};
+use std::io;
+use std::io::Write;
 use std::iter::FromIterator;
-use std::ops::{Deref, DerefMut, Index, Shr};
+use std::ops::{Bound, Deref, DerefMut, Index, Shr};
 use std::path::{Path, PathBuf};
-use std::sync::atomic::{AtomicUsize, Ordering};
-
-use rad_db_types::Type;
-
-use crate::identifier::Identifier;
-use crate::key::primary::PrimaryKeyDefinition;
-use crate::relations::tuple_storage::{BlockIterator, StoredTupleIterator, TupleStorage};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::OnceLock;
+use std::time::{Duration, SystemTime};
+
+use num_bigint::BigUint;
+use rad_db_types::comparison::TextComparison;
+use rad_db_types::serialization::TextFormat;
+use rad_db_types::{Kind, Text, Type, Value};
+
+use crate::constraint::{Constraint, ConstraintPipeline, RelationLookup};
+use crate::history::{RetentionPolicy, VersionHistory};
+use crate::identifier::{CasePolicy, Identifier};
+use crate::key::encoding::EncodedKey;
+use crate::key::index::KeyIndex;
+use crate::key::primary::{PrimaryKey, PrimaryKeyDefinition};
+use crate::key::secondary::{IndexEntry, IndexPredicate, SecondaryIndex};
+use crate::relations::blob::{
+    file_of, handles_in, id_of, BlobIds, BlobReader, BlobWriter, BLOBS_DIRECTORY, INLINE_BLOB_BYTES,
+};
+use crate::relations::bucket_size::{should_resize, BucketSize, TupleWidths};
+use crate::relations::hooks::MutationHooks;
+use crate::relations::quota::{Quota, QuotaUsage};
+use crate::relations::statistics::{FieldStatistics, RelationStatistics};
+use crate::relations::tuple_storage::{
+    BlockIterator, InsertionResult, LockResult, StoredTupleIterator, TupleInsertionError,
+    TupleStorage,
+};
 use crate::relations::AsTypeList;
+use crate::temp;
 use crate::tuple::Tuple;
 use crate::Rename;
 
+/// The file in the storage directory of a relation that marks it as cleanly shut down
+const CLEAN_SHUTDOWN_FILE: &str = "CLEAN_SHUTDOWN";
+/// The file the seeds the primary key hashes keys with are kept in, next to the stored tuples
+/// that were hashed with them
+const KEY_SEEDS_FILE: &str = "KEY_SEEDS";
+
 pub struct Relation {
     name: Identifier,
     attributes: Vec<(String, Type)>,
     primary_key: PrimaryKeyDefinition,
     backing_table: TupleStorage,
+    case_policy: CasePolicy,
+    /// How conditions that don't set how they compare text compare each field
+    text_comparisons: Vec<TextComparison>,
+    constraints: ConstraintPipeline,
+    history: Option<VersionHistory>,
+    hooks: MutationHooks,
+    quota: Quota,
+    /// The bytes taken up by the tuples, only kept while there's a limit on them
+    bytes_used: Option<u64>,
+    /// Changed whenever the schema of the relation changes, and never the same as the version of
+    /// any other relation
+    schema_version: u64,
+    statistics: Option<RelationStatistics>,
+    /// The fraction of tuples that have to be modified before the relation is analyzed again
+    auto_analyze: Option<f64>,
+    /// Built the first time a range of keys is read, and kept up to date afterwards
+    key_index: OnceLock<KeyIndex>,
+    /// Kept in memory, and up to date with every tuple stored
+    indexes: Vec<SecondaryIndex>,
+    bucket_sizing: BucketSize,
+    /// Only kept while the size of blocks is in bytes
+    tuple_widths: Option<TupleWidths>,
+    blob_ids: BlobIds,
 }
 
 impl Relation {
@@ -28,6 +81,87 @@ impl Relation {
         bucket_size: usize,
         primary_key: PrimaryKeyDefinition,
     ) -> Self {
+        Self::new_with_text_format(
+            name,
+            attributes,
+            bucket_size,
+            primary_key,
+            TextFormat::default(),
+        )
+    }
+
+    /// Creates a new relation that will save it's contents into the file system, using the
+    /// given delimiter, quote and `NULL` token
+    ///
+    /// # Panic
+    /// Panics if the relation can't be [opened](Relation::open_with_text_format)
+    pub fn new_with_text_format<S: ToString, I: IntoIterator<Item = (S, Type)>>(
+        name: Identifier,
+        attributes: I,
+        bucket_size: usize,
+        primary_key: PrimaryKeyDefinition,
+        text_format: TextFormat,
+    ) -> Self {
+        Self::open_with_text_format(
+            name.clone(),
+            attributes,
+            bucket_size,
+            primary_key,
+            text_format,
+        )
+        .unwrap_or_else(|e| panic!("Couldn't open {}: {}", name, e))
+    }
+
+    /// Opens the relation that saves its contents into the file system, creating it if it has
+    /// no files yet
+    pub fn open<S: ToString, I: IntoIterator<Item = (S, Type)>>(
+        name: Identifier,
+        attributes: I,
+        bucket_size: usize,
+        primary_key: PrimaryKeyDefinition,
+    ) -> io::Result<Self> {
+        Self::open_with_text_format(
+            name,
+            attributes,
+            bucket_size,
+            primary_key,
+            TextFormat::default(),
+        )
+    }
+
+    /// Opens the relation that saves its contents into the file system like
+    /// [open](Relation::open), using the given delimiter, quote and `NULL` token. Fails if the
+    /// stored tuples were hashed with other seeds than the primary key hashes keys with, as none
+    /// of them could be found by their key.
+    pub fn open_with_text_format<S: ToString, I: IntoIterator<Item = (S, Type)>>(
+        name: Identifier,
+        attributes: I,
+        bucket_size: usize,
+        primary_key: PrimaryKeyDefinition,
+        text_format: TextFormat,
+    ) -> io::Result<Self> {
+        let directory = storage_directory_of(&name);
+        let seeds_file = directory.join(KEY_SEEDS_FILE);
+        match std::fs::read_to_string(&seeds_file) {
+            Ok(contents) => {
+                let stored = parse_seeds(&contents).ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("{} doesn't hold primary key seeds", seeds_file.display()),
+                    )
+                })?;
+                primary_key
+                    .verify_seeds(stored)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                let [a, b, c, d] = primary_key.seeds();
+                std::fs::create_dir_all(&directory)?;
+                std::fs::write(&seeds_file, format!("{} {} {} {}\n", a, b, c, d))?;
+            }
+            Err(e) => return Err(e),
+        }
+
         let attributes: Vec<(String, Type)> = attributes
             .into_iter()
             .map(|(s, ty)| (s.to_string(), ty))
@@ -37,15 +171,32 @@ impl Relation {
             .into_iter()
             .map(|(string, ty)| (Identifier::with_parent(&name, string), ty))
             .collect();
-        let definition = RelationDefinition::new(definition);
+        let definition = RelationDefinition::new(definition).with_text_format(text_format);
         let backing_table =
             Relation::generate_tuple_storage(&name, bucket_size, &primary_key, definition);
-        Relation {
+        // the storage is in use until the relation is closed again
+        let _ = std::fs::remove_file(directory.join(CLEAN_SHUTDOWN_FILE));
+        Ok(Relation {
             name,
+            text_comparisons: vec![TextComparison::default(); attributes.len()],
             attributes,
             primary_key,
             backing_table,
-        }
+            case_policy: CasePolicy::default(),
+            constraints: ConstraintPipeline::new(),
+            history: None,
+            hooks: MutationHooks::new(),
+            quota: Quota::unlimited(),
+            bytes_used: None,
+            schema_version: next_schema_version(),
+            statistics: None,
+            auto_analyze: None,
+            key_index: OnceLock::new(),
+            indexes: vec![],
+            bucket_sizing: BucketSize::Tuples(bucket_size),
+            tuple_widths: None,
+            blob_ids: BlobIds::default(),
+        })
     }
 
     /// Creates a relation that only lasts for as long as the program runs
@@ -69,9 +220,24 @@ impl Relation {
             TupleStorage::new_volatile(name.clone(), definition,primary_key.clone(), bucket_size);
         Relation {
             name,
+            text_comparisons: vec![TextComparison::default(); attributes.len()],
             attributes,
             primary_key,
             backing_table,
+            case_policy: CasePolicy::default(),
+            constraints: ConstraintPipeline::new(),
+            history: None,
+            hooks: MutationHooks::new(),
+            quota: Quota::unlimited(),
+            bytes_used: None,
+            schema_version: next_schema_version(),
+            statistics: None,
+            auto_analyze: None,
+            key_index: OnceLock::new(),
+            indexes: vec![],
+            bucket_sizing: BucketSize::Tuples(bucket_size),
+            tuple_widths: None,
+            blob_ids: BlobIds::default(),
         }
     }
 
@@ -84,6 +250,196 @@ impl Relation {
         TupleStorage::new(name.clone(), definition, primary_key.clone(), bucket_size)
     }
 
+    /// Closes the relation, writing every loaded block to its file, merging the logs of the
+    /// blocks into their files, and then marking the storage of the relation as cleanly shut
+    /// down. If a block can't be written, the error is returned and the storage isn't marked.
+    /// Dropping a relation also writes its loaded blocks, but doesn't mark the storage.
+    pub fn close(self) -> std::io::Result<()> {
+        let directory = self.backing_table.directory();
+        let tuples = self.len();
+        self.backing_table.try_flush()?;
+        self.backing_table.try_compact()?;
+        std::mem::drop(self);
+        if let Some(directory) = directory {
+            std::fs::create_dir_all(&directory)?;
+            std::fs::write(directory.join(CLEAN_SHUTDOWN_FILE), format!("{}\n", tuples))?;
+        }
+        Ok(())
+    }
+
+    /// Gets the directory the files of the relation are stored in, or `None` if it's only kept in
+    /// memory
+    pub fn storage_directory(&self) -> Option<PathBuf> {
+        self.backing_table.directory()
+    }
+
+    /// Gets the directory blobs stored out of line are kept in, or `None` if the relation is only
+    /// kept in memory, where blobs are always kept within their tuples
+    pub fn blob_directory(&self) -> Option<PathBuf> {
+        self.storage_directory()
+            .map(|directory| directory.join(BLOBS_DIRECTORY))
+    }
+
+    /// Starts writing a blob out of line. The value the writer is [finished](BlobWriter::finish)
+    /// with can be inserted into this relation in place of the blob.
+    pub fn write_blob(&self) -> io::Result<BlobWriter> {
+        let directory = self.blob_directory().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("{} is only kept in memory", self.name),
+            )
+        })?;
+        self.blob_ids.create(&directory)
+    }
+
+    /// Reads a blob of a tuple of this relation, whether it's stored out of line or within the
+    /// tuple
+    pub fn read_blob(&self, value: &Value) -> io::Result<BlobReader> {
+        match value {
+            Type::Optional(Some(inner)) => self.read_blob(inner),
+            Type::Text(Text::Blob(bytes)) => Ok(BlobReader::inline(bytes.clone())),
+            Type::Text(Text::BlobRef(handle)) => match self.blob_directory() {
+                Some(directory) => BlobReader::open(&directory, *handle),
+                None => Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("{} doesn't store blobs out of line", self.name),
+                )),
+            },
+            other => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} isn't a blob", other),
+            )),
+        }
+    }
+
+    /// Removes the files of the blobs stored out of line that no tuple holds the handle of,
+    /// returning how many were removed. Blobs that were written but aren't inserted yet are
+    /// removed too.
+    pub fn remove_unreferenced_blobs(&self) -> io::Result<usize> {
+        let directory = match self.blob_directory() {
+            Some(directory) if directory.is_dir() => directory,
+            _ => return Ok(0),
+        };
+        let referenced: HashSet<u64> = self
+            .tuples()
+            .flat_map(|tuple| tuple.iter().flat_map(handles_in).collect::<Vec<_>>())
+            .map(|handle| handle.id())
+            .collect();
+        let mut removed = 0;
+        for entry in std::fs::read_dir(&directory)? {
+            let path = entry?.path();
+            if let Some(id) = id_of(&path) {
+                if !referenced.contains(&id) {
+                    std::fs::remove_file(path)?;
+                    removed += 1;
+                }
+            }
+        }
+        Ok(removed)
+    }
+
+    /// Moves the blobs of the tuple that are longer than [INLINE_BLOB_BYTES] into files of their
+    /// own, leaving their handles in their place and adding their ids to `written`. Blobs in the
+    /// primary key stay in the tuple.
+    fn store_blobs_out_of_line(
+        &self,
+        mut tuple: Tuple,
+        written: &mut Vec<u64>,
+    ) -> io::Result<Tuple> {
+        if self.backing_table.directory().is_none() {
+            return Ok(tuple);
+        }
+        for (index, value) in tuple.iter_mut().enumerate() {
+            if self.primary_key.contains(&index) {
+                continue;
+            }
+            let value = match value {
+                Type::Optional(Some(inner)) => &mut **inner,
+                value => value,
+            };
+            if let Type::Text(Text::Blob(bytes)) = value {
+                if bytes.len() > INLINE_BLOB_BYTES {
+                    let mut writer = self.write_blob()?;
+                    writer.write_all(bytes)?;
+                    *value = writer.finish()?;
+                    written.extend(handles_in(value).iter().map(|handle| handle.id()));
+                }
+            }
+        }
+        Ok(tuple)
+    }
+
+    /// Removes the files of blobs that were stored out of line for tuples that weren't inserted
+    fn remove_blobs(&self, ids: &[u64]) {
+        if let Some(directory) = self.blob_directory() {
+            for &id in ids {
+                if let Err(e) = std::fs::remove_file(file_of(&directory, id)) {
+                    log::warn!("Couldn't remove the blob {} of {}: {}", id, self.name, e);
+                }
+            }
+        }
+    }
+
+    /// Gets whether the storage of the relation with this name was closed with
+    /// [close](Relation::close) the last time it was used, and hasn't been opened since
+    pub fn closed_cleanly(name: &Identifier) -> bool {
+        storage_directory_of(name)
+            .join(CLEAN_SHUTDOWN_FILE)
+            .is_file()
+    }
+
+    /// Creates a relation with the name that has the fields, primary key, constraints and tuples
+    /// of this one, along with its case policy, quota and sizes of blocks. The copy is stored in
+    /// files if this relation is. Indexes, hooks and the history of tuples aren't copied.
+    pub fn copy_table(&self, name: Identifier) -> Relation {
+        let mut copy = match self.backing_table.directory() {
+            Some(_) => Relation::new_with_text_format(
+                name,
+                self.attributes.clone(),
+                self.bucket_size(),
+                self.primary_key.clone(),
+                self.backing_table.relation().text_format().clone(),
+            ),
+            None => Relation::new_volatile(
+                name,
+                self.attributes.clone(),
+                self.bucket_size(),
+                self.primary_key.clone(),
+            ),
+        };
+        copy.constraints = self.constraints.clone();
+        copy.case_policy = self.case_policy;
+        copy.auto_analyze = self.auto_analyze;
+        copy.bucket_sizing = self.bucket_sizing;
+        copy.tuple_widths = self.tuple_widths;
+        let copied = self
+            .backing_table
+            .copy_into(&mut copy.backing_table)
+            .expect("the copy isn't shared with other threads yet");
+        if let Err(e) = self.copy_blobs_into(&copy) {
+            log::warn!("Couldn't copy the blobs of {}: {}", self.name, e);
+        }
+        copy.set_quota(self.quota);
+        copy.modified(copied);
+        copy
+    }
+
+    /// Copies the files of the blobs stored out of line into the blob directory of the copy
+    fn copy_blobs_into(&self, copy: &Relation) -> io::Result<()> {
+        let (from, to) = match (self.blob_directory(), copy.blob_directory()) {
+            (Some(from), Some(to)) if from.is_dir() => (from, to),
+            _ => return Ok(()),
+        };
+        std::fs::create_dir_all(&to)?;
+        for entry in std::fs::read_dir(&from)? {
+            let path = entry?.path();
+            if let Some(id) = id_of(&path) {
+                std::fs::copy(&path, file_of(&to, id))?;
+            }
+        }
+        Ok(())
+    }
+
     /// Loads the relation from memory
     pub fn load_from_memory(id: Identifier) -> Self {
         unimplemented!()
@@ -104,6 +460,46 @@ impl Relation {
         &self.primary_key
     }
 
+    /// Gets how the case of names is treated when fields of the relation are looked up
+    pub fn case_policy(&self) -> CasePolicy {
+        self.case_policy
+    }
+
+    /// Sets how the case of names is treated when fields of the relation are looked up
+    pub fn set_case_policy(&mut self, policy: CasePolicy) {
+        self.case_policy = policy;
+        self.schema_changed();
+    }
+
+    /// Gets how conditions on the field at the index compare text, unless they set how they
+    /// compare it themselves
+    pub fn text_comparison(&self, index: usize) -> TextComparison {
+        self.text_comparisons[index]
+    }
+
+    /// Sets how conditions on the field at the index compare text, unless they set how they
+    /// compare it themselves, such as ignoring trailing spaces for a `CHAR` field
+    pub fn set_text_comparison(&mut self, index: usize, comparison: TextComparison) {
+        self.text_comparisons[index] = comparison;
+        self.schema_changed();
+    }
+
+    /// Gets a number that changes whenever the schema of the relation changes, which includes
+    /// its case policy and constraints. No two relations of the process ever have the same
+    /// version, even if one has taken the name of another that was dropped.
+    pub fn schema_version(&self) -> u64 {
+        self.schema_version
+    }
+
+    fn schema_changed(&mut self) {
+        self.schema_version = next_schema_version();
+    }
+
+    /// Gets the format tuples of the relation are stored in
+    pub fn text_format(&self) -> &TextFormat {
+        self.backing_table.relation().text_format()
+    }
+
     /// Gets the amount of tuples in the relation
     pub fn len(&self) -> usize {
         self.backing_table.len()
@@ -120,31 +516,781 @@ impl Relation {
             let identifier = Identifier::with_parent(&self.name, name);
             ret.push((identifier, ty.clone()));
         }
-        RelationDefinition::new(ret)
+        let mut definition =
+            RelationDefinition::new(ret).with_text_format(self.text_format().clone());
+        for (index, comparison) in self.text_comparisons.iter().enumerate() {
+            definition = definition.with_text_comparison(index, *comparison);
+        }
+        definition
+    }
+
+    /// Finds the tuple whose primary key has the values, given in the order the fields of the
+    /// key appear in the relation. Only the block the key hashes into is read, so the values must
+    /// have the exact types of the fields.
+    pub fn find_by_key(&self, key: &[Type]) -> Option<Tuple> {
+        let key = PrimaryKey::new(key.iter().collect(), self.primary_key.seeds());
+        self.backing_table.find_by_primary(key)
+    }
+
+    /// Gets the tuples whose primary keys are within the bounds, in key order, only reading the
+    /// blocks those tuples are stored in. A bound is a prefix of the key, with values for the
+    /// first fields of the key in the order they appear in the relation, so it includes or
+    /// excludes every key starting with those values.
+    pub fn key_range<'r>(
+        &'r self,
+        lower: Bound<&[Type]>,
+        upper: Bound<&[Type]>,
+    ) -> impl Iterator<Item = Tuple> + 'r {
+        let hashes: Vec<BigUint> = self.key_hashes(lower, upper).cloned().collect();
+        hashes
+            .into_iter()
+            .filter_map(move |hash| self.backing_table.find_by_hash(hash))
+    }
+
+    /// The number of tuples whose primary keys are within the bounds, counted without reading
+    /// the tuples
+    pub fn key_range_len(&self, lower: Bound<&[Type]>, upper: Bound<&[Type]>) -> usize {
+        self.key_hashes(lower, upper).count()
+    }
+
+    fn key_hashes(
+        &self,
+        lower: Bound<&[Type]>,
+        upper: Bound<&[Type]>,
+    ) -> impl Iterator<Item = &BigUint> + '_ {
+        let encode = |bound: Bound<&[Type]>| match bound {
+            Bound::Included(prefix) => Bound::Included(self.encode_key_prefix(prefix)),
+            Bound::Excluded(prefix) => Bound::Excluded(self.encode_key_prefix(prefix)),
+            Bound::Unbounded => Bound::Unbounded,
+        };
+        let (lower, upper) = (encode(lower), encode(upper));
+        self.key_index().range(lower.as_ref(), upper.as_ref())
+    }
+
+    /// Encodes values for the first fields of the key, converting them to the types of the fields
+    /// where they can be so they're encoded the same way as the stored keys
+    fn encode_key_prefix(&self, prefix: &[Type]) -> EncodedKey {
+        let mut fields = self.primary_key.to_vec();
+        fields.sort_unstable();
+        self.encode_prefix(prefix, &fields)
+    }
+
+    /// Encodes values for the first of the fields, converted to the types of the fields where
+    /// they can be
+    fn encode_prefix(&self, prefix: &[Type], fields: &[usize]) -> EncodedKey {
+        let values: Vec<Type> = prefix
+            .iter()
+            .zip(fields)
+            .map(|(value, &field)| {
+                value
+                    .coerce_like(&self.attributes[field].1)
+                    .unwrap_or_else(|| value.clone())
+            })
+            .collect();
+        EncodedKey::new(&values)
+    }
+
+    /// The ordered index of the primary keys, which is built the first time it's used
+    fn key_index(&self) -> &KeyIndex {
+        self.key_index.get_or_init(|| {
+            let mut index = KeyIndex::new();
+            for tuple in self.tuples() {
+                let hash = self.backing_table.hash_tuple(&tuple);
+                index.insert(self.primary_key.key_of(&tuple).encode(), hash);
+            }
+            index
+        })
+    }
+
+    /// Adds the key of a stored tuple to the key index, if it has been built, removing the key of
+    /// the tuple it replaced when that was encoded differently
+    fn index_key(&mut self, tuple: &Tuple, replaced: Option<&Tuple>, hash: BigUint) {
+        if let Some(index) = self.key_index.get_mut() {
+            let key = self.primary_key.key_of(tuple).encode();
+            if let Some(old) = replaced {
+                let old_key = self.primary_key.key_of(old).encode();
+                if old_key != key {
+                    index.remove(&old_key);
+                }
+            }
+            index.insert(key, hash);
+        }
+    }
+
+    /// Creates an index of the fields, in the order given, returning whether it was created.
+    /// It isn't if there's already an index with the name.
+    pub fn create_index<S: ToString>(&mut self, name: S, fields: Vec<usize>) -> bool {
+        let index = SecondaryIndex::new(name, fields, &self.primary_key);
+        self.add_index(index)
+    }
+
+    /// Creates an index of the fields that only holds the tuples the predicate matches,
+    /// returning whether it was created. It isn't if there's already an index with the name.
+    pub fn create_partial_index<S: ToString, P: IndexPredicate + 'static>(
+        &mut self,
+        name: S,
+        fields: Vec<usize>,
+        predicate: P,
+    ) -> bool {
+        let index = SecondaryIndex::partial(name, fields, &self.primary_key, predicate);
+        self.add_index(index)
+    }
+
+    /// Creates an index of the fields that also keeps the values of the included fields, so that
+    /// queries that only need the fields it covers can be answered without reading any blocks.
+    /// Returns whether it was created, which it isn't if there's already an index with the name.
+    pub fn create_covering_index<S: ToString>(
+        &mut self,
+        name: S,
+        fields: Vec<usize>,
+        included: Vec<usize>,
+    ) -> bool {
+        let index = SecondaryIndex::new(name, fields, &self.primary_key).including(included);
+        self.add_index(index)
+    }
+
+    fn add_index(&mut self, mut index: SecondaryIndex) -> bool {
+        if self.index(index.name()).is_some() {
+            return false;
+        }
+        for tuple in self.tuples() {
+            let hash = self.backing_table.hash_tuple(&tuple);
+            index.insert(&tuple, hash);
+        }
+        self.indexes.push(index);
+        self.schema_changed();
+        true
+    }
+
+    /// Removes the index with the name, returning whether there was one
+    pub fn drop_index(&mut self, name: &str) -> bool {
+        let before = self.indexes.len();
+        self.indexes.retain(|index| index.name() != name);
+        let removed = self.indexes.len() < before;
+        if removed {
+            self.schema_changed();
+        }
+        removed
+    }
+
+    /// Gets the indexes of fields other than the primary key
+    pub fn indexes(&self) -> &[SecondaryIndex] {
+        &self.indexes
+    }
+
+    pub fn index(&self, name: &str) -> Option<&SecondaryIndex> {
+        self.indexes.iter().find(|index| index.name() == name)
+    }
+
+    /// Gets the tuples held by an index whose indexed values are within the bounds, in index
+    /// order, only reading the blocks those tuples are stored in. A bound is a prefix of the
+    /// indexed values, like the bounds of [Relation::key_range].
+    ///
+    /// # Panic
+    /// Panics if there's no index with the name
+    pub fn index_range<'r>(
+        &'r self,
+        name: &str,
+        lower: Bound<&[Type]>,
+        upper: Bound<&[Type]>,
+    ) -> impl Iterator<Item = Tuple> + 'r {
+        let hashes: Vec<BigUint> = self
+            .index_entries(name, lower, upper)
+            .map(|entry| entry.hash().clone())
+            .collect();
+        hashes
+            .into_iter()
+            .filter_map(move |hash| self.backing_table.find_by_hash(hash))
+    }
+
+    /// The number of tuples held by an index whose indexed values are within the bounds, counted
+    /// without reading the tuples
+    ///
+    /// # Panic
+    /// Panics if there's no index with the name
+    pub fn index_range_len(
+        &self,
+        name: &str,
+        lower: Bound<&[Type]>,
+        upper: Bound<&[Type]>,
+    ) -> usize {
+        self.index_entries(name, lower, upper).count()
+    }
+
+    /// Gets the values of the fields an index covers, in the order of the relation, for the
+    /// tuples it holds whose indexed values are within the bounds. They're read from the index
+    /// alone, in index order, without reading any blocks.
+    ///
+    /// # Panic
+    /// Panics if there's no index with the name
+    pub fn index_only_range<'r>(
+        &'r self,
+        name: &str,
+        lower: Bound<&[Type]>,
+        upper: Bound<&[Type]>,
+    ) -> impl Iterator<Item = Tuple> + 'r {
+        self.index_entries(name, lower, upper)
+            .map(|entry| Tuple::new(entry.values().to_vec()))
+    }
+
+    fn index_entries(
+        &self,
+        name: &str,
+        lower: Bound<&[Type]>,
+        upper: Bound<&[Type]>,
+    ) -> impl Iterator<Item = &IndexEntry> + '_ {
+        let index = self
+            .index(name)
+            .unwrap_or_else(|| panic!("No index named {} on {}", name, self.name));
+        let encode = |bound: Bound<&[Type]>| match bound {
+            Bound::Included(prefix) => Bound::Included(self.encode_prefix(prefix, index.fields())),
+            Bound::Excluded(prefix) => Bound::Excluded(self.encode_prefix(prefix, index.fields())),
+            Bound::Unbounded => Bound::Unbounded,
+        };
+        let (lower, upper) = (encode(lower), encode(upper));
+        index.range(lower.as_ref(), upper.as_ref())
+    }
+
+    /// Moves a tuple being stored into the indexes that hold it, out of the place of the tuple
+    /// it replaces
+    fn index_tuple(&mut self, tuple: &Tuple, replaced: Option<&Tuple>, hash: &BigUint) {
+        for index in &mut self.indexes {
+            if let Some(old) = replaced {
+                index.remove(old);
+            }
+            index.insert(tuple, hash.clone());
+        }
+    }
+
+    /// The number of blocks the tuples of the relation are stored in
+    pub fn block_count(&self) -> usize {
+        self.backing_table.block_count()
+    }
+
+    /// The most tuples one block of the relation can hold
+    pub fn bucket_size(&self) -> usize {
+        self.backing_table.bucket_size()
+    }
+
+    /// Writes the blocks of the relation that were changed to their files, so that the files hold
+    /// every tuple even while the blocks stay loaded, and returns how many blocks were written.
+    /// Volatile relations have no files, and never write any blocks.
+    pub fn flush(&self) -> usize {
+        self.backing_table.flush()
+    }
+
+    /// Merges the changes each block appended to its log into the file of the block, and returns
+    /// how many blocks were compacted. Logs are merged on their own once they outgrow their
+    /// blocks, so this only needs to run as periodic maintenance.
+    pub fn compact(&self) -> usize {
+        self.backing_table.compact()
+    }
+
+    /// Gets how the size of the blocks of the relation is chosen
+    pub fn bucket_size_policy(&self) -> BucketSize {
+        self.bucket_sizing
+    }
+
+    /// Sets the size of the blocks of the relation. A size in tuples rebuilds the blocks with
+    /// that size right away, while a size in bytes rebuilds them whenever the average width of
+    /// the tuples stored calls for a different number of tuples per block.
+    pub fn set_bucket_size<B: Into<BucketSize>>(&mut self, bucket_size: B) {
+        self.bucket_sizing = bucket_size.into();
+        match self.bucket_sizing {
+            BucketSize::Tuples(tuples) => {
+                self.tuple_widths = None;
+                if tuples != self.bucket_size() {
+                    self.backing_table.resize_buckets(tuples);
+                }
+            }
+            BucketSize::Bytes(_) => {
+                let mut widths = TupleWidths::default();
+                for tuple in self.tuples() {
+                    widths.record(self.backing_table.stored_size(&tuple));
+                }
+                self.tuple_widths = Some(widths);
+                self.adapt_bucket_size();
+            }
+        }
+    }
+
+    /// Rebuilds the blocks if the average width of the tuples calls for blocks of a very
+    /// different number of tuples than they hold
+    fn adapt_bucket_size(&mut self) {
+        let average = match self.tuple_widths.and_then(|widths| widths.average()) {
+            Some(average) => average,
+            None => return,
+        };
+        let ideal = self.bucket_sizing.tuples_per_block(average);
+        if should_resize(self.bucket_size(), ideal) {
+            self.backing_table.resize_buckets(ideal);
+        }
+    }
+
+    /// Gets a [StoredTupleIterator] for the tuple storage
+    ///
+    /// [StoredTupleIterator]: tuple_storage::StoredTupleIterator
+    pub fn tuples(&self) -> StoredTupleIterator {
+        self.backing_table.all_tuples()
+    }
+
+    /// Gets a [BlockIterator] for the tuple storage
+    ///
+    /// [BlockIterator]: tuple_storage::BlockIterator
+    pub fn blocks(&self) -> BlockIterator {
+        self.backing_table.blocks()
+    }
+
+    /// Gets a [StoredTupleIterator] for the tuple storage, unless waiting for other threads to
+    /// finish with the blocks would deadlock or take longer than the
+    /// [lock timeout](Relation::set_lock_timeout)
+    pub fn try_tuples(&self) -> LockResult<StoredTupleIterator<'_>> {
+        self.backing_table.try_all_tuples()
+    }
+
+    /// Gets a [BlockIterator] for the tuple storage, unless waiting for other threads to finish
+    /// with the blocks would deadlock or take longer than the
+    /// [lock timeout](Relation::set_lock_timeout)
+    pub fn try_blocks(&self) -> LockResult<BlockIterator<'_>> {
+        self.backing_table.try_blocks()
+    }
+
+    /// How long inserts and fallible scans wait for other threads to finish with the blocks, or
+    /// `None` if they wait for as long as it takes
+    pub fn lock_timeout(&self) -> Option<Duration> {
+        self.backing_table.lock_timeout()
+    }
+
+    /// Limits how long inserts, [try_tuples](Relation::try_tuples) and
+    /// [try_blocks](Relation::try_blocks) wait for other threads to finish with the blocks
+    /// before failing with [LockError::Timeout]
+    ///
+    /// [LockError::Timeout]: crate::relations::tuple_storage::LockError::Timeout
+    pub fn set_lock_timeout(&mut self, timeout: Option<Duration>) {
+        self.backing_table.set_lock_timeout(timeout);
+    }
+
+    /// Makes the relation temporary, so that it's contents are deleted from the
+    /// file system after the relation drops
+    pub fn into_temp(self) -> TempRelation {
+        TempRelation::new(self)
+    }
+
+    /// Gets the constraints checked when tuples are inserted
+    pub fn constraints(&self) -> &ConstraintPipeline {
+        &self.constraints
+    }
+
+    /// Adds a constraint that is checked after the existing constraints
+    pub fn add_constraint<C: Constraint + 'static>(&mut self, constraint: C) {
+        self.constraints.push(constraint);
+        self.schema_changed();
+    }
+
+    /// Removes the constraint with the name, returning whether there was one
+    pub fn remove_constraint(&mut self, name: &str) -> bool {
+        let removed = self.constraints.remove(name);
+        if removed {
+            self.schema_changed();
+        }
+        removed
+    }
+
+    /// Sets whether the constraint with the name is only checked at the end of a batch, like a
+    /// bulk load, returning whether there is such a constraint
+    pub fn defer_constraint(&mut self, name: &str, deferred: bool) -> bool {
+        self.constraints.defer(name, deferred)
+    }
+
+    /// Inserts a tuple into the relation, after checking that it matches the relation's
+    /// definition and constraints
+    pub fn insert(&mut self, tuple: Tuple) -> InsertionResult<Option<Tuple>> {
+        self.insert_with(tuple, &())
+    }
+
+    /// Inserts a tuple into the relation, looking up the relations referenced by its
+    /// constraints in `others`
+    pub fn insert_with(
+        &mut self,
+        tuple: Tuple,
+        others: &dyn RelationLookup,
+    ) -> InsertionResult<Option<Tuple>> {
+        tuple
+            .validate(self.backing_table.relation())
+            .map_err(TupleInsertionError::IncorrectTypes)?;
+        self.constraints
+            .check(&tuple, self, others)
+            .map_err(TupleInsertionError::ConstraintViolations)?;
+        let mut blobs = vec![];
+        let tuple = match self.store_blobs_out_of_line(tuple, &mut blobs) {
+            Ok(tuple) => tuple,
+            Err(e) => {
+                self.remove_blobs(&blobs);
+                return Err(TupleInsertionError::BlobNotStored(e));
+            }
+        };
+        let added_bytes = self.added_bytes(&tuple);
+        if let Err(e) = self.check_quota(&tuple, added_bytes) {
+            self.remove_blobs(&blobs);
+            return Err(e);
+        }
+        if self.history.is_none()
+            && self.hooks.is_empty()
+            && added_bytes.is_none()
+            && self.indexes.is_empty()
+            && self.key_index.get().is_none()
+        {
+            let replaced = match self.backing_table.insert(tuple) {
+                Ok(replaced) => replaced,
+                Err(e) => {
+                    self.remove_blobs(&blobs);
+                    return Err(e);
+                }
+            };
+            self.modified(1);
+            return Ok(replaced);
+        }
+        let hash = self.backing_table.hash_tuple(&tuple);
+        let replaced = match self
+            .backing_table
+            .insert_hashed(tuple.clone(), hash.clone())
+        {
+            Ok(replaced) => replaced,
+            Err(e) => {
+                self.remove_blobs(&blobs);
+                return Err(e);
+            }
+        };
+        self.stored(tuple, replaced.as_ref(), &hash, added_bytes);
+        self.modified(1);
+        self.adapt_bucket_size();
+        Ok(replaced)
+    }
+
+    /// Keeps the indexes, size, hooks and history of the relation up to date with a tuple that
+    /// was just stored, taking up the bytes given if they're being counted
+    fn stored(
+        &mut self,
+        tuple: Tuple,
+        replaced: Option<&Tuple>,
+        hash: &BigUint,
+        added_bytes: Option<u64>,
+    ) {
+        self.index_key(&tuple, replaced, hash.clone());
+        if !self.indexes.is_empty() {
+            self.index_tuple(&tuple, replaced, hash);
+        }
+        if let Some(added) = added_bytes {
+            let removed = replaced.map_or(0, |old| self.backing_table.stored_size(old));
+            self.bytes_used = self.bytes_used.map(|used| used + added - removed);
+            if let Some(widths) = &mut self.tuple_widths {
+                widths.record(added);
+            }
+        }
+        if let Some(old) = replaced {
+            self.hooks.deleted(old, &self.primary_key.key_of(old));
+        }
+        self.hooks
+            .inserted(&tuple, &self.primary_key.key_of(&tuple));
+        if let Some(history) = &mut self.history {
+            history.record(tuple, replaced, SystemTime::now());
+        }
+    }
+
+    /// The bytes a tuple takes up when stored, if they're being counted
+    fn added_bytes(&self, tuple: &Tuple) -> Option<u64> {
+        if self.bytes_used.is_some() || self.tuple_widths.is_some() {
+            Some(self.backing_table.stored_size(tuple))
+        } else {
+            None
+        }
+    }
+
+    /// Stores a tuple through a shared reference, so that stress tests can write while other
+    /// threads read. The tuple is checked against the definition and constraints of the
+    /// relation, but its quota, blobs, indexes, hooks, history and statistics aren't kept up to
+    /// date.
+    #[cfg(any(test, feature = "testing"))]
+    pub(crate) fn store_shared(&self, tuple: Tuple) -> InsertionResult<Option<Tuple>> {
+        tuple
+            .validate(self.backing_table.relation())
+            .map_err(TupleInsertionError::IncorrectTypes)?;
+        self.constraints
+            .check(&tuple, self, &())
+            .map_err(TupleInsertionError::ConstraintViolations)?;
+        let hash = self.backing_table.hash_tuple(&tuple);
+        self.backing_table.insert_hashed(tuple, hash)
+    }
+
+    /// Stores tuples whose primary keys have already been hashed, which have already been checked
+    /// against the definition and constraints of the relation, returning how many were stored.
+    ///
+    /// Like [insert_with](Relation::insert_with), blobs are stored out of line, and the indexes,
+    /// size, hooks and history of the relation are kept up to date. The blobs and quota are
+    /// checked for every tuple before any is stored. If one of them can't be stored, its
+    /// position among the tuples is returned with why, and the relation is left as it was: the
+    /// tuples already stored are taken back out, the blobs written for them are removed, and no
+    /// hook or history sees any of them.
+    pub(crate) fn store_hashed<I: IntoIterator<Item = (BigUint, Tuple)>>(
+        &mut self,
+        tuples: I,
+    ) -> Result<usize, (usize, TupleInsertionError)> {
+        self.store_hashed_with(tuples, |storage, tuple, hash| {
+            storage.insert_hashed(tuple, hash)
+        })
+    }
+
+    /// Stores tuples like [store_hashed](Relation::store_hashed), putting each into the backing
+    /// table with `insert`
+    fn store_hashed_with<I, F>(
+        &mut self,
+        tuples: I,
+        mut insert: F,
+    ) -> Result<usize, (usize, TupleInsertionError)>
+    where
+        I: IntoIterator<Item = (BigUint, Tuple)>,
+        F: FnMut(&TupleStorage, Tuple, BigUint) -> InsertionResult<Option<Tuple>>,
+    {
+        let mut blobs = vec![];
+        let mut prepared = vec![];
+        for (position, (hash, tuple)) in tuples.into_iter().enumerate() {
+            match self.store_blobs_out_of_line(tuple, &mut blobs) {
+                Ok(tuple) => {
+                    let added_bytes = self.added_bytes(&tuple);
+                    prepared.push((hash, tuple, added_bytes));
+                }
+                Err(e) => {
+                    self.remove_blobs(&blobs);
+                    return Err((position, TupleInsertionError::BlobNotStored(e)));
+                }
+            }
+        }
+        if let Err(e) = self.check_batch_quota(&prepared) {
+            self.remove_blobs(&blobs);
+            return Err(e);
+        }
+
+        let mut replaced = Vec::with_capacity(prepared.len());
+        for (position, (hash, tuple, _)) in prepared.iter().enumerate() {
+            match insert(&self.backing_table, tuple.clone(), hash.clone()) {
+                Ok(old) => replaced.push(old),
+                Err(e) => {
+                    // later tuples with the same key replaced earlier ones, so they're undone
+                    // last to first
+                    for ((hash, _, _), old) in prepared.iter().zip(replaced).rev() {
+                        self.backing_table.restore_hashed(hash.clone(), old);
+                    }
+                    self.remove_blobs(&blobs);
+                    return Err((position, e));
+                }
+            }
+        }
+        let stored = prepared.len();
+        for ((hash, tuple, added_bytes), old) in prepared.into_iter().zip(replaced) {
+            self.stored(tuple, old.as_ref(), &hash, added_bytes);
+        }
+        self.modified(stored);
+        self.adapt_bucket_size();
+        Ok(stored)
+    }
+
+    /// Checks that storing the tuples one after the other keeps the relation within its quota,
+    /// returning the position of the first that would take it past it
+    fn check_batch_quota(
+        &self,
+        tuples: &[(BigUint, Tuple, Option<u64>)],
+    ) -> Result<(), (usize, TupleInsertionError)> {
+        if self.quota == Quota::unlimited() {
+            return Ok(());
+        }
+        let mut usage = QuotaUsage {
+            tuples: self.len(),
+            bytes: self.bytes_used.unwrap_or(0),
+        };
+        // the bytes of the latest tuple with each key, which a later one replaces
+        let mut batch: HashMap<&BigUint, u64> = HashMap::new();
+        for (position, (hash, _, added_bytes)) in tuples.iter().enumerate() {
+            let added = added_bytes.unwrap_or(0);
+            let removed = match batch.get(hash) {
+                Some(bytes) => Some(*bytes),
+                None => self.backing_table.find_by_hash(hash.clone()).map(|old| {
+                    if self.bytes_used.is_some() {
+                        self.backing_table.stored_size(&old)
+                    } else {
+                        0
+                    }
+                }),
+            };
+            match removed {
+                Some(removed) => usage.bytes = usage.bytes + added - removed,
+                None => {
+                    usage.tuples += 1;
+                    usage.bytes += added;
+                }
+            }
+            if !self.quota.allows(usage) {
+                return Err((
+                    position,
+                    TupleInsertionError::QuotaExceeded {
+                        usage,
+                        quota: self.quota,
+                    },
+                ));
+            }
+            batch.insert(hash, added);
+        }
+        Ok(())
+    }
+
+    /// Notes that tuples were inserted or replaced, analyzing the relation if enough of it has
+    /// changed
+    fn modified(&mut self, tuples: usize) {
+        if let Some(statistics) = &mut self.statistics {
+            statistics.record_modifications(tuples);
+        }
+        if let Some(fraction) = self.auto_analyze {
+            let stale = self
+                .statistics
+                .as_ref()
+                .map_or(true, |statistics| statistics.staleness() >= fraction);
+            if stale {
+                self.analyze();
+            }
+        }
+    }
+
+    /// Gathers statistics about the contents of the relation, replacing any gathered before
+    pub fn analyze(&mut self) -> &RelationStatistics {
+        let statistics = RelationStatistics::gather(
+            self.attributes.iter().map(|(name, _)| name.as_str()),
+            self.tuples(),
+        );
+        self.statistics.insert(statistics)
+    }
+
+    /// Gets the statistics gathered the last time the relation was analyzed
+    pub fn statistics(&self) -> Option<&RelationStatistics> {
+        self.statistics.as_ref()
+    }
+
+    /// Gets the statistics of a field gathered the last time the relation was analyzed, or
+    /// `None` if it hasn't been or there's no such field
+    pub fn column_stats<I: Into<Identifier>>(&self, field: I) -> Option<&FieldStatistics> {
+        let index = self.get_field_index(field)?;
+        self.statistics.as_ref()?.field(index)
+    }
+
+    /// Gets the fraction of tuples that have to be modified before the relation is analyzed
+    /// again automatically, if it is
+    pub fn auto_analyze(&self) -> Option<f64> {
+        self.auto_analyze
+    }
+
+    /// Analyzes the relation whenever the tuples modified since it was last analyzed reach the
+    /// fraction of the tuples it had then, or stops analyzing it automatically if `None`
+    pub fn set_auto_analyze(&mut self, fraction: Option<f64>) {
+        self.auto_analyze = fraction;
+    }
+
+    /// Fails if storing the tuple would take the relation past its quota. A tuple that replaces
+    /// one with the same primary key only uses the difference in their sizes.
+    fn check_quota(&self, tuple: &Tuple, added_bytes: Option<u64>) -> InsertionResult<()> {
+        if self.quota == Quota::unlimited() {
+            return Ok(());
+        }
+        let mut usage = QuotaUsage {
+            tuples: self.len() + 1,
+            bytes: self.bytes_used.unwrap_or(0) + added_bytes.unwrap_or(0),
+        };
+        if self.quota.allows(usage) {
+            return Ok(());
+        }
+        let hash = self.backing_table.hash_tuple(tuple);
+        if let Some(replaced) = self.backing_table.find_by_hash(hash) {
+            usage.tuples -= 1;
+            if self.bytes_used.is_some() {
+                usage.bytes -= self.backing_table.stored_size(&replaced);
+            }
+        }
+        if self.quota.allows(usage) {
+            Ok(())
+        } else {
+            Err(TupleInsertionError::QuotaExceeded {
+                usage,
+                quota: self.quota,
+            })
+        }
+    }
+
+    /// Gets the limits on the size of the relation
+    pub fn quota(&self) -> Quota {
+        self.quota
+    }
+
+    /// Limits the size of the relation. Tuples already in the relation are kept even if they
+    /// are past the quota, but no more can be inserted until there's room.
+    pub fn set_quota(&mut self, quota: Quota) {
+        self.quota = quota;
+        if quota.max_bytes().is_none() {
+            self.bytes_used = None;
+        } else if self.bytes_used.is_none() {
+            self.bytes_used = Some(self.stored_bytes());
+        }
+    }
+
+    /// Gets how much of its quota the relation uses
+    pub fn usage(&self) -> QuotaUsage {
+        QuotaUsage {
+            tuples: self.len(),
+            bytes: self.bytes_used.unwrap_or_else(|| self.stored_bytes()),
+        }
+    }
+
+    fn stored_bytes(&self) -> u64 {
+        self.tuples()
+            .map(|tuple| self.backing_table.stored_size(&tuple))
+            .sum()
+    }
+
+    /// Registers a callback that is given every tuple stored in the relation, after it has been
+    /// stored
+    pub fn on_insert<F: FnMut(&Tuple, &PrimaryKey<'_>) + Send + 'static>(&mut self, hook: F) {
+        self.hooks.on_insert(hook);
     }
 
-    /// Gets a [StoredTupleIterator] for the tuple storage
-    ///
-    /// [StoredTupleIterator]: tuple_storage::StoredTupleIterator
-    pub fn tuples(&self) -> StoredTupleIterator {
-        self.backing_table.all_tuples()
+    /// Registers a callback that is given every tuple removed from the relation, including
+    /// tuples replaced by a tuple with the same primary key
+    pub fn on_delete<F: FnMut(&Tuple, &PrimaryKey<'_>) + Send + 'static>(&mut self, hook: F) {
+        self.hooks.on_delete(hook);
     }
 
-    /// Gets a [BlockIterator] for the tuple storage
-    ///
-    /// [BlockIterator]: tuple_storage::BlockIterator
-    pub fn blocks(&self) -> BlockIterator {
-        self.backing_table.blocks()
+    /// Starts keeping the versions of the tuples of the relation, so that it can be read as it
+    /// was in the past. The tuples already in the relation are recorded as of now.
+    pub fn retain_history(&mut self, retention: RetentionPolicy) {
+        match &mut self.history {
+            Some(history) => history.set_retention(retention),
+            None => {
+                let mut history = VersionHistory::new(retention);
+                let now = SystemTime::now();
+                for tuple in self.backing_table.all_tuples() {
+                    history.record(tuple, None, now);
+                }
+                self.history = Some(history);
+            }
+        }
     }
 
-    /// Makes the relation temporary, so that it's contents are deleted from the
-    /// file system after the relation drops
-    pub fn into_temp(self) -> TempRelation {
-        TempRelation::new(self)
+    /// Gets the versions of the tuples, if the relation retains them
+    pub fn history(&self) -> Option<&VersionHistory> {
+        self.history.as_ref()
     }
 
-    pub fn insert(&mut self, tuple: Tuple) {
-        self.backing_table.insert(tuple);
+    /// Removes the versions of tuples the retention policy no longer keeps, returning how many
+    /// were removed
+    pub fn vacuum(&mut self) -> usize {
+        self.history
+            .as_mut()
+            .map_or(0, |history| history.vacuum(SystemTime::now()))
     }
 
     pub fn get_field_index<I : Into<Identifier>>(&self, identifier: I) -> Option<usize> {
@@ -158,7 +1304,7 @@ impl Relation {
                     identifier.base()
                 }
                 Some(parent) => {
-                    if parent == &self.name {
+                    if self.case_policy.identifiers_match(parent, &self.name) {
                         identifier.base()
                     } else {
                         return None;
@@ -169,14 +1315,69 @@ impl Relation {
         self.attributes
             .iter()
             .map(|(id, _)| id)
-            .position(|id| id == field_name)
+            .position(|id| self.case_policy.names_match(id, field_name))
+    }
+}
+
+/// Gives out schema versions from a counter shared by every relation
+fn next_schema_version() -> u64 {
+    static VERSIONS: AtomicU64 = AtomicU64::new(0);
+    VERSIONS.fetch_add(1, Ordering::Relaxed)
+}
+
+/// The directory the files of the relation with the name are stored in
+fn storage_directory_of(name: &Identifier) -> PathBuf {
+    let mut directory = PathBuf::from("DB_STORAGE");
+    for part in name {
+        directory.push(part);
+    }
+    directory
+}
+
+/// Reads the seeds written to the [KEY_SEEDS_FILE]
+fn parse_seeds(contents: &str) -> Option<[u64; 4]> {
+    let mut seeds = [0; 4];
+    let mut parts = contents.split_whitespace();
+    for seed in &mut seeds {
+        *seed = parts.next()?.parse().ok()?;
+    }
+    match parts.next() {
+        None => Some(seeds),
+        Some(_) => None,
     }
 }
 
 impl<I: Into<Identifier>> Rename<I> for Relation {
     fn rename(&mut self, name: I) {
+        let blobs = self.blob_directory();
+        let seeds = self
+            .storage_directory()
+            .map(|directory| directory.join(KEY_SEEDS_FILE));
         self.name = name.into();
-        self.backing_table.rename(self.name.clone())
+        self.backing_table.rename(self.name.clone());
+        if let (Some(from), Some(to)) = (seeds, self.storage_directory()) {
+            let moved = std::fs::create_dir_all(&to)
+                .and_then(|_| std::fs::rename(&from, to.join(KEY_SEEDS_FILE)));
+            match moved {
+                Ok(()) => {
+                    // only removed if the seeds were all that was left in it
+                    let _ = from.parent().map(std::fs::remove_dir);
+                }
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                Err(e) => log::warn!("Couldn't move the key seeds of {}: {}", self.name, e),
+            }
+        }
+        if let (Some(from), Some(to)) = (blobs, self.blob_directory()) {
+            if from.is_dir() {
+                let moved = to
+                    .parent()
+                    .map_or(Ok(()), std::fs::create_dir_all)
+                    .and_then(|_| std::fs::rename(&from, &to));
+                if let Err(e) = moved {
+                    log::warn!("Couldn't move the blobs of {}: {}", self.name, e);
+                }
+            }
+        }
     }
 }
 
@@ -186,15 +1387,42 @@ impl AsTypeList for Relation {
     }
 }
 
-/// A structure representing the actual names and types of a relation
+/// A structure representing the actual names and kinds of the fields of a relation
 #[derive(Debug, Clone)]
 pub struct RelationDefinition {
-    attributes: Vec<(Identifier, Type)>,
+    attributes: Vec<(Identifier, Kind)>,
+    text_format: TextFormat,
+    text_comparisons: Vec<TextComparison>,
 }
 
 impl RelationDefinition {
+    /// Creates a definition of fields declared with a value of their kind
     pub fn new(attributes: Vec<(Identifier, Type)>) -> Self {
-        RelationDefinition { attributes }
+        Self::with_kinds(
+            attributes
+                .into_iter()
+                .map(|(id, prototype)| (id, Kind::of(&prototype)))
+                .collect(),
+        )
+    }
+
+    pub fn with_kinds(attributes: Vec<(Identifier, Kind)>) -> Self {
+        RelationDefinition {
+            text_comparisons: vec![TextComparison::default(); attributes.len()],
+            attributes,
+            text_format: TextFormat::default(),
+        }
+    }
+
+    /// Sets the format tuples of the relation are stored in
+    pub fn with_text_format(mut self, text_format: TextFormat) -> Self {
+        self.text_format = text_format;
+        self
+    }
+
+    /// Gets the format tuples of the relation are stored in
+    pub fn text_format(&self) -> &TextFormat {
+        &self.text_format
     }
 
     /// Gets the minimum number of id levels
@@ -247,7 +1475,7 @@ impl RelationDefinition {
             if vec.is_empty() {
                 None
             } else {
-                Some(RelationDefinition::new(vec))
+                Some(self.with_fields(vec))
             }
         } else {
             let min = self.min_id_length();
@@ -269,11 +1497,20 @@ impl RelationDefinition {
             if vec.is_empty() {
                 None
             } else {
-                Some(RelationDefinition::new(vec))
+                Some(self.with_fields(vec))
             }
         }
     }
 
+    /// A definition of the same relation with the fields renamed, keeping its settings
+    fn with_fields(&self, attributes: Vec<(Identifier, Kind)>) -> RelationDefinition {
+        RelationDefinition {
+            attributes,
+            text_format: self.text_format.clone(),
+            text_comparisons: self.text_comparisons.clone(),
+        }
+    }
+
     pub fn identifier_iter(&self) -> impl IntoIterator<Item = &Identifier> {
         self.attributes.iter().map(|(id, _)| id)
     }
@@ -281,6 +1518,51 @@ impl RelationDefinition {
     pub fn len(&self) -> usize {
         self.attributes.len()
     }
+
+    /// The kind of the field at the index
+    pub fn kind(&self, index: usize) -> &Kind {
+        &self.attributes[index].1
+    }
+
+    /// Whether the field at the index accepts `NULL`
+    pub fn is_nullable(&self, index: usize) -> bool {
+        self.attributes[index].1.is_nullable()
+    }
+
+    /// The indexes of the fields that accept `NULL`
+    pub fn nullable_fields(&self) -> Vec<usize> {
+        (0..self.len())
+            .filter(|&index| self.is_nullable(index))
+            .collect()
+    }
+
+    /// The labels of the field at the index, if it's an enumeration. Values of the field are
+    /// stored as ordinals, which are read back into labels using these.
+    pub fn labels(&self, index: usize) -> Option<&[String]> {
+        match self.attributes[index].1.non_null() {
+            Kind::Enum(labels) => Some(labels),
+            _ => None,
+        }
+    }
+
+    /// Makes the field at the index accept `NULL`, or stop accepting it
+    pub fn with_nullable(mut self, index: usize, nullable: bool) -> Self {
+        let kind = &mut self.attributes[index].1;
+        *kind = std::mem::replace(kind, Kind::Any).nullable(nullable);
+        self
+    }
+
+    /// How conditions on the field at the index compare text, unless they set how they compare
+    /// it themselves
+    pub fn text_comparison(&self, index: usize) -> TextComparison {
+        self.text_comparisons[index]
+    }
+
+    /// Sets how conditions on the field at the index compare text
+    pub fn with_text_comparison(mut self, index: usize, comparison: TextComparison) -> Self {
+        self.text_comparisons[index] = comparison;
+        self
+    }
 }
 
 impl FromIterator<(Identifier, Type)> for RelationDefinition {
@@ -296,7 +1578,7 @@ impl FromIterator<(String, Type)> for RelationDefinition {
 }
 
 impl Index<usize> for RelationDefinition {
-    type Output = (Identifier, Type);
+    type Output = (Identifier, Kind);
 
     fn index(&self, index: usize) -> &Self::Output {
         &self.attributes[index]
@@ -304,7 +1586,7 @@ impl Index<usize> for RelationDefinition {
 }
 
 impl Index<Identifier> for RelationDefinition {
-    type Output = Type;
+    type Output = Kind;
 
     fn index(&self, index: Identifier) -> &Self::Output {
         for (id, ty) in &self.attributes {
@@ -352,31 +1634,33 @@ impl PartialEq for RelationDefinition {
     }
 }
 
+/// Iterates over a value of the kind of each field, which values of the fields are parsed like
 impl IntoIterator for &RelationDefinition {
     type Item = Type;
     type IntoIter = <Vec<Type> as IntoIterator>::IntoIter;
 
     fn into_iter(self) -> Self::IntoIter {
-        let ret: Vec<_> = self.attributes.iter().map(|(_, ty)| ty.clone()).collect();
+        let ret: Vec<_> = self
+            .attributes
+            .iter()
+            .map(|(_, kind)| kind.prototype())
+            .collect();
         ret.into_iter()
     }
 }
 
 /// A relation that automatically destroys all saved data
-#[repr(transparent)]
 #[derive(Debug)]
-pub struct TempRelation(Relation);
-
-static TEMP_COUNT: AtomicUsize = AtomicUsize::new(0);
+pub struct TempRelation(Relation, PathBuf);
 
 impl TempRelation {
+    /// Moves the relation into its own directory within the
+    /// [temporary namespace](crate::temp) of this process
     pub fn new(mut relation: Relation) -> Self {
-        let id = TEMP_COUNT.fetch_add(1, Ordering::Relaxed);
-        let name = format!("temp{}", id);
-        let fixed = Identifier::concat(name, &relation.name);
-        relation.rename(fixed);
-        std::fs::remove_dir_all(PathBuf::from(&relation.name));
-        Self(relation)
+        let name = temp::unique_identifier(&relation.name);
+        let directory = temp::directory_of(name.parent().expect("Temporary names have a parent"));
+        relation.rename(name);
+        Self(relation, directory)
     }
 }
 
@@ -399,9 +1683,12 @@ impl Drop for TempRelation {
         let skeleton = self.backing_table.to_skeleton();
         let internals = std::mem::replace(&mut self.backing_table, skeleton);
         std::mem::drop(internals);
-        let mut file = PathBuf::from("DB_STORAGE");
-        file.push(PathBuf::from(&self.name));
-        std::fs::remove_dir_all(file.parent().unwrap()).unwrap();
+        match std::fs::remove_dir_all(&self.1) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                log::warn!("Couldn't remove {}: {}", self.1.display(), e)
+            }
+            _ => {}
+        }
     }
 }
 
@@ -417,7 +1704,9 @@ impl Debug for Relation {
 
 #[cfg(test)]
 mod tests {
-    use rad_db_types::{Numeric, Unsigned};
+    use rad_db_types::{Enumeration, Numeric, Unsigned};
+
+    use crate::tuple::FieldTypeError;
 
     use super::*;
 
@@ -434,6 +1723,727 @@ mod tests {
         assert!(iterator.next().is_none());
     }
 
+    #[test]
+    fn invalid_tuples_rejected() {
+        let mut relation = Relation::new_volatile(
+            Identifier::new("validated"),
+            vec![
+                ("id", Type::from(0u8)),
+                ("code", Type::from(Text::String(String::new(), Some(2)))),
+                ("amount", Type::from(0u16)),
+            ],
+            4,
+            PrimaryKeyDefinition::new(vec![0]),
+        );
+        let invalid = Tuple::new(vec![
+            Type::Optional(None),
+            Type::from("abc"),
+            Type::from(70_000u32),
+        ]);
+        match relation.insert(invalid) {
+            Err(TupleInsertionError::IncorrectTypes(errors)) => assert_eq!(
+                errors,
+                vec![
+                    FieldTypeError::NotNullable(0),
+                    FieldTypeError::TooLong(1),
+                    FieldTypeError::OutOfRange(2)
+                ]
+            ),
+            other => panic!("Expected the tuple to be rejected, got {:?}", other),
+        }
+        assert!(relation.is_empty());
+        let valid = Tuple::new(vec![Type::from(1u8), Type::from("ab"), Type::from(7u16)]);
+        assert!(relation.insert(valid).is_ok());
+    }
+
+    #[test]
+    fn float_keys() {
+        let mut relation = Relation::new_volatile(
+            Identifier::new("measurements"),
+            vec![("reading", Type::from(Numeric::Double(0.0)))],
+            4,
+            PrimaryKeyDefinition::new(vec![0]),
+        );
+        for reading in &[0.0, 1.5, f64::NAN] {
+            let tuple = Tuple::new(vec![Type::from(Numeric::Double(*reading))]);
+            assert_eq!(relation.insert(tuple).unwrap(), None);
+        }
+        // -0.0 is equal to 0.0, and every NaN is equal to every other one
+        let negative_zero = Tuple::new(vec![Type::from(Numeric::Double(-0.0))]);
+        assert!(relation.insert(negative_zero).unwrap().is_some());
+        let other_nan = f64::from_bits(f64::NAN.to_bits() | 1);
+        let nan = Tuple::new(vec![Type::from(Numeric::Double(other_nan))]);
+        assert!(relation.insert(nan).unwrap().is_some());
+        assert_eq!(relation.len(), 3);
+        assert_eq!(
+            relation
+                .key_range(Bound::Unbounded, Bound::Unbounded)
+                .count(),
+            3
+        );
+        let zero = Tuple::new(vec![Type::from(Numeric::Double(0.0))]);
+        assert!(relation.insert(zero).unwrap().is_some());
+        assert_eq!(
+            relation
+                .key_range(Bound::Unbounded, Bound::Unbounded)
+                .count(),
+            3
+        );
+    }
+
+    #[test]
+    fn close() {
+        let name = Identifier::new(format!("closed{}", std::process::id()));
+        let mut relation = Relation::new(
+            name.clone(),
+            vec![("id", Type::from(0u8))],
+            4,
+            PrimaryKeyDefinition::new(vec![0]),
+        );
+        for i in 0..10u8 {
+            relation.insert(Tuple::new(vec![i.into()])).unwrap();
+        }
+        assert!(!Relation::closed_cleanly(&name));
+        relation.close().unwrap();
+        assert!(Relation::closed_cleanly(&name));
+
+        let reopened = Relation::new(
+            name.clone(),
+            vec![("id", Type::from(0u8))],
+            4,
+            PrimaryKeyDefinition::new(vec![0]),
+        );
+        assert!(!Relation::closed_cleanly(&name));
+        std::mem::drop(reopened);
+        std::fs::remove_dir_all(PathBuf::from("DB_STORAGE").join(name.to_string())).unwrap();
+    }
+
+    #[test]
+    fn unsaved_relations_are_not_closed_cleanly() {
+        let name = Identifier::new(format!("unsaved{}", std::process::id()));
+        let mut relation = Relation::new(
+            name.clone(),
+            vec![("id", Type::from(0u8))],
+            4,
+            PrimaryKeyDefinition::new(vec![0]),
+        );
+        for i in 0..10u8 {
+            relation.insert(Tuple::new(vec![i.into()])).unwrap();
+        }
+        // the blocks have nowhere to be written
+        let directory = relation.storage_directory().unwrap();
+        std::fs::remove_dir_all(&directory).unwrap();
+        assert!(relation.close().is_err());
+        assert!(!Relation::closed_cleanly(&name));
+        let _ = std::fs::remove_dir_all(directory);
+    }
+
+    #[test]
+    fn key_seeds_are_checked_on_open() {
+        let name = Identifier::new(format!("seeded{}", std::process::id()));
+        let open = |primary_key: PrimaryKeyDefinition| {
+            Relation::open(name.clone(), vec![("id", Type::from(0u8))], 4, primary_key)
+        };
+        let mut relation = open(PrimaryKeyDefinition::new(vec![0])).unwrap();
+        relation.insert(Tuple::new(vec![3u8.into()])).unwrap();
+        relation.close().unwrap();
+
+        let error = open(PrimaryKeyDefinition::with_seeds(vec![0], [1, 2, 3, 4]))
+            .err()
+            .unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        // the storage wasn't touched by the failed open
+        assert!(Relation::closed_cleanly(&name));
+
+        let reopened = open(PrimaryKeyDefinition::new(vec![0])).unwrap();
+        std::mem::drop(reopened);
+        std::fs::remove_dir_all(storage_directory_of(&name)).unwrap();
+    }
+
+    #[test]
+    fn flush() {
+        let name = Identifier::new(format!("flushed{}", std::process::id()));
+        let mut relation = Relation::new(
+            name.clone(),
+            vec![("id", Type::from(0u8))],
+            4,
+            PrimaryKeyDefinition::new(vec![0]),
+        );
+        for i in 0..10u8 {
+            relation.insert(Tuple::new(vec![i.into()])).unwrap();
+        }
+        let directory = relation.backing_table.directory().unwrap();
+        let stored = || -> usize {
+            std::fs::read_dir(&directory)
+                .unwrap()
+                .map(|file| file.unwrap().path())
+                .filter(|path| !path.ends_with(KEY_SEEDS_FILE))
+                .map(|path| std::fs::read_to_string(path).unwrap())
+                .map(|contents| {
+                    contents
+                        .lines()
+                        .filter(|line| !line.starts_with('@'))
+                        .count()
+                })
+                .sum()
+        };
+        assert!(relation.flush() > 0);
+        assert_eq!(stored(), 10);
+        assert_eq!(relation.flush(), 0);
+        assert_eq!(relation.len(), 10);
+
+        let mut volatile = Relation::new_volatile(
+            Identifier::new("volatile"),
+            vec![("id", Type::from(0u8))],
+            4,
+            PrimaryKeyDefinition::new(vec![0]),
+        );
+        volatile.insert(Tuple::new(vec![0u8.into()])).unwrap();
+        assert_eq!(volatile.flush(), 0);
+
+        std::mem::drop(relation);
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn compact() {
+        let name = Identifier::new(format!("compacted{}", std::process::id()));
+        let mut relation = Relation::new(
+            name.clone(),
+            vec![("id", Type::from(0u8))],
+            4,
+            PrimaryKeyDefinition::new(vec![0]),
+        );
+        for i in 0..10u8 {
+            relation.insert(Tuple::new(vec![i.into()])).unwrap();
+        }
+        let directory = relation.backing_table.directory().unwrap();
+        let logs = || -> usize {
+            std::fs::read_dir(&directory)
+                .unwrap()
+                .filter(|file| file.as_ref().unwrap().path().extension() == Some("log".as_ref()))
+                .count()
+        };
+        relation.flush();
+        let logged = logs();
+        assert!(logged > 0);
+        assert_eq!(relation.compact(), logged);
+        assert_eq!(logs(), 0);
+        assert_eq!(relation.compact(), 0);
+        assert_eq!(relation.len(), 10);
+
+        relation.insert(Tuple::new(vec![10u8.into()])).unwrap();
+        assert_eq!(relation.flush(), 1);
+        assert_eq!(logs(), 1);
+
+        std::mem::drop(relation);
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn auto_analyze() {
+        let mut relation = Relation::new_volatile(
+            Identifier::new("analyzed"),
+            vec![("id", Type::from(0u8)), ("group", Type::from(0u8))],
+            4,
+            PrimaryKeyDefinition::new(vec![0]),
+        );
+        for i in 0..10u8 {
+            relation
+                .insert(Tuple::new(vec![i.into(), (i % 2).into()]))
+                .unwrap();
+        }
+        assert!(relation.statistics().is_none());
+        let statistics = relation.analyze();
+        assert_eq!(statistics.tuples(), 10);
+        assert_eq!(statistics.field(1).unwrap().distinct(), 2);
+        let group = relation.column_stats("group").unwrap();
+        assert_eq!(
+            (group.min(), group.max()),
+            (Some(&0u8.into()), Some(&1u8.into()))
+        );
+        assert_eq!(group.nulls(), 0);
+        assert!(relation.column_stats("missing").is_none());
+
+        relation.set_auto_analyze(Some(0.5));
+        for i in 10..14u8 {
+            relation
+                .insert(Tuple::new(vec![i.into(), 2u8.into()]))
+                .unwrap();
+        }
+        assert_eq!(relation.statistics().unwrap().modified(), 4);
+        relation
+            .insert(Tuple::new(vec![14u8.into(), 3u8.into()]))
+            .unwrap();
+        let statistics = relation.statistics().unwrap();
+        assert_eq!((statistics.tuples(), statistics.modified()), (15, 0));
+        assert_eq!(statistics.field(1).unwrap().distinct(), 4);
+    }
+
+    #[test]
+    fn case_policy() {
+        let mut relation = Relation::new_volatile(
+            Identifier::new("Users"),
+            vec![("Name", Type::from(""))],
+            4,
+            PrimaryKeyDefinition::new(vec![0]),
+        );
+        assert_eq!(relation.get_field_index("Name"), Some(0));
+        assert_eq!(relation.get_field_index("name"), None);
+        relation.set_case_policy(CasePolicy::LowercaseFold);
+        assert_eq!(relation.get_field_index("name"), Some(0));
+        assert_eq!(
+            relation.get_field_index(Identifier::from_iter(&["users", "NAME"])),
+            Some(0)
+        );
+        assert_eq!(
+            relation.get_field_index(Identifier::from_iter(&["orders", "name"])),
+            None
+        );
+    }
+
+    #[test]
+    fn text_format() {
+        let format = TextFormat::new(',', '\'', "\\N");
+        let mut relation = Relation::new_with_text_format(
+            Identifier::new("formatted"),
+            vec![("id", Type::from(0u8)), ("text", Type::from(""))],
+            4,
+            PrimaryKeyDefinition::new(vec![0]),
+            format.clone(),
+        )
+        .into_temp();
+        assert_eq!(relation.text_format(), &format);
+        let definition = relation.get_relation_definition();
+        assert_eq!(definition.text_format(), &format);
+        assert_eq!((definition >> 1).text_format(), &format);
+
+        let tuple = Tuple::new(vec![1u8.into(), "a|b,c".into()]);
+        relation.insert(tuple.clone()).unwrap();
+        assert_eq!(relation.tuples().collect::<Vec<_>>(), vec![tuple]);
+    }
+
+    #[test]
+    fn quotas() {
+        let mut relation = Relation::new_volatile(
+            Identifier::new("limited"),
+            vec![("id", Type::from(0u8)), ("text", Type::from(""))],
+            4,
+            PrimaryKeyDefinition::new(vec![0]),
+        );
+        relation.set_quota(Quota::unlimited().with_max_tuples(2));
+        relation
+            .insert(Tuple::new(vec![1u8.into(), "a".into()]))
+            .unwrap();
+        relation
+            .insert(Tuple::new(vec![2u8.into(), "b".into()]))
+            .unwrap();
+        match relation.insert(Tuple::new(vec![3u8.into(), "c".into()])) {
+            Err(TupleInsertionError::QuotaExceeded { usage, .. }) => assert_eq!(usage.tuples, 3),
+            other => panic!("expected the quota to be exceeded, got {:?}", other),
+        }
+        // replacing a tuple doesn't grow the relation
+        relation
+            .insert(Tuple::new(vec![2u8.into(), "d".into()]))
+            .unwrap();
+
+        let usage = relation.usage();
+        assert_eq!(usage.tuples, 2);
+        relation.set_quota(Quota::unlimited().with_max_bytes(usage.bytes));
+        assert_eq!(relation.usage(), usage);
+        assert!(relation
+            .insert(Tuple::new(vec![2u8.into(), "longer".into()]))
+            .is_err());
+        relation
+            .insert(Tuple::new(vec![2u8.into(), "".into()]))
+            .unwrap();
+        assert!(relation.usage().bytes < usage.bytes);
+        assert_eq!(relation.usage().bytes, relation.stored_bytes());
+    }
+
+    #[test]
+    fn bucket_size_in_bytes() {
+        let mut relation = Relation::new(
+            Identifier::new("narrow"),
+            vec![("id", Type::from(0u32))],
+            2,
+            PrimaryKeyDefinition::new(vec![0]),
+        )
+        .into_temp();
+        for id in 0..64u32 {
+            relation.insert(Tuple::new(vec![id.into()])).unwrap();
+        }
+        let blocks = relation.block_count();
+
+        relation.set_bucket_size(BucketSize::Bytes(4096));
+        assert!(relation.bucket_size() > 64);
+        assert!(relation.block_count() < blocks);
+        for id in 64..128u32 {
+            relation.insert(Tuple::new(vec![id.into()])).unwrap();
+        }
+        assert_eq!(relation.len(), 128);
+        for id in 0..128u32 {
+            assert!(relation.find_by_key(&[id.into()]).is_some());
+        }
+
+        relation.set_bucket_size(4);
+        assert_eq!(relation.bucket_size(), 4);
+        assert_eq!(relation.bucket_size_policy(), BucketSize::Tuples(4));
+        assert_eq!(relation.tuples().count(), 128);
+    }
+
+    #[test]
+    fn copy_table() {
+        use crate::constraint::Check;
+
+        let mut relation = Relation::new(
+            Identifier::new("original"),
+            vec![("id", Type::from(0u32)), ("name", Type::from(""))],
+            4,
+            PrimaryKeyDefinition::new(vec![0]),
+        )
+        .into_temp();
+        relation.add_constraint(Check::new("named", |tuple: &Tuple| tuple[1] != ""));
+        for id in 0..32u32 {
+            let name = format!("name {}", id);
+            relation
+                .insert(Tuple::new(vec![id.into(), name.into()]))
+                .unwrap();
+        }
+
+        // the copy stores its tuples as it's created, so it's named within the temporary
+        // namespace from the start
+        let name = temp::unique_identifier("copy");
+        let mut copy = relation.copy_table(name.clone());
+        assert_eq!(copy.len(), 32);
+        assert_eq!(
+            copy.constraints().names().collect::<Vec<_>>(),
+            vec!["named"]
+        );
+        for id in 0..32u32 {
+            assert_eq!(
+                copy.find_by_key(&[id.into()]),
+                relation.find_by_key(&[id.into()])
+            );
+        }
+        assert!(copy
+            .insert(Tuple::new(vec![32u32.into(), "".into()]))
+            .is_err());
+        copy.insert(Tuple::new(vec![32u32.into(), "new".into()]))
+            .unwrap();
+        assert_eq!(relation.len(), 32);
+
+        std::mem::drop(copy);
+        std::fs::remove_dir_all(temp::directory_of(name.parent().unwrap())).unwrap();
+    }
+
+    #[test]
+    fn mutation_hooks() {
+        use std::sync::{Arc, Mutex};
+
+        let mut relation = Relation::new_volatile(
+            Identifier::new("cached"),
+            vec![("id", Type::from(0u8)), ("text", Type::from(""))],
+            4,
+            PrimaryKeyDefinition::new(vec![0]),
+        );
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let inserts = events.clone();
+        relation.on_insert(move |tuple, key| {
+            assert_eq!(key.len(), 1);
+            inserts.lock().unwrap().push(("insert", tuple.clone()));
+        });
+        let deletes = events.clone();
+        relation.on_delete(move |tuple, _| deletes.lock().unwrap().push(("delete", tuple.clone())));
+
+        let first = Tuple::new(vec![1u8.into(), "a".into()]);
+        let second = Tuple::new(vec![1u8.into(), "b".into()]);
+        relation.insert(first.clone()).unwrap();
+        relation.insert(second.clone()).unwrap();
+        assert!(relation.insert(Tuple::new(vec![2u8.into()])).is_err());
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![
+                ("insert", first.clone()),
+                ("delete", first),
+                ("insert", second)
+            ]
+        );
+    }
+
+    #[test]
+    fn failed_batches_are_undone() {
+        use std::sync::{Arc, Mutex};
+
+        use crate::relations::tuple_storage::LockError;
+
+        let mut relation = Relation::new(
+            temp::unique_identifier("undone"),
+            vec![
+                ("id", Type::from(0u8)),
+                ("contents", Type::Text(Text::Blob(vec![]))),
+            ],
+            2,
+            PrimaryKeyDefinition::new(vec![0]),
+        )
+        .into_temp();
+        let blob = |byte: u8| Type::Text(Text::Blob(vec![byte; 2 * INLINE_BLOB_BYTES]));
+        for id in 0..3u8 {
+            relation
+                .insert(Tuple::new(vec![id.into(), Text::Blob(vec![id]).into()]))
+                .unwrap();
+        }
+        let events = Arc::new(Mutex::new(0));
+        let inserts = events.clone();
+        relation.on_insert(move |_, _| *inserts.lock().unwrap() += 1);
+
+        let batch: Vec<(BigUint, Tuple)> = [(1u8, 1u8), (5, 2), (6, 3), (1, 4), (7, 5)]
+            .iter()
+            .map(|&(id, byte)| {
+                let tuple = Tuple::new(vec![id.into(), blob(byte)]);
+                (relation.backing_table.hash_tuple(&tuple), tuple)
+            })
+            .collect();
+        let mut inserted = 0;
+        let result = relation.store_hashed_with(batch, |storage, tuple, hash| {
+            inserted += 1;
+            if inserted == 5 {
+                return Err(TupleInsertionError::Lock(LockError::Timeout));
+            }
+            storage.insert_hashed(tuple, hash)
+        });
+        assert!(matches!(
+            result,
+            Err((4, TupleInsertionError::Lock(LockError::Timeout)))
+        ));
+        assert_eq!(relation.len(), 3);
+        for id in 0..3u8 {
+            assert_eq!(
+                relation.find_by_key(&[id.into()]),
+                Some(Tuple::new(vec![id.into(), Text::Blob(vec![id]).into()]))
+            );
+        }
+        assert_eq!(*events.lock().unwrap(), 0);
+        let blobs = relation.blob_directory().unwrap();
+        assert_eq!(std::fs::read_dir(blobs).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn find_by_key() {
+        let mut relation = Relation::new_volatile(
+            Identifier::new("keyed"),
+            vec![("id", Type::from(0u16)), ("text", Type::from(""))],
+            4,
+            PrimaryKeyDefinition::new(vec![0]),
+        );
+        for id in 0..20u16 {
+            relation
+                .insert(Tuple::new(vec![id.into(), id.to_string().into()]))
+                .unwrap();
+        }
+        assert!(relation.block_count() > 1);
+        assert_eq!(
+            relation.find_by_key(&[Type::from(13u16)]),
+            Some(Tuple::new(vec![13u16.into(), "13".into()]))
+        );
+        assert_eq!(relation.find_by_key(&[Type::from(20u16)]), None);
+    }
+
+    #[test]
+    fn key_range() {
+        let mut relation = Relation::new_volatile(
+            Identifier::new("events"),
+            vec![
+                ("tenant", Type::from(0u8)),
+                ("time", Type::from(0u32)),
+                ("text", Type::from("")),
+            ],
+            4,
+            PrimaryKeyDefinition::new(vec![0, 1]),
+        );
+        let event = |tenant: u8, time: u32| {
+            Tuple::new(vec![
+                tenant.into(),
+                time.into(),
+                format!("{}@{}", tenant, time).into(),
+            ])
+        };
+        for tenant in (0..4u8).rev() {
+            for time in 0..5u32 {
+                relation.insert(event(tenant, time)).unwrap();
+            }
+        }
+
+        let tenant = [Type::from(2u64)];
+        let tuples: Vec<Tuple> = relation
+            .key_range(Bound::Included(&tenant), Bound::Included(&tenant))
+            .collect();
+        assert_eq!(
+            tuples,
+            (0..5).map(|time| event(2, time)).collect::<Vec<_>>()
+        );
+
+        let lower = [Type::from(1u64), Type::from(3u64)];
+        let upper = [Type::from(2u64), Type::from(1u64)];
+        assert_eq!(
+            relation
+                .key_range(Bound::Excluded(&lower), Bound::Excluded(&upper))
+                .collect::<Vec<_>>(),
+            vec![event(1, 4), event(2, 0)]
+        );
+
+        // tuples inserted after the index is built are found too
+        relation.insert(event(2, 10)).unwrap();
+        assert_eq!(
+            relation.key_range_len(Bound::Included(&tenant), Bound::Unbounded),
+            11
+        );
+    }
+
+    #[test]
+    fn partial_indexes_follow_inserts() {
+        struct NotDeleted;
+
+        impl IndexPredicate for NotDeleted {
+            fn matches(&self, tuple: &Tuple) -> bool {
+                tuple[2] == false
+            }
+
+            fn as_any(&self) -> &dyn std::any::Any {
+                self
+            }
+        }
+
+        let mut relation = Relation::new_volatile(
+            Identifier::new("accounts"),
+            vec![
+                ("id", Type::from(0u32)),
+                ("owner", Type::from("")),
+                ("deleted", Type::from(false)),
+            ],
+            4,
+            PrimaryKeyDefinition::new(vec![0]),
+        );
+        let account = |id: u32, owner: &str, deleted: bool| {
+            Tuple::new(vec![id.into(), owner.into(), deleted.into()])
+        };
+        relation.insert(account(0, "bo", false)).unwrap();
+        relation.insert(account(1, "al", true)).unwrap();
+        // existing tuples are indexed when the index is created
+        assert!(relation.create_partial_index("live_owners", vec![1], NotDeleted));
+        assert!(!relation.create_index("live_owners", vec![1]));
+        relation.insert(account(2, "al", false)).unwrap();
+        relation.insert(account(3, "cy", false)).unwrap();
+        // replacing a tuple moves it out of the index when it no longer belongs in it
+        relation.insert(account(3, "cy", true)).unwrap();
+
+        let owners = |relation: &Relation| -> Vec<Tuple> {
+            relation
+                .index_range("live_owners", Bound::Unbounded, Bound::Unbounded)
+                .collect()
+        };
+        assert_eq!(
+            owners(&relation),
+            vec![account(2, "al", false), account(0, "bo", false)]
+        );
+        let al = [Type::from("al")];
+        assert_eq!(
+            relation.index_range_len("live_owners", Bound::Included(&al), Bound::Included(&al)),
+            1
+        );
+
+        let version = relation.schema_version();
+        assert!(relation.drop_index("live_owners"));
+        assert!(!relation.drop_index("live_owners"));
+        assert!(relation.schema_version() > version);
+        assert!(relation.indexes().is_empty());
+    }
+
+    #[test]
+    fn covering_indexes() {
+        let mut relation = Relation::new_volatile(
+            Identifier::new("balances"),
+            vec![
+                ("id", Type::from(0u32)),
+                ("owner", Type::from("")),
+                ("balance", Type::from(0u64)),
+                ("note", Type::from("")),
+            ],
+            4,
+            PrimaryKeyDefinition::new(vec![0]),
+        );
+        let balance = |id: u32, owner: &str, balance: u64| {
+            Tuple::new(vec![id.into(), owner.into(), balance.into(), "".into()])
+        };
+        relation.insert(balance(0, "bo", 10)).unwrap();
+        relation.insert(balance(1, "al", 20)).unwrap();
+        assert!(relation.create_covering_index("owner_balances", vec![1], vec![2]));
+        relation.insert(balance(2, "al", 5)).unwrap();
+        relation.insert(balance(1, "al", 25)).unwrap();
+
+        let index = relation.index("owner_balances").unwrap();
+        assert_eq!(index.covered(), &[0, 1, 2]);
+        assert!(!index.covers(&[3]));
+        let al = [Type::from("al")];
+        let values: Vec<Tuple> = relation
+            .index_only_range("owner_balances", Bound::Included(&al), Bound::Included(&al))
+            .collect();
+        assert_eq!(
+            values,
+            vec![
+                Tuple::new(vec![1u32.into(), "al".into(), 25u64.into()]),
+                Tuple::new(vec![2u32.into(), "al".into(), 5u64.into()]),
+            ]
+        );
+    }
+
+    #[test]
+    fn relations_can_move_between_threads() {
+        fn assert_send<T: Send>() {}
+        assert_send::<Relation>();
+        assert_send::<TupleStorage>();
+
+        let mut relation = Relation::new_volatile(
+            Identifier::new("moved"),
+            vec![("id", Type::from(0u8))],
+            4,
+            PrimaryKeyDefinition::new(vec![0]),
+        );
+        let relation = std::thread::spawn(move || {
+            relation.insert(Tuple::new(vec![1u8.into()])).unwrap();
+            relation
+        })
+        .join()
+        .unwrap();
+        assert_eq!(relation.len(), 1);
+    }
+
+    #[test]
+    fn relations_can_be_shared_between_threads() {
+        fn assert_sync<T: Sync>() {}
+        assert_sync::<Relation>();
+
+        let mut relation = Relation::new_volatile(
+            Identifier::new("shared"),
+            vec![("id", Type::from(0u8))],
+            4,
+            PrimaryKeyDefinition::new(vec![0]),
+        );
+        for i in 0..64u8 {
+            relation.insert(Tuple::new(vec![i.into()])).unwrap();
+        }
+        std::thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    assert_eq!(relation.tuples().count(), 64);
+                    for i in 0..64u8 {
+                        assert!(relation.find_by_key(&[i.into()]).is_some());
+                    }
+                });
+            }
+        });
+    }
+
     #[test]
     fn add_one() {
         let mut relation = Relation::new(
@@ -553,6 +2563,32 @@ mod tests {
         assert_eq!(calc_sum, sum);
     }
 
+    #[test]
+    fn enumeration_fields() {
+        use rad_db_types::deserialization::parse_using_types_with;
+        use rad_db_types::serialization::serialize_values_with;
+
+        let status = Enumeration::new(vec!["todo", "doing", "done"], 0).unwrap();
+        let definition = RelationDefinition::from_iter(vec![
+            ("id".to_string(), Type::from(0u32)),
+            ("status".to_string(), Type::from(status.clone())),
+        ])
+        .with_nullable(1, true);
+        assert_eq!(definition.labels(0), None);
+        assert_eq!(
+            definition.labels(1),
+            Some(&["todo".to_string(), "doing".to_string(), "done".to_string()][..])
+        );
+
+        let values = vec![Type::from(7u32), status.with_label("done").unwrap().into()];
+        let written = serialize_values_with(values, definition.text_format());
+        assert_eq!(written, "7|2");
+        let types: Vec<Type> = (&definition).into_iter().collect();
+        let read = parse_using_types_with(&written, types.clone(), definition.text_format());
+        assert_eq!(read.unwrap()[1], "done");
+        assert!(parse_using_types_with("7|3", types, definition.text_format()).is_err());
+    }
+
     /// Splits one bucket many times before splitting the other
     #[test]
     fn late_split() {
@@ -0,0 +1,273 @@
+//! Statistics about the contents of a relation, which the optimizer uses to estimate how many
+//! tuples a query produces
+
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::ops::Bound;
+use std::time::SystemTime;
+
+use rad_db_types::Value;
+
+use crate::tuple::Tuple;
+
+/// The most buckets a histogram is split into
+const HISTOGRAM_BUCKETS: usize = 32;
+
+/// How the values of a field are distributed. The bounds are the values at evenly spaced ranks
+/// of the sorted values, from the smallest to the largest, so each bucket between two bounds
+/// holds about as many values as any other.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Histogram {
+    bounds: Vec<Value>,
+}
+
+impl Histogram {
+    /// Creates the histogram of the values, or `None` if there are none or some can't be ordered
+    /// against each other
+    fn new(mut values: Vec<Value>) -> Option<Self> {
+        let mut ordered = true;
+        values.sort_by(|left, right| {
+            left.compare(right).unwrap_or_else(|| {
+                ordered = false;
+                Ordering::Equal
+            })
+        });
+        if values.is_empty() || !ordered {
+            return None;
+        }
+        let last = values.len() - 1;
+        let buckets = HISTOGRAM_BUCKETS.min(last).max(1);
+        let bounds = (0..=buckets)
+            .map(|i| values[i * last / buckets].clone())
+            .collect();
+        Some(Histogram { bounds })
+    }
+
+    pub fn bounds(&self) -> &[Value] {
+        &self.bounds
+    }
+
+    /// Estimates the fraction of the values within the bounds, or `None` if a bound can't be
+    /// ordered against the values
+    pub fn fraction_within(&self, lower: Bound<&Value>, upper: Bound<&Value>) -> Option<f64> {
+        let lower = match lower {
+            Bound::Included(value) => self.fraction_below(value, false)?,
+            Bound::Excluded(value) => self.fraction_below(value, true)?,
+            Bound::Unbounded => 0.0,
+        };
+        let upper = match upper {
+            Bound::Included(value) => self.fraction_below(value, true)?,
+            Bound::Excluded(value) => self.fraction_below(value, false)?,
+            Bound::Unbounded => 1.0,
+        };
+        Some((upper - lower).max(0.0))
+    }
+
+    /// The fraction of the values less than the value, or also equal to it if `inclusive`. A
+    /// value within a bucket is assumed to be in the middle of it.
+    fn fraction_below(&self, value: &Value, inclusive: bool) -> Option<f64> {
+        let mut below = 0;
+        for bound in &self.bounds {
+            match bound.compare(value)? {
+                Ordering::Less => below += 1,
+                Ordering::Equal if inclusive => below += 1,
+                _ => {}
+            }
+        }
+        let buckets = (self.bounds.len() - 1) as f64;
+        Some(((below as f64 - 0.5) / buckets).max(0.0).min(1.0))
+    }
+}
+
+/// Statistics about a field of a relation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldStatistics {
+    name: String,
+    distinct: usize,
+    nulls: usize,
+    histogram: Option<Histogram>,
+}
+
+impl FieldStatistics {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The number of distinct values of the field, not counting `NULL`
+    pub fn distinct(&self) -> usize {
+        self.distinct
+    }
+
+    /// The number of tuples where the field is `NULL`
+    pub fn nulls(&self) -> usize {
+        self.nulls
+    }
+
+    /// The smallest value of the field other than `NULL`, if its values can be ordered
+    pub fn min(&self) -> Option<&Value> {
+        self.histogram.as_ref()?.bounds.first()
+    }
+
+    /// The largest value of the field other than `NULL`, if its values can be ordered
+    pub fn max(&self) -> Option<&Value> {
+        self.histogram.as_ref()?.bounds.last()
+    }
+
+    /// How the values of the field other than `NULL` are distributed, if they can be ordered
+    pub fn histogram(&self) -> Option<&Histogram> {
+        self.histogram.as_ref()
+    }
+}
+
+/// Statistics about a relation, gathered when the relation is
+/// [analyzed](crate::relations::Relation::analyze)
+#[derive(Debug, Clone)]
+pub struct RelationStatistics {
+    tuples: usize,
+    fields: Vec<FieldStatistics>,
+    analyzed: SystemTime,
+    /// The tuples inserted or replaced since the statistics were gathered
+    modified: usize,
+}
+
+impl RelationStatistics {
+    /// Gathers the statistics of tuples with the fields
+    pub(crate) fn gather<'f, F, I>(fields: F, tuples: I) -> Self
+    where
+        F: IntoIterator<Item = &'f str>,
+        I: IntoIterator<Item = Tuple>,
+    {
+        let names: Vec<&str> = fields.into_iter().collect();
+        let mut distinct: Vec<HashSet<String>> = names.iter().map(|_| HashSet::new()).collect();
+        let mut values: Vec<Vec<Value>> = names.iter().map(|_| vec![]).collect();
+        let mut nulls = vec![0; names.len()];
+        let mut count = 0;
+        for tuple in tuples {
+            count += 1;
+            for (i, value) in tuple.into_iter().enumerate().take(names.len()) {
+                match value {
+                    Value::Optional(None) => nulls[i] += 1,
+                    // the values of nullable fields are described without their wrapping
+                    Value::Optional(Some(value)) => {
+                        distinct[i].insert(value.to_string());
+                        values[i].push(*value);
+                    }
+                    // floating point values can't be hashed, so values are compared as text
+                    value => {
+                        distinct[i].insert(value.to_string());
+                        values[i].push(value);
+                    }
+                }
+            }
+        }
+        let fields = names
+            .into_iter()
+            .zip(distinct)
+            .zip(nulls)
+            .zip(values)
+            .map(|(((name, distinct), nulls), values)| FieldStatistics {
+                name: name.to_string(),
+                distinct: distinct.len(),
+                nulls,
+                histogram: Histogram::new(values),
+            })
+            .collect();
+        RelationStatistics {
+            tuples: count,
+            fields,
+            analyzed: SystemTime::now(),
+            modified: 0,
+        }
+    }
+
+    /// The number of tuples in the relation when it was analyzed
+    pub fn tuples(&self) -> usize {
+        self.tuples
+    }
+
+    pub fn fields(&self) -> &[FieldStatistics] {
+        &self.fields
+    }
+
+    /// Gets the statistics of the field at the index
+    pub fn field(&self, index: usize) -> Option<&FieldStatistics> {
+        self.fields.get(index)
+    }
+
+    /// When the relation was analyzed
+    pub fn analyzed(&self) -> SystemTime {
+        self.analyzed
+    }
+
+    /// The number of tuples inserted or replaced since the relation was analyzed
+    pub fn modified(&self) -> usize {
+        self.modified
+    }
+
+    /// The tuples modified since the relation was analyzed, as a fraction of the tuples the
+    /// relation had then
+    pub fn staleness(&self) -> f64 {
+        self.modified as f64 / self.tuples.max(1) as f64
+    }
+
+    pub(crate) fn record_modifications(&mut self, tuples: usize) {
+        self.modified += tuples;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gather() {
+        let tuples = (0..10u64).map(|i| {
+            let nullable = if i < 3 {
+                Value::Optional(None)
+            } else {
+                Value::Optional(Some(Box::new(Value::from(i))))
+            };
+            Tuple::new(vec![Value::from(i % 4), nullable])
+        });
+        let mut statistics = RelationStatistics::gather(vec!["group", "maybe"], tuples);
+        assert_eq!(statistics.tuples(), 10);
+        assert_eq!(statistics.field(0).unwrap().distinct(), 4);
+        assert_eq!(statistics.field(1).unwrap().distinct(), 7);
+        assert_eq!(statistics.field(1).unwrap().nulls(), 3);
+        assert_eq!(statistics.field(1).unwrap().min(), Some(&Value::from(3u64)));
+        assert_eq!(statistics.field(1).unwrap().max(), Some(&Value::from(9u64)));
+
+        statistics.record_modifications(5);
+        assert_eq!(statistics.staleness(), 0.5);
+    }
+
+    #[test]
+    fn histograms() {
+        let tuples = (0..1000u64).map(|i| Tuple::new(vec![Value::from(i), Value::from("same")]));
+        let statistics = RelationStatistics::gather(vec!["number", "text"], tuples);
+        let histogram = statistics.field(0).unwrap().histogram().unwrap();
+        assert_eq!(histogram.bounds().len(), HISTOGRAM_BUCKETS + 1);
+        let within = |lower: Bound<u64>, upper: Bound<u64>| {
+            let lower = lower.map(Value::from);
+            let upper = upper.map(Value::from);
+            histogram
+                .fraction_within(lower.as_ref(), upper.as_ref())
+                .unwrap()
+        };
+        assert!((within(Bound::Included(250), Bound::Excluded(750)) - 0.5).abs() < 0.05);
+        assert!(within(Bound::Unbounded, Bound::Excluded(100)) < 0.15);
+        assert_eq!(within(Bound::Excluded(999), Bound::Unbounded), 0.0);
+        assert_eq!(within(Bound::Unbounded, Bound::Unbounded), 1.0);
+        assert_eq!(
+            histogram.fraction_within(Bound::Included(&Value::from("a")), Bound::Unbounded),
+            None
+        );
+
+        let text = statistics.field(1).unwrap().histogram().unwrap();
+        let same = Value::from("same");
+        assert_eq!(
+            text.fraction_within(Bound::Included(&same), Bound::Included(&same)),
+            Some(1.0)
+        );
+    }
+}
@@ -1,9 +1,10 @@
-use std::collections::{HashMap, VecDeque};
+use std::collections::BTreeSet;
 use std::error::Error;
 use std::fmt::{Display, Formatter};
 use std::fs::File;
 use std::fs::OpenOptions;
 use std::hash::Hasher;
+use std::io;
 use std::io::Write;
 use std::io::{BufRead, BufReader, BufWriter};
 use std::iter::{FilterMap, Map};
@@ -13,18 +14,23 @@ use std::ptr::null_mut;
 use std::str::FromStr;
 use std::sync::atomic::{AtomicBool, AtomicIsize, AtomicUsize, Ordering};
 use std::sync::mpsc::{self, Sender, TryRecvError};
-use std::sync::{PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::sync::{
+    Arc, Mutex, OnceLock, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard, Weak,
+};
 use std::thread;
 use std::time::{Duration, Instant};
 use thread::JoinHandle;
 
 use memmap::{Mmap, MmapMut};
 
-use rad_db_types::deserialization::parse_using_types;
-use rad_db_types::serialization::serialize_values;
+use rad_db_types::deserialization::parse_using_types_with;
+use rad_db_types::serialization::serialize_values_to_with;
 use rad_db_types::Type;
 
 use crate::identifier::Identifier;
+use crate::memory::{tuple_size, MemoryBudget, Reservation};
+use crate::relations::tuple_storage::dictionary::{BlockDictionaries, DICTIONARY_MARKER};
+use crate::relations::tuple_storage::prefetch::ReadAhead;
 use crate::relations::RelationDefinition;
 use crate::tuple::Tuple;
 use num_bigint::BigUint;
@@ -35,23 +41,78 @@ use tokio::io::AsyncWrite;
 pub const ROLLING_AVERAGE_COUNT: usize = 100;
 /// The minimum amount of time in milliseconds the rolling average must be to keep the block loaded in memory
 pub const MIN_TIME_FOR_MAINTAIN_LOAD: u128 = 500;
+/// The most entries the log of a block holds before it's merged into the file of the block, unless
+/// the block holds more tuples than this
+pub const MAX_LOG_ENTRIES: usize = 64;
+
+/// Starts an entry of the log that stores a tuple
+const PUT: char = '+';
+/// Starts an entry of the log that removes a tuple
+const REMOVE: char = '-';
+/// Separates an entry of the log from the checksum that ends it
+const CHECKSUM: char = '~';
 
 pub struct Block {
     parent_table: Identifier,
     relationship_definition: RelationDefinition,
     block_num: usize,
-    block_contents: Option<BlockContents>,
-    len: usize,
-    usage: RwLock<()>,
-    reads: AtomicUsize,
+    /// The contents while they're loaded from the file. Reads of the contents share the lock,
+    /// while changing, loading or unloading them takes it alone.
+    contents: RwLock<Option<BlockContents>>,
+    len: AtomicUsize,
     no_backing_file: bool,
     access_info: RwLock<AccessInformation>,
-    load_block: AtomicBool,
+    /// Whether the contents were borrowed mutably since they were last written to the file
+    dirty: AtomicBool,
+    /// The memory taken up by the contents while they're loaded from the file
+    memory: Mutex<Option<Reservation<'static>>>,
+    /// The number of entries in the log of the block, as of when it was last loaded or saved
+    log_entries: AtomicUsize,
+}
+
+/// The blocks stored in files, which the global memory budget asks to unload their contents when
+/// a reservation would go past its limit
+fn tracked_blocks() -> &'static Mutex<Vec<Weak<Block>>> {
+    static TRACKED: OnceLock<Mutex<Vec<Weak<Block>>>> = OnceLock::new();
+    TRACKED.get_or_init(|| {
+        MemoryBudget::global().add_evictor(evict_clean_blocks);
+        Mutex::new(vec![])
+    })
+}
+
+/// Unloads the contents of tracked blocks that are clean and not in use until the bytes needed
+/// are freed, returning how many bytes were freed
+fn evict_clean_blocks(needed: usize) -> usize {
+    let blocks: Vec<Arc<Block>> = {
+        let mut tracked = tracked_blocks()
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        tracked.retain(|block| block.strong_count() > 0);
+        tracked.iter().filter_map(Weak::upgrade).collect()
+    };
+    let mut freed = 0;
+    for block in blocks {
+        if freed >= needed {
+            break;
+        }
+        freed += block.evict_if_clean();
+    }
+    freed
+}
+
+/// The name of the file of the block with the number, within the directory of its relation
+pub(super) fn file_name_of(block_num: usize) -> String {
+    format!("block_{}.txt", block_num)
+}
+
+/// The name of the log of changes to the block with the number, next to the file of the block
+pub(super) fn log_name_of(block_num: usize) -> String {
+    format!("block_{}.log", block_num)
 }
 
 impl Block {
     pub fn len(&self) -> usize {
-        self.len
+        self.len.load(Ordering::Acquire)
     }
 }
 
@@ -66,12 +127,6 @@ impl Display for ReadInUseError {
 
 impl Error for ReadInUseError {}
 
-impl From<PoisonError<RwLockReadGuard<'_, ()>>> for ReadInUseError {
-    fn from(_: PoisonError<RwLockReadGuard<'_, ()>>) -> Self {
-        ReadInUseError
-    }
-}
-
 #[derive(Debug)]
 pub struct WriteInUseError;
 
@@ -83,12 +138,6 @@ impl Display for WriteInUseError {
 
 impl Error for WriteInUseError {}
 
-impl From<PoisonError<RwLockWriteGuard<'_, ()>>> for WriteInUseError {
-    fn from(_: PoisonError<RwLockWriteGuard<'_, ()>>) -> Self {
-        WriteInUseError
-    }
-}
-
 impl Block {
     pub fn new(
         parent_table: Identifier,
@@ -99,13 +148,13 @@ impl Block {
             parent_table,
             relationship_definition,
             block_num,
-            block_contents: None,
-            len: 0,
-            usage: RwLock::new(()),
-            reads: Default::default(),
+            contents: Default::default(),
+            len: Default::default(),
             no_backing_file: false,
             access_info: Default::default(),
-            load_block: Default::default(),
+            dirty: Default::default(),
+            memory: Default::default(),
+            log_entries: Default::default(),
         };
         ret.initialize_file().unwrap();
         ret
@@ -117,28 +166,30 @@ impl Block {
         block_num: usize,
         relationship_definition: RelationDefinition,
     ) -> Self {
-        let mut ret = Block {
+        let contents = BlockContents {
+            relationship: relationship_definition.clone(),
+            file: None,
+            internal: vec![],
+            changed: BTreeSet::new(),
+            rewrite: false,
+        };
+        Block {
             parent_table,
             relationship_definition,
             block_num,
-            block_contents: None,
-            len: 0,
-            usage: RwLock::new(()),
-            reads: Default::default(),
+            contents: RwLock::new(Some(contents)),
+            len: Default::default(),
             no_backing_file: true,
             access_info: Default::default(),
-            load_block: Default::default(),
-        };
-        ret.block_contents = Some(BlockContents {
-            relationship: ret.relationship_definition.clone(),
-            file: None,
-            internal: vec![],
-        });
-        ret
+            dirty: Default::default(),
+            memory: Default::default(),
+            log_entries: Default::default(),
+        }
     }
 
     fn initialize_file(&self) -> std::io::Result<()> {
         let file_name = self.file_name();
+        ReadAhead::global().invalidate(&file_name);
 
         if file_name.exists() {
             return Ok(());
@@ -160,42 +211,41 @@ impl Block {
     }
 
     pub fn try_get_contents(&self) -> Result<InUse, ReadInUseError> {
-        let read_guard = self.usage.read()?;
-        self.reads.fetch_add(1, Ordering::Acquire);
         self.notify_access();
-        if !self.load_status() {
-            unsafe {
-                self.load();
+        loop {
+            let read = self.contents.read().map_err(|_| ReadInUseError)?;
+            if read.is_some() {
+                return Ok(InUse {
+                    parent: self,
+                    read: Some(read),
+                });
+            }
+            drop(read);
+            // the contents may have been loaded by another thread while waiting for the write
+            let mut write = self.contents.write().map_err(|_| ReadInUseError)?;
+            if write.is_none() {
+                *write = Some(self.load());
             }
         }
-        let ret = InUse {
-            parent: self,
-            read: read_guard,
-        };
-        Ok(ret)
     }
 
-    /// Attempts to get immutable access to the contents of the block
-    pub fn get_contents_mut(&mut self) -> InUseMut {
+    /// Gets mutable access to the contents of the block, waiting for every other use of them to
+    /// finish first
+    pub fn get_contents_mut(&self) -> InUseMut<'_> {
         self.try_get_contents_mut().unwrap()
     }
 
-    pub fn try_get_contents_mut(&mut self) -> Result<InUseMut, WriteInUseError> {
-        let write_copy = (self as *mut Self);
-        let write_guard = self.usage.write()?;
+    pub fn try_get_contents_mut(&self) -> Result<InUseMut<'_>, WriteInUseError> {
+        let mut write = self.contents.write().map_err(|_| WriteInUseError)?;
         self.notify_access();
-        if !self.load_status() {
-            unsafe {
-                self.load();
-            }
-        }
-        unsafe {
-            let ret = InUseMut {
-                parent: &mut *write_copy,
-                write: write_guard,
-            };
-            Ok(ret)
+        if write.is_none() {
+            *write = Some(self.load());
         }
+        self.dirty.store(true, Ordering::Release);
+        Ok(InUseMut {
+            parent: self,
+            write: Some(write),
+        })
     }
 
     fn file_name(&self) -> PathBuf {
@@ -203,13 +253,12 @@ impl Block {
         for name in &self.parent_table {
             ret.push(name);
         }
-        ret.push(format!("block_{}.txt", self.block_num));
+        ret.push(file_name_of(self.block_num));
         ret
     }
 
-    /// Determines if the contents of the block is loaded
-    fn load_status(&self) -> bool {
-        self.block_contents.is_some()
+    fn log_name(&self) -> PathBuf {
+        self.file_name().with_file_name(log_name_of(self.block_num))
     }
 
     fn notify_access(&self) {
@@ -218,23 +267,18 @@ impl Block {
     }
 
     fn notify_finish(&self) {
-        let mut access_info = self.access_info.read().unwrap();
-        if self.reads.load(Ordering::Acquire) == 0
-            && self.load_status()
-            && access_info.should_unload()
-        {
-            unsafe {
-                self.unload();
-            }
+        if !self.access_info.read().unwrap().should_unload() {
+            return;
+        }
+        // while the contents are still in use, the last use to finish unloads them instead
+        if let Ok(mut contents) = self.contents.try_write() {
+            self.unload(&mut contents);
         }
     }
 
-    unsafe fn load(&self) {
+    /// Reads the contents of the block from its file and log
+    fn load(&self) -> BlockContents {
         //println!("Loading Block {}", self.block_num);
-        while self.load_block.load(Ordering::Relaxed) {}
-        if self.no_backing_file {
-            return;
-        }
         let path = self.file_name();
         let file = OpenOptions::new()
             .write(true)
@@ -242,9 +286,19 @@ impl Block {
             .open(&path)
             .expect(&*format!("Could not open file {:?}", path));
 
-        let mut buf_reader = BufReader::new(&file);
+        let read_ahead = ReadAhead::global();
+        if let Some(directory) = path.parent() {
+            read_ahead.loaded(directory, self.block_num);
+        }
+        let prefetched = read_ahead.take(&path);
+        let mut buf_reader: Box<dyn BufRead> = match &prefetched {
+            Some(bytes) => Box::new(&bytes[..]),
+            None => Box::new(BufReader::new(&file)),
+        };
         let mut tuples = vec![];
-        let mut len = 0;
+        // the dictionaries come before every tuple
+        let mut dictionaries = BlockDictionaries::default();
+        let mut types: Vec<Type> = self.relationship_definition.into_iter().collect();
         loop {
             let mut str = String::new();
             match buf_reader.read_line(&mut str) {
@@ -254,91 +308,330 @@ impl Block {
                 Ok(0) => break,
                 Ok(_) => {
                     let str = str.trim_end();
+                    if let Some(dictionary) = str.strip_prefix(DICTIONARY_MARKER) {
+                        dictionaries
+                            .read_line(dictionary, &self.relationship_definition)
+                            .expect("Could not parse dictionary");
+                        types = dictionaries.encoded_types(&self.relationship_definition);
+                        continue;
+                    }
                     let mut split = str.splitn(2, ":");
                     let hash = split.next().unwrap();
                     let tuple_str = split.next().unwrap();
 
-                    let tuple = Tuple::new(
-                        parse_using_types(tuple_str, &self.relationship_definition)
-                            .expect("Could not parse type")
-                            .into_iter(),
-                    );
-                    len += 1;
+                    let values = parse_using_types_with(
+                        tuple_str,
+                        types.iter().cloned(),
+                        self.relationship_definition.text_format(),
+                    )
+                    .ok()
+                    .and_then(|values| dictionaries.decode(values, &self.relationship_definition))
+                    .expect("Could not parse type");
+                    let tuple = Tuple::new(values.into_iter());
                     tuples.push((BigUint::from_str(hash).unwrap(), tuple));
                 }
             }
         }
 
-        let contents = BlockContents {
+        drop(buf_reader);
+
+        let mut contents = BlockContents {
             relationship: self.relationship_definition.clone(),
             file: Some(file),
             internal: tuples,
+            changed: BTreeSet::new(),
+            rewrite: false,
         };
-        unsafe {
-            let mutable = self as *const Self as *mut Self;
-            (*mutable).block_contents = Some(contents);
-            (*mutable).len = len;
+        let log_entries = self.replay_log(&mut contents);
+        self.log_entries.store(log_entries, Ordering::Release);
+        self.len.store(contents.internal.len(), Ordering::Release);
+        let bytes = contents.all().map(tuple_size).sum();
+        // reserving may evict other blocks, so the memory of this one isn't locked until after
+        let reservation = MemoryBudget::global().reserve_anyway(bytes);
+        *self.memory.lock().unwrap_or_else(PoisonError::into_inner) = Some(reservation);
+        contents
+    }
+
+    /// Lets the global memory budget unload the contents of the block to make room for other
+    /// memory, while they're clean and not in use
+    pub fn track(block: &Arc<Block>) {
+        if block.no_backing_file {
+            return;
         }
+        tracked_blocks()
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(Arc::downgrade(block));
     }
 
-    unsafe fn unload(&self) {
+    /// Unloads the contents if they're loaded, haven't changed since they were saved and aren't
+    /// in use, returning the bytes of memory freed
+    fn evict_if_clean(&self) -> usize {
+        if self.no_backing_file || self.dirty.load(Ordering::Acquire) {
+            return 0;
+        }
+        let mut contents = match self.contents.try_write() {
+            Ok(contents) => contents,
+            Err(_) => return 0,
+        };
+        if contents.is_none() || self.dirty.load(Ordering::Acquire) {
+            return 0;
+        }
+        let reservation = match self.memory.try_lock() {
+            Ok(mut memory) => memory.take(),
+            Err(_) => return 0,
+        };
+        *contents = None;
+        reservation.map_or(0, |reservation| reservation.bytes())
+    }
+
+    /// Saves the contents, if they're loaded, and lets go of them. Contents that can't be saved
+    /// are let go of all the same, as nothing is left to report the error to.
+    fn unload(&self, contents: &mut Option<BlockContents>) {
         //println!("Flushing Block {}", self.block_num);
         if self.no_backing_file {
             return;
         }
+        if let Some(contents) = contents.take() {
+            if let Err(e) = self.save(&contents) {
+                log::error!(
+                    "Couldn't save block {} of {}: {}",
+                    self.block_num,
+                    self.parent_table,
+                    e
+                );
+            }
+        }
+        *self.memory.lock().unwrap_or_else(PoisonError::into_inner) = None;
+    }
+}
 
-        let unsafe_self = self as *const Self as *mut Self;
-        while self
-            .load_block
-            .compare_and_swap(false, true, Ordering::Relaxed)
-        {}
-        let replaced = std::mem::replace(&mut (*unsafe_self).block_contents, None);
-        if let Some(contents) = replaced {
-            let BlockContents {
-                file: _file,
-                internal,
-                ..
-            } = contents;
-            let file_name = self.file_name();
-            std::fs::remove_file(&file_name).unwrap();
-
-            let mut file = File::create(file_name).expect("Failed to recreate file");
-
-            let mut saved = 0;
-            let mut buf_writer = BufWriter::new(file);
-
-            for (hash, tuple) in internal {
-                writeln!(
-                    buf_writer,
-                    "{}:{}",
-                    hash,
-                    serialize_values(tuple.into_iter())
-                )
-                .unwrap();
-                saved += 1;
+impl Block {
+    /// Writes the contents to the file if they were changed since they were last written, keeping
+    /// them loaded, and returns whether they were written
+    pub fn flush(&self) -> bool {
+        self.try_flush().expect("Failed to flush block")
+    }
+
+    /// Writes the contents to the file like [flush](Block::flush), failing if they can't be
+    /// written. Contents that weren't written are still marked as changed.
+    pub fn try_flush(&self) -> io::Result<bool> {
+        if self.no_backing_file || !self.dirty.load(Ordering::Acquire) {
+            return Ok(false);
+        }
+        // waits for the contents to stop being used
+        let mut contents = self
+            .contents
+            .write()
+            .unwrap_or_else(PoisonError::into_inner);
+        match contents.as_mut() {
+            Some(contents) => {
+                self.save(contents)?;
+                contents.saved();
+                Ok(true)
             }
-            //(*unsafe_self).len = saved;
-            buf_writer.flush();
-            self.load_block.store(false, Ordering::Release);
-            /*
-            println!(
-                "Saved {} Tuples in {} seconds",
-                saved,
-                instant.elapsed().as_secs_f64()
+            None => Ok(false),
+        }
+    }
+
+    /// Merges the log of the block into its file, so that loading the block reads the file alone,
+    /// and returns whether there was a log to merge. A block that isn't loaded is loaded to be
+    /// merged, and unloaded again afterwards.
+    pub fn compact(&self) -> bool {
+        self.try_compact().expect("Failed to compact block")
+    }
+
+    /// Merges the log of the block into its file like [compact](Block::compact), failing if the
+    /// file can't be written
+    pub fn try_compact(&self) -> io::Result<bool> {
+        if self.no_backing_file || !self.log_name().exists() {
+            return Ok(false);
+        }
+        // nothing else can load, unload or save the contents while they're being written
+        let mut contents = self
+            .contents
+            .write()
+            .unwrap_or_else(PoisonError::into_inner);
+        let loaded = contents.is_some();
+        let written = contents.get_or_insert_with(|| self.load());
+        self.write_file(&written.internal)?;
+        written.saved();
+        if !loaded {
+            self.unload(&mut contents);
+        }
+        Ok(true)
+    }
+
+    /// Saves the changes made to the contents since they were last saved. Changes to single
+    /// tuples are appended to the log, while other changes, or a log that has grown longer than
+    /// the block, write the whole file again.
+    fn save(&self, contents: &BlockContents) -> io::Result<()> {
+        let entries = self.log_entries.load(Ordering::Acquire) + contents.changed.len();
+        if contents.rewrite || entries > contents.internal.len().max(MAX_LOG_ENTRIES) {
+            self.write_file(&contents.internal)?;
+        } else if !contents.changed.is_empty() {
+            self.append_log(contents)?;
+        }
+        self.dirty.store(false, Ordering::Release);
+        Ok(())
+    }
+
+    /// Appends an entry for every changed tuple to the log, storing the tuple if it's still in
+    /// the contents and removing it otherwise. Each entry ends with a checksum, so that an entry
+    /// that was only partly written can be told apart when the log is replayed.
+    fn append_log(&self, contents: &BlockContents) -> io::Result<()> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.log_name())?;
+        let mut buf_writer = BufWriter::new(file);
+        for hash in &contents.changed {
+            let mut entry = Vec::new();
+            match contents.get_tuple(hash.clone()) {
+                Some(tuple) => {
+                    write!(entry, "{}{}:", PUT, hash).unwrap();
+                    serialize_values_to_with(
+                        tuple.clone(),
+                        self.relationship_definition.text_format(),
+                        &mut entry,
+                    )
+                    .unwrap();
+                }
+                None => write!(entry, "{}{}", REMOVE, hash).unwrap(),
+            }
+            buf_writer.write_all(&entry)?;
+            writeln!(buf_writer, "{}{:016x}", CHECKSUM, seahash::hash(&entry))?;
+        }
+        buf_writer.flush()?;
+        self.log_entries
+            .fetch_add(contents.changed.len(), Ordering::AcqRel);
+        Ok(())
+    }
+
+    /// Applies the entries of the log to the contents read from the file, returning how many
+    /// entries there were. The log is cut off before the first entry that is torn or corrupt,
+    /// such as one the process stopped in the middle of writing, along with everything after it.
+    fn replay_log(&self, contents: &mut BlockContents) -> usize {
+        let file = match OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(self.log_name())
+        {
+            Ok(file) => file,
+            Err(_) => return 0,
+        };
+        let types: Vec<Type> = self.relationship_definition.into_iter().collect();
+        let mut reader = BufReader::new(&file);
+        let mut entries = 0;
+        let mut intact = 0;
+        let mut line = Vec::new();
+        loop {
+            line.clear();
+            let read = reader
+                .read_until(b'\n', &mut line)
+                .expect("Couldn't read block log");
+            if read == 0 {
+                break;
+            }
+            let applied = checked_entry(&line)
+                .and_then(|entry| self.apply_log_entry(entry, &types, contents));
+            if applied.is_none() {
+                log::warn!(
+                    "Dropping the torn end of the log of block {} of {}",
+                    self.block_num,
+                    self.parent_table
+                );
+                file.set_len(intact).expect("Couldn't truncate block log");
+                break;
+            }
+            intact += read as u64;
+            entries += 1;
+        }
+        contents.saved();
+        entries
+    }
+
+    /// Applies an entry of the log to the contents, or returns `None` if it can't be read
+    fn apply_log_entry(
+        &self,
+        entry: &str,
+        types: &[Type],
+        contents: &mut BlockContents,
+    ) -> Option<()> {
+        if let Some(entry) = entry.strip_prefix(PUT) {
+            let (hash, tuple_str) = entry.split_once(':')?;
+            let values = parse_using_types_with(
+                tuple_str,
+                types.iter().cloned(),
+                self.relationship_definition.text_format(),
             )
+            .ok()?;
+            contents.insert_tuple(BigUint::from_str(hash).ok()?, Tuple::new(values));
+        } else {
+            let hash = entry.strip_prefix(REMOVE)?;
+            contents.remove_tuple(BigUint::from_str(hash).ok()?);
+        }
+        Some(())
+    }
 
-             */
+    /// Replaces the file of the block with the tuples
+    fn write_file(&self, tuples: &[(BigUint, Tuple)]) -> io::Result<()> {
+        let file_name = self.file_name();
+        ReadAhead::global().invalidate(&file_name);
+        std::fs::remove_file(&file_name)?;
+
+        let file = File::create(&file_name)?;
+        let mut buf_writer = BufWriter::new(file);
+
+        let dictionaries = BlockDictionaries::build(
+            tuples.iter().map(|(_, tuple)| tuple),
+            &self.relationship_definition,
+        );
+        dictionaries.write_to(&mut buf_writer, &self.relationship_definition)?;
+        for (hash, tuple) in tuples {
+            write!(buf_writer, "{}:", hash)?;
+            serialize_values_to_with(
+                dictionaries.encode(tuple.clone()),
+                self.relationship_definition.text_format(),
+                &mut buf_writer,
+            )?;
+            writeln!(buf_writer)?;
         }
+        buf_writer.flush()?;
+        // the file holds every change in the log now
+        match std::fs::remove_file(self.log_name()) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+            _ => {}
+        }
+        self.log_entries.store(0, Ordering::Release);
+        self.dirty.store(false, Ordering::Release);
+        // a read ahead that started while the file was written may have read part of it
+        ReadAhead::global().invalidate(&file_name);
+        Ok(())
     }
 }
 
+/// Splits a line of the log into its entry, or returns `None` if the line doesn't end with a
+/// newline and the checksum of the entry
+fn checked_entry(line: &[u8]) -> Option<&str> {
+    let line = line.strip_suffix(b"\n")?;
+    // the checksum is the separator followed by 16 hex digits
+    let (entry, checksum) = line.split_at(line.len().checked_sub(17)?);
+    let checksum = std::str::from_utf8(checksum).ok()?.strip_prefix(CHECKSUM)?;
+    if u64::from_str_radix(checksum, 16).ok()? != seahash::hash(entry) {
+        return None;
+    }
+    std::str::from_utf8(entry).ok()
+}
+
 impl Drop for Block {
     fn drop(&mut self) {
-        if self.load_status() {
-            unsafe {
-                self.unload();
-            }
-        }
+        let mut contents = self
+            .contents
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take();
+        self.unload(&mut contents);
     }
 }
 
@@ -393,39 +686,39 @@ impl AccessInformation {
 
 pub struct InUse<'a> {
     parent: &'a Block,
-    read: RwLockReadGuard<'a, ()>,
+    /// Only let go of when the use is dropped, after which the block may be unloaded
+    read: Option<RwLockReadGuard<'a, Option<BlockContents>>>,
 }
 
 impl Deref for InUse<'_> {
     type Target = BlockContents;
 
     fn deref(&self) -> &Self::Target {
-        if self.parent.block_contents.is_none() {
-            unsafe {
-                self.parent.load();
-            }
-        }
-        self.parent.block_contents.as_ref().unwrap()
+        self.read
+            .as_ref()
+            .and_then(|read| read.as_ref())
+            .expect("contents are loaded while in use")
     }
 }
 
 impl Drop for InUse<'_> {
     fn drop(&mut self) {
-        self.parent.reads.fetch_sub(1, Ordering::Acquire);
+        self.read = None;
         self.parent.notify_finish();
     }
 }
 
 pub struct InUseMut<'a> {
-    parent: &'a mut Block,
-    write: RwLockWriteGuard<'a, ()>,
+    parent: &'a Block,
+    /// Only let go of when the use is dropped, after which the block may be unloaded
+    write: Option<RwLockWriteGuard<'a, Option<BlockContents>>>,
 }
 
 impl<'a> InUseMut<'a> {
     pub fn insert_tuple(&mut self, hash: BigUint, tuple: Tuple) -> Option<Tuple> {
         let ret = (**self).insert_tuple(hash, tuple);
         if ret.is_none() {
-            self.parent.len += 1;
+            self.parent.len.fetch_add(1, Ordering::AcqRel);
         }
         ret
     }
@@ -433,26 +726,27 @@ impl<'a> InUseMut<'a> {
     pub fn remove_tuple(&mut self, hash: BigUint) -> Option<Tuple> {
         let ret = (**self).remove_tuple(hash);
         if ret.is_some() {
-            self.parent.len -= 1;
+            self.parent.len.fetch_sub(1, Ordering::AcqRel);
         }
         ret
     }
 
     pub fn take_all(&mut self) -> Vec<Tuple> {
         let ret = (**self).take_all();
-        self.parent.len = 0;
+        self.parent.len.store(0, Ordering::Release);
         ret
     }
 
     pub fn take_all_with_key(&mut self) -> Vec<(BigUint, Tuple)> {
         let ret = (**self).take_all_with_key();
-        self.parent.len = 0;
+        self.parent.len.store(0, Ordering::Release);
         ret
     }
 }
 
 impl Drop for InUseMut<'_> {
     fn drop(&mut self) {
+        self.write = None;
         self.parent.notify_finish()
     }
 }
@@ -461,13 +755,19 @@ impl Deref for InUseMut<'_> {
     type Target = BlockContents;
 
     fn deref(&self) -> &Self::Target {
-        self.parent.block_contents.as_ref().unwrap()
+        self.write
+            .as_ref()
+            .and_then(|write| write.as_ref())
+            .expect("contents are loaded while in use")
     }
 }
 
 impl DerefMut for InUseMut<'_> {
     fn deref_mut(&mut self) -> &mut Self::Target {
-        self.parent.block_contents.as_mut().unwrap()
+        self.write
+            .as_mut()
+            .and_then(|write| write.as_mut())
+            .expect("contents are loaded while in use")
     }
 }
 
@@ -475,6 +775,11 @@ pub struct BlockContents {
     relationship: RelationDefinition,
     file: Option<File>,
     internal: Vec<(BigUint, Tuple)>,
+    /// The hashes of the tuples inserted or removed since the contents were last saved
+    changed: BTreeSet<BigUint>,
+    /// Whether the tuples were changed in a way the log can't record, such as through a mutable
+    /// reference, so the whole file has to be written again
+    rewrite: bool,
 }
 
 fn filter_map_helper<T>(input: &Option<T>) -> Option<&T> {
@@ -495,6 +800,7 @@ impl BlockContents {
     }
 
     pub fn get_tuple_mut(&mut self, hash: BigUint) -> Option<&mut Tuple> {
+        self.rewrite = true;
         for (h, tuple) in &mut self.internal {
             if *h == hash {
                 return Some(tuple);
@@ -504,8 +810,10 @@ impl BlockContents {
     }
 
     fn insert_tuple(&mut self, hash: BigUint, tuple: Tuple) -> Option<Tuple> {
-        if let Some(old) = self.get_tuple_mut(hash.clone()) {
-            Some(std::mem::replace(old, tuple))
+        self.changed.insert(hash.clone());
+        let pos = self.internal.iter().position(|(t_hash, _)| t_hash == &hash);
+        if let Some(pos) = pos {
+            Some(std::mem::replace(&mut self.internal[pos].1, tuple))
         } else {
             self.internal.push((hash, tuple));
             None
@@ -515,12 +823,19 @@ impl BlockContents {
     fn remove_tuple(&mut self, hash: BigUint) -> Option<Tuple> {
         let pos = self.internal.iter().position(|(t_hash, _)| t_hash == &hash);
         if let Some(pos) = pos {
+            self.changed.insert(hash);
             Some(self.internal.remove(pos).1)
         } else {
             None
         }
     }
 
+    /// Forgets the changes, once they've been saved
+    fn saved(&mut self) {
+        self.changed.clear();
+        self.rewrite = false;
+    }
+
     pub fn get_tuple_from_inner(input: &(BigUint, Tuple)) -> &Tuple {
         &input.1
     }
@@ -540,15 +855,18 @@ impl BlockContents {
     pub fn all_mut(
         &mut self,
     ) -> Map<IterMut<(BigUint, Tuple)>, fn(&mut (BigUint, Tuple)) -> &mut Tuple> {
+        self.rewrite = true;
         self.internal.iter_mut().map(Self::get_tuple_from_inner_mut)
     }
 
     fn take_all(&mut self) -> Vec<Tuple> {
+        self.rewrite = true;
         let replace = std::mem::replace(&mut self.internal, Vec::new());
         replace.into_iter().map(|(_, t)| t).collect()
     }
 
     fn take_all_with_key(&mut self) -> Vec<(BigUint, Tuple)> {
+        self.rewrite = true;
         std::mem::replace(&mut self.internal, Vec::new())
     }
 }
@@ -584,3 +902,122 @@ impl<'a> IntoIterator for &'a mut BlockContents {
         self.all_mut()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn log_replayed_on_load() {
+        let name = Identifier::new(format!("block_log{}", std::process::id()));
+        let definition = RelationDefinition::new(vec![(
+            Identifier::with_parent(&name, "id"),
+            Type::from(0u8),
+        )]);
+        let block = Block::new(name.clone(), 0, definition.clone());
+        {
+            let mut contents = block.get_contents_mut();
+            for i in 0..3u8 {
+                contents.insert_tuple(BigUint::from(i), Tuple::new(vec![i.into()]));
+            }
+        }
+        block.flush();
+        {
+            let mut contents = block.get_contents_mut();
+            contents.remove_tuple(BigUint::from(1u8));
+            contents.insert_tuple(BigUint::from(2u8), Tuple::new(vec![20u8.into()]));
+        }
+        block.flush();
+        assert!(block.log_name().exists());
+
+        let reopened = Block::new(name.clone(), 0, definition);
+        let tuples: Vec<Tuple> = reopened.get_contents().all().cloned().collect();
+        assert_eq!(
+            tuples,
+            vec![Tuple::new(vec![0u8.into()]), Tuple::new(vec![20u8.into()])]
+        );
+        assert!(reopened.compact());
+        assert!(!reopened.log_name().exists());
+
+        std::mem::drop(block);
+        std::mem::drop(reopened);
+        std::fs::remove_dir_all(PathBuf::from("DB_STORAGE").join(name.to_string())).unwrap();
+    }
+
+    #[test]
+    fn clean_blocks_are_evicted() {
+        let name = Identifier::new(format!("block_evicted{}", std::process::id()));
+        let definition = RelationDefinition::new(vec![(
+            Identifier::with_parent(&name, "id"),
+            Type::from(0u8),
+        )]);
+        let block = Arc::new(Block::new(name.clone(), 0, definition));
+        Block::track(&block);
+        {
+            let mut contents = block.get_contents_mut();
+            for i in 0..3u8 {
+                contents.insert_tuple(BigUint::from(i), Tuple::new(vec![i.into()]));
+            }
+        }
+        block.flush();
+        let is_loaded = || block.contents.read().unwrap().is_some();
+        // blocks that aren't used often unload as soon as they're done being used
+        let keep_loaded = || {
+            let mut contents = block.contents.write().unwrap();
+            if contents.is_none() {
+                *contents = Some(block.load());
+            }
+        };
+        keep_loaded();
+
+        // contents that are in use or haven't been saved are kept
+        let in_use = block.get_contents();
+        assert_eq!(block.evict_if_clean(), 0);
+        std::mem::drop(in_use);
+        block.dirty.store(true, Ordering::Release);
+        assert_eq!(block.evict_if_clean(), 0);
+        block.dirty.store(false, Ordering::Release);
+
+        keep_loaded();
+        assert!(evict_clean_blocks(usize::MAX) > 0);
+        assert!(!is_loaded());
+        assert_eq!(block.get_contents().all().count(), 3);
+
+        std::mem::drop(block);
+        std::fs::remove_dir_all(PathBuf::from("DB_STORAGE").join(name.to_string())).unwrap();
+    }
+
+    #[test]
+    fn torn_log_entries_are_dropped() {
+        let name = Identifier::new(format!("block_torn{}", std::process::id()));
+        let definition = RelationDefinition::new(vec![(
+            Identifier::with_parent(&name, "id"),
+            Type::from(0u8),
+        )]);
+        let block = Block::new(name.clone(), 0, definition.clone());
+        for i in 0..2u8 {
+            block
+                .get_contents_mut()
+                .insert_tuple(BigUint::from(i), Tuple::new(vec![i.into()]));
+            block.flush();
+        }
+        let log = block.log_name();
+        std::mem::drop(block);
+        let intact = std::fs::metadata(&log).unwrap().len();
+        // the process stopped while the next entry was being written
+        let mut file = OpenOptions::new().append(true).open(&log).unwrap();
+        write!(file, "{}2:", PUT).unwrap();
+        std::mem::drop(file);
+
+        let reopened = Block::new(name.clone(), 0, definition);
+        let tuples: Vec<Tuple> = reopened.get_contents().all().cloned().collect();
+        assert_eq!(
+            tuples,
+            vec![Tuple::new(vec![0u8.into()]), Tuple::new(vec![1u8.into()])]
+        );
+        assert_eq!(std::fs::metadata(&log).unwrap().len(), intact);
+
+        std::mem::drop(reopened);
+        std::fs::remove_dir_all(PathBuf::from("DB_STORAGE").join(name.to_string())).unwrap();
+    }
+}
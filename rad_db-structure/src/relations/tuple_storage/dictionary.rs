@@ -0,0 +1,267 @@
+//! Dictionary encoding of the string fields of a block whose values keep repeating, such as a
+//! status or a category.
+//!
+//! Every distinct value of an encoded field is written once, on a line at the start of the
+//! block's file, and each tuple only writes the position of its value within that dictionary.
+//! Tuples are decoded as the block is loaded, so nothing outside of the block ever sees the
+//! encoding. A block without any encoded fields is written the same way as before.
+
+use std::collections::HashSet;
+use std::io;
+use std::io::Write;
+
+use rad_db_types::deserialization::parse_using_types_with;
+use rad_db_types::serialization::serialize_values_to_with;
+use rad_db_types::{Kind, Numeric, Text, Type, Unsigned};
+
+use crate::relations::RelationDefinition;
+use crate::tuple::Tuple;
+
+/// Starts the lines of a block file that hold the dictionary of a field
+pub(super) const DICTIONARY_MARKER: char = '@';
+
+/// The most distinct values an encoded field can have, so that a position fits in a byte
+const MAX_DICTIONARY_LEN: usize = 256;
+
+/// The distinct values of a field, in the order they were first seen
+struct FieldDictionary {
+    field: usize,
+    values: Vec<Type>,
+}
+
+/// The dictionaries of the encoded fields of a block
+#[derive(Default)]
+pub(super) struct BlockDictionaries {
+    dictionaries: Vec<FieldDictionary>,
+}
+
+impl BlockDictionaries {
+    /// Chooses the string fields whose values repeat enough within the tuples to be worth
+    /// encoding, which are the ones where every value is used at least twice on average
+    pub fn build<'a, I>(tuples: I, definition: &RelationDefinition) -> Self
+    where
+        I: IntoIterator<Item = &'a Tuple>,
+        I::IntoIter: Clone,
+    {
+        let tuples = tuples.into_iter();
+        let dictionaries = (0..definition.len())
+            .filter(|&field| is_string(definition.kind(field)))
+            .filter_map(|field| {
+                let mut seen = HashSet::new();
+                let mut values = vec![];
+                let mut used = 0;
+                for tuple in tuples.clone() {
+                    let string = match string_of(&tuple[field]) {
+                        Some(string) => string,
+                        None => continue,
+                    };
+                    used += 1;
+                    if seen.insert(string) {
+                        if values.len() == MAX_DICTIONARY_LEN {
+                            return None;
+                        }
+                        values.push(tuple[field].unwrap_optional().clone());
+                    }
+                }
+                if values.is_empty() || values.len() * 2 > used {
+                    return None;
+                }
+                Some(FieldDictionary { field, values })
+            })
+            .collect();
+        BlockDictionaries { dictionaries }
+    }
+
+    /// Writes a line for every dictionary
+    pub fn write_to<W: Write>(
+        &self,
+        writer: &mut W,
+        definition: &RelationDefinition,
+    ) -> io::Result<()> {
+        for dictionary in &self.dictionaries {
+            write!(
+                writer,
+                "{}{}:{}:",
+                DICTIONARY_MARKER,
+                dictionary.field,
+                dictionary.values.len()
+            )?;
+            serialize_values_to_with(
+                dictionary.values.iter().cloned(),
+                definition.text_format(),
+                writer,
+            )?;
+            writeln!(writer)?;
+        }
+        Ok(())
+    }
+
+    /// Reads a line written by [write_to](BlockDictionaries::write_to), without its marker. The
+    /// line is the field, the number of values and then the values.
+    pub fn read_line(&mut self, line: &str, definition: &RelationDefinition) -> Option<()> {
+        let mut split = line.splitn(3, ':');
+        let field: usize = split.next()?.parse().ok()?;
+        let count: usize = split.next()?.parse().ok()?;
+        if field >= definition.len() || !is_string(definition.kind(field)) {
+            return None;
+        }
+        let value_type = definition.kind(field).non_null().prototype();
+        let values = split.next()?;
+        let values = parse_using_types_with(
+            values,
+            std::iter::repeat(value_type).take(count),
+            definition.text_format(),
+        )
+        .ok()?;
+        self.dictionaries.push(FieldDictionary { field, values });
+        Some(())
+    }
+
+    /// The types the values of tuples are written as, where encoded fields are positions
+    pub fn encoded_types(&self, definition: &RelationDefinition) -> Vec<Type> {
+        let mut types: Vec<Type> = definition.into_iter().collect();
+        for dictionary in &self.dictionaries {
+            types[dictionary.field] = match &types[dictionary.field] {
+                Type::Optional(_) => Type::Optional(Some(Box::new(Type::from(0u8)))),
+                _ => Type::from(0u8),
+            };
+        }
+        types
+    }
+
+    /// Replaces the values of encoded fields with their positions in the dictionaries
+    pub fn encode(&self, tuple: Tuple) -> Vec<Type> {
+        let mut values: Vec<Type> = tuple.into_iter().collect();
+        for dictionary in &self.dictionaries {
+            let value = &mut values[dictionary.field];
+            if let Some(string) = string_of(value) {
+                let position = dictionary
+                    .values
+                    .iter()
+                    .position(|value| string_of(value) == Some(string))
+                    .expect("Every value of an encoded field is in its dictionary");
+                *value = Type::from(position as u8);
+            }
+        }
+        values
+    }
+
+    /// Replaces the positions of encoded fields with their values, or returns `None` if a
+    /// position isn't in its dictionary
+    pub fn decode(
+        &self,
+        mut values: Vec<Type>,
+        definition: &RelationDefinition,
+    ) -> Option<Vec<Type>> {
+        for dictionary in &self.dictionaries {
+            let value = &mut values[dictionary.field];
+            let position = match value.unwrap_optional() {
+                Type::Optional(None) => continue,
+                Type::Numeric(Numeric::Unsigned(Unsigned::Byte(position))) => *position as usize,
+                _ => return None,
+            };
+            let decoded = dictionary.values.get(position)?.clone();
+            *value = if definition.is_nullable(dictionary.field) {
+                Type::Optional(Some(Box::new(decoded)))
+            } else {
+                decoded
+            };
+        }
+        Some(values)
+    }
+}
+
+fn is_string(kind: &Kind) -> bool {
+    matches!(kind.non_null(), Kind::String { .. })
+}
+
+/// The string of a value of a string field, or `None` for `NULL`
+fn string_of(value: &Type) -> Option<&str> {
+    match value.unwrap_optional() {
+        Type::Text(Text::String(string, _)) => Some(string),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rad_db_types::serialization::{serialize_values_with, TextFormat};
+    use std::iter::FromIterator;
+
+    fn definition() -> RelationDefinition {
+        let optional_string = Type::Optional(Some(Box::new(Type::from(""))));
+        RelationDefinition::from_iter(vec![
+            ("id".to_string(), Type::from(0u32)),
+            ("name".to_string(), Type::from("")),
+            ("status".to_string(), Type::from("")),
+            ("category".to_string(), optional_string),
+        ])
+        .with_text_format(TextFormat::new(',', '"', "NULL"))
+    }
+
+    fn tuples() -> Vec<Tuple> {
+        (0..12u32)
+            .map(|id| {
+                let status = ["open", "closed, for now", ""][id as usize % 3];
+                let category = match id % 4 {
+                    0 => Type::Optional(None),
+                    c => Type::Optional(Some(Box::new(Type::from(format!("c{}", c))))),
+                };
+                let name = Type::from(format!("name {}", id));
+                Tuple::new(vec![Type::from(id), name, Type::from(status), category])
+            })
+            .collect()
+    }
+
+    #[test]
+    fn repeated_strings_are_encoded() {
+        let definition = definition();
+        let tuples = tuples();
+        let dictionaries = BlockDictionaries::build(&tuples, &definition);
+        let fields: Vec<usize> = dictionaries
+            .dictionaries
+            .iter()
+            .map(|dictionary| dictionary.field)
+            .collect();
+        // every name is different
+        assert_eq!(fields, vec![2, 3]);
+
+        let mut written = vec![];
+        dictionaries.write_to(&mut written, &definition).unwrap();
+        let written = String::from_utf8(written).unwrap();
+        let mut read = BlockDictionaries::default();
+        for line in written.lines() {
+            let line = line.strip_prefix(DICTIONARY_MARKER).unwrap();
+            read.read_line(line, &definition).unwrap();
+        }
+
+        let types = read.encoded_types(&definition);
+        for tuple in tuples {
+            let encoded = dictionaries.encode(tuple.clone());
+            let line = serialize_values_with(encoded, definition.text_format());
+            let parsed =
+                parse_using_types_with(&line, types.iter().cloned(), definition.text_format())
+                    .unwrap();
+            let decoded = read.decode(parsed, &definition).unwrap();
+            assert_eq!(Tuple::new(decoded), tuple);
+        }
+    }
+
+    #[test]
+    fn unknown_positions_are_rejected() {
+        let definition = definition();
+        let mut dictionaries = BlockDictionaries::default();
+        assert!(dictionaries.read_line("0:1:3", &definition).is_none());
+        dictionaries
+            .read_line(r#"2:2:"open","closed""#, &definition)
+            .unwrap();
+        let values = vec![
+            Type::from(0u32),
+            Type::from(""),
+            Type::from(2u8),
+            Type::Optional(None),
+        ];
+        assert!(dictionaries.decode(values, &definition).is_none());
+    }
+}
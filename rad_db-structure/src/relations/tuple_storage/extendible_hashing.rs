@@ -1,9 +1,9 @@
-use std::cell::UnsafeCell;
 use std::cmp::min;
 use std::collections::{HashMap, VecDeque};
 use std::fmt::{Debug, Formatter};
 use std::ops::{BitAnd, Deref, DerefMut, Not};
-use std::sync::{Arc, RwLock, RwLockReadGuard};
+use std::sync::Arc;
+use std::time::Duration;
 
 use num_bigint::{BigUint, ToBigUint};
 use num_traits::{One, ToPrimitive, Zero};
@@ -11,7 +11,9 @@ use num_traits::{One, ToPrimitive, Zero};
 use crate::identifier::Identifier;
 use crate::key::primary::{PrimaryKey, PrimaryKeyDefinition};
 use crate::relations::tuple_storage::block::{Block, InUse};
-use crate::relations::tuple_storage::lock::{Lock, LockRead, LockWrite};
+use crate::relations::tuple_storage::lock::{
+    Lock, LockRead, LockResult, LockUpgradeableRead, LockWrite,
+};
 use crate::relations::tuple_storage::TupleStorage;
 use crate::relations::RelationDefinition;
 use crate::tuple::Tuple;
@@ -20,7 +22,7 @@ use crate::Rename;
 /// A local bucket that contains information on the local block
 pub(super) struct Bucket {
     local_depth: usize,
-    block: Block,
+    block: Arc<Block>,
     mask: BigUint,
 }
 
@@ -59,9 +61,66 @@ impl Deref for Bucket {
     }
 }
 
-impl DerefMut for Bucket {
-    fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.block
+/// The buckets of a block directory, and which bucket each directory hash points to. Changing
+/// which buckets there are takes the lock of the directory alone, while the tuples within a bucket
+/// are guarded by the block of the bucket.
+#[derive(Default)]
+pub(super) struct Directory {
+    buckets: Vec<Bucket>,
+    /// Key is the directory hash, value is the location of the index of the corresponding bucket
+    directories: HashMap<BigUint, usize>,
+    global_depth: usize,
+    mask: BigUint,
+}
+
+impl Directory {
+    fn new() -> Self {
+        Directory {
+            global_depth: 1,
+            mask: BigUint::one(),
+            ..Default::default()
+        }
+    }
+
+    pub(super) fn buckets(&self) -> &[Bucket] {
+        &self.buckets
+    }
+
+    fn generate_mask(&mut self) {
+        let mut mask = BigUint::zero();
+        for _ in 0..self.global_depth {
+            mask <<= 1;
+            mask |= BigUint::one();
+        }
+        self.mask = mask;
+    }
+
+    fn get_directory(&self, hash: &BigUint) -> BigUint {
+        hash.bitand(&self.mask)
+    }
+
+    /// The bucket the hash is stored in, if its directory has one yet
+    fn bucket_for(&self, hash: &BigUint) -> Option<&Bucket> {
+        let bucket_num = *self.directories.get(&self.get_directory(hash))?;
+        self.buckets.get(bucket_num)
+    }
+
+    /// Expand the directory
+    fn expand_directory(&mut self) {
+        let mut new_hash_map = HashMap::with_capacity(self.directories.len() * 2);
+        for (key, value) in self.directories.iter() {
+            let new_key1 = key.clone();
+            let new_key2 = key.clone() | (BigUint::one() << self.global_depth);
+            new_hash_map.insert(new_key1, *value);
+            new_hash_map.insert(new_key2, *value);
+        }
+        self.directories = new_hash_map;
+        self.global_depth += 1;
+        self.generate_mask();
+    }
+
+    fn len(&self) -> usize {
+        self.buckets.iter().map(|bucket| bucket.len()).sum()
     }
 }
 
@@ -70,15 +129,13 @@ impl DerefMut for Bucket {
 pub struct BlockDirectory {
     parent_table: Identifier,
     relationship_definition: RelationDefinition,
-    bucket_lock: Lock,
-    buckets: UnsafeCell<Vec<Box<Bucket>>>,
+    directory: Lock<Directory>,
     bucket_size: usize,
-    global_depth: usize,
-    /// Key is the directory hash, value is the location of the index of the corresponding bucket
-    directories: RwLock<HashMap<BigUint, usize>>,
-    mask: BigUint,
     primary_key_definition: PrimaryKeyDefinition,
     volatile: bool,
+    /// How long inserts and fallible scans wait on the directory, or `None` to wait for as long
+    /// as it takes
+    lock_timeout: Option<Duration>,
 }
 
 impl BlockDirectory {
@@ -92,14 +149,11 @@ impl BlockDirectory {
         BlockDirectory {
             parent_table,
             relationship_definition,
-            bucket_lock: Default::default(),
-            buckets: Default::default(),
+            directory: Lock::new(Directory::new()),
             bucket_size,
-            global_depth: 1,
-            directories: Default::default(),
-            mask: BigUint::one(),
             primary_key_definition,
             volatile: false,
+            lock_timeout: None,
         }
     }
 
@@ -112,14 +166,11 @@ impl BlockDirectory {
         BlockDirectory {
             parent_table,
             relationship_definition,
-            bucket_lock: Default::default(),
-            buckets: Default::default(),
+            directory: Lock::new(Directory::new()),
             bucket_size,
-            global_depth: 1,
-            directories: Default::default(),
-            mask: BigUint::one(),
             primary_key_definition,
             volatile: true,
+            lock_timeout: None,
         }
     }
 
@@ -127,85 +178,73 @@ impl BlockDirectory {
         self.bucket_size
     }
 
-    fn hash_tuple(&self, tuple: &Tuple) -> BigUint {
-        let primary_key = self.get_primary_key_of_tuple(tuple);
-        primary_key.hash()
-    }
-
-    fn get_primary_key_of_tuple<'a>(&self, tuple: &'a Tuple) -> PrimaryKey<'a> {
-        let definition = &self.primary_key_definition;
-        let ret = tuple
-            .iter()
-            .enumerate()
-            .filter(|(pos, _)| definition.contains(pos))
-            .map(|(_, val)| val)
-            .collect();
-        PrimaryKey::new(ret, definition.create_seeds())
-    }
-
-    fn generate_mask(&mut self) {
-        let mut mask = BigUint::zero();
-        for _ in 0..self.global_depth {
-            mask <<= 1;
-            mask |= BigUint::one();
-        }
-        self.mask = mask;
+    pub(super) fn lock_timeout(&self) -> Option<Duration> {
+        self.lock_timeout
     }
 
-    fn get_directory(&self, hash: &BigUint) -> BigUint {
-        hash.bitand(&self.mask)
+    pub(super) fn set_lock_timeout(&mut self, timeout: Option<Duration>) {
+        self.lock_timeout = timeout;
     }
 
-    pub(super) fn buckets(&self) -> (&Vec<Box<Bucket>>, LockRead) {
-        unsafe {
-            let read = self.bucket_lock.read();
-            (&*self.buckets.get(), read)
-        }
+    /// Reads the buckets, keeping buckets from being added or split until the read is dropped
+    pub(super) fn directory(&self) -> LockRead<'_, Directory> {
+        self.directory.read()
     }
 
-    pub(super) fn buckets_mut(&self) -> (&mut Vec<Box<Bucket>>, LockWrite) {
-        unsafe {
-            let write = self.bucket_lock.write();
-            (&mut *self.buckets.get(), write)
+    /// Reads the buckets like [directory](BlockDirectory::directory), but fails if waiting
+    /// would deadlock or take longer than the timeout
+    fn checked_directory(&self) -> LockResult<LockRead<'_, Directory>> {
+        match self.lock_timeout {
+            None => self.directory.checked_read(),
+            Some(timeout) => self.directory.try_read_for(timeout),
         }
     }
 
-    pub(super) fn bucket(&self, index: usize, _read: &LockRead<'_>) -> Option<&Box<Bucket>> {
-        if index >= self.bucket_count() {
-            return None;
+    /// Reads the buckets, keeping any other thread from changing them, failing if waiting would
+    /// deadlock or take longer than the timeout
+    fn upgradeable_directory(&self) -> LockResult<LockUpgradeableRead<'_, Directory>> {
+        match self.lock_timeout {
+            None => self.directory.checked_upgradeable_read(),
+            Some(timeout) => self.directory.try_upgradeable_read_for(timeout),
         }
-
-        unsafe { (*self.buckets.get()).get(index) }
     }
 
-    pub(super) fn bucket_mut(
+    /// Waits for the other reads of the buckets to finish so they can be changed, failing if
+    /// waiting would deadlock or take longer than the timeout
+    fn upgrade_directory<'a>(
         &self,
-        index: usize,
-        _write: &mut LockWrite<'_>,
-    ) -> Option<&mut Box<Bucket>> {
-        if index >= self.bucket_count() {
-            return None;
+        directory: LockUpgradeableRead<'a, Directory>,
+    ) -> LockResult<LockWrite<'a, Directory>> {
+        match self.lock_timeout {
+            None => directory.checked_upgrade(),
+            Some(timeout) => directory.try_upgrade_for(timeout),
         }
+    }
 
-        unsafe { (*self.buckets.get()).get_mut(index) }
+    /// Whether the bucket the hash points to can take another tuple
+    fn has_room(&self, directory: &Directory, full_hash: &BigUint) -> bool {
+        directory
+            .bucket_for(full_hash)
+            .map_or(false, |bucket| bucket.len() < self.bucket_size)
     }
 
     /// Creates a new block and returns its id/index
-    fn create_new_bucket(&self, local_depth: usize) -> usize {
-        let (mut buckets, _lock) = self.buckets_mut();
-        let id = buckets.len();
+    fn create_new_bucket(&self, directory: &mut Directory, local_depth: usize) -> usize {
+        let id = directory.buckets.len();
         let block = if self.volatile {
-            Block::new_unbacked(
+            Arc::new(Block::new_unbacked(
                 self.parent_table.clone(),
                 id,
                 self.relationship_definition.clone(),
-            )
+            ))
         } else {
-            Block::new(
+            let block = Arc::new(Block::new(
                 self.parent_table.clone(),
                 id,
                 self.relationship_definition.clone(),
-            )
+            ));
+            Block::track(&block);
+            block
         };
         let bucket = Bucket {
             local_depth,
@@ -213,234 +252,143 @@ impl BlockDirectory {
             mask: mask(local_depth).to_biguint().unwrap(),
         };
 
-        buckets.push(Box::new(bucket));
+        directory.buckets.push(bucket);
         id
     }
 
-    /// Expand the directory
-    fn expand_directory(&mut self) {
-        {
-            let mut lock = self.directories.write().unwrap();
-            let mut new_hash_map = HashMap::with_capacity(lock.len() * 2);
-            for (key, value) in lock.iter() {
-                let new_key1 = key.clone();
-                let new_key2 = key.clone() | (BigUint::one() << self.global_depth);
-                new_hash_map.insert(new_key1, *value);
-                new_hash_map.insert(new_key2, *value);
-            }
-
-            *lock = new_hash_map;
+    fn split_bucket(
+        &self,
+        directory: &mut Directory,
+        bucket_index: usize,
+        directory_number: &BigUint,
+    ) {
+        if directory.buckets[bucket_index].local_depth == directory.global_depth {
+            directory.expand_directory();
         }
-        self.global_depth += 1;
-        self.generate_mask();
-    }
-
-    fn split_bucket(&mut self, bucket_index: usize, directory_number: &BigUint) {
-        // println!("[BEFORE split] {:?}", self);
-        let (new_block_index, tuples, local_depth) = {
-            {
-                let expand = {
-                    let (mut buckets, _lock) = self.buckets();
-                    let bucket = &buckets[bucket_index];
-                    bucket.local_depth == self.global_depth
-                };
-                if expand {
-                    self.expand_directory();
-                }
-            }
-            let (mut buckets, lock) = self.buckets_mut();
-            let bucket = &mut buckets[bucket_index];
+        let (tuples, local_depth) = {
+            let bucket = &mut directory.buckets[bucket_index];
             bucket.local_depth += 1;
             bucket.mask = mask(bucket.local_depth).to_biguint().unwrap();
-            let local_depth = bucket.local_depth;
-
-            let mut in_use = bucket.get_contents_mut();
-            let mut tuples = in_use.take_all();
-            std::mem::drop(in_use);
-            std::mem::drop(lock);
-            (self.create_new_bucket(local_depth), tuples, local_depth)
+            let tuples = bucket.get_contents_mut().take_all_with_key();
+            (tuples, bucket.local_depth)
         };
-
-        {
-            let small_mask = mask(local_depth - 1).to_biguint().unwrap();
-            let original_real_check = small_mask & directory_number;
-
-            let higher_directory_check =
-                original_real_check | (BigUint::one() << (local_depth - 1));
-            let mut directories = self.directories.write().unwrap();
-            let local_mask = mask(local_depth);
-
-            /*
-            let local_mask =
-            let mut rewrite =
-            for key in directories.keys() {
-                if &(key & directory_number) == key {
-
-                }
-            }
-
-             */
-
-            for dir in directories.iter_mut() {
-                let masked_local = dir.0 & BigUint::from(local_mask);
-                //let check = &masked & &higher_directory_check;
-                if &masked_local == &higher_directory_check && dir.1 == &bucket_index {
-                    *dir.1 = new_block_index;
-                }
+        let new_block_index = self.create_new_bucket(directory, local_depth);
+
+        let small_mask = mask(local_depth - 1).to_biguint().unwrap();
+        let original_real_check = small_mask & directory_number;
+        let higher_directory_check = original_real_check | (BigUint::one() << (local_depth - 1));
+        let local_mask = mask(local_depth);
+        for dir in directory.directories.iter_mut() {
+            let masked_local = dir.0 & BigUint::from(local_mask);
+            if masked_local == higher_directory_check && dir.1 == &bucket_index {
+                *dir.1 = new_block_index;
             }
-
-            //directories.insert(higher_directory_check, new_block_index);
         }
-        //println!("[DURING split] {:?}", self);
-        let (mut buckets, _lock) = self.buckets_mut();
-
-        for tuple in tuples {
-            let hash = self.hash_tuple(&tuple);
-
-            let dir = self.get_directory(&hash);
-            let bucket_from_dir = self.directories.read().unwrap().get(&dir).cloned().unwrap();
-            let as_usize = bucket_from_dir.to_usize().unwrap();
-            let bucket = &mut buckets[as_usize];
-            /*
-            let bucket = if masked == bucket_index {
-                &mut buckets[bucket_index]
-            } else if masked == new_block_index {
-                &mut buckets[new_block_index]
-            } else {
-                panic!("Something in the split bucket function went wrong when determining what bucket to put something in")
-            };
 
-             */
-            let mut use_mut = bucket.get_contents_mut();
-
-            use_mut.insert_tuple(hash, tuple);
+        for (hash, tuple) in tuples {
+            let bucket = directory
+                .bucket_for(&hash)
+                .expect("tuples of a split bucket stay within its directories");
+            bucket.get_contents_mut().insert_tuple(hash, tuple);
         }
-        // println!("[AFTER split] {:#?}", self);
-    }
-
-    fn get_bucket_num(&self, directory: &BigUint) -> Option<usize> {
-        let lock = self.directories.read().unwrap();
-        let bucket_option = lock.get(directory);
-        bucket_option.map(|u| *u)
     }
 
-    fn get_bucket_from_directory(&self, directory: BigUint) -> &Bucket {
-        let bucket_option = self.get_bucket_num(&directory);
-        {
-            if let Some(bucket) = bucket_option {
-                unsafe {
-                    let (bucket_lock, _lock) = self.buckets();
-                    let boxed = &*bucket_lock[bucket] as *const Bucket;
-
-                    return &*boxed;
+    /// Makes room in the directory for the hash, by giving its directory a bucket or splitting
+    /// its bucket when it's full
+    fn make_room(&self, directory: &mut Directory, full_hash: &BigUint) {
+        let directory_number = directory.get_directory(full_hash);
+        match directory.directories.get(&directory_number) {
+            None => {
+                let new_bucket = self.create_new_bucket(directory, 1);
+                directory.directories.insert(directory_number, new_bucket);
+            }
+            Some(&bucket_num) => {
+                if directory.buckets[bucket_num].len() >= self.bucket_size {
+                    self.split_bucket(directory, bucket_num, &directory_number);
                 }
             }
         }
-        let mut lock = self.directories.write().unwrap();
-        let new_bucket = self.create_new_bucket(1);
-        lock.insert(directory, new_bucket);
-        let (buckets, _lock) = self.buckets();
-        unsafe {
-            let boxed = &*buckets[new_bucket] as *const Bucket;
-
-            return &*boxed;
-        }
     }
 
-    fn get_bucket_from_directory_mut(&mut self, directory: BigUint) -> &mut Bucket {
-        {
-            let lock = self.directories.read().unwrap();
-            let bucket_option = lock.get(&directory);
-            if let Some(bucket) = bucket_option {
-                unsafe {
-                    let (mut bucket_lock, _lock) = self.buckets_mut();
-                    let boxed = &mut *bucket_lock[*bucket] as *mut Bucket;
-
-                    return &mut *boxed;
+    /// Inserts the tuple into the bucket its hash points to. Tuples going into different buckets
+    /// are inserted at the same time. When the bucket is full or missing, the insert takes an
+    /// upgradeable read of the directory, so that scans can carry on while it checks again, and
+    /// only upgrades it to add or split the bucket. Fails instead of waiting on the directory if
+    /// waiting would deadlock or take longer than the timeout.
+    pub fn insert(&self, tuple: Tuple, full_hash: BigUint) -> LockResult<Option<Tuple>> {
+        loop {
+            {
+                let directory = self.checked_directory()?;
+                if let Some(bucket) = directory.bucket_for(&full_hash) {
+                    let mut contents = bucket.get_contents_mut();
+                    // replacing a tuple doesn't take up any more room
+                    if bucket.len() < self.bucket_size
+                        || contents.get_tuple(full_hash.clone()).is_some()
+                    {
+                        return Ok(contents.insert_tuple(full_hash, tuple));
+                    }
                 }
             }
-        }
-        let mut lock = self.directories.write().unwrap();
-        let new_bucket = self.create_new_bucket(1);
-        lock.insert(directory, new_bucket);
-        let (buckets, _lock) = self.buckets_mut();
-        unsafe {
-            let boxed = &mut *buckets[new_bucket] as *mut Bucket;
-
-            return &mut *boxed;
+            let directory = self.upgradeable_directory()?;
+            // another insert may have made room while this one waited
+            if self.has_room(&directory, &full_hash) {
+                continue;
+            }
+            let mut directory = self.upgrade_directory(directory)?;
+            self.make_room(&mut directory, &full_hash);
         }
     }
 
-    pub fn insert(&mut self, tuple: Tuple, full_hash: BigUint) -> Option<Tuple> {
-        let (bucket, directory_number) = {
-            let directory_number = self.get_directory(&full_hash);
-            let bucket_size = self.bucket_size;
-            let bucket = self.get_bucket_from_directory(directory_number.clone());
-            let len = bucket.len();
-            if len == bucket_size {
-                // Overflow!
-                let bucket_num = self.get_bucket_num(&directory_number).unwrap();
-                self.split_bucket(bucket_num, &directory_number);
-                return self.insert(tuple, full_hash);
-            } else {
-                // easy insert
-                let bucket = self.get_bucket_from_directory_mut(directory_number.clone());
-                (bucket, directory_number)
-            }
-        };
-        //let bucket = self.get_bucket_from_directory_mut(directory_number.clone());
-
-        let ret = {
-            let mut in_use = bucket.block.get_contents_mut();
-            in_use.insert_tuple(full_hash, tuple)
-        };
-        if ret.is_none() {
-            //*bucket.len_mut() += 1;
-            if bucket.len() > self.bucket_size {
-                panic!(
-                    "Added too many tuples to bucket {}",
-                    self.get_bucket_num(&directory_number).unwrap()
-                )
+    /// Puts back the tuple stored under the hash before an insert, or removes the inserted tuple
+    /// if there wasn't one, so that an insert can be undone. Waits on the directory for as long
+    /// as it takes, as an undo that gave up would leave the insert in place.
+    pub(super) fn restore(&self, full_hash: BigUint, previous: Option<Tuple>) {
+        let directory = self.directory.read();
+        if let Some(bucket) = directory.bucket_for(&full_hash) {
+            let mut contents = bucket.get_contents_mut();
+            match previous {
+                Some(previous) => {
+                    contents.insert_tuple(full_hash, previous);
+                }
+                None => {
+                    contents.remove_tuple(full_hash);
+                }
             }
         }
-        ret
     }
 
-    pub(super) fn get_bucket_for_primary_key(&self, full_hash: BigUint) -> &Bucket {
-        let directory_number = self.get_directory(&full_hash);
-        self.get_bucket_from_directory(directory_number.clone())
+    /// Finds the tuple stored under the hash, only reading the block the hash is in
+    pub(super) fn find(&self, full_hash: BigUint) -> Option<Tuple> {
+        let directory = self.directory.read();
+        let bucket = directory.bucket_for(&full_hash)?;
+        let contents = bucket.get_contents();
+        contents.get_tuple(full_hash).cloned()
     }
 
     pub fn bucket_count(&self) -> usize {
-        self.buckets().0.len()
-    }
-
-    pub unsafe fn len_unsafe(&self) -> usize {
-        let mut output = 0;
-
-        let buckets = &*self.buckets.get();
-        for b in buckets {
-            output += b.block.len()
-        }
-
-        output
+        self.directory.read().buckets.len()
     }
 
     /// Gets the amount of tuples in the directory
     pub fn len(&self) -> usize {
-        let mut output = 0;
-
-        let (buckets, _) = self.buckets();
-        for b in buckets {
-            output += b.block.len()
-        }
-
-        output
+        self.directory.read().len()
     }
 
     /// Retrieves a block iterator of the directory
     pub fn blocks(&self) -> BlockIterator {
-        BlockIterator::new(self)
+        BlockIterator::new(self.directory.read())
+    }
+
+    /// Retrieves a block iterator of the directory, unless waiting for the directory would
+    /// deadlock or take longer than the timeout
+    pub fn try_blocks(&self) -> LockResult<BlockIterator<'_>> {
+        Ok(BlockIterator::new(self.checked_directory()?))
+    }
+
+    /// Retrieves an iterator over every tuple of the directory, unless waiting for the directory
+    /// would deadlock or take longer than the timeout
+    pub fn try_tuples(&self) -> LockResult<StoredTupleIterator<'_>> {
+        Ok(StoredTupleIterator::new(self.checked_directory()?))
     }
 }
 
@@ -451,19 +399,16 @@ impl BlockDirectory {
 pub struct BlockIterator<'a> {
     bucket_num: usize,
     max_block_num: usize,
-    directory: &'a BlockDirectory,
-    read: LockRead<'a>,
+    read: LockRead<'a, Directory>,
 }
 
 impl<'a> BlockIterator<'a> {
-    fn new(directory: &'a BlockDirectory) -> Self {
-        let read = directory.bucket_lock.read();
-        let max_block_num = directory.bucket_count();
+    fn new(read: LockRead<'a, Directory>) -> Self {
+        let max_block_num = read.buckets.len();
 
         BlockIterator {
             bucket_num: 0,
             max_block_num,
-            directory,
             read,
         }
     }
@@ -482,7 +427,7 @@ impl<'a> Iterator for BlockIterator<'a> {
         }
 
         while self.bucket_num < self.max_block_num {
-            let bucket = self.directory.bucket(self.bucket_num, &self.read).unwrap();
+            let bucket = &self.read.buckets[self.bucket_num];
             self.bucket_num += 1;
             if !bucket.is_empty() {
                 let contents = bucket.block.get_contents();
@@ -497,19 +442,17 @@ impl<'a> Iterator for BlockIterator<'a> {
 pub struct RepeatableBlockIterator<'a> {
     bucket_num: usize,
     max_block_num: usize,
-    directory: &'a BlockDirectory,
-    read: LockRead<'a>,
+    read: LockRead<'a, Directory>,
 }
 
 impl<'a> RepeatableBlockIterator<'a> {
     fn new(directory: &'a BlockDirectory) -> Self {
-        let read = directory.bucket_lock.read();
-        let max_block_num = directory.bucket_count();
+        let read = directory.directory.read();
+        let max_block_num = read.buckets.len();
 
         RepeatableBlockIterator {
             bucket_num: 0,
             max_block_num,
-            directory,
             read,
         }
     }
@@ -520,13 +463,11 @@ impl<'a> From<&BlockIterator<'a>> for RepeatableBlockIterator<'a> {
         let BlockIterator {
             bucket_num,
             max_block_num,
-            directory,
             read,
         } = i;
         Self {
             bucket_num: *bucket_num,
             max_block_num: *max_block_num,
-            directory: *directory,
             read: read.clone(),
         }
     }
@@ -546,7 +487,6 @@ impl<'a> IntoIterator for &RepeatableBlockIterator<'a> {
         BlockIterator {
             bucket_num: 0,
             max_block_num: self.bucket_num,
-            directory: self.directory,
             read: self.read.clone(),
         }
     }
@@ -559,20 +499,17 @@ pub struct StoredTupleIterator<'a> {
     buffer: VecDeque<Tuple>,
     bucket_num: usize,
     max_block_num: usize,
-    directory: &'a BlockDirectory,
-    read: LockRead<'a>,
+    read: LockRead<'a, Directory>,
 }
 
 impl<'a> StoredTupleIterator<'a> {
-    fn new(directory: &'a BlockDirectory) -> Self {
-        let read = directory.bucket_lock.read();
-        let max_block_num = directory.bucket_count();
+    fn new(read: LockRead<'a, Directory>) -> Self {
+        let max_block_num = read.buckets.len();
 
         StoredTupleIterator {
             buffer: Default::default(),
             bucket_num: 0,
             max_block_num,
-            directory,
             read,
         }
     }
@@ -587,7 +524,7 @@ impl<'a> Iterator for StoredTupleIterator<'a> {
         }
 
         while self.buffer.is_empty() && self.bucket_num < self.max_block_num {
-            let block = self.directory.bucket(self.bucket_num, &self.read).unwrap();
+            let block = &self.read.buckets[self.bucket_num];
             let contents = block.get_contents();
             for tuple in contents.all() {
                 self.buffer.push_back(tuple.clone())
@@ -598,20 +535,18 @@ impl<'a> Iterator for StoredTupleIterator<'a> {
     }
 
     fn size_hint(&self) -> (usize, Option<usize>) {
-        (self.directory.len(), Some(self.directory.len()))
+        (self.read.len(), Some(self.read.len()))
     }
 }
 
-impl <'a> ExactSizeIterator for StoredTupleIterator<'a> {
-
-}
+impl<'a> ExactSizeIterator for StoredTupleIterator<'a> {}
 
 impl<'a> IntoIterator for &'a BlockDirectory {
     type Item = Tuple;
     type IntoIter = StoredTupleIterator<'a>;
 
     fn into_iter(self) -> Self::IntoIter {
-        StoredTupleIterator::new(self)
+        StoredTupleIterator::new(self.directory.read())
     }
 }
 
@@ -624,8 +559,7 @@ impl Rename<Identifier> for BlockDirectory {
 impl Drop for BlockDirectory {
     /// Concurrently drops all of the blocks in storage
     fn drop(&mut self) {
-        let buckets = std::mem::replace(&mut self.buckets, UnsafeCell::new(Vec::new()));
-        let buckets = buckets.into_inner();
+        let buckets = std::mem::take(&mut self.directory.get_mut().buckets);
         let handles = buckets.into_iter().map(|bucket| {
             std::thread::spawn(move || {
                 std::mem::drop(bucket);
@@ -641,20 +575,21 @@ impl Drop for BlockDirectory {
 impl Debug for BlockDirectory {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         writeln!(f, "{} Block Directory {{", self.parent_table)?;
-        unsafe {
-            writeln!(f, "\tLen = {}", self.len_unsafe())?;
-        }
-        writeln!(f, "\tGlobal Depth = {}", self.global_depth)?;
-        writeln!(f, "\tMask = {:b}", self.mask)?;
+        // a thread changing the buckets can still print the directory
+        let directory = match self.directory.try_read() {
+            Some(directory) => directory,
+            None => return write!(f, "\t<locked>\n}}"),
+        };
+        writeln!(f, "\tLen = {}", directory.len())?;
+        writeln!(f, "\tGlobal Depth = {}", directory.global_depth)?;
+        writeln!(f, "\tMask = {:b}", directory.mask)?;
         writeln!(f, "\tBucket Size = {}", self.bucket_size)?;
         writeln!(f, "\tDirectories:")?;
-        let guard = self.directories.read().unwrap();
-        for (key, value) in &*guard {
+        for (key, value) in &directory.directories {
             writeln!(f, "\t\t{:b} -> {}", key, value)?;
         }
         writeln!(f, "\tBuckets:")?;
-        let buckets = unsafe { &*self.buckets.get() };
-        for (index, bucket) in buckets.iter().enumerate() {
+        for (index, bucket) in directory.buckets.iter().enumerate() {
             write!(
                 f,
                 "\t\tBucket {}: Length={} Local Depth={}",
@@ -676,3 +611,99 @@ impl Debug for BlockDirectory {
         write!(f, "}}")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::relations::tuple_storage::LockError;
+    use rad_db_types::Type;
+
+    #[test]
+    fn inserts_fail_instead_of_deadlocking() {
+        let name = Identifier::new("locked");
+        let definition = RelationDefinition::new(vec![(
+            Identifier::with_parent(&name, "id"),
+            Type::from(0u8),
+        )]);
+        let directory =
+            BlockDirectory::new_volatile(name, definition, 1, PrimaryKeyDefinition::new(vec![0]));
+        let tuple = |i: u8| Tuple::new(vec![i.into()]);
+        assert!(directory
+            .insert(tuple(0), BigUint::from(0u8))
+            .unwrap()
+            .is_none());
+
+        // the bucket is full, so splitting it would wait on the read this thread holds
+        let blocks = directory.blocks();
+        assert_eq!(
+            directory.insert(tuple(2), BigUint::from(2u8)).err(),
+            Some(LockError::Deadlock)
+        );
+        drop(blocks);
+        assert!(directory
+            .insert(tuple(2), BigUint::from(2u8))
+            .unwrap()
+            .is_none());
+        assert_eq!(directory.len(), 2);
+    }
+
+    #[test]
+    fn concurrent_inserts_split_buckets() {
+        let name = Identifier::new("concurrent");
+        let definition = RelationDefinition::new(vec![(
+            Identifier::with_parent(&name, "id"),
+            Type::from(0u16),
+        )]);
+        let directory =
+            BlockDirectory::new_volatile(name, definition, 4, PrimaryKeyDefinition::new(vec![0]));
+        std::thread::scope(|scope| {
+            for thread in 0..4u16 {
+                let directory = &directory;
+                scope.spawn(move || {
+                    for i in (0..64u16).map(|i| i * 4 + thread) {
+                        let inserted =
+                            directory.insert(Tuple::new(vec![i.into()]), BigUint::from(i));
+                        assert!(inserted.unwrap().is_none());
+                    }
+                });
+                scope.spawn(move || {
+                    // scans carry on while buckets are split
+                    assert!(directory.blocks().flatten().count() <= 256);
+                });
+            }
+        });
+        assert_eq!(directory.len(), 256);
+        for i in 0..256u16 {
+            assert_eq!(
+                directory.find(BigUint::from(i)),
+                Some(Tuple::new(vec![i.into()]))
+            );
+        }
+    }
+
+    #[test]
+    fn waits_on_the_directory_time_out() {
+        let name = Identifier::new("timed");
+        let definition = RelationDefinition::new(vec![(
+            Identifier::with_parent(&name, "id"),
+            Type::from(0u8),
+        )]);
+        let mut directory =
+            BlockDirectory::new_volatile(name, definition, 1, PrimaryKeyDefinition::new(vec![0]));
+        directory.set_lock_timeout(Some(Duration::from_millis(20)));
+        let splitting = directory.directory.write();
+        std::thread::scope(|scope| {
+            scope.spawn(|| {
+                assert_eq!(directory.try_tuples().err(), Some(LockError::Timeout));
+                assert_eq!(
+                    directory
+                        .insert(Tuple::new(vec![0u8.into()]), BigUint::from(0u8))
+                        .err(),
+                    Some(LockError::Timeout)
+                );
+            });
+        });
+        drop(splitting);
+        assert_eq!(directory.try_tuples().unwrap().count(), 0);
+    }
+}
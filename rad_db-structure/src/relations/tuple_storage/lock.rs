@@ -1,78 +1,715 @@
-use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
-use std::sync::Mutex;
+use std::cell::UnsafeCell;
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::ops::{Deref, DerefMut};
+use std::sync::{Condvar, Mutex, MutexGuard, OnceLock};
+use std::thread::{self, ThreadId};
+use std::time::{Duration, Instant};
 
-/// Use for locking of the structure, ensuring that inner fields are locked properly, but also
-/// allow for releasing of internal references
+/// When a lock couldn't be taken
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LockError {
+    /// Waiting for the lock would never end, because the holders of the lock are waiting on
+    /// locks held by this thread
+    Deadlock,
+    /// The lock wasn't released before the time limit
+    Timeout,
+}
+
+impl Display for LockError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LockError::Deadlock => write!(f, "Waiting for the lock would deadlock"),
+            LockError::Timeout => write!(f, "Timed out waiting for the lock"),
+        }
+    }
+}
+
+impl Error for LockError {}
+
+pub type LockResult<T> = Result<T, LockError>;
+
+/// What a blocked thread is waiting to take
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Waiting {
+    Read,
+    UpgradeableRead,
+    /// Only taken by tests, as writes are otherwise taken by upgrading
+    #[cfg(test)]
+    Write,
+    /// Turning the upgradeable read the thread holds into a write
+    Upgrade,
+}
+
+impl Waiting {
+    /// Whether the thread waits to write, which holds back the reads of threads without one
+    fn is_write(self) -> bool {
+        match self {
+            Waiting::Read | Waiting::UpgradeableRead => false,
+            #[cfg(test)]
+            Waiting::Write => true,
+            Waiting::Upgrade => true,
+        }
+    }
+}
+
+/// A lock a thread is blocked on. The thread keeps the lock alive until it has removed it from
+/// the graph.
+struct Waited(*const LockCore);
+
+// only dereferenced while the graph is held, and the blocked thread keeps the lock alive until then
+unsafe impl Send for Waited {}
+
+/// The lock each blocked thread is waiting on. A thread is only recorded while it has to wait, so
+/// taking a lock that's free never touches the graph.
 #[derive(Default)]
-pub struct Lock {
-    internal_lock: Mutex<()>,
-    write: AtomicBool,
-    read: AtomicUsize,
+struct WaitsFor {
+    waiting: HashMap<ThreadId, Waited>,
 }
 
-impl Lock {
-    pub fn try_read(&self) -> Option<LockRead<'_>> {
-        let _mutex = self.internal_lock.lock().unwrap();
-        if self.write.load(Ordering::Relaxed) {
-            None
-        } else {
-            self.read.fetch_add(1, Ordering::Acquire);
-            Some(LockRead(self))
+impl WaitsFor {
+    fn get() -> MutexGuard<'static, WaitsFor> {
+        static WAITS_FOR: OnceLock<Mutex<WaitsFor>> = OnceLock::new();
+        WAITS_FOR
+            .get_or_init(Default::default)
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Records that the thread is waiting on the lock, unless following the threads holding it
+    /// back through the locks they are waiting on leads back to the thread. The thread has to be
+    /// one of the waiters of the lock already.
+    fn start_waiting(&mut self, lock: &LockCore, thread: ThreadId) -> LockResult<()> {
+        self.waiting.insert(thread, Waited(lock));
+        let mut visited = HashSet::new();
+        let mut stack = vec![thread];
+        while let Some(waiter) = stack.pop() {
+            if !visited.insert(waiter) {
+                continue;
+            }
+            let lock = match self.waiting.get(&waiter) {
+                // Safety: the waiter doesn't stop waiting until it's removed from the graph
+                Some(Waited(lock)) => unsafe { &**lock },
+                None => continue,
+            };
+            // the waiter may have taken the lock without having removed itself from the graph yet
+            let blockers = match lock.state().blockers(waiter) {
+                Some(blockers) => blockers,
+                None => continue,
+            };
+            if blockers.contains(&thread) {
+                self.waiting.remove(&thread);
+                return Err(LockError::Deadlock);
+            }
+            stack.extend(blockers);
         }
+        Ok(())
+    }
+}
+
+/// Guards the value it holds, ensuring that inner fields are locked properly, but also allow for
+/// releasing of internal references
+///
+/// Waiting writers are preferred over new readers, so a steady stream of reads can't starve a
+/// write. A thread that already holds a read can still take another one while writers wait, such
+/// as when a relation is joined with itself, since holding it back would wait on itself.
+///
+/// Before blocking, a thread checks whether it would wait forever on a thread that is itself
+/// waiting on this thread, and fails with [LockError::Deadlock] instead.
+pub struct Lock<T = ()> {
+    core: LockCore,
+    value: UnsafeCell<T>,
+}
+
+// the value is only reached through the guards, which the state of the lock hands out like a
+// read-write lock does
+unsafe impl<T: Send + Sync> Sync for Lock<T> {}
+
+impl<T: Default> Default for Lock<T> {
+    fn default() -> Self {
+        Lock::new(T::default())
+    }
+}
+
+/// The part of a lock that tracks who holds it, which blocked threads refer to while looking for
+/// deadlocks
+#[derive(Default)]
+struct LockCore {
+    state: Mutex<LockState>,
+    /// Notified when a read, write or wait is given up
+    released: Condvar,
+}
+
+#[derive(Default)]
+struct LockState {
+    /// The number of reads each thread holds
+    readers: HashMap<ThreadId, usize>,
+    writer: Option<ThreadId>,
+    /// The thread holding the upgradeable read, if one is held
+    upgradeable: Option<ThreadId>,
+    /// The threads blocked on the lock, and what each is waiting to take
+    waiters: HashMap<ThreadId, Waiting>,
+}
+
+impl LockState {
+    fn can_read(&self, thread: ThreadId) -> bool {
+        self.writer.is_none() && (self.holds(thread) || !self.writers_waiting())
+    }
+
+    fn can_upgradeable_read(&self, thread: ThreadId) -> bool {
+        self.can_read(thread) && self.upgradeable.is_none()
     }
 
-    pub fn read(&self) -> LockRead<'_> {
-        loop {
-            if let Some(read) = self.try_read() {
-                return read;
+    fn can_upgrade(&self) -> bool {
+        self.writer.is_none() && self.readers.is_empty()
+    }
+
+    #[cfg(test)]
+    fn can_write(&self) -> bool {
+        self.can_upgrade() && self.upgradeable.is_none()
+    }
+
+    /// Whether the thread holds a read or the upgradeable read
+    fn holds(&self, thread: ThreadId) -> bool {
+        self.readers.contains_key(&thread) || self.upgradeable == Some(thread)
+    }
+
+    fn writers_waiting(&self) -> bool {
+        self.waiters.values().any(|waiting| waiting.is_write())
+    }
+
+    /// The threads the waiter is waiting on, or `None` if it isn't waiting on the lock
+    fn blockers(&self, waiter: ThreadId) -> Option<Vec<ThreadId>> {
+        let waiting = *self.waiters.get(&waiter)?;
+        let mut blockers: Vec<ThreadId> = self.writer.into_iter().collect();
+        match waiting {
+            Waiting::Read | Waiting::UpgradeableRead => {
+                blockers.extend(
+                    self.waiters
+                        .iter()
+                        .filter(|(thread, waiting)| **thread != waiter && waiting.is_write())
+                        .map(|(thread, _)| *thread),
+                );
+                if waiting == Waiting::UpgradeableRead {
+                    blockers.extend(self.upgradeable);
+                }
             }
+            #[cfg(test)]
+            Waiting::Write => {
+                blockers.extend(self.readers.keys());
+                blockers.extend(self.upgradeable);
+            }
+            // the waiter holds the upgradeable read itself
+            Waiting::Upgrade => blockers.extend(self.readers.keys()),
         }
+        Some(blockers)
     }
 
-    pub fn try_write(&self) -> Option<LockWrite<'_>> {
-        let _mutex = self.internal_lock.lock().unwrap();
-        if self.read.load(Ordering::Relaxed) > 0 {
-            return None;
+    fn add_reader(&mut self, thread: ThreadId) {
+        *self.readers.entry(thread).or_default() += 1;
+    }
+
+    fn remove_reader(&mut self, thread: ThreadId) {
+        if let Some(count) = self.readers.get_mut(&thread) {
+            *count -= 1;
+            if *count == 0 {
+                self.readers.remove(&thread);
+            }
         }
-        if self.write.compare_and_swap(false, true, Ordering::Relaxed) {
-            None
+    }
+}
+
+impl LockCore {
+    /// The state only holds counters, which are never left half updated, so a panic while the
+    /// state was held doesn't invalidate it
+    fn state(&self) -> MutexGuard<'_, LockState> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn wait<'a>(&self, state: MutexGuard<'a, LockState>) -> MutexGuard<'a, LockState> {
+        self.released
+            .wait(state)
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Takes the lock for the current thread with `take` once it's `ready`. If it isn't ready
+    /// yet, the thread is recorded as a waiter of the lock, and checked for deadlocks, before it
+    /// blocks.
+    fn acquire(
+        &self,
+        waiting: Waiting,
+        deadline: Option<Instant>,
+        ready: impl Fn(&LockState, ThreadId) -> bool,
+        take: impl FnOnce(&mut LockState, ThreadId),
+    ) -> LockResult<ThreadId> {
+        let thread = thread::current().id();
+        let mut state = self.state();
+        if ready(&state, thread) {
+            take(&mut state, thread);
+            return Ok(thread);
+        }
+        state.waiters.insert(thread, waiting);
+        // the graph is never taken while the state of a lock is held, as the graph reads the
+        // states of the locks while looking for deadlocks
+        drop(state);
+        let started = WaitsFor::get().start_waiting(self, thread);
+        let waited = started.and_then(|()| {
+            let mut state = self.state();
+            while !ready(&state, thread) {
+                state = match deadline {
+                    None => self.wait(state),
+                    Some(deadline) => {
+                        let now = Instant::now();
+                        if now >= deadline {
+                            return Err(LockError::Timeout);
+                        }
+                        self.released
+                            .wait_timeout(state, deadline - now)
+                            .map(|(state, _)| state)
+                            .unwrap_or_else(|poisoned| poisoned.into_inner().0)
+                    }
+                };
+            }
+            state.waiters.remove(&thread);
+            take(&mut state, thread);
+            Ok(())
+        });
+        if waited.is_err() {
+            self.state().waiters.remove(&thread);
+            // readers held back by this thread waiting to write can go ahead
+            self.released.notify_all();
+        }
+        WaitsFor::get().waiting.remove(&thread);
+        waited.map(|()| thread)
+    }
+}
+
+impl<T> Lock<T> {
+    pub fn new(value: T) -> Self {
+        Lock {
+            core: Default::default(),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    /// Gets the value without locking, as no guards can exist while the lock is borrowed mutably
+    pub fn get_mut(&mut self) -> &mut T {
+        self.value.get_mut()
+    }
+
+    fn state(&self) -> MutexGuard<'_, LockState> {
+        self.core.state()
+    }
+
+    pub fn try_read(&self) -> Option<LockRead<'_, T>> {
+        let thread = thread::current().id();
+        let mut state = self.state();
+        if state.can_read(thread) {
+            state.add_reader(thread);
+            Some(LockRead(self, thread))
         } else {
-            Some(LockWrite(self))
+            None
         }
     }
 
-    pub fn write(&self) -> LockWrite<'_> {
-        loop {
-            if let Some(write) = self.try_write() {
-                return write;
-            }
+    /// Blocks until no write is held or waiting, unless the thread already holds a read, then
+    /// takes a read
+    ///
+    /// # Panic
+    /// Panics if waiting would deadlock
+    pub fn read(&self) -> LockRead<'_, T> {
+        self.checked_read().unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Blocks until no write is held or waiting, unless the thread already holds a read, then
+    /// takes a read, unless waiting would deadlock
+    pub fn checked_read(&self) -> LockResult<LockRead<'_, T>> {
+        self.read_until(None)
+    }
+
+    /// Like [checked_read](Lock::checked_read), but gives up after waiting for the duration
+    pub fn try_read_for(&self, duration: Duration) -> LockResult<LockRead<'_, T>> {
+        self.read_until(Some(Instant::now() + duration))
+    }
+
+    fn read_until(&self, deadline: Option<Instant>) -> LockResult<LockRead<'_, T>> {
+        let thread = self.core.acquire(
+            Waiting::Read,
+            deadline,
+            |state, thread| state.can_read(thread),
+            LockState::add_reader,
+        )?;
+        Ok(LockRead(self, thread))
+    }
+
+    /// Blocks until no write or other upgradeable read is held or waiting, then takes a read
+    /// that can later be upgraded into a write. Other reads can still be taken until then.
+    ///
+    /// # Panic
+    /// Panics if waiting would deadlock
+    #[cfg(test)]
+    pub fn upgradeable_read(&self) -> LockUpgradeableRead<'_, T> {
+        self.checked_upgradeable_read()
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Blocks until no write or other upgradeable read is held or waiting, then takes a read
+    /// that can later be upgraded into a write, unless waiting would deadlock
+    pub fn checked_upgradeable_read(&self) -> LockResult<LockUpgradeableRead<'_, T>> {
+        self.upgradeable_read_until(None)
+    }
+
+    /// Like [checked_upgradeable_read](Lock::checked_upgradeable_read), but gives up after
+    /// waiting for the duration
+    pub fn try_upgradeable_read_for(
+        &self,
+        duration: Duration,
+    ) -> LockResult<LockUpgradeableRead<'_, T>> {
+        self.upgradeable_read_until(Some(Instant::now() + duration))
+    }
+
+    fn upgradeable_read_until(
+        &self,
+        deadline: Option<Instant>,
+    ) -> LockResult<LockUpgradeableRead<'_, T>> {
+        self.core.acquire(
+            Waiting::UpgradeableRead,
+            deadline,
+            |state, thread| state.can_upgradeable_read(thread),
+            |state, thread| state.upgradeable = Some(thread),
+        )?;
+        Ok(LockUpgradeableRead(self))
+    }
+}
+
+/// Writes are only taken by upgrading an upgradeable read outside of tests, which check how
+/// writes are held back and hold back others
+#[cfg(test)]
+impl<T> Lock<T> {
+    pub fn try_write(&self) -> Option<LockWrite<'_, T>> {
+        let thread = thread::current().id();
+        let mut state = self.state();
+        if state.can_write() {
+            state.writer = Some(thread);
+            Some(LockWrite(self))
+        } else {
+            None
         }
     }
+
+    /// Blocks until no read or write is held, then takes the write
+    ///
+    /// # Panic
+    /// Panics if waiting would deadlock
+    pub fn write(&self) -> LockWrite<'_, T> {
+        self.checked_write().unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Blocks until no read or write is held, then takes the write, unless waiting would
+    /// deadlock
+    pub fn checked_write(&self) -> LockResult<LockWrite<'_, T>> {
+        self.write_until(None)
+    }
+
+    /// Like [checked_write](Lock::checked_write), but gives up after waiting for the duration
+    pub fn try_write_for(&self, duration: Duration) -> LockResult<LockWrite<'_, T>> {
+        self.write_until(Some(Instant::now() + duration))
+    }
+
+    fn write_until(&self, deadline: Option<Instant>) -> LockResult<LockWrite<'_, T>> {
+        self.core.acquire(
+            Waiting::Write,
+            deadline,
+            |state, _| state.can_write(),
+            |state, thread| state.writer = Some(thread),
+        )?;
+        Ok(LockWrite(self))
+    }
 }
 
 /// Locks a write lock from being formed, but still allows for more than one read to be made
-pub struct LockRead<'a>(&'a Lock);
+pub struct LockRead<'a, T = ()>(&'a Lock<T>, ThreadId);
 
-impl Clone for LockRead<'_> {
+impl<T> Clone for LockRead<'_, T> {
     /// Can create more reads from a single read, and will extend the read until all LockRead instances
     /// have been dropped
     fn clone(&self) -> Self {
-        self.0.read.fetch_add(1, Ordering::Acquire);
-        LockRead(self.0)
+        self.0.state().add_reader(self.1);
+        LockRead(self.0, self.1)
+    }
+}
+
+impl<T> Deref for LockRead<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // Safety: no write is held while a read is
+        unsafe { &*self.0.value.get() }
     }
 }
 
-impl Drop for LockRead<'_> {
+impl<T> Drop for LockRead<'_, T> {
     fn drop(&mut self) {
-        self.0.read.fetch_sub(1, Ordering::Release);
+        let mut state = self.0.state();
+        state.remove_reader(self.1);
+        if state.readers.is_empty() {
+            self.0.core.released.notify_all();
+        }
+    }
+}
+
+/// A read that keeps writes and other upgradeable reads from being formed, so that it can be
+/// turned into a write without releasing the lock in between
+pub struct LockUpgradeableRead<'a, T = ()>(&'a Lock<T>);
+
+impl<'a, T> LockUpgradeableRead<'a, T> {
+    /// Waits for the other reads to be released, then turns this read into a write
+    ///
+    /// # Panic
+    /// Panics if waiting would deadlock
+    #[cfg(test)]
+    pub fn upgrade(self) -> LockWrite<'a, T> {
+        self.checked_upgrade().unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Waits for the other reads to be released, then turns this read into a write. If waiting
+    /// would deadlock, the read is released.
+    pub fn checked_upgrade(self) -> LockResult<LockWrite<'a, T>> {
+        self.upgrade_until(None)
+    }
+
+    /// Like [checked_upgrade](LockUpgradeableRead::checked_upgrade), but gives up after waiting
+    /// for the duration, releasing the read
+    pub fn try_upgrade_for(self, duration: Duration) -> LockResult<LockWrite<'a, T>> {
+        self.upgrade_until(Some(Instant::now() + duration))
+    }
+
+    fn upgrade_until(self, deadline: Option<Instant>) -> LockResult<LockWrite<'a, T>> {
+        let lock = self.0;
+        lock.core.acquire(
+            Waiting::Upgrade,
+            deadline,
+            |state, _| state.can_upgrade(),
+            |state, thread| {
+                state.upgradeable = None;
+                state.writer = Some(thread);
+            },
+        )?;
+        // the read became the write, so it isn't released
+        std::mem::forget(self);
+        Ok(LockWrite(lock))
+    }
+}
+
+impl<T> Deref for LockUpgradeableRead<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // Safety: no write is held while the upgradeable read is
+        unsafe { &*self.0.value.get() }
+    }
+}
+
+impl<T> Drop for LockUpgradeableRead<'_, T> {
+    fn drop(&mut self) {
+        self.0.state().upgradeable = None;
+        self.0.core.released.notify_all();
     }
 }
 
 /// Prevents read locks from being formed, and no other write lock can be made
-pub struct LockWrite<'a>(&'a Lock);
+pub struct LockWrite<'a, T = ()>(&'a Lock<T>);
 
-impl Drop for LockWrite<'_> {
+impl<T> Deref for LockWrite<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // Safety: nothing else is held while the write is
+        unsafe { &*self.0.value.get() }
+    }
+}
+
+impl<T> DerefMut for LockWrite<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        // Safety: nothing else is held while the write is
+        unsafe { &mut *self.0.value.get() }
+    }
+}
+
+impl<T> Drop for LockWrite<'_, T> {
     fn drop(&mut self) {
-        self.0.write.store(false, Ordering::Release)
+        self.0.state().writer = None;
+        self.0.core.released.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Barrier;
+    use std::thread;
+
+    #[test]
+    fn writes_exclude_reads() {
+        let lock: Lock = Lock::default();
+        let read = lock.read();
+        assert!(lock.try_write().is_none());
+        let another = read.clone();
+        drop(read);
+        assert!(lock.try_write().is_none());
+        drop(another);
+        let write = lock.try_write().unwrap();
+        assert!(lock.try_read().is_none());
+        assert!(lock.try_write().is_none());
+        drop(write);
+        assert!(lock.try_read().is_some());
+    }
+
+    #[test]
+    fn guards_reach_the_value() {
+        let lock = Lock::new(vec![1]);
+        lock.write().push(2);
+        let upgradeable = lock.upgradeable_read();
+        assert_eq!(*upgradeable, vec![1, 2]);
+        upgradeable.upgrade().push(3);
+        assert_eq!(*lock.read(), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn blocked_writers_are_woken() {
+        let lock: Lock = Lock::default();
+        let counter = AtomicUsize::new(0);
+        thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    for _ in 0..100 {
+                        let _write = lock.write();
+                        let value = counter.load(Ordering::Relaxed);
+                        counter.store(value + 1, Ordering::Relaxed);
+                    }
+                });
+                scope.spawn(|| {
+                    for _ in 0..100 {
+                        let _read = lock.read();
+                    }
+                });
+            }
+        });
+        assert_eq!(counter.load(Ordering::Relaxed), 400);
+    }
+
+    #[test]
+    fn nested_reads_pass_waiting_writers() {
+        let lock: Lock = Lock::default();
+        let read = lock.read();
+        thread::scope(|scope| {
+            let writer = scope.spawn(|| {
+                let _write = lock.write();
+            });
+            while !lock.state().writers_waiting() {
+                thread::yield_now();
+            }
+            // other threads wait for the writer, but this one already holds a read
+            let nested = lock.read();
+            thread::scope(|inner| {
+                inner.spawn(|| assert!(lock.try_read().is_none()));
+            });
+            drop(nested);
+            drop(read);
+            writer.join().unwrap();
+        });
+        assert!(lock.try_write().is_some());
+    }
+
+    #[test]
+    fn deadlocks_are_detected() {
+        let first: Lock = Lock::default();
+        let second: Lock = Lock::default();
+        let barrier = Barrier::new(2);
+        let results = thread::scope(|scope| {
+            let one = scope.spawn(|| {
+                let _held = first.write();
+                barrier.wait();
+                second.checked_write().map(|_| ())
+            });
+            let two = scope.spawn(|| {
+                let _held = second.write();
+                barrier.wait();
+                first.checked_write().map(|_| ())
+            });
+            vec![one.join().unwrap(), two.join().unwrap()]
+        });
+        assert!(results.contains(&Ok(())));
+        assert!(results.contains(&Err(LockError::Deadlock)));
+    }
+
+    #[test]
+    fn waiting_on_own_read_is_a_deadlock() {
+        let lock: Lock = Lock::default();
+        let _read = lock.read();
+        assert_eq!(lock.checked_write().err(), Some(LockError::Deadlock));
+        assert!(lock.try_read().is_some());
+    }
+
+    #[test]
+    fn timeouts() {
+        let lock: Lock = Lock::default();
+        let write = lock.write();
+        thread::scope(|scope| {
+            scope.spawn(|| {
+                let limit = Duration::from_millis(20);
+                assert_eq!(lock.try_read_for(limit).err(), Some(LockError::Timeout));
+                assert_eq!(lock.try_write_for(limit).err(), Some(LockError::Timeout));
+            });
+        });
+        drop(write);
+        let read = lock.try_read_for(Duration::from_millis(20)).unwrap();
+        thread::scope(|scope| {
+            scope.spawn(|| {
+                assert!(lock.try_read_for(Duration::from_millis(20)).is_ok());
+                assert_eq!(
+                    lock.try_write_for(Duration::from_millis(20)).err(),
+                    Some(LockError::Timeout)
+                );
+            });
+        });
+        drop(read);
+        assert!(lock.try_write_for(Duration::from_millis(20)).is_ok());
+    }
+
+    #[test]
+    fn upgradeable_reads() {
+        let lock: Lock = Lock::default();
+        let upgradeable = lock.upgradeable_read();
+        let read = lock.try_read().unwrap();
+        assert!(lock.try_write().is_none());
+        assert!(lock.try_read().is_some());
+        thread::scope(|scope| {
+            let upgrading = scope.spawn(|| {
+                let _write = upgradeable.upgrade();
+            });
+            thread::sleep(Duration::from_millis(20));
+            // the waiting upgrade holds back reads of threads that don't hold one yet
+            scope
+                .spawn(|| assert!(lock.try_read().is_none()))
+                .join()
+                .unwrap();
+            assert!(lock.try_read().is_some());
+            drop(read);
+            upgrading.join().unwrap();
+        });
+        assert!(lock.try_write().is_some());
+
+        let upgradeable = lock.upgradeable_read();
+        let _read = lock.read();
+        assert_eq!(
+            upgradeable.checked_upgrade().err(),
+            Some(LockError::Deadlock)
+        );
+        assert!(lock.try_read().is_some());
     }
 }
@@ -3,27 +3,46 @@ use std::collections::HashMap;
 use std::error::Error;
 use std::fmt::{Debug, Display, Formatter};
 use std::hash::{Hash, Hasher};
+use std::io;
+use std::path::PathBuf;
+use std::time::Duration;
 
 use num_bigint::BigUint;
+use rad_db_types::serialization::serialize_values_with;
 
 pub use extendible_hashing::{BlockIterator, StoredTupleIterator};
+pub use lock::{LockError, LockResult};
 
+use crate::constraint::ConstraintViolation;
 use crate::identifier::Identifier;
 use crate::key::primary::{PrimaryKey, PrimaryKeyDefinition};
+use crate::relations::quota::{Quota, QuotaUsage};
 use crate::relations::tuple_storage::extendible_hashing::BlockDirectory;
 use crate::relations::RelationDefinition;
-use crate::tuple::Tuple;
+use crate::tuple::{FieldTypeError, Tuple};
 use crate::Rename;
 
 mod block;
+mod dictionary;
 mod extendible_hashing;
 mod lock;
+pub(crate) mod prefetch;
 
 /// When a tuple couldn't be inserted for some reason
 #[derive(Debug)]
 pub enum TupleInsertionError {
     PrimaryKeyPresent,
-    IncorrectTypes(Vec<usize>),
+    IncorrectTypes(Vec<FieldTypeError>),
+    ConstraintViolations(Vec<ConstraintViolation>),
+    /// Storing the tuple would take the relation past its quota, to the usage given
+    QuotaExceeded {
+        usage: QuotaUsage,
+        quota: Quota,
+    },
+    /// A blob of the tuple couldn't be stored out of line
+    BlobNotStored(std::io::Error),
+    /// The blocks couldn't be locked to store the tuple
+    Lock(LockError),
 }
 
 impl Display for TupleInsertionError {
@@ -33,10 +52,38 @@ impl Display for TupleInsertionError {
                 write!(f, "Couldn't insert tuple, primary key already present")
             }
             TupleInsertionError::IncorrectTypes(vec) => {
-                write!(f, "Invalid types at indexes {:?}", vec)
+                write!(f, "Invalid tuple: ")?;
+                write_all(f, vec)
             }
+            TupleInsertionError::ConstraintViolations(vec) => {
+                write!(f, "Constraints violated: ")?;
+                write_all(f, vec)
+            }
+            TupleInsertionError::QuotaExceeded { usage, .. } => {
+                write!(f, "Couldn't insert tuple, quota exceeded with {}", usage)
+            }
+            TupleInsertionError::BlobNotStored(e) => {
+                write!(f, "Couldn't insert tuple, a blob couldn't be stored: {}", e)
+            }
+            TupleInsertionError::Lock(e) => write!(f, "Couldn't insert tuple: {}", e),
+        }
+    }
+}
+
+impl From<LockError> for TupleInsertionError {
+    fn from(e: LockError) -> Self {
+        TupleInsertionError::Lock(e)
+    }
+}
+
+fn write_all<T: Display>(f: &mut Formatter<'_>, values: &[T]) -> std::fmt::Result {
+    for (i, value) in values.iter().enumerate() {
+        if i > 0 {
+            write!(f, ", ")?;
         }
+        write!(f, "{}", value)?;
     }
+    Ok(())
 }
 
 impl Error for TupleInsertionError {}
@@ -49,6 +96,7 @@ pub struct TupleStorage {
     relation: RelationDefinition,
     primary_key_definition: PrimaryKeyDefinition,
     true_storage: BlockDirectory,
+    volatile: bool,
 }
 
 impl TupleStorage {
@@ -68,6 +116,7 @@ impl TupleStorage {
                 max_size,
                 primary_key_definition,
             ),
+            volatile: false,
         }
     }
 
@@ -87,6 +136,7 @@ impl TupleStorage {
                 max_size,
                 primary_key_definition,
             ),
+            volatile: true,
         }
     }
 
@@ -99,19 +149,193 @@ impl TupleStorage {
         )
     }
 
+    /// Gets the directory the block files are stored in, or `None` if the storage only lasts for
+    /// as long as the program runs
+    pub fn directory(&self) -> Option<PathBuf> {
+        if self.volatile {
+            return None;
+        }
+        let mut directory = PathBuf::from("DB_STORAGE");
+        for name in &self.identifier {
+            directory.push(name);
+        }
+        Some(directory)
+    }
+
+    /// Gets the definition of the tuples in the storage medium
+    pub fn relation(&self) -> &RelationDefinition {
+        &self.relation
+    }
+
     /// Insert an entire tuple into the storage medium
     pub fn insert(&mut self, tuple: Tuple) -> InsertionResult<Option<Tuple>> {
         let hash = self.hash_tuple(&tuple);
-        let result = Ok(self.true_storage.insert(tuple, hash));
-        //println!("{:#?}", self.true_storage);
-        result
+        self.insert_hashed(tuple, hash)
+    }
+
+    /// Inserts a tuple whose primary key has already been hashed. Other threads can insert and
+    /// read at the same time.
+    pub(crate) fn insert_hashed(
+        &self,
+        tuple: Tuple,
+        hash: BigUint,
+    ) -> InsertionResult<Option<Tuple>> {
+        Ok(self.true_storage.insert(tuple, hash)?)
     }
+
+    /// Undoes an insert of a tuple whose primary key hashed to the hash, putting back the tuple
+    /// it replaced, or removing it if it didn't replace one
+    pub(crate) fn restore_hashed(&self, hash: BigUint, replaced: Option<Tuple>) {
+        self.true_storage.restore(hash, replaced)
+    }
+
     pub fn remove(&mut self, primary_key: PrimaryKey<'_>) -> Result<Tuple, ()> {
         unimplemented!()
     }
 
-    pub fn find_by_primary(&self, primary_key: PrimaryKey<'_>) -> Result<&Tuple, ()> {
-        unimplemented!()
+    /// Finds the tuple with the primary key, only reading the block the key hashes into
+    pub fn find_by_primary(&self, primary_key: PrimaryKey<'_>) -> Option<Tuple> {
+        self.find_by_hash(primary_key.hash())
+    }
+
+    /// Finds the tuple whose primary key has the hash, only reading the block the hash is in
+    pub fn find_by_hash(&self, hash: BigUint) -> Option<Tuple> {
+        self.true_storage.find(hash)
+    }
+
+    /// The number of blocks the tuples are stored in
+    pub fn block_count(&self) -> usize {
+        self.true_storage.bucket_count()
+    }
+
+    /// The most tuples a block can hold
+    pub fn bucket_size(&self) -> usize {
+        self.true_storage.bucket_size()
+    }
+
+    /// How long inserts and fallible scans wait for other threads to finish with the blocks, or
+    /// `None` if they wait for as long as it takes
+    pub fn lock_timeout(&self) -> Option<Duration> {
+        self.true_storage.lock_timeout()
+    }
+
+    /// Limits how long inserts and fallible scans wait for other threads to finish with the
+    /// blocks before failing with [LockError::Timeout]
+    pub fn set_lock_timeout(&mut self, timeout: Option<Duration>) {
+        self.true_storage.set_lock_timeout(timeout);
+    }
+
+    /// Writes every loaded block that was changed since it was last written to its file, keeping
+    /// it loaded, and returns how many blocks were written
+    pub fn flush(&self) -> usize {
+        let directory = self.true_storage.directory();
+        directory
+            .buckets()
+            .iter()
+            .filter(|bucket| bucket.flush())
+            .count()
+    }
+
+    /// Writes every changed block like [flush](TupleStorage::flush), stopping at the first block
+    /// that can't be written
+    pub fn try_flush(&self) -> io::Result<usize> {
+        let directory = self.true_storage.directory();
+        let mut flushed = 0;
+        for bucket in directory.buckets() {
+            if bucket.try_flush()? {
+                flushed += 1;
+            }
+        }
+        Ok(flushed)
+    }
+
+    /// Merges the log of every block into the file of the block, and returns how many blocks had
+    /// a log to merge
+    pub fn compact(&self) -> usize {
+        let directory = self.true_storage.directory();
+        directory
+            .buckets()
+            .iter()
+            .filter(|bucket| bucket.compact())
+            .count()
+    }
+
+    /// Merges the log of every block like [compact](TupleStorage::compact), stopping at the first
+    /// block whose file can't be written
+    pub fn try_compact(&self) -> io::Result<usize> {
+        let directory = self.true_storage.directory();
+        let mut compacted = 0;
+        for bucket in directory.buckets() {
+            if bucket.try_compact()? {
+                compacted += 1;
+            }
+        }
+        Ok(compacted)
+    }
+
+    /// Copies every tuple into the other storage, returning how many were copied. When both
+    /// storages hash keys the same way, each block is read once and its tuples are copied with
+    /// the hashes they're stored under. Otherwise every tuple is hashed again as it's copied.
+    pub fn copy_into(&self, other: &mut TupleStorage) -> InsertionResult<usize> {
+        if self.primary_key_definition != other.primary_key_definition {
+            let mut copied = 0;
+            for tuple in self.all_tuples() {
+                let hash = other.hash_tuple(&tuple);
+                other.insert_hashed(tuple, hash)?;
+                copied += 1;
+            }
+            return Ok(copied);
+        }
+        let directory = self.true_storage.directory();
+        let mut copied = 0;
+        for bucket in directory.buckets() {
+            let contents = bucket.get_contents();
+            for (hash, tuple) in contents.all_with_key() {
+                other.insert_hashed(tuple.clone(), hash.clone())?;
+                copied += 1;
+            }
+        }
+        Ok(copied)
+    }
+
+    /// Rebuilds the blocks so that each holds up to `bucket_size` tuples. Every tuple is read
+    /// into memory while the blocks are rebuilt.
+    pub fn resize_buckets(&mut self, bucket_size: usize) {
+        let tuples: Vec<(BigUint, Tuple)> = self
+            .all_tuples()
+            .map(|tuple| (self.hash_tuple(&tuple), tuple))
+            .collect();
+        let blocks = self.block_count();
+        let mut true_storage = if self.volatile {
+            BlockDirectory::new_volatile(
+                self.identifier.clone(),
+                self.relation.clone(),
+                bucket_size,
+                self.primary_key_definition.clone(),
+            )
+        } else {
+            BlockDirectory::new(
+                self.identifier.clone(),
+                self.relation.clone(),
+                bucket_size,
+                self.primary_key_definition.clone(),
+            )
+        };
+        true_storage.set_lock_timeout(self.lock_timeout());
+        // the old blocks write themselves out as they're dropped, so their files are removed
+        // afterwards, before new blocks with the same numbers can read them
+        std::mem::drop(std::mem::replace(&mut self.true_storage, true_storage));
+        if let Some(directory) = self.directory() {
+            for block in 0..blocks {
+                let _ = std::fs::remove_file(directory.join(block::file_name_of(block)));
+                let _ = std::fs::remove_file(directory.join(block::log_name_of(block)));
+            }
+        }
+        for (hash, tuple) in tuples {
+            self.true_storage
+                .insert(tuple, hash)
+                .expect("no other thread can reach the new blocks yet");
+        }
     }
     /// Gets a [StoredTupleIterator] for the tuple storage
     ///
@@ -120,6 +344,14 @@ impl TupleStorage {
         (&self.true_storage).into_iter()
     }
 
+    /// Gets a [StoredTupleIterator] for the tuple storage, unless waiting for other threads to
+    /// finish with the blocks would deadlock or take longer than the lock timeout
+    ///
+    /// [StoredTupleIterator]: StoredTupleIterator
+    pub fn try_all_tuples(&self) -> LockResult<StoredTupleIterator<'_>> {
+        self.true_storage.try_tuples()
+    }
+
     /// Gets a [BlockIterator] for the tuple storage
     ///
     /// [BlockIterator]: self::BlockIterator
@@ -127,6 +359,22 @@ impl TupleStorage {
         (&self.true_storage).blocks()
     }
 
+    /// Gets a [BlockIterator] for the tuple storage, unless waiting for other threads to finish
+    /// with the blocks would deadlock or take longer than the lock timeout
+    ///
+    /// [BlockIterator]: self::BlockIterator
+    pub fn try_blocks(&self) -> LockResult<BlockIterator<'_>> {
+        self.true_storage.try_blocks()
+    }
+
+    /// The number of bytes the tuple takes up in a block file
+    pub fn stored_size(&self, tuple: &Tuple) -> u64 {
+        let hash = self.hash_tuple(tuple).to_string();
+        let values = serialize_values_with(tuple.iter().cloned(), self.relation.text_format());
+        // the hash and values are separated by a colon, and followed by a newline
+        (hash.len() + values.len() + 2) as u64
+    }
+
     pub fn hash_tuple(&self, tuple: &Tuple) -> BigUint {
         let primary_key = self.get_primary_key_of_tuple(tuple);
         primary_key.hash()
@@ -137,14 +385,7 @@ impl TupleStorage {
     }
 
     fn get_primary_key_of_tuple<'a>(&self, tuple: &'a Tuple) -> PrimaryKey<'a> {
-        let definition = self.get_primary_key_definition();
-        let ret = tuple
-            .iter()
-            .enumerate()
-            .filter(|(pos, _)| definition.contains(pos))
-            .map(|(_, val)| val)
-            .collect();
-        PrimaryKey::new(ret, definition.create_seeds())
+        self.get_primary_key_definition().key_of(tuple)
     }
 
     pub(crate) fn len(&self) -> usize {
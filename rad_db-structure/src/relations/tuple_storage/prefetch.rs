@@ -0,0 +1,220 @@
+//! Reads block files ahead of sequential scans.
+//!
+//! When the blocks of a relation are loaded one after another in order, the next few block
+//! files are read on a background thread, so that reading them overlaps with processing the
+//! blocks before them instead of alternating with it.
+
+use std::collections::{HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock, Weak};
+
+use crate::memory::{MemoryBudget, Reservation};
+use crate::settings::{Settings, READ_AHEAD_BLOCKS};
+
+/// How many blocks past the last loaded block are read ahead, unless
+/// [READ_AHEAD_BLOCKS] is set
+pub const READ_AHEAD: usize = 4;
+/// How many blocks have to be loaded in order before a scan is treated as sequential
+pub const SEQUENTIAL_LOADS: usize = 2;
+/// The most block files that are kept in memory waiting to be loaded
+const MAX_PREFETCHED: usize = 64;
+
+#[derive(Default)]
+struct State {
+    /// The scans of each directory of block files
+    scans: HashMap<PathBuf, Scan>,
+    prefetched: HashMap<PathBuf, (Vec<u8>, Reservation<'static>)>,
+    /// The order files were prefetched in, so that the oldest is dropped first
+    order: VecDeque<PathBuf>,
+    /// Changed whenever a file is written, so that reads that overlap a write are discarded
+    generations: HashMap<PathBuf, u64>,
+}
+
+struct Scan {
+    last: usize,
+    sequential: usize,
+    requested_to: usize,
+}
+
+/// Detects sequential scans and reads the blocks they are about to load
+pub struct ReadAhead {
+    state: Arc<Mutex<State>>,
+    requests: Mutex<Sender<(PathBuf, u64)>>,
+}
+
+impl ReadAhead {
+    /// Creates a read-ahead with its own thread to read files on
+    pub fn new() -> Self {
+        let state = Arc::new(Mutex::new(State::default()));
+        let (sender, receiver) = mpsc::channel::<(PathBuf, u64)>();
+        let evicted: Weak<Mutex<State>> = Arc::downgrade(&state);
+        MemoryBudget::global().add_evictor(move |needed| match evicted.upgrade() {
+            Some(state) => Self::evict(&state, needed),
+            None => 0,
+        });
+        let worker_state = state.clone();
+        std::thread::Builder::new()
+            .name("read-ahead".to_string())
+            .spawn(move || {
+                for (path, generation) in receiver {
+                    if let Ok(bytes) = std::fs::read(&path) {
+                        Self::finish(&worker_state, path, generation, bytes);
+                    }
+                }
+            })
+            .expect("Couldn't start the read-ahead thread");
+        ReadAhead {
+            state,
+            requests: Mutex::new(sender),
+        }
+    }
+
+    /// The read-ahead shared by every block
+    pub fn global() -> &'static ReadAhead {
+        static GLOBAL: OnceLock<ReadAhead> = OnceLock::new();
+        GLOBAL.get_or_init(ReadAhead::new)
+    }
+
+    fn lock(state: &Mutex<State>) -> MutexGuard<'_, State> {
+        state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn finish(state: &Mutex<State>, path: PathBuf, generation: u64, bytes: Vec<u8>) {
+        // reads that don't fit in the memory budget are dropped, and the block is read when
+        // it's loaded instead
+        let reservation = match MemoryBudget::global().reserve(bytes.len()) {
+            Ok(reservation) => reservation,
+            Err(_) => return,
+        };
+        let mut state = Self::lock(state);
+        if state.generations.get(&path).copied().unwrap_or(0) != generation {
+            return;
+        }
+        if state.prefetched.len() >= MAX_PREFETCHED {
+            if let Some(oldest) = state.order.pop_front() {
+                state.prefetched.remove(&oldest);
+            }
+        }
+        state.order.push_back(path.clone());
+        state.prefetched.insert(path, (bytes, reservation));
+    }
+
+    /// Drops the oldest reads until the bytes are freed, returning how many bytes were freed
+    fn evict(state: &Mutex<State>, needed: usize) -> usize {
+        let mut state = Self::lock(state);
+        let mut freed = 0;
+        while freed < needed {
+            let oldest = match state.order.pop_front() {
+                Some(oldest) => oldest,
+                None => break,
+            };
+            if let Some((_, reservation)) = state.prefetched.remove(&oldest) {
+                freed += reservation.bytes();
+            }
+        }
+        freed
+    }
+
+    /// Notes that a block in the directory was loaded, reading the blocks after it if the
+    /// directory is being scanned sequentially
+    pub fn loaded(&self, directory: &Path, block_num: usize) {
+        let mut state = Self::lock(&self.state);
+        let scan = state.scans.entry(directory.to_path_buf()).or_insert(Scan {
+            last: block_num,
+            sequential: 0,
+            requested_to: block_num,
+        });
+        if block_num == scan.last + 1 {
+            scan.sequential += 1;
+        } else if block_num != scan.last {
+            scan.sequential = 0;
+            scan.requested_to = block_num;
+        }
+        scan.last = block_num;
+        if scan.sequential < SEQUENTIAL_LOADS {
+            return;
+        }
+
+        let start = scan.requested_to.max(block_num) + 1;
+        let end = block_num + Settings::global().usize(READ_AHEAD_BLOCKS);
+        scan.requested_to = scan.requested_to.max(end);
+        let requests = self.requests.lock().unwrap_or_else(|e| e.into_inner());
+        for next in start..=end {
+            let path = directory.join(format!("block_{}.txt", next));
+            if !path.exists() {
+                break;
+            }
+            let generation = state.generations.get(&path).copied().unwrap_or(0);
+            if requests.send((path, generation)).is_err() {
+                break;
+            }
+        }
+    }
+
+    /// Takes the contents of a file if it has been read ahead
+    pub fn take(&self, path: &Path) -> Option<Vec<u8>> {
+        let mut state = Self::lock(&self.state);
+        let (bytes, _) = state.prefetched.remove(path)?;
+        state.order.retain(|prefetched| prefetched != path);
+        Some(bytes)
+    }
+
+    /// Discards what has been read of a file, which has to be done whenever the file is written
+    pub fn invalidate(&self, path: &Path) {
+        let mut state = Self::lock(&self.state);
+        *state.generations.entry(path.to_path_buf()).or_insert(0) += 1;
+        if state.prefetched.remove(path).is_some() {
+            state.order.retain(|prefetched| prefetched != path);
+        }
+    }
+}
+
+impl Default for ReadAhead {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::*;
+
+    fn wait_for(read_ahead: &ReadAhead, path: &Path) -> Option<Vec<u8>> {
+        let start = Instant::now();
+        while start.elapsed() < Duration::from_secs(5) {
+            if let Some(bytes) = read_ahead.take(path) {
+                return Some(bytes);
+            }
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        None
+    }
+
+    #[test]
+    fn sequential_loads_read_ahead() {
+        let directory = std::env::temp_dir().join(format!("read_ahead_{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let block = |n: usize| directory.join(format!("block_{}.txt", n));
+        for n in 0..10 {
+            std::fs::write(block(n), format!("{}", n)).unwrap();
+        }
+
+        let read_ahead = ReadAhead::new();
+        read_ahead.loaded(&directory, 5);
+        read_ahead.loaded(&directory, 0);
+        read_ahead.loaded(&directory, 1);
+        assert!(read_ahead.take(&block(2)).is_none());
+        read_ahead.loaded(&directory, 2);
+        assert_eq!(wait_for(&read_ahead, &block(3)), Some(b"3".to_vec()));
+        assert_eq!(wait_for(&read_ahead, &block(6)), Some(b"6".to_vec()));
+
+        wait_for(&read_ahead, &block(4)).unwrap();
+        read_ahead.invalidate(&block(5));
+        assert!(read_ahead.take(&block(5)).is_none());
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
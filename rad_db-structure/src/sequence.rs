@@ -0,0 +1,319 @@
+//! Sequences hand out numbers that are never handed out twice, for keys that don't come from
+//! the data itself.
+//!
+//! A persistent sequence is stored in the directory of its name, and keeps going where it left
+//! off when it's opened again. Writing the sequence for every number would make each number
+//! cost a write, so a sequence reserves numbers ahead of time and only writes the last number
+//! it reserved. Opening a sequence continues after its reserved numbers, so the numbers that
+//! were reserved but not handed out before a restart are skipped.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use crate::identifier::Identifier;
+use crate::temp::directory_of;
+
+/// The file a sequence is stored in, within the directory of its name
+const SEQUENCE_FILE: &str = "SEQUENCE";
+
+/// How many numbers are reserved with each write
+const RESERVED_NUMBERS: i64 = 32;
+
+/// Why a sequence couldn't be used
+#[derive(Debug)]
+pub enum SequenceError {
+    /// The next number of the sequence doesn't fit in an `i64`
+    Exhausted(Identifier),
+    /// A sequence with the name already exists
+    AlreadyExists(Identifier),
+    /// The stored sequence couldn't be read or written
+    Io(io::Error),
+}
+
+impl Display for SequenceError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SequenceError::Exhausted(name) => write!(f, "Sequence {} is exhausted", name),
+            SequenceError::AlreadyExists(name) => write!(f, "Sequence {} already exists", name),
+            SequenceError::Io(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl Error for SequenceError {}
+
+impl From<io::Error> for SequenceError {
+    fn from(error: io::Error) -> Self {
+        SequenceError::Io(error)
+    }
+}
+
+/// Numbers starting at `start` and going up, or down, by `step`
+#[derive(Debug)]
+pub struct Sequence {
+    name: Identifier,
+    start: i64,
+    step: i64,
+    /// The last number handed out
+    current: Option<i64>,
+    /// The last number that's been reserved, which is only stored for persistent sequences
+    reserved: Option<i64>,
+    persistent: bool,
+}
+
+impl Sequence {
+    /// Creates a sequence that is stored in the file system
+    ///
+    /// # Panic
+    /// Panics if the step is 0
+    pub fn new<I: Into<Identifier>>(name: I, start: i64, step: i64) -> Result<Self, SequenceError> {
+        let name = name.into();
+        if Self::file_of(&name).exists() {
+            return Err(SequenceError::AlreadyExists(name));
+        }
+        let mut sequence = Self::new_volatile(name, start, step);
+        sequence.persistent = true;
+        sequence.write()?;
+        Ok(sequence)
+    }
+
+    /// Creates a sequence that only lasts for as long as the program runs
+    ///
+    /// # Panic
+    /// Panics if the step is 0
+    pub fn new_volatile<I: Into<Identifier>>(name: I, start: i64, step: i64) -> Self {
+        assert_ne!(step, 0, "A sequence must have a step");
+        Sequence {
+            name: name.into(),
+            start,
+            step,
+            current: None,
+            reserved: None,
+            persistent: false,
+        }
+    }
+
+    /// Opens a sequence stored in the file system, continuing after every number it reserved
+    pub fn open<I: Into<Identifier>>(name: I) -> Result<Self, SequenceError> {
+        let name = name.into();
+        let contents = fs::read_to_string(Self::file_of(&name))?;
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, "Invalid sequence file");
+        let numbers = contents
+            .split_whitespace()
+            .map(|number| number.parse::<i64>().map_err(|_| invalid()))
+            .collect::<Result<Vec<_>, _>>()?;
+        let (start, step, reserved) = match numbers[..] {
+            [start, step] if step != 0 => (start, step, None),
+            [start, step, reserved] if step != 0 => (start, step, Some(reserved)),
+            _ => return Err(invalid().into()),
+        };
+        Ok(Sequence {
+            name,
+            start,
+            step,
+            current: reserved,
+            reserved,
+            persistent: true,
+        })
+    }
+
+    pub fn name(&self) -> &Identifier {
+        &self.name
+    }
+
+    pub fn start(&self) -> i64 {
+        self.start
+    }
+
+    pub fn step(&self) -> i64 {
+        self.step
+    }
+
+    /// The last number handed out, if any has been
+    pub fn current(&self) -> Option<i64> {
+        self.current
+    }
+
+    /// Hands out the next number of the sequence
+    pub fn next_value(&mut self) -> Result<i64, SequenceError> {
+        let next = match self.current {
+            None => self.start,
+            Some(current) => current
+                .checked_add(self.step)
+                .ok_or_else(|| SequenceError::Exhausted(self.name.clone()))?,
+        };
+        if self.persistent && !self.is_reserved(next) {
+            // reserving past the end of the numbers just reserves up to the end
+            let reserved = (1..RESERVED_NUMBERS)
+                .rev()
+                .find_map(|count| next.checked_add(self.step * count))
+                .unwrap_or(next);
+            self.reserved = Some(reserved);
+            self.write()?;
+        }
+        self.current = Some(next);
+        Ok(next)
+    }
+
+    fn is_reserved(&self, number: i64) -> bool {
+        match self.reserved {
+            None => false,
+            Some(reserved) if self.step > 0 => number <= reserved,
+            Some(reserved) => number >= reserved,
+        }
+    }
+
+    fn file_of(name: &Identifier) -> PathBuf {
+        directory_of(name).join(SEQUENCE_FILE)
+    }
+
+    /// Stores the sequence, replacing the stored sequence all at once so that a crash can't
+    /// leave half of it written
+    fn write(&self) -> io::Result<()> {
+        let file = Self::file_of(&self.name);
+        let directory = file
+            .parent()
+            .expect("A sequence file is within a directory");
+        fs::create_dir_all(directory)?;
+        let mut contents = format!("{} {}", self.start, self.step);
+        if let Some(reserved) = self.reserved {
+            contents.push_str(&format!(" {}", reserved));
+        }
+        contents.push('\n');
+        let temporary = file.with_extension("tmp");
+        fs::write(&temporary, contents)?;
+        fs::rename(temporary, file)
+    }
+
+    /// Removes a stored sequence
+    pub fn remove(self) -> io::Result<()> {
+        if self.persistent {
+            fs::remove_file(Self::file_of(&self.name))?;
+        }
+        Ok(())
+    }
+}
+
+/// The sequences in use, by name
+#[derive(Debug, Default)]
+pub struct SequenceCatalog {
+    sequences: HashMap<Identifier, Sequence>,
+}
+
+impl SequenceCatalog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a sequence that is stored in the file system
+    pub fn create_sequence<I: Into<Identifier>>(
+        &mut self,
+        name: I,
+        start: i64,
+        step: i64,
+    ) -> Result<&mut Sequence, SequenceError> {
+        let name = name.into();
+        if self.sequences.contains_key(&name) {
+            return Err(SequenceError::AlreadyExists(name));
+        }
+        let sequence = Sequence::new(name.clone(), start, step)?;
+        Ok(self.sequences.entry(name).or_insert(sequence))
+    }
+
+    /// Gets a sequence, opening it from the file system if it isn't in use yet
+    pub fn sequence<I: Into<Identifier>>(
+        &mut self,
+        name: I,
+    ) -> Result<&mut Sequence, SequenceError> {
+        let name = name.into();
+        if !self.sequences.contains_key(&name) {
+            let sequence = Sequence::open(name.clone())?;
+            self.sequences.insert(name.clone(), sequence);
+        }
+        Ok(self.sequences.get_mut(&name).unwrap())
+    }
+
+    /// Hands out the next number of a sequence
+    pub fn next_value<I: Into<Identifier>>(&mut self, name: I) -> Result<i64, SequenceError> {
+        self.sequence(name)?.next_value()
+    }
+
+    /// Removes a sequence, including its stored copy, returning whether there was one
+    pub fn drop_sequence<I: Into<Identifier>>(&mut self, name: I) -> Result<bool, SequenceError> {
+        let name = name.into();
+        let sequence = match self.sequences.remove(&name) {
+            Some(sequence) => sequence,
+            None => match Sequence::open(name) {
+                Ok(sequence) => sequence,
+                Err(SequenceError::Io(error)) if error.kind() == io::ErrorKind::NotFound => {
+                    return Ok(false)
+                }
+                Err(error) => return Err(error),
+            },
+        };
+        sequence.remove()?;
+        Ok(true)
+    }
+
+    pub fn len(&self) -> usize {
+        self.sequences.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sequences.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::temp::unique_identifier;
+
+    #[test]
+    fn volatile_sequences() {
+        let mut sequence = Sequence::new_volatile("ids", 10, -3);
+        assert_eq!(sequence.current(), None);
+        let numbers: Vec<i64> = (0..4).map(|_| sequence.next_value().unwrap()).collect();
+        assert_eq!(numbers, vec![10, 7, 4, 1]);
+        assert_eq!(sequence.current(), Some(1));
+
+        let mut sequence = Sequence::new_volatile("ids", i64::MAX - 1, 1);
+        assert_eq!(sequence.next_value().unwrap(), i64::MAX - 1);
+        assert_eq!(sequence.next_value().unwrap(), i64::MAX);
+        assert!(matches!(
+            sequence.next_value(),
+            Err(SequenceError::Exhausted(_))
+        ));
+    }
+
+    #[test]
+    fn sequences_survive_restarts() {
+        let name = unique_identifier("orders_seq");
+        let mut catalog = SequenceCatalog::new();
+        catalog.create_sequence(name.clone(), 1, 2).unwrap();
+        assert!(matches!(
+            catalog.create_sequence(name.clone(), 1, 2),
+            Err(SequenceError::AlreadyExists(_))
+        ));
+        assert_eq!(catalog.next_value(name.clone()).unwrap(), 1);
+        assert_eq!(catalog.next_value(name.clone()).unwrap(), 3);
+
+        // a new catalog opens the stored sequence, which continues after the reserved numbers
+        let mut restarted = SequenceCatalog::new();
+        let next = restarted.next_value(name.clone()).unwrap();
+        assert!(next > 3);
+        assert_eq!((next - 1) % 2, 0);
+        assert!(matches!(
+            restarted.create_sequence(name.clone(), 1, 2),
+            Err(SequenceError::AlreadyExists(_))
+        ));
+
+        assert!(restarted.drop_sequence(name.clone()).unwrap());
+        assert!(!restarted.drop_sequence(name.clone()).unwrap());
+        assert!(Sequence::open(name).is_err());
+    }
+}
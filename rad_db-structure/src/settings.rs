@@ -0,0 +1,369 @@
+//! Configuration variables of the database, such as how many tuples the optimizer samples.
+//!
+//! Every variable is registered with a default value, which is also the kind its values are cast
+//! into, so that `SET optimizer_samples = 1000` stores a number. The [global](Settings::global)
+//! settings are read by the optimizer, the buffer pool and the executor. A
+//! [session](Settings::session) starts as a copy of them, and only the variables scoped to
+//! sessions can be set in one; variables of the whole system can only be set globally.
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::sync::{OnceLock, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use rad_db_types::{CastError, Numeric, Type};
+
+use crate::memory::MemoryBudget;
+use crate::relations::tuple_storage::prefetch::READ_AHEAD;
+
+/// How many tuples of each relation the optimizer samples to estimate selections
+pub const OPTIMIZER_SAMPLES: &str = "optimizer_samples";
+/// How many tuples selections and projections process at a time, or 0 to process them one at a
+/// time
+pub const EXECUTION_BATCH_SIZE: &str = "execution_batch_size";
+/// The most bytes loaded blocks, caches and query operators can use together
+pub const BLOCK_CACHE_BYTES: &str = "block_cache_bytes";
+/// How many blocks past the last loaded block sequential scans read ahead
+pub const READ_AHEAD_BLOCKS: &str = "read_ahead_blocks";
+
+/// Where a variable can be set
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scope {
+    /// The variable is shared by every session, and can only be set globally
+    System,
+    /// Every session can set the variable for itself
+    Session,
+}
+
+/// A registered configuration variable
+#[derive(Debug, Clone, PartialEq)]
+pub struct Variable {
+    name: String,
+    default: Type,
+    scope: Scope,
+    description: String,
+}
+
+impl Variable {
+    /// Creates a variable, whose values are cast into the kind of its default
+    pub fn new<S: AsRef<str>, D: AsRef<str>>(
+        name: S,
+        default: Type,
+        scope: Scope,
+        description: D,
+    ) -> Self {
+        Variable {
+            name: name.as_ref().to_string(),
+            default,
+            scope,
+            description: description.as_ref().to_string(),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn default_value(&self) -> &Type {
+        &self.default
+    }
+
+    pub fn scope(&self) -> Scope {
+        self.scope
+    }
+
+    pub fn description(&self) -> &str {
+        &self.description
+    }
+}
+
+/// Why a variable couldn't be set
+#[derive(Debug, Clone, PartialEq)]
+pub enum SettingError {
+    /// No variable is registered with the name
+    Unknown(String),
+    /// The variable is a system variable, and the settings are a session's
+    SystemOnly(String),
+    /// The value couldn't be cast into the kind of the variable
+    InvalidValue(String, CastError),
+    /// The statement isn't a `SET` or `RESET` statement
+    Unparsable(String),
+}
+
+impl Display for SettingError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SettingError::Unknown(name) => write!(f, "No variable is named {}", name),
+            SettingError::SystemOnly(name) => {
+                write!(f, "{} can only be set for the whole system", name)
+            }
+            SettingError::InvalidValue(name, error) => {
+                write!(f, "Invalid value for {}: {}", name, error)
+            }
+            SettingError::Unparsable(statement) => {
+                write!(f, "Couldn't parse {:?} as a setting", statement)
+            }
+        }
+    }
+}
+
+impl Error for SettingError {}
+
+/// The values of the registered configuration variables
+#[derive(Debug, Clone)]
+pub struct Settings {
+    variables: BTreeMap<String, Variable>,
+    values: BTreeMap<String, Type>,
+    session: bool,
+}
+
+impl Settings {
+    /// Creates settings of the whole system, with the built-in variables at their defaults
+    pub fn new() -> Self {
+        let mut settings = Settings {
+            variables: BTreeMap::new(),
+            values: BTreeMap::new(),
+            session: false,
+        };
+        settings.register(Variable::new(
+            OPTIMIZER_SAMPLES,
+            Type::from(500u64),
+            Scope::Session,
+            "How many tuples of each relation the optimizer samples",
+        ));
+        settings.register(Variable::new(
+            EXECUTION_BATCH_SIZE,
+            Type::from(0u64),
+            Scope::Session,
+            "How many tuples selections and projections process at a time, or 0 for one",
+        ));
+        settings.register(Variable::new(
+            BLOCK_CACHE_BYTES,
+            Type::from(u64::MAX),
+            Scope::System,
+            "The most bytes of memory blocks, caches and queries can use together",
+        ));
+        settings.register(Variable::new(
+            READ_AHEAD_BLOCKS,
+            Type::from(READ_AHEAD as u64),
+            Scope::System,
+            "How many blocks sequential scans read ahead",
+        ));
+        settings
+    }
+
+    /// The settings of the whole system
+    pub fn global() -> RwLockReadGuard<'static, Settings> {
+        Self::global_lock()
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// The settings of the whole system, to change them. Use [execute_global](Self::execute_global)
+    /// for variables that take effect when they're set, like [BLOCK_CACHE_BYTES].
+    pub fn global_mut() -> RwLockWriteGuard<'static, Settings> {
+        Self::global_lock()
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn global_lock() -> &'static RwLock<Settings> {
+        static GLOBAL: OnceLock<RwLock<Settings>> = OnceLock::new();
+        GLOBAL.get_or_init(|| RwLock::new(Settings::new()))
+    }
+
+    /// Executes a `SET` or `RESET` statement on the global settings, and applies the variables
+    /// that take effect when they're set
+    pub fn execute_global(statement: &str) -> Result<(), SettingError> {
+        let mut settings = Self::global_mut();
+        settings.execute(statement)?;
+        MemoryBudget::global().set_limit(settings.usize(BLOCK_CACHE_BYTES));
+        Ok(())
+    }
+
+    /// Settings of a new session, starting with the values of the global settings
+    pub fn session() -> Self {
+        let mut settings = Self::global().clone();
+        settings.session = true;
+        settings
+    }
+
+    pub fn is_session(&self) -> bool {
+        self.session
+    }
+
+    /// Registers a variable, replacing any variable with the same name and its value
+    pub fn register(&mut self, variable: Variable) {
+        self.values.remove(variable.name());
+        self.variables.insert(variable.name().to_string(), variable);
+    }
+
+    pub fn variable(&self, name: &str) -> Option<&Variable> {
+        self.variables.get(name)
+    }
+
+    /// The registered variables, ordered by name
+    pub fn variables(&self) -> impl Iterator<Item = &Variable> {
+        self.variables.values()
+    }
+
+    /// The value of the variable, which is its default unless it was set
+    pub fn get(&self, name: &str) -> Option<&Type> {
+        self.values
+            .get(name)
+            .or_else(|| self.variables.get(name).map(Variable::default_value))
+    }
+
+    /// The value of a variable of unsigned numbers, saturating at `usize::MAX`
+    ///
+    /// # Panic
+    /// Panics if no variable of unsigned numbers is registered with the name
+    pub fn usize(&self, name: &str) -> usize {
+        match self.get(name) {
+            Some(Type::Numeric(Numeric::Unsigned(value))) => {
+                let value: u64 = (*value).into();
+                value.min(usize::MAX as u64) as usize
+            }
+            _ => panic!("{} isn't a variable of unsigned numbers", name),
+        }
+    }
+
+    /// Sets the variable to the value, cast into the kind of the variable
+    pub fn set(&mut self, name: &str, value: Type) -> Result<(), SettingError> {
+        let variable = self.settable(name)?;
+        let value = value
+            .cast_to(variable.default_value())
+            .map_err(|error| SettingError::InvalidValue(name.to_string(), error))?;
+        self.values.insert(name.to_string(), value);
+        Ok(())
+    }
+
+    /// Sets the variable back to its default
+    pub fn reset(&mut self, name: &str) -> Result<(), SettingError> {
+        self.settable(name)?;
+        self.values.remove(name);
+        Ok(())
+    }
+
+    fn settable(&self, name: &str) -> Result<&Variable, SettingError> {
+        let variable = self
+            .variables
+            .get(name)
+            .ok_or_else(|| SettingError::Unknown(name.to_string()))?;
+        if self.session && variable.scope() == Scope::System {
+            return Err(SettingError::SystemOnly(name.to_string()));
+        }
+        Ok(variable)
+    }
+
+    /// Executes a statement of the form `SET name = value`, `SET name TO value` or `RESET name`.
+    /// The value is parsed as text, which may be quoted, and cast into the kind of the variable.
+    pub fn execute(&mut self, statement: &str) -> Result<(), SettingError> {
+        let unparsable = || SettingError::Unparsable(statement.to_string());
+        let statement = statement.trim().trim_end_matches(';');
+        let (keyword, rest) = statement
+            .split_once(char::is_whitespace)
+            .ok_or_else(unparsable)?;
+        let rest = rest.trim();
+        if keyword.eq_ignore_ascii_case("RESET") {
+            return self.reset(rest);
+        }
+        if !keyword.eq_ignore_ascii_case("SET") {
+            return Err(unparsable());
+        }
+        let (name, value) = match rest.split_once('=') {
+            Some((name, value)) => (name.trim(), value.trim()),
+            None => {
+                let mut words = rest.splitn(3, char::is_whitespace);
+                match (words.next(), words.next(), words.next()) {
+                    (Some(name), Some(to), Some(value)) if to.eq_ignore_ascii_case("TO") => {
+                        (name, value.trim())
+                    }
+                    _ => return Err(unparsable()),
+                }
+            }
+        };
+        if name.is_empty() || value.is_empty() {
+            return Err(unparsable());
+        }
+        if value.eq_ignore_ascii_case("DEFAULT") {
+            return self.reset(name);
+        }
+        let value = value
+            .strip_prefix('\'')
+            .and_then(|value| value.strip_suffix('\''))
+            .unwrap_or(value);
+        let value = match self.variable(name).map(Variable::default_value) {
+            Some(Type::Boolean(_)) => match value.to_ascii_lowercase().as_str() {
+                "true" | "on" => Type::Boolean(true),
+                "false" | "off" => Type::Boolean(false),
+                _ => Type::from(value),
+            },
+            _ => Type::from(value),
+        };
+        self.set(name, value)
+    }
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn set_variables() {
+        let mut settings = Settings::new();
+        assert_eq!(settings.usize(OPTIMIZER_SAMPLES), 500);
+        settings.execute("SET optimizer_samples = 1000;").unwrap();
+        assert_eq!(settings.usize(OPTIMIZER_SAMPLES), 1000);
+        settings
+            .execute("set execution_batch_size to '64'")
+            .unwrap();
+        assert_eq!(settings.usize(EXECUTION_BATCH_SIZE), 64);
+        settings.execute("RESET optimizer_samples").unwrap();
+        assert_eq!(settings.usize(OPTIMIZER_SAMPLES), 500);
+
+        assert_eq!(
+            settings.execute("SET optimizer_samples = lots"),
+            Err(SettingError::InvalidValue(
+                OPTIMIZER_SAMPLES.to_string(),
+                CastError::Unparsable("lots".to_string())
+            ))
+        );
+        assert_eq!(
+            settings.execute("SET nothing = 1"),
+            Err(SettingError::Unknown("nothing".to_string()))
+        );
+        assert!(matches!(
+            settings.execute("SET optimizer_samples"),
+            Err(SettingError::Unparsable(_))
+        ));
+
+        settings.register(Variable::new(
+            "verbose",
+            Type::from(false),
+            Scope::Session,
+            "Whether to explain more",
+        ));
+        settings.execute("SET verbose = on").unwrap();
+        assert_eq!(settings.get("verbose"), Some(&Type::Boolean(true)));
+    }
+
+    #[test]
+    fn sessions_only_set_session_variables() {
+        let mut session = Settings::session();
+        assert!(session.is_session());
+        session.execute("SET optimizer_samples = 20").unwrap();
+        assert_eq!(session.usize(OPTIMIZER_SAMPLES), 20);
+        assert_eq!(Settings::global().usize(OPTIMIZER_SAMPLES), 500);
+        assert_eq!(
+            session.execute("SET block_cache_bytes = 1024"),
+            Err(SettingError::SystemOnly(BLOCK_CACHE_BYTES.to_string()))
+        );
+    }
+}
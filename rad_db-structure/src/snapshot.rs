@@ -0,0 +1,120 @@
+//! Read snapshots see every relation as it was when the snapshot was taken.
+//!
+//! Relations don't keep older versions of their tuples, so a snapshot copies the tuples of
+//! each relation when it is taken. Scans of the snapshot then never see tuples stored after
+//! it, no matter what writers do to the relations afterwards.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use rad_db_types::Type;
+
+use crate::identifier::Identifier;
+use crate::relations::Relation;
+use crate::tuple::Tuple;
+
+/// A single relation as it was when the snapshot was taken
+#[derive(Debug, Clone)]
+pub struct RelationSnapshot {
+    name: Identifier,
+    attributes: Vec<(String, Type)>,
+    tuples: Arc<[Tuple]>,
+}
+
+impl RelationSnapshot {
+    pub fn new(relation: &Relation) -> Self {
+        RelationSnapshot {
+            name: relation.name().clone(),
+            attributes: relation.attributes().clone(),
+            tuples: relation.tuples().collect(),
+        }
+    }
+
+    pub fn name(&self) -> &Identifier {
+        &self.name
+    }
+
+    pub fn attributes(&self) -> &Vec<(String, Type)> {
+        &self.attributes
+    }
+
+    pub fn len(&self) -> usize {
+        self.tuples.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tuples.is_empty()
+    }
+
+    /// Scans the tuples of the relation
+    pub fn tuples(&self) -> impl Iterator<Item = &Tuple> {
+        self.tuples.iter()
+    }
+}
+
+/// A consistent view of a set of relations. Cloning a snapshot is cheap, and the clones share
+/// the same tuples.
+#[derive(Debug, Clone, Default)]
+pub struct ReadSnapshot {
+    relations: HashMap<Identifier, RelationSnapshot>,
+}
+
+impl ReadSnapshot {
+    /// Takes a snapshot of every relation. As taking the snapshot borrows every relation, none
+    /// of them can be written to until the snapshot has been taken.
+    pub fn new<'a, I: IntoIterator<Item = &'a Relation>>(relations: I) -> Self {
+        let relations = relations
+            .into_iter()
+            .map(|relation| (relation.name().clone(), RelationSnapshot::new(relation)))
+            .collect();
+        ReadSnapshot { relations }
+    }
+
+    pub fn relation(&self, name: &Identifier) -> Option<&RelationSnapshot> {
+        self.relations.get(name)
+    }
+
+    pub fn relations(&self) -> impl Iterator<Item = &RelationSnapshot> {
+        self.relations.values()
+    }
+
+    /// Scans the tuples of the relation with the name, if it is part of the snapshot
+    pub fn scan(&self, name: &Identifier) -> Option<impl Iterator<Item = &Tuple>> {
+        self.relation(name).map(RelationSnapshot::tuples)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rad_db_types::Value;
+
+    use super::*;
+    use crate::key::primary::PrimaryKeyDefinition;
+
+    #[test]
+    fn snapshots_ignore_later_writes() {
+        let mut relation = Relation::new_volatile(
+            Identifier::new("numbers"),
+            vec![("n", Type::from(0u32))],
+            4,
+            PrimaryKeyDefinition::new(vec![0]),
+        );
+        for n in 0..5u32 {
+            relation.insert(Tuple::new(vec![Value::from(n)])).unwrap();
+        }
+
+        let snapshot = ReadSnapshot::new([&relation]);
+        for n in 5..10u32 {
+            relation.insert(Tuple::new(vec![Value::from(n)])).unwrap();
+        }
+
+        let name = Identifier::new("numbers");
+        assert_eq!(relation.len(), 10);
+        assert_eq!(snapshot.scan(&name).unwrap().count(), 5);
+        assert!(snapshot
+            .scan(&name)
+            .unwrap()
+            .all(|tuple| (0..5u32).any(|n| tuple[0] == Value::from(n))));
+        assert!(snapshot.scan(&Identifier::new("missing")).is_none());
+    }
+}
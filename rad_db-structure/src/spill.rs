@@ -0,0 +1,134 @@
+//! Tuples written to the spill directory of the process while they don't fit in the memory
+//! budget, and read back in the order they were written.
+//!
+//! Tuples are written one per line in the text format, the same way blocks store them. A tuple
+//! that wouldn't read back the same, such as one holding binary text, can't be spilled, and has
+//! to be kept in memory instead.
+
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use rad_db_types::deserialization::parse_using_types_with;
+use rad_db_types::serialization::{serialize_values_to_with, TextFormat};
+use rad_db_types::{Text, Type};
+
+use crate::temp;
+use crate::tuple::Tuple;
+
+/// A file of tuples in the spill directory, which is removed when it's dropped
+pub struct SpillFile {
+    path: PathBuf,
+    writer: BufWriter<File>,
+    types: Vec<Type>,
+    format: TextFormat,
+    len: usize,
+}
+
+impl SpillFile {
+    /// Creates an empty file for tuples with fields of the types
+    pub fn new(types: Vec<Type>) -> io::Result<Self> {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let id = COUNT.fetch_add(1, Ordering::Relaxed);
+        let path = temp::spill_directory()?.join(format!("spill_{}.txt", id));
+        let writer = BufWriter::new(File::create(&path)?);
+        Ok(SpillFile {
+            path,
+            writer,
+            types,
+            format: TextFormat::default(),
+            len: 0,
+        })
+    }
+
+    /// The number of tuples written to the file
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Writes the tuple to the end of the file, returning whether it could be. It can't be if it
+    /// wouldn't be read back the same.
+    pub fn push(&mut self, tuple: &Tuple) -> io::Result<bool> {
+        if tuple.iter().any(is_binary) {
+            return Ok(false);
+        }
+        let mut line = vec![];
+        serialize_values_to_with(tuple.iter().cloned(), &self.format, &mut line)?;
+        if self.parse(&line).as_ref() != Some(tuple) {
+            return Ok(false);
+        }
+        line.push(b'\n');
+        self.writer.write_all(&line)?;
+        self.len += 1;
+        Ok(true)
+    }
+
+    /// Reads the tuples back in the order they were written
+    pub fn read(&mut self) -> io::Result<impl Iterator<Item = Tuple> + '_> {
+        self.writer.flush()?;
+        let reader = BufReader::new(File::open(&self.path)?);
+        Ok(reader.split(b'\n').map(move |line| {
+            line.ok()
+                .and_then(|line| self.parse(&line))
+                .expect("Couldn't read back a spilled tuple")
+        }))
+    }
+
+    fn parse(&self, line: &[u8]) -> Option<Tuple> {
+        let line = std::str::from_utf8(line).ok()?;
+        parse_using_types_with(line, self.types.iter().cloned(), &self.format)
+            .ok()
+            .map(Tuple::new)
+    }
+}
+
+impl Drop for SpillFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Whether the value holds binary text, which the text format can't write
+fn is_binary(value: &Type) -> bool {
+    match value {
+        Type::Text(Text::Binary(_)) | Type::Text(Text::BinaryString(_, _)) => true,
+        Type::Optional(Some(inner)) => is_binary(inner),
+        Type::Array(values, _) => values.iter().any(is_binary),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rad_db_types::Value;
+
+    use super::*;
+
+    #[test]
+    fn tuples_read_back_in_order() {
+        let types = vec![Type::from(0u32), Type::from("")];
+        let mut file = SpillFile::new(types).unwrap();
+        let tuples: Vec<Tuple> = (0..100u32)
+            .map(|i| Tuple::new(vec![Value::from(i), Value::from(format!("a|\"{}\"", i))]))
+            .collect();
+        for tuple in &tuples {
+            assert!(file.push(tuple).unwrap());
+        }
+        assert_eq!(file.len(), 100);
+        assert_eq!(file.read().unwrap().collect::<Vec<_>>(), tuples);
+
+        // a tuple that doesn't match the types wouldn't be read back the same
+        let wrong = Tuple::new(vec![Value::from(-1i64), Value::from("")]);
+        assert!(!file.push(&wrong).unwrap());
+
+        let path = file.path.clone();
+        assert!(path.exists());
+        std::mem::drop(file);
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,525 @@
+//! Stress tests of relations, where readers and writers work on a relation at the same time
+//! while its invariants are checked. Only available with the `testing` feature.
+//!
+//! Writers insert random tuples that fit the relation, some of which replace tuples that are
+//! already stored, and remember the last tuple stored with each key. Readers keep checking that
+//! the number of tuples the relation holds is between its length before and after counting them,
+//! that every tuple it holds was stored by a writer and can be found by its key, which it can't be
+//! if the directory points the key at another block, and that no tuple a writer stored has been
+//! lost. Once every worker is done, the length has to match exactly, and every tuple has to be the
+//! last one stored with its key.
+//!
+//! Every worker shares the relation, so readers scan it while writers store tuples in it, and
+//! writers store tuples alongside each other. Writers only take turns on keeping track of what
+//! they stored, and never store tuples with the same key at the same time, so that the tuple
+//! each one replaces is known.
+//!
+//! Each writer generates its tuples from its own seed. The order the threads run in still
+//! changes from run to run, so a failing run may take a few tries to repeat.
+
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Mutex, MutexGuard};
+use std::thread;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::key::encoding::EncodedKey;
+use crate::key::primary::PrimaryKeyDefinition;
+use crate::relations::Relation;
+use crate::testing::random_tuple;
+use crate::tuple::Tuple;
+use rad_db_types::Value;
+
+/// How many workers a stress test runs, and how much they do
+#[derive(Debug, Clone)]
+pub struct StressConfig {
+    readers: usize,
+    writers: usize,
+    writes: usize,
+    replace_fraction: f64,
+    seed: u64,
+}
+
+impl Default for StressConfig {
+    fn default() -> Self {
+        StressConfig {
+            readers: 4,
+            writers: 2,
+            writes: 256,
+            replace_fraction: 0.25,
+            seed: 0,
+        }
+    }
+}
+
+impl StressConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_readers(mut self, readers: usize) -> Self {
+        self.readers = readers;
+        self
+    }
+
+    pub fn with_writers(mut self, writers: usize) -> Self {
+        self.writers = writers;
+        self
+    }
+
+    /// Sets how many tuples each writer inserts
+    pub fn with_writes(mut self, writes: usize) -> Self {
+        self.writes = writes;
+        self
+    }
+
+    /// Sets the fraction of writes that replace a tuple that's already stored
+    ///
+    /// # Panic
+    /// Panics if the fraction isn't between 0 and 1
+    pub fn with_replace_fraction(mut self, fraction: f64) -> Self {
+        assert!(
+            (0.0..=1.0).contains(&fraction),
+            "The fraction of replacements must be between 0 and 1"
+        );
+        self.replace_fraction = fraction;
+        self
+    }
+
+    /// Sets the seed the seeds of the writers are made from
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    pub fn readers(&self) -> usize {
+        self.readers
+    }
+
+    pub fn writers(&self) -> usize {
+        self.writers
+    }
+
+    pub fn writes(&self) -> usize {
+        self.writes
+    }
+
+    pub fn replace_fraction(&self) -> f64 {
+        self.replace_fraction
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+}
+
+/// What the workers of a stress test did
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StressReport {
+    inserted: usize,
+    replaced: usize,
+    rejected: usize,
+    checks: usize,
+}
+
+impl StressReport {
+    /// The number of tuples stored with a new key
+    pub fn inserted(&self) -> usize {
+        self.inserted
+    }
+
+    /// The number of tuples that replaced a stored tuple
+    pub fn replaced(&self) -> usize {
+        self.replaced
+    }
+
+    /// The number of tuples the relation refused, such as for violating its constraints
+    pub fn rejected(&self) -> usize {
+        self.rejected
+    }
+
+    /// The number of times the invariants were checked
+    pub fn checks(&self) -> usize {
+        self.checks
+    }
+}
+
+/// An invariant of the relation that didn't hold, or a worker that failed
+#[derive(Debug, Clone, PartialEq)]
+pub enum StressFailure {
+    /// The length of the relation isn't the number of tuples it holds
+    LengthMismatch { len: usize, counted: usize },
+    /// A tuple a writer stored can't be found by its key
+    Lost(Tuple),
+    /// The relation holds a tuple no writer stored
+    Unexpected(Tuple),
+    /// A tuple the relation holds can't be found by its key
+    Misplaced(Tuple),
+    /// Storing a tuple replaced another tuple than the one last stored with its key
+    WrongReplacement {
+        expected: Option<Tuple>,
+        replaced: Option<Tuple>,
+    },
+    /// A worker panicked, with the message it panicked with
+    Panicked(String),
+}
+
+impl Display for StressFailure {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StressFailure::LengthMismatch { len, counted } => write!(
+                f,
+                "The relation has a length of {} but holds {} tuples",
+                len, counted
+            ),
+            StressFailure::Lost(tuple) => write!(f, "The tuple {} was lost", tuple),
+            StressFailure::Unexpected(tuple) => {
+                write!(f, "The tuple {} was never stored", tuple)
+            }
+            StressFailure::Misplaced(tuple) => {
+                write!(f, "The tuple {} can't be found by its key", tuple)
+            }
+            StressFailure::WrongReplacement { expected, replaced } => {
+                let describe = |tuple: &Option<Tuple>| {
+                    tuple
+                        .as_ref()
+                        .map_or("no tuple".to_string(), |tuple| tuple.to_string())
+                };
+                write!(
+                    f,
+                    "Replaced {} instead of {}",
+                    describe(replaced),
+                    describe(expected)
+                )
+            }
+            StressFailure::Panicked(message) => write!(f, "A worker panicked: {}", message),
+        }
+    }
+}
+
+impl Error for StressFailure {}
+
+/// What the writers stored, and what they're storing
+#[derive(Default)]
+struct Book {
+    /// The last tuple stored with each key
+    stored: HashMap<EncodedKey, Tuple>,
+    /// The keys of the stored tuples, to pick the tuples to replace from
+    keys: Vec<EncodedKey>,
+    /// Every tuple a writer started storing with each key, which a reader can see even after it's
+    /// replaced
+    versions: HashMap<EncodedKey, Vec<Tuple>>,
+    /// The keys writers are storing tuples with right now
+    writing: HashSet<EncodedKey>,
+}
+
+/// Checks every invariant of the relation once nothing is writing to it
+fn check(relation: &Relation, book: &Book) -> Result<(), StressFailure> {
+    let primary_key = relation.primary_key();
+    let mut counted = 0;
+    for tuple in relation.tuples() {
+        counted += 1;
+        let key = primary_key.key_of(&tuple).encode();
+        if book.stored.get(&key) != Some(&tuple) {
+            return Err(StressFailure::Unexpected(tuple));
+        }
+        if relation.find_by_key(&key_values(primary_key, &tuple)) != Some(tuple.clone()) {
+            return Err(StressFailure::Misplaced(tuple));
+        }
+    }
+    let len = relation.len();
+    if len != counted {
+        return Err(StressFailure::LengthMismatch { len, counted });
+    }
+    for tuple in book.stored.values() {
+        if relation
+            .find_by_key(&key_values(primary_key, tuple))
+            .as_ref()
+            != Some(tuple)
+        {
+            return Err(StressFailure::Lost(tuple.clone()));
+        }
+    }
+    Ok(())
+}
+
+/// Checks the invariants of the relation that hold while writers store tuples in it. Tuples are
+/// only ever added or replaced, so the number counted has to be between the length before and
+/// after counting, and every tuple stored before counting has to be found afterwards, though it
+/// may have been replaced since.
+fn check_while_writing(relation: &Relation, book: &Mutex<Book>) -> Result<(), StressFailure> {
+    let primary_key = relation.primary_key();
+    let stored: Vec<Tuple> = lock(book).stored.values().cloned().collect();
+    let before = relation.len();
+    let seen: Vec<Tuple> = relation.tuples().collect();
+    let after = relation.len();
+    if seen.len() < before || seen.len() > after {
+        return Err(StressFailure::LengthMismatch {
+            len: after,
+            counted: seen.len(),
+        });
+    }
+    for tuple in &seen {
+        let key = key_values(primary_key, tuple);
+        let found = relation.find_by_key(&key);
+        if found.map(|found| key_values(primary_key, &found)) != Some(key) {
+            return Err(StressFailure::Misplaced(tuple.clone()));
+        }
+    }
+    {
+        let book = lock(book);
+        for tuple in seen {
+            let key = primary_key.key_of(&tuple).encode();
+            let versions = book.versions.get(&key);
+            if !versions.map_or(false, |versions| versions.contains(&tuple)) {
+                return Err(StressFailure::Unexpected(tuple));
+            }
+        }
+    }
+    for tuple in stored {
+        if relation
+            .find_by_key(&key_values(primary_key, &tuple))
+            .is_none()
+        {
+            return Err(StressFailure::Lost(tuple));
+        }
+    }
+    Ok(())
+}
+
+/// The values of the key of the tuple, in the order they appear in the tuple
+fn key_values(primary_key: &PrimaryKeyDefinition, tuple: &Tuple) -> Vec<Value> {
+    tuple
+        .iter()
+        .enumerate()
+        .filter(|(index, _)| primary_key.contains(index))
+        .map(|(_, value)| value.clone())
+        .collect()
+}
+
+/// A panicking worker is reported when it's joined, so the others carry on with the book
+fn lock(book: &Mutex<Book>) -> MutexGuard<'_, Book> {
+    book.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Counts a writer as done when it's dropped, even if the writer panics
+struct Done<'a>(&'a AtomicUsize);
+
+impl Drop for Done<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Picks the next tuple to store, and claims its key so that no other writer stores a tuple
+/// with the same key until this one is done, which would make the replaced tuple ambiguous.
+/// Returns the tuple, its key and the tuple it should replace.
+fn claim(
+    relation: &Relation,
+    book: &Mutex<Book>,
+    config: &StressConfig,
+    rng: &mut StdRng,
+) -> (Tuple, EncodedKey, Option<Tuple>) {
+    let definition = relation.get_relation_definition();
+    let primary_key = relation.primary_key();
+    loop {
+        let mut book = lock(book);
+        let mut tuple = random_tuple(&definition, rng);
+        if !book.keys.is_empty() && rng.gen_bool(config.replace_fraction) {
+            let key = &book.keys[rng.gen_range(0..book.keys.len())];
+            let old = &book.stored[key];
+            for &field in primary_key.iter() {
+                tuple[field] = old[field].clone();
+            }
+        }
+        let key = primary_key.key_of(&tuple).encode();
+        if !book.writing.insert(key.clone()) {
+            continue;
+        }
+        book.versions
+            .entry(key.clone())
+            .or_default()
+            .push(tuple.clone());
+        let expected = book.stored.get(&key).cloned();
+        return (tuple, key, expected);
+    }
+}
+
+fn write(
+    relation: &Relation,
+    book: &Mutex<Book>,
+    config: &StressConfig,
+    seed: u64,
+    failed: &AtomicBool,
+) -> Result<StressReport, StressFailure> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut report = StressReport::default();
+    for _ in 0..config.writes {
+        if failed.load(Ordering::SeqCst) {
+            break;
+        }
+        let (tuple, key, expected) = claim(relation, book, config, &mut rng);
+        // stored without holding the book, while readers and other writers go on
+        let result = relation.store_shared(tuple.clone());
+        let mut book = lock(book);
+        book.writing.remove(&key);
+        match result {
+            Ok(replaced) => {
+                if replaced != expected {
+                    return Err(StressFailure::WrongReplacement { expected, replaced });
+                }
+                book.stored.insert(key.clone(), tuple);
+                if replaced.is_some() {
+                    report.replaced += 1;
+                } else {
+                    book.keys.push(key);
+                    report.inserted += 1;
+                }
+            }
+            Err(_) => report.rejected += 1,
+        }
+    }
+    Ok(report)
+}
+
+fn read(
+    relation: &Relation,
+    book: &Mutex<Book>,
+    writing: &AtomicUsize,
+    failed: &AtomicBool,
+) -> Result<StressReport, StressFailure> {
+    let mut report = StressReport::default();
+    loop {
+        let done = writing.load(Ordering::SeqCst) == 0;
+        if failed.load(Ordering::SeqCst) {
+            break;
+        }
+        check_while_writing(relation, book)?;
+        report.checks += 1;
+        if done {
+            break;
+        }
+        thread::yield_now();
+    }
+    Ok(report)
+}
+
+/// Runs readers and writers against the relation, checking its invariants while they do and
+/// once more after they're done. Returns the first invariant that didn't hold, or what the
+/// workers did if every check passed.
+///
+/// The relation should start out empty, as the tuples it already holds weren't stored by any
+/// writer. Writers store tuples without keeping the quota, blobs, indexes, hooks, history or
+/// statistics of the relation up to date, so it shouldn't have any.
+pub fn stress(relation: &Relation, config: &StressConfig) -> Result<StressReport, StressFailure> {
+    let book = Mutex::new(Book::default());
+    let writing = AtomicUsize::new(config.writers);
+    let failed = AtomicBool::new(false);
+    let results: Vec<Result<StressReport, StressFailure>> = thread::scope(|scope| {
+        let mut workers = vec![];
+        for writer in 0..config.writers {
+            let (book, writing, failed) = (&book, &writing, &failed);
+            workers.push(scope.spawn(move || {
+                let _done = Done(writing);
+                let seed = config.seed.wrapping_add(writer as u64);
+                let result = write(relation, book, config, seed, failed);
+                if result.is_err() {
+                    failed.store(true, Ordering::SeqCst);
+                }
+                result
+            }));
+        }
+        for _ in 0..config.readers {
+            let (book, writing, failed) = (&book, &writing, &failed);
+            workers.push(scope.spawn(move || {
+                let result = read(relation, book, writing, failed);
+                if result.is_err() {
+                    failed.store(true, Ordering::SeqCst);
+                }
+                result
+            }));
+        }
+        workers
+            .into_iter()
+            .map(|worker| {
+                worker.join().unwrap_or_else(|panic| {
+                    failed.store(true, Ordering::SeqCst);
+                    let message = panic
+                        .downcast_ref::<&str>()
+                        .map(|message| message.to_string())
+                        .or_else(|| panic.downcast_ref::<String>().cloned())
+                        .unwrap_or_default();
+                    Err(StressFailure::Panicked(message))
+                })
+            })
+            .collect()
+    });
+
+    let mut report = StressReport::default();
+    for result in results {
+        let result = result?;
+        report.inserted += result.inserted;
+        report.replaced += result.replaced;
+        report.rejected += result.rejected;
+        report.checks += result.checks;
+    }
+    check(relation, &lock(&book))?;
+    report.checks += 1;
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::identifier::Identifier;
+    use crate::key::primary::PrimaryKeyDefinition;
+    use rad_db_types::Type;
+
+    #[test]
+    fn readers_and_writers() {
+        let relation = Relation::new_volatile(
+            Identifier::new("stressed"),
+            vec![
+                ("id", Type::from(0u16)),
+                ("name", Type::from("")),
+                ("flag", Type::from(false)),
+            ],
+            8,
+            PrimaryKeyDefinition::new(vec![0]),
+        );
+        let config = StressConfig::new()
+            .with_readers(3)
+            .with_writers(3)
+            .with_writes(200)
+            .with_seed(7);
+        let report = stress(&relation, &config).unwrap();
+        assert_eq!(
+            report.inserted() + report.replaced() + report.rejected(),
+            600
+        );
+        assert!(report.replaced() > 0);
+        assert!(report.checks() > 1);
+        assert_eq!(relation.len(), report.inserted());
+        assert!(relation.block_count() > 1);
+    }
+
+    #[test]
+    fn tuples_not_stored_are_found() {
+        let mut relation = Relation::new_volatile(
+            Identifier::new("tampered"),
+            vec![("id", Type::from(0u8))],
+            8,
+            PrimaryKeyDefinition::new(vec![0]),
+        );
+        relation.insert(Tuple::new(vec![Type::from(1u8)])).unwrap();
+        let config = StressConfig::new().with_readers(1).with_writers(0);
+        assert_eq!(
+            stress(&relation, &config),
+            Err(StressFailure::Unexpected(Tuple::new(vec![Type::from(1u8)])))
+        );
+    }
+}
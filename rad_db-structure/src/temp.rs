@@ -0,0 +1,208 @@
+//! The namespace temporary relations and data spilled to disk are stored in.
+//!
+//! Every process gets its own directory under `DB_STORAGE/TEMP`, named after its process id, so
+//! the temporary files of different processes can't collide, and removing them can't touch
+//! anything outside of it. A process holds an advisory lock on the [LOCK_FILE] in its directory
+//! for as long as it runs, which the system lets go of however the process ends. The directories
+//! whose lock can be taken were left behind by processes that aren't running anymore, such as
+//! ones that crashed, and are removed the first time a process uses the namespace. A process that
+//! exits normally removes its own directory on the way out.
+
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, OnceLock};
+
+use crate::identifier::Identifier;
+
+/// The directory every relation is stored in
+const STORAGE_ROOT: &str = "DB_STORAGE";
+
+/// The name of the namespace within the storage root that holds the namespace of every process
+pub const TEMP_NAMESPACE: &str = "TEMP";
+
+/// The file in the directory of the namespace of a process that the process holds a lock on
+pub const LOCK_FILE: &str = "LOCK";
+
+/// The namespace of this process, removing the namespaces of processes that aren't running
+/// anymore the first time it's used
+pub fn namespace() -> &'static Identifier {
+    static NAMESPACE: OnceLock<Identifier> = OnceLock::new();
+    NAMESPACE.get_or_init(|| {
+        let namespace = Identifier::with_parent(
+            &Identifier::new(TEMP_NAMESPACE),
+            format!("p{}", std::process::id()),
+        );
+        let directory = directory_of(&namespace);
+        // the lock is held until the process ends
+        let lock = lock_namespace(&directory)
+            .map_err(|e| log::warn!("Couldn't lock the temporary namespace: {}", e))
+            .ok();
+        *GUARD.lock().unwrap_or_else(|e| e.into_inner()) = Some(NamespaceGuard { directory, lock });
+        // safe, as the handler only touches the guard, which lives as long as the process
+        if unsafe { atexit(release_on_exit) } != 0 {
+            log::warn!("The temporary namespace won't be removed when the process exits");
+        }
+        if let Err(e) = remove_orphans() {
+            log::warn!("Couldn't remove orphaned temporary files: {}", e);
+        }
+        namespace
+    })
+}
+
+/// The guard of this process's namespace, taken and dropped when the process exits
+static GUARD: Mutex<Option<NamespaceGuard>> = Mutex::new(None);
+
+extern "C" {
+    fn atexit(callback: extern "C" fn()) -> std::os::raw::c_int;
+}
+
+extern "C" fn release_on_exit() {
+    let guard = GUARD.lock().unwrap_or_else(|e| e.into_inner()).take();
+    drop(guard);
+}
+
+/// Holds the lock of a namespace, letting go of it and removing the namespace's directory when
+/// dropped
+struct NamespaceGuard {
+    directory: PathBuf,
+    lock: Option<File>,
+}
+
+impl Drop for NamespaceGuard {
+    fn drop(&mut self) {
+        // the lock file can't be removed while it's open on some systems
+        drop(self.lock.take());
+        match fs::remove_dir_all(&self.directory) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => {
+                log::warn!("Couldn't remove the temporary namespace: {}", e)
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Creates the directory of a namespace and takes the lock of it, waiting for whoever holds it
+fn lock_namespace(directory: &Path) -> io::Result<File> {
+    fs::create_dir_all(directory)?;
+    let file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(true)
+        .open(directory.join(LOCK_FILE))?;
+    file.lock()?;
+    Ok(file)
+}
+
+/// Gives a name within its own directory of this process's namespace, so that the directory can
+/// be removed without removing anything else
+pub fn unique_identifier<I: Into<Identifier>>(name: I) -> Identifier {
+    static COUNT: AtomicUsize = AtomicUsize::new(0);
+    let id = COUNT.fetch_add(1, Ordering::Relaxed);
+    Identifier::concat(
+        Identifier::with_parent(namespace(), format!("t{}", id)),
+        name,
+    )
+}
+
+/// The directory the files of an identifier are stored in
+pub fn directory_of(identifier: &Identifier) -> PathBuf {
+    PathBuf::from(STORAGE_ROOT).join(PathBuf::from(identifier))
+}
+
+/// A directory for data queries spill to disk, created if it doesn't exist yet
+pub fn spill_directory() -> io::Result<PathBuf> {
+    let directory = directory_of(namespace()).join("spill");
+    fs::create_dir_all(&directory)?;
+    Ok(directory)
+}
+
+/// Removes the namespaces of processes that aren't running anymore, returning how many were
+/// removed. A namespace is only removed while holding its lock, so namespaces without a lock
+/// file, whose process may not have locked it yet, are kept.
+pub fn remove_orphans() -> io::Result<usize> {
+    let root = PathBuf::from(STORAGE_ROOT).join(TEMP_NAMESPACE);
+    let entries = match fs::read_dir(&root) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(e),
+    };
+    let mut removed = 0;
+    for entry in entries {
+        let entry = entry?;
+        let lock = match File::open(entry.path().join(LOCK_FILE)) {
+            Ok(lock) => lock,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+        };
+        match lock.try_lock() {
+            Ok(()) => {
+                // another process may be removing the same orphan
+                match fs::remove_dir_all(entry.path()) {
+                    Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                    _ => removed += 1,
+                }
+            }
+            Err(TryLockError::WouldBlock) => {}
+            Err(TryLockError::Error(e)) => return Err(e),
+        }
+    }
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn identifiers_are_unique() {
+        let first = unique_identifier("relation");
+        let second = unique_identifier("relation");
+        assert_ne!(first, second);
+        assert_eq!(first.base(), "relation");
+        assert!(directory_of(&first).starts_with(directory_of(namespace())));
+    }
+
+    #[test]
+    fn orphans_are_removed() {
+        namespace();
+        let temp = PathBuf::from(STORAGE_ROOT).join(TEMP_NAMESPACE);
+        // process ids are never this large, so no process can have these namespaces
+        let orphan = temp.join(format!("p{}", u32::MAX));
+        let held = temp.join(format!("p{}", u32::MAX - 1));
+        let unlocked = temp.join(format!("p{}", u32::MAX - 2));
+        drop(lock_namespace(&orphan).unwrap());
+        fs::create_dir_all(orphan.join("t0")).unwrap();
+        let lock = lock_namespace(&held).unwrap();
+        fs::create_dir_all(&unlocked).unwrap();
+        let mine = spill_directory().unwrap();
+
+        remove_orphans().unwrap();
+        assert!(!orphan.exists());
+        assert!(held.exists());
+        assert!(unlocked.exists());
+        assert!(mine.exists());
+
+        drop(lock);
+        remove_orphans().unwrap();
+        assert!(!held.exists());
+        fs::remove_dir(unlocked).unwrap();
+    }
+
+    #[test]
+    fn guard_removes_its_namespace() {
+        // process ids are never this large, so no process can have this namespace
+        let directory = PathBuf::from(STORAGE_ROOT)
+            .join(TEMP_NAMESPACE)
+            .join(format!("p{}", u32::MAX - 3));
+        let lock = lock_namespace(&directory).unwrap();
+        fs::create_dir_all(directory.join("t0")).unwrap();
+
+        drop(NamespaceGuard {
+            directory: directory.clone(),
+            lock: Some(lock),
+        });
+        assert!(!directory.exists());
+    }
+}
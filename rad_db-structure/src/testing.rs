@@ -0,0 +1,59 @@
+//! Generators of random tuples for relations, used for writing property tests of the storage
+//! layer. Only available with the `testing` feature.
+
+use rand::Rng;
+
+pub use rad_db_types::testing::*;
+
+use crate::relations::RelationDefinition;
+use crate::tuple::Tuple;
+
+/// Generates a random tuple that fits the relation definition
+pub fn random_tuple<R: Rng + ?Sized>(definition: &RelationDefinition, rng: &mut R) -> Tuple {
+    definition
+        .into_iter()
+        .map(|kind| random_value(&kind, rng))
+        .collect()
+}
+
+/// Generates many random tuples that fit the relation definition
+pub fn random_tuples<R: Rng + ?Sized>(
+    definition: &RelationDefinition,
+    count: usize,
+    rng: &mut R,
+) -> Vec<Tuple> {
+    (0..count).map(|_| random_tuple(definition, rng)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::identifier::Identifier;
+    use crate::key::primary::PrimaryKeyDefinition;
+    use crate::relations::Relation;
+    use rad_db_types::Type;
+
+    #[test]
+    fn insert_random_tuples() {
+        let mut rng = rand::thread_rng();
+        let mut relation = Relation::new_volatile(
+            Identifier::new("random"),
+            vec![
+                ("id", Type::from(0u64)),
+                ("name", Type::from("")),
+                ("flag", Type::from(false)),
+            ],
+            16,
+            PrimaryKeyDefinition::new(vec![0]),
+        );
+        let definition = relation.get_relation_definition();
+        let tuples = random_tuples(&definition, 256, &mut rng);
+        for tuple in &tuples {
+            assert!(tuple.validate(&definition).is_ok());
+        }
+        for tuple in tuples {
+            relation.insert(tuple).unwrap();
+        }
+        assert_eq!(relation.tuples().count(), relation.len());
+    }
+}
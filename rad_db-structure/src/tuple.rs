@@ -8,12 +8,14 @@ use std::ops::{
 use std::str::FromStr;
 
 use rad_db_types::serialization::serialize_values;
-use rad_db_types::{Type, Value};
+use rad_db_types::{Kind, Type, ValidationError, Value};
 use std::cmp::Reverse;
 
+use crate::relations::RelationDefinition;
+
 /// Represents a single row within a database.
 /// A tuple knows no information about itself besides its contents
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct Tuple(Vec<Type>);
 
 impl Tuple {
@@ -33,7 +35,6 @@ impl Tuple {
         removal.sort_by_key(|u| Reverse(*u));
         let mut values = self.0;
         for remove in removal {
-
             values.remove(remove);
         }
         Tuple(values)
@@ -44,7 +45,185 @@ impl Tuple {
         self.remove(index)
     }
 
+    /// Creates a tuple of the values at the indexes, in the order of the indexes. Values are
+    /// moved out of this tuple, and only cloned when an index is repeated.
+    ///
+    /// # Panic
+    /// Panics if an index is outside of the tuple
+    pub fn project(self, indexes: &[usize]) -> Tuple {
+        let mut values: Vec<Option<Type>> = self.0.into_iter().map(Some).collect();
+        let mut projected = Vec::with_capacity(indexes.len());
+        for (position, &index) in indexes.iter().enumerate() {
+            // the last use of an index takes the value
+            let value = if indexes[position + 1..].contains(&index) {
+                values[index].clone()
+            } else {
+                values[index].take()
+            };
+            projected.push(value.expect("Values are only taken by the last use of their index"));
+        }
+        Tuple(projected)
+    }
+
+    /// A view of the values at the indexes, in the order of the indexes, which borrows the values
+    /// instead of copying them
+    ///
+    /// # Panic
+    /// Panics if an index is outside of the tuple
+    pub fn project_ref(&self, indexes: &[usize]) -> TupleRef<'_> {
+        TupleRef {
+            parts: indexes
+                .iter()
+                .map(|&index| std::slice::from_ref(&self.0[index]))
+                .collect(),
+        }
+    }
+
+    /// Finds the values that need to change to turn this tuple into the other tuple.
+    ///
+    /// # Panic
+    /// Panics if the tuples are of different lengths
+    pub fn diff(&self, other: &Tuple) -> TuplePatch {
+        assert_eq!(
+            self.len(),
+            other.len(),
+            "Can only diff tuples of the same length"
+        );
+        let changes = self
+            .iter()
+            .zip(other.iter())
+            .enumerate()
+            .filter(|(_, (old, new))| old != new)
+            .map(|(index, (_, new))| (index, new.clone()))
+            .collect();
+        TuplePatch { changes }
+    }
+
+    /// Replaces the values of this tuple with the changed values of the patch
+    ///
+    /// # Panic
+    /// Panics if the patch changes an index outside of the tuple
+    pub fn apply_patch(&mut self, patch: TuplePatch) {
+        for (index, value) in patch {
+            self[index] = value;
+        }
+    }
 
+    /// Checks that this tuple can be stored in a relation with the given definition.
+    ///
+    /// Every value is [validated](Kind::validate) against the kind of its field, so strings may be
+    /// shorter than the maximum length of their field, and `NULL` is only accepted by nullable
+    /// fields. Every problem found is returned.
+    pub fn validate(&self, definition: &RelationDefinition) -> Result<(), Vec<FieldTypeError>> {
+        if self.len() != definition.len() {
+            return Err(vec![FieldTypeError::WrongLength {
+                expected: definition.len(),
+                found: self.len(),
+            }]);
+        }
+        let errors: Vec<_> = self
+            .iter()
+            .enumerate()
+            .filter_map(|(index, value)| validate_value(index, value, &definition[index].1))
+            .collect();
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+/// The values that changed between two versions of a tuple, by index
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct TuplePatch {
+    changes: Vec<(usize, Value)>,
+}
+
+impl TuplePatch {
+    pub fn new<I: IntoIterator<Item = (usize, Value)>>(changes: I) -> Self {
+        let mut changes: Vec<_> = changes.into_iter().collect();
+        changes.sort_by_key(|(index, _)| *index);
+        TuplePatch { changes }
+    }
+
+    /// Gets the changed values in index order
+    pub fn changes(&self) -> &[(usize, Value)] {
+        &self.changes
+    }
+
+    /// Gets the indexes of the changed values
+    pub fn changed_indexes(&self) -> impl Iterator<Item = usize> + '_ {
+        self.changes.iter().map(|(index, _)| *index)
+    }
+
+    pub fn len(&self) -> usize {
+        self.changes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+}
+
+impl IntoIterator for TuplePatch {
+    type Item = (usize, Value);
+    type IntoIter = <Vec<(usize, Value)> as IntoIterator>::IntoIter;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.changes.into_iter()
+    }
+}
+
+/// A reason a tuple can't be stored in a relation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FieldTypeError {
+    /// The tuple doesn't have as many values as the relation has fields
+    WrongLength { expected: usize, found: usize },
+    /// The value at the index isn't of the field's type
+    WrongType(usize),
+    /// The value at the index is longer than the field allows
+    TooLong(usize),
+    /// The value at the index isn't as long as the field requires
+    WrongWidth(usize),
+    /// The value at the index is `NULL`, but the field isn't nullable
+    NotNullable(usize),
+    /// The value at the index is a number outside the range of the field
+    OutOfRange(usize),
+}
+
+impl Display for FieldTypeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FieldTypeError::WrongLength { expected, found } => {
+                write!(f, "Expected {} values, found {}", expected, found)
+            }
+            FieldTypeError::WrongType(index) => write!(f, "Wrong type at index {}", index),
+            FieldTypeError::TooLong(index) => write!(f, "Value too long at index {}", index),
+            FieldTypeError::WrongWidth(index) => {
+                write!(f, "Value of the wrong width at index {}", index)
+            }
+            FieldTypeError::NotNullable(index) => {
+                write!(f, "NULL in non-nullable field at index {}", index)
+            }
+            FieldTypeError::OutOfRange(index) => {
+                write!(f, "Number out of range at index {}", index)
+            }
+        }
+    }
+}
+
+impl Error for FieldTypeError {}
+
+fn validate_value(index: usize, value: &Value, field: &Kind) -> Option<FieldTypeError> {
+    match field.validate(value) {
+        Ok(()) => None,
+        Err(ValidationError::Null) => Some(FieldTypeError::NotNullable(index)),
+        Err(ValidationError::TooLong { .. }) => Some(FieldTypeError::TooLong(index)),
+        Err(ValidationError::WrongLength { .. }) => Some(FieldTypeError::WrongWidth(index)),
+        Err(ValidationError::OutOfRange { .. }) => Some(FieldTypeError::OutOfRange(index)),
+        Err(ValidationError::WrongKind { .. }) => Some(FieldTypeError::WrongType(index)),
+    }
 }
 
 impl Deref for Tuple {
@@ -109,7 +288,9 @@ impl Add<&Tuple> for Tuple {
     type Output = Tuple;
 
     fn add(self, rhs: &Tuple) -> Self::Output {
-        self.concat(rhs.clone())
+        let mut backing = self.0;
+        backing.extend(rhs.iter().cloned());
+        Self(backing)
     }
 }
 
@@ -125,7 +306,7 @@ impl Add<&Tuple> for &Tuple {
     type Output = Tuple;
 
     fn add(self, rhs: &Tuple) -> Self::Output {
-        self.clone().concat(rhs.clone())
+        TupleRef::from(self).concat(rhs.into()).to_tuple()
     }
 }
 
@@ -212,3 +393,216 @@ impl Sub<&[usize]> for &Tuple {
         self.clone() - rhs
     }
 }
+
+/// A view of the values of one or more tuples placed end to end, which borrows the values
+/// instead of copying them. The values are only copied once the view becomes a [Tuple].
+#[derive(Debug, Clone)]
+pub struct TupleRef<'a> {
+    parts: Vec<&'a [Type]>,
+}
+
+impl<'a> TupleRef<'a> {
+    pub fn new(values: &'a [Type]) -> Self {
+        TupleRef {
+            parts: vec![values],
+        }
+    }
+
+    /// Places the values of another view after the values of this view
+    pub fn concat(mut self, other: TupleRef<'a>) -> Self {
+        self.parts.extend(other.parts);
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.parts.iter().map(|part| part.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get(&self, mut index: usize) -> Option<&'a Type> {
+        for part in &self.parts {
+            if index < part.len() {
+                return Some(&part[index]);
+            }
+            index -= part.len();
+        }
+        None
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &'a Type> + '_ {
+        self.parts.iter().flat_map(|part| part.iter())
+    }
+
+    /// Copies the values into a tuple
+    pub fn to_tuple(&self) -> Tuple {
+        let mut values = Vec::with_capacity(self.len());
+        values.extend(self.iter().cloned());
+        Tuple(values)
+    }
+}
+
+impl<'a> From<&'a Tuple> for TupleRef<'a> {
+    fn from(tuple: &'a Tuple) -> Self {
+        TupleRef::new(&tuple.0)
+    }
+}
+
+impl Index<usize> for TupleRef<'_> {
+    type Output = Value;
+
+    fn index(&self, index: usize) -> &Self::Output {
+        self.get(index).unwrap()
+    }
+}
+
+impl PartialEq<Tuple> for TupleRef<'_> {
+    fn eq(&self, other: &Tuple) -> bool {
+        self.len() == other.len() && self.iter().eq(other.iter())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rad_db_types::Text;
+
+    #[test]
+    fn projection() {
+        let tuple = Tuple::new(vec![Value::from(1u32), Value::from("a"), Value::from(true)]);
+        let expected = Tuple::new(vec![
+            Value::from(true),
+            Value::from(1u32),
+            Value::from(true),
+        ]);
+        assert_eq!(tuple.project_ref(&[2, 0, 2]), expected);
+        assert_eq!(tuple.project_ref(&[1])[0], Value::from("a"));
+        assert!(tuple.project_ref(&[]).is_empty());
+        assert_eq!(tuple.project(&[2, 0, 2]), expected);
+    }
+
+    #[test]
+    fn diff_and_patch() {
+        let old = Tuple::new(vec![Value::from(1u32), Value::from("a"), Value::from(true)]);
+        let new = Tuple::new(vec![
+            Value::from(1u32),
+            Value::from("b"),
+            Value::from(false),
+        ]);
+        let patch = old.diff(&new);
+        assert_eq!(patch.changed_indexes().collect::<Vec<_>>(), vec![1, 2]);
+        assert_eq!(patch.changes()[0], (1, Value::from("b")));
+        assert!(old.diff(&old).is_empty());
+
+        let mut patched = old.clone();
+        patched.apply_patch(patch);
+        assert_eq!(patched, new);
+        patched.apply_patch(TuplePatch::new(vec![(0, Value::from(2u32))]));
+        assert_eq!(patched[0], Value::from(2u32));
+    }
+
+    #[test]
+    fn validate() {
+        let definition: RelationDefinition = vec![
+            ("id".to_string(), Type::from(0u32)),
+            (
+                "code".to_string(),
+                Type::from(Text::String(String::new(), Some(3))),
+            ),
+            (
+                "note".to_string(),
+                Type::Optional(Some(Box::new(Type::from("")))),
+            ),
+        ]
+        .into_iter()
+        .collect();
+
+        let valid = Tuple::new(vec![
+            Value::from(1u32),
+            Value::from("abc"),
+            Value::Optional(None),
+        ]);
+        assert_eq!(valid.validate(&definition), Ok(()));
+        assert_eq!(definition.nullable_fields(), vec![2]);
+        let with_note = Tuple::new(vec![Value::from(1u32), Value::from("ab"), Value::from("x")]);
+        assert_eq!(with_note.validate(&definition), Ok(()));
+
+        let invalid = Tuple::new(vec![
+            Value::Optional(None),
+            Value::from("abcd"),
+            Value::from(2u32),
+        ]);
+        assert_eq!(
+            invalid.validate(&definition),
+            Err(vec![
+                FieldTypeError::NotNullable(0),
+                FieldTypeError::TooLong(1),
+                FieldTypeError::WrongType(2)
+            ])
+        );
+        assert_eq!(
+            Tuple::new(vec![Value::from(1u32)]).validate(&definition),
+            Err(vec![FieldTypeError::WrongLength {
+                expected: 3,
+                found: 1
+            }])
+        );
+
+        let definition = definition.with_nullable(0, true).with_nullable(2, false);
+        assert!(definition.is_nullable(0));
+        assert!(!definition.is_nullable(2));
+        assert_eq!(
+            valid.validate(&definition),
+            Err(vec![FieldTypeError::NotNullable(2)])
+        );
+        let with_id = Tuple::new(vec![
+            Value::Optional(None),
+            Value::from("a"),
+            Value::from("b"),
+        ]);
+        assert_eq!(with_id.validate(&definition), Ok(()));
+    }
+
+    #[test]
+    fn validate_arrays() {
+        let code = Type::from(Text::String(String::new(), Some(2)));
+        let definition: RelationDefinition = vec![("codes".to_string(), Type::array(code, vec![]))]
+            .into_iter()
+            .collect();
+        let codes = |codes: Vec<Value>| Tuple::new(vec![Type::array(Type::from(""), codes)]);
+        assert_eq!(codes(vec![]).validate(&definition), Ok(()));
+        assert_eq!(
+            codes(vec!["ab".into(), "c".into()]).validate(&definition),
+            Ok(())
+        );
+        assert_eq!(
+            codes(vec!["ab".into(), "abc".into()]).validate(&definition),
+            Err(vec![FieldTypeError::TooLong(0)])
+        );
+        assert_eq!(
+            codes(vec![Value::Optional(None)]).validate(&definition),
+            Err(vec![FieldTypeError::NotNullable(0)])
+        );
+        let numbers = Tuple::new(vec![Type::array(Type::from(0u8), vec![1u8.into()])]);
+        assert_eq!(
+            numbers.validate(&definition),
+            Err(vec![FieldTypeError::WrongType(0)])
+        );
+    }
+
+    #[test]
+    fn tuple_ref() {
+        let left = Tuple::new(vec![Value::from(1u8), Value::from(2u8)]);
+        let right = Tuple::new(vec![Value::from("three")]);
+        let joined = TupleRef::from(&left).concat(TupleRef::from(&right));
+        assert_eq!(joined.len(), 3);
+        assert_eq!(joined[1], Value::from(2u8));
+        assert_eq!(joined[2], Value::from("three"));
+        assert_eq!(joined.get(3), None);
+        assert_eq!(joined, left.clone() + right.clone());
+        assert_eq!(joined.to_tuple(), left + right);
+        assert!(TupleRef::new(&[]).is_empty());
+    }
+}
@@ -0,0 +1,484 @@
+//! Arithmetic on numbers of any width.
+//!
+//! Both operands are promoted to the kind of the result first. Integers of the same signedness
+//! give the wider of the two, and mixing signed and unsigned integers gives a signed integer twice
+//! as wide as the unsigned one, up to 64 bits. Integers mixed with integers wider than 64 bits give
+//! an integer of the bits every value of both fits in. Floats stay floats when mixed with bytes
+//! and shorts, and anything else mixed with a floating point number gives a double. Decimals mixed
+//! with integers give a decimal, as in [Decimal](crate::Decimal)'s own arithmetic.
+//!
+//! An integer result that doesn't fit in its kind follows an [Overflow] behavior. The operators
+//! fail on overflow, and `NULL` operands give `NULL`.
+
+use crate::{Decimal, Integer, Numeric, Signed, Type, Unsigned};
+use num_bigint::BigInt;
+use num_traits::{Signed as _, Zero};
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::ops::{Add, Div, Mul, Sub};
+
+/// What happens to an integer result that doesn't fit in the kind of the result
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Overflow {
+    /// The operation fails
+    Fail,
+    /// The result wraps around the bounds of the kind, keeping its lowest bits
+    Wrap,
+    /// The result is clamped to the bounds of the kind
+    Saturate,
+}
+
+/// Why an arithmetic operation failed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArithmeticError {
+    /// The result doesn't fit in the kind of the result. Decimals can't wrap or saturate, so
+    /// they always fail when they overflow.
+    Overflow,
+    DivisionByZero,
+    /// One of the operands isn't a number
+    NotNumeric,
+}
+
+impl Display for ArithmeticError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ArithmeticError::Overflow => write!(f, "The result doesn't fit in its kind"),
+            ArithmeticError::DivisionByZero => write!(f, "Can't divide by zero"),
+            ArithmeticError::NotNumeric => write!(f, "Can only do arithmetic on numbers"),
+        }
+    }
+}
+
+impl Error for ArithmeticError {}
+
+/// An arithmetic operation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operator {
+    Add,
+    Subtract,
+    Multiply,
+    /// Divides, rounding integer quotients towards zero
+    Divide,
+}
+
+/// The kind of the result of an operation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ResultKind {
+    Integer { signed: bool, bits: u32 },
+    BigInt { bits: u16 },
+    Float,
+    Double,
+    Decimal,
+}
+
+fn integer_bits(numeric: &Numeric) -> Option<(bool, u32)> {
+    match numeric {
+        Numeric::Signed(Signed::Byte(_)) => Some((true, 8)),
+        Numeric::Signed(Signed::Short(_)) => Some((true, 16)),
+        Numeric::Signed(Signed::Int(_)) => Some((true, 32)),
+        Numeric::Signed(Signed::Long(_)) => Some((true, 64)),
+        Numeric::Unsigned(Unsigned::Byte(_)) => Some((false, 8)),
+        Numeric::Unsigned(Unsigned::Short(_)) => Some((false, 16)),
+        Numeric::Unsigned(Unsigned::Int(_)) => Some((false, 32)),
+        Numeric::Unsigned(Unsigned::Long(_)) => Some((false, 64)),
+        _ => None,
+    }
+}
+
+fn result_kind(left: &Numeric, right: &Numeric) -> ResultKind {
+    match (left, right) {
+        (Numeric::Double(_), _) | (_, Numeric::Double(_)) => ResultKind::Double,
+        (Numeric::Float(_), Numeric::Float(_)) => ResultKind::Float,
+        (Numeric::Float(_), other) | (other, Numeric::Float(_)) => match integer_bits(other) {
+            Some((_, bits)) if bits <= 16 => ResultKind::Float,
+            _ => ResultKind::Double,
+        },
+        (Numeric::Decimal(_), _) | (_, Numeric::Decimal(_)) => ResultKind::Decimal,
+        (Numeric::BigInt(_), _) | (_, Numeric::BigInt(_)) => {
+            let bits = |numeric| Integer::of(numeric).expect("Only integers are left").bits();
+            ResultKind::BigInt {
+                bits: bits(left).max(bits(right)),
+            }
+        }
+        (left, right) => {
+            let (left_signed, left_bits) = integer_bits(left).unwrap();
+            let (right_signed, right_bits) = integer_bits(right).unwrap();
+            match (left_signed, right_signed) {
+                (true, false) => ResultKind::Integer {
+                    signed: true,
+                    bits: left_bits.max(right_bits * 2).min(64),
+                },
+                (false, true) => ResultKind::Integer {
+                    signed: true,
+                    bits: right_bits.max(left_bits * 2).min(64),
+                },
+                (signed, _) => ResultKind::Integer {
+                    signed,
+                    bits: left_bits.max(right_bits),
+                },
+            }
+        }
+    }
+}
+
+fn integer_of(numeric: Numeric) -> i128 {
+    match numeric {
+        Numeric::Signed(signed) => Into::<i64>::into(signed) as i128,
+        Numeric::Unsigned(unsigned) => Into::<u64>::into(unsigned) as i128,
+        _ => unreachable!("Only integers have an integer value"),
+    }
+}
+
+fn decimal_of(numeric: Numeric) -> Option<Decimal> {
+    match numeric {
+        Numeric::Decimal(decimal) => Some(decimal),
+        Numeric::BigInt(integer) => Decimal::from_integer(integer.to_i128()?, 0),
+        integer => Decimal::from_integer(integer_of(integer), 0),
+    }
+}
+
+/// The integer of the kind with the value, which has to fit in the kind
+fn integer_numeric(value: i128, signed: bool, bits: u32) -> Numeric {
+    match (signed, bits) {
+        (true, 8) => Numeric::Signed(Signed::Byte(value as i8)),
+        (true, 16) => Numeric::Signed(Signed::Short(value as i16)),
+        (true, 32) => Numeric::Signed(Signed::Int(value as i32)),
+        (true, _) => Numeric::Signed(Signed::Long(value as i64)),
+        (false, 8) => Numeric::Unsigned(Unsigned::Byte(value as u8)),
+        (false, 16) => Numeric::Unsigned(Unsigned::Short(value as u16)),
+        (false, 32) => Numeric::Unsigned(Unsigned::Int(value as u32)),
+        (false, _) => Numeric::Unsigned(Unsigned::Long(value as u64)),
+    }
+}
+
+/// Fits an exact result, or `None` if it didn't fit in 128 bits, into an integer kind. A wrapped
+/// result is computed from `wrapped`, the result wrapped around 128 bits, and a saturated one from
+/// `negative`, whether the exact result is below zero.
+fn fit_integer(
+    exact: Option<i128>,
+    wrapped: i128,
+    negative: bool,
+    signed: bool,
+    bits: u32,
+    overflow: Overflow,
+) -> Result<Numeric, ArithmeticError> {
+    let (min, max) = if signed {
+        (-(1i128 << (bits - 1)), (1i128 << (bits - 1)) - 1)
+    } else {
+        (0, (1i128 << bits) - 1)
+    };
+    let value = match exact {
+        Some(value) if value >= min && value <= max => value,
+        _ => match overflow {
+            Overflow::Fail => return Err(ArithmeticError::Overflow),
+            // the lowest bits are the same however wide the result wrapped around
+            Overflow::Wrap => wrapped,
+            Overflow::Saturate if negative => min,
+            Overflow::Saturate => max,
+        },
+    };
+    Ok(integer_numeric(value, signed, bits))
+}
+
+/// Fits an exact result into an integer of the bits, like [fit_integer]
+fn fit_big_integer(
+    exact: BigInt,
+    bits: u16,
+    overflow: Overflow,
+) -> Result<Numeric, ArithmeticError> {
+    let limit = BigInt::from(1) << (bits as usize - 1);
+    let value = if exact < limit && exact >= -&limit {
+        exact
+    } else {
+        match overflow {
+            Overflow::Fail => return Err(ArithmeticError::Overflow),
+            Overflow::Wrap => {
+                let modulus = &limit << 1;
+                let wrapped = exact % &modulus;
+                if wrapped >= limit {
+                    wrapped - modulus
+                } else if wrapped < -&limit {
+                    wrapped + modulus
+                } else {
+                    wrapped
+                }
+            }
+            Overflow::Saturate if exact.is_negative() => -limit,
+            Overflow::Saturate => limit - 1,
+        }
+    };
+    Ok(Numeric::BigInt(
+        Integer::new(value, bits).expect("The value was fit into the bits"),
+    ))
+}
+
+impl Numeric {
+    /// Applies the operator to the numbers, with integer results that don't fit in their kind
+    /// following the overflow behavior
+    pub fn apply(
+        self,
+        operator: Operator,
+        other: Numeric,
+        overflow: Overflow,
+    ) -> Result<Numeric, ArithmeticError> {
+        match result_kind(&self, &other) {
+            ResultKind::Integer { signed, bits } => {
+                let (left, right) = (integer_of(self), integer_of(other));
+                let (exact, wrapped) = match operator {
+                    Operator::Add => (left.checked_add(right), left.wrapping_add(right)),
+                    Operator::Subtract => (left.checked_sub(right), left.wrapping_sub(right)),
+                    Operator::Multiply => (left.checked_mul(right), left.wrapping_mul(right)),
+                    Operator::Divide => {
+                        if right == 0 {
+                            return Err(ArithmeticError::DivisionByZero);
+                        }
+                        (Some(left / right), left / right)
+                    }
+                };
+                // only products of 64 bit integers can overflow 128 bits
+                let negative = exact.map_or((left < 0) != (right < 0), |exact| exact < 0);
+                fit_integer(exact, wrapped, negative, signed, bits, overflow)
+            }
+            ResultKind::BigInt { bits } => {
+                let big = |numeric: &Numeric| {
+                    Integer::of(numeric)
+                        .expect("Only integers give wide integers")
+                        .value()
+                        .clone()
+                };
+                let (left, right) = (big(&self), big(&other));
+                let exact = match operator {
+                    Operator::Add => left + right,
+                    Operator::Subtract => left - right,
+                    Operator::Multiply => left * right,
+                    Operator::Divide if right.is_zero() => {
+                        return Err(ArithmeticError::DivisionByZero)
+                    }
+                    Operator::Divide => left / right,
+                };
+                fit_big_integer(exact, bits, overflow)
+            }
+            ResultKind::Float => {
+                let (left, right) = (self.to_f64() as f32, other.to_f64() as f32);
+                let result = match operator {
+                    Operator::Add => left + right,
+                    Operator::Subtract => left - right,
+                    Operator::Multiply => left * right,
+                    Operator::Divide if right == 0.0 => {
+                        return Err(ArithmeticError::DivisionByZero)
+                    }
+                    Operator::Divide => left / right,
+                };
+                Ok(Numeric::Float(result))
+            }
+            ResultKind::Double => {
+                let (left, right) = (self.to_f64(), other.to_f64());
+                let result = match operator {
+                    Operator::Add => left + right,
+                    Operator::Subtract => left - right,
+                    Operator::Multiply => left * right,
+                    Operator::Divide if right == 0.0 => {
+                        return Err(ArithmeticError::DivisionByZero)
+                    }
+                    Operator::Divide => left / right,
+                };
+                Ok(Numeric::Double(result))
+            }
+            ResultKind::Decimal => {
+                let left = decimal_of(self).ok_or(ArithmeticError::Overflow)?;
+                let right = decimal_of(other).ok_or(ArithmeticError::Overflow)?;
+                let result = match operator {
+                    Operator::Add => left.checked_add(right),
+                    Operator::Subtract => left.checked_sub(right),
+                    Operator::Multiply => left.checked_mul(right),
+                    Operator::Divide if right.mantissa() == 0 => {
+                        return Err(ArithmeticError::DivisionByZero)
+                    }
+                    Operator::Divide => left.checked_div(right),
+                };
+                result
+                    .map(Numeric::Decimal)
+                    .ok_or(ArithmeticError::Overflow)
+            }
+        }
+    }
+}
+
+impl Type {
+    /// Applies the operator to the values, which have to be numbers or `NULL`. `NULL` operands
+    /// give `NULL`.
+    pub fn apply(
+        &self,
+        operator: Operator,
+        other: &Type,
+        overflow: Overflow,
+    ) -> Result<Type, ArithmeticError> {
+        match (self, other) {
+            (Type::Numeric(left), Type::Numeric(right)) => left
+                .clone()
+                .apply(operator, right.clone(), overflow)
+                .map(Type::Numeric),
+            (Type::Optional(None), Type::Numeric(_) | Type::Optional(_))
+            | (Type::Numeric(_), Type::Optional(None)) => Ok(Type::Optional(None)),
+            (Type::Optional(Some(left)), right) => left.apply(operator, right, overflow),
+            (left, Type::Optional(Some(right))) => left.apply(operator, right, overflow),
+            _ => Err(ArithmeticError::NotNumeric),
+        }
+    }
+}
+
+macro_rules! operator {
+    ($trait:ident, $method:ident, $operator:ident) => {
+        /// # Panic
+        /// Panics if the result doesn't fit in its kind, or when dividing by zero
+        impl $trait for Numeric {
+            type Output = Numeric;
+
+            fn $method(self, other: Numeric) -> Numeric {
+                match self.apply(Operator::$operator, other, Overflow::Fail) {
+                    Ok(result) => result,
+                    Err(error) => panic!("{}", error),
+                }
+            }
+        }
+
+        /// # Panic
+        /// Panics if either value isn't a number or `NULL`, if the result doesn't fit in its
+        /// kind, or when dividing by zero
+        impl $trait for Type {
+            type Output = Type;
+
+            fn $method(self, other: Type) -> Type {
+                match self.apply(Operator::$operator, &other, Overflow::Fail) {
+                    Ok(result) => result,
+                    Err(error) => panic!("{}", error),
+                }
+            }
+        }
+    };
+}
+
+operator!(Add, add, Add);
+operator!(Sub, sub, Subtract);
+operator!(Mul, mul, Multiply);
+operator!(Div, div, Divide);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn promotion() {
+        assert_eq!(Type::from(3i8) + Type::from(4i32), Type::from(7i32));
+        assert_eq!(Type::from(3u8) - Type::from(4i8), Type::from(-1i16));
+        assert_eq!(Type::from(3u32) * Type::from(2i8), Type::from(6i64));
+        assert_eq!(
+            Numeric::Float(1.5) + Numeric::Signed(Signed::Short(2)),
+            Numeric::Float(3.5)
+        );
+        assert_eq!(
+            Numeric::Float(1.5) + Numeric::Signed(Signed::Int(2)),
+            Numeric::Double(3.5)
+        );
+        assert_eq!(Type::from(7u64) / Type::from(2u8), Type::from(3u64));
+        let half = Decimal::parse("0.5", 2, 1).unwrap();
+        assert_eq!(
+            Type::from(half) + Type::from(1u8),
+            Type::from(Decimal::parse("1.5", 3, 1).unwrap())
+        );
+        assert_eq!(Type::Optional(None) + Type::from(1u8), Type::Optional(None));
+        assert_eq!(
+            Type::Optional(Some(Box::new(Type::from(2u8)))) * Type::from(3u8),
+            Type::from(6u8)
+        );
+    }
+
+    #[test]
+    fn overflow() {
+        let byte = |value: i8| Numeric::Signed(Signed::Byte(value));
+        assert_eq!(
+            byte(100).apply(Operator::Add, byte(100), Overflow::Fail),
+            Err(ArithmeticError::Overflow)
+        );
+        assert_eq!(
+            byte(100).apply(Operator::Add, byte(100), Overflow::Wrap),
+            Ok(byte(-56))
+        );
+        assert_eq!(
+            byte(100).apply(Operator::Add, byte(100), Overflow::Saturate),
+            Ok(byte(127))
+        );
+        assert_eq!(
+            byte(-100).apply(Operator::Multiply, byte(2), Overflow::Saturate),
+            Ok(byte(-128))
+        );
+        assert_eq!(
+            byte(-128).apply(Operator::Divide, byte(-1), Overflow::Wrap),
+            Ok(byte(-128))
+        );
+        let long = Numeric::Unsigned(Unsigned::Long(u64::MAX));
+        assert_eq!(
+            long.clone().apply(Operator::Multiply, long, Overflow::Wrap),
+            Ok(Numeric::Unsigned(Unsigned::Long(1)))
+        );
+        assert_eq!(
+            Numeric::Unsigned(Unsigned::Byte(1)).apply(
+                Operator::Subtract,
+                Numeric::Unsigned(Unsigned::Byte(2)),
+                Overflow::Saturate
+            ),
+            Ok(Numeric::Unsigned(Unsigned::Byte(0)))
+        );
+    }
+
+    #[test]
+    fn wide_integers() {
+        let wide = |value: i128, bits: u16| Numeric::BigInt(Integer::new(value, bits).unwrap());
+        assert_eq!(
+            Type::from(Integer::from(1i128 << 100)) + Type::from(i64::MAX),
+            Type::from(Integer::from((1i128 << 100) + i64::MAX as i128))
+        );
+        assert_eq!(
+            Type::from(u64::MAX) * Type::from(Integer::from(4i128)),
+            Type::from(Integer::from(u64::MAX as i128 * 4))
+        );
+        assert_eq!(
+            wide(1 << 68, 70).apply(Operator::Add, wide(1 << 68, 70), Overflow::Fail),
+            Err(ArithmeticError::Overflow)
+        );
+        assert_eq!(
+            wide(1 << 68, 70).apply(Operator::Add, wide(1 << 68, 70), Overflow::Wrap),
+            Ok(wide(-(1 << 69), 70))
+        );
+        assert_eq!(
+            wide(-(1 << 68), 70).apply(Operator::Multiply, wide(4, 70), Overflow::Saturate),
+            Ok(wide(-(1 << 69), 70))
+        );
+        assert_eq!(
+            wide(1, 70).apply(Operator::Divide, wide(0, 70), Overflow::Wrap),
+            Err(ArithmeticError::DivisionByZero)
+        );
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            Type::from(1u8).apply(Operator::Divide, &Type::from(0i64), Overflow::Wrap),
+            Err(ArithmeticError::DivisionByZero)
+        );
+        assert_eq!(
+            Numeric::Double(1.0).apply(Operator::Divide, Numeric::Double(0.0), Overflow::Fail),
+            Err(ArithmeticError::DivisionByZero)
+        );
+        assert_eq!(
+            Type::from("1").apply(Operator::Add, &Type::from(1u8), Overflow::Fail),
+            Err(ArithmeticError::NotNumeric)
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn operators_fail_on_overflow() {
+        let _ = Type::from(u64::MAX) + Type::from(1u8);
+    }
+}
@@ -0,0 +1,91 @@
+//! Arrays hold any number of values of the same kind, so that a field can have many values
+//! without moving them into a relation of their own.
+//!
+//! An array keeps the kind of its elements even when it's empty. The kind is a value like any
+//! other type, where only its kind matters, so arrays of the same elements are equal no matter
+//! which value their kind was made from.
+
+use std::hash::{Hash, Hasher};
+use std::ops::Deref;
+
+use crate::{SameType, Type};
+
+/// The kind of the elements of an array
+#[derive(Debug, Clone)]
+pub struct ElementKind(Box<Type>);
+
+impl ElementKind {
+    pub fn new(kind: Type) -> Self {
+        ElementKind(Box::new(kind))
+    }
+
+    pub fn into_inner(self) -> Type {
+        *self.0
+    }
+}
+
+impl Deref for ElementKind {
+    type Target = Type;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl PartialEq for ElementKind {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.same_type(&other.0)
+    }
+}
+
+impl Eq for ElementKind {}
+
+impl Hash for ElementKind {
+    /// Kinds that are equal can be made from different values, so nothing about a kind is hashed
+    fn hash<H: Hasher>(&self, _state: &mut H) {}
+}
+
+impl Type {
+    /// Creates an array of values of the kind
+    pub fn array<I: IntoIterator<Item = Type>>(kind: Type, values: I) -> Type {
+        Type::Array(values.into_iter().collect(), ElementKind::new(kind))
+    }
+
+    /// The elements of an array, or `None` if this isn't an array
+    pub fn as_array(&self) -> Option<&[Type]> {
+        match self {
+            Type::Array(values, _) => Some(values),
+            Type::Optional(Some(inner)) => inner.as_array(),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::hash_map::DefaultHasher;
+
+    fn hash_of(value: &Type) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        value.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    fn equality_ignores_the_kind_value() {
+        let left = Type::array(Type::from(0u32), vec![1u32.into(), 2u32.into()]);
+        let right = Type::array(Type::from(7u32), vec![1u32.into(), 2u32.into()]);
+        assert_eq!(left, right);
+        assert_eq!(hash_of(&left), hash_of(&right));
+        assert_ne!(
+            left,
+            Type::array(Type::from(0u32), vec![2u32.into(), 1u32.into()])
+        );
+        assert_ne!(
+            Type::array(Type::from(0u32), vec![]),
+            Type::array(Type::from(""), vec![])
+        );
+        assert_eq!(left.as_array().map(<[Type]>::len), Some(2));
+    }
+}
@@ -0,0 +1,368 @@
+//! Strings of bits, for sets of flags that don't fit or don't belong in an integer.
+//!
+//! Bits are packed eight to a byte, first bit in the highest bit of the first byte, and the bits
+//! after the end of the string are always zero. Bit strings are stored in that packed form,
+//! written as the number of bits and the hex of the bytes, like `5:a8` for `10101`. They are
+//! ordered bit by bit, with a bit string before any longer bit string it is a prefix of.
+//!
+//! The bitwise operators need both bit strings to be the same length, and `NULL` operands give
+//! `NULL`.
+
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::iter::FromIterator;
+use std::ops::{BitAnd, BitOr, BitXor, Not};
+
+use crate::extension::{from_hex, to_hex};
+use crate::{Text, Type};
+
+/// A string of bits of any length
+///
+/// The bytes are compared before the length, which orders bit strings bit by bit as the bits
+/// after the end are zero.
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct BitString {
+    bytes: Vec<u8>,
+    len: usize,
+}
+
+/// How many bits the bit strings of a kind have
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BitWidth {
+    /// Exactly the bits, like `BIT(n)`
+    Fixed(u16),
+    /// At most the bits if there is a limit, like `BIT VARYING(n)`
+    Varying(Option<u16>),
+}
+
+impl BitWidth {
+    /// Whether bit strings of the length are of the width
+    pub fn allows(&self, len: usize) -> bool {
+        match self {
+            BitWidth::Fixed(width) => len == *width as usize,
+            BitWidth::Varying(Some(max)) => len <= *max as usize,
+            BitWidth::Varying(None) => true,
+        }
+    }
+}
+
+/// A bitwise operation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BitOperator {
+    And,
+    Or,
+    Xor,
+}
+
+/// Why a bitwise operation failed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BitError {
+    /// The bit strings aren't the same length
+    LengthMismatch { left: usize, right: usize },
+    /// One of the operands isn't a bit string
+    NotBits,
+}
+
+impl Display for BitError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BitError::LengthMismatch { left, right } => write!(
+                f,
+                "Can't combine bit strings of {} and {} bits",
+                left, right
+            ),
+            BitError::NotBits => write!(f, "Can only do bitwise operations on bit strings"),
+        }
+    }
+}
+
+impl Error for BitError {}
+
+impl BitString {
+    /// A bit string of the length where every bit is zero
+    pub fn zeroes(len: usize) -> Self {
+        BitString {
+            bytes: vec![0; (len + 7) / 8],
+            len,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The bit at the index, or `None` if the index is past the end
+    pub fn get(&self, index: usize) -> Option<bool> {
+        if index < self.len {
+            Some(self.bytes[index / 8] & Self::mask(index) != 0)
+        } else {
+            None
+        }
+    }
+
+    /// # Panic
+    /// Panics if the index is past the end
+    pub fn set(&mut self, index: usize, bit: bool) {
+        assert!(
+            index < self.len,
+            "The index {} is past the end of {} bits",
+            index,
+            self.len
+        );
+        if bit {
+            self.bytes[index / 8] |= Self::mask(index);
+        } else {
+            self.bytes[index / 8] &= !Self::mask(index);
+        }
+    }
+
+    /// The number of bits that are one
+    pub fn count_ones(&self) -> usize {
+        self.bytes
+            .iter()
+            .map(|byte| byte.count_ones() as usize)
+            .sum()
+    }
+
+    /// The bits, eight to a byte. The bits after the end are zero.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// Applies the operator to each pair of bits, or fails if the lengths aren't the same
+    pub fn apply(&self, operator: BitOperator, other: &BitString) -> Result<BitString, BitError> {
+        if self.len != other.len {
+            return Err(BitError::LengthMismatch {
+                left: self.len,
+                right: other.len,
+            });
+        }
+        let bytes = self
+            .bytes
+            .iter()
+            .zip(&other.bytes)
+            .map(|(left, right)| match operator {
+                BitOperator::And => left & right,
+                BitOperator::Or => left | right,
+                BitOperator::Xor => left ^ right,
+            })
+            .collect();
+        Ok(BitString {
+            bytes,
+            len: self.len,
+        })
+    }
+
+    /// Parses a string of `0`s and `1`s, or returns `None` if the string isn't one
+    pub fn parse(string: &str) -> Option<Self> {
+        let string = string.trim();
+        let mut bits = BitString::zeroes(string.len());
+        for (index, c) in string.chars().enumerate() {
+            match c {
+                '0' => {}
+                '1' => bits.set(index, true),
+                _ => return None,
+            }
+        }
+        Some(bits)
+    }
+
+    /// Writes the bits in their packed form, like `5:a8`
+    pub fn to_packed(&self) -> String {
+        format!("{}:{}", self.len, to_hex(&self.bytes))
+    }
+
+    /// Parses bits written by [to_packed](BitString::to_packed), or returns `None` if the string
+    /// isn't in the packed form
+    pub fn from_packed(string: &str) -> Option<Self> {
+        let (len, hex) = string.trim().split_once(':')?;
+        let len: usize = len.parse().ok()?;
+        let bytes = from_hex(hex)?;
+        let bits = BitString { bytes, len };
+        if bits.bytes.len() != (len + 7) / 8 || bits.clear_end().bytes != bits.bytes {
+            return None;
+        }
+        Some(bits)
+    }
+
+    fn mask(index: usize) -> u8 {
+        0x80 >> (index % 8)
+    }
+
+    /// The bits with every bit after the end cleared
+    fn clear_end(&self) -> BitString {
+        let mut bits = self.clone();
+        if let Some(last) = bits.bytes.last_mut() {
+            if bits.len % 8 != 0 {
+                *last &= !(0xFF >> (bits.len % 8));
+            }
+        }
+        bits
+    }
+}
+
+impl Display for BitString {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for index in 0..self.len {
+            let bit = if self.get(index) == Some(true) {
+                '1'
+            } else {
+                '0'
+            };
+            write!(f, "{}", bit)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromIterator<bool> for BitString {
+    fn from_iter<T: IntoIterator<Item = bool>>(iter: T) -> Self {
+        let bits: Vec<bool> = iter.into_iter().collect();
+        let mut string = BitString::zeroes(bits.len());
+        for (index, bit) in bits.into_iter().enumerate() {
+            string.set(index, bit);
+        }
+        string
+    }
+}
+
+impl Not for BitString {
+    type Output = BitString;
+
+    fn not(self) -> BitString {
+        BitString {
+            bytes: self.bytes.iter().map(|byte| !byte).collect(),
+            len: self.len,
+        }
+        .clear_end()
+    }
+}
+
+macro_rules! operator {
+    ($trait:ident, $method:ident, $operator:ident) => {
+        /// # Panic
+        /// Panics if the bit strings aren't the same length
+        impl $trait for BitString {
+            type Output = BitString;
+
+            fn $method(self, other: BitString) -> BitString {
+                match self.apply(BitOperator::$operator, &other) {
+                    Ok(result) => result,
+                    Err(error) => panic!("{}", error),
+                }
+            }
+        }
+    };
+}
+
+operator!(BitAnd, bitand, And);
+operator!(BitOr, bitor, Or);
+operator!(BitXor, bitxor, Xor);
+
+impl Type {
+    /// Applies the bitwise operator to the values, which have to be bit strings or `NULL`. `NULL`
+    /// operands give `NULL`, and the result is of the width of the left operand.
+    pub fn apply_bitwise(&self, operator: BitOperator, other: &Type) -> Result<Type, BitError> {
+        match (self, other) {
+            (Type::Text(Text::BitString(left, width)), Type::Text(Text::BitString(right, _))) => {
+                Ok(Text::BitString(left.apply(operator, right)?, *width).into())
+            }
+            (Type::Optional(None), Type::Text(Text::BitString(..)) | Type::Optional(_))
+            | (Type::Text(Text::BitString(..)), Type::Optional(None)) => Ok(Type::Optional(None)),
+            (Type::Optional(Some(left)), right) => left.apply_bitwise(operator, right),
+            (left, Type::Optional(Some(right))) => left.apply_bitwise(operator, right),
+            _ => Err(BitError::NotBits),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bits() {
+        let mut flags = BitString::parse("10101").unwrap();
+        assert_eq!(flags.len(), 5);
+        assert_eq!(flags.get(2), Some(true));
+        assert_eq!(flags.get(3), Some(false));
+        assert_eq!(flags.get(5), None);
+        assert_eq!(flags.count_ones(), 3);
+        flags.set(3, true);
+        assert_eq!(flags.to_string(), "10111");
+        assert_eq!(BitString::parse("10201"), None);
+        assert_eq!(
+            vec![true, false, true].into_iter().collect::<BitString>(),
+            BitString::parse("101").unwrap()
+        );
+
+        let packed = BitString::parse("1010100001").unwrap().to_packed();
+        assert_eq!(packed, "10:a840");
+        assert_eq!(
+            BitString::from_packed(&packed),
+            BitString::parse("1010100001")
+        );
+        assert_eq!(BitString::from_packed("10:a8"), None);
+        assert_eq!(BitString::from_packed("10:a841"), None);
+    }
+
+    #[test]
+    fn operators() {
+        let left = BitString::parse("1100").unwrap();
+        let right = BitString::parse("1010").unwrap();
+        assert_eq!(
+            left.clone() & right.clone(),
+            BitString::parse("1000").unwrap()
+        );
+        assert_eq!(
+            left.clone() | right.clone(),
+            BitString::parse("1110").unwrap()
+        );
+        assert_eq!(left.clone() ^ right, BitString::parse("0110").unwrap());
+        assert_eq!(!left.clone(), BitString::parse("0011").unwrap());
+        assert_eq!((!left).as_bytes(), &[0x30]);
+        assert_eq!(
+            BitString::parse("1")
+                .unwrap()
+                .apply(BitOperator::Or, &BitString::zeroes(2)),
+            Err(BitError::LengthMismatch { left: 1, right: 2 })
+        );
+
+        let value = |bits: &str| {
+            Type::from(Text::BitString(
+                BitString::parse(bits).unwrap(),
+                BitWidth::Fixed(bits.len() as u16),
+            ))
+        };
+        assert_eq!(
+            value("0110").apply_bitwise(BitOperator::And, &value("1100")),
+            Ok(value("0100"))
+        );
+        assert_eq!(
+            value("0110").apply_bitwise(BitOperator::Xor, &Type::Optional(None)),
+            Ok(Type::Optional(None))
+        );
+        assert_eq!(
+            value("0110").apply_bitwise(BitOperator::Or, &Type::from(3u8)),
+            Err(BitError::NotBits)
+        );
+    }
+
+    #[test]
+    fn order() {
+        let mut strings: Vec<BitString> = vec!["1", "", "0111", "10", "01", "1000000001", "101"]
+            .into_iter()
+            .map(|bits| BitString::parse(bits).unwrap())
+            .collect();
+        strings.sort();
+        let strings: Vec<String> = strings.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            strings,
+            vec!["", "01", "0111", "1", "10", "1000000001", "101"]
+        );
+    }
+}
@@ -0,0 +1,55 @@
+//! Handles of blobs that are stored out of line.
+//!
+//! A relation can store a blob in a file of its own instead of in the block of its tuple, and keep
+//! a [BlobHandle] in the tuple in its place. A handle only means something to the relation that
+//! stored the blob, which reads the blob back a part at a time.
+
+use std::fmt::{Display, Formatter};
+
+/// A blob that a relation stored out of line, by the id of its file and its length in bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct BlobHandle {
+    id: u64,
+    length: u64,
+}
+
+impl BlobHandle {
+    pub fn new(id: u64, length: u64) -> Self {
+        BlobHandle { id, length }
+    }
+
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// The number of bytes in the blob
+    pub fn length(&self) -> u64 {
+        self.length
+    }
+
+    /// Parses a handle in the form it's displayed in, such as `blob:3:1024`
+    pub fn parse(string: &str) -> Option<Self> {
+        let (id, length) = string.strip_prefix("blob:")?.split_once(':')?;
+        Some(BlobHandle::new(id.parse().ok()?, length.parse().ok()?))
+    }
+}
+
+impl Display for BlobHandle {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "blob:{}:{}", self.id, self.length)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_handles() {
+        let handle = BlobHandle::new(3, 1024);
+        assert_eq!(handle.to_string(), "blob:3:1024");
+        assert_eq!(BlobHandle::parse(&handle.to_string()), Some(handle));
+        assert_eq!(BlobHandle::parse("0x6869"), None);
+        assert_eq!(BlobHandle::parse("blob:3"), None);
+    }
+}
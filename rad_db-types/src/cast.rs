@@ -0,0 +1,420 @@
+//! Explicit conversions of values into other kinds of values.
+//!
+//! A kind is given as any value of that kind, the same way the fields of a relation are declared.
+//! Numbers can be cast into any other kind of number they can be represented in exactly, and text
+//! can be parsed into numbers and times, which can be written as text in turn.
+
+use crate::decimal::MAX_PRECISION;
+use crate::integer::MAX_BITS;
+use crate::{
+    BitString, Decimal, Inet, Integer, MacAddress, Numeric, Point, Signed, Text, Time, Type,
+    Unsigned,
+};
+use num_bigint::BigInt;
+use num_traits::FromPrimitive;
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+
+/// Why a value couldn't be cast into a kind
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CastError {
+    /// The value is `NULL`, and the kind isn't optional
+    Null,
+    /// Values of the value's kind are never cast into the kind
+    Unsupported,
+    /// The value can't be represented exactly as the kind, such as a number too large for it or
+    /// a string longer than it allows
+    OutOfRange,
+    /// The text isn't a value of the kind
+    Unparsable(String),
+}
+
+impl Display for CastError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CastError::Null => write!(f, "NULL can only be cast into an optional kind"),
+            CastError::Unsupported => write!(f, "The value can't be cast into the kind"),
+            CastError::OutOfRange => write!(f, "The value doesn't fit in the kind"),
+            CastError::Unparsable(text) => write!(f, "{:?} isn't a value of the kind", text),
+        }
+    }
+}
+
+impl Error for CastError {}
+
+impl Type {
+    /// Casts the value into the kind of `kind`. Numbers are cast if they fit in the kind exactly,
+    /// text is parsed into numbers and times, and numbers, times and labels are written as text.
+    /// `NULL` can only be cast into an optional kind, and other values cast into an optional kind
+    /// are cast into its inner kind.
+    pub fn cast_to(&self, kind: &Type) -> Result<Type, CastError> {
+        match (self, kind) {
+            (Type::Optional(None), Type::Optional(_)) => Ok(Type::Optional(None)),
+            (Type::Optional(None), _) => Err(CastError::Null),
+            (Type::Optional(Some(value)), kind) => value.cast_to(kind),
+            (value, Type::Optional(Some(inner))) => {
+                Ok(Type::Optional(Some(Box::new(value.cast_to(inner)?))))
+            }
+            (value, Type::Optional(None)) => Ok(Type::Optional(Some(Box::new(value.clone())))),
+            (Type::Numeric(value), Type::Numeric(kind)) => {
+                cast_numeric(value.clone(), kind).map(Type::Numeric)
+            }
+            (Type::Text(Text::String(string, _)), Type::Numeric(kind)) => {
+                parse_numeric(string, kind).map(Type::Numeric)
+            }
+            (Type::Text(Text::String(string, _)), Type::Time(kind)) => {
+                parse_time(string, kind).map(Type::Time)
+            }
+            (Type::Text(Text::String(string, _)), Type::Point(_)) => Point::parse(string)
+                .map(Type::Point)
+                .ok_or_else(|| CastError::Unparsable(string.clone())),
+            (Type::Text(Text::String(string, _)), Type::Inet(_)) => Inet::parse(string)
+                .map(Type::Inet)
+                .ok_or_else(|| CastError::Unparsable(string.clone())),
+            (Type::Text(Text::String(string, _)), Type::MacAddress(_)) => MacAddress::parse(string)
+                .map(Type::MacAddress)
+                .ok_or_else(|| CastError::Unparsable(string.clone())),
+            (Type::Text(Text::String(label, _)), Type::Enum(kind)) => kind
+                .with_label(label)
+                .map(Type::Enum)
+                .ok_or_else(|| CastError::Unparsable(label.clone())),
+            (Type::Numeric(value), Type::Text(kind)) => to_text(value.to_string(), kind),
+            (Type::Time(time), Type::Text(kind)) => to_text(write_time(time), kind),
+            (Type::Enum(value), Type::Text(kind)) => to_text(value.label().to_string(), kind),
+            (Type::Point(point), Type::Text(kind)) => to_text(point.to_string(), kind),
+            (Type::Text(Text::BitString(bits, _)), Type::Text(kind)) => {
+                to_text(bits.to_string(), kind)
+            }
+            (Type::Inet(inet), Type::Text(kind)) => to_text(inet.to_string(), kind),
+            (Type::MacAddress(address), Type::Text(kind)) => to_text(address.to_string(), kind),
+            (Type::Text(Text::String(string, _)), Type::Text(kind)) => {
+                to_text(string.clone(), kind)
+            }
+            (Type::Text(Text::Char(c)), Type::Text(kind)) => to_text(c.to_string(), kind),
+            (value, kind) => value.coerce_like(kind).ok_or(CastError::Unsupported),
+        }
+    }
+}
+
+/// The value of an integer, if the number is one
+fn integer(value: &Numeric) -> Option<i128> {
+    match value {
+        Numeric::Signed(signed) => Some(Into::<i64>::into(*signed) as i128),
+        Numeric::Unsigned(unsigned) => Some(Into::<u64>::into(*unsigned) as i128),
+        Numeric::BigInt(integer) => integer.to_i128(),
+        _ => None,
+    }
+}
+
+fn cast_numeric(value: Numeric, kind: &Numeric) -> Result<Numeric, CastError> {
+    match (value, kind) {
+        (Numeric::Float(f), Numeric::Double(_)) => Ok(Numeric::Double(f as f64)),
+        (value, Numeric::Double(_)) => match integer(&value) {
+            Some(i) if (i as f64) as i128 == i => Ok(Numeric::Double(i as f64)),
+            Some(_) => Err(CastError::OutOfRange),
+            None if matches!(value, Numeric::BigInt(_)) => Err(CastError::OutOfRange),
+            // decimals become the nearest double
+            None => Ok(Numeric::Double(value.to_f64())),
+        },
+        (value, Numeric::Float(_)) => {
+            let float = match integer(&value) {
+                Some(i) if (i as f32) as i128 == i => i as f32,
+                Some(_) => return Err(CastError::OutOfRange),
+                None if matches!(value, Numeric::BigInt(_)) => return Err(CastError::OutOfRange),
+                None => value.to_f64() as f32,
+            };
+            // doubles are only narrowed when they don't lose any precision
+            match value {
+                Numeric::Double(d) if float as f64 != d && !d.is_nan() => {
+                    Err(CastError::OutOfRange)
+                }
+                _ => Ok(Numeric::Float(float)),
+            }
+        }
+        (value @ Numeric::Float(_), kind) | (value @ Numeric::Double(_), kind) => {
+            let float = value.to_f64();
+            if !float.is_finite() {
+                return Err(CastError::OutOfRange);
+            }
+            let exact = match kind {
+                Numeric::Decimal(decimal) => {
+                    Decimal::parse(&float.to_string(), decimal.precision(), decimal.scale())
+                        .filter(|exact| exact.to_f64() == float)
+                        .map(Numeric::Decimal)
+                        .ok_or(CastError::OutOfRange)?
+                }
+                Numeric::BigInt(_) if float.fract() == 0.0 => BigInt::from_f64(float)
+                    .and_then(|integer| Integer::new(integer, MAX_BITS))
+                    .map(Numeric::BigInt)
+                    .ok_or(CastError::OutOfRange)?,
+                _ if float.fract() == 0.0 && float.abs() < i64::MAX as f64 => {
+                    Numeric::Signed(Signed::Long(float as i64))
+                }
+                _ if float.fract() == 0.0 && float > 0.0 && float < u64::MAX as f64 => {
+                    Numeric::Unsigned(Unsigned::Long(float as u64))
+                }
+                _ => return Err(CastError::OutOfRange),
+            };
+            cast_numeric(exact, kind)
+        }
+        (value, kind) => match Type::Numeric(value).coerce_like(&Type::Numeric(kind.clone())) {
+            Some(Type::Numeric(numeric)) => Ok(numeric),
+            _ => Err(CastError::OutOfRange),
+        },
+    }
+}
+
+fn parse_numeric(string: &str, kind: &Numeric) -> Result<Numeric, CastError> {
+    let unparsable = || CastError::Unparsable(string.to_string());
+    let trimmed = string.trim();
+    let parsed = match kind {
+        Numeric::Float(_) => Numeric::Float(trimmed.parse().map_err(|_| unparsable())?),
+        Numeric::Double(_) => Numeric::Double(trimmed.parse().map_err(|_| unparsable())?),
+        Numeric::Decimal(decimal) => {
+            Decimal::parse(trimmed, MAX_PRECISION, 0).ok_or_else(unparsable)?;
+            // parsing rounds the digits past the scale, which would change the number
+            let fraction = trimmed.split_once('.').map_or("", |(_, fraction)| fraction);
+            if fraction.trim_end_matches('0').len() > decimal.scale() as usize {
+                return Err(CastError::OutOfRange);
+            }
+            Numeric::Decimal(
+                Decimal::parse(trimmed, MAX_PRECISION, decimal.scale()).ok_or_else(unparsable)?,
+            )
+        }
+        Numeric::BigInt(_) => {
+            Numeric::BigInt(Integer::parse(trimmed, MAX_BITS).ok_or_else(unparsable)?)
+        }
+        Numeric::Signed(_) | Numeric::Unsigned(_) => {
+            let integer: i128 = trimmed.parse().map_err(|_| unparsable())?;
+            if let Ok(signed) = i64::try_from(integer) {
+                Numeric::Signed(Signed::Long(signed))
+            } else {
+                let unsigned = u64::try_from(integer).map_err(|_| CastError::OutOfRange)?;
+                Numeric::Unsigned(Unsigned::Long(unsigned))
+            }
+        }
+    };
+    cast_numeric(parsed, kind)
+}
+
+fn parse_time(string: &str, kind: &Time) -> Result<Time, CastError> {
+    let unparsable = || CastError::Unparsable(string.to_string());
+    let trimmed = string.trim();
+    match kind {
+        Time::Date(_) => Time::parse_date(trimmed).map_err(|_| unparsable()),
+        Time::TimeOfDay(_) => Time::parse_time_of_day(trimmed).map_err(|_| unparsable()),
+        Time::DateTime(_) => Time::parse_date_time(trimmed).map_err(|_| unparsable()),
+        Time::Timestamp(_) => Time::parse_timestamp(trimmed).map_err(|_| unparsable()),
+        Time::TimestampTz(_) => Time::parse_timestamp_tz(trimmed).map_err(|_| unparsable()),
+        Time::Year(_) => trimmed.parse().map(Time::Year).map_err(|_| unparsable()),
+    }
+}
+
+/// Writes a time the way [parse_time] parses it
+fn write_time(time: &Time) -> String {
+    time.to_string()
+}
+
+fn to_text(string: String, kind: &Text) -> Result<Type, CastError> {
+    let text = match kind {
+        Text::String(_, limit) => {
+            if limit.map_or(false, |limit| string.chars().count() > limit as usize) {
+                return Err(CastError::OutOfRange);
+            }
+            Text::String(string, *limit)
+        }
+        Text::BitString(_, width) => {
+            let bits = BitString::parse(&string).ok_or(CastError::Unparsable(string))?;
+            if !width.allows(bits.len()) {
+                return Err(CastError::OutOfRange);
+            }
+            Text::BitString(bits, *width)
+        }
+        Text::Char(_) => {
+            let mut chars = string.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => Text::Char(c),
+                _ => return Err(CastError::OutOfRange),
+            }
+        }
+        _ => return Err(CastError::Unsupported),
+    };
+    Ok(Type::Text(text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BitWidth;
+    use std::net::Ipv4Addr;
+
+    #[test]
+    fn numbers() {
+        assert_eq!(
+            Type::from(7u64).cast_to(&Type::from(0i32)),
+            Ok(Type::from(7i32))
+        );
+        assert_eq!(
+            Type::from(-1i8).cast_to(&Type::from(0u64)),
+            Err(CastError::OutOfRange)
+        );
+        assert_eq!(
+            Type::from(300u32).cast_to(&Type::from(0u8)),
+            Err(CastError::OutOfRange)
+        );
+        let double = Type::Numeric(Numeric::Double(0.0));
+        assert_eq!(
+            Type::from(3i16).cast_to(&double),
+            Ok(Numeric::Double(3.0).into())
+        );
+        assert_eq!(
+            Type::Numeric(Numeric::Double(2.0)).cast_to(&Type::from(0u8)),
+            Ok(Type::from(2u8))
+        );
+        assert_eq!(
+            Type::Numeric(Numeric::Double(2.5)).cast_to(&Type::from(0u8)),
+            Err(CastError::OutOfRange)
+        );
+        assert_eq!(
+            Type::Numeric(Numeric::Double(0.1)).cast_to(&Type::Numeric(Numeric::Float(0.0))),
+            Err(CastError::OutOfRange)
+        );
+        let cents = Type::from(Decimal::zero(6, 2));
+        assert_eq!(
+            Type::Numeric(Numeric::Double(0.25)).cast_to(&cents),
+            Ok(Decimal::parse("0.25", 6, 2).unwrap().into())
+        );
+        assert_eq!(
+            Type::from(u64::MAX).cast_to(&double),
+            Err(CastError::OutOfRange)
+        );
+    }
+
+    #[test]
+    fn wide_integers() {
+        let wide = Type::from(Integer::zero(128));
+        assert_eq!(
+            Type::from(u64::MAX).cast_to(&wide),
+            Ok(Integer::new(u64::MAX, 128).unwrap().into())
+        );
+        assert_eq!(
+            Type::from(Integer::from(1i128 << 100)).cast_to(&Type::from(0i64)),
+            Err(CastError::OutOfRange)
+        );
+        assert_eq!(
+            Type::from(Integer::from(-5i128)).cast_to(&Type::from(0i8)),
+            Ok(Type::from(-5i8))
+        );
+        assert_eq!(
+            Type::from("170141183460469231731687303715884105727").cast_to(&wide),
+            Ok(Integer::from(i128::MAX).into())
+        );
+        assert_eq!(
+            Type::from("170141183460469231731687303715884105728").cast_to(&wide),
+            Err(CastError::OutOfRange)
+        );
+        assert_eq!(
+            Type::Numeric(Numeric::Double(2f64.powi(100))).cast_to(&wide),
+            Ok(Integer::new(1i128 << 100, 128).unwrap().into())
+        );
+    }
+
+    #[test]
+    fn text() {
+        assert_eq!(
+            Type::from(" 42 ").cast_to(&Type::from(0i64)),
+            Ok(Type::from(42i64))
+        );
+        let origin = Type::from(Point::new(0.0, 0.0));
+        assert_eq!(
+            Type::from("(1.5, -2)").cast_to(&origin),
+            Ok(Point::new(1.5, -2.0).into())
+        );
+        assert_eq!(origin.cast_to(&Type::from("")), Ok(Type::from("(0, 0)")));
+        let flags = Type::from(Text::BitString(BitString::zeroes(3), BitWidth::Fixed(3)));
+        assert_eq!(
+            Type::from("101").cast_to(&flags),
+            Ok(Text::BitString(BitString::parse("101").unwrap(), BitWidth::Fixed(3)).into())
+        );
+        assert_eq!(Type::from("10").cast_to(&flags), Err(CastError::OutOfRange));
+        assert_eq!(flags.cast_to(&Type::from("")), Ok(Type::from("000")));
+        let subnet = Inet::parse("10.0.0.0/8").unwrap();
+        assert_eq!(
+            Type::from("10.0.0.0/8").cast_to(&Inet::host(Ipv4Addr::LOCALHOST).into()),
+            Ok(subnet.into())
+        );
+        assert_eq!(
+            Type::from(subnet).cast_to(&Type::from("")),
+            Ok(Type::from("10.0.0.0/8"))
+        );
+        assert_eq!(
+            Type::from("08-00-2b-01-02-03").cast_to(&MacAddress::new([0; 6]).into()),
+            Ok(MacAddress::new([0x08, 0x00, 0x2b, 0x01, 0x02, 0x03]).into())
+        );
+        assert_eq!(
+            Type::from("4x").cast_to(&Type::from(0i64)),
+            Err(CastError::Unparsable("4x".to_string()))
+        );
+        let cents = Type::from(Decimal::zero(6, 2));
+        assert_eq!(
+            Type::from("12.5").cast_to(&cents),
+            Ok(Decimal::parse("12.50", 6, 2).unwrap().into())
+        );
+        assert_eq!(
+            Type::from("12.505").cast_to(&cents),
+            Err(CastError::OutOfRange)
+        );
+        assert_eq!(
+            Type::from(-12i32).cast_to(&Type::from("")),
+            Ok(Type::from("-12"))
+        );
+        let short = Type::Text(Text::String(String::new(), Some(2)));
+        assert_eq!(
+            Type::from(123u8).cast_to(&short),
+            Err(CastError::OutOfRange)
+        );
+        assert_eq!(
+            Type::from("x").cast_to(&Type::Text(Text::Char(' '))),
+            Ok(Type::Text(Text::Char('x')))
+        );
+        assert_eq!(
+            Type::from(true).cast_to(&Type::from(0u8)),
+            Err(CastError::Unsupported)
+        );
+    }
+
+    #[test]
+    fn times() {
+        let date = Type::from(Time::date(2021, 12, 25).unwrap());
+        assert_eq!(Type::from("2021-12-25").cast_to(&date), Ok(date.clone()));
+        assert_eq!(date.cast_to(&Type::from("")), Ok(Type::from("2021-12-25")));
+        assert!(Type::from("2021-13-25").cast_to(&date).is_err());
+
+        let now = Type::from(Time::Timestamp(chrono::Utc::now()));
+        let written = now.cast_to(&Type::from("")).unwrap();
+        assert_eq!(written.cast_to(&now), Ok(now));
+
+        let zoned = Type::from(Time::parse_timestamp_tz("2021-12-25T10:00:00-05:00").unwrap());
+        let written = zoned.cast_to(&Type::from("")).unwrap();
+        assert_eq!(written, Type::from("2021-12-25T10:00:00-05:00"));
+        assert_eq!(written.cast_to(&zoned), Ok(zoned));
+    }
+
+    #[test]
+    fn optional() {
+        let nullable = Type::Optional(Some(Box::new(Type::from(0i32))));
+        assert_eq!(
+            Type::from("5").cast_to(&nullable),
+            Ok(Type::Optional(Some(Box::new(Type::from(5i32)))))
+        );
+        assert_eq!(
+            Type::Optional(None).cast_to(&nullable),
+            Ok(Type::Optional(None))
+        );
+        assert_eq!(
+            Type::Optional(None).cast_to(&Type::from(0i32)),
+            Err(CastError::Null)
+        );
+    }
+}
@@ -0,0 +1,955 @@
+use crate::decimal::MAX_PRECISION;
+use crate::{Decimal, Integer, Numeric, Signed, Text, Time, Type, Unsigned};
+use num_bigint::BigInt;
+use num_traits::FromPrimitive;
+use std::cmp::{max, Ordering};
+use std::convert::TryFrom;
+
+/// Determines how two text values are compared against each other
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TextComparison {
+    /// Text is compared byte by byte
+    #[default]
+    Exact,
+    /// The case of letters is ignored
+    CaseInsensitive,
+    /// Trailing spaces are ignored, like the semantics of a `CHAR` column
+    TrimTrailing,
+    /// Both the case of letters and trailing spaces are ignored
+    CaseInsensitiveTrimmed,
+}
+
+impl TextComparison {
+    fn ignores_case(&self) -> bool {
+        matches!(
+            self,
+            TextComparison::CaseInsensitive | TextComparison::CaseInsensitiveTrimmed
+        )
+    }
+
+    fn ignores_trailing(&self) -> bool {
+        matches!(
+            self,
+            TextComparison::TrimTrailing | TextComparison::CaseInsensitiveTrimmed
+        )
+    }
+
+    /// Checks if two strings are equal under this comparison mode
+    pub fn equals(&self, left: &str, right: &str) -> bool {
+        let (left, right) = if self.ignores_trailing() {
+            (left.trim_end_matches(' '), right.trim_end_matches(' '))
+        } else {
+            (left, right)
+        };
+
+        if self.ignores_case() {
+            left.chars()
+                .flat_map(char::to_lowercase)
+                .eq(right.chars().flat_map(char::to_lowercase))
+        } else {
+            left == right
+        }
+    }
+
+    /// Converts a string into the form that is equal to the normalized form of every string it's
+    /// equal to under this comparison mode, so that equal strings can be hashed the same
+    pub fn normalize(&self, text: &str) -> String {
+        let text = if self.ignores_trailing() {
+            text.trim_end_matches(' ')
+        } else {
+            text
+        };
+        if self.ignores_case() {
+            text.chars().flat_map(char::to_lowercase).collect()
+        } else {
+            text.to_string()
+        }
+    }
+
+    /// Checks if a string matches a `LIKE` pattern under this comparison mode, where `%` matches
+    /// any number of characters and `_` matches exactly one. There is no escape character.
+    pub fn like(&self, text: &str, pattern: &str) -> bool {
+        let text = if self.ignores_trailing() {
+            text.trim_end_matches(' ')
+        } else {
+            text
+        };
+        let chars = |s: &str| -> Vec<char> {
+            if self.ignores_case() {
+                s.chars().flat_map(char::to_lowercase).collect()
+            } else {
+                s.chars().collect()
+            }
+        };
+        like_chars(&chars(text), &chars(pattern))
+    }
+}
+
+fn like_chars(text: &[char], pattern: &[char]) -> bool {
+    let (mut t, mut p) = (0, 0);
+    // where to retry from if the characters after the last `%` stop matching
+    let mut retry: Option<(usize, usize)> = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('%') => {
+                p += 1;
+                retry = Some((p, t));
+            }
+            Some(c) if *c == '_' || *c == text[t] => {
+                t += 1;
+                p += 1;
+            }
+            _ => match retry {
+                Some((after, start)) => {
+                    p = after;
+                    t = start + 1;
+                    retry = Some((after, start + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '%')
+}
+
+impl Text {
+    /// Gets the textual contents of this value, if it's a character based value
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Text::String(s, _) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// Checks if two text values are equal using a comparison mode. Characters and strings can
+    /// be compared to each other, while binary values are always compared exactly.
+    pub fn eq_with(&self, other: &Text, comparison: TextComparison) -> bool {
+        let mut left_buffer = [0; 4];
+        let mut right_buffer = [0; 4];
+        let left = match self {
+            Text::Char(c) => Some(&*c.encode_utf8(&mut left_buffer)),
+            other => other.as_str(),
+        };
+        let right = match other {
+            Text::Char(c) => Some(&*c.encode_utf8(&mut right_buffer)),
+            other => other.as_str(),
+        };
+
+        match (left, right) {
+            (Some(left), Some(right)) => comparison.equals(left, right),
+            _ => self == other,
+        }
+    }
+}
+
+impl Numeric {
+    /// Converts the numeric into a double, potentially losing precision
+    pub(crate) fn to_f64(&self) -> f64 {
+        match self {
+            Numeric::Float(f) => *f as f64,
+            Numeric::Double(d) => *d,
+            Numeric::Signed(s) => Into::<i64>::into(*s) as f64,
+            Numeric::Unsigned(u) => Into::<u64>::into(*u) as f64,
+            Numeric::Decimal(d) => d.to_f64(),
+            Numeric::BigInt(i) => i.to_f64(),
+        }
+    }
+
+    /// Orders two numbers by their values, exactly, whatever their variants. `NaN` is ordered
+    /// after every other number and is equal to itself, and `-0.0` is equal to `0.0`.
+    pub fn numeric_cmp(&self, other: &Numeric) -> Ordering {
+        match (Exact::of(self), Exact::of(other)) {
+            (Exact::Float(left), right) => compare_float(left, right),
+            (left, Exact::Float(right)) => compare_float(right, left).reverse(),
+            (Exact::Big(left), right) => compare_big(&left, right),
+            (left, Exact::Big(right)) => compare_big(&right, left).reverse(),
+            (Exact::Integer(left), Exact::Integer(right)) => left.cmp(&right),
+            (left, right) => left.decimal().cmp(&right.decimal()),
+        }
+    }
+
+    /// The order of numbers with the same value but different variants, so that numbers are only
+    /// equal when they're the same
+    fn rank(&self) -> u8 {
+        match self {
+            Numeric::Signed(Signed::Byte(_)) => 0,
+            Numeric::Signed(Signed::Short(_)) => 1,
+            Numeric::Signed(Signed::Int(_)) => 2,
+            Numeric::Signed(Signed::Long(_)) => 3,
+            Numeric::Unsigned(Unsigned::Byte(_)) => 4,
+            Numeric::Unsigned(Unsigned::Short(_)) => 5,
+            Numeric::Unsigned(Unsigned::Int(_)) => 6,
+            Numeric::Unsigned(Unsigned::Long(_)) => 7,
+            Numeric::BigInt(_) => 8,
+            Numeric::Decimal(_) => 9,
+            Numeric::Float(_) => 10,
+            Numeric::Double(_) => 11,
+        }
+    }
+
+    /// Promotes two numeric values into the same variant so they can be compared. Integers are
+    /// widened into 64 bit integers, or into integers of the wider bits when either is wider than
+    /// 64 bits. Decimals and the integers compared to them become decimals with the same scale,
+    /// and anything involving floating point values becomes a double.
+    pub fn promote_pair(self, other: Numeric) -> (Numeric, Numeric) {
+        match (self, other) {
+            (Numeric::Signed(left), Numeric::Signed(right)) => (
+                Numeric::Signed(Signed::Long(left.into())),
+                Numeric::Signed(Signed::Long(right.into())),
+            ),
+            (Numeric::Unsigned(left), Numeric::Unsigned(right)) => (
+                Numeric::Unsigned(Unsigned::Long(left.into())),
+                Numeric::Unsigned(Unsigned::Long(right.into())),
+            ),
+            (Numeric::Signed(signed), Numeric::Unsigned(unsigned)) => {
+                promote_mixed(signed, unsigned)
+            }
+            (Numeric::Unsigned(unsigned), Numeric::Signed(signed)) => {
+                let (signed, unsigned) = promote_mixed(signed, unsigned);
+                (unsigned, signed)
+            }
+            (left, right) => promote_big_integers(&left, &right)
+                .or_else(|| promote_decimals(&left, &right))
+                .unwrap_or_else(|| {
+                    (
+                        Numeric::Double(left.to_f64()),
+                        Numeric::Double(right.to_f64()),
+                    )
+                }),
+        }
+    }
+}
+
+impl PartialEq for Numeric {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Numeric {}
+
+impl PartialOrd for Numeric {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Numeric {
+    /// Orders numbers by their [values](Numeric::numeric_cmp), and numbers with the same value by
+    /// their variants
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.numeric_cmp(other)
+            .then_with(|| self.rank().cmp(&other.rank()))
+    }
+}
+
+/// A number as a value that can be compared exactly
+#[derive(Clone)]
+enum Exact {
+    Integer(i128),
+    Decimal(Decimal),
+    Float(f64),
+    Big(BigInt),
+}
+
+impl Exact {
+    fn of(numeric: &Numeric) -> Self {
+        match numeric {
+            Numeric::Float(float) => Exact::Float(*float as f64),
+            Numeric::Double(double) => Exact::Float(*double),
+            Numeric::Signed(signed) => Exact::Integer(Into::<i64>::into(*signed) as i128),
+            Numeric::Unsigned(unsigned) => Exact::Integer(Into::<u64>::into(*unsigned) as i128),
+            Numeric::Decimal(decimal) => Exact::Decimal(*decimal),
+            Numeric::BigInt(integer) => match integer.to_i128() {
+                Some(integer) => Exact::Integer(integer),
+                None => Exact::Big(integer.value().clone()),
+            },
+        }
+    }
+
+    /// The number as a decimal, if it isn't a float
+    fn decimal(self) -> Decimal {
+        match self {
+            Exact::Integer(integer) => {
+                Decimal::from_integer(integer, 0).expect("64 bit integers fit in a decimal")
+            }
+            Exact::Decimal(decimal) => decimal,
+            Exact::Float(_) | Exact::Big(_) => {
+                unreachable!("floats and wide integers aren't compared as decimals")
+            }
+        }
+    }
+}
+
+/// Compares an integer wider than 128 bits to a number that isn't a float
+fn compare_big(big: &BigInt, other: Exact) -> Ordering {
+    match other {
+        Exact::Big(other) => big.cmp(&other),
+        Exact::Integer(integer) => big.cmp(&BigInt::from(integer)),
+        // the integer part decides, unless it's equal and the decimal has a fraction
+        Exact::Decimal(decimal) => {
+            let unit = 10i128.pow(decimal.scale() as u32);
+            big.cmp(&BigInt::from(decimal.mantissa() / unit))
+                .then_with(|| 0.cmp(&(decimal.mantissa() % unit)))
+        }
+        Exact::Float(_) => unreachable!("floats are compared with compare_float"),
+    }
+}
+
+/// Compares a float to a number, with `NaN` after every other number
+fn compare_float(float: f64, other: Exact) -> Ordering {
+    match other {
+        Exact::Float(other) => match (float.is_nan(), other.is_nan()) {
+            (true, true) => Ordering::Equal,
+            (true, false) => Ordering::Greater,
+            (false, true) => Ordering::Less,
+            (false, false) => float.partial_cmp(&other).unwrap(),
+        },
+        _ if float.is_nan() => Ordering::Greater,
+        Exact::Integer(integer) => compare_parts(float, integer, 0.0),
+        Exact::Big(_) if float.is_infinite() => float.partial_cmp(&0.0).unwrap(),
+        Exact::Big(big) => {
+            let truncated = BigInt::from_f64(float.trunc()).expect("Finite floats are integers");
+            truncated
+                .cmp(&big)
+                .then_with(|| float.fract().partial_cmp(&0.0).unwrap())
+        }
+        Exact::Decimal(decimal) => {
+            let unit = 10i128.pow(decimal.scale() as u32);
+            let fraction = (decimal.mantissa() % unit) as f64 / unit as f64;
+            compare_parts(float, decimal.mantissa() / unit, fraction)
+        }
+    }
+}
+
+/// Compares a float that isn't `NaN` to a number split into its integer part and its fraction,
+/// which has the same sign, so that large integers aren't rounded into floats
+fn compare_parts(float: f64, integer: i128, fraction: f64) -> Ordering {
+    let limit = 2f64.powi(127);
+    if float >= limit {
+        Ordering::Greater
+    } else if float < -limit {
+        Ordering::Less
+    } else {
+        (float.trunc() as i128)
+            .cmp(&integer)
+            .then_with(|| float.fract().partial_cmp(&fraction).unwrap())
+    }
+}
+
+/// Promotes integers into integers of the wider bits when either is wider than 64 bits, or
+/// returns `None` if either isn't an integer or neither is wider
+fn promote_big_integers(left: &Numeric, right: &Numeric) -> Option<(Numeric, Numeric)> {
+    if !matches!(left, Numeric::BigInt(_)) && !matches!(right, Numeric::BigInt(_)) {
+        return None;
+    }
+    let (left, right) = (Integer::of(left)?, Integer::of(right)?);
+    let bits = max(left.bits(), right.bits());
+    Some((
+        Numeric::BigInt(left.with_bits(bits)?),
+        Numeric::BigInt(right.with_bits(bits)?),
+    ))
+}
+
+/// Promotes decimals and integers into decimals with the same scale, or returns `None` if either
+/// is a floating point value or they don't both fit
+fn promote_decimals(left: &Numeric, right: &Numeric) -> Option<(Numeric, Numeric)> {
+    let as_decimal = |numeric: &Numeric| match numeric {
+        Numeric::Decimal(decimal) => Some(*decimal),
+        Numeric::Signed(signed) => Decimal::from_integer(Into::<i64>::into(*signed) as i128, 0),
+        Numeric::Unsigned(unsigned) => {
+            Decimal::from_integer(Into::<u64>::into(*unsigned) as i128, 0)
+        }
+        Numeric::BigInt(integer) => Decimal::from_integer(integer.to_i128()?, 0),
+        Numeric::Float(_) | Numeric::Double(_) => None,
+    };
+    let (left, right) = (as_decimal(left)?, as_decimal(right)?);
+    let scale = max(left.scale(), right.scale());
+    Some((
+        Numeric::Decimal(left.with_precision(MAX_PRECISION, scale)?),
+        Numeric::Decimal(right.with_precision(MAX_PRECISION, scale)?),
+    ))
+}
+
+/// Promotes a signed and unsigned integer into the same variant, returned in that order. Only
+/// when neither 64 bit representation can hold both values is a double used.
+fn promote_mixed(signed: Signed, unsigned: Unsigned) -> (Numeric, Numeric) {
+    let signed: i64 = signed.into();
+    let unsigned: u64 = unsigned.into();
+    if let Ok(unsigned) = i64::try_from(unsigned) {
+        (
+            Numeric::Signed(Signed::Long(signed)),
+            Numeric::Signed(Signed::Long(unsigned)),
+        )
+    } else if let Ok(signed) = u64::try_from(signed) {
+        (
+            Numeric::Unsigned(Unsigned::Long(signed)),
+            Numeric::Unsigned(Unsigned::Long(unsigned)),
+        )
+    } else {
+        (
+            Numeric::Double(signed as f64),
+            Numeric::Double(unsigned as f64),
+        )
+    }
+}
+
+impl Type {
+    /// Promotes two numeric values into the same variant, or returns `None` if either value
+    /// isn't numeric
+    pub fn promote_pair(&self, other: &Type) -> Option<(Type, Type)> {
+        match (self, other) {
+            (Type::Numeric(left), Type::Numeric(right)) => {
+                let (left, right) = left.clone().promote_pair(right.clone());
+                Some((left.into(), right.into()))
+            }
+            _ => None,
+        }
+    }
+
+    /// Checks if two values are equal, comparing numbers by their [values](Numeric::numeric_cmp)
+    /// so that numbers of different widths can be equal. Non-numeric values use normal equality.
+    pub fn numeric_eq(&self, other: &Type) -> bool {
+        match (self, other) {
+            (Type::Optional(Some(inner)), other) => inner.numeric_eq(other),
+            (this, Type::Optional(Some(inner))) => this.numeric_eq(inner),
+            (Type::Numeric(left), Type::Numeric(right)) => {
+                left.numeric_cmp(right) == Ordering::Equal
+            }
+            _ => self == other,
+        }
+    }
+
+    /// Orders two values, with numbers ordered by their [values](Numeric::numeric_cmp) whatever
+    /// their variants. Characters and strings are ordered by their text, and times and extension
+    /// values are only ordered against values of the same kind. Arrays are ordered by their
+    /// elements, with an array before any longer array it starts. Enumerations are ordered by the
+    /// order their labels were declared in, and text is ordered against them as the label it
+    /// names. Values that have no ordering between them, including `NULL`, return `None`.
+    pub fn compare(&self, other: &Type) -> Option<Ordering> {
+        match (self, other) {
+            (Type::Optional(Some(left)), right) => left.compare(right),
+            (left, Type::Optional(Some(right))) => left.compare(right),
+            (Type::Numeric(left), Type::Numeric(right)) => Some(left.numeric_cmp(right)),
+            (Type::Text(Text::BitString(left, _)), Type::Text(Text::BitString(right, _))) => {
+                Some(left.cmp(right))
+            }
+            (Type::Text(left), Type::Text(right)) => {
+                let mut left_buffer = [0; 4];
+                let mut right_buffer = [0; 4];
+                let left = match left {
+                    Text::Char(c) => Some(&*c.encode_utf8(&mut left_buffer)),
+                    other => other.as_str(),
+                };
+                let right = match right {
+                    Text::Char(c) => Some(&*c.encode_utf8(&mut right_buffer)),
+                    other => other.as_str(),
+                };
+                Some(left?.cmp(right?))
+            }
+            (Type::Boolean(left), Type::Boolean(right)) => Some(left.cmp(right)),
+            // points are ordered by their first coordinate, then their second
+            (Type::Point(left), Type::Point(right)) => match left.x().partial_cmp(&right.x())? {
+                Ordering::Equal => left.y().partial_cmp(&right.y()),
+                ordering => Some(ordering),
+            },
+            (Type::Inet(left), Type::Inet(right)) => Some(left.cmp(right)),
+            (Type::MacAddress(left), Type::MacAddress(right)) => Some(left.cmp(right)),
+            (Type::Time(left), Type::Time(right)) => match (left, right) {
+                (Time::Date(left), Time::Date(right)) => Some(left.cmp(right)),
+                (Time::TimeOfDay(left), Time::TimeOfDay(right)) => Some(left.cmp(right)),
+                (Time::DateTime(left), Time::DateTime(right)) => Some(left.cmp(right)),
+                (Time::Timestamp(left), Time::Timestamp(right)) => Some(left.cmp(right)),
+                (Time::TimestampTz(left), Time::TimestampTz(right)) => Some(left.cmp(right)),
+                _ => None,
+            },
+            // custom types are only ordered through the catalog that declares them
+            (Type::Extension(left_id, left), Type::Extension(right_id, right))
+                if left_id == right_id && left == right =>
+            {
+                Some(Ordering::Equal)
+            }
+            (Type::Array(left, _), Type::Array(right, _)) => {
+                for (left, right) in left.iter().zip(right) {
+                    match left.compare(right)? {
+                        Ordering::Equal => {}
+                        ordering => return Some(ordering),
+                    }
+                }
+                Some(left.len().cmp(&right.len()))
+            }
+            (Type::Enum(left), Type::Enum(right)) if left.same_labels(right) => {
+                Some(left.ordinal().cmp(&right.ordinal()))
+            }
+            (Type::Enum(left), Type::Text(right)) => {
+                let right = left.with_label(right.as_str()?)?;
+                Some(left.ordinal().cmp(&right.ordinal()))
+            }
+            (Type::Text(_), Type::Enum(_)) => other.compare(self).map(Ordering::reverse),
+            _ => None,
+        }
+    }
+
+    /// Converts the value into the same variant as `like`, such as an integer of the same width,
+    /// so that it hashes the same as the values of that type. Returns `None` if the value can't
+    /// be represented exactly as that variant.
+    pub fn coerce_like(&self, like: &Type) -> Option<Type> {
+        let integer = |value: &Type| -> Option<i128> {
+            match value {
+                Type::Numeric(Numeric::Signed(signed)) => Some(Into::<i64>::into(*signed) as i128),
+                Type::Numeric(Numeric::Unsigned(unsigned)) => {
+                    Some(Into::<u64>::into(*unsigned) as i128)
+                }
+                Type::Numeric(Numeric::Decimal(decimal)) => decimal.to_integer(),
+                Type::Numeric(Numeric::BigInt(integer)) => integer.to_i128(),
+                _ => None,
+            }
+        };
+        let coerced = match (self, like) {
+            (value, Type::Numeric(Numeric::Signed(width))) => {
+                let value = integer(value)?;
+                let signed = match width {
+                    Signed::Byte(_) => Signed::Byte(i8::try_from(value).ok()?),
+                    Signed::Short(_) => Signed::Short(i16::try_from(value).ok()?),
+                    Signed::Int(_) => Signed::Int(i32::try_from(value).ok()?),
+                    Signed::Long(_) => Signed::Long(i64::try_from(value).ok()?),
+                };
+                signed.into()
+            }
+            (value, Type::Numeric(Numeric::Unsigned(width))) => {
+                let value = integer(value)?;
+                let unsigned = match width {
+                    Unsigned::Byte(_) => Unsigned::Byte(u8::try_from(value).ok()?),
+                    Unsigned::Short(_) => Unsigned::Short(u16::try_from(value).ok()?),
+                    Unsigned::Int(_) => Unsigned::Int(u32::try_from(value).ok()?),
+                    Unsigned::Long(_) => Unsigned::Long(u64::try_from(value).ok()?),
+                };
+                unsigned.into()
+            }
+            (Type::Numeric(numeric), Type::Numeric(Numeric::BigInt(like))) => {
+                let integer = match numeric {
+                    Numeric::Decimal(decimal) => Integer::from(decimal.to_integer()?),
+                    numeric => Integer::of(numeric)?,
+                };
+                integer.with_bits(like.bits())?.into()
+            }
+            (value, Type::Numeric(Numeric::Decimal(like))) => {
+                let decimal = match value {
+                    Type::Numeric(Numeric::Decimal(decimal)) => *decimal,
+                    value => Decimal::from_integer(integer(value)?, 0)?,
+                };
+                let coerced = decimal.with_precision(like.precision(), like.scale())?;
+                // rounding would change the value
+                if coerced != decimal {
+                    return None;
+                }
+                coerced.into()
+            }
+            (Type::Text(Text::String(string, _)), Type::Text(Text::String(_, limit))) => {
+                if limit.map_or(false, |limit| string.chars().count() > limit as usize) {
+                    return None;
+                }
+                Type::Text(Text::String(string.clone(), *limit))
+            }
+            (Type::Text(Text::Char(c)), Type::Text(Text::Char(_))) => Type::Text(Text::Char(*c)),
+            (Type::Text(Text::BitString(bits, _)), Type::Text(Text::BitString(_, width))) => {
+                if !width.allows(bits.len()) {
+                    return None;
+                }
+                Type::Text(Text::BitString(bits.clone(), *width))
+            }
+            (Type::Boolean(b), Type::Boolean(_)) => Type::Boolean(*b),
+            (Type::Point(point), Type::Point(_)) => Type::Point(*point),
+            (Type::Inet(inet), Type::Inet(_)) => Type::Inet(*inet),
+            (Type::MacAddress(address), Type::MacAddress(_)) => Type::MacAddress(*address),
+            (Type::Text(Text::String(label, _)), Type::Enum(like)) => {
+                like.with_label(label)?.into()
+            }
+            (Type::Enum(e), Type::Enum(like)) if e.same_labels(like) => e.clone().into(),
+            _ => return None,
+        };
+        Some(coerced)
+    }
+
+    /// The text of a value, if it's a character, string or label that isn't `NULL`
+    fn native_text(&self) -> Option<NativeText<'_>> {
+        match self {
+            Type::Optional(Some(inner)) => inner.native_text(),
+            Type::Enum(e) => Some(NativeText::String(e.label())),
+            Type::Text(Text::Char(c)) => Some(NativeText::Char(*c)),
+            Type::Text(Text::String(string, _)) => Some(NativeText::String(string)),
+            _ => None,
+        }
+    }
+}
+
+enum NativeText<'a> {
+    Char(char),
+    String(&'a str),
+}
+
+impl NativeText<'_> {
+    fn equals(&self, other: &str) -> bool {
+        match self {
+            NativeText::Char(c) => {
+                let mut chars = other.chars();
+                chars.next() == Some(*c) && chars.next().is_none()
+            }
+            NativeText::String(string) => *string == other,
+        }
+    }
+}
+
+/// Lets values be compared to native numbers, with the same promotion as
+/// [numeric_eq](Type::numeric_eq)
+macro_rules! native_numeric_eq {
+    ($($native:ty => $to_value:expr),* $(,)?) => {
+        $(
+            impl PartialEq<$native> for Type {
+                fn eq(&self, other: &$native) -> bool {
+                    let to_value: fn($native) -> Type = $to_value;
+                    self.numeric_eq(&to_value(*other))
+                }
+            }
+
+            impl PartialEq<Type> for $native {
+                fn eq(&self, other: &Type) -> bool {
+                    other == self
+                }
+            }
+        )*
+    };
+}
+
+native_numeric_eq! {
+    i8 => Type::from,
+    i16 => Type::from,
+    i32 => Type::from,
+    i64 => Type::from,
+    u8 => Type::from,
+    u16 => Type::from,
+    u32 => Type::from,
+    u64 => Type::from,
+    f32 => |f| Numeric::Float(f).into(),
+    f64 => |d| Numeric::Double(d).into(),
+    Decimal => Type::from,
+}
+
+impl PartialEq<bool> for Type {
+    fn eq(&self, other: &bool) -> bool {
+        match self {
+            Type::Optional(Some(inner)) => **inner == *other,
+            Type::Boolean(b) => b == other,
+            _ => false,
+        }
+    }
+}
+
+impl PartialEq<Type> for bool {
+    fn eq(&self, other: &Type) -> bool {
+        other == self
+    }
+}
+
+impl PartialEq<str> for Type {
+    fn eq(&self, other: &str) -> bool {
+        self.native_text().map_or(false, |text| text.equals(other))
+    }
+}
+
+impl PartialEq<&str> for Type {
+    fn eq(&self, other: &&str) -> bool {
+        self == *other
+    }
+}
+
+impl PartialEq<String> for Type {
+    fn eq(&self, other: &String) -> bool {
+        self == other.as_str()
+    }
+}
+
+impl PartialEq<char> for Type {
+    fn eq(&self, other: &char) -> bool {
+        self == other.encode_utf8(&mut [0; 4]) as &str
+    }
+}
+
+impl PartialEq<Type> for str {
+    fn eq(&self, other: &Type) -> bool {
+        other == self
+    }
+}
+
+impl PartialEq<Type> for &str {
+    fn eq(&self, other: &Type) -> bool {
+        other == *self
+    }
+}
+
+impl PartialEq<Type> for String {
+    fn eq(&self, other: &Type) -> bool {
+        other == self
+    }
+}
+
+impl PartialEq<Type> for char {
+    fn eq(&self, other: &Type) -> bool {
+        other == self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Enumeration;
+
+    #[test]
+    fn case_insensitive() {
+        let comparison = TextComparison::CaseInsensitive;
+        assert!(comparison.equals("Hello", "hELLO"));
+        assert!(!comparison.equals("Hello ", "hello"));
+        assert!(!TextComparison::Exact.equals("Hello", "hello"));
+    }
+
+    #[test]
+    fn trimmed() {
+        let comparison = TextComparison::TrimTrailing;
+        assert!(comparison.equals("abc   ", "abc"));
+        assert!(!comparison.equals("  abc", "abc"));
+        assert!(!comparison.equals("ABC", "abc"));
+        assert!(TextComparison::CaseInsensitiveTrimmed.equals("ABC  ", "abc"));
+        assert_eq!(
+            TextComparison::CaseInsensitiveTrimmed.normalize("ABC  "),
+            TextComparison::CaseInsensitiveTrimmed.normalize("abc")
+        );
+    }
+
+    #[test]
+    fn like() {
+        let exact = TextComparison::Exact;
+        assert!(exact.like("abcdef", "abc%"));
+        assert!(exact.like("abc", "abc%"));
+        assert!(!exact.like("xabc", "abc%"));
+        assert!(exact.like("abcdef", "a_c%f"));
+        assert!(!exact.like("abcdef", "a_c%e"));
+        assert!(exact.like("mississippi", "%iss%ppi"));
+        assert!(exact.like("", "%"));
+        assert!(!exact.like("", "_"));
+        assert!(!exact.like("ABC", "abc"));
+        assert!(TextComparison::CaseInsensitive.like("ABC", "a%"));
+        assert!(TextComparison::TrimTrailing.like("abc  ", "%c"));
+    }
+
+    #[test]
+    fn text_values() {
+        let string = Text::String("a ".to_string(), Some(2));
+        let char = Text::Char('A');
+        assert!(string.eq_with(&char, TextComparison::CaseInsensitiveTrimmed));
+        assert!(!string.eq_with(&char, TextComparison::Exact));
+        let blob = Text::Blob(b"a".to_vec());
+        assert!(!blob.eq_with(&char, TextComparison::CaseInsensitiveTrimmed));
+    }
+
+    #[test]
+    fn promote_numerics() {
+        assert!(Type::from(3u32).numeric_eq(&Type::from(3u64)));
+        assert!(Type::from(3i8).numeric_eq(&Type::from(3u64)));
+        assert!(Type::from(-3i16).numeric_eq(&Type::from(-3i64)));
+        assert!(!Type::from(-1i64).numeric_eq(&Type::from(u64::MAX)));
+        assert!(Type::from(2u8).numeric_eq(&Numeric::Double(2.0).into()));
+        assert!(!Type::from(2u8).numeric_eq(&Type::from("2")));
+        assert_eq!(
+            Type::from(7i32).promote_pair(&Type::from(u64::MAX)),
+            Some((Type::from(7u64), Type::from(u64::MAX)))
+        );
+        assert_eq!(
+            Type::from(-7i32).promote_pair(&Type::from(u64::MAX)),
+            Some((
+                Numeric::Double(-7.0).into(),
+                Numeric::Double(u64::MAX as f64).into()
+            ))
+        );
+    }
+
+    #[test]
+    fn compare() {
+        assert_eq!(
+            Type::from(3u8).compare(&Type::from(-4i64)),
+            Some(Ordering::Greater)
+        );
+        assert_eq!(
+            Type::from(2u32).compare(&Numeric::Double(2.5).into()),
+            Some(Ordering::Less)
+        );
+        assert_eq!(
+            Type::from("b").compare(&Text::Char('a').into()),
+            Some(Ordering::Greater)
+        );
+        assert_eq!(
+            Type::Optional(Some(Box::new(Type::from(1u64)))).compare(&Type::from(1u8)),
+            Some(Ordering::Equal)
+        );
+        assert_eq!(Type::Optional(None).compare(&Type::from(1u8)), None);
+        assert_eq!(Type::from("1").compare(&Type::from(1u8)), None);
+        assert_eq!(
+            Type::from(Time::date(2020, 1, 2).unwrap())
+                .compare(&Type::from(Time::date(2021, 1, 1).unwrap())),
+            Some(Ordering::Less)
+        );
+    }
+
+    #[test]
+    fn total_order() {
+        let three = Numeric::Signed(Signed::Int(3));
+        assert_eq!(
+            three.numeric_cmp(&Numeric::Unsigned(Unsigned::Long(3))),
+            Ordering::Equal
+        );
+        assert_ne!(three, Numeric::Unsigned(Unsigned::Long(3)));
+        assert_eq!(
+            Numeric::Double(u64::MAX as f64)
+                .numeric_cmp(&Numeric::Unsigned(Unsigned::Long(u64::MAX))),
+            Ordering::Greater
+        );
+        let tenth = Numeric::Decimal(Decimal::parse("-0.1", 2, 1).unwrap());
+        // the float nearest to -0.1 is below it
+        assert_eq!(Numeric::Float(-0.1).numeric_cmp(&tenth), Ordering::Less);
+        assert_eq!(
+            Numeric::Double(-0.05).numeric_cmp(&tenth),
+            Ordering::Greater
+        );
+        assert_eq!(
+            Numeric::Double(-0.0).numeric_cmp(&Numeric::Double(0.0)),
+            Ordering::Equal
+        );
+
+        let nan = Numeric::Double(f64::NAN);
+        assert_eq!(nan, Numeric::Float(f32::NAN).max(nan.clone()));
+        assert_eq!(
+            Type::from(nan.clone()).compare(&Type::from(u64::MAX)),
+            Some(Ordering::Greater)
+        );
+        assert!(Type::from(nan.clone()).numeric_eq(&Type::from(nan.clone())));
+
+        let wide = Numeric::BigInt(Integer::from(1i128 << 100));
+        let mut numbers = vec![
+            nan.clone(),
+            wide.clone(),
+            Numeric::Unsigned(Unsigned::Long(3)),
+            Numeric::Double(f64::NEG_INFINITY),
+            tenth.clone(),
+            three.clone(),
+            Numeric::Signed(Signed::Byte(-7)),
+            Numeric::Float(2.5),
+        ];
+        numbers.sort();
+        assert_eq!(
+            numbers,
+            vec![
+                Numeric::Double(f64::NEG_INFINITY),
+                Numeric::Signed(Signed::Byte(-7)),
+                tenth,
+                Numeric::Float(2.5),
+                three,
+                Numeric::Unsigned(Unsigned::Long(3)),
+                wide,
+                nan,
+            ]
+        );
+    }
+
+    #[test]
+    fn coerce_like() {
+        assert_eq!(
+            Type::from(7u64).coerce_like(&Type::from(0u8)),
+            Some(Type::from(7u8))
+        );
+        assert_eq!(
+            Type::from(-7i64).coerce_like(&Type::from(0i16)),
+            Some(Type::from(-7i16))
+        );
+        assert_eq!(Type::from(300u64).coerce_like(&Type::from(0u8)), None);
+        assert_eq!(Type::from(-1i64).coerce_like(&Type::from(0u32)), None);
+        assert_eq!(
+            Type::from("abc").coerce_like(&Type::Text(Text::String(String::new(), Some(2)))),
+            None
+        );
+        assert_eq!(Type::from("abc").coerce_like(&Type::from(0u8)), None);
+        let cents = Type::from(Decimal::zero(6, 2));
+        assert_eq!(
+            Type::from(12u8).coerce_like(&cents),
+            Some(Decimal::parse("12.00", 6, 2).unwrap().into())
+        );
+        assert_eq!(
+            Type::from(Decimal::parse("1.5", 3, 1).unwrap()).coerce_like(&cents),
+            Some(Decimal::parse("1.50", 6, 2).unwrap().into())
+        );
+        assert_eq!(
+            Type::from(Decimal::parse("1.505", 4, 3).unwrap()).coerce_like(&cents),
+            None
+        );
+        assert_eq!(Type::from(123456u32).coerce_like(&cents), None);
+    }
+
+    #[test]
+    fn decimals() {
+        let half = Type::from(Decimal::parse("0.5", 2, 1).unwrap());
+        assert!(half.numeric_eq(&Decimal::parse("0.500", 4, 3).unwrap().into()));
+        assert!(half.numeric_eq(&Numeric::Double(0.5).into()));
+        assert_eq!(half.compare(&Type::from(1u8)), Some(Ordering::Less));
+        assert_eq!(
+            Type::from(-2i32).promote_pair(&half),
+            Some((
+                Decimal::parse("-2.0", 38, 1).unwrap().into(),
+                Decimal::parse("0.5", 38, 1).unwrap().into()
+            ))
+        );
+    }
+
+    #[test]
+    fn arrays() {
+        let array = |values: &[u32]| Type::array(0u32.into(), values.iter().map(|&v| v.into()));
+        let ordering = |left: &[u32], right: &[u32]| array(left).compare(&array(right));
+        assert_eq!(ordering(&[1, 2], &[1, 3]), Some(Ordering::Less));
+        assert_eq!(ordering(&[1, 2], &[1]), Some(Ordering::Greater));
+        assert_eq!(ordering(&[], &[]), Some(Ordering::Equal));
+        let with_null = Type::array(0u32.into(), vec![Type::Optional(None)]);
+        assert_eq!(with_null.compare(&array(&[1])), None);
+    }
+
+    #[test]
+    fn enumerations() {
+        let low = Enumeration::new(vec!["low", "high"], 0).unwrap();
+        let high = Type::from(low.with_label("high").unwrap());
+        let low = Type::from(low);
+        assert_eq!(low.compare(&high), Some(Ordering::Less));
+        assert_eq!(Type::from("low").compare(&high), Some(Ordering::Less));
+        assert_eq!(high.compare(&Type::from("medium")), None);
+        let other = Type::from(Enumeration::new(vec!["high", "low"], 0).unwrap());
+        assert_eq!(other.compare(&high), None);
+
+        assert_eq!(Type::from("high").coerce_like(&low), Some(high.clone()));
+        assert_eq!(Type::from("medium").coerce_like(&low), None);
+        assert_eq!(high, "high");
+    }
+
+    #[test]
+    fn native_values() {
+        assert_eq!(Type::from(3u8), 3u64);
+        assert_eq!(-3i64, Type::from(-3i16));
+        assert_ne!(Type::from(-1i32), u64::MAX);
+        assert_eq!(Type::from(2u32), 2.0f64);
+        assert_eq!(Type::Optional(Some(Box::new(Type::from(true)))), true);
+        assert_ne!(Type::Optional(None), 0u8);
+        assert_ne!(Type::from("3"), 3u8);
+
+        assert_eq!(Type::from("abc"), "abc");
+        assert_eq!("abc", Type::from("abc"));
+        assert_eq!(Type::from("abc"), "abc".to_string());
+        assert_eq!(Type::Text(Text::Char('a')), "a");
+        assert_eq!(Type::from("a"), 'a');
+        assert_ne!(Type::from("ab"), 'a');
+        assert_ne!(Type::Text(Text::Blob(b"abc".to_vec())), "abc");
+    }
+}
@@ -0,0 +1,362 @@
+//! Fixed-point decimal numbers, for values such as amounts of money that have to be stored and
+//! added up exactly.
+//!
+//! A decimal is an integer mantissa scaled down by a power of ten. Its precision is the most
+//! digits it can have, and its scale is how many of those digits are after the decimal point, so
+//! `DECIMAL(5, 2)` holds numbers from `-999.99` to `999.99`. Two decimals are equal if they're
+//! the same number, whatever their precision and scale.
+//!
+//! Arithmetic never loses digits silently. The result of an operation has a precision and scale
+//! large enough for any result of its operands, up to [MAX_PRECISION] digits, and only division
+//! and multiplications that would need more digits than that round, half away from zero. An
+//! operation whose result doesn't fit returns `None`.
+
+use std::cmp::{max, min, Ordering};
+use std::fmt::{Display, Formatter};
+use std::hash::{Hash, Hasher};
+
+/// The most digits a decimal can have, which is the most that always fit in its mantissa
+pub const MAX_PRECISION: u8 = 38;
+
+/// The fewest digits after the decimal point the quotient of a division has
+const MIN_DIVISION_SCALE: u8 = 6;
+
+/// A number with a fixed number of digits after the decimal point
+#[derive(Debug, Clone, Copy)]
+pub struct Decimal {
+    mantissa: i128,
+    precision: u8,
+    scale: u8,
+}
+
+fn power_of_ten(exponent: u8) -> i128 {
+    10i128.pow(exponent as u32)
+}
+
+/// Divides, rounding half away from zero, or `None` when dividing by zero
+fn divide_rounded(numerator: i128, denominator: i128) -> Option<i128> {
+    if denominator == 0 {
+        return None;
+    }
+    let quotient = numerator / denominator;
+    let remainder = numerator % denominator;
+    if remainder.unsigned_abs() >= denominator.unsigned_abs() - remainder.unsigned_abs() {
+        let away = if (numerator < 0) == (denominator < 0) {
+            1
+        } else {
+            -1
+        };
+        quotient.checked_add(away)
+    } else {
+        Some(quotient)
+    }
+}
+
+/// Changes the scale of a mantissa, rounding if digits are dropped
+fn rescale_mantissa(mantissa: i128, from: u8, to: u8) -> Option<i128> {
+    match to.cmp(&from) {
+        Ordering::Equal => Some(mantissa),
+        Ordering::Greater => mantissa.checked_mul(power_of_ten(to - from)),
+        Ordering::Less => divide_rounded(mantissa, power_of_ten(from - to)),
+    }
+}
+
+impl Decimal {
+    /// Creates the decimal `mantissa / 10^scale`, or `None` if the mantissa has more digits than
+    /// the precision
+    ///
+    /// # Panic
+    /// Panics if the precision is 0 or more than [MAX_PRECISION], or if the scale is more than the
+    /// precision
+    pub fn new(mantissa: i128, precision: u8, scale: u8) -> Option<Self> {
+        assert!(
+            (1..=MAX_PRECISION).contains(&precision),
+            "The precision of a decimal must be from 1 to {}",
+            MAX_PRECISION
+        );
+        assert!(
+            scale <= precision,
+            "The scale of a decimal can't be more than its precision"
+        );
+        if mantissa.unsigned_abs() >= power_of_ten(precision) as u128 {
+            return None;
+        }
+        Some(Decimal {
+            mantissa,
+            precision,
+            scale,
+        })
+    }
+
+    /// Zero with the precision and scale, which can be used as the type of a field
+    ///
+    /// # Panic
+    /// Panics under the same conditions as [new](Decimal::new)
+    pub fn zero(precision: u8, scale: u8) -> Self {
+        Self::new(0, precision, scale).expect("Zero fits in every decimal")
+    }
+
+    /// An integer as a decimal with the scale, or `None` if it doesn't fit in [MAX_PRECISION]
+    /// digits
+    pub fn from_integer(value: i128, scale: u8) -> Option<Self> {
+        let mantissa = value.checked_mul(power_of_ten(scale))?;
+        Self::new(mantissa, MAX_PRECISION, scale)
+    }
+
+    /// Parses a number such as `-12.5`, rounding it to the scale. Returns `None` if the string
+    /// isn't a number or the number doesn't fit in the precision.
+    pub fn parse(string: &str, precision: u8, scale: u8) -> Option<Self> {
+        let string = string.trim();
+        let (negative, digits) = match string.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, string.strip_prefix('+').unwrap_or(string)),
+        };
+        let (integer, fraction) = match digits.split_once('.') {
+            Some((integer, fraction)) => (integer, fraction),
+            None => (digits, ""),
+        };
+        let all_digits = |part: &str| part.chars().all(|c| c.is_ascii_digit());
+        if (integer.is_empty() && fraction.is_empty())
+            || !all_digits(integer)
+            || !all_digits(fraction)
+        {
+            return None;
+        }
+
+        let mut mantissa: i128 = 0;
+        let integer = integer.trim_start_matches('0');
+        let kept = &fraction[..min(fraction.len(), scale as usize)];
+        for digit in integer.chars().chain(kept.chars()) {
+            let digit = digit.to_digit(10)? as i128;
+            mantissa = mantissa.checked_mul(10)?.checked_add(digit)?;
+        }
+        mantissa = mantissa.checked_mul(power_of_ten(scale - kept.len() as u8))?;
+        // the first dropped digit decides the rounding
+        if fraction[kept.len()..].starts_with(|c: char| c >= '5') {
+            mantissa = mantissa.checked_add(1)?;
+        }
+        if negative {
+            mantissa = -mantissa;
+        }
+        Self::new(mantissa, precision, scale)
+    }
+
+    /// The number as `mantissa / 10^scale`
+    pub fn mantissa(&self) -> i128 {
+        self.mantissa
+    }
+
+    pub fn precision(&self) -> u8 {
+        self.precision
+    }
+
+    pub fn scale(&self) -> u8 {
+        self.scale
+    }
+
+    /// The same number with another precision and scale, rounded if the scale is smaller, or
+    /// `None` if it doesn't fit
+    ///
+    /// # Panic
+    /// Panics under the same conditions as [new](Decimal::new)
+    pub fn with_precision(self, precision: u8, scale: u8) -> Option<Self> {
+        let mantissa = rescale_mantissa(self.mantissa, self.scale, scale)?;
+        Self::new(mantissa, precision, scale)
+    }
+
+    /// Converts the number into a double, potentially losing precision
+    pub fn to_f64(self) -> f64 {
+        self.mantissa as f64 / power_of_ten(self.scale) as f64
+    }
+
+    /// The number as an integer, if it has no fractional part
+    pub fn to_integer(self) -> Option<i128> {
+        let unit = power_of_ten(self.scale);
+        if self.mantissa % unit == 0 {
+            Some(self.mantissa / unit)
+        } else {
+            None
+        }
+    }
+
+    /// The mantissa and scale of the number without trailing zeros after the decimal point,
+    /// which are the same for every decimal equal to it
+    pub fn normalized(self) -> (i128, u8) {
+        let (mut mantissa, mut scale) = (self.mantissa, self.scale);
+        while scale > 0 && mantissa % 10 == 0 {
+            mantissa /= 10;
+            scale -= 1;
+        }
+        (mantissa, scale)
+    }
+
+    pub fn checked_add(self, other: Decimal) -> Option<Decimal> {
+        let scale = max(self.scale, other.scale);
+        let left = rescale_mantissa(self.mantissa, self.scale, scale)?;
+        let right = rescale_mantissa(other.mantissa, other.scale, scale)?;
+        let integer_digits = max(self.integer_digits(), other.integer_digits()) + 1;
+        let precision = min(MAX_PRECISION, integer_digits + scale);
+        Self::new(left.checked_add(right)?, precision, scale)
+    }
+
+    pub fn checked_sub(self, other: Decimal) -> Option<Decimal> {
+        self.checked_add(other.negated())
+    }
+
+    pub fn checked_mul(self, other: Decimal) -> Option<Decimal> {
+        let product = self.mantissa.checked_mul(other.mantissa)?;
+        let product_scale = self.scale + other.scale;
+        let scale = min(MAX_PRECISION, product_scale);
+        let mantissa = rescale_mantissa(product, product_scale, scale)?;
+        let precision = min(MAX_PRECISION, self.precision + other.precision);
+        Self::new(mantissa, precision, scale)
+    }
+
+    /// Divides the numbers, or returns `None` if the divisor is zero or the quotient doesn't fit.
+    /// The quotient has at least six digits after the decimal point, or more if the operands do.
+    pub fn checked_div(self, other: Decimal) -> Option<Decimal> {
+        let integer_digits = self.integer_digits() + other.scale;
+        // the scale is cut down to leave room for the integer digits, but never below the minimum
+        let wanted = max(MIN_DIVISION_SCALE, self.scale + other.precision + 1);
+        let available = MAX_PRECISION.saturating_sub(integer_digits);
+        let scale = max(MIN_DIVISION_SCALE, min(wanted, available));
+        let precision = min(MAX_PRECISION, integer_digits + scale);
+        // mantissa = self / other * 10^scale
+        //          = self.mantissa * 10^(scale + other.scale - self.scale) / other.mantissa
+        let exponent = scale as i32 + other.scale as i32 - self.scale as i32;
+        let mantissa = if exponent >= 0 {
+            let numerator = self.mantissa.checked_mul(power_of_ten(exponent as u8))?;
+            divide_rounded(numerator, other.mantissa)?
+        } else {
+            let denominator = other.mantissa.checked_mul(power_of_ten(-exponent as u8))?;
+            divide_rounded(self.mantissa, denominator)?
+        };
+        Self::new(mantissa, precision, scale)
+    }
+
+    pub fn negated(self) -> Decimal {
+        Decimal {
+            mantissa: -self.mantissa,
+            ..self
+        }
+    }
+
+    /// The most digits before the decimal point
+    fn integer_digits(&self) -> u8 {
+        self.precision - self.scale
+    }
+}
+
+impl PartialEq for Decimal {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Decimal {}
+
+impl PartialOrd for Decimal {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Decimal {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let scale = max(self.scale, other.scale);
+        let left = rescale_mantissa(self.mantissa, self.scale, scale);
+        let right = rescale_mantissa(other.mantissa, other.scale, scale);
+        match (left, right) {
+            (Some(left), Some(right)) => left.cmp(&right),
+            // the side with the larger scale is never scaled up, so the side that can't be is
+            // further from zero than any mantissa
+            (None, _) => self.mantissa.cmp(&0),
+            (_, None) => 0.cmp(&other.mantissa),
+        }
+    }
+}
+
+impl Hash for Decimal {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.normalized().hash(state)
+    }
+}
+
+impl Display for Decimal {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let digits = format!(
+            "{:0width$}",
+            self.mantissa.unsigned_abs(),
+            width = self.scale as usize + 1
+        );
+        let (integer, fraction) = digits.split_at(digits.len() - self.scale as usize);
+        if self.mantissa < 0 {
+            write!(f, "-")?;
+        }
+        if fraction.is_empty() {
+            write!(f, "{}", integer)
+        } else {
+            write!(f, "{}.{}", integer, fraction)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decimal(string: &str, precision: u8, scale: u8) -> Decimal {
+        Decimal::parse(string, precision, scale).unwrap()
+    }
+
+    #[test]
+    fn parse_and_display() {
+        assert_eq!(decimal("12.5", 5, 2).to_string(), "12.50");
+        assert_eq!(decimal("-0.125", 5, 2).to_string(), "-0.13");
+        assert_eq!(decimal(".5", 3, 0).to_string(), "1");
+        assert_eq!(decimal("007", 3, 1).mantissa(), 70);
+        assert_eq!(decimal("-0.001", 4, 3).to_string(), "-0.001");
+        assert!(Decimal::parse("1000", 5, 2).is_none());
+        assert!(Decimal::parse("1.2.3", 5, 2).is_none());
+        assert!(Decimal::parse("-", 5, 2).is_none());
+        assert!(Decimal::parse("1e5", 5, 2).is_none());
+    }
+
+    #[test]
+    fn equal_whatever_the_scale() {
+        assert_eq!(decimal("1.5", 5, 1), decimal("1.50", 10, 4));
+        assert!(decimal("-2", 5, 1) < decimal("1.99", 3, 2));
+        assert_eq!(
+            decimal("1.5", 5, 1).normalized(),
+            decimal("1.50", 10, 4).normalized()
+        );
+        let huge = Decimal::new(10i128.pow(37), 38, 0).unwrap();
+        assert!(decimal("0.5", 38, 37) < huge);
+        assert!(huge.negated() < decimal("-0.5", 38, 37));
+    }
+
+    #[test]
+    fn arithmetic() {
+        let price = decimal("19.99", 5, 2);
+        let rate = decimal("0.075", 4, 3);
+
+        let sum = price.checked_add(decimal("0.01", 3, 2)).unwrap();
+        assert_eq!(sum.to_string(), "20.00");
+        assert_eq!((sum.precision(), sum.scale()), (6, 2));
+        assert_eq!(price.checked_sub(price).unwrap().to_string(), "0.00");
+
+        let tax = price.checked_mul(rate).unwrap();
+        assert_eq!(tax.to_string(), "1.49925");
+        assert_eq!(tax.with_precision(5, 2).unwrap().to_string(), "1.50");
+
+        let third = decimal("1", 1, 0).checked_div(decimal("3", 1, 0)).unwrap();
+        assert_eq!(third.to_string(), "0.333333");
+        let share = price.checked_div(decimal("4", 1, 0)).unwrap();
+        assert_eq!(share, decimal("4.9975", 6, 4));
+        assert!(price.checked_div(decimal("0", 1, 0)).is_none());
+
+        let max = Decimal::new(10i128.pow(38) - 1, 38, 0).unwrap();
+        assert!(max.checked_add(decimal("1", 1, 0)).is_none());
+        assert!(max.checked_mul(max).is_none());
+    }
+}
@@ -1,9 +1,14 @@
 use std::error::Error;
 use std::fmt::{Display, Formatter};
 
-use chrono::{Local, TimeZone};
+use chrono::{NaiveDate, NaiveTime};
 
-use crate::{Numeric, Signed, Text, Time, Type, Unsigned};
+use crate::extension::from_hex;
+use crate::serialization::TextFormat;
+use crate::{
+    BitString, BlobHandle, Decimal, Inet, Integer, MacAddress, Numeric, Point, Signed, Text, Time,
+    Type, Unsigned,
+};
 use std::ops::Deref;
 
 #[derive(Debug)]
@@ -27,49 +32,87 @@ pub fn parse_using_types<S: AsRef<str>, I: IntoIterator<Item = Type>>(
     to_parse: S,
     iterator: I,
 ) -> Result<Vec<Type>> {
-    parse_using_types_helper(to_parse.as_ref(), iterator.into_iter().collect())
+    parse_using_types_with(to_parse, iterator, &TextFormat::default())
 }
 
-fn parse_using_types_helper(to_parse: &str, iterator: Vec<Type>) -> Result<Vec<Type>> {
+/// Parses values written in the given text format
+pub fn parse_using_types_with<S: AsRef<str>, I: IntoIterator<Item = Type>>(
+    to_parse: S,
+    iterator: I,
+    format: &TextFormat,
+) -> Result<Vec<Type>> {
+    parse_using_types_helper(to_parse.as_ref(), iterator.into_iter().collect(), format)
+}
+
+fn parse_using_types_helper(
+    to_parse: &str,
+    iterator: Vec<Type>,
+    format: &TextFormat,
+) -> Result<Vec<Type>> {
+    let strings_vector = split_fields(to_parse, format)?;
+
+    let mut output = vec![];
+    let mut string_iter = strings_vector.into_iter();
+    let mut type_iter = iterator.into_iter();
+    while let (Some(base_type), Some((string, quoted))) = (type_iter.next(), string_iter.next()) {
+        output.push(parse_field(base_type, string, quoted, format)?);
+    }
+
+    if !(string_iter.next().is_none() && type_iter.next().is_none()) {
+        Err(ParseTupleFailure)
+    } else {
+        Ok(output)
+    }
+}
+
+/// Splits text into its fields, with quotes and escapes removed, along with whether each field
+/// was quoted
+fn split_fields(to_parse: &str, format: &TextFormat) -> Result<Vec<(String, bool)>> {
     let mut current = String::new();
     let mut strings_vector = vec![];
     let mut in_quote = false;
+    // whether the current field was quoted, in which case it's kept even if empty
+    let mut quoted = false;
     let mut chars_iterator = to_parse.chars();
 
     while let Some(c) = chars_iterator.next() {
-        if c == '"' {
+        if c == format.quote() {
             in_quote = !in_quote;
-        } else if c == '\\' {
+            quoted = true;
+        } else if c == TextFormat::ESCAPE {
             let next = chars_iterator.next().ok_or_else(|| ParseTupleFailure)?;
             current += &next.to_string();
-        } else if c == '|' && !in_quote {
+        } else if c == format.delimiter() && !in_quote {
             let string = std::mem::replace(&mut current, String::new());
-            strings_vector.push(string);
+            strings_vector.push((string, quoted));
+            quoted = false;
         } else {
             current += &c.to_string();
         }
     }
 
-    if !current.trim().is_empty() {
-        strings_vector.push(current);
+    if quoted || !current.trim().is_empty() {
+        strings_vector.push((current, quoted));
     }
+    Ok(strings_vector)
+}
 
-    let mut output = vec![];
-    let mut string_iter = strings_vector.into_iter();
-    let mut type_iter = iterator.into_iter();
-    while let (Some(base_type), Some(string)) = (type_iter.next(), string_iter.next()) {
-        let created = parse_type(base_type, string)?;
-        output.push(created);
+fn parse_field(base_type: Type, string: String, quoted: bool, format: &TextFormat) -> Result<Type> {
+    match base_type {
+        Type::Optional(_) if !quoted && string == format.null() => Ok(Type::Optional(None)),
+        base_type => parse_type(base_type, string, quoted, format),
     }
+}
 
-    if !(string_iter.next().is_none() && type_iter.next().is_none()) {
-        Err(ParseTupleFailure)
-    } else {
-        Ok(output)
+/// Blobs are written in hex after `0x`, and any other text is read as its bytes
+fn parse_blob(string: String) -> Vec<u8> {
+    match string.strip_prefix("0x").and_then(from_hex) {
+        Some(bytes) => bytes,
+        None => string.into_bytes(),
     }
 }
 
-fn parse_type(base_type: Type, string: String) -> Result<Type> {
+fn parse_type(base_type: Type, string: String, quoted: bool, format: &TextFormat) -> Result<Type> {
     let mut created = base_type.clone();
     match &mut created {
         Type::Numeric(n) => match n {
@@ -107,6 +150,12 @@ fn parse_type(base_type: Type, string: String) -> Result<Type> {
                     *l = string.parse()?;
                 }
             },
+            Numeric::Decimal(d) => {
+                *d = Decimal::parse(&string, d.precision(), d.scale()).ok_or(ParseTupleFailure)?;
+            }
+            Numeric::BigInt(i) => {
+                *i = Integer::parse(&string, i.bits()).ok_or(ParseTupleFailure)?;
+            }
         },
         Type::Text(t) => match t {
             Text::Char(c) => {
@@ -130,23 +179,32 @@ fn parse_type(base_type: Type, string: String) -> Result<Type> {
 
                 *bs = string.as_bytes().to_vec();
             }
-            Text::Blob(blob) => {
-                *blob = string.as_bytes().to_vec();
+            Text::BitString(bits, width) => {
+                *bits = BitString::from_packed(&string).ok_or(ParseTupleFailure)?;
+                if !width.allows(bits.len()) {
+                    return Err(ParseTupleFailure);
+                }
+            }
+            Text::Blob(_) | Text::BlobRef(_) => {
+                *t = match BlobHandle::parse(&string) {
+                    Some(handle) => Text::BlobRef(handle),
+                    None => Text::Blob(parse_blob(string)),
+                };
             }
         },
         Type::Time(t) => match t {
             Time::Date(d) => {
-                let split = string.split('-').collect::<Vec<_>>();
-                let year: i32 = split[0].parse()?;
-                let month: u32 = split[0].parse()?;
-                let day: u32 = split[0].parse()?;
-                *d = Local.ymd(year, month, day)
+                *d = NaiveDate::parse_from_str(&string, Time::DATE_FORMAT)?;
             }
-            Time::DateTime(d) => {
-                *d = string.parse()?;
+            Time::TimeOfDay(time) => {
+                *time = NaiveTime::parse_from_str(&string, Time::TIME_OF_DAY_FORMAT)?;
             }
-            Time::Timestamp(t) => {
-                *t = string.parse()?;
+            Time::DateTime(_) | Time::Timestamp(_) | Time::TimestampTz(_) => {
+                *t = match t {
+                    Time::DateTime(_) => Time::parse_date_time(&string)?,
+                    Time::Timestamp(_) => Time::parse_timestamp(&string)?,
+                    _ => Time::parse_timestamp_tz(&string)?,
+                };
             }
             Time::Year(y) => {
                 *y = string.parse()?;
@@ -155,16 +213,39 @@ fn parse_type(base_type: Type, string: String) -> Result<Type> {
         Type::Boolean(b) => {
             *b = string.parse()?;
         }
-        Type::Optional(o) => match &*string {
-            "NULL" => {
-                *o = None;
-            }
-            non_null => {
-                let inner_type = o.as_ref().map(|b| b.deref().clone()).unwrap();
-                let inner = parse_type(inner_type, non_null.to_string())?;
-                *o = Some(Box::new(inner))
-            }
-        },
+        Type::Point(point) => {
+            *point = Point::parse(&string).ok_or(ParseTupleFailure)?;
+        }
+        Type::Inet(inet) => {
+            *inet = Inet::parse(&string).ok_or(ParseTupleFailure)?;
+        }
+        Type::MacAddress(address) => {
+            *address = MacAddress::parse(&string).ok_or(ParseTupleFailure)?;
+        }
+        Type::Optional(o) => {
+            let inner_type = o.as_ref().map(|b| b.deref().clone()).unwrap();
+            let inner = parse_type(inner_type, string, quoted, format)?;
+            *o = Some(Box::new(inner))
+        }
+        Type::Extension(_, bytes) => {
+            *bytes = from_hex(&string).ok_or(ParseTupleFailure)?;
+        }
+        Type::Array(values, kind) => {
+            let format = format.element_format();
+            *values = split_fields(&string, &format)?
+                .into_iter()
+                .map(|(element, quoted)| parse_field((**kind).clone(), element, quoted, &format))
+                .collect::<Result<_>>()?;
+        }
+        // enumerations are written as their ordinals, but a quoted field is read as a label
+        Type::Enum(e) => {
+            let parsed = if quoted {
+                e.with_label(&string)
+            } else {
+                e.with_ordinal(string.parse()?)
+            };
+            *e = parsed.ok_or(ParseTupleFailure)?;
+        }
     }
     Ok(created)
 }
@@ -184,11 +265,11 @@ mod tests {
         ];
         let input = vec!["3", "23241212332", "\"Hello World!\""].join("|");
         let output = parse_using_types(input, types).unwrap();
-        assert_eq!(output[0], Signed::Byte(3).into());
-        assert_eq!(output[1], Unsigned::Long(23241212332).into());
+        assert_eq!(output[0], Type::from(Signed::Byte(3)));
+        assert_eq!(output[1], Type::from(Unsigned::Long(23241212332)));
         assert_eq!(
             output[2],
-            Text::String("Hello World!".to_string(), None).into()
+            Type::from(Text::String("Hello World!".to_string(), None))
         );
     }
 
@@ -198,4 +279,213 @@ mod tests {
         let input = vec!["\"Hello World!\""].join("|");
         parse_using_types(input, types).unwrap_err();
     }
+
+    #[test]
+    fn quoted_empty_last_field() {
+        let types: Vec<Type> = vec![0u8.into(), Text::String(String::new(), None).into()];
+        let output = parse_using_types("3|\"\"", types).unwrap();
+        assert_eq!(output[1], Type::from(Text::String(String::new(), None)));
+        // an unquoted trailing delimiter still doesn't start another field
+        let types: Vec<Type> = vec![0u8.into()];
+        assert_eq!(
+            parse_using_types("3|", types).unwrap(),
+            vec![Type::from(3u8)]
+        );
+    }
+
+    #[test]
+    fn dates_and_times() {
+        let types: Vec<Type> = vec![
+            Time::date(1970, 1, 1).unwrap().into(),
+            Time::time_of_day(0, 0, 0).unwrap().into(),
+        ];
+        let output = parse_using_types("2021-12-25|08:30:15.5", types).unwrap();
+        assert_eq!(output[0], Type::from(Time::date(2021, 12, 25).unwrap()));
+        assert_eq!(
+            output[1],
+            Type::from(Time::TimeOfDay(
+                NaiveTime::from_hms_milli_opt(8, 30, 15, 500).unwrap()
+            ))
+        );
+    }
+
+    #[test]
+    fn custom_format() {
+        use crate::serialization::serialize_values_with;
+
+        let format = TextFormat::new(',', '\'', "\\N");
+        let nullable_string =
+            Type::Optional(Some(Box::new(Text::String(String::new(), None).into())));
+        let types: Vec<Type> = vec![
+            0u8.into(),
+            nullable_string.clone(),
+            nullable_string.clone(),
+            nullable_string,
+        ];
+        let values: Vec<Type> = vec![
+            3u8.into(),
+            Type::Optional(Some(Box::new("a|b, 'c' \\ d".into()))),
+            Type::Optional(None),
+            Type::Optional(Some(Box::new("\\N".into()))),
+        ];
+        let written = serialize_values_with(values.clone(), &format);
+        assert!(written.starts_with("3,'a|b, \\'c\\'"));
+        let output = parse_using_types_with(written, types, &format).unwrap();
+        assert_eq!(output, values);
+    }
+
+    #[test]
+    fn arrays() {
+        use crate::serialization::serialize_values_with;
+
+        let nullable_string =
+            Type::Optional(Some(Box::new(Text::String(String::new(), None).into())));
+        let strings = Type::array(nullable_string.clone(), vec![]);
+        let nested = Type::array(Type::array(0u32.into(), vec![]), vec![]);
+        let some = |string: &str| Type::Optional(Some(Box::new(string.into())));
+        let values: Vec<Type> = vec![
+            Type::array(
+                nullable_string.clone(),
+                vec![some("a,b"), some(""), Type::Optional(None), some("\"c\"|")],
+            ),
+            Type::array(
+                Type::array(0u32.into(), vec![]),
+                vec![
+                    Type::array(0u32.into(), vec![1u32.into(), 2u32.into()]),
+                    Type::array(0u32.into(), vec![]),
+                ],
+            ),
+            Type::array(nullable_string, vec![]),
+        ];
+        for format in &[TextFormat::default(), TextFormat::new(',', '\'', "\\N")] {
+            let written = serialize_values_with(values.clone(), format);
+            let types = vec![strings.clone(), nested.clone(), strings.clone()];
+            let output = parse_using_types_with(&written, types, format).unwrap();
+            assert_eq!(output, values);
+        }
+        let numbers = Type::array(0u32.into(), vec![]);
+        assert!(parse_using_types("\"1,x\"", vec![numbers]).is_err());
+    }
+
+    #[test]
+    fn enumerations() {
+        use crate::serialization::serialize_values;
+        use crate::Enumeration;
+
+        let status = Type::from(Enumeration::new(vec!["open", "closed", "12"], 0).unwrap());
+        let types = vec![status.clone(), Type::Optional(Some(Box::new(status)))];
+        let output = parse_using_types("1|\"12\"", types.clone()).unwrap();
+        assert_eq!(output[0], "closed");
+        assert_eq!(output[1], "12");
+        assert_eq!(serialize_values(output), "1|2");
+
+        parse_using_types("3|0", types.clone()).unwrap_err();
+        parse_using_types("0|\"pending\"", types).unwrap_err();
+    }
+    #[test]
+    fn wide_integers() {
+        use crate::serialization::serialize_values;
+        use crate::Integer;
+
+        let types = vec![Type::from(Integer::zero(128)), Type::from(Integer::zero(8))];
+        let output = parse_using_types("-1267650600228229401496703205376|-128", types.clone());
+        assert_eq!(
+            output.unwrap(),
+            vec![
+                Type::from(Integer::from(-(1i128 << 100))),
+                Type::from(Integer::new(-128, 8).unwrap())
+            ]
+        );
+        let written = serialize_values(parse_using_types("12|0", types.clone()).unwrap());
+        assert_eq!(written, "12|0");
+        parse_using_types("0|128", types).unwrap_err();
+    }
+
+    #[test]
+    fn points() {
+        use crate::serialization::serialize_values;
+
+        let types = vec![
+            Type::from(Point::new(0.0, 0.0)),
+            Type::array(Point::new(0.0, 0.0).into(), vec![]),
+        ];
+        let values = vec![
+            Type::from(Point::new(1.5, -2.0)),
+            Type::array(
+                Point::new(0.0, 0.0).into(),
+                vec![Point::new(3.0, 4.0).into(), Point::new(-1.0, 0.5).into()],
+            ),
+        ];
+        let written = serialize_values(values.clone());
+        assert_eq!(parse_using_types(written, types.clone()).unwrap(), values);
+        parse_using_types("(1.5)|\"\"", types).unwrap_err();
+    }
+
+    #[test]
+    fn network_addresses() {
+        use crate::serialization::serialize_values;
+
+        let types = vec![
+            Type::from(Inet::parse("0.0.0.0").unwrap()),
+            Type::from(MacAddress::new([0; 6])),
+            Type::array(Inet::parse("0.0.0.0").unwrap().into(), vec![]),
+        ];
+        let values = vec![
+            Type::from(Inet::parse("192.168.0.1/24").unwrap()),
+            Type::from(MacAddress::new([0x08, 0x00, 0x2b, 0x01, 0x02, 0x03])),
+            Type::array(
+                Inet::parse("0.0.0.0").unwrap().into(),
+                vec![
+                    Inet::parse("2001:db8::/32").unwrap().into(),
+                    Inet::parse("10.0.0.1").unwrap().into(),
+                ],
+            ),
+        ];
+        let written = serialize_values(values.clone());
+        assert_eq!(parse_using_types(written, types.clone()).unwrap(), values);
+        parse_using_types("10.0.0.1/33|08:00:2b:01:02:03|\"\"", types).unwrap_err();
+    }
+
+    #[test]
+    fn bit_strings() {
+        use crate::serialization::serialize_values;
+        use crate::BitWidth;
+
+        let bits =
+            |bits: &str, width| Type::from(Text::BitString(BitString::parse(bits).unwrap(), width));
+        let types = vec![
+            bits("000", BitWidth::Fixed(3)),
+            bits("", BitWidth::Varying(None)),
+        ];
+        let values = vec![
+            bits("101", BitWidth::Fixed(3)),
+            bits("1111000011", BitWidth::Varying(None)),
+        ];
+        let written = serialize_values(values.clone());
+        assert_eq!(written, "3:a0|10:f0c0");
+        assert_eq!(parse_using_types(written, types.clone()).unwrap(), values);
+        parse_using_types("2:80|0:", types).unwrap_err();
+    }
+
+    #[test]
+    fn blobs() {
+        use crate::serialization::serialize_values;
+        use crate::BlobHandle;
+
+        let values = vec![
+            Type::Text(Text::Blob(vec![0xff, 0, b'|'])),
+            Type::Text(Text::BlobRef(BlobHandle::new(4, 1 << 20))),
+        ];
+        let written = serialize_values(values.clone());
+        assert_eq!(written, "0xff007c|blob:4:1048576");
+        let types = vec![Type::Text(Text::Blob(vec![])); 2];
+        assert_eq!(parse_using_types(&written, types.clone()).unwrap(), values);
+        assert_eq!(
+            parse_using_types("hi|0x", types).unwrap(),
+            vec![
+                Type::Text(Text::Blob(b"hi".to_vec())),
+                Type::Text(Text::Blob(vec![]))
+            ]
+        );
+    }
 }
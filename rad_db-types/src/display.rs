@@ -0,0 +1,93 @@
+//! Shows values to people. Binary text isn't necessarily valid UTF-8, so it's either written as
+//! hex, which shows every byte, or decoded with invalid sequences replaced by `U+FFFD`, which is
+//! easier to read when the bytes are mostly text.
+
+use std::borrow::Cow;
+
+use crate::extension::to_hex;
+use crate::{Text, Type};
+
+/// How binary text is shown
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryDisplay {
+    /// The bytes in hex after `0x`, which is lossless
+    Hex,
+    /// The bytes decoded as UTF-8, with invalid sequences replaced
+    Lossy,
+}
+
+impl Default for BinaryDisplay {
+    fn default() -> Self {
+        BinaryDisplay::Hex
+    }
+}
+
+impl BinaryDisplay {
+    pub fn render(&self, bytes: &[u8]) -> String {
+        match self {
+            BinaryDisplay::Hex => format!("0x{}", to_hex(bytes)),
+            BinaryDisplay::Lossy => String::from_utf8_lossy(bytes).into_owned(),
+        }
+    }
+}
+
+impl Text {
+    /// The bytes of the text, where characters and strings are encoded as UTF-8. The bytes of a
+    /// blob stored out of line aren't in the value, so it's its handle.
+    pub fn as_bytes(&self) -> Cow<'_, [u8]> {
+        match self {
+            Text::Char(c) => Cow::Owned(c.to_string().into_bytes()),
+            Text::String(s, _) => Cow::Borrowed(s.as_bytes()),
+            Text::Binary(b) => Cow::Borrowed(std::slice::from_ref(b)),
+            Text::BinaryString(bytes, _) | Text::Blob(bytes) => Cow::Borrowed(bytes),
+            Text::BlobRef(handle) => Cow::Owned(handle.to_string().into_bytes()),
+            Text::BitString(bits, _) => Cow::Borrowed(bits.as_bytes()),
+        }
+    }
+
+    /// Whether the text is made of bytes instead of characters
+    pub fn is_binary(&self) -> bool {
+        matches!(
+            self,
+            Text::Binary(_) | Text::BinaryString(..) | Text::Blob(_)
+        )
+    }
+}
+
+/// Shows a value without quoting text, as a cell of a table would, with binary text shown the
+/// given way
+pub fn render_value(value: &Type, binary: BinaryDisplay) -> String {
+    match value {
+        Type::Optional(None) => "NULL".to_string(),
+        Type::Optional(Some(inner)) => render_value(inner, binary),
+        Type::Text(text) if text.is_binary() => binary.render(&text.as_bytes()),
+        Type::Text(Text::Char(c)) => c.to_string(),
+        Type::Text(Text::String(s, _)) => s.clone(),
+        value => value.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn binary_text() {
+        let invalid = Text::Blob(vec![b'a', 0xff, b'b']);
+        assert_eq!(invalid.as_bytes().as_ref(), &[b'a', 0xff, b'b']);
+        assert_eq!(invalid.to_string(), "0x61ff62");
+        assert_eq!(
+            BinaryDisplay::Lossy.render(&invalid.as_bytes()),
+            "a\u{FFFD}b"
+        );
+        assert_eq!(Text::Char('é').as_bytes().as_ref(), "é".as_bytes());
+
+        let value = Type::Optional(Some(Box::new(Type::Text(invalid))));
+        assert_eq!(render_value(&value, BinaryDisplay::Hex), "0x61ff62");
+        assert_eq!(render_value(&Type::from("a b"), BinaryDisplay::Hex), "a b");
+        assert_eq!(
+            render_value(&Type::Optional(None), BinaryDisplay::Lossy),
+            "NULL"
+        );
+    }
+}
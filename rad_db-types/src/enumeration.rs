@@ -0,0 +1,116 @@
+//! Enumerations are values that are one of a declared set of labels, such as the status of an
+//! order. A value only stores its ordinal, the position of its label within the labels, and the
+//! labels are kept by the type of the field so that the label can be found again as values are
+//! read.
+//!
+//! Values of the same labels are ordered the way their labels were declared.
+
+use std::fmt::{Display, Formatter};
+use std::sync::Arc;
+
+/// A label out of a declared set of labels
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Enumeration {
+    labels: Arc<[String]>,
+    ordinal: u16,
+}
+
+impl Enumeration {
+    /// The most labels an enumeration can declare, so that every ordinal fits in a `u16`
+    pub const MAX_LABELS: usize = u16::MAX as usize + 1;
+
+    /// Creates the value at the ordinal within the labels, or `None` if there is no label there
+    ///
+    /// # Panic
+    /// Panics if a label is declared more than once, or if there are more than
+    /// [MAX_LABELS](Enumeration::MAX_LABELS) labels
+    pub fn new<I, S>(labels: I, ordinal: u16) -> Option<Self>
+    where
+        I: IntoIterator<Item = S>,
+        S: ToString,
+    {
+        let labels: Vec<String> = labels.into_iter().map(|label| label.to_string()).collect();
+        assert!(
+            labels.len() <= Self::MAX_LABELS,
+            "An enumeration can't have more than {} labels",
+            Self::MAX_LABELS
+        );
+        for (index, label) in labels.iter().enumerate() {
+            assert!(
+                !labels[..index].contains(label),
+                "The label {:?} is declared more than once",
+                label
+            );
+        }
+        Enumeration {
+            labels: labels.into(),
+            ordinal: 0,
+        }
+        .with_ordinal(ordinal)
+    }
+
+    /// The value of the same labels at the ordinal, or `None` if there is no label there
+    pub fn with_ordinal(&self, ordinal: u16) -> Option<Self> {
+        if ordinal as usize >= self.labels.len() {
+            return None;
+        }
+        Some(Enumeration {
+            labels: self.labels.clone(),
+            ordinal,
+        })
+    }
+
+    /// The value of the same labels with the label, or `None` if it isn't one of the labels
+    pub fn with_label(&self, label: &str) -> Option<Self> {
+        let ordinal = self.labels.iter().position(|declared| declared == label)?;
+        self.with_ordinal(ordinal as u16)
+    }
+
+    pub fn labels(&self) -> &[String] {
+        &self.labels
+    }
+
+    pub fn ordinal(&self) -> u16 {
+        self.ordinal
+    }
+
+    pub fn label(&self) -> &str {
+        &self.labels[self.ordinal as usize]
+    }
+
+    /// Whether both values are of the same labels, declared in the same order
+    pub fn same_labels(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.labels, &other.labels) || self.labels == other.labels
+    }
+}
+
+impl Display for Enumeration {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.label())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn labels() {
+        let open = Enumeration::new(vec!["open", "closed"], 0).unwrap();
+        assert_eq!(open.label(), "open");
+        let closed = open.with_label("closed").unwrap();
+        assert_eq!(closed.ordinal(), 1);
+        assert_eq!(closed.to_string(), "closed");
+        assert!(closed.same_labels(&open));
+        assert!(open.with_label("pending").is_none());
+        assert!(open.with_ordinal(2).is_none());
+        assert!(Enumeration::new(Vec::<String>::new(), 0).is_none());
+        assert!(!open.same_labels(&Enumeration::new(vec!["closed", "open"], 0).unwrap()));
+    }
+
+    #[test]
+    #[should_panic]
+    fn repeated_labels() {
+        Enumeration::new(vec!["open", "open"], 0);
+    }
+}
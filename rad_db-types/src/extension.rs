@@ -0,0 +1,254 @@
+//! Allows for applications to define their own column types. A custom type implements [DBValue],
+//! is declared in the [ExtensionCatalog] of a database, and is then stored as a
+//! [Type::Extension] holding the type's id and its serialized bytes. Values of custom types are
+//! only displayed, compared and checked as their type through the catalog that declares it, and
+//! are otherwise treated as bytes.
+//!
+//! [DBValue]: self::DBValue
+//! [ExtensionCatalog]: self::ExtensionCatalog
+//! [Type::Extension]: crate::Type::Extension
+
+use std::cmp::Ordering;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+
+use crate::Type;
+
+/// A custom value that can be stored within a relation
+pub trait DBValue: Display + Sized + 'static {
+    /// The id of this type. This id is saved alongside values, so it must never change between
+    /// runs, and must be unique among the types declared in a catalog.
+    const TYPE_ID: u32;
+
+    /// Serializes the value into bytes. Equal values must produce equal bytes, as the bytes are
+    /// used for both equality and hashing of the value.
+    fn serialize(&self) -> Vec<u8>;
+
+    /// Recreates a value from its serialized bytes
+    fn deserialize(bytes: &[u8]) -> Option<Self>;
+
+    /// Compares two values of this type
+    fn compare(&self, other: &Self) -> Ordering;
+}
+
+/// The functions needed to handle a declared type when only its bytes are known
+#[derive(Clone, Copy)]
+struct ExtensionFunctions {
+    type_id: u32,
+    display: fn(&[u8], &mut Formatter<'_>) -> std::fmt::Result,
+    compare: fn(&[u8], &[u8]) -> Option<Ordering>,
+    validate: fn(&[u8]) -> bool,
+}
+
+fn display_helper<T: DBValue>(bytes: &[u8], f: &mut Formatter<'_>) -> std::fmt::Result {
+    match T::deserialize(bytes) {
+        None => Err(std::fmt::Error),
+        Some(value) => write!(f, "{}", value),
+    }
+}
+
+fn compare_helper<T: DBValue>(left: &[u8], right: &[u8]) -> Option<Ordering> {
+    let left = T::deserialize(left)?;
+    let right = T::deserialize(right)?;
+    Some(left.compare(&right))
+}
+
+fn validate_helper<T: DBValue>(bytes: &[u8]) -> bool {
+    T::deserialize(bytes).is_some()
+}
+
+/// When a type couldn't be declared
+#[derive(Debug)]
+pub struct DuplicateExtensionError(u32);
+
+impl Display for DuplicateExtensionError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "A type with id {} has already been declared", self.0)
+    }
+}
+
+impl Error for DuplicateExtensionError {}
+
+/// The custom types declared for a database. Each database keeps its own catalog, so the types
+/// one declares are never seen by another.
+#[derive(Clone, Default)]
+pub struct ExtensionCatalog {
+    types: Vec<ExtensionFunctions>,
+}
+
+impl ExtensionCatalog {
+    /// Creates a catalog without any types declared
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Declares a custom type so that its values can be displayed, compared and parsed
+    pub fn register<T: DBValue>(&mut self) -> Result<(), DuplicateExtensionError> {
+        if self.is_registered(T::TYPE_ID) {
+            return Err(DuplicateExtensionError(T::TYPE_ID));
+        }
+        self.types.push(ExtensionFunctions {
+            type_id: T::TYPE_ID,
+            display: display_helper::<T>,
+            compare: compare_helper::<T>,
+            validate: validate_helper::<T>,
+        });
+        Ok(())
+    }
+
+    /// Checks whether a type with this id has been declared
+    pub fn is_registered(&self, type_id: u32) -> bool {
+        self.functions(type_id).is_some()
+    }
+
+    fn functions(&self, type_id: u32) -> Option<&ExtensionFunctions> {
+        self.types.iter().find(|ext| ext.type_id == type_id)
+    }
+
+    /// Compares the bytes of two values of a declared type. Returns `None` if the type isn't
+    /// declared or the bytes aren't valid for the type.
+    pub fn compare(&self, type_id: u32, left: &[u8], right: &[u8]) -> Option<Ordering> {
+        self.functions(type_id)
+            .and_then(|functions| (functions.compare)(left, right))
+    }
+
+    /// Checks that the bytes are a valid value of a type. Bytes of undeclared types are always
+    /// considered valid, as there's no way to check them.
+    pub fn validate(&self, type_id: u32, bytes: &[u8]) -> bool {
+        self.functions(type_id)
+            .is_none_or(|functions| (functions.validate)(bytes))
+    }
+
+    /// Displays a value, using the display of its type if it's of a declared type
+    pub fn display<'c>(&'c self, value: &'c Type) -> ExtensionDisplay<'c> {
+        ExtensionDisplay {
+            catalog: self,
+            value,
+        }
+    }
+}
+
+/// A value displayed through the catalog that declares its type
+pub struct ExtensionDisplay<'c> {
+    catalog: &'c ExtensionCatalog,
+    value: &'c Type,
+}
+
+impl Display for ExtensionDisplay<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.value {
+            Type::Extension(type_id, bytes) => match self.catalog.functions(*type_id) {
+                Some(functions) => (functions.display)(bytes, f),
+                None => write!(f, "{}", to_hex(bytes)),
+            },
+            value => write!(f, "{}", value),
+        }
+    }
+}
+
+pub(crate) fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+pub(crate) fn from_hex(string: &str) -> Option<Vec<u8>> {
+    if !string.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..string.len())
+        .step_by(2)
+        .map(|i| {
+            string
+                .get(i..i + 2)
+                .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+        })
+        .collect()
+}
+
+impl Type {
+    /// Creates a value of a custom type
+    pub fn extension<T: DBValue>(value: &T) -> Self {
+        Type::Extension(T::TYPE_ID, value.serialize())
+    }
+
+    /// Attempts to get the custom value stored in this value
+    pub fn as_extension<T: DBValue>(&self) -> Option<T> {
+        match self {
+            Type::Extension(type_id, bytes) if *type_id == T::TYPE_ID => T::deserialize(bytes),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::deserialization::parse_using_types;
+    use crate::serialization::serialize_values;
+
+    #[derive(Debug, PartialEq)]
+    struct Ipv4([u8; 4]);
+
+    impl Display for Ipv4 {
+        fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+            write!(f, "{}.{}.{}.{}", self.0[0], self.0[1], self.0[2], self.0[3])
+        }
+    }
+
+    impl DBValue for Ipv4 {
+        const TYPE_ID: u32 = 4;
+
+        fn serialize(&self) -> Vec<u8> {
+            self.0.to_vec()
+        }
+
+        fn deserialize(bytes: &[u8]) -> Option<Self> {
+            if bytes.len() == 4 {
+                Some(Ipv4([bytes[0], bytes[1], bytes[2], bytes[3]]))
+            } else {
+                None
+            }
+        }
+
+        fn compare(&self, other: &Self) -> Ordering {
+            self.0.cmp(&other.0)
+        }
+    }
+
+    #[test]
+    fn custom_type() {
+        let mut catalog = ExtensionCatalog::new();
+        catalog.register::<Ipv4>().unwrap();
+        assert!(catalog.register::<Ipv4>().is_err());
+
+        let local = Type::extension(&Ipv4([127, 0, 0, 1]));
+        let other = Type::extension(&Ipv4([192, 168, 0, 1]));
+        assert_eq!(catalog.display(&local).to_string(), "127.0.0.1");
+        assert_eq!(local.to_string(), "7f000001");
+        assert_eq!(local.as_extension::<Ipv4>(), Some(Ipv4([127, 0, 0, 1])));
+        assert_eq!(
+            catalog.compare(Ipv4::TYPE_ID, &[127, 0, 0, 1], &[192, 168, 0, 1]),
+            Some(Ordering::Less)
+        );
+
+        let serialized = serialize_values(vec![local.clone(), other.clone()]);
+        let blank = Type::Extension(Ipv4::TYPE_ID, vec![]);
+        let parsed = parse_using_types(serialized, vec![blank.clone(), blank.clone()]).unwrap();
+        assert_eq!(parsed, vec![local, other]);
+        let short = parse_using_types("7f0001", vec![blank]).unwrap();
+        match &short[0] {
+            Type::Extension(type_id, bytes) => assert!(!catalog.validate(*type_id, bytes)),
+            value => panic!("parsed {:?}", value),
+        }
+    }
+
+    #[test]
+    fn catalogs_are_separate() {
+        let mut catalog = ExtensionCatalog::new();
+        catalog.register::<Ipv4>().unwrap();
+        let other = ExtensionCatalog::new();
+        assert!(catalog.is_registered(Ipv4::TYPE_ID));
+        assert!(!other.is_registered(Ipv4::TYPE_ID));
+        let local = Type::extension(&Ipv4([127, 0, 0, 1]));
+        assert_eq!(other.display(&local).to_string(), "7f000001");
+    }
+}
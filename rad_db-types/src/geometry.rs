@@ -0,0 +1,145 @@
+//! Points on a plane, and the shapes they can be filtered by.
+//!
+//! Distances are Euclidean, so coordinates such as longitudes and latitudes are treated as if
+//! they were on a flat map, which is close enough over short distances.
+
+use std::fmt::{Display, Formatter};
+use std::hash::{Hash, Hasher};
+
+/// A point on a plane, such as a longitude and a latitude
+#[derive(Debug, Clone, Copy)]
+pub struct Point {
+    x: f64,
+    y: f64,
+}
+
+impl Point {
+    pub fn new(x: f64, y: f64) -> Self {
+        Point { x, y }
+    }
+
+    pub fn x(&self) -> f64 {
+        self.x
+    }
+
+    pub fn y(&self) -> f64 {
+        self.y
+    }
+
+    /// The straight line distance between the points
+    pub fn distance(&self, other: &Point) -> f64 {
+        (self.x - other.x).hypot(self.y - other.y)
+    }
+
+    /// Whether the point is at most the radius away from the center
+    pub fn within_radius(&self, center: &Point, radius: f64) -> bool {
+        self.distance(center) <= radius
+    }
+
+    /// Parses a point written like `(1.5, -2)`, or returns `None` if the string isn't one
+    pub fn parse(string: &str) -> Option<Self> {
+        let inner = string.trim().strip_prefix('(')?.strip_suffix(')')?;
+        let (x, y) = inner.split_once(',')?;
+        Some(Point::new(x.trim().parse().ok()?, y.trim().parse().ok()?))
+    }
+
+    /// The bits of a coordinate, where both zeroes are the same
+    fn bits(coordinate: f64) -> u64 {
+        if coordinate == 0.0 {
+            0
+        } else {
+            coordinate.to_bits()
+        }
+    }
+}
+
+impl PartialEq for Point {
+    /// Points are equal if their coordinates are, where `NaN` equals itself so that points can
+    /// be hashed
+    fn eq(&self, other: &Self) -> bool {
+        Self::bits(self.x) == Self::bits(other.x) && Self::bits(self.y) == Self::bits(other.y)
+    }
+}
+
+impl Eq for Point {}
+
+impl Hash for Point {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        Self::bits(self.x).hash(state);
+        Self::bits(self.y).hash(state);
+    }
+}
+
+impl Display for Point {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "({}, {})", self.x, self.y)
+    }
+}
+
+/// A polygon made of the vertices in order, where the last vertex connects back to the first
+#[derive(Debug, Clone, PartialEq)]
+pub struct Polygon {
+    vertices: Vec<Point>,
+}
+
+impl Polygon {
+    /// # Panic
+    /// Panics if there are fewer than 3 vertices
+    pub fn new<I: IntoIterator<Item = Point>>(vertices: I) -> Self {
+        let vertices: Vec<Point> = vertices.into_iter().collect();
+        assert!(
+            vertices.len() >= 3,
+            "A polygon must have at least 3 vertices"
+        );
+        Polygon { vertices }
+    }
+
+    pub fn vertices(&self) -> &[Point] {
+        &self.vertices
+    }
+
+    /// Whether the point is inside the polygon, counting how many edges a ray from the point
+    /// crosses. Points exactly on an edge may be either inside or outside.
+    pub fn contains(&self, point: &Point) -> bool {
+        let mut inside = false;
+        let mut previous = self.vertices[self.vertices.len() - 1];
+        for vertex in &self.vertices {
+            if (vertex.y > point.y) != (previous.y > point.y) {
+                let crossing = (previous.x - vertex.x) * (point.y - vertex.y)
+                    / (previous.y - vertex.y)
+                    + vertex.x;
+                if point.x < crossing {
+                    inside = !inside;
+                }
+            }
+            previous = *vertex;
+        }
+        inside
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn points_and_polygons() {
+        let origin = Point::new(0.0, 0.0);
+        assert_eq!(Point::new(3.0, 4.0).distance(&origin), 5.0);
+        assert!(Point::new(3.0, 4.0).within_radius(&origin, 5.0));
+        assert!(!Point::new(3.0, 4.1).within_radius(&origin, 5.0));
+        assert_eq!(Point::parse(" (1.5, -2) "), Some(Point::new(1.5, -2.0)));
+        assert_eq!(Point::parse("1.5, -2"), None);
+        assert_eq!(Point::new(-0.0, 1.0), Point::new(0.0, 1.0));
+        assert_eq!(Point::new(1.5, -2.0).to_string(), "(1.5, -2)");
+
+        let triangle = Polygon::new(vec![
+            Point::new(0.0, 0.0),
+            Point::new(4.0, 0.0),
+            Point::new(0.0, 4.0),
+        ]);
+        assert!(triangle.contains(&Point::new(1.0, 1.0)));
+        assert!(!triangle.contains(&Point::new(3.0, 3.0)));
+        assert!(!triangle.contains(&Point::new(-1.0, 1.0)));
+    }
+}
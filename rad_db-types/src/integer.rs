@@ -0,0 +1,228 @@
+//! Integers wider than 64 bits, for values such as identifiers and hashes that don't fit in a
+//! `BIGINT`.
+//!
+//! An integer is bounded by a number of bits, so an integer of 128 bits holds the numbers a 128
+//! bit two's complement integer can. Two integers are equal if they're the same number, whatever
+//! their bounds. Arithmetic is bounded by the wider operand, and an operation whose result doesn't
+//! fit returns `None`.
+
+use std::cmp::{max, Ordering};
+use std::fmt::{Display, Formatter};
+use std::hash::{Hash, Hasher};
+
+use num_bigint::BigInt;
+use num_traits::{ToPrimitive, Zero};
+
+use crate::Numeric;
+
+/// The most bits an integer can be bounded by
+pub const MAX_BITS: u16 = 4096;
+
+/// An integer of at most a number of bits
+#[derive(Debug, Clone)]
+pub struct Integer {
+    value: BigInt,
+    bits: u16,
+}
+
+impl Integer {
+    /// Creates an integer of the bits, or `None` if the value doesn't fit in them
+    ///
+    /// # Panic
+    /// Panics if the bits are 0 or more than [MAX_BITS]
+    pub fn new<V: Into<BigInt>>(value: V, bits: u16) -> Option<Self> {
+        assert!(
+            (1..=MAX_BITS).contains(&bits),
+            "An integer must have from 1 to {} bits",
+            MAX_BITS
+        );
+        let value = value.into();
+        if !Self::fits(&value, bits) {
+            return None;
+        }
+        Some(Integer { value, bits })
+    }
+
+    /// Zero with the bits, which can be used as the type of a field
+    ///
+    /// # Panic
+    /// Panics under the same conditions as [new](Integer::new)
+    pub fn zero(bits: u16) -> Self {
+        Self::new(BigInt::zero(), bits).expect("Zero fits in every integer")
+    }
+
+    /// Parses a number such as `-12`, or returns `None` if the string isn't an integer or the
+    /// integer doesn't fit in the bits
+    pub fn parse(string: &str, bits: u16) -> Option<Self> {
+        let string = string.trim();
+        let digits = string
+            .strip_prefix(|c| c == '-' || c == '+')
+            .unwrap_or(string);
+        if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+        Self::new(string.parse::<BigInt>().ok()?, bits)
+    }
+
+    /// The number as an integer, if it's one, bounded by the fewest bits every value of its kind
+    /// fits in
+    pub fn of(numeric: &Numeric) -> Option<Self> {
+        match numeric {
+            Numeric::Signed(signed) => Some(Integer::from(Into::<i64>::into(*signed))),
+            Numeric::Unsigned(unsigned) => Self::new(Into::<u64>::into(*unsigned), 65),
+            Numeric::BigInt(integer) => Some(integer.clone()),
+            _ => None,
+        }
+    }
+
+    /// Whether the value is within the range of a two's complement integer of the bits
+    fn fits(value: &BigInt, bits: u16) -> bool {
+        let limit = BigInt::from(1) << (bits as usize - 1);
+        value < &limit && value >= &-limit
+    }
+
+    pub fn value(&self) -> &BigInt {
+        &self.value
+    }
+
+    pub fn bits(&self) -> u16 {
+        self.bits
+    }
+
+    /// The most decimal digits an integer of these bits can have
+    pub fn digits(&self) -> u32 {
+        ((self.bits - 1) as f64 * 2f64.log10()).floor() as u32 + 1
+    }
+
+    /// The same number with other bits, or `None` if it doesn't fit in them
+    ///
+    /// # Panic
+    /// Panics under the same conditions as [new](Integer::new)
+    pub fn with_bits(self, bits: u16) -> Option<Self> {
+        Self::new(self.value, bits)
+    }
+
+    /// The number, if it fits in an `i128`
+    pub fn to_i128(&self) -> Option<i128> {
+        self.value.to_i128()
+    }
+
+    /// Converts the number into a double, potentially losing precision
+    pub fn to_f64(&self) -> f64 {
+        self.value.to_f64().unwrap_or(f64::NAN)
+    }
+
+    pub fn checked_add(&self, other: &Integer) -> Option<Integer> {
+        Self::new(&self.value + &other.value, max(self.bits, other.bits))
+    }
+
+    pub fn checked_sub(&self, other: &Integer) -> Option<Integer> {
+        Self::new(&self.value - &other.value, max(self.bits, other.bits))
+    }
+
+    pub fn checked_mul(&self, other: &Integer) -> Option<Integer> {
+        Self::new(&self.value * &other.value, max(self.bits, other.bits))
+    }
+
+    /// Divides the numbers, rounding towards zero, or returns `None` if the divisor is zero or
+    /// the quotient doesn't fit
+    pub fn checked_div(&self, other: &Integer) -> Option<Integer> {
+        if other.value.is_zero() {
+            return None;
+        }
+        Self::new(&self.value / &other.value, max(self.bits, other.bits))
+    }
+
+    /// The remainder of dividing the numbers, or `None` if the divisor is zero
+    pub fn checked_rem(&self, other: &Integer) -> Option<Integer> {
+        if other.value.is_zero() {
+            return None;
+        }
+        Self::new(&self.value % &other.value, max(self.bits, other.bits))
+    }
+
+    /// The negated number, or `None` for the smallest number of the bits
+    pub fn checked_neg(&self) -> Option<Integer> {
+        Self::new(-&self.value, self.bits)
+    }
+}
+
+impl From<i64> for Integer {
+    /// The number as an integer of 64 bits
+    fn from(value: i64) -> Self {
+        Integer {
+            value: value.into(),
+            bits: 64,
+        }
+    }
+}
+
+impl From<i128> for Integer {
+    /// The number as an integer of 128 bits
+    fn from(value: i128) -> Self {
+        Integer {
+            value: value.into(),
+            bits: 128,
+        }
+    }
+}
+
+impl PartialEq for Integer {
+    fn eq(&self, other: &Self) -> bool {
+        self.value == other.value
+    }
+}
+
+impl Eq for Integer {}
+
+impl PartialOrd for Integer {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Integer {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.value.cmp(&other.value)
+    }
+}
+
+impl Hash for Integer {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.value.hash(state)
+    }
+}
+
+impl Display for Integer {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bounded_by_bits() {
+        let max = Integer::parse("170141183460469231731687303715884105727", 128).unwrap();
+        assert_eq!(max.to_i128(), Some(i128::MAX));
+        assert_eq!(max.checked_add(&Integer::from(1i64)), None);
+        assert!(Integer::parse("170141183460469231731687303715884105728", 128).is_none());
+        assert!(Integer::parse("-170141183460469231731687303715884105728", 128).is_some());
+        assert_eq!(Integer::parse("-128", 8).unwrap().checked_neg(), None);
+        assert!(Integer::parse("12a", 64).is_none());
+        assert!(Integer::parse("", 64).is_none());
+
+        let wide = max.clone().with_bits(256).unwrap();
+        let doubled = wide.checked_mul(&Integer::from(2i64)).unwrap();
+        assert_eq!(
+            doubled.to_string(),
+            "340282366920938463463374607431768211454"
+        );
+        assert_eq!(doubled.bits(), 256);
+        assert_eq!(doubled.checked_div(&Integer::from(2i64)).unwrap(), max);
+        assert_eq!(max.checked_div(&Integer::zero(8)), None);
+        assert_eq!(Integer::from(7i64), Integer::new(7, 300).unwrap());
+    }
+}
@@ -0,0 +1,108 @@
+//! Writes values the way they are written in JSON
+
+use crate::extension::to_hex;
+use crate::{Numeric, Text, Time, Type, Value};
+
+/// Quotes a string as a JSON string, escaping quotes, backslashes and control characters
+pub fn json_string(string: &str) -> String {
+    let mut quoted = String::with_capacity(string.len() + 2);
+    quoted.push('"');
+    for c in string.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+fn json_number(number: f64) -> String {
+    if number.is_finite() {
+        number.to_string()
+    } else {
+        json_string(&number.to_string())
+    }
+}
+
+fn hex_string(bytes: &[u8]) -> String {
+    format!("\"0x{}\"", to_hex(bytes))
+}
+
+/// Writes the value as a JSON value. Numbers that JSON can't represent, like `NaN`, are written
+/// as strings, and bytes are written as strings of their hex.
+pub fn json_value(value: &Value) -> String {
+    match value {
+        Type::Optional(None) => "null".to_string(),
+        Type::Optional(Some(inner)) => json_value(inner),
+        Type::Numeric(Numeric::Float(f)) if !f.is_finite() => json_string(&f.to_string()),
+        Type::Numeric(Numeric::Double(d)) if !d.is_finite() => json_string(&d.to_string()),
+        Type::Numeric(numeric) => numeric.to_string(),
+        Type::Text(text) => match text {
+            Text::Char(c) => json_string(c.encode_utf8(&mut [0; 4])),
+            Text::String(s, _) => json_string(s),
+            Text::Binary(b) => hex_string(&[*b]),
+            Text::BinaryString(bytes, _) | Text::Blob(bytes) => hex_string(bytes),
+            Text::BlobRef(handle) => json_string(&handle.to_string()),
+            Text::BitString(bits, _) => json_string(&bits.to_string()),
+        },
+        Type::Time(time) => match time {
+            Time::Date(date) => json_string(&date.to_string()),
+            Time::TimeOfDay(time) => json_string(&time.to_string()),
+            Time::DateTime(date_time) => {
+                json_string(&date_time.format("%Y-%m-%dT%H:%M:%S%.f").to_string())
+            }
+            Time::Timestamp(timestamp) => json_string(&timestamp.to_rfc3339()),
+            Time::TimestampTz(timestamp) => json_string(&timestamp.to_rfc3339()),
+            Time::Year(year) => year.to_string(),
+        },
+        Type::Boolean(b) => b.to_string(),
+        Type::Point(point) => format!("[{},{}]", json_number(point.x()), json_number(point.y())),
+        Type::Inet(inet) => json_string(&inet.to_string()),
+        Type::MacAddress(address) => json_string(&address.to_string()),
+        Type::Extension(_, bytes) => hex_string(bytes),
+        Type::Array(values, _) => {
+            let values: Vec<String> = values.iter().map(json_value).collect();
+            format!("[{}]", values.join(","))
+        }
+        Type::Enum(e) => json_string(e.label()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Inet, Point};
+
+    #[test]
+    fn values() {
+        assert_eq!(
+            json_value(&Value::from("say \"hi\"\n")),
+            r#""say \"hi\"\n""#
+        );
+        assert_eq!(json_value(&Value::from(-3i32)), "-3");
+        assert_eq!(json_value(&Numeric::Double(f64::NAN).into()), "\"NaN\"");
+        assert_eq!(json_value(&Value::from(false)), "false");
+        assert_eq!(json_value(&Point::new(1.5, -2.0).into()), "[1.5,-2]");
+        assert_eq!(
+            json_value(&Inet::parse("10.0.0.0/8").unwrap().into()),
+            "\"10.0.0.0/8\""
+        );
+        assert_eq!(json_value(&Value::Optional(None)), "null");
+        assert_eq!(
+            json_value(&Time::date(2021, 3, 4).unwrap().into()),
+            "\"2021-03-04\""
+        );
+        assert_eq!(
+            json_value(&Type::array(Type::from(0u8), vec![1u8.into(), 2u8.into()])),
+            "[1,2]"
+        );
+        assert_eq!(json_value(&Text::Blob(vec![0xab, 1]).into()), "\"0xab01\"");
+        assert_eq!(json_string("\u{1}"), "\"\\u0001\"");
+    }
+}
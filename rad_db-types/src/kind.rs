@@ -0,0 +1,531 @@
+//! Kinds of values, which describe what values a field can hold.
+//!
+//! Fields are declared with a value of their kind, such as `Type::from(0u64)`, which can be
+//! turned into a [Kind] with [Kind::of]. A kind carries the constraints of the field that aren't
+//! part of any one value, like how long its strings can be and whether it can be `NULL`, and
+//! [validates](Kind::validate) values against them.
+
+use crate::sql::sql_column_type;
+use crate::{
+    BitString, BitWidth, ElementKind, Enumeration, Inet, MacAddress, Numeric, Point, Signed, Text,
+    Time, Type, Unsigned,
+};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime, Utc};
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::net::Ipv4Addr;
+use std::time::UNIX_EPOCH;
+
+/// What values a field can hold
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Kind {
+    Byte,
+    Short,
+    Int,
+    Long,
+    UnsignedByte,
+    UnsignedShort,
+    UnsignedInt,
+    UnsignedLong,
+    Float,
+    Double,
+    Decimal {
+        precision: u8,
+        scale: u8,
+    },
+    /// Integers of at most the bits, including their sign
+    BigInt {
+        bits: u16,
+    },
+    Char,
+    /// Strings of at most the length in bytes, if there is one
+    String {
+        max_length: Option<u16>,
+    },
+    Binary,
+    BinaryString {
+        max_length: u16,
+    },
+    Blob,
+    BitString {
+        width: BitWidth,
+    },
+    Date,
+    TimeOfDay,
+    DateTime,
+    Timestamp,
+    TimestampTz,
+    Year,
+    Boolean,
+    Point,
+    Inet,
+    MacAddress,
+    /// Arrays of values of the element kind
+    Array(Box<Kind>),
+    /// One of the labels
+    Enum(Vec<String>),
+    /// A registered [extension](crate::extension) type, by its id
+    Extension(u32),
+    /// Values of the kind, or `NULL`
+    Nullable(Box<Kind>),
+    /// Any value, including `NULL`, which is the kind of a field declared with `NULL` itself
+    Any,
+}
+
+/// Why a value isn't of a kind
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mismatch {
+    /// The value is `NULL`, and the kind isn't nullable
+    Null,
+    /// The value is longer than the kind allows
+    TooLong,
+    /// The value isn't as long as a kind of a fixed length requires
+    WrongLength,
+    /// The value is a number the kind can't represent
+    OutOfRange,
+    /// The value is of another kind
+    WrongKind,
+}
+
+/// Why a value isn't of a kind, with the limit of the kind it breaks
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ValidationError {
+    /// The value is `NULL`, and the kind isn't nullable
+    Null,
+    /// The value is longer than the most bytes, or bits for bit strings, the kind allows
+    TooLong { max: usize, found: usize },
+    /// The value isn't as long as a kind of a fixed length requires
+    WrongLength { expected: usize, found: usize },
+    /// The value is a number outside the range of the numeric kind, or one it can't represent
+    /// exactly, like `300` for an unsigned byte
+    OutOfRange { expected: Kind },
+    /// The value is of another kind
+    WrongKind { expected: Kind, found: Kind },
+}
+
+impl ValidationError {
+    /// What kind of mismatch the error is, without its details
+    pub fn mismatch(&self) -> Mismatch {
+        match self {
+            ValidationError::Null => Mismatch::Null,
+            ValidationError::TooLong { .. } => Mismatch::TooLong,
+            ValidationError::WrongLength { .. } => Mismatch::WrongLength,
+            ValidationError::OutOfRange { .. } => Mismatch::OutOfRange,
+            ValidationError::WrongKind { .. } => Mismatch::WrongKind,
+        }
+    }
+}
+
+impl Display for ValidationError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ValidationError::Null => write!(f, "NULL isn't allowed"),
+            ValidationError::TooLong { max, found } => {
+                write!(
+                    f,
+                    "Length {} is longer than the most allowed, {}",
+                    found, max
+                )
+            }
+            ValidationError::WrongLength { expected, found } => {
+                write!(f, "Expected a length of {}, found {}", expected, found)
+            }
+            ValidationError::OutOfRange { expected } => {
+                write!(f, "Number out of the range of {}", expected)
+            }
+            ValidationError::WrongKind { expected, found } => {
+                write!(f, "Expected a value of {}, found {}", expected, found)
+            }
+        }
+    }
+}
+
+impl Error for ValidationError {}
+
+impl Kind {
+    /// The kind of a value, where optional values are of nullable kinds
+    pub fn of(value: &Type) -> Kind {
+        match value {
+            Type::Numeric(numeric) => match numeric {
+                Numeric::Signed(Signed::Byte(_)) => Kind::Byte,
+                Numeric::Signed(Signed::Short(_)) => Kind::Short,
+                Numeric::Signed(Signed::Int(_)) => Kind::Int,
+                Numeric::Signed(Signed::Long(_)) => Kind::Long,
+                Numeric::Unsigned(Unsigned::Byte(_)) => Kind::UnsignedByte,
+                Numeric::Unsigned(Unsigned::Short(_)) => Kind::UnsignedShort,
+                Numeric::Unsigned(Unsigned::Int(_)) => Kind::UnsignedInt,
+                Numeric::Unsigned(Unsigned::Long(_)) => Kind::UnsignedLong,
+                Numeric::Float(_) => Kind::Float,
+                Numeric::Double(_) => Kind::Double,
+                Numeric::Decimal(decimal) => Kind::Decimal {
+                    precision: decimal.precision(),
+                    scale: decimal.scale(),
+                },
+                Numeric::BigInt(integer) => Kind::BigInt {
+                    bits: integer.bits(),
+                },
+            },
+            Type::Text(text) => match text {
+                Text::Char(_) => Kind::Char,
+                Text::String(_, max_length) => Kind::String {
+                    max_length: *max_length,
+                },
+                Text::Binary(_) => Kind::Binary,
+                Text::BinaryString(_, max_length) => Kind::BinaryString {
+                    max_length: *max_length,
+                },
+                Text::Blob(_) | Text::BlobRef(_) => Kind::Blob,
+                Text::BitString(_, width) => Kind::BitString { width: *width },
+            },
+            Type::Time(time) => match time {
+                Time::Date(_) => Kind::Date,
+                Time::TimeOfDay(_) => Kind::TimeOfDay,
+                Time::DateTime(_) => Kind::DateTime,
+                Time::Timestamp(_) => Kind::Timestamp,
+                Time::TimestampTz(_) => Kind::TimestampTz,
+                Time::Year(_) => Kind::Year,
+            },
+            Type::Boolean(_) => Kind::Boolean,
+            Type::Point(_) => Kind::Point,
+            Type::Inet(_) => Kind::Inet,
+            Type::MacAddress(_) => Kind::MacAddress,
+            Type::Optional(Some(inner)) => Kind::of(inner).nullable(true),
+            Type::Optional(None) => Kind::Any,
+            Type::Array(_, element) => Kind::Array(Box::new(Kind::of(element))),
+            Type::Enum(enumeration) => Kind::Enum(enumeration.labels().to_vec()),
+            Type::Extension(id, _) => Kind::Extension(*id),
+        }
+    }
+
+    /// A value of the kind, such as zero or an empty string, that fields can be declared with
+    pub fn prototype(&self) -> Type {
+        match self {
+            Kind::Byte => Type::from(0i8),
+            Kind::Short => Type::from(0i16),
+            Kind::Int => Type::from(0i32),
+            Kind::Long => Type::from(0i64),
+            Kind::UnsignedByte => Type::from(0u8),
+            Kind::UnsignedShort => Type::from(0u16),
+            Kind::UnsignedInt => Type::from(0u32),
+            Kind::UnsignedLong => Type::from(0u64),
+            Kind::Float => Numeric::Float(0.0).into(),
+            Kind::Double => Numeric::Double(0.0).into(),
+            Kind::Decimal { precision, scale } => crate::Decimal::zero(*precision, *scale).into(),
+            Kind::BigInt { bits } => crate::Integer::zero(*bits).into(),
+            Kind::Char => Text::Char(' ').into(),
+            Kind::String { max_length } => Text::String(String::new(), *max_length).into(),
+            Kind::Binary => Text::Binary(0).into(),
+            Kind::BinaryString { max_length } => Text::BinaryString(vec![], *max_length).into(),
+            Kind::Blob => Text::Blob(vec![]).into(),
+            Kind::BitString { width } => {
+                let len = match width {
+                    BitWidth::Fixed(width) => *width as usize,
+                    BitWidth::Varying(_) => 0,
+                };
+                Text::BitString(BitString::zeroes(len), *width).into()
+            }
+            Kind::Date => Time::Date(NaiveDate::from_ymd_opt(1970, 1, 1).unwrap()).into(),
+            Kind::TimeOfDay => Time::TimeOfDay(NaiveTime::from_hms_opt(0, 0, 0).unwrap()).into(),
+            Kind::DateTime => Time::DateTime(NaiveDateTime::default()).into(),
+            Kind::Timestamp => Time::Timestamp(DateTime::<Utc>::from(UNIX_EPOCH)).into(),
+            Kind::TimestampTz => {
+                Time::TimestampTz(DateTime::<Utc>::from(UNIX_EPOCH).fixed_offset()).into()
+            }
+            Kind::Year => Time::Year(1970).into(),
+            Kind::Boolean => Type::Boolean(false),
+            Kind::Point => Point::new(0.0, 0.0).into(),
+            Kind::Inet => Inet::host(Ipv4Addr::UNSPECIFIED).into(),
+            Kind::MacAddress => MacAddress::new([0; 6]).into(),
+            Kind::Array(element) => Type::Array(vec![], ElementKind::new(element.prototype())),
+            Kind::Enum(labels) => Enumeration::new(labels, 0)
+                .expect("The labels of a kind are the labels of an enumeration")
+                .into(),
+            Kind::Extension(id) => Type::Extension(*id, vec![]),
+            Kind::Nullable(inner) => Type::Optional(Some(Box::new(inner.prototype()))),
+            Kind::Any => Type::Optional(None),
+        }
+    }
+
+    /// Whether `NULL` is of the kind
+    pub fn is_nullable(&self) -> bool {
+        matches!(self, Kind::Nullable(_) | Kind::Any)
+    }
+
+    /// The kind, accepting `NULL` or not
+    pub fn nullable(self, nullable: bool) -> Kind {
+        match (nullable, self) {
+            (true, kind) if kind.is_nullable() => kind,
+            (true, kind) => Kind::Nullable(Box::new(kind)),
+            (false, Kind::Nullable(inner)) => *inner,
+            (false, kind) => kind,
+        }
+    }
+
+    /// The kind of the values that aren't `NULL`
+    pub fn non_null(&self) -> &Kind {
+        match self {
+            Kind::Nullable(inner) => inner.non_null(),
+            kind => kind,
+        }
+    }
+
+    /// The most bytes values of the kind can have, if it limits them
+    pub fn max_length(&self) -> Option<usize> {
+        match self.non_null() {
+            Kind::String { max_length } => max_length.map(usize::from),
+            Kind::BinaryString { max_length } => Some(*max_length as usize),
+            _ => None,
+        }
+    }
+
+    /// Checks that the value is of the kind, isn't `NULL` unless the kind is nullable and isn't
+    /// longer than the kind allows, giving the limit it breaks if it isn't. The elements of arrays
+    /// are checked against the element kind. Numbers of other numeric kinds are never of the
+    /// kind, but are out of its range if they can't be represented by it.
+    pub fn validate(&self, value: &Type) -> Result<(), ValidationError> {
+        match (self, value) {
+            (Kind::Any, _) => Ok(()),
+            (Kind::Nullable(_), Type::Optional(None)) => Ok(()),
+            (_, Type::Optional(None)) => Err(ValidationError::Null),
+            (kind, Type::Optional(Some(value))) => kind.validate(value),
+            (Kind::Nullable(kind), value) => kind.validate(value),
+            (Kind::String { max_length }, Type::Text(Text::String(string, _))) => {
+                match max_length {
+                    Some(max) if string.len() > *max as usize => Err(ValidationError::TooLong {
+                        max: *max as usize,
+                        found: string.len(),
+                    }),
+                    _ => Ok(()),
+                }
+            }
+            (Kind::BinaryString { max_length }, Type::Text(Text::BinaryString(bytes, _))) => {
+                if bytes.len() > *max_length as usize {
+                    Err(ValidationError::TooLong {
+                        max: *max_length as usize,
+                        found: bytes.len(),
+                    })
+                } else {
+                    Ok(())
+                }
+            }
+            (Kind::BitString { width }, Type::Text(Text::BitString(bits, _))) => match width {
+                _ if width.allows(bits.len()) => Ok(()),
+                BitWidth::Fixed(expected) => Err(ValidationError::WrongLength {
+                    expected: *expected as usize,
+                    found: bits.len(),
+                }),
+                BitWidth::Varying(max) => Err(ValidationError::TooLong {
+                    max: max.map_or(usize::MAX, usize::from),
+                    found: bits.len(),
+                }),
+            },
+            (Kind::Array(element), Type::Array(values, _)) => {
+                values.iter().try_for_each(|value| element.validate(value))
+            }
+            (kind, value) => {
+                let found = Kind::of(value);
+                if found == *kind {
+                    Ok(())
+                } else if kind.is_exact_number()
+                    && found.is_exact_number()
+                    && value.coerce_like(&kind.prototype()).is_none()
+                {
+                    Err(ValidationError::OutOfRange {
+                        expected: kind.clone(),
+                    })
+                } else {
+                    Err(ValidationError::WrongKind {
+                        expected: kind.clone(),
+                        found,
+                    })
+                }
+            }
+        }
+    }
+
+    /// Checks the value like [validate](Kind::validate), giving only what kind of mismatch the
+    /// value is if it isn't of the kind
+    pub fn check(&self, value: &Type) -> Result<(), Mismatch> {
+        self.validate(value).map_err(|error| error.mismatch())
+    }
+
+    /// Whether the kind is of integers or decimals, whose ranges values can be checked against
+    fn is_exact_number(&self) -> bool {
+        matches!(
+            self,
+            Kind::Byte
+                | Kind::Short
+                | Kind::Int
+                | Kind::Long
+                | Kind::UnsignedByte
+                | Kind::UnsignedShort
+                | Kind::UnsignedInt
+                | Kind::UnsignedLong
+                | Kind::Decimal { .. }
+                | Kind::BigInt { .. }
+        )
+    }
+
+    /// Whether the value is of the kind, as [checked](Kind::check)
+    pub fn matches(&self, value: &Type) -> bool {
+        self.check(value).is_ok()
+    }
+}
+
+impl From<&Type> for Kind {
+    fn from(value: &Type) -> Self {
+        Kind::of(value)
+    }
+}
+
+impl From<Type> for Kind {
+    fn from(value: Type) -> Self {
+        Kind::of(&value)
+    }
+}
+
+impl Display for Kind {
+    /// Writes the kind as the SQL type of a column
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Kind::Any => write!(f, "ANY"),
+            kind => write!(f, "{}", sql_column_type(&kind.prototype())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Decimal;
+
+    #[test]
+    fn kinds_of_prototypes() {
+        let prototypes = vec![
+            Type::from(0u8),
+            Type::from(0i64),
+            Decimal::zero(6, 2).into(),
+            Text::String(String::new(), Some(4)).into(),
+            Time::date(2020, 1, 2).unwrap().into(),
+            Type::array(Type::from(0u32), vec![]),
+            Enumeration::new(&["red", "green"], 1).unwrap().into(),
+            Type::Optional(Some(Box::new(Type::from(false)))),
+        ];
+        for prototype in prototypes {
+            let kind = Kind::of(&prototype);
+            assert_eq!(Kind::of(&kind.prototype()), kind);
+        }
+        assert_eq!(
+            Kind::of(&Type::Optional(Some(Box::new(Type::from(0u8))))),
+            Kind::Nullable(Box::new(Kind::UnsignedByte))
+        );
+        assert_eq!(Kind::UnsignedLong.nullable(true).to_string(), "NUMERIC(20)");
+        assert_eq!(Kind::Boolean.to_string(), "BOOLEAN NOT NULL");
+    }
+
+    #[test]
+    fn check_values() {
+        let short = Kind::String {
+            max_length: Some(3),
+        };
+        assert!(short.matches(&Type::from("abc")));
+        assert_eq!(short.check(&Type::from("abcd")), Err(Mismatch::TooLong));
+        assert_eq!(short.check(&Type::Optional(None)), Err(Mismatch::Null));
+        assert!(short.clone().nullable(true).matches(&Type::Optional(None)));
+        assert!(short
+            .clone()
+            .nullable(true)
+            .matches(&Type::Optional(Some(Box::new(Type::from("ab"))))));
+        assert_eq!(
+            Kind::UnsignedByte.check(&Type::from(0u16)),
+            Err(Mismatch::WrongKind)
+        );
+        assert_eq!(
+            Kind::Decimal {
+                precision: 6,
+                scale: 2
+            }
+            .check(&Decimal::zero(6, 3).into()),
+            Err(Mismatch::WrongKind)
+        );
+        let flags = |bits: &str, width: BitWidth| {
+            Kind::BitString { width }
+                .check(&Text::BitString(BitString::parse(bits).unwrap(), width).into())
+        };
+        assert_eq!(flags("101", BitWidth::Fixed(3)), Ok(()));
+        assert_eq!(flags("10", BitWidth::Fixed(3)), Err(Mismatch::WrongLength));
+        assert_eq!(flags("10", BitWidth::Varying(Some(3))), Ok(()));
+        assert_eq!(
+            flags("1010", BitWidth::Varying(Some(3))),
+            Err(Mismatch::TooLong)
+        );
+        let array = Kind::Array(Box::new(Kind::UnsignedInt));
+        assert!(array.matches(&Type::array(Type::from(0u32), vec![Type::from(1u32)])));
+        assert!(Kind::Any.matches(&Type::from(1u8)));
+    }
+
+    #[test]
+    fn validate_values() {
+        let short = Kind::String {
+            max_length: Some(3),
+        };
+        assert_eq!(
+            short.validate(&Type::from("abcde")),
+            Err(ValidationError::TooLong { max: 3, found: 5 })
+        );
+        assert_eq!(
+            Kind::BitString {
+                width: BitWidth::Fixed(3)
+            }
+            .validate(&Text::BitString(BitString::zeroes(2), BitWidth::Fixed(2)).into()),
+            Err(ValidationError::WrongLength {
+                expected: 3,
+                found: 2
+            })
+        );
+        assert_eq!(
+            Kind::UnsignedByte.validate(&Type::from(300u16)),
+            Err(ValidationError::OutOfRange {
+                expected: Kind::UnsignedByte
+            })
+        );
+        assert_eq!(
+            Kind::UnsignedByte.validate(&Type::from(-1i8)),
+            Err(ValidationError::OutOfRange {
+                expected: Kind::UnsignedByte
+            })
+        );
+        assert_eq!(
+            Kind::UnsignedByte.validate(&Type::from(3u16)),
+            Err(ValidationError::WrongKind {
+                expected: Kind::UnsignedByte,
+                found: Kind::UnsignedShort
+            })
+        );
+        let money = Kind::Decimal {
+            precision: 4,
+            scale: 2,
+        };
+        assert_eq!(
+            money.validate(&Type::from(100u32)),
+            Err(ValidationError::OutOfRange {
+                expected: money.clone()
+            })
+        );
+        assert_eq!(
+            Kind::UnsignedByte.validate(&Type::from("3")),
+            Err(ValidationError::WrongKind {
+                expected: Kind::UnsignedByte,
+                found: Kind::String { max_length: None }
+            })
+        );
+        assert_eq!(
+            Kind::UnsignedByte.check(&Type::from(300u16)),
+            Err(Mismatch::OutOfRange)
+        );
+        assert_eq!(
+            Kind::Boolean.validate(&Type::Optional(None)),
+            Err(ValidationError::Null)
+        );
+    }
+}
@@ -1,7 +1,7 @@
 //! This crate contains all of the types that can be used in the RadDB program. It establishes
 //! all relevant traits as well.
 
-use chrono::{Date, DateTime, Local, Utc};
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveDateTime, NaiveTime, ParseResult, Utc};
 use std::cmp::min;
 use std::convert::{TryFrom, TryInto};
 use std::ffi::CString;
@@ -9,34 +9,95 @@ use std::fmt::{Display, Formatter};
 use std::hash::{Hash, Hasher};
 use std::num::NonZeroU8;
 
+pub use array::ElementKind;
+pub use bits::{BitString, BitWidth};
+pub use blob::BlobHandle;
+pub use cast::CastError;
+pub use decimal::Decimal;
+use display::BinaryDisplay;
+pub use enumeration::Enumeration;
+pub use geometry::Point;
+pub use integer::Integer;
+pub use kind::{Kind, Mismatch, ValidationError};
+pub use network::{Inet, MacAddress};
+
+pub mod arithmetic;
+pub mod array;
+pub mod bits;
+pub mod blob;
+pub mod cast;
+pub mod comparison;
+pub mod decimal;
 pub mod deserialization;
+pub mod display;
+pub mod enumeration;
+pub mod extension;
+pub mod geometry;
+pub mod integer;
+pub mod json;
+pub mod kind;
+pub mod network;
 pub mod serialization;
+pub mod sql;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
 
 /// Values and Types are equivalent!
 pub type Value = Type;
 
-#[derive(Debug, Clone, Copy, PartialOrd, PartialEq)]
+/// Numbers are [ordered](Numeric::numeric_cmp) by their values whatever their variants, so that
+/// columns of mixed numbers can be sorted
+#[derive(Debug, Clone)]
 pub enum Numeric {
     Float(f32),
     Double(f64),
     Signed(Signed),
     Unsigned(Unsigned),
+    /// A fixed-point number, whose type is its precision and scale
+    Decimal(Decimal),
+    /// An integer of any width, whose type is its number of bits
+    BigInt(Integer),
 }
 
-impl Eq for Numeric {}
-
+/// Floating point numbers are hashed by their canonical bits, so that numbers that are equal
+/// hash the same: `-0.0` hashes like `0.0`, and every `NaN` hashes like every other `NaN`, as
+/// they are all equal to each other. Numbers of different variants are never equal, so a float
+/// can hash the same as an integer without being equal to it.
 impl Hash for Numeric {
     fn hash<H: Hasher>(&self, state: &mut H) {
         match self {
-            Numeric::Float(_) | Numeric::Double(_) => {
-                panic!("Can't hash on floating point numbers")
-            }
+            Numeric::Float(f) => canonical_bits(*f as f64).hash(state),
+            Numeric::Double(d) => canonical_bits(*d).hash(state),
             Numeric::Signed(s) => s.hash(state),
             Numeric::Unsigned(o) => o.hash(state),
+            Numeric::Decimal(d) => d.hash(state),
+            Numeric::BigInt(i) => i.hash(state),
         }
     }
 }
 
+/// The bits of the float, with `-0.0` replaced by `0.0` and every `NaN` by the same `NaN`
+pub fn canonical_bits(float: f64) -> u64 {
+    if float.is_nan() {
+        f64::NAN.to_bits()
+    } else if float == 0.0 {
+        0
+    } else {
+        float.to_bits()
+    }
+}
+
+/// The bits of the single precision float, made canonical like [`canonical_bits`]
+pub fn canonical_bits32(float: f32) -> u32 {
+    if float.is_nan() {
+        f32::NAN.to_bits()
+    } else if float == 0.0 {
+        0
+    } else {
+        float.to_bits()
+    }
+}
+
 #[derive(Debug, Clone, Copy, Ord, PartialOrd, Eq, PartialEq, Hash)]
 pub enum Signed {
     Byte(i8),
@@ -60,16 +121,86 @@ pub enum Text {
     Binary(u8),
     BinaryString(Vec<u8>, u16),
     Blob(Vec<u8>),
+    /// A blob that a relation stored out of line, and reads through its handle
+    BlobRef(BlobHandle),
+    /// Bits of the width, written as `0`s and `1`s
+    BitString(BitString, BitWidth),
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Time {
-    Date(Date<Local>),
-    DateTime(DateTime<Local>),
+    Date(NaiveDate),
+    /// A time within a day, without any date attached
+    TimeOfDay(NaiveTime),
+    /// A date and time of day, without a time zone, which reads the same on every machine
+    DateTime(NaiveDateTime),
+    /// An instant, kept in UTC
     Timestamp(DateTime<Utc>),
+    /// An instant, along with the offset from UTC it was written in
+    TimestampTz(DateTime<FixedOffset>),
     Year(i32),
 }
 
+impl Time {
+    /// The format dates are written and parsed in
+    pub const DATE_FORMAT: &'static str = "%Y-%m-%d";
+    /// The format times of day are written and parsed in
+    pub const TIME_OF_DAY_FORMAT: &'static str = "%H:%M:%S%.f";
+    /// The format dates with times of day are written and parsed in
+    pub const DATE_TIME_FORMAT: &'static str = "%Y-%m-%dT%H:%M:%S%.f";
+
+    /// Creates a date, or `None` if the date doesn't exist
+    pub fn date(year: i32, month: u32, day: u32) -> Option<Self> {
+        NaiveDate::from_ymd_opt(year, month, day).map(Time::Date)
+    }
+
+    /// Creates a time of day, or `None` if the time doesn't exist
+    pub fn time_of_day(hour: u32, minute: u32, second: u32) -> Option<Self> {
+        NaiveTime::from_hms_opt(hour, minute, second).map(Time::TimeOfDay)
+    }
+
+    /// Parses a date in the `YYYY-MM-DD` format
+    pub fn parse_date<S: AsRef<str>>(string: S) -> ParseResult<Self> {
+        NaiveDate::parse_from_str(string.as_ref(), Self::DATE_FORMAT).map(Time::Date)
+    }
+
+    /// Parses a time of day in the `HH:MM:SS` format, with optional fractional seconds
+    pub fn parse_time_of_day<S: AsRef<str>>(string: S) -> ParseResult<Self> {
+        NaiveTime::parse_from_str(string.as_ref(), Self::TIME_OF_DAY_FORMAT).map(Time::TimeOfDay)
+    }
+
+    /// Parses a date and time of day in the `YYYY-MM-DDTHH:MM:SS` format, with optional fractional
+    /// seconds. Date times with an offset, which is how they used to be written, are read as the
+    /// time they show.
+    pub fn parse_date_time<S: AsRef<str>>(string: S) -> ParseResult<Self> {
+        let string = string.as_ref();
+        NaiveDateTime::parse_from_str(string, Self::DATE_TIME_FORMAT)
+            .or_else(|error| {
+                string
+                    .parse::<DateTime<FixedOffset>>()
+                    .map(|date_time| date_time.naive_local())
+                    .map_err(|_| error)
+            })
+            .map(Time::DateTime)
+    }
+
+    /// Parses an instant in the RFC 3339 format, converting it to UTC
+    pub fn parse_timestamp<S: AsRef<str>>(string: S) -> ParseResult<Self> {
+        string
+            .as_ref()
+            .parse::<DateTime<FixedOffset>>()
+            .map(|timestamp| Time::Timestamp(timestamp.with_timezone(&Utc)))
+    }
+
+    /// Parses an instant in the RFC 3339 format, keeping its offset
+    pub fn parse_timestamp_tz<S: AsRef<str>>(string: S) -> ParseResult<Self> {
+        string
+            .as_ref()
+            .parse::<DateTime<FixedOffset>>()
+            .map(Time::TimestampTz)
+    }
+}
+
 /// Base type for all data types
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum Type {
@@ -78,6 +209,41 @@ pub enum Type {
     Time(Time),
     Boolean(bool),
     Optional(Option<Box<Type>>),
+    /// Any number of values of the element kind
+    Array(Vec<Type>, ElementKind),
+    /// A point on a plane
+    Point(Point),
+    /// An IP address with the prefix of its subnet
+    Inet(Inet),
+    MacAddress(MacAddress),
+    /// One of a declared set of labels, whose type is its labels
+    Enum(Enumeration),
+    /// A custom type registered through the [extension](extension) module, made up of the id of the
+    /// type and the serialized value
+    Extension(u32, Vec<u8>),
+}
+
+impl Type {
+    /// Whether this is a `NULL`
+    pub fn is_null(&self) -> bool {
+        matches!(self, Type::Optional(None))
+    }
+
+    /// What this wraps, through any number of optionals, or this if it isn't optional
+    pub fn unwrap_optional(&self) -> &Type {
+        match self {
+            Type::Optional(Some(inner)) => inner.unwrap_optional(),
+            other => other,
+        }
+    }
+
+    /// This as the type of a nullable field, wrapping it in an optional unless it already is one
+    pub fn nullable(self) -> Type {
+        match self {
+            Type::Optional(_) => self,
+            other => Type::Optional(Some(Box::new(other))),
+        }
+    }
 }
 
 impl From<Numeric> for Type {
@@ -98,6 +264,42 @@ impl From<Unsigned> for Type {
     }
 }
 
+impl From<Decimal> for Type {
+    fn from(n: Decimal) -> Self {
+        Numeric::Decimal(n).into()
+    }
+}
+
+impl From<Integer> for Type {
+    fn from(n: Integer) -> Self {
+        Numeric::BigInt(n).into()
+    }
+}
+
+impl From<Point> for Type {
+    fn from(point: Point) -> Self {
+        Type::Point(point)
+    }
+}
+
+impl From<Inet> for Type {
+    fn from(inet: Inet) -> Self {
+        Type::Inet(inet)
+    }
+}
+
+impl From<MacAddress> for Type {
+    fn from(address: MacAddress) -> Self {
+        Type::MacAddress(address)
+    }
+}
+
+impl From<Enumeration> for Type {
+    fn from(e: Enumeration) -> Self {
+        Type::Enum(e)
+    }
+}
+
 impl From<Text> for Type {
     fn from(t: Text) -> Self {
         Type::Text(t)
@@ -204,6 +406,8 @@ impl Display for Numeric {
             Numeric::Double(d) => d,
             Numeric::Signed(s) => s,
             Numeric::Unsigned(u) => u,
+            Numeric::Decimal(d) => d,
+            Numeric::BigInt(i) => i,
         };
         write!(f, "{}", disp)
     }
@@ -214,44 +418,53 @@ impl Display for Text {
         let disp: &dyn Display = match self {
             Text::Char(c) => c,
             Text::String(s, _) => s,
-            Text::Binary(b) => b,
-            Text::BinaryString(b, _) => unsafe {
-                return write!(f, "{}", String::from_utf8_unchecked(b.clone()));
-            },
-            Text::Blob(blob) => unsafe {
-                return write!(f, "{}", String::from_utf8_unchecked(blob.clone()));
-            },
+            Text::BlobRef(handle) => return write!(f, "{}", handle),
+            Text::BitString(bits, _) => return write!(f, "{}", bits),
+            binary => return write!(f, "{}", BinaryDisplay::Hex.render(&binary.as_bytes())),
         };
         write!(f, "\"{}\"", disp)
     }
 }
 
 impl Display for Time {
+    /// Writes the time in the format it's parsed in, which doesn't depend on the local time zone
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        let disp: &dyn Display = match self {
-            Time::Date(d) => d,
-            Time::DateTime(datetime) => datetime,
-            Time::Timestamp(t) => t,
-            Time::Year(yr) => yr,
-        };
-        write!(f, "{}", disp)
+        match self {
+            Time::Date(d) => write!(f, "{}", d.format(Time::DATE_FORMAT)),
+            Time::TimeOfDay(t) => write!(f, "{}", t.format(Time::TIME_OF_DAY_FORMAT)),
+            Time::DateTime(datetime) => write!(f, "{}", datetime.format(Time::DATE_TIME_FORMAT)),
+            Time::Timestamp(t) => write!(f, "{}", t.to_rfc3339()),
+            Time::TimestampTz(t) => write!(f, "{}", t.to_rfc3339()),
+            Time::Year(yr) => write!(f, "{}", yr),
+        }
     }
 }
 
 impl Display for Type {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         let disp: &dyn Display = match self {
+            Type::Extension(_, bytes) => return write!(f, "{}", extension::to_hex(bytes)),
             Type::Numeric(n) => n,
             Type::Text(t) => t,
             Type::Time(t) => t,
             Type::Boolean(b) => b,
+            Type::Enum(e) => e,
+            Type::Point(point) => point,
+            Type::Inet(inet) => inet,
+            Type::MacAddress(address) => address,
             Type::Optional(Some(inner)) => inner,
             Type::Optional(None) => &"NULL",
+            Type::Array(values, _) => {
+                let values: Vec<String> = values.iter().map(ToString::to_string).collect();
+                return write!(f, "[{}]", values.join(", "));
+            }
         };
         write!(f, "{}", disp)
     }
 }
 
+/// Whether values are of the same type. An optional type is the same as the type it wraps, so
+/// nullable and non-nullable fields of the same kind hold the same values.
 pub trait SameType {
     fn same_type(&self, other: &Self) -> bool;
 }
@@ -287,6 +500,10 @@ impl SameType for Numeric {
             (Numeric::Unsigned(self_n), Numeric::Unsigned(other_n)) => self_n.same_type(other_n),
             (Numeric::Double(_), Numeric::Double(_)) => true,
             (Numeric::Float(_), Numeric::Float(_)) => true,
+            (Numeric::Decimal(self_n), Numeric::Decimal(other_n)) => {
+                self_n.precision() == other_n.precision() && self_n.scale() == other_n.scale()
+            }
+            (Numeric::BigInt(self_n), Numeric::BigInt(other_n)) => self_n.bits() == other_n.bits(),
             _ => false,
         }
     }
@@ -299,7 +516,8 @@ impl SameType for Text {
             (Text::String(_, len1), Text::String(_, len2)) => len1 == len2,
             (Text::Binary(_), Text::Binary(_)) => true,
             (Text::BinaryString(_, len1), Text::BinaryString(_, len2)) => len1 == len2,
-            (Text::Blob(_), Text::Blob(_)) => true,
+            (Text::Blob(_) | Text::BlobRef(_), Text::Blob(_) | Text::BlobRef(_)) => true,
+            (Text::BitString(_, width1), Text::BitString(_, width2)) => width1 == width2,
             _ => false,
         }
     }
@@ -309,8 +527,10 @@ impl SameType for Time {
     fn same_type(&self, other: &Self) -> bool {
         match (self, other) {
             (Time::Date(_), Time::Date(_)) => true,
+            (Time::TimeOfDay(_), Time::TimeOfDay(_)) => true,
             (Time::DateTime(_), Time::DateTime(_)) => true,
             (Time::Timestamp(_), Time::Timestamp(_)) => true,
+            (Time::TimestampTz(_), Time::TimestampTz(_)) => true,
             (Time::Year(_), Time::Year(_)) => true,
             _ => false,
         }
@@ -324,6 +544,20 @@ impl SameType for Type {
             (Type::Text(self_n), Type::Text(other_n)) => self_n.same_type(other_n),
             (Type::Time(self_n), Type::Time(other_n)) => self_n.same_type(other_n),
             (Type::Boolean(_), Type::Boolean(_)) => true,
+            (Type::Point(_), Type::Point(_)) => true,
+            (Type::Inet(_), Type::Inet(_)) => true,
+            (Type::MacAddress(_), Type::MacAddress(_)) => true,
+            (Type::Extension(self_id, _), Type::Extension(other_id, _)) => self_id == other_id,
+            (Type::Array(_, self_kind), Type::Array(_, other_kind)) => self_kind == other_kind,
+            (Type::Enum(self_e), Type::Enum(other_e)) => self_e.same_labels(other_e),
+            (Type::Optional(Some(self_n)), Type::Optional(Some(other_n))) => {
+                self_n.same_type(other_n)
+            }
+            // NULL is of every nullable type, but of no type that isn't nullable
+            (Type::Optional(None), Type::Optional(_))
+            | (Type::Optional(_), Type::Optional(None)) => true,
+            (Type::Optional(Some(self_n)), other) => self_n.as_ref().same_type(other),
+            (this, Type::Optional(Some(other_n))) => this.same_type(other_n.as_ref()),
             _ => false,
         }
     }
@@ -460,24 +694,47 @@ impl TryInto<CString> for Text {
     }
 }
 
+/// Converts the value within a value that isn't `NULL`, giving back the whole value if it can't
+fn try_from_present<T: TryFrom<Value, Error = Value>>(inner: Value) -> Result<T, Value> {
+    T::try_from(inner).map_err(|inner| Value::Optional(Some(Box::new(inner))))
+}
+
+/// The integer a value holds, if it's a whole number
+fn integer_of(value: &Value) -> Option<i128> {
+    match value {
+        Value::Numeric(Numeric::Signed(signed)) => Some(Into::<i64>::into(*signed) as i128),
+        Value::Numeric(Numeric::Unsigned(unsigned)) => Some(Into::<u64>::into(*unsigned) as i128),
+        Value::Numeric(Numeric::BigInt(integer)) => integer.to_i128(),
+        _ => None,
+    }
+}
+
+/// Any integer that fits, including one that isn't `NULL`
 impl TryFrom<Value> for u64 {
     type Error = Value;
 
     fn try_from(value: Value) -> Result<Self, Self::Error> {
         match value {
-            Value::Numeric(Numeric::Unsigned(unsigned)) => Ok(unsigned.into()),
-            v => Err(v),
+            Value::Optional(Some(inner)) => try_from_present(*inner),
+            v => match integer_of(&v).and_then(|integer| u64::try_from(integer).ok()) {
+                Some(integer) => Ok(integer),
+                None => Err(v),
+            },
         }
     }
 }
 
+/// Any integer that fits, including one that isn't `NULL`
 impl TryFrom<Value> for i64 {
     type Error = Value;
 
     fn try_from(value: Value) -> Result<Self, Self::Error> {
         match value {
-            Value::Numeric(Numeric::Signed(signed)) => Ok(signed.into()),
-            v => Err(v),
+            Value::Optional(Some(inner)) => try_from_present(*inner),
+            v => match integer_of(&v).and_then(|integer| i64::try_from(integer).ok()) {
+                Some(integer) => Ok(integer),
+                None => Err(v),
+            },
         }
     }
 }
@@ -488,6 +745,7 @@ impl TryFrom<Value> for f64 {
     fn try_from(value: Value) -> Result<Self, Self::Error> {
         match value {
             Value::Numeric(num) => num.try_into().map_err(Value::Numeric),
+            Value::Optional(Some(inner)) => try_from_present(*inner),
             v => Err(v),
         }
     }
@@ -499,16 +757,123 @@ impl TryFrom<Value> for String {
     fn try_from(value: Value) -> Result<Self, Self::Error> {
         match value {
             Value::Text(text) => text.try_into().map_err(Value::Text),
+            Value::Optional(Some(inner)) => try_from_present(*inner),
+            v => Err(v),
+        }
+    }
+}
+
+impl TryFrom<Value> for bool {
+    type Error = Value;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Boolean(boolean) => Ok(boolean),
+            Value::Optional(Some(inner)) => try_from_present(*inner),
+            v => Err(v),
+        }
+    }
+}
+
+impl TryFrom<Value> for NaiveDate {
+    type Error = Value;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Time(Time::Date(date)) => Ok(date),
+            Value::Optional(Some(inner)) => try_from_present(*inner),
+            v => Err(v),
+        }
+    }
+}
+
+impl TryFrom<Value> for NaiveTime {
+    type Error = Value;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Time(Time::TimeOfDay(time)) => Ok(time),
+            Value::Optional(Some(inner)) => try_from_present(*inner),
+            v => Err(v),
+        }
+    }
+}
+
+impl TryFrom<Value> for NaiveDateTime {
+    type Error = Value;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Time(Time::DateTime(date_time)) => Ok(date_time),
+            Value::Optional(Some(inner)) => try_from_present(*inner),
+            v => Err(v),
+        }
+    }
+}
+
+/// Either kind of timestamp, as the instant it is
+impl TryFrom<Value> for DateTime<Utc> {
+    type Error = Value;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Time(Time::Timestamp(timestamp)) => Ok(timestamp),
+            Value::Time(Time::TimestampTz(timestamp)) => Ok(timestamp.with_timezone(&Utc)),
+            Value::Optional(Some(inner)) => try_from_present(*inner),
             v => Err(v),
         }
     }
 }
 
+/// Either kind of timestamp, where timestamps kept in UTC have no offset
+impl TryFrom<Value> for DateTime<FixedOffset> {
+    type Error = Value;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Time(Time::TimestampTz(timestamp)) => Ok(timestamp),
+            Value::Time(Time::Timestamp(timestamp)) => Ok(timestamp.fixed_offset()),
+            Value::Optional(Some(inner)) => try_from_present(*inner),
+            v => Err(v),
+        }
+    }
+}
+
+/// Converts `NULL` into `None`, and every other value that can be converted into `Some`
+macro_rules! try_from_optional {
+    ($($native:ty),*) => {
+        $(
+            impl TryFrom<Value> for Option<$native> {
+                type Error = Value;
+
+                fn try_from(value: Value) -> Result<Self, Self::Error> {
+                    match value {
+                        Value::Optional(None) => Ok(None),
+                        value => <$native>::try_from(value).map(Some),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+try_from_optional!(
+    u64,
+    i64,
+    f64,
+    String,
+    bool,
+    NaiveDate,
+    NaiveTime,
+    NaiveDateTime,
+    DateTime<Utc>,
+    DateTime<FixedOffset>
+);
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::serialization::serialize_values;
-    use chrono::TimeZone;
 
     #[test]
     fn conversion() {
@@ -521,10 +886,102 @@ mod tests {
         assert_eq!("3,9,\"hello\"", text);
     }
 
+    #[test]
+    fn native_conversions() {
+        let present = |value: Type| Type::Optional(Some(Box::new(value)));
+        assert_eq!(u64::try_from(Type::from(7u8)), Ok(7));
+        assert_eq!(u64::try_from(Type::from(7i32)), Ok(7));
+        assert_eq!(u64::try_from(Type::from(-7i32)), Err(Type::from(-7i32)));
+        assert_eq!(i64::try_from(present(Type::from(7u16))), Ok(7));
+        assert_eq!(
+            i64::try_from(Type::from(u64::MAX)),
+            Err(Type::from(u64::MAX))
+        );
+        assert_eq!(f64::try_from(Type::from(Numeric::Double(0.5))), Ok(0.5));
+        assert_eq!(String::try_from(Type::from("abc")), Ok("abc".to_string()));
+        assert_eq!(
+            String::try_from(present(Type::from(1u8))),
+            Err(present(Type::from(1u8)))
+        );
+        assert_eq!(bool::try_from(Type::from(true)), Ok(true));
+
+        let date = NaiveDate::from_ymd_opt(2021, 6, 1).unwrap();
+        assert_eq!(NaiveDate::try_from(Type::from(Time::Date(date))), Ok(date));
+        assert!(NaiveTime::try_from(Type::from(Time::Date(date))).is_err());
+        let timestamp = Time::parse_timestamp_tz("2021-06-01T08:30:00+02:00").unwrap();
+        let utc = DateTime::<Utc>::try_from(Type::from(timestamp)).unwrap();
+        assert_eq!(utc.to_rfc3339(), "2021-06-01T06:30:00+00:00");
+        assert_eq!(
+            DateTime::<FixedOffset>::try_from(Type::from(timestamp))
+                .unwrap()
+                .to_rfc3339(),
+            "2021-06-01T08:30:00+02:00"
+        );
+
+        assert_eq!(Option::<u64>::try_from(Type::Optional(None)), Ok(None));
+        assert_eq!(
+            Option::<u64>::try_from(present(Type::from(3u8))),
+            Ok(Some(3))
+        );
+        assert_eq!(Option::<bool>::try_from(Type::from(false)), Ok(Some(false)));
+        assert!(Option::<String>::try_from(Type::from(3u8)).is_err());
+    }
+
+    #[test]
+    fn nullable_types() {
+        let nullable = Type::Optional(Some(Box::new(Type::from(0u32))));
+        assert!(nullable.same_type(&Type::from(5u32)));
+        assert!(Type::from(5u32).same_type(&nullable));
+        assert!(nullable.same_type(&Type::Optional(None)));
+        assert!(!nullable.same_type(&Type::from(5u64)));
+        assert!(!Type::Optional(None).same_type(&Type::from(5u32)));
+        assert!(vec![nullable, Type::from("")].same_type(&vec![Type::Optional(None), "a".into()]));
+
+        let nullable = Type::from(0u32).nullable();
+        assert_eq!(nullable, Type::Optional(Some(Box::new(Type::from(0u32)))));
+        assert_eq!(nullable.clone().nullable(), nullable);
+        assert_eq!(nullable.unwrap_optional(), &Type::from(0u32));
+        assert!(Type::Optional(None).is_null());
+        assert!(!nullable.is_null());
+    }
+
     #[test]
     fn date() {
-        let date = Time::Date(Local.ymd(1999, 3, 7));
-        println!("{}", date);
+        let date = Time::date(1999, 3, 7).unwrap();
+        assert_eq!(date.to_string(), "1999-03-07");
+        assert_eq!(Time::parse_date("1999-03-07").unwrap(), date);
+        assert!(Time::date(1999, 2, 30).is_none());
+    }
+
+    #[test]
+    fn time_of_day() {
+        let time = Time::time_of_day(13, 5, 9).unwrap();
+        assert_eq!(time.to_string(), "13:05:09");
+        assert_eq!(Time::parse_time_of_day("13:05:09").unwrap(), time);
+        assert!(Time::parse_time_of_day("25:00:00").is_err());
+    }
+
+    #[test]
+    fn date_times() {
+        let date_time = Time::parse_date_time("2021-06-01T08:30:00").unwrap();
+        assert_eq!(date_time.to_string(), "2021-06-01T08:30:00");
+        assert_eq!(
+            Time::parse_date_time("2021-06-01 08:30:00 +02:00").unwrap(),
+            date_time
+        );
+
+        let timestamp_tz = Time::parse_timestamp_tz("2021-06-01T08:30:00+02:00").unwrap();
+        assert_eq!(timestamp_tz.to_string(), "2021-06-01T08:30:00+02:00");
+        assert_eq!(
+            Time::parse_timestamp("2021-06-01T08:30:00+02:00")
+                .unwrap()
+                .to_string(),
+            "2021-06-01T06:30:00+00:00"
+        );
+        assert_eq!(
+            Time::parse_timestamp_tz(timestamp_tz.to_string()).unwrap(),
+            timestamp_tz
+        );
     }
 
     #[test]
@@ -533,6 +990,7 @@ mod tests {
             Signed::Byte(3).into(),
             Unsigned::Long(23241212332).into(),
             Text::String("Hello World!".to_string(), None).into(),
+            Decimal::parse("-12.5", 5, 2).unwrap().into(),
         ];
         let to_check = types.clone();
         let serialized = serialize_values(types);
@@ -540,8 +998,39 @@ mod tests {
             Signed::Byte(0).into(),
             Unsigned::Long(0).into(),
             Text::String(String::new(), None).into(),
+            Decimal::zero(5, 2).into(),
         ];
         let deserialized = deserialization::parse_using_types(serialized, types).unwrap();
         assert_eq!(deserialized, to_check);
     }
+
+    #[test]
+    fn float_hashing() {
+        use std::collections::hash_map::DefaultHasher;
+
+        fn hash_of(numeric: Numeric) -> u64 {
+            let mut hasher = DefaultHasher::new();
+            numeric.hash(&mut hasher);
+            hasher.finish()
+        }
+
+        assert_eq!(Numeric::Double(-0.0), Numeric::Double(0.0));
+        assert_eq!(
+            hash_of(Numeric::Double(-0.0)),
+            hash_of(Numeric::Double(0.0))
+        );
+        assert_eq!(hash_of(Numeric::Float(-0.0)), hash_of(Numeric::Float(0.0)));
+        let other_nan = f64::from_bits(f64::NAN.to_bits() | 1);
+        assert!(other_nan.is_nan());
+        assert_eq!(Numeric::Double(f64::NAN), Numeric::Double(other_nan));
+        assert_eq!(
+            hash_of(Numeric::Double(f64::NAN)),
+            hash_of(Numeric::Double(other_nan))
+        );
+        assert_eq!(
+            hash_of(Numeric::Float(-f32::NAN)),
+            hash_of(Numeric::Float(f32::NAN))
+        );
+        assert_ne!(hash_of(Numeric::Double(1.5)), hash_of(Numeric::Double(2.5)));
+    }
 }
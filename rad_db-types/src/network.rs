@@ -0,0 +1,230 @@
+//! Network addresses, such as the addresses of hosts and the subnets they belong to.
+//!
+//! Both kinds of addresses are stored as their bytes rather than their text, so an IPv4 address
+//! with its prefix takes 6 bytes, an IPv6 address 18 and a MAC address 6.
+
+use std::convert::TryFrom;
+use std::fmt::{Display, Formatter};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+/// An IPv4 or IPv6 address with the length of the prefix of its subnet, like `192.168.0.1/24`.
+/// The bits of the address after the prefix are kept, so it's both a host and its subnet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Inet {
+    address: IpAddr,
+    prefix: u8,
+}
+
+impl Inet {
+    /// Returns `None` if the prefix is longer than the address
+    pub fn new<A: Into<IpAddr>>(address: A, prefix: u8) -> Option<Self> {
+        let address = address.into();
+        if prefix > max_prefix(&address) {
+            None
+        } else {
+            Some(Inet { address, prefix })
+        }
+    }
+
+    /// A single host, whose prefix is the whole address
+    pub fn host<A: Into<IpAddr>>(address: A) -> Self {
+        let address = address.into();
+        Inet {
+            address,
+            prefix: max_prefix(&address),
+        }
+    }
+
+    pub fn address(&self) -> IpAddr {
+        self.address
+    }
+
+    pub fn prefix(&self) -> u8 {
+        self.prefix
+    }
+
+    /// The address with every bit after the prefix cleared
+    pub fn network(&self) -> IpAddr {
+        let width = max_prefix(&self.address);
+        let bits = bits(&self.address) & mask(self.prefix, width);
+        match self.address {
+            IpAddr::V4(_) => IpAddr::V4(Ipv4Addr::from(bits as u32)),
+            IpAddr::V6(_) => IpAddr::V6(Ipv6Addr::from(bits)),
+        }
+    }
+
+    /// Whether the other address is in this subnet, which is when both are the same version,
+    /// the other prefix is at least as long and their first bits up to this prefix are the same.
+    /// A subnet contains itself.
+    pub fn contains(&self, other: &Inet) -> bool {
+        self.address.is_ipv4() == other.address.is_ipv4()
+            && other.prefix >= self.prefix
+            && (bits(&self.address) ^ bits(&other.address))
+                & mask(self.prefix, max_prefix(&self.address))
+                == 0
+    }
+
+    /// Parses an address written like `192.168.0.1/24` or `::1`, where an address without a
+    /// prefix is a single host. Returns `None` if the string isn't one.
+    pub fn parse(string: &str) -> Option<Self> {
+        let string = string.trim();
+        match string.split_once('/') {
+            Some((address, prefix)) => {
+                Inet::new(address.parse::<IpAddr>().ok()?, prefix.parse().ok()?)
+            }
+            None => Some(Inet::host(string.parse::<IpAddr>().ok()?)),
+        }
+    }
+
+    /// The version of the address, then the bytes of the address and the prefix, which sort in
+    /// the same order as the addresses
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = match self.address {
+            IpAddr::V4(address) => {
+                let mut bytes = vec![4];
+                bytes.extend_from_slice(&address.octets());
+                bytes
+            }
+            IpAddr::V6(address) => {
+                let mut bytes = vec![6];
+                bytes.extend_from_slice(&address.octets());
+                bytes
+            }
+        };
+        bytes.push(self.prefix);
+        bytes
+    }
+
+    /// Reads an address written by [to_bytes](Inet::to_bytes)
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let (&version, rest) = bytes.split_first()?;
+        let (&prefix, address) = rest.split_last()?;
+        let address = match version {
+            4 => IpAddr::V4(Ipv4Addr::from(<[u8; 4]>::try_from(address).ok()?)),
+            6 => IpAddr::V6(Ipv6Addr::from(<[u8; 16]>::try_from(address).ok()?)),
+            _ => return None,
+        };
+        Inet::new(address, prefix)
+    }
+}
+
+impl Display for Inet {
+    /// The prefix is only written if the address isn't a single host
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.prefix == max_prefix(&self.address) {
+            write!(f, "{}", self.address)
+        } else {
+            write!(f, "{}/{}", self.address, self.prefix)
+        }
+    }
+}
+
+fn max_prefix(address: &IpAddr) -> u8 {
+    match address {
+        IpAddr::V4(_) => 32,
+        IpAddr::V6(_) => 128,
+    }
+}
+
+fn bits(address: &IpAddr) -> u128 {
+    match address {
+        IpAddr::V4(address) => u32::from(*address) as u128,
+        IpAddr::V6(address) => u128::from(*address),
+    }
+}
+
+/// The bits of the first `prefix` bits of an address `width` bits long
+fn mask(prefix: u8, width: u8) -> u128 {
+    let all = u128::MAX >> (128 - width as u32);
+    all ^ all.checked_shr(prefix as u32).unwrap_or(0)
+}
+
+/// The hardware address of a network interface, like `08:00:2b:01:02:03`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct MacAddress([u8; 6]);
+
+impl MacAddress {
+    pub fn new(bytes: [u8; 6]) -> Self {
+        MacAddress(bytes)
+    }
+
+    pub fn bytes(&self) -> [u8; 6] {
+        self.0
+    }
+
+    /// Parses six pairs of hex digits separated by colons or hyphens, or returns `None` if the
+    /// string isn't one
+    pub fn parse(string: &str) -> Option<Self> {
+        let string = string.trim();
+        let separator = if string.contains('-') { '-' } else { ':' };
+        let mut bytes = [0; 6];
+        let mut parts = string.split(separator);
+        for byte in &mut bytes {
+            let part = parts.next()?;
+            if part.len() != 2 {
+                return None;
+            }
+            *byte = u8::from_str_radix(part, 16).ok()?;
+        }
+        match parts.next() {
+            None => Some(MacAddress(bytes)),
+            Some(_) => None,
+        }
+    }
+}
+
+impl Display for MacAddress {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let [a, b, c, d, e, g] = self.0;
+        write!(
+            f,
+            "{:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}",
+            a, b, c, d, e, g
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn inets() {
+        let subnet = Inet::parse("192.168.0.1/24").unwrap();
+        assert_eq!(subnet.prefix(), 24);
+        assert_eq!(subnet.network(), "192.168.0.0".parse::<IpAddr>().unwrap());
+        assert_eq!(subnet.to_string(), "192.168.0.1/24");
+        assert_eq!(Inet::parse("10.0.0.1").unwrap().to_string(), "10.0.0.1");
+        assert_eq!(Inet::parse("10.0.0.1/33"), None);
+        assert_eq!(Inet::parse("10.0.0/8"), None);
+
+        assert!(subnet.contains(&Inet::parse("192.168.0.200").unwrap()));
+        assert!(subnet.contains(&Inet::parse("192.168.0.0/25").unwrap()));
+        assert!(subnet.contains(&subnet));
+        assert!(!subnet.contains(&Inet::parse("192.168.1.1").unwrap()));
+        assert!(!subnet.contains(&Inet::parse("192.168.0.0/16").unwrap()));
+        assert!(!subnet.contains(&Inet::parse("::ffff:c0a8:1").unwrap()));
+        assert!(Inet::parse("0.0.0.0/0").unwrap().contains(&subnet));
+
+        let v6 = Inet::parse("2001:db8::/32").unwrap();
+        assert!(v6.contains(&Inet::parse("2001:db8:1::1").unwrap()));
+        assert!(!v6.contains(&Inet::parse("2001:db9::1").unwrap()));
+
+        for inet in &[subnet, v6] {
+            assert_eq!(Inet::from_bytes(&inet.to_bytes()), Some(*inet));
+        }
+        assert_eq!(subnet.to_bytes().len(), 6);
+        assert!(subnet.to_bytes() < v6.to_bytes());
+    }
+
+    #[test]
+    fn mac_addresses() {
+        let address = MacAddress::new([0x08, 0x00, 0x2b, 0x01, 0x02, 0x03]);
+        assert_eq!(MacAddress::parse("08:00:2b:01:02:03"), Some(address));
+        assert_eq!(MacAddress::parse("08-00-2B-01-02-03"), Some(address));
+        assert_eq!(address.to_string(), "08:00:2b:01:02:03");
+        assert_eq!(MacAddress::parse("08:00:2b:01:02"), None);
+        assert_eq!(MacAddress::parse("08:00:2b:01:02:03:04"), None);
+        assert_eq!(MacAddress::parse("08:00:2b:01:02:3"), None);
+    }
+}
@@ -1,28 +1,188 @@
+use std::io;
+use std::io::Write;
+
+use crate::extension::to_hex;
 use crate::{Text, Type};
 
-pub fn serialize_values<I: IntoIterator<Item = Type>>(values: I) -> String {
-    let vec = values
-        .into_iter()
-        .map(|v: Type| match v {
+/// How values are written in the text format of tuples
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextFormat {
+    delimiter: char,
+    quote: char,
+    null: String,
+}
+
+impl Default for TextFormat {
+    fn default() -> Self {
+        TextFormat {
+            delimiter: '|',
+            quote: '"',
+            null: "NULL".to_string(),
+        }
+    }
+}
+
+impl TextFormat {
+    /// The character used to escape other characters
+    pub const ESCAPE: char = '\\';
+
+    /// # Panic
+    /// Panics if the delimiter and quote are the same, if either is the escape character, or if
+    /// the `NULL` token is empty
+    pub fn new<S: ToString>(delimiter: char, quote: char, null: S) -> Self {
+        let null = null.to_string();
+        assert_ne!(
+            delimiter, quote,
+            "The delimiter and quote must be different"
+        );
+        assert!(
+            delimiter != Self::ESCAPE && quote != Self::ESCAPE,
+            "The escape character can't be a delimiter or quote"
+        );
+        assert!(!null.is_empty(), "The NULL token can't be empty");
+        TextFormat {
+            delimiter,
+            quote,
+            null,
+        }
+    }
+
+    pub fn delimiter(&self) -> char {
+        self.delimiter
+    }
+
+    pub fn quote(&self) -> char {
+        self.quote
+    }
+
+    /// The unquoted field that is read and written for `NULL`
+    pub fn null(&self) -> &str {
+        &self.null
+    }
+
+    /// The format the elements of arrays are written in, within the quotes of the array
+    pub(crate) fn element_format(&self) -> TextFormat {
+        let delimiter = if self.quote == ',' { ';' } else { ',' };
+        TextFormat {
+            delimiter,
+            ..self.clone()
+        }
+    }
+
+    fn write_escaped<W: Write>(
+        &self,
+        string: &str,
+        quoted: bool,
+        writer: &mut W,
+    ) -> io::Result<()> {
+        // the characters between escapes are written all at once
+        let mut unescaped = 0;
+        for (index, c) in string.char_indices() {
+            if c == Self::ESCAPE || c == self.quote || (!quoted && c == self.delimiter) {
+                writer.write_all(&string.as_bytes()[unescaped..index])?;
+                write!(writer, "{}", Self::ESCAPE)?;
+                unescaped = index;
+            }
+        }
+        writer.write_all(&string.as_bytes()[unescaped..])
+    }
+
+    fn write_quoted<W: Write>(&self, string: &str, writer: &mut W) -> io::Result<()> {
+        write!(writer, "{}", self.quote)?;
+        self.write_escaped(string, true, writer)?;
+        write!(writer, "{}", self.quote)
+    }
+
+    fn write_value<W: Write>(&self, value: Type, writer: &mut W) -> io::Result<()> {
+        match value {
+            Type::Optional(None) => self.write_escaped(&self.null, false, writer),
+            Type::Optional(Some(inner)) => self.write_value(*inner, writer),
             Type::Text(text) => match text {
-                Text::Char(c) => {
-                    format!("\"{}\"", c)
-                }
-                Text::String(s, _) => {
-                    format!("\"{}\"", s)
-                }
+                Text::Char(c) => self.write_quoted(c.encode_utf8(&mut [0; 4]), writer),
+                Text::String(s, _) => self.write_quoted(&s, writer),
                 Text::Binary(_) => {
                     unimplemented!()
                 }
                 Text::BinaryString(_, _) => {
                     unimplemented!()
                 }
-                Text::Blob(_) => {
-                    unimplemented!()
+                Text::Blob(bytes) => {
+                    self.write_escaped(&format!("0x{}", to_hex(&bytes)), false, writer)
                 }
+                Text::BlobRef(handle) => self.write_escaped(&handle.to_string(), false, writer),
+                Text::BitString(bits, _) => self.write_escaped(&bits.to_packed(), false, writer),
             },
-            rest => rest.to_string(),
-        })
-        .collect::<Vec<String>>();
-    vec.join("|")
+            Type::Extension(_, bytes) => writer.write_all(to_hex(&bytes).as_bytes()),
+            Type::Enum(e) => write!(writer, "{}", e.ordinal()),
+            // the elements are escaped again as a whole, so they have to be written out first
+            Type::Array(values, _) => self.write_quoted(
+                &serialize_values_with(values, &self.element_format()),
+                writer,
+            ),
+            rest => self.write_escaped(&rest.to_string(), false, writer),
+        }
+    }
+}
+
+pub fn serialize_values<I: IntoIterator<Item = Type>>(values: I) -> String {
+    serialize_values_with(values, &TextFormat::default())
+}
+
+/// Writes the values in the given text format
+pub fn serialize_values_with<I: IntoIterator<Item = Type>>(
+    values: I,
+    format: &TextFormat,
+) -> String {
+    let mut written = vec![];
+    serialize_values_to_with(values, format, &mut written).expect("Writing to memory can't fail");
+    String::from_utf8(written).expect("Only strings were written")
+}
+
+/// Writes the values straight into the writer, without building a string for them first
+pub fn serialize_values_to<I, W>(values: I, writer: &mut W) -> io::Result<()>
+where
+    I: IntoIterator<Item = Type>,
+    W: Write,
+{
+    serialize_values_to_with(values, &TextFormat::default(), writer)
+}
+
+/// Writes the values in the given text format straight into the writer
+pub fn serialize_values_to_with<I, W>(
+    values: I,
+    format: &TextFormat,
+    writer: &mut W,
+) -> io::Result<()>
+where
+    I: IntoIterator<Item = Type>,
+    W: Write,
+{
+    for (index, value) in values.into_iter().enumerate() {
+        if index > 0 {
+            write!(writer, "{}", format.delimiter)?;
+        }
+        format.write_value(value, writer)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn write_into_writer() {
+        let format = TextFormat::new(',', '\'', "NULL");
+        let values = vec![
+            Type::from(3u8),
+            Type::from("it's, quoted \\"),
+            Type::Optional(None),
+            Type::array(Type::from(0u8), vec![1u8.into(), 2u8.into()]),
+        ];
+        let mut written = vec![];
+        serialize_values_to_with(values.clone(), &format, &mut written).unwrap();
+        let written = String::from_utf8(written).unwrap();
+        assert_eq!(written, "3,'it\\'s, quoted \\\\',NULL,'1,2'");
+        assert_eq!(written, serialize_values_with(values, &format));
+    }
 }
@@ -0,0 +1,163 @@
+//! Writes types and values the way they are written in SQL
+
+use crate::extension::to_hex;
+use crate::{BitWidth, Numeric, Signed, Text, Time, Type, Unsigned, Value};
+
+/// Quotes a name so that it can be used as a SQL identifier
+pub fn quote_identifier(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+fn quote_string(string: &str) -> String {
+    format!("'{}'", string.replace('\'', "''"))
+}
+
+fn hex_literal(bytes: &[u8]) -> String {
+    format!("X'{}'", to_hex(bytes))
+}
+
+/// Gets the SQL type of a field, including whether it can be `NULL`
+pub fn sql_column_type(ty: &Type) -> String {
+    match ty {
+        Type::Optional(Some(inner)) => sql_type(inner),
+        Type::Optional(None) => "BLOB".to_string(),
+        ty => format!("{} NOT NULL", sql_type(ty)),
+    }
+}
+
+/// Gets the SQL type of values of the type. SQL has no unsigned integers, so they are widened
+/// into the next larger signed integer.
+pub fn sql_type(ty: &Type) -> String {
+    match ty {
+        Type::Numeric(numeric) => match numeric {
+            Numeric::Float(_) => "REAL",
+            Numeric::Double(_) => "DOUBLE PRECISION",
+            Numeric::Signed(Signed::Byte(_)) | Numeric::Signed(Signed::Short(_)) => "SMALLINT",
+            Numeric::Unsigned(Unsigned::Byte(_)) => "SMALLINT",
+            Numeric::Signed(Signed::Int(_)) | Numeric::Unsigned(Unsigned::Short(_)) => "INTEGER",
+            Numeric::Signed(Signed::Long(_)) | Numeric::Unsigned(Unsigned::Int(_)) => "BIGINT",
+            Numeric::Unsigned(Unsigned::Long(_)) => "NUMERIC(20)",
+            Numeric::Decimal(decimal) => {
+                return format!("DECIMAL({}, {})", decimal.precision(), decimal.scale())
+            }
+            Numeric::BigInt(integer) => return format!("NUMERIC({})", integer.digits()),
+        }
+        .to_string(),
+        Type::Text(text) => match text {
+            Text::Char(_) => "CHAR(1)".to_string(),
+            Text::String(_, Some(len)) => format!("VARCHAR({})", len),
+            Text::String(_, None) => "TEXT".to_string(),
+            Text::Binary(_) => "BINARY(1)".to_string(),
+            Text::BinaryString(_, len) => format!("VARBINARY({})", len),
+            Text::Blob(_) | Text::BlobRef(_) => "BLOB".to_string(),
+            Text::BitString(_, BitWidth::Fixed(width)) => format!("BIT({})", width),
+            Text::BitString(_, BitWidth::Varying(Some(max))) => format!("BIT VARYING({})", max),
+            Text::BitString(_, BitWidth::Varying(None)) => "BIT VARYING".to_string(),
+        },
+        Type::Time(time) => match time {
+            Time::Date(_) => "DATE",
+            Time::TimeOfDay(_) => "TIME",
+            Time::DateTime(_) => "TIMESTAMP",
+            Time::Timestamp(_) => "TIMESTAMP",
+            Time::TimestampTz(_) => "TIMESTAMP WITH TIME ZONE",
+            Time::Year(_) => "INTEGER",
+        }
+        .to_string(),
+        Type::Boolean(_) => "BOOLEAN".to_string(),
+        Type::Point(_) => "POINT".to_string(),
+        Type::Inet(_) => "INET".to_string(),
+        Type::MacAddress(_) => "MACADDR".to_string(),
+        Type::Optional(Some(inner)) => sql_type(inner),
+        Type::Optional(None) | Type::Extension(..) => "BLOB".to_string(),
+        Type::Array(_, kind) => format!("{}[]", sql_type(kind)),
+        Type::Enum(e) => {
+            let labels: Vec<String> = e.labels().iter().map(|label| quote_string(label)).collect();
+            format!("ENUM({})", labels.join(", "))
+        }
+    }
+}
+
+/// Writes the value as a SQL literal
+pub fn sql_literal(value: &Value) -> String {
+    match value {
+        Type::Optional(None) => "NULL".to_string(),
+        Type::Optional(Some(inner)) => sql_literal(inner),
+        Type::Numeric(Numeric::Float(f)) if !f.is_finite() => quote_string(&f.to_string()),
+        Type::Numeric(Numeric::Double(d)) if !d.is_finite() => quote_string(&d.to_string()),
+        Type::Numeric(numeric) => numeric.to_string(),
+        Type::Text(text) => match text {
+            Text::Char(c) => quote_string(&c.to_string()),
+            Text::String(s, _) => quote_string(s),
+            Text::Binary(b) => hex_literal(&[*b]),
+            Text::BinaryString(bytes, _) | Text::Blob(bytes) => hex_literal(bytes),
+            Text::BlobRef(handle) => quote_string(&handle.to_string()),
+            Text::BitString(bits, _) => format!("B{}", quote_string(&bits.to_string())),
+        },
+        Type::Time(time) => match time {
+            Time::Date(date) => format!("DATE {}", quote_string(&date.to_string())),
+            Time::TimeOfDay(time) => format!("TIME {}", quote_string(&time.to_string())),
+            Time::DateTime(date_time) => {
+                format!("TIMESTAMP {}", quote_string(&date_time.to_string()))
+            }
+            Time::Timestamp(timestamp) => format!(
+                "TIMESTAMP {}",
+                quote_string(&timestamp.naive_utc().to_string())
+            ),
+            Time::TimestampTz(timestamp) => format!(
+                "TIMESTAMP WITH TIME ZONE {}",
+                quote_string(&timestamp.to_rfc3339())
+            ),
+            Time::Year(year) => year.to_string(),
+        },
+        Type::Boolean(b) => if *b { "TRUE" } else { "FALSE" }.to_string(),
+        Type::Point(point) => format!("POINT({}, {})", point.x(), point.y()),
+        Type::Inet(inet) => format!("INET {}", quote_string(&inet.to_string())),
+        Type::MacAddress(address) => format!("MACADDR {}", quote_string(&address.to_string())),
+        Type::Extension(_, bytes) => hex_literal(bytes),
+        Type::Array(values, _) => {
+            let values: Vec<String> = values.iter().map(sql_literal).collect();
+            format!("ARRAY[{}]", values.join(", "))
+        }
+        Type::Enum(e) => quote_string(e.label()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BitString, Enumeration};
+
+    #[test]
+    fn literals() {
+        assert_eq!(sql_literal(&Value::from("it's")), "'it''s'");
+        assert_eq!(sql_literal(&Value::from(-3i32)), "-3");
+        assert_eq!(sql_literal(&Value::from(true)), "TRUE");
+        assert_eq!(sql_literal(&Value::Optional(None)), "NULL");
+        assert_eq!(
+            sql_literal(&Time::date(2021, 3, 4).unwrap().into()),
+            "DATE '2021-03-04'"
+        );
+        assert_eq!(
+            sql_literal(&Value::Numeric(Numeric::Double(f64::NAN))),
+            "'NaN'"
+        );
+        assert_eq!(quote_identifier("a\"b"), "\"a\"\"b\"");
+        let tags = Type::array(Type::from(""), vec!["a".into(), "b'c".into()]);
+        assert_eq!(sql_literal(&tags), "ARRAY['a', 'b''c']");
+        assert_eq!(sql_column_type(&tags), "TEXT[] NOT NULL");
+        assert_eq!(sql_column_type(&Value::from(0u32)), "BIGINT NOT NULL");
+        let status = Value::from(Enumeration::new(vec!["open", "won't fix"], 1).unwrap());
+        assert_eq!(sql_literal(&status), "'won''t fix'");
+        assert_eq!(sql_type(&status), "ENUM('open', 'won''t fix')");
+        assert_eq!(
+            sql_column_type(&Type::Optional(Some(Box::new(Value::from(""))))),
+            "TEXT"
+        );
+        let flags = Value::from(Text::BitString(
+            BitString::parse("0110").unwrap(),
+            BitWidth::Fixed(4),
+        ));
+        assert_eq!(sql_literal(&flags), "B'0110'");
+        assert_eq!(sql_type(&flags), "BIT(4)");
+    }
+}
@@ -0,0 +1,230 @@
+//! Generators of random values, used for writing property tests and fuzzing the serialization and
+//! storage layers. Only available with the `testing` feature.
+
+use chrono::{FixedOffset, NaiveDate, NaiveTime, TimeZone, Utc};
+use num_bigint::{BigInt, BigUint, Sign};
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use crate::{
+    BitWidth, Decimal, Enumeration, Inet, Integer, MacAddress, Numeric, Point, Signed, Text, Time,
+    Type, Unsigned,
+};
+
+/// The maximum length of generated strings when the type has no maximum length
+pub const DEFAULT_MAX_STRING_LENGTH: usize = 16;
+
+/// The maximum length of generated arrays
+pub const MAX_ARRAY_LENGTH: usize = 4;
+
+/// Generates a random string made of alphanumeric characters, so that it can always be
+/// serialized into the text format
+fn random_string<R: Rng + ?Sized>(max_length: usize, rng: &mut R) -> String {
+    let length = rng.gen_range(0..=max_length);
+    (0..length)
+        .map(|_| rng.sample(Alphanumeric) as char)
+        .collect()
+}
+
+fn random_bytes<R: Rng + ?Sized>(length: usize, rng: &mut R) -> Vec<u8> {
+    (0..length).map(|_| rng.gen()).collect()
+}
+
+/// Generates a random value of the same kind as the given type. Length limits on text are
+/// respected, and optional types are sometimes `NULL`. Extension types can't be generated, so
+/// a copy of the given type is returned for them.
+pub fn random_value<R: Rng + ?Sized>(kind: &Type, rng: &mut R) -> Type {
+    match kind {
+        Type::Numeric(numeric) => Type::Numeric(match numeric {
+            Numeric::Float(_) => Numeric::Float(rng.gen()),
+            Numeric::Double(_) => Numeric::Double(rng.gen()),
+            Numeric::Signed(signed) => Numeric::Signed(match signed {
+                Signed::Byte(_) => Signed::Byte(rng.gen()),
+                Signed::Short(_) => Signed::Short(rng.gen()),
+                Signed::Int(_) => Signed::Int(rng.gen()),
+                Signed::Long(_) => Signed::Long(rng.gen()),
+            }),
+            Numeric::Unsigned(unsigned) => Numeric::Unsigned(match unsigned {
+                Unsigned::Byte(_) => Unsigned::Byte(rng.gen()),
+                Unsigned::Short(_) => Unsigned::Short(rng.gen()),
+                Unsigned::Int(_) => Unsigned::Int(rng.gen()),
+                Unsigned::Long(_) => Unsigned::Long(rng.gen()),
+            }),
+            Numeric::Decimal(decimal) => {
+                let limit = 10i128.pow(decimal.precision() as u32);
+                let mantissa = rng.gen_range(-limit + 1..limit);
+                Numeric::Decimal(
+                    Decimal::new(mantissa, decimal.precision(), decimal.scale())
+                        .expect("The mantissa fits in the precision"),
+                )
+            }
+            Numeric::BigInt(integer) => {
+                let bits = integer.bits() as usize - 1;
+                let magnitude = BigUint::from_bytes_le(&random_bytes((bits + 7) / 8, rng))
+                    >> ((8 - bits % 8) % 8);
+                let sign = if rng.gen() { Sign::Minus } else { Sign::Plus };
+                Numeric::BigInt(
+                    Integer::new(BigInt::from_biguint(sign, magnitude), integer.bits())
+                        .expect("The magnitude fits in the bits"),
+                )
+            }
+        }),
+        Type::Text(text) => Type::Text(match text {
+            Text::Char(_) => Text::Char(rng.sample(Alphanumeric) as char),
+            Text::String(_, max_length) => {
+                let max = max_length.map_or(DEFAULT_MAX_STRING_LENGTH, |max| max as usize);
+                Text::String(random_string(max, rng), *max_length)
+            }
+            Text::Binary(_) => Text::Binary(rng.gen()),
+            Text::BinaryString(_, length) => {
+                Text::BinaryString(random_bytes(*length as usize, rng), *length)
+            }
+            Text::BitString(_, width) => {
+                let len = match width {
+                    BitWidth::Fixed(width) => *width as usize,
+                    BitWidth::Varying(max) => {
+                        rng.gen_range(0..=max.map_or(DEFAULT_MAX_STRING_LENGTH, |max| max as usize))
+                    }
+                };
+                Text::BitString((0..len).map(|_| rng.gen::<bool>()).collect(), *width)
+            }
+            Text::Blob(_) | Text::BlobRef(_) => {
+                let length = rng.gen_range(0..=DEFAULT_MAX_STRING_LENGTH);
+                Text::Blob(random_bytes(length, rng))
+            }
+        }),
+        Type::Time(time) => {
+            let seconds = rng.gen_range(0..(1i64 << 32));
+            Type::Time(match time {
+                Time::Date(_) => Time::Date(
+                    NaiveDate::from_ymd_opt(
+                        rng.gen_range(1..=9999),
+                        rng.gen_range(1..=12),
+                        rng.gen_range(1..=28),
+                    )
+                    .unwrap(),
+                ),
+                Time::TimeOfDay(_) => Time::TimeOfDay(
+                    NaiveTime::from_num_seconds_from_midnight_opt(rng.gen_range(0..86400), 0)
+                        .unwrap(),
+                ),
+                Time::DateTime(_) => {
+                    Time::DateTime(Utc.timestamp_opt(seconds, 0).unwrap().naive_utc())
+                }
+                Time::Timestamp(_) => Time::Timestamp(Utc.timestamp_opt(seconds, 0).unwrap()),
+                Time::TimestampTz(_) => {
+                    let offset = FixedOffset::east_opt(rng.gen_range(-12..=14) * 3600).unwrap();
+                    Time::TimestampTz(offset.timestamp_opt(seconds, 0).unwrap())
+                }
+                Time::Year(_) => Time::Year(rng.gen_range(1..=9999)),
+            })
+        }
+        Type::Boolean(_) => Type::Boolean(rng.gen()),
+        Type::Point(_) => Point::new(rng.gen(), rng.gen()).into(),
+        Type::Inet(inet) => {
+            let address: IpAddr = if inet.address().is_ipv4() {
+                Ipv4Addr::from(rng.gen::<u32>()).into()
+            } else {
+                Ipv6Addr::from(rng.gen::<u128>()).into()
+            };
+            let max_prefix = if address.is_ipv4() { 32 } else { 128 };
+            Inet::new(address, rng.gen_range(0..=max_prefix))
+                .expect("The prefix is at most the length of the address")
+                .into()
+        }
+        Type::MacAddress(_) => MacAddress::new(rng.gen()).into(),
+        Type::Optional(inner) => match inner {
+            Some(inner) if rng.gen_bool(0.75) => {
+                Type::Optional(Some(Box::new(random_value(inner, rng))))
+            }
+            _ => Type::Optional(None),
+        },
+        Type::Extension(..) => kind.clone(),
+        Type::Array(_, element) => {
+            let length = rng.gen_range(0..=MAX_ARRAY_LENGTH);
+            let values = (0..length).map(|_| random_value(element, rng)).collect();
+            Type::Array(values, element.clone())
+        }
+        Type::Enum(e) => {
+            let ordinal = rng.gen_range(0..e.labels().len());
+            Type::Enum(
+                e.with_ordinal(ordinal as u16)
+                    .expect("The ordinal is of a label"),
+            )
+        }
+    }
+}
+
+/// Generates a random value for each of the given types
+pub fn random_values<'a, R, I>(kinds: I, rng: &mut R) -> Vec<Type>
+where
+    R: Rng + ?Sized,
+    I: IntoIterator<Item = &'a Type>,
+{
+    kinds
+        .into_iter()
+        .map(|kind| random_value(kind, rng))
+        .collect()
+}
+
+/// Generates a random type that can be used as the type of a column
+pub fn random_kind<R: Rng + ?Sized>(rng: &mut R) -> Type {
+    let kinds: [Type; 15] = [
+        0i8.into(),
+        0i16.into(),
+        0i32.into(),
+        0i64.into(),
+        0u8.into(),
+        0u16.into(),
+        0u32.into(),
+        0u64.into(),
+        Numeric::Double(0.0).into(),
+        Decimal::zero(12, 3).into(),
+        Text::Char(' ').into(),
+        Text::String(String::new(), None).into(),
+        false.into(),
+        Type::array(Text::String(String::new(), None).into(), vec![]),
+        Enumeration::new(vec!["low", "medium", "high"], 0)
+            .unwrap()
+            .into(),
+    ];
+    kinds[rng.gen_range(0..kinds.len())].clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::deserialization::parse_using_types;
+    use crate::serialization::serialize_values;
+    use crate::Kind;
+
+    #[test]
+    fn generated_values_round_trip() {
+        let mut rng = rand::thread_rng();
+        for _ in 0..100 {
+            let kinds: Vec<Type> = (0..8).map(|_| random_kind(&mut rng)).collect();
+            let values = random_values(&kinds, &mut rng);
+            assert!(kinds
+                .iter()
+                .zip(&values)
+                .all(|(kind, value)| Kind::of(kind).matches(value)));
+            let serialized = serialize_values(values.clone());
+            let parsed = parse_using_types(serialized, kinds).unwrap();
+            assert_eq!(parsed, values);
+        }
+    }
+
+    #[test]
+    fn string_length_respected() {
+        let mut rng = rand::thread_rng();
+        let kind = Type::from(Text::String(String::new(), Some(4)));
+        for _ in 0..100 {
+            if let Type::Text(Text::String(s, _)) = random_value(&kind, &mut rng) {
+                assert!(s.len() <= 4);
+            } else {
+                panic!("Generated a value of the wrong kind")
+            }
+        }
+    }
+}
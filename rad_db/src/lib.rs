@@ -0,0 +1,58 @@
+//! The public interface of RadDB. Each sub-crate is re-exported as a module, and the
+//! [prelude](crate::prelude) contains everything needed to define relations and query them, so
+//! that only this crate needs to be depended on.
+
+pub use rad_db_algebra as algebra;
+pub use rad_db_structure as structure;
+pub use rad_db_types as types;
+
+pub mod prelude {
+    pub use rad_db_algebra::query::conditions::{
+        Condition, ConditionOperation, JoinCondition, Operand,
+    };
+    pub use rad_db_algebra::query::cost::CostModel;
+    pub use rad_db_algebra::query::feedback::CardinalityFeedback;
+    pub use rad_db_algebra::query::hints::Hint;
+    pub use rad_db_algebra::query::plan_cache::PlanCache;
+    pub use rad_db_algebra::query::query_node::QueryNode;
+    pub use rad_db_algebra::query::query_result::QueryResult;
+    pub use rad_db_algebra::query::sort::SortKey;
+    pub use rad_db_algebra::query::table::TablePrinter;
+    pub use rad_db_algebra::query::workload::WorkloadLog;
+    pub use rad_db_structure::prelude::*;
+    pub use rad_db_structure::relations::bulk::BulkLoader;
+    pub use rad_db_structure::settings::Settings;
+    pub use rad_db_types::geometry::{Point, Polygon};
+    pub use rad_db_types::network::{Inet, MacAddress};
+    pub use rad_db_types::{BitString, BitWidth, Type, Value};
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prelude::*;
+
+    #[test]
+    fn query_through_prelude() {
+        let mut relation = Relation::new_volatile(
+            Identifier::new("people"),
+            vec![("id", Type::from(0u64)), ("name", Type::from(""))],
+            16,
+            PrimaryKeyDefinition::new(vec![0]),
+        );
+        for (id, name) in ["ada", "grace", "barbara"].iter().enumerate() {
+            relation
+                .insert(Tuple::new(vec![Value::from(id as u64), Value::from(*name)]))
+                .unwrap();
+        }
+
+        let query = QueryNode::projection(
+            QueryNode::select_on_condition(
+                QueryNode::source(&relation),
+                Condition::new("id", ConditionOperation::Equals(Operand::UnsignedNumber(1))),
+            ),
+            vec!["name"],
+        );
+        let tuples: Vec<Tuple> = query.optimized().execute_query().into_iter().collect();
+        assert_eq!(tuples, vec![Tuple::new(vec![Value::from("grace")])]);
+    }
+}